@@ -0,0 +1,55 @@
+//! Throughput and peak memory of search-replace over a large made-up
+//! database, a value at a time as a dump rewrite feeds it.
+//!
+//! `cargo bench -p wordpress-sync-core --bench large_data`; set
+//! `WPSYNC_BENCH_MB` for a size other than 256 MB.
+
+use std::time::Instant;
+
+use wordpress_sync_core::search_replace::Replacer;
+
+fn main() {
+    let megabytes: usize = std::env::var("WPSYNC_BENCH_MB")
+        .ok()
+        .and_then(|mb| mb.parse().ok())
+        .unwrap_or(256);
+    let replacer = Replacer::new([
+        ("https://www.example.com", "https://staging.example.test"),
+        ("www.example.com", "staging.example.test"),
+    ]);
+    let text = "Read more at https://www.example.com/blog/ and say hello. ".repeat(40);
+    let url = "https://www.example.com/wp-content/uploads/2024/05/photo.jpg";
+    let serialized = format!(
+        "a:3:{{s:5:\"title\";s:{}:\"{text}\";s:3:\"url\";s:{}:\"{url}\";s:4:\"meta\";a:1:{{i:0;s:4:\"none\";}}}}",
+        text.len(),
+        url.len(),
+    );
+
+    let (mut read, mut written, mut values) = (0, 0, 0);
+    let started = Instant::now();
+    while read < megabytes << 20 {
+        for value in [serialized.as_bytes(), text.as_bytes(), b"no match here"] {
+            let replaced = replacer.value(value);
+            read += value.len();
+            written += replaced.as_deref().map_or(value.len(), <[u8]>::len);
+            values += 1;
+        }
+    }
+    let seconds = started.elapsed().as_secs_f64();
+    println!(
+        "search-replace: {values} values, {} MB in {seconds:.2}s ({:.0} MB/s), {} MB out",
+        read >> 20,
+        (read >> 20) as f64 / seconds,
+        written >> 20,
+    );
+    if let Some(peak) = peak_memory() {
+        println!("peak memory: {peak}");
+    }
+}
+
+/// The process's peak resident memory, where the system says.
+fn peak_memory() -> Option<String> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    Some(line["VmHWM:".len()..].trim().to_string())
+}
@@ -0,0 +1,98 @@
+//! File names that aren't valid UTF-8, as the `String`s that trees, manifests
+//! and reports name files by.
+//!
+//! Old uploads libraries often have names in Latin-1 or another legacy
+//! encoding. A name's valid UTF-8 is kept as it is, and each byte that isn't
+//! part of any is kept as one of the last 128 characters of Unicode's private
+//! use plane 16, which real names don't use. Turning a name back into bytes,
+//! for this machine's file system or a command line on the server, gives the
+//! exact bytes it was listed with, so the file is read and written as itself
+//! rather than under a replacement character.
+
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+
+/// The character standing for the raw byte `0x80`; the bytes above it follow.
+const RAW_BASE: u32 = 0x10FF00;
+
+/// `bytes`, a name or anything naming files, with the bytes that aren't UTF-8 kept.
+pub fn from_bytes(bytes: &[u8]) -> String {
+    let mut name = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        name.push_str(chunk.valid());
+        name.extend(chunk.invalid().iter().map(|&byte| raw_char(byte)));
+    }
+    name
+}
+
+/// The bytes [`from_bytes`] was given for `name`.
+pub fn to_bytes(name: &str) -> Cow<'_, [u8]> {
+    if !is_raw(name) {
+        return Cow::Borrowed(name.as_bytes());
+    }
+    let mut bytes = Vec::with_capacity(name.len());
+    for c in name.chars() {
+        match raw_byte(c) {
+            Some(byte) => bytes.push(byte),
+            None => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    Cow::Owned(bytes)
+}
+
+/// A name from this machine's file system. Windows' names are UTF-16, which
+/// has no raw bytes to keep.
+pub fn from_os(name: &OsStr) -> String {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        from_bytes(name.as_bytes())
+    }
+    #[cfg(not(unix))]
+    {
+        name.to_string_lossy().into_owned()
+    }
+}
+
+/// `name` as this machine's file system takes it.
+pub fn to_os(name: &str) -> OsString {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStringExt;
+        OsString::from_vec(to_bytes(name).into_owned())
+    }
+    #[cfg(not(unix))]
+    {
+        OsString::from(name)
+    }
+}
+
+/// Whether `name` has bytes in it that aren't UTF-8.
+pub fn is_raw(name: &str) -> bool {
+    name.chars().any(|c| raw_byte(c).is_some())
+}
+
+/// `name` to be shown to the user, its raw bytes written `\xNN`.
+pub fn display(name: &str) -> Cow<'_, str> {
+    if !is_raw(name) {
+        return Cow::Borrowed(name);
+    }
+    Cow::Owned(
+        name.chars()
+            .map(|c| match raw_byte(c) {
+                Some(byte) => format!("\\x{byte:02X}"),
+                None => c.to_string(),
+            })
+            .collect(),
+    )
+}
+
+fn raw_char(byte: u8) -> char {
+    // UTF-8 only ever rejects bytes from 0x80 up, which all land in range.
+    char::from_u32(RAW_BASE + u32::from(byte)).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+
+fn raw_byte(c: char) -> Option<u8> {
+    let byte = u32::from(c).checked_sub(RAW_BASE)?;
+    (0x80..=0xFF).contains(&byte).then_some(byte as u8)
+}
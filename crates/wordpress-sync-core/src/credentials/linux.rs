@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use secret_service::blocking::SecretService;
+use secret_service::EncryptionType;
+
+use super::{CredentialStore, StoredCredential};
+
+use crate::messages;
+
+/// Items in the user's default Secret Service collection (GNOME Keyring, KWallet).
+///
+/// Entries are identified by `service` and `account` lookup attributes, which is the
+/// same schema `secret-tool` and the `keyring` crate use.
+pub struct SecretServiceStore;
+
+fn attributes<'a>(service: &'a str, account: &'a str) -> HashMap<&'a str, &'a str> {
+    HashMap::from([("service", service), ("account", account)])
+}
+
+fn connect() -> Result<SecretService<'static>, String> {
+    SecretService::connect(EncryptionType::Dh)
+        .map_err(|e| messages::text("error.failed.connect_secret_service", &[("error", &e)]))
+}
+
+impl CredentialStore for SecretServiceStore {
+    fn set(&self, service: &str, account: &str, secret: &str) -> Result<(), String> {
+        let ss = connect()?;
+        let collection = ss
+            .get_default_collection()
+            .map_err(|e| messages::text("error.failed.open_default_keyring", &[("error", &e)]))?;
+        if collection.is_locked().unwrap_or(true) {
+            collection.unlock().map_err(|e| {
+                messages::text("error.failed.unlock_default_keyring", &[("error", &e)])
+            })?;
+        }
+        collection
+            .create_item(
+                &format!("{service} ({account})"),
+                attributes(service, account),
+                secret.as_bytes(),
+                true, // replace an existing item with the same attributes
+                "text/plain",
+            )
+            .map_err(|e| messages::text("error.failed.store_credential", &[("error", &e)]))?;
+        Ok(())
+    }
+
+    fn get(&self, service: &str, account: &str) -> Result<Option<String>, String> {
+        let ss = connect()?;
+        let found = ss
+            .search_items(attributes(service, account))
+            .map_err(|e| messages::text("error.failed.retrieve_credential", &[("error", &e)]))?;
+        let item = match found.unlocked.into_iter().next() {
+            Some(item) => item,
+            None => match found.locked.into_iter().next() {
+                Some(item) => {
+                    item.unlock().map_err(|e| {
+                        messages::text("error.failed.unlock_credential", &[("error", &e)])
+                    })?;
+                    item
+                }
+                // No credential stored — not an error
+                None => return Ok(None),
+            },
+        };
+        let bytes = item
+            .get_secret()
+            .map_err(|e| messages::text("error.failed.retrieve_credential", &[("error", &e)]))?;
+        let s = String::from_utf8(bytes)
+            .map_err(|e| messages::text("error.invalid_utf_8", &[("error", &e)]))?;
+        Ok(Some(s))
+    }
+
+    fn delete(&self, service: &str, account: &str) -> Result<(), String> {
+        let ss = connect()?;
+        let found = ss
+            .search_items(attributes(service, account))
+            .map_err(|e| messages::text("error.failed.delete_credential", &[("error", &e)]))?;
+        // Nothing found means nothing to delete
+        for item in found.unlocked.iter().chain(found.locked.iter()) {
+            item.delete()
+                .map_err(|e| messages::text("error.failed.delete_credential", &[("error", &e)]))?;
+        }
+        Ok(())
+    }
+
+    fn list(&self, service_prefix: &str) -> Result<Vec<StoredCredential>, String> {
+        let ss = connect()?;
+        // No attributes to match lists every item; a locked item still shows its attributes.
+        let found = ss
+            .search_items(HashMap::new())
+            .map_err(|e| messages::text("error.failed.list_credentials", &[("error", &e)]))?;
+        let mut entries = Vec::new();
+        for item in found.unlocked.iter().chain(found.locked.iter()) {
+            let attributes = item
+                .get_attributes()
+                .map_err(|e| messages::text("error.failed.list_credentials", &[("error", &e)]))?;
+            if let (Some(service), Some(account)) =
+                (attributes.get("service"), attributes.get("account"))
+            {
+                if service.starts_with(service_prefix) {
+                    entries.push(StoredCredential {
+                        service: service.clone(),
+                        account: account.clone(),
+                        orphaned: false,
+                    });
+                }
+            }
+        }
+        Ok(entries)
+    }
+}
@@ -0,0 +1,168 @@
+use security_framework::passwords::{
+    delete_generic_password, delete_generic_password_options, generic_password,
+    get_generic_password, set_generic_password, set_generic_password_options, AccessControlOptions,
+    PasswordOptions,
+};
+
+use security_framework::item::{ItemClass, ItemSearchOptions, Limit};
+
+use super::{CredentialStore, StoredCredential};
+
+use crate::messages;
+
+/// errSecItemNotFound — no matching Keychain item.
+const ERR_SEC_ITEM_NOT_FOUND: i32 = -25300;
+
+/// errSecMissingEntitlement — the binary isn't signed for the data protection keychain.
+const ERR_SEC_MISSING_ENTITLEMENT: i32 = -34018;
+
+/// Generic passwords in the macOS Keychain.
+///
+/// Protected secrets live in the data protection keychain, the only one that
+/// enforces access control, behind a user presence check: reading one shows the
+/// system's Touch ID (or login password) prompt. [`get`](CredentialStore::get)
+/// and [`delete`](CredentialStore::delete) look in both keychains.
+pub struct KeychainStore;
+
+/// The entry for `service` and `account` in the data protection keychain.
+fn protected(service: &str, account: &str) -> PasswordOptions {
+    let mut options = PasswordOptions::new_generic_password(service, account);
+    options.use_protected_keychain();
+    options
+}
+
+impl CredentialStore for KeychainStore {
+    fn set(&self, service: &str, account: &str, secret: &str) -> Result<(), String> {
+        // Delete any existing entry first (set_generic_password fails if it already exists)
+        let _ = self.delete(service, account);
+        set_generic_password(service, account, secret.as_bytes())
+            .map_err(|e| messages::text("error.failed.store_credential", &[("error", &e)]))
+    }
+
+    fn set_protected(&self, service: &str, account: &str, secret: &str) -> Result<(), String> {
+        let _ = self.delete(service, account);
+        let mut options = protected(service, account);
+        options.set_access_control_options(AccessControlOptions::USER_PRESENCE);
+        set_generic_password_options(secret.as_bytes(), options).map_err(|e| {
+            if e.code() == ERR_SEC_MISSING_ENTITLEMENT {
+                messages::text("error.failed.store_credential_touch_id", &[])
+            } else {
+                messages::text("error.failed.store_credential", &[("error", &e)])
+            }
+        })
+    }
+
+    fn is_protected(&self, service: &str, account: &str) -> Result<bool, String> {
+        let mut search = ItemSearchOptions::new();
+        search
+            .class(ItemClass::generic_password())
+            .service(service)
+            .account(account)
+            .load_attributes(true)
+            .ignore_legacy_keychains();
+        match search.search() {
+            Ok(results) => Ok(!results.is_empty()),
+            Err(e)
+                if e.code() == ERR_SEC_ITEM_NOT_FOUND
+                    || e.code() == ERR_SEC_MISSING_ENTITLEMENT =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(messages::text(
+                "error.failed.look_up_credential",
+                &[("error", &e)],
+            )),
+        }
+    }
+
+    fn get(&self, service: &str, account: &str) -> Result<Option<String>, String> {
+        let found = match get_generic_password(service, account) {
+            Err(e) if e.code() == ERR_SEC_ITEM_NOT_FOUND => {
+                // Blocks on the user presence prompt if the entry is there.
+                generic_password(protected(service, account))
+            }
+            other => other,
+        };
+        match found {
+            Ok(bytes) => {
+                let s = String::from_utf8(bytes)
+                    .map_err(|e| messages::text("error.invalid_utf_8", &[("error", &e)]))?;
+                Ok(Some(s))
+            }
+            // No credential stored — not an error
+            Err(e) if e.code() == ERR_SEC_ITEM_NOT_FOUND => Ok(None),
+            // An unsigned build can't see the data protection keychain, so has no such entry
+            Err(e) if e.code() == ERR_SEC_MISSING_ENTITLEMENT => Ok(None),
+            Err(e) => Err(messages::text(
+                "error.failed.retrieve_credential",
+                &[("error", &e)],
+            )),
+        }
+    }
+
+    fn delete(&self, service: &str, account: &str) -> Result<(), String> {
+        for result in [
+            delete_generic_password(service, account),
+            delete_generic_password_options(protected(service, account)),
+        ] {
+            match result {
+                Ok(()) => {}
+                // Nothing to delete
+                Err(e)
+                    if e.code() == ERR_SEC_ITEM_NOT_FOUND
+                        || e.code() == ERR_SEC_MISSING_ENTITLEMENT => {}
+                Err(e) => {
+                    return Err(messages::text(
+                        "error.failed.delete_credential",
+                        &[("error", &e)],
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn list(&self, service_prefix: &str) -> Result<Vec<StoredCredential>, String> {
+        let mut entries = Vec::new();
+        for protected in [false, true] {
+            let mut search = ItemSearchOptions::new();
+            search
+                .class(ItemClass::generic_password())
+                .load_attributes(true)
+                .limit(Limit::All);
+            if protected {
+                search.ignore_legacy_keychains();
+            }
+            let results = match search.search() {
+                Ok(results) => results,
+                Err(e)
+                    if e.code() == ERR_SEC_ITEM_NOT_FOUND
+                        || e.code() == ERR_SEC_MISSING_ENTITLEMENT =>
+                {
+                    continue
+                }
+                Err(e) => {
+                    return Err(messages::text(
+                        "error.failed.list_credentials",
+                        &[("error", &e)],
+                    ))
+                }
+            };
+            // Attributes only: listing never asks for Touch ID.
+            for attributes in results.iter().filter_map(|result| result.simplify_dict()) {
+                if let (Some(service), Some(account)) =
+                    (attributes.get("svce"), attributes.get("acct"))
+                {
+                    if service.starts_with(service_prefix) {
+                        entries.push(StoredCredential {
+                            service: service.clone(),
+                            account: account.clone(),
+                            orphaned: false,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(entries)
+    }
+}
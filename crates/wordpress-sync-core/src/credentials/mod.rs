@@ -0,0 +1,264 @@
+//! Platform credential storage.
+//!
+//! Every backend stores a secret under a `(service, account)` pair, mirroring the
+//! macOS Keychain's generic-password model. The implementation is selected at
+//! compile time: Keychain on macOS, Credential Manager on Windows, and the Secret
+//! Service (GNOME Keyring / KWallet) on Linux.
+//!
+//! On macOS a secret can also be stored behind Touch ID: reading it back, for
+//! the app or for a sync that needs it, prompts the user first.
+
+use serde::Serialize;
+
+use crate::messages;
+use crate::profile::{self, JumpHost};
+use crate::proxy::{self, ProxyChoice};
+use crate::schedule::email;
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(target_os = "windows")]
+mod windows;
+
+#[cfg(target_os = "linux")]
+pub use linux::SecretServiceStore as PlatformStore;
+#[cfg(target_os = "macos")]
+pub use macos::KeychainStore as PlatformStore;
+#[cfg(target_os = "windows")]
+pub use windows::CredentialManagerStore as PlatformStore;
+
+/// Start of the name of every service the app stores secrets under.
+pub const SERVICE_PREFIX: &str = "com.wordpress-sync.";
+
+/// Service under which SSH login passwords are stored, keyed by profile id.
+pub const SSH_PASSWORD_SERVICE: &str = "com.wordpress-sync.ssh";
+
+/// Service under which FTP passwords are stored, keyed by profile id.
+pub const FTP_PASSWORD_SERVICE: &str = "com.wordpress-sync.ftp";
+
+/// Service under which WordPress Application Passwords for the REST API are
+/// stored, keyed by profile id.
+pub const REST_PASSWORD_SERVICE: &str = "com.wordpress-sync.rest";
+
+/// Service under which the server's database passwords are stored, keyed by
+/// profile id, for syncs that reach the database through a tunnel.
+pub const DATABASE_PASSWORD_SERVICE: &str = "com.wordpress-sync.db";
+
+/// Service under which the server's sudo passwords are stored, keyed by
+/// profile id, for profiles whose sudo asks for one.
+pub const SUDO_PASSWORD_SERVICE: &str = "com.wordpress-sync.sudo";
+
+/// Service under which the secrets profiles refer to by name are stored,
+/// keyed by that name.
+pub const NAMED_SECRET_SERVICE: &str = "com.wordpress-sync.secret";
+
+/// How a profile's reference to a named secret starts, as in `keychain:prod-db`.
+pub const SECRET_REFERENCE_PREFIX: &str = "keychain:";
+
+/// Service under which the SMTP password for emailing scheduled syncs' results
+/// is stored, keyed by the SMTP username.
+pub const SMTP_PASSWORD_SERVICE: &str = "com.wordpress-sync.smtp";
+
+/// Service under which proxy passwords are stored, keyed by
+/// [`Proxy::password_account`](crate::proxy::Proxy::password_account).
+pub const PROXY_PASSWORD_SERVICE: &str = "com.wordpress-sync.proxy";
+
+/// Service under which managed hosts' API credentials are stored, keyed by
+/// [`Provider::id`](crate::providers::Provider::id).
+pub const PROVIDER_TOKEN_SERVICE: &str = "com.wordpress-sync.provider";
+
+/// Service under which SSH key passphrases are stored, keyed by the key file's
+/// absolute path.
+pub const SSH_KEY_PASSPHRASE_SERVICE: &str = "com.wordpress-sync.ssh-key";
+
+/// Service under which the API tokens for purging a profile's CDN are stored,
+/// keyed by profile id.
+pub const CDN_TOKEN_SERVICE: &str = "com.wordpress-sync.cdn";
+
+/// Service under which the key that encrypts local backups and kept dumps is
+/// stored, under the account `local`.
+pub const ARTIFACT_KEY_SERVICE: &str = "com.wordpress-sync.artifacts";
+
+/// A backend capable of storing secrets in the operating system's credential store.
+pub trait CredentialStore: Send + Sync {
+    /// Store a secret, replacing any existing entry for the same service and account.
+    fn set(&self, service: &str, account: &str, secret: &str) -> Result<(), String>;
+
+    /// Store a secret that can only be read back once the user has proved they
+    /// are at the machine, as with Touch ID. Stores with no such check store
+    /// it as [`set`](Self::set) does.
+    fn set_protected(&self, service: &str, account: &str, secret: &str) -> Result<(), String> {
+        self.set(service, account, secret)
+    }
+
+    /// Whether the entry was stored with [`set_protected`](Self::set_protected)
+    /// and the store enforces it.
+    fn is_protected(&self, _service: &str, _account: &str) -> Result<bool, String> {
+        Ok(false)
+    }
+
+    /// Retrieve a secret. Returns `None` if no entry exists.
+    fn get(&self, service: &str, account: &str) -> Result<Option<String>, String>;
+
+    /// Delete a secret. Succeeds silently if no entry exists.
+    fn delete(&self, service: &str, account: &str) -> Result<(), String>;
+
+    /// Every entry whose service starts with `service_prefix`, without its
+    /// secret and in no particular order.
+    fn list(&self, service_prefix: &str) -> Result<Vec<StoredCredential>, String>;
+}
+
+/// An entry in the credential store, without its secret.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StoredCredential {
+    pub service: String,
+    pub account: String,
+    /// It belongs to a profile that no longer exists, so nothing will read it
+    /// again. Only ever set for the app's own services.
+    pub orphaned: bool,
+}
+
+/// The credential store for the platform this binary was built for.
+pub fn platform_store() -> PlatformStore {
+    PlatformStore
+}
+
+/// The name of the secret `reference`, such as `keychain:prod-db`, refers to.
+pub fn secret_name(reference: &str) -> Result<&str, String> {
+    reference
+        .strip_prefix(SECRET_REFERENCE_PREFIX)
+        .filter(|name| !name.trim().is_empty())
+        .ok_or_else(|| {
+            messages::text(
+                "error.reference_isnt_secret_such_keychain_prod",
+                &[("reference", &format_args!("{reference:?}"))],
+            )
+        })
+}
+
+/// The named secret `reference`, such as `keychain:prod-db`, refers to,
+/// which must be stored.
+pub fn named_secret(reference: &str) -> Result<String, String> {
+    let name = secret_name(reference)?;
+    platform_store()
+        .get(NAMED_SECRET_SERVICE, name)?
+        .ok_or_else(|| messages::text("error.no_secret_named_name_stored", &[("name", &name)]))
+}
+
+/// The secret a profile uses for what is stored under `service`: the named
+/// secret `reference` gives, which must be stored, or without one, whatever
+/// is stored under `service` for `account`.
+pub fn resolve(
+    reference: Option<&str>,
+    service: &str,
+    account: &str,
+) -> Result<Option<String>, String> {
+    match reference {
+        Some(reference) => named_secret(reference).map(Some),
+        None => platform_store().get(service, account),
+    }
+}
+
+/// What [`migrate`] did with each account under the old service.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CredentialMigration {
+    /// Accounts copied to the new service.
+    pub migrated: Vec<String>,
+    /// Accounts the new service already had a secret for, which was kept.
+    pub kept: Vec<String>,
+}
+
+/// Move every secret stored under `old_service` to `new_service`, with the
+/// same accounts, and delete the old entries. An account the new service
+/// already has keeps its secret there. A secret behind Touch ID prompts to be
+/// read, and is stored under the new service behind Touch ID again.
+///
+/// Fails on the first entry that can't be moved; entries moved before it stay
+/// moved, so running it again carries on.
+pub fn migrate(old_service: &str, new_service: &str) -> Result<CredentialMigration, String> {
+    if old_service == new_service {
+        return Err(messages::text("error.old_new_service_are_same", &[]));
+    }
+    let store = platform_store();
+    let mut migration = CredentialMigration::default();
+    let mut accounts: Vec<String> = store
+        .list(old_service)?
+        .into_iter()
+        .filter(|entry| entry.service == old_service)
+        .map(|entry| entry.account)
+        .collect();
+    accounts.sort();
+    accounts.dedup();
+    for account in accounts {
+        if store.get(new_service, &account)?.is_some() {
+            migration.kept.push(account.clone());
+        } else if let Some(secret) = store.get(old_service, &account)? {
+            if store.is_protected(old_service, &account)? {
+                store.set_protected(new_service, &account, &secret)?;
+            } else {
+                store.set(new_service, &account, &secret)?;
+            }
+            migration.migrated.push(account.clone());
+        }
+        store.delete(old_service, &account)?;
+    }
+    Ok(migration)
+}
+
+/// The entries under services starting with `service_prefix`, sorted, with
+/// those left behind by deleted profiles marked as orphaned.
+pub fn audit(service_prefix: &str) -> Result<Vec<StoredCredential>, String> {
+    let mut entries = platform_store().list(service_prefix)?;
+    let profiles = profile::list()?;
+    let ids: Vec<&str> = profiles.iter().map(|profile| profile.id.as_str()).collect();
+    let names: Vec<&str> = profiles
+        .iter()
+        .flat_map(|profile| {
+            let webhooks = profile.webhooks.iter().map(|webhook| webhook.url.as_str());
+            profile
+                .secrets
+                .references()
+                .map(|(_, reference)| reference)
+                .chain(webhooks)
+        })
+        .filter_map(|reference| secret_name(reference).ok())
+        .collect();
+    let smtp_username = email::settings()?.map(|settings| settings.username);
+    let proxy_accounts: Vec<String> = proxy::settings()?
+        .into_iter()
+        .chain(
+            profiles
+                .iter()
+                .filter_map(|profile| match &profile.remote.proxy {
+                    ProxyChoice::Custom(proxy) => Some(proxy.clone()),
+                    _ => None,
+                }),
+        )
+        .map(|proxy| proxy.password_account())
+        .collect();
+    let in_use = |service: &str, account: &str| match service {
+        SSH_PASSWORD_SERVICE => ids
+            .iter()
+            .any(|id| account == *id || account == JumpHost::password_account(id)),
+        FTP_PASSWORD_SERVICE
+        | REST_PASSWORD_SERVICE
+        | DATABASE_PASSWORD_SERVICE
+        | SUDO_PASSWORD_SERVICE
+        | CDN_TOKEN_SERVICE => ids.contains(&account),
+        NAMED_SECRET_SERVICE => names.contains(&account),
+        SMTP_PASSWORD_SERVICE => smtp_username.as_deref() == Some(account),
+        PROXY_PASSWORD_SERVICE => proxy_accounts.iter().any(|used| used == account),
+        // What it encrypts can't be read without it.
+        ARTIFACT_KEY_SERVICE => true,
+        // Not one of ours, so who knows what reads it.
+        _ => true,
+    };
+    for entry in &mut entries {
+        entry.orphaned = !in_use(&entry.service, &entry.account);
+    }
+    entries.sort_by(|a, b| (&a.service, &a.account).cmp(&(&b.service, &b.account)));
+    entries.dedup();
+    Ok(entries)
+}
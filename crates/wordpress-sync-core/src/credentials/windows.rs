@@ -0,0 +1,164 @@
+use std::ptr;
+
+use windows_sys::Win32::Foundation::{GetLastError, ERROR_NOT_FOUND, FILETIME};
+use windows_sys::Win32::Security::Credentials::{
+    CredDeleteW, CredEnumerateW, CredFree, CredReadW, CredWriteW, CREDENTIALW,
+    CRED_PERSIST_LOCAL_MACHINE, CRED_TYPE_GENERIC,
+};
+
+use super::{CredentialStore, StoredCredential};
+
+use crate::messages;
+
+/// Generic credentials in the Windows Credential Manager.
+///
+/// Credential Manager has a single flat namespace, so each entry's target name is
+/// `account.service` (the same scheme the `keyring` crate uses), with the account
+/// also recorded as the credential's user name.
+pub struct CredentialManagerStore;
+
+fn target_name(service: &str, account: &str) -> Vec<u16> {
+    to_wide(&format!("{account}.{service}"))
+}
+
+fn to_wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+/// The NUL-terminated UTF-16 string at `wide`, or an empty one for null.
+///
+/// # Safety
+///
+/// `wide` must be null or point to a NUL-terminated string.
+unsafe fn from_wide(wide: *const u16) -> String {
+    if wide.is_null() {
+        return String::new();
+    }
+    let mut len = 0;
+    while unsafe { *wide.add(len) } != 0 {
+        len += 1;
+    }
+    String::from_utf16_lossy(unsafe { std::slice::from_raw_parts(wide, len) })
+}
+
+impl CredentialStore for CredentialManagerStore {
+    fn set(&self, service: &str, account: &str, secret: &str) -> Result<(), String> {
+        let mut target = target_name(service, account);
+        let mut user = to_wide(account);
+        let mut blob = secret.as_bytes().to_vec();
+        let credential = CREDENTIALW {
+            Flags: 0,
+            Type: CRED_TYPE_GENERIC,
+            TargetName: target.as_mut_ptr(),
+            Comment: ptr::null_mut(),
+            LastWritten: FILETIME {
+                dwLowDateTime: 0,
+                dwHighDateTime: 0,
+            },
+            CredentialBlobSize: blob.len() as u32,
+            CredentialBlob: blob.as_mut_ptr(),
+            Persist: CRED_PERSIST_LOCAL_MACHINE,
+            AttributeCount: 0,
+            Attributes: ptr::null_mut(),
+            TargetAlias: ptr::null_mut(),
+            UserName: user.as_mut_ptr(),
+        };
+        // SAFETY: every pointer in `credential` refers to a buffer that outlives the call.
+        // CredWriteW replaces an existing entry with the same target name.
+        if unsafe { CredWriteW(&credential, 0) } == 0 {
+            let code = unsafe { GetLastError() };
+            return Err(messages::text(
+                "error.failed.store_credential_windows_code",
+                &[("code", &code)],
+            ));
+        }
+        Ok(())
+    }
+
+    fn get(&self, service: &str, account: &str) -> Result<Option<String>, String> {
+        let target = target_name(service, account);
+        let mut credential: *mut CREDENTIALW = ptr::null_mut();
+        // SAFETY: `target` is NUL-terminated and `credential` is a valid out-pointer.
+        if unsafe { CredReadW(target.as_ptr(), CRED_TYPE_GENERIC, 0, &mut credential) } == 0 {
+            let code = unsafe { GetLastError() };
+            // No credential stored — not an error
+            if code == ERROR_NOT_FOUND {
+                return Ok(None);
+            }
+            return Err(messages::text(
+                "error.failed.retrieve_credential_windows_code",
+                &[("code", &code)],
+            ));
+        }
+        // SAFETY: CredReadW succeeded, so `credential` points to a valid CREDENTIALW whose
+        // blob is `CredentialBlobSize` bytes long. It must be released with CredFree.
+        let bytes = unsafe {
+            let blob = std::slice::from_raw_parts(
+                (*credential).CredentialBlob,
+                (*credential).CredentialBlobSize as usize,
+            )
+            .to_vec();
+            CredFree(credential as *const _);
+            blob
+        };
+        let s = String::from_utf8(bytes)
+            .map_err(|e| messages::text("error.invalid_utf_8", &[("error", &e)]))?;
+        Ok(Some(s))
+    }
+
+    fn delete(&self, service: &str, account: &str) -> Result<(), String> {
+        let target = target_name(service, account);
+        // SAFETY: `target` is NUL-terminated.
+        if unsafe { CredDeleteW(target.as_ptr(), CRED_TYPE_GENERIC, 0) } == 0 {
+            let code = unsafe { GetLastError() };
+            if code == ERROR_NOT_FOUND {
+                return Ok(()); // Nothing to delete
+            }
+            return Err(messages::text(
+                "error.failed.delete_credential_windows_code",
+                &[("code", &code)],
+            ));
+        }
+        Ok(())
+    }
+
+    fn list(&self, service_prefix: &str) -> Result<Vec<StoredCredential>, String> {
+        let mut count = 0;
+        let mut credentials: *mut *mut CREDENTIALW = ptr::null_mut();
+        // SAFETY: a null filter lists every credential; both out-pointers are valid.
+        if unsafe { CredEnumerateW(ptr::null(), 0, &mut count, &mut credentials) } == 0 {
+            let code = unsafe { GetLastError() };
+            // No credentials at all
+            if code == ERROR_NOT_FOUND {
+                return Ok(Vec::new());
+            }
+            return Err(messages::text(
+                "error.failed.list_credentials_windows_code",
+                &[("code", &code)],
+            ));
+        }
+        let mut entries = Vec::new();
+        // SAFETY: CredEnumerateW succeeded, so `credentials` holds `count` valid
+        // CREDENTIALW pointers, released together with CredFree.
+        unsafe {
+            for &credential in std::slice::from_raw_parts(credentials, count as usize) {
+                if (*credential).Type != CRED_TYPE_GENERIC {
+                    continue;
+                }
+                let target = from_wide((*credential).TargetName);
+                let account = from_wide((*credential).UserName);
+                // The target name is `account.service`.
+                let service = target.strip_prefix(&format!("{account}.")).unwrap_or("");
+                if !service.is_empty() && service.starts_with(service_prefix) {
+                    entries.push(StoredCredential {
+                        service: service.to_string(),
+                        account,
+                        orphaned: false,
+                    });
+                }
+            }
+            CredFree(credentials as *const _);
+        }
+        Ok(entries)
+    }
+}
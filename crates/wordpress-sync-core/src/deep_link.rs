@@ -0,0 +1,208 @@
+//! `wpsync://` links, which let shortcuts and other tools start a sync in the
+//! app: `wpsync://run?profile=My%20Site&direction=push&components=database`.
+//!
+//! `profile` is a profile's id or its name; `direction` defaults to `pull`
+//! and `components` to `all`. The OS hands a link to the app on its command
+//! line on Windows and Linux, where [`register`] makes the app the scheme's
+//! handler each time it starts; macOS reads the scheme from the bundle's
+//! `Info.plist` and delivers links as an event instead.
+
+use url::Url;
+
+use crate::messages;
+use crate::profile::{self, Profile};
+use crate::sync::{Components, Direction};
+
+/// The URL scheme the app handles.
+pub const SCHEME: &str = "wpsync";
+
+/// What a link asks the app to do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeepLink {
+    /// Queue a sync of a profile.
+    Run {
+        /// The profile's id or name, as the link gives it.
+        profile: String,
+        direction: Direction,
+        components: Components,
+    },
+}
+
+impl DeepLink {
+    /// Parse a `wpsync://` link.
+    pub fn parse(link: &str) -> Result<Self, String> {
+        let url = Url::parse(link)
+            .map_err(|e| messages::text("error.invalid_link", &[("link", &link), ("error", &e)]))?;
+        if url.scheme() != SCHEME {
+            return Err(messages::text(
+                "error.link_isnt_scheme",
+                &[("link", &link), ("scheme", &SCHEME)],
+            ));
+        }
+        // `wpsync://run` puts the action in the host, `wpsync:run` in the path.
+        let action = url
+            .host_str()
+            .unwrap_or_else(|| url.path())
+            .trim_matches('/');
+        if action != "run" {
+            return Err(messages::text(
+                "error.unknown_scheme_action",
+                &[("scheme", &SCHEME), ("action", &action)],
+            ));
+        }
+        let value = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+        };
+        let profile = value("profile")
+            .filter(|profile| !profile.is_empty())
+            .ok_or_else(|| messages::text("error.link_doesnt_name_profile", &[("link", &link)]))?;
+        let direction = match value("direction").as_deref() {
+            None | Some("pull") => Direction::Pull,
+            Some("push") => Direction::Push,
+            Some(other) => {
+                return Err(messages::text(
+                    "error.unknown_direction_other_link",
+                    &[("other", &other), ("link", &link)],
+                ))
+            }
+        };
+        let components = match value("components") {
+            None => Components::default(),
+            Some(components) => serde_json::from_value(serde_json::Value::String(components))
+                .map_err(|_| messages::text("error.unknown_components_link", &[("link", &link)]))?,
+        };
+        Ok(Self::Run {
+            profile,
+            direction,
+            components,
+        })
+    }
+
+    /// The profile a link names, by id and then by name.
+    pub fn profile(&self) -> Result<Profile, String> {
+        let Self::Run { profile: named, .. } = self;
+        if let Ok(profile) = profile::load(named) {
+            return Ok(profile);
+        }
+        profile::list()?
+            .into_iter()
+            .find(|profile| profile.name == *named)
+            .ok_or_else(|| messages::text("error.no_profile_id_name_named", &[("named", &named)]))
+    }
+}
+
+/// The first of `args` that is a `wpsync://` link, as the OS passes one.
+pub fn find(args: &[String]) -> Option<&str> {
+    let prefix = format!("{SCHEME}:");
+    args.iter().map(String::as_str).find(|arg| {
+        arg.get(..prefix.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(&prefix))
+    })
+}
+
+/// Make this binary the handler of `wpsync://` links for the current user.
+/// It's started with the link as its only argument.
+#[cfg(target_os = "windows")]
+pub fn register() -> Result<(), String> {
+    use std::process::Command;
+
+    let exe = std::env::current_exe()
+        .map_err(|e| messages::text("error.failed.locate_app_binary", &[("error", &e)]))?;
+    let key = format!(r"HKCU\Software\Classes\{SCHEME}");
+    let command = format!("\"{}\" \"%1\"", exe.display());
+    for args in [
+        vec!["add", &key, "/ve", "/d", "URL:WordPress Sync", "/f"],
+        vec!["add", &key, "/v", "URL Protocol", "/d", "", "/f"],
+        vec![
+            "add",
+            &format!(r"{key}\shell\open\command"),
+            "/ve",
+            "/d",
+            &command,
+            "/f",
+        ],
+    ] {
+        let output = Command::new("reg")
+            .args(&args)
+            .output()
+            .map_err(|e| messages::text("error.failed.run_reg", &[("error", &e)]))?;
+        if !output.status.success() {
+            return Err(messages::text(
+                "error.reg_args_failed_stderr",
+                &[
+                    ("args", &args.join(" ")),
+                    ("stderr", &String::from_utf8_lossy(&output.stderr).trim()),
+                ],
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Make this binary the handler of `wpsync://` links for the current user,
+/// with a desktop entry that only handles the scheme.
+#[cfg(target_os = "linux")]
+pub fn register() -> Result<(), String> {
+    use std::fs;
+    use std::process::Command;
+
+    const DESKTOP_FILE: &str = "wordpress-sync-handler.desktop";
+    let exe = std::env::current_exe()
+        .map_err(|e| messages::text("error.failed.locate_app_binary", &[("error", &e)]))?;
+    let dir = dirs::data_dir()
+        .map(|dir| dir.join("applications"))
+        .ok_or_else(|| messages::text("error.could_not_determine_data_directory", &[]))?;
+    // Desktop entries quote their arguments much as unit files do.
+    let exec = format!(
+        "\"{}\"",
+        exe.display()
+            .to_string()
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('%', "%%")
+    );
+    let entry = format!(
+        "[Desktop Entry]\nType=Application\nName=WordPress Sync\nExec={exec} %u\n\
+         NoDisplay=true\nMimeType=x-scheme-handler/{SCHEME};\n"
+    );
+    let path = dir.join(DESKTOP_FILE);
+    if fs::read_to_string(&path).is_ok_and(|existing| existing == entry) {
+        return Ok(());
+    }
+    fs::create_dir_all(&dir).map_err(|e| {
+        messages::text(
+            "error.failed.create_path",
+            &[("path", &dir.display()), ("error", &e)],
+        )
+    })?;
+    fs::write(&path, entry).map_err(|e| {
+        messages::text(
+            "error.failed.write_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })?;
+    let output = Command::new("xdg-mime")
+        .args([
+            "default",
+            DESKTOP_FILE,
+            &format!("x-scheme-handler/{SCHEME}"),
+        ])
+        .output()
+        .map_err(|e| messages::text("error.failed.run_xdg_mime", &[("error", &e)]))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(messages::text(
+            "error.xdg_mime_failed_stderr",
+            &[("stderr", &String::from_utf8_lossy(&output.stderr).trim())],
+        ))
+    }
+}
+
+/// On macOS the scheme is declared in the app bundle, so there's nothing to do.
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+pub fn register() -> Result<(), String> {
+    Ok(())
+}
@@ -0,0 +1,237 @@
+//! The error type every command returns to the frontend.
+//!
+//! The backend modules mostly fail with plain `String` messages; commands and
+//! the sync engine's entry points sort those into a [`SyncError`] by where they
+//! happened, as the [`TEXT`](messages::TEXT) message, or make one from a
+//! catalog [`Message`] of their own. The frontend receives `{ code, message, message_id, params, hint,
+//! retryable }`, so it can show what to do about an error, offer a retry only
+//! when one might help, and show the message in a locale of its own from its
+//! id and parameters. An `in_progress` error also carries the `job_id` of the
+//! sync in the way. Messages and hints, like the rest of the backend's text,
+//! are in the current locale.
+
+use std::borrow::Cow;
+use std::fmt;
+
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+
+use crate::messages::{self, Message};
+use crate::ssh::ConnectError;
+
+/// What went wrong, with a message saying exactly what.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncError {
+    /// The server couldn't be reached, or its host key isn't trusted.
+    Connection(Message),
+    /// The server rejected every authentication method.
+    Auth(Message),
+    /// A command on the server (or a local wp-cli) failed.
+    RemoteCommand(Message),
+    /// Copying files failed.
+    Transfer(Message),
+    /// Exporting, rewriting, or importing a database failed.
+    Database(Message),
+    /// A profile, setting, or file of the app's own is missing or invalid.
+    Config(Message),
+    /// A machine the sync writes to hasn't room for what it would receive.
+    DiskSpace(Message),
+    /// A push to a protected profile wasn't confirmed, or its confirmation
+    /// was wrong, used, or out of date.
+    ConfirmationRequired(Message),
+    /// The sync would start in one of the profile's blackout windows.
+    Blackout(Message),
+    /// A pull would overwrite uncommitted changes in the local site's git
+    /// repository.
+    UncommittedChanges(Message),
+    /// The sync ran into a warning the profile's warning policy stops it for.
+    WarningBlocked(Message),
+    /// The user cancelled the job.
+    Cancelled,
+    /// Another sync of the same profile, in this app or another process, holds
+    /// its lock.
+    InProgress {
+        message: Message,
+        /// The job holding the lock.
+        job_id: String,
+    },
+}
+
+impl SyncError {
+    // Each kind of error from a catalog message or the engine's text.
+
+    pub fn connection(message: impl Into<Message>) -> Self {
+        Self::Connection(message.into())
+    }
+
+    pub fn auth(message: impl Into<Message>) -> Self {
+        Self::Auth(message.into())
+    }
+
+    pub fn remote_command(message: impl Into<Message>) -> Self {
+        Self::RemoteCommand(message.into())
+    }
+
+    pub fn transfer(message: impl Into<Message>) -> Self {
+        Self::Transfer(message.into())
+    }
+
+    pub fn database(message: impl Into<Message>) -> Self {
+        Self::Database(message.into())
+    }
+
+    pub fn config(message: impl Into<Message>) -> Self {
+        Self::Config(message.into())
+    }
+
+    pub fn disk_space(message: impl Into<Message>) -> Self {
+        Self::DiskSpace(message.into())
+    }
+
+    pub fn confirmation_required(message: impl Into<Message>) -> Self {
+        Self::ConfirmationRequired(message.into())
+    }
+
+    pub fn blackout(message: impl Into<Message>) -> Self {
+        Self::Blackout(message.into())
+    }
+
+    pub fn uncommitted_changes(message: impl Into<Message>) -> Self {
+        Self::UncommittedChanges(message.into())
+    }
+
+    pub fn warning_blocked(message: impl Into<Message>) -> Self {
+        Self::WarningBlocked(message.into())
+    }
+
+    /// Stable identifier for the frontend to match on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Connection(_) => "connection",
+            Self::Auth(_) => "auth",
+            Self::RemoteCommand(_) => "remote_command",
+            Self::Transfer(_) => "transfer",
+            Self::Database(_) => "database",
+            Self::Config(_) => "config",
+            Self::DiskSpace(_) => "disk_space",
+            Self::ConfirmationRequired(_) => "confirmation_required",
+            Self::Blackout(_) => "blackout",
+            Self::UncommittedChanges(_) => "uncommitted_changes",
+            Self::WarningBlocked(_) => "warning_blocked",
+            Self::Cancelled => "cancelled",
+            Self::InProgress { .. } => "in_progress",
+        }
+    }
+
+    /// The message and its parameters.
+    pub fn catalog_message(&self) -> Cow<'_, Message> {
+        Cow::Borrowed(match self {
+            Self::Connection(message)
+            | Self::Auth(message)
+            | Self::RemoteCommand(message)
+            | Self::Transfer(message)
+            | Self::Database(message)
+            | Self::Config(message)
+            | Self::DiskSpace(message)
+            | Self::ConfirmationRequired(message)
+            | Self::Blackout(message)
+            | Self::UncommittedChanges(message)
+            | Self::WarningBlocked(message)
+            | Self::InProgress { message, .. } => message,
+            Self::Cancelled => return Cow::Owned(Message::new("error.cancelled", &[])),
+        })
+    }
+
+    /// What went wrong, in the current locale.
+    pub fn message(&self) -> String {
+        self.catalog_message().to_string()
+    }
+
+    /// What the user can do about it, in the current locale.
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            Self::Cancelled => None,
+            _ => Some(messages::text(&format!("error.{}.hint", self.code()), &[])),
+        }
+    }
+
+    /// Whether the same request might succeed if simply tried again. Problems
+    /// that need the user to change something first aren't.
+    pub fn retryable(&self) -> bool {
+        match self {
+            Self::Connection(_)
+            | Self::RemoteCommand(_)
+            | Self::Transfer(_)
+            | Self::Database(_)
+            | Self::InProgress { .. } => true,
+            Self::Auth(_)
+            | Self::Config(_)
+            | Self::DiskSpace(_)
+            | Self::ConfirmationRequired(_)
+            | Self::Blackout(_)
+            | Self::UncommittedChanges(_)
+            | Self::WarningBlocked(_)
+            | Self::Cancelled => false,
+        }
+    }
+
+    /// Rebuild an error from its [`code`](Self::code) and message, as stored in the history.
+    pub fn from_code(code: &str, message: impl Into<Message>) -> Option<Self> {
+        let message = message.into();
+        Some(match code {
+            "connection" => Self::Connection(message),
+            "auth" => Self::Auth(message),
+            "remote_command" => Self::RemoteCommand(message),
+            "transfer" => Self::Transfer(message),
+            "database" => Self::Database(message),
+            "config" => Self::Config(message),
+            "disk_space" => Self::DiskSpace(message),
+            "confirmation_required" => Self::ConfirmationRequired(message),
+            "blackout" => Self::Blackout(message),
+            "uncommitted_changes" => Self::UncommittedChanges(message),
+            "warning_blocked" => Self::WarningBlocked(message),
+            "cancelled" => Self::Cancelled,
+            // The history doesn't keep the other job's id.
+            "in_progress" => Self::InProgress {
+                message,
+                job_id: String::new(),
+            },
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.catalog_message().fmt(f)
+    }
+}
+
+impl std::error::Error for SyncError {}
+
+impl Serialize for SyncError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut error = serializer.serialize_struct("SyncError", 7)?;
+        let message = self.catalog_message();
+        error.serialize_field("code", self.code())?;
+        error.serialize_field("message", &self.message())?;
+        error.serialize_field("message_id", &message.id)?;
+        error.serialize_field("params", &message.params)?;
+        error.serialize_field("hint", &self.hint())?;
+        error.serialize_field("retryable", &self.retryable())?;
+        match self {
+            Self::InProgress { job_id, .. } => error.serialize_field("job_id", job_id)?,
+            _ => error.skip_field("job_id")?,
+        }
+        error.end()
+    }
+}
+
+impl From<ConnectError> for SyncError {
+    fn from(error: ConnectError) -> Self {
+        match error {
+            ConnectError::AuthFailed(message) => Self::Auth(message.into()),
+            other => Self::Connection(other.message()),
+        }
+    }
+}
@@ -0,0 +1,318 @@
+//! Sync history: one record per sync job, kept in an SQLite database at
+//! `~/.wordpress-sync/history.sqlite` so the GUI can show each site's timeline.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::error::SyncError;
+use crate::messages;
+use crate::paths;
+use crate::sync::{Components, Direction, Phase, PhaseReport, ResourceUsage, ScreenshotDiff};
+
+/// Version of the database schema written by this build, kept in `PRAGMA user_version`.
+const SCHEMA_VERSION: i64 = 6;
+
+/// The statements that bring a database from one schema version to the next;
+/// `MIGRATIONS[n]` upgrades version `n` to `n + 1`.
+const MIGRATIONS: [&str; SCHEMA_VERSION as usize] = [
+    SCHEMA,
+    "ALTER TABLE jobs ADD COLUMN error_code TEXT;",
+    "ALTER TABLE jobs ADD COLUMN screenshots TEXT;",
+    "ALTER TABLE jobs ADD COLUMN git_head TEXT;",
+    "ALTER TABLE phases ADD COLUMN bytes_per_second INTEGER;",
+    "ALTER TABLE jobs ADD COLUMN resources TEXT;",
+];
+
+const SCHEMA: &str = "
+CREATE TABLE jobs (
+    id TEXT PRIMARY KEY,
+    profile_id TEXT NOT NULL,
+    profile_name TEXT NOT NULL,
+    direction TEXT NOT NULL,
+    components TEXT NOT NULL,
+    started_at TEXT NOT NULL,
+    finished_at TEXT NOT NULL,
+    status TEXT NOT NULL,
+    error TEXT,
+    bytes_transferred INTEGER NOT NULL,
+    files_transferred INTEGER NOT NULL,
+    warnings TEXT NOT NULL,
+    restore_point_id TEXT
+);
+CREATE INDEX jobs_by_profile ON jobs (profile_id, started_at);
+CREATE TABLE phases (
+    job_id TEXT NOT NULL REFERENCES jobs (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    phase TEXT NOT NULL,
+    duration_ms INTEGER NOT NULL,
+    bytes_transferred INTEGER NOT NULL,
+    files_transferred INTEGER NOT NULL,
+    PRIMARY KEY (job_id, position)
+);
+";
+
+/// How long to wait for another writer (a second window, say) to finish.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The default and largest number of entries [`list`] returns.
+pub const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 1000;
+
+/// How a sync job ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+/// Everything recorded about one sync job.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryEntry {
+    pub job_id: String,
+    pub profile_id: String,
+    /// The profile's name at the time, so entries stay readable after it is renamed or deleted.
+    pub profile_name: String,
+    pub direction: Direction,
+    pub components: Components,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub status: JobStatus,
+    /// Why the job failed; `None` unless `status` is `failed` or `cancelled`.
+    pub error: Option<SyncError>,
+    /// The phases that completed, in order.
+    pub phases: Vec<PhaseReport>,
+    pub bytes_transferred: u64,
+    pub files_transferred: u64,
+    pub warnings: Vec<String>,
+    pub restore_point_id: Option<String>,
+    /// The commit the local site's git repository was at before a pull.
+    pub git_head: Option<String>,
+    /// The screenshots of a push's pages, before and after it.
+    pub screenshots: Vec<ScreenshotDiff>,
+    /// How much of this machine the job used; `None` for jobs from before
+    /// it was measured.
+    pub resources: Option<ResourceUsage>,
+}
+
+/// Add a finished job to the history.
+pub fn record(entry: &HistoryEntry) -> Result<(), String> {
+    let mut conn = open()?;
+    let failed =
+        |e: rusqlite::Error| messages::text("error.failed.record_sync_history", &[("error", &e)]);
+    let tx = conn.transaction().map_err(failed)?;
+    tx.execute(
+        "INSERT OR REPLACE INTO jobs (id, profile_id, profile_name, direction, components,
+             started_at, finished_at, status, error, error_code, bytes_transferred,
+             files_transferred, warnings, restore_point_id, screenshots, git_head, resources)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17)",
+        params![
+            entry.job_id,
+            entry.profile_id,
+            entry.profile_name,
+            text(&entry.direction),
+            text(&entry.components),
+            entry.started_at,
+            entry.finished_at,
+            text(&entry.status),
+            entry.error.as_ref().map(SyncError::message),
+            entry.error.as_ref().map(SyncError::code),
+            entry.bytes_transferred as i64,
+            entry.files_transferred as i64,
+            serde_json::to_string(&entry.warnings).unwrap_or_default(),
+            entry.restore_point_id,
+            serde_json::to_string(&entry.screenshots).unwrap_or_default(),
+            entry.git_head,
+            entry
+                .resources
+                .as_ref()
+                .and_then(|resources| serde_json::to_string(resources).ok()),
+        ],
+    )
+    .map_err(failed)?;
+    tx.execute("DELETE FROM phases WHERE job_id = ?1", [&entry.job_id])
+        .map_err(failed)?;
+    for (position, report) in entry.phases.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO phases (job_id, position, phase, duration_ms, bytes_transferred,
+                 files_transferred, bytes_per_second)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                entry.job_id,
+                position as i64,
+                text(&report.phase),
+                report.duration_ms as i64,
+                report.bytes_transferred as i64,
+                report.files_transferred as i64,
+                report.bytes_per_second.map(|rate| rate as i64),
+            ],
+        )
+        .map_err(failed)?;
+    }
+    tx.commit().map_err(failed)
+}
+
+/// The most recent jobs, newest first, for one profile or all of them.
+/// `limit` defaults to [`DEFAULT_LIMIT`].
+pub fn list(profile_id: Option<&str>, limit: Option<u32>) -> Result<Vec<HistoryEntry>, String> {
+    let conn = open()?;
+    let failed =
+        |e: rusqlite::Error| messages::text("error.failed.read_sync_history", &[("error", &e)]);
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let mut stmt = conn
+        .prepare(
+            "SELECT * FROM jobs WHERE ?1 IS NULL OR profile_id = ?1
+             ORDER BY started_at DESC LIMIT ?2",
+        )
+        .map_err(failed)?;
+    let mut entries = stmt
+        .query_map(params![profile_id, limit], entry)
+        .map_err(failed)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(failed)?;
+    for entry in &mut entries {
+        entry.phases = phases(&conn, &entry.job_id).map_err(failed)?;
+    }
+    Ok(entries)
+}
+
+/// One job by its id.
+pub fn get(job_id: &str) -> Result<HistoryEntry, String> {
+    let conn = open()?;
+    let failed =
+        |e: rusqlite::Error| messages::text("error.failed.read_sync_history", &[("error", &e)]);
+    let mut entry = conn
+        .query_row("SELECT * FROM jobs WHERE id = ?1", [job_id], entry)
+        .optional()
+        .map_err(failed)?
+        .ok_or_else(|| messages::text("error.no_sync_id_job_history", &[("job_id", &job_id)]))?;
+    entry.phases = phases(&conn, job_id).map_err(failed)?;
+    Ok(entry)
+}
+
+fn open() -> Result<Connection, String> {
+    let path = paths::history_db()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| {
+            messages::text(
+                "error.failed.create_path",
+                &[("path", &dir.display()), ("error", &e)],
+            )
+        })?;
+    }
+    let opened = |e: rusqlite::Error| {
+        messages::text(
+            "error.failed.open_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    };
+    let conn = Connection::open(&path).map_err(opened)?;
+    conn.busy_timeout(BUSY_TIMEOUT).map_err(opened)?;
+    conn.pragma_update(None, "foreign_keys", true)
+        .map_err(opened)?;
+    let version: i64 = conn
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(opened)?;
+    if version > SCHEMA_VERSION {
+        return Err(messages::text(
+            "error.path_was_written_newer_version_app",
+            &[
+                ("path", &path.display()),
+                ("version", &version),
+                ("schema_version", &SCHEMA_VERSION),
+            ],
+        ));
+    }
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let tx = conn.unchecked_transaction().map_err(opened)?;
+        tx.execute_batch(migration).map_err(opened)?;
+        tx.pragma_update(None, "user_version", from as i64 + 1)
+            .map_err(opened)?;
+        tx.commit().map_err(opened)?;
+    }
+    Ok(conn)
+}
+
+/// A `jobs` row, without its phases.
+fn entry(row: &Row) -> rusqlite::Result<HistoryEntry> {
+    let warnings: String = row.get("warnings")?;
+    Ok(HistoryEntry {
+        job_id: row.get("id")?,
+        profile_id: row.get("profile_id")?,
+        profile_name: row.get("profile_name")?,
+        direction: parse(row, "direction")?,
+        components: parse(row, "components")?,
+        started_at: row.get("started_at")?,
+        finished_at: row.get("finished_at")?,
+        status: parse(row, "status")?,
+        error: error(row)?,
+        phases: Vec::new(),
+        bytes_transferred: row.get::<_, i64>("bytes_transferred")? as u64,
+        files_transferred: row.get::<_, i64>("files_transferred")? as u64,
+        warnings: serde_json::from_str(&warnings).unwrap_or_default(),
+        restore_point_id: row.get("restore_point_id")?,
+        git_head: row.get("git_head")?,
+        screenshots: row
+            .get::<_, Option<String>>("screenshots")?
+            .and_then(|screenshots| serde_json::from_str(&screenshots).ok())
+            .unwrap_or_default(),
+        resources: row
+            .get::<_, Option<String>>("resources")?
+            .and_then(|resources| serde_json::from_str(&resources).ok()),
+    })
+}
+
+/// The error a `jobs` row records. Rows from before error codes were stored
+/// read as [`SyncError::RemoteCommand`], the most general kind.
+fn error(row: &Row) -> rusqlite::Result<Option<SyncError>> {
+    let message: Option<String> = row.get("error")?;
+    let code: Option<String> = row.get("error_code")?;
+    Ok(message.map(|message| {
+        let code = code.as_deref().unwrap_or_default();
+        SyncError::from_code(code, message.clone()).unwrap_or(SyncError::remote_command(message))
+    }))
+}
+
+fn phases(conn: &Connection, job_id: &str) -> rusqlite::Result<Vec<PhaseReport>> {
+    let mut stmt = conn.prepare(
+        "SELECT phase, duration_ms, bytes_transferred, files_transferred, bytes_per_second
+         FROM phases WHERE job_id = ?1 ORDER BY position",
+    )?;
+    // Phases recorded before their throughput was have it worked out.
+    let reports = stmt.query_map([job_id], |row| {
+        let duration_ms = row.get::<_, i64>("duration_ms")? as u64;
+        let bytes_transferred = row.get::<_, i64>("bytes_transferred")? as u64;
+        Ok(PhaseReport {
+            phase: parse::<Phase>(row, "phase")?,
+            duration_ms,
+            bytes_transferred,
+            files_transferred: row.get::<_, i64>("files_transferred")? as u64,
+            bytes_per_second: row
+                .get::<_, Option<i64>>("bytes_per_second")?
+                .map(|rate| rate as u64)
+                .or_else(|| crate::sync::throughput(bytes_transferred, duration_ms)),
+        })
+    })?;
+    reports.collect()
+}
+
+/// A unit enum as the name it has in JSON, e.g. `uploads_only`.
+pub(crate) fn text(value: &impl Serialize) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => String::new(),
+    }
+}
+
+/// The reverse of [`text`], for one column.
+fn parse<T: DeserializeOwned>(row: &Row, column: &str) -> rusqlite::Result<T> {
+    let name: String = row.get(column)?;
+    serde_json::from_value(serde_json::Value::String(name)).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
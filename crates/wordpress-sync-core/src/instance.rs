@@ -0,0 +1,146 @@
+//! Keeping the app to one running copy.
+//!
+//! The first copy listens on a loopback port and records it, with a token,
+//! in `instance.json`. A copy started after it, as when a `wpsync://` link
+//! or a shortcut launches the app again, hands its arguments to the first
+//! over that port and exits, and the first brings its window forward.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+use std::{fs, thread};
+
+use serde::{Deserialize, Serialize};
+
+use crate::messages;
+use crate::paths;
+
+/// How long to wait for the running copy to take the arguments.
+const HANDOFF_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where the running copy listens, as `instance.json` records it.
+#[derive(Serialize, Deserialize)]
+struct Listening {
+    port: u16,
+    /// Shared secret so other local processes can't send it arguments.
+    token: String,
+}
+
+/// The running copy's listener, once this copy is it.
+pub struct Instance {
+    /// `None` when the listener couldn't be set up, and later copies start
+    /// as they would have.
+    listener: Option<(TcpListener, String)>,
+}
+
+/// Hand `args` to the copy of the app already running, returning `None` once
+/// it has them. Otherwise this copy becomes the one later copies hand theirs
+/// to, and should [`serve`](Instance::serve) them.
+///
+/// A listener that can't be set up is only logged: the app then runs as it
+/// would alone.
+pub fn claim(args: &[String]) -> Option<Instance> {
+    if hand_off(args).is_ok() {
+        return None;
+    }
+    match listen() {
+        Ok(listener) => Some(Instance {
+            listener: Some(listener),
+        }),
+        Err(e) => {
+            tracing::warn!(error = %e, "failed to listen for other copies of the app");
+            Some(Instance { listener: None })
+        }
+    }
+}
+
+impl Instance {
+    /// Call `on_args` with the arguments of each later copy, in the background,
+    /// for as long as the app runs.
+    pub fn serve(self, on_args: impl Fn(Vec<String>) + Send + 'static) {
+        let Some((listener, token)) = self.listener else {
+            return;
+        };
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                match receive(stream, &token) {
+                    Ok(args) => on_args(args),
+                    Err(e) => tracing::warn!(error = %e, "rejected another copy of the app"),
+                }
+            }
+        });
+    }
+}
+
+/// Send `args` to the running copy and wait for it to take them.
+fn hand_off(args: &[String]) -> Result<(), String> {
+    let path = paths::instance_file()?;
+    let raw = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    let listening: Listening = serde_json::from_str(&raw).map_err(|e| e.to_string())?;
+    let mut stream =
+        TcpStream::connect_timeout(&([127, 0, 0, 1], listening.port).into(), HANDOFF_TIMEOUT)
+            .map_err(|e| e.to_string())?;
+    stream
+        .set_read_timeout(Some(HANDOFF_TIMEOUT))
+        .map_err(|e| e.to_string())?;
+    let args = serde_json::to_string(args).map_err(|e| e.to_string())?;
+    stream
+        .write_all(format!("{}\n{args}\n", listening.token).as_bytes())
+        .map_err(|e| e.to_string())?;
+    // A stale file's port may now be some other program's.
+    let mut reply = String::new();
+    BufReader::new(stream)
+        .read_line(&mut reply)
+        .map_err(|e| e.to_string())?;
+    if reply.trim_end() == "ok" {
+        Ok(())
+    } else {
+        Err(messages::text(
+            "error.running_copy_didnt_take_arguments",
+            &[],
+        ))
+    }
+}
+
+/// Listen on a loopback port and record it, with a new token, for later copies.
+fn listen() -> Result<(TcpListener, String), String> {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .map_err(|e| messages::text("error.failed.listen", &[("error", &e)]))?;
+    let port = listener
+        .local_addr()
+        .map_err(|e| messages::text("error.failed.listen", &[("error", &e)]))?
+        .port();
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    let json = serde_json::to_string(&Listening {
+        port,
+        token: token.clone(),
+    })
+    .map_err(|e| messages::text("error.failed.serialize_instance_file", &[("error", &e)]))?;
+    let dir = paths::config_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| {
+        messages::text(
+            "error.failed.create_path",
+            &[("path", &dir.display()), ("error", &e)],
+        )
+    })?;
+    let path = paths::instance_file()?;
+    paths::atomic_write(&path, json)?;
+    Ok((listener, token))
+}
+
+/// Read one later copy's arguments, checking its token, and tell it they arrived.
+fn receive(stream: TcpStream, expected: &str) -> Result<Vec<String>, String> {
+    stream
+        .set_read_timeout(Some(HANDOFF_TIMEOUT))
+        .map_err(|e| e.to_string())?;
+    let mut reader = BufReader::new(stream.try_clone().map_err(|e| e.to_string())?);
+    let (mut token, mut args) = (String::new(), String::new());
+    reader.read_line(&mut token).map_err(|e| e.to_string())?;
+    if token.trim_end() != expected {
+        return Err(messages::text("error.bad_token", &[]));
+    }
+    reader.read_line(&mut args).map_err(|e| e.to_string())?;
+    let args: Vec<String> = serde_json::from_str(&args).map_err(|e| e.to_string())?;
+    let _ = (&stream).write_all(b"ok\n");
+    Ok(args)
+}
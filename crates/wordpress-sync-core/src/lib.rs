@@ -0,0 +1,31 @@
+//! The WordPress Sync engine: profiles, the push/pull pipeline, restore points,
+//! schedules, and the sync history, shared by the desktop app and the `wpsync`
+//! CLI. Nothing here knows about Tauri; progress and host key prompts go
+//! through a [`ProgressSink`](sync::ProgressSink) the front end provides.
+
+pub mod byte_path;
+pub mod credentials;
+pub mod deep_link;
+pub mod error;
+pub mod history;
+pub mod instance;
+pub mod logging;
+pub mod messages;
+pub mod net;
+pub mod paths;
+pub mod profile;
+pub mod providers;
+pub mod proxy;
+pub mod schedule;
+pub mod search_replace;
+pub mod settings;
+pub mod shell;
+pub mod ssh;
+pub(crate) mod stderr;
+pub mod support;
+pub mod sync;
+pub mod telemetry;
+pub mod update;
+
+pub use schedule::{run_scheduled_sync, SCHEDULED_SYNC_FLAG};
+pub use ssh::{run_bridge_client, BRIDGE_FLAG};
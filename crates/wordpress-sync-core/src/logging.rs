@@ -0,0 +1,456 @@
+//! Structured logging with `tracing`.
+//!
+//! Everything the backend logs goes to a daily-rotated `app.log` under
+//! `~/.wordpress-sync/logs`. Events inside a sync job's span are also written
+//! to that job's own file, `logs/jobs/<job_id>.log`, which the GUI tails for its
+//! log pane and users can attach to bug reports. Only the newest job logs are
+//! kept, as many as the `job_logs_kept` setting says, and as many days of
+//! `app.log` as `app_log_days` does.
+//!
+//! A job's log can also be [followed](follow) as it is written, each line
+//! tagged with its level and the phase the job was in.
+//!
+//! The app log records the app at the `log_level` setting's level. The
+//! `WORDPRESS_SYNC_LOG` environment variable overrides its filter, in
+//! `tracing_subscriber::EnvFilter` syntax.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError, error::TryRecvError};
+use tokio::time::Instant;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::{Event, Level, Subscriber};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::{EnvFilter, Targets};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+use crate::messages;
+use crate::paths;
+use crate::settings;
+
+/// Name of the span a sync job runs in; its `job_id` field picks the job's log file.
+pub const JOB_SPAN: &str = "sync_job";
+
+const FILTER_ENV: &str = "WORDPRESS_SYNC_LOG";
+
+/// How many lines, across all jobs, a follower can fall behind by before it
+/// skips ahead.
+const FOLLOW_CAPACITY: usize = 4096;
+
+/// The most lines one [`LogBatch`] carries.
+const MAX_BATCH: usize = 500;
+
+/// How often a follower hands over a batch, at most, so a chatty job doesn't
+/// flood whoever shows the lines.
+const BATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The log files of the jobs still running, by job id. Followers read a file
+/// while holding them, so no line is missed or seen twice.
+fn open_logs() -> &'static Mutex<HashMap<String, OpenLog>> {
+    static OPEN_LOGS: OnceLock<Mutex<HashMap<String, OpenLog>>> = OnceLock::new();
+    OPEN_LOGS.get_or_init(Mutex::default)
+}
+
+/// A running job's log file, and the phase its last line was in.
+struct OpenLog {
+    file: File,
+    phase: Option<String>,
+}
+
+/// Each line written to a job log as `(job_id, Some(line))`, and
+/// `(job_id, None)` once the job has ended.
+fn live() -> &'static broadcast::Sender<(String, Option<LogLine>)> {
+    static LIVE: OnceLock<broadcast::Sender<(String, Option<LogLine>)>> = OnceLock::new();
+    LIVE.get_or_init(|| broadcast::channel(FOLLOW_CAPACITY).0)
+}
+
+/// Install the global subscriber. Logging is best effort: if the log directory
+/// can't be created, the app runs without it.
+pub fn init() {
+    let Ok(dir) = paths::logs_dir() else {
+        return;
+    };
+    let jobs_dir = dir.join("jobs");
+    if fs::create_dir_all(&jobs_dir).is_err() {
+        return;
+    }
+    let Ok(appender) = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("app")
+        .filename_suffix("log")
+        .max_log_files(settings::app_log_days())
+        .build(&dir)
+    else {
+        return;
+    };
+    let own_crate = env!("CARGO_CRATE_NAME");
+    let app_filter = EnvFilter::try_from_env(FILTER_ENV)
+        .unwrap_or_else(|_| EnvFilter::new(format!("warn,{own_crate}={}", settings::log_level())));
+    let app_log = tracing_subscriber::fmt::layer()
+        .with_ansi(false)
+        .with_writer(appender)
+        .with_filter(app_filter);
+    let job_logs =
+        JobLogs::new(jobs_dir).with_filter(Targets::new().with_target(own_crate, Level::DEBUG));
+    let _ = tracing_subscriber::registry()
+        .with(app_log)
+        .with(job_logs)
+        .try_init();
+}
+
+/// The last `tail` lines of a job's log, or all of it.
+pub fn job_log(job_id: &str, tail: Option<usize>) -> Result<String, String> {
+    let path = job_log_path(&paths::logs_dir()?.join("jobs"), job_id)?;
+    let log = match fs::read_to_string(&path) {
+        Ok(log) => log,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(messages::text(
+                "error.no_log_job_id",
+                &[("job_id", &job_id)],
+            ));
+        }
+        Err(e) => {
+            return Err(messages::text(
+                "error.failed.read_path",
+                &[("path", &path.display()), ("error", &e)],
+            ))
+        }
+    };
+    Ok(match tail {
+        Some(tail) => {
+            let lines: Vec<&str> = log.lines().collect();
+            let mut tailed = lines[lines.len().saturating_sub(tail)..].join("\n");
+            if !tailed.is_empty() {
+                tailed.push('\n');
+            }
+            tailed
+        }
+        None => log,
+    })
+}
+
+/// One line of a job's log.
+#[derive(Debug, Clone, Serialize)]
+pub struct LogLine {
+    /// When it was written, in RFC 3339.
+    pub time: String,
+    /// `ERROR`, `WARN`, `INFO`, `DEBUG`, or `TRACE`.
+    pub level: String,
+    /// The phase the job was in, as in `TransferFiles`; `None` before the first.
+    pub phase: Option<String>,
+    pub target: String,
+    pub message: String,
+}
+
+/// Lines of a followed job's log, in order.
+#[derive(Debug, Clone, Serialize)]
+pub struct LogBatch {
+    pub job_id: String,
+    pub lines: Vec<LogLine>,
+    /// At most this many lines were dropped just before these, as the
+    /// follower fell behind.
+    pub skipped: u64,
+}
+
+/// Follows one job's log; see [`follow`].
+pub struct JobLogFollower {
+    job_id: String,
+    /// What the log held when following began, not yet handed over.
+    backlog: VecDeque<LogLine>,
+    /// `None` once the job has ended.
+    live: Option<broadcast::Receiver<(String, Option<LogLine>)>>,
+    last_batch: Option<Instant>,
+}
+
+/// Follow a job's log, running or finished: the lines it has so far, then
+/// each line as it is written, until the job ends.
+pub fn follow(job_id: &str) -> Result<JobLogFollower, String> {
+    let path = job_log_path(&paths::logs_dir()?.join("jobs"), job_id)?;
+    // Writers wait while the file is read, so the backlog and the live lines meet exactly.
+    let open = open_logs().lock().unwrap();
+    let running = open.contains_key(job_id);
+    let log = match fs::read_to_string(&path) {
+        Ok(log) => log,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && running => String::new(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(messages::text(
+                "error.no_log_job_id",
+                &[("job_id", &job_id)],
+            ));
+        }
+        Err(e) => {
+            return Err(messages::text(
+                "error.failed.read_path",
+                &[("path", &path.display()), ("error", &e)],
+            ))
+        }
+    };
+    let live = running.then(|| live().subscribe());
+    drop(open);
+    let mut phase = None;
+    let backlog = log
+        .lines()
+        .filter_map(|line| parse_line(line, &mut phase))
+        .collect();
+    Ok(JobLogFollower {
+        job_id: job_id.to_string(),
+        backlog,
+        live,
+        last_batch: None,
+    })
+}
+
+impl JobLogFollower {
+    /// The next lines, waiting for them if need be and handing over a batch
+    /// at most every [`BATCH_INTERVAL`], or `None` once the job has ended and
+    /// every line has been handed over.
+    pub async fn next_batch(&mut self) -> Option<LogBatch> {
+        if let Some(last) = self.last_batch {
+            tokio::time::sleep_until(last + BATCH_INTERVAL).await;
+        }
+        let mut batch = LogBatch {
+            job_id: self.job_id.clone(),
+            lines: Vec::new(),
+            skipped: 0,
+        };
+        if self.backlog.is_empty() {
+            // Wait for the first line, then take whatever else has arrived.
+            let live = self.live.as_mut()?;
+            loop {
+                match live.recv().await {
+                    Ok((job_id, _)) if job_id != self.job_id => {}
+                    Ok((_, Some(line))) => {
+                        batch.lines.push(line);
+                        break;
+                    }
+                    Ok((_, None)) | Err(RecvError::Closed) => {
+                        self.live = None;
+                        return None;
+                    }
+                    Err(RecvError::Lagged(skipped)) => batch.skipped += skipped,
+                }
+            }
+        }
+        let room = MAX_BATCH - batch.lines.len();
+        let from_backlog = room.min(self.backlog.len());
+        batch.lines.extend(self.backlog.drain(..from_backlog));
+        while batch.lines.len() < MAX_BATCH && self.backlog.is_empty() {
+            let Some(live) = self.live.as_mut() else {
+                break;
+            };
+            match live.try_recv() {
+                Ok((job_id, _)) if job_id != self.job_id => {}
+                Ok((_, Some(line))) => batch.lines.push(line),
+                Ok((_, None)) | Err(TryRecvError::Closed) => self.live = None,
+                Err(TryRecvError::Lagged(skipped)) => batch.skipped += skipped,
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+        self.last_batch = Some(Instant::now());
+        Some(batch)
+    }
+}
+
+/// A line of a job's log file, parsed back into its parts. `phase` carries
+/// the phase the lines before it left the job in.
+fn parse_line(line: &str, phase: &mut Option<String>) -> Option<LogLine> {
+    let (time, rest) = line.split_once(' ')?;
+    let (level, rest) = rest.trim_start().split_once(' ')?;
+    let (target, message) = rest.split_once(": ")?;
+    if let Some(named) = phase_of(message) {
+        *phase = Some(named);
+    }
+    Some(LogLine {
+        time: time.to_string(),
+        level: level.to_string(),
+        phase: phase.clone(),
+        target: target.to_string(),
+        message: message.to_string(),
+    })
+}
+
+/// The phase a line names in its `phase` field, which moves the job on to it.
+fn phase_of(message: &str) -> Option<String> {
+    let (_, rest) = message.split_once(" phase=")?;
+    let phase = rest.split(' ').next()?;
+    Some(phase.to_string())
+}
+
+/// The log file for a job id, refusing ids that could escape the directory.
+fn job_log_path(dir: &Path, job_id: &str) -> Result<PathBuf, String> {
+    let valid = !job_id.is_empty()
+        && job_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(messages::text(
+            "error.invalid_job_id",
+            &[("job_id", &format_args!("{job_id:?}"))],
+        ));
+    }
+    Ok(dir.join(format!("{job_id}.log")))
+}
+
+/// Writes the events of each job span to that job's file, in
+/// [`open_logs`] while the span is alive, and to its followers.
+struct JobLogs {
+    dir: PathBuf,
+}
+
+/// Marks a job span with its job id.
+struct JobLog(String);
+
+impl JobLogs {
+    fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn open(&self, job_id: &str) {
+        let Ok(path) = job_log_path(&self.dir, job_id) else {
+            return;
+        };
+        if let Ok(file) = File::options().create(true).append(true).open(path) {
+            let log = OpenLog { file, phase: None };
+            open_logs().lock().unwrap().insert(job_id.to_string(), log);
+            self.prune();
+        }
+    }
+
+    fn write(&self, job_id: &str, level: Level, target: &str, message: &str) {
+        let mut open = open_logs().lock().unwrap();
+        let Some(log) = open.get_mut(job_id) else {
+            return;
+        };
+        let time = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+        // A log line that can't be written isn't worth failing the sync over.
+        let _ = writeln!(log.file, "{time} {level:>5} {target}: {message}");
+        if let Some(phase) = phase_of(message) {
+            log.phase = Some(phase);
+        }
+        if live().receiver_count() > 0 {
+            let line = LogLine {
+                time,
+                level: level.to_string(),
+                phase: log.phase.clone(),
+                target: target.to_string(),
+                message: message.to_string(),
+            };
+            let _ = live().send((job_id.to_string(), Some(line)));
+        }
+    }
+
+    fn close(&self, job_id: &str) {
+        open_logs().lock().unwrap().remove(job_id);
+        let _ = live().send((job_id.to_string(), None));
+    }
+
+    /// Delete all but the newest job logs, as many as the settings keep.
+    fn prune(&self) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        let mut logs: Vec<_> = entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let modified = entry.metadata().and_then(|m| m.modified()).ok()?;
+                Some((modified, entry.path()))
+            })
+            .filter(|(_, path)| path.extension().is_some_and(|ext| ext == "log"))
+            .collect();
+        logs.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+        for (_, path) in logs.into_iter().skip(settings::job_logs_kept()) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+impl<S> Layer<S> for JobLogs
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if attrs.metadata().name() != JOB_SPAN {
+            return;
+        }
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        let Some(job_id) = fields.take("job_id") else {
+            return;
+        };
+        if let Some(span) = ctx.span(id) {
+            self.open(&job_id);
+            let mut line = format!("job {job_id} started");
+            fields.append_to(&mut line);
+            self.write(&job_id, Level::INFO, attrs.metadata().target(), &line);
+            span.extensions_mut().insert(JobLog(job_id));
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let Some(scope) = ctx.event_scope(event) else {
+            return;
+        };
+        for span in scope {
+            let extensions = span.extensions();
+            if let Some(JobLog(job_id)) = extensions.get::<JobLog>() {
+                let mut fields = Fields::default();
+                event.record(&mut fields);
+                let mut line = fields.take("message").unwrap_or_default();
+                fields.append_to(&mut line);
+                let meta = event.metadata();
+                self.write(job_id, *meta.level(), meta.target(), &line);
+                return;
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let extensions = span.extensions();
+        if let Some(JobLog(job_id)) = extensions.get::<JobLog>() {
+            self.close(job_id);
+        }
+    }
+}
+
+/// The fields of a span or event, in the order they were recorded.
+#[derive(Default)]
+struct Fields(Vec<(&'static str, String)>);
+
+impl Fields {
+    fn take(&mut self, name: &str) -> Option<String> {
+        let i = self.0.iter().position(|(field, _)| *field == name)?;
+        Some(self.0.remove(i).1)
+    }
+
+    /// Append the remaining fields as ` name=value` pairs.
+    fn append_to(&self, line: &mut String) {
+        for (name, value) in &self.0 {
+            let _ = write!(line, " {name}={value}");
+        }
+    }
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.push((field.name(), value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.push((field.name(), format!("{value:?}")));
+    }
+}
@@ -0,0 +1,1598 @@
+//! The text the backend shows people — error hints, phase names, reports, and
+//! notifications — as a catalog of messages keyed by id, with `{name}`
+//! placeholders for their parameters.
+//!
+//! English is built in. Another locale's catalog is read from
+//! `~/.wordpress-sync/locales/<locale>.json`, an object of ids to texts, and
+//! any message it lacks is shown in English. The locale is a setting, so the
+//! CLI and scheduled syncs speak the app's language too; the frontend can
+//! fetch the whole [`catalog`] to show the ids it's given.
+//!
+//! Errors and job warnings carry the [`Message`] they were made from, so the
+//! frontend gets their id and parameters as well as their text. The engine's
+//! own failures still pass around as text in the current locale; those are
+//! the [`TEXT`] message.
+
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display};
+use std::fs;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{paths, settings};
+
+/// The locale whose catalog is built in.
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// The current locale and its catalog, loaded the first time a message is
+/// shown and again whenever the locale changes.
+static CURRENT: RwLock<Option<Arc<Catalog>>> = RwLock::new(None);
+
+/// The message of text no other message makes.
+pub const TEXT: &str = "text";
+
+/// The built-in English catalog.
+const EN: &[(&str, &str)] = &[
+    (TEXT, "{text}"),
+    ("error.cancelled", "Sync cancelled"),
+    ("error.no_running_sync", "No running sync with id {job_id}"),
+    ("error.sync_already_running", "Sync {job_id} is already running"),
+    ("error.touch_id_didnt_confirm_push", "Touch ID didn't confirm the push"),
+    ("error.profile_already_watched", "{name} is already being watched"),
+    ("error.profile_isnt_watched", "Profile {profile_id} isn't being watched"),
+    (
+        "error.protected_push_needs_app",
+        "{name} is protected: confirm the push in the app",
+    ),
+    ("error.no_profile_named", "No profile with id or name {key}"),
+    (
+        "error.profiles_share_name",
+        "{count} profiles are named {key}; use the id instead",
+    ),
+    (
+        "error.remote_rsync_missing",
+        "{program} didn't run as rsync on the server",
+    ),
+    (
+        "error.profile_sync_in_progress",
+        "A sync of this profile is already in progress",
+    ),
+    (
+        "error.profile_sync_in_progress_job",
+        "A sync of this profile is already in progress (job {job_id})",
+    ),
+    (
+        "error.connection.hint",
+        "Check the host name, port, and network connection. If the server's host key is new or \
+         has changed, confirm it before trying again.",
+    ),
+    (
+        "error.auth.hint",
+        "Check the user name and authentication method, that the key is loaded in your SSH \
+         agent, or store the password again.",
+    ),
+    (
+        "error.remote_command.hint",
+        "Check that wp-cli is installed on both machines and that each path points at a \
+         WordPress root.",
+    ),
+    (
+        "error.transfer.hint",
+        "Check free disk space and file permissions on the destination. rsync transfers need \
+         rsync installed on both machines.",
+    ),
+    (
+        "error.database.hint",
+        "Check the database settings in wp-config.php and that `wp db check` works for that \
+         install.",
+    ),
+    (
+        "error.config.hint",
+        "Fix the profile or setting named in the message.",
+    ),
+    (
+        "error.disk_space.hint",
+        "Free up space where the message says, or exclude large directories from the sync.",
+    ),
+    (
+        "error.confirmation_required.hint",
+        "The server is protected: confirm the push by typing the profile's name, then start it \
+         again.",
+    ),
+    (
+        "error.blackout.hint",
+        "Wait until the blackout window is over, or start the sync anyway if it can't wait.",
+    ),
+    (
+        "error.uncommitted_changes.hint",
+        "Commit or stash the changes, or have the profile stash or commit them before each pull.",
+    ),
+    (
+        "error.warning_blocked.hint",
+        "Deal with what the warning says, or change the profile's warning policy to let the sync \
+         go ahead.",
+    ),
+    (
+        "error.in_progress.hint",
+        "Wait for the other sync to finish, or cancel it.",
+    ),
+    (
+        "error.no_ssh",
+        "This profile has no SSH access to the server",
+    ),
+    (
+        "error.no_ssh.database_sync",
+        "This profile has no SSH access to the server, so its database can't be synced",
+    ),
+    (
+        "error.no_ssh.database_read",
+        "This profile has no SSH access to the server, so its database can't be read",
+    ),
+    (
+        "error.no_ssh.database_query",
+        "This profile has no SSH access to the server, so its database can't be queried",
+    ),
+    (
+        "error.no_ssh.wp_config",
+        "This profile has no SSH access to the server, so its wp-config.php can't be read",
+    ),
+    (
+        "error.no_ssh.environment",
+        "This profile has no SSH access to the server, so its environment can't be detected",
+    ),
+    (
+        "error.no_ssh.commands",
+        "This profile has no SSH access to the server, so remote commands can't be run",
+    ),
+    (
+        "error.no_ssh.wp_cli_install",
+        "This profile has no SSH access to the server, so wp-cli can't be installed there",
+    ),
+    (
+        "error.no_ssh.browse",
+        "This profile has no SSH access to the server, so its files can't be browsed",
+    ),
+    (
+        "error.no_ssh.plugins",
+        "This profile has no SSH access to the server, so wp-cli can't list its plugins",
+    ),
+    (
+        "error.no_ssh.server_sync",
+        "{name} has no SSH access to its server, so it can't be synced with another server",
+    ),
+    (
+        "error.no_ssh.maintenance",
+        "Maintenance mode was not enabled: the profile has no SSH access to the server",
+    ),
+    ("phase.connect", "Connect"),
+    ("phase.dump_database", "Dump database"),
+    ("phase.search_replace", "Search and replace"),
+    ("phase.anonymize", "Anonymize"),
+    ("phase.backup", "Backup"),
+    ("phase.transfer_files", "Transfer files"),
+    ("phase.sync_content", "Sync content"),
+    ("phase.import_database", "Import database"),
+    ("phase.post_sync", "Post-sync"),
+    ("phase.finalize", "Finalize"),
+    ("direction.push", "Push"),
+    ("direction.pull", "Pull"),
+    ("components.all", "All"),
+    ("components.files", "Files"),
+    ("components.uploads_only", "Uploads only"),
+    ("components.database", "Database"),
+    ("status.succeeded", "Succeeded"),
+    ("status.failed", "Failed"),
+    ("status.cancelled", "Cancelled"),
+    ("notification.push.succeeded", "Pushed {name}"),
+    ("notification.pull.succeeded", "Pulled {name}"),
+    ("notification.push.failed", "Push of {name} failed"),
+    ("notification.pull.failed", "Pull of {name} failed"),
+    ("notification.push.cancelled", "Push of {name} cancelled"),
+    ("notification.pull.cancelled", "Pull of {name} cancelled"),
+    ("notification.moved", "{size} in {duration}"),
+    ("notification.file_changed", "1 file changed"),
+    ("notification.files_changed", "{count} files changed"),
+    ("notification.warning", "1 warning"),
+    ("notification.warnings", "{count} warnings"),
+    ("notification.couldnt_start_sync", "Couldn't start the sync"),
+    ("notification.couldnt_open_link", "Couldn't open the link"),
+    ("tray.recent_syncs", "Recent syncs"),
+    ("tray.run", "Run"),
+    ("tray.cancel_running_syncs", "Cancel running syncs"),
+    ("tray.open", "Open WordPress Sync"),
+    ("tray.quit", "Quit"),
+    ("tray.no_syncs_running", "No syncs running"),
+    ("tray.syncing", "Syncing"),
+    ("tray.job_phase", "{job}: {phase}"),
+    ("tray.job_percent", "{job} ({percent}%)"),
+    ("tray.job_and_more", "{job}, and {count} more"),
+    ("tray.pushing", "Pushing {name}"),
+    ("tray.pulling", "Pulling {name}"),
+    ("link.run_this_sync", "Run this sync?"),
+    (
+        "link.asks_to_push",
+        "A link asks to push the {what} of {name} to its server.",
+    ),
+    (
+        "link.asks_to_pull",
+        "A link asks to pull the {what} of {name} from its server.",
+    ),
+    ("link.components.all", "files and database"),
+    ("link.components.files", "files"),
+    ("link.components.uploads_only", "uploads"),
+    ("link.components.database", "database"),
+    ("link.cancel", "Cancel"),
+    ("touch_id.push_to_url", "push to {url}"),
+    ("report.summary", "Summary"),
+    ("report.phases", "Phases"),
+    ("report.warnings", "Warnings"),
+    ("report.planned_and_done", "Planned and done"),
+    ("report.screenshots", "Screenshots"),
+    ("report.resources", "Resources used"),
+    ("report.profile", "Profile"),
+    ("report.direction", "Direction"),
+    ("report.components", "Components"),
+    ("report.status", "Status"),
+    ("report.started", "Started"),
+    ("report.finished", "Finished"),
+    ("report.duration", "Duration"),
+    ("report.data_moved", "Data moved"),
+    ("report.files_changed", "Files changed"),
+    ("report.error", "Error"),
+    ("report.restore_point", "Restore point"),
+    ("report.git_head", "Git commit before"),
+    ("report.job", "Job"),
+    ("report.phase", "Phase"),
+    ("report.data", "Data"),
+    ("report.files", "Files"),
+    ("report.page", "Page"),
+    ("report.changed", "Changed"),
+    ("report.before", "Before"),
+    ("report.after", "After"),
+    ("report.what", "What"),
+    ("report.planned", "Planned"),
+    ("report.done", "Done"),
+    ("report.no_warnings", "None."),
+    (
+        "report.no_plan",
+        "No dry run was made in the day before this sync.",
+    ),
+    ("report.files_copied", "Files copied"),
+    ("report.file_data", "File data"),
+    ("report.files_deleted", "Files deleted"),
+    ("report.tables_written", "Tables written"),
+    ("report.values_replaced", "Values replaced"),
+    ("report.not_run", "not run"),
+    ("report.imported", "imported"),
+    ("report.cpu_time", "CPU time"),
+    ("report.peak_cpu", "Peak CPU"),
+    ("report.peak_memory", "Peak memory"),
+    ("report.network", "Network"),
+    ("report.peak_rate", "Fastest phase"),
+    ("report.parallel_transfers", "Parallel transfers"),
+    ("report.import_workers", "Import workers"),
+    ("report.compression", "Compression"),
+    ("report.unknown", "unknown"),
+    ("report.not_imported", "not imported"),
+    (
+        "error.no_password_stored_proxy_account",
+        "No password is stored for the proxy {password_account}",
+    ),
+    (
+        "error.no_host_key_fingerprint_awaiting_confirmation",
+        "No host key with fingerprint {fingerprint} is awaiting confirmation",
+    ),
+    (
+        "error.key_path_passphrase_protected_enter",
+        "Key {path} is passphrase-protected; enter its passphrase",
+    ),
+    (
+        "error.key_passphrase_protected_unlock_app_add",
+        "the key is passphrase-protected; unlock it in the app or add it to your SSH agent",
+    ),
+    ("error.timed_out_connecting_host_port_via", "Timed out connecting to {host}:{port}{via}"),
+    (
+        "error.reference_isnt_secret_such_keychain_prod",
+        "{reference} isn't a reference to a secret, such as keychain:prod-db",
+    ),
+    ("error.no_cloudflare_api_token_stored_name", "No Cloudflare API token is stored for {name}"),
+    ("error.database_dump_server_corrupt", "The database dump from the server is corrupt: {error}"),
+    (
+        "error.unexpected_output_wp_search_replace",
+        "Unexpected output from wp search-replace: {output}",
+    ),
+    ("error.name_server_cant_reach_name2", "{name}'s server can't reach {name2}: {error}"),
+    ("error.no_sudo_password_stored_name", "No sudo password is stored for {name}"),
+    (
+        "error.listing_local_media_returned_unexpected_output",
+        "Listing the local media returned unexpected output: {error}",
+    ),
+    ("error.no_database_password_stored_name", "No database password is stored for {name}"),
+    (
+        "error.database_import_failed_line_number_dump",
+        "The database import failed at line {line_number} of the dump: {error}",
+    ),
+    ("error.invalid_link", "Invalid link {link}: {error}"),
+    ("error.link_isnt_scheme", "{link} isn't a {scheme}:// link"),
+    ("error.unknown_scheme_action", "Unknown {scheme}:// action '{action}'"),
+    ("error.link_doesnt_name_profile", "{link} doesn't name a profile"),
+    ("error.unknown_direction_other_link", "Unknown direction '{other}' in {link}"),
+    ("error.unknown_components_link", "Unknown components in {link}"),
+    ("error.no_profile_id_name_named", "No profile with the id or name '{named}'"),
+    ("error.failed.locate_app_binary", "Failed to locate the app binary: {error}"),
+    ("error.failed.run_reg", "Failed to run reg: {error}"),
+    ("error.reg_args_failed_stderr", "reg {args} failed: {stderr}"),
+    ("error.could_not_determine_data_directory", "Could not determine the data directory"),
+    ("error.failed.create_path", "Failed to create {path}: {error}"),
+    ("error.failed.write_path", "Failed to write {path}: {error}"),
+    ("error.failed.run_xdg_mime", "Failed to run xdg-mime: {error}"),
+    ("error.xdg_mime_failed_stderr", "xdg-mime failed: {stderr}"),
+    ("error.failed.record_sync_history", "Failed to record sync history: {error}"),
+    ("error.failed.read_sync_history", "Failed to read sync history: {error}"),
+    ("error.no_sync_id_job_history", "No sync with id {job_id} in the history"),
+    ("error.failed.open_path", "Failed to open {path}: {error}"),
+    (
+        "error.path_was_written_newer_version_app",
+        "{path} was written by a newer version of the app (schema {version}, this build reads up \
+         to {schema_version})",
+    ),
+    ("error.running_copy_didnt_take_arguments", "The running copy didn't take the arguments"),
+    ("error.failed.listen", "Failed to listen: {error}"),
+    ("error.failed.serialize_instance_file", "Failed to serialize the instance file: {error}"),
+    ("error.bad_token", "bad token"),
+    ("error.no_log_job_id", "No log for job {job_id}"),
+    ("error.failed.read_path", "Failed to read {path}: {error}"),
+    ("error.invalid_locale", "Invalid locale {locale}"),
+    ("error.invalid_catalog_path", "Invalid catalog {path}: {error}"),
+    ("error.invalid_job_id", "Invalid job id {job_id}"),
+    ("error.could_not_resolve_host", "Could not resolve {host}: {error}"),
+    (
+        "error.host_key_changed",
+        "The host key for {host} has CHANGED (now {algorithm} {fingerprint}). This may be a \
+         man-in-the-middle attack; only trust the new key if you know the server was rebuilt or \
+         rekeyed",
+    ),
+    (
+        "error.host_key_unverified",
+        "The authenticity of {host} can't be established ({algorithm} key {fingerprint}). \
+         Confirm the fingerprint to trust it",
+    ),
+    (
+        "error.host_certificate_unsupported",
+        "{host} presented a host certificate, which is not supported",
+    ),
+    ("error.host_has_no_name_addresses", "{host} has no {name} addresses"),
+    ("error.could_not_determine_home_directory", "Could not determine the home directory"),
+    ("error.proxys_host_required", "The proxy's host is required"),
+    ("error.proxys_port_must_between_1_65535", "The proxy's port must be between 1 and 65535"),
+    ("error.proxys_username_too_long", "The proxy's username is too long"),
+    ("error.invalid_proxy_host", "Invalid proxy {host}: {error}"),
+    ("error.invalid_proxy_host_2", "Invalid proxy {host}"),
+    ("error.invalid_proxy_settings_path", "Invalid proxy settings {path}: {error}"),
+    ("error.failed.remove_path", "Failed to remove {path}: {error}"),
+    ("error.failed.serialize_proxy_settings", "Failed to serialize the proxy settings: {error}"),
+    ("error.failed.reach_proxy_host_port", "Failed to reach the proxy {host}:{port}: {error}"),
+    ("error.proxy_host_port", "The proxy {host}:{port} {error}"),
+    ("error.dropped_connection", "dropped the connection: {error}"),
+    ("error.sent_too_long_reply", "sent too long a reply"),
+    ("error.refused_username_password", "refused the username and password"),
+    ("error.refused_connect_target_status_line", "refused to connect to {target}: {status_line}"),
+    ("error.cant_sent_password_that_long", "can't be sent a password that long"),
+    ("error.wants_username_password", "wants a username and password"),
+    ("error.accepts_none_ways_logging_offered", "accepts none of the ways of logging in offered"),
+    ("error.isnt_socks5_proxy", "isn't a SOCKS5 proxy"),
+    ("error.cant_sent_name_long_host", "can't be sent a name as long as {host}"),
+    ("error.sent_reply_that_isnt_socks5", "sent a reply that isn't SOCKS5"),
+    ("error.name_must_whole_number_min_max", "{name} must be a whole number from {min} to {max}"),
+    ("error.name_must_true_false", "{name} must be true or false"),
+    ("error.name_must_one_log_levels", "{name} must be one of {log_levels}"),
+    (
+        "error.theres_no_message_catalog_locale_add",
+        "There's no message catalog for {locale}; add one to the locales directory",
+    ),
+    ("error.name_must_locale_such_en_de", "{name} must be a locale such as en or de"),
+    ("error.name_set_whole_app_not_per", "{name} is set for the whole app, not per profile"),
+    ("error.invalid_settings_path", "Invalid settings {path}: {error}"),
+    ("error.failed.serialize_settings", "Failed to serialize the settings: {error}"),
+    ("error.failed.read_usage_metrics", "Failed to read the usage metrics: {error}"),
+    ("error.failed.clear_usage_metrics", "Failed to clear the usage metrics: {error}"),
+    (
+        "error.usage_metrics_are_only_sent_once",
+        "Usage metrics are only sent once the usage_metrics setting is on",
+    ),
+    ("error.this_build_app_doesnt_send_usage", "This build of the app doesn't send usage metrics"),
+    ("error.failed.set_up_http", "Failed to set up HTTP: {error}"),
+    ("error.failed.send_usage_metrics_url", "Failed to send usage metrics to {url}: {error}"),
+    ("error.failed.count_name", "Failed to count {name}: {error}"),
+    ("error.failed.note_usage_metrics_sent", "Failed to note the usage metrics sent: {error}"),
+    ("error.this_build_app_doesnt_look_updates", "This build of the app doesn't look for updates"),
+    ("error.invalid_update_endpoint", "Invalid update endpoint {url}: {error}"),
+    ("error.app_already_up_date", "The app is already up to date"),
+    ("error.failed.check_updates", "Failed to look for updates: {error}"),
+    ("error.failed.install_update", "Failed to install the update: {error}"),
+    ("error.failed.download_url", "Failed to download {url}: {error}"),
+    ("error.invalid_update_settings_path", "Invalid update settings {path}: {error}"),
+    ("error.failed.serialize_update_settings", "Failed to serialize the update settings: {error}"),
+    ("error.no_ssh_agent_running", "No SSH agent is running"),
+    (
+        "error.failed.connect_ssh_agent_sock",
+        "Failed to connect to the SSH agent at {sock}: {error}",
+    ),
+    ("error.failed.start_ssh_bridge", "Failed to start SSH bridge: {error}"),
+    ("error.rejected_ssh_bridge_client_bad_token", "Rejected SSH bridge client with a bad token"),
+    ("error.invalid_port_alias_ssh_config", "Invalid Port {port} for {alias} in ~/.ssh/config"),
+    ("error.too_many_nested_includes_path", "Too many nested Includes at {path}"),
+    ("error.failed.update_known_hosts", "Failed to update known_hosts: {error}"),
+    ("error.failed.read_known_hosts", "Failed to read known_hosts: {error}"),
+    ("error.failed.read_key_path", "Failed to read key {path}: {error}"),
+    ("error.wrong_passphrase_key_path", "Wrong passphrase for key {path}"),
+    (
+        "error.stored_passphrase_doesnt_decrypt_key_unlock",
+        "the stored passphrase doesn't decrypt the key; unlock it again",
+    ),
+    (
+        "error.login_isnt_waiting_these_answers_any",
+        "The login isn't waiting for these answers any more",
+    ),
+    ("error.server_kept_asking_questions", "the server kept asking questions"),
+    (
+        "error.server_asks_value_which_needs_answering",
+        "the server asks {value}, which needs answering in the app",
+    ),
+    ("error.login_was_cancelled", "the login was cancelled"),
+    ("error.no_answer_came_servers_questions", "no answer came to the server's questions"),
+    (
+        "error.server_asked_count_questions_but_count2",
+        "the server asked {count} questions, but {count2} answers came",
+    ),
+    ("error.failed.open_ssh_channel", "Failed to open SSH channel: {error}"),
+    ("error.failed.request_agent_forwarding", "Failed to request agent forwarding: {error}"),
+    ("error.failed.start_remote_command", "Failed to start remote command: {error}"),
+    (
+        "error.server_wouldnt_forward_host_port",
+        "The server wouldn't forward to {host}:{port}: {error}",
+    ),
+    ("error.failed.start_name_subsystem", "Failed to start the {name} subsystem: {error}"),
+    ("error.failed.send_input_command", "Failed to send input to `{command}`: {error}"),
+    ("error.failed.connect_host_port_via", "Failed to connect to {host}:{port}{via}: {error}"),
+    (
+        "error.no_ssh_authentication_methods_are_available",
+        "No SSH authentication methods are available for {user}@{host}",
+    ),
+    (
+        "error.authentication_failed_user_host_tried",
+        "Authentication failed for {user}@{host}. Tried: {tried}",
+    ),
+    ("error.failed.list_agent_keys", "Failed to list agent keys: {error}"),
+    ("error.failed.open_tunnel_host_port", "Failed to open a tunnel to {host}:{port}: {error}"),
+    ("error.failed.connect_secret_service", "Failed to connect to the Secret Service: {error}"),
+    ("error.failed.open_default_keyring", "Failed to open the default keyring: {error}"),
+    ("error.failed.unlock_default_keyring", "Failed to unlock the default keyring: {error}"),
+    ("error.failed.store_credential", "Failed to store credential: {error}"),
+    (
+        "error.failed.store_credential_touch_id",
+        "Failed to store credential: Touch ID protection needs a signed build of the app",
+    ),
+    ("error.failed.retrieve_credential", "Failed to retrieve credential: {error}"),
+    ("error.failed.unlock_credential", "Failed to unlock credential: {error}"),
+    ("error.invalid_utf_8", "Invalid UTF-8: {error}"),
+    ("error.failed.delete_credential", "Failed to delete credential: {error}"),
+    ("error.failed.list_credentials", "Failed to list credentials: {error}"),
+    ("error.failed.look_up_credential", "Failed to look up credential: {error}"),
+    ("error.no_secret_named_name_stored", "No secret named {name} is stored"),
+    ("error.old_new_service_are_same", "The old and new service are the same"),
+    (
+        "error.failed.store_credential_windows_code",
+        "Failed to store credential: Windows error {code}",
+    ),
+    (
+        "error.failed.retrieve_credential_windows_code",
+        "Failed to retrieve credential: Windows error {code}",
+    ),
+    (
+        "error.failed.delete_credential_windows_code",
+        "Failed to delete credential: Windows error {code}",
+    ),
+    (
+        "error.failed.list_credentials_windows_code",
+        "Failed to list credentials: Windows error {code}",
+    ),
+    (
+        "error.no_name_credentials_are_stored_store",
+        "No {name} credentials are stored; store its {token_format}",
+    ),
+    (
+        "error.stored_name_credentials_should_token_format",
+        "The stored {name} credentials should be its {token_format}",
+    ),
+    ("error.failed.reach_name_api", "Failed to reach the {name} API: {error}"),
+    (
+        "error.name_api_turned_down_stored_credentials",
+        "The {name} API turned down the stored credentials ({status})",
+    ),
+    ("error.name_api_failed_status", "The {name} API failed: {status}"),
+    (
+        "error.name_api_returned_something_other_than",
+        "The {name} API returned something other than JSON: {error}",
+    ),
+    (
+        "error.profiles_bases_extend_each_other_more",
+        "The profile's bases extend each other more than {max_depth} deep, or in a circle",
+    ),
+    ("error.there_no_profile_base_named_name", "There is no profile base named {name}"),
+    ("error.invalid_profile_base_path", "Invalid profile base {path}: {error}"),
+    (
+        "error.invalid_profile_base_path_expected_object",
+        "Invalid profile base {path}: expected an object of settings",
+    ),
+    ("error.invalid_profile_base_name", "Invalid profile base name {name}"),
+    (
+        "error.invalid_profile_path_expected_object_settings",
+        "Invalid profile {path}: expected an object of settings",
+    ),
+    ("error.invalid_profile_path_version", "Invalid profile {path}: invalid version"),
+    ("error.profile_field_wrong_kind", "{field} has the wrong kind of value"),
+    (
+        "error.profile_path_was_saved_newer_version",
+        "Profile {path} was saved by a newer version of the app (format {version}, this build \
+         reads up to {profile_version})",
+    ),
+    ("error.failed.back_up_path", "Failed to back up {path}: {error}"),
+    (
+        "error.failed.upgrade_profile_path_format",
+        "Failed to upgrade profile {path} from format {from}: {error}",
+    ),
+    ("error.failed.serialize_profile", "Failed to serialize profile: {error}"),
+    ("error.invalid_table_pattern_raw", "Invalid table pattern {raw}: {error}"),
+    ("error.host_missing_closing", "{host} is missing its closing `]`"),
+    (
+        "error.host_socket_which_cant_reached_through",
+        "{host} is a socket, which can't be reached through a tunnel",
+    ),
+    ("error.port_host_not", "{port} in {host} is not a port"),
+    ("error.failed.list_path", "Failed to list {path}: {error}"),
+    ("error.failed.delete_path", "Failed to delete {path}: {error}"),
+    ("error.failed.read_profile_path", "Failed to read profile {path}: {error}"),
+    ("error.invalid_profile_path", "Invalid profile {path}: {error}"),
+    ("error.invalid_profile_id", "Invalid profile id {id}"),
+    ("error.name_has_no_preset_named_name2", "{name} has no preset named {name2}"),
+    ("error.path_isnt_exported_profile", "{path} isn't an exported profile: {error}"),
+    ("error.path_isnt_exported_profile_2", "{path} isn't an exported profile"),
+    (
+        "error.exported_profile_outside_seal",
+        "{path} has its profile outside what the passphrase seals, which only older exports \
+         do, so it has been changed since it was exported",
+    ),
+    (
+        "error.path_was_exported_newer_version_app",
+        "{path} was exported by a newer version of the app (format {version}, this build reads up \
+         to {format_version})",
+    ),
+    (
+        "error.profile_was_exported_secrets_give_passphrase",
+        "The profile was exported with its secrets; give its passphrase",
+    ),
+    ("error.failed.serialize_secrets", "Failed to serialize the secrets: {error}"),
+    ("error.failed.encrypt_secrets", "Failed to encrypt the secrets"),
+    (
+        "error.exported_key_costs_out_of_range",
+        "The exported file asks for key derivation costs this app doesn't use",
+    ),
+    ("error.exported_secrets_are_corrupt", "The exported secrets are corrupt"),
+    (
+        "error.passphrase_wrong_exported_secrets_are_corrupt",
+        "The passphrase is wrong, or the exported secrets are corrupt",
+    ),
+    ("error.invalid_key_derivation_parameters", "Invalid key derivation parameters: {error}"),
+    ("error.failed.derive_key_passphrase", "Failed to derive a key from the passphrase: {error}"),
+    ("error.there_no_site_id", "There is no site {site_id}"),
+    ("error.failed.replace_path", "Failed to replace {path}: {error}"),
+    ("error.mail_servers_host_required", "The mail server's host is required"),
+    (
+        "error.least_one_address_send_reports_required",
+        "At least one address to send reports to is required",
+    ),
+    ("error.address_not_email", "{address} is not an email address"),
+    (
+        "error.logging_mail_server_needs_encrypted_connection",
+        "Logging in to the mail server needs an encrypted connection (TLS or STARTTLS)",
+    ),
+    ("error.invalid_email_settings_path", "Invalid email settings {path}: {error}"),
+    ("error.failed.serialize_email_settings", "Failed to serialize the email settings: {error}"),
+    ("error.no_smtp_password_stored_username", "No SMTP password is stored for {username}"),
+    ("error.failed.read_smtp_password", "Failed to read the SMTP password: {error}"),
+    ("error.mail_server_took_more_than_timeout", "The mail server took more than {timeout}s"),
+    ("error.failed.reach_mail_server_host", "Failed to reach the mail server {host}: {error}"),
+    ("error.invalid_mail_server_host", "Invalid mail server host {host}: {error}"),
+    ("error.failed.set_up_tls_host", "Failed to set up TLS with {host}: {error}"),
+    ("error.failed.log_mail_server", "Failed to log in to the mail server: {error}"),
+    ("error.failed.write_mail_server", "Failed to write to the mail server: {error}"),
+    ("error.failed.read_mail_server", "Failed to read from the mail server: {error}"),
+    ("error.mail_server_closed_connection", "The mail server closed the connection"),
+    ("error.mail_server_answered_line", "The mail server answered {line}"),
+    ("error.mail_server_answered_code_text", "The mail server answered {code} {text}"),
+    ("error.failed.run_launchctl", "Failed to run launchctl: {error}"),
+    ("error.launchctl_args_failed_stderr", "launchctl {args} failed: {stderr}"),
+    ("error.profile_id_has_no_schedule", "Profile {profile_id} has no schedule"),
+    ("error.invalid_cron_expression", "Invalid cron expression {cron}: {error}"),
+    ("error.invalid_schedules_file_path", "Invalid schedules file {path}: {error}"),
+    ("error.failed.serialize_schedules", "Failed to serialize schedules: {error}"),
+    ("error.could_not_determine_config_directory", "Could not determine the config directory"),
+    ("error.failed.run_systemctl", "Failed to run systemctl: {error}"),
+    ("error.systemctl_user_args_failed_stderr", "systemctl --user {args} failed: {stderr}"),
+    ("error.failed.run_schtasks", "Failed to run schtasks: {error}"),
+    ("error.schtasks_args_failed_stderr", "schtasks {args} failed: {stderr}"),
+    ("error.failed.compress_name", "Failed to compress {name}: {error}"),
+    ("error.name_too_long", "{name} is too long"),
+    (
+        "warning.destinations_plugins_were_not_switched_off",
+        "The destination's plugins were not switched on or off: it has no wp-cli",
+    ),
+    (
+        "warning.destinations_plugins_were_not_all_switched",
+        "The destination's plugins were not all switched on or off: {error}",
+    ),
+    (
+        "warning.plugins_activate_destination_arent_installed_there",
+        "Plugins to activate on the destination aren't installed there: {missing}",
+    ),
+    ("error.failed.encrypt_path", "Failed to encrypt {path}: {error}"),
+    ("error.failed.decrypt_path", "Failed to decrypt {path}: {error}"),
+    (
+        "error.path_isnt_encrypted",
+        "{path} isn't encrypted, as everything the app keeps is, so it isn't read",
+    ),
+    ("error.encryption_key_isnt_available", "The encryption key isn't available: {error}"),
+    ("error.stored_encryption_key_invalid", "The stored encryption key is invalid"),
+    ("error.invalid_audit_log_path", "Invalid audit log {path}: {error}"),
+    ("error.failed.serialize_audit_entry", "Failed to serialize the audit entry: {error}"),
+    (
+        "warning.restore_points_row_counts_werent_recorded",
+        "The restore point's row counts weren't recorded, so a rollback to it can't check them: \
+         {error}",
+    ),
+    (
+        "error.restore_point_cant_be_encrypted",
+        "The restore point can't be encrypted, so it isn't saved: {error}",
+    ),
+    ("error.failed.restore_files_archive", "Failed to restore files from {archive}: {error}"),
+    ("error.failed.read_restore_point_path", "Failed to read restore point {path}: {error}"),
+    ("error.invalid_restore_point_path", "Invalid restore point {path}: {error}"),
+    ("error.failed.serialize_restore_point", "Failed to serialize restore point: {error}"),
+    ("error.invalid_restore_point_id", "Invalid restore point id {id}"),
+    (
+        "error.name_doesnt_sync_start_end_days",
+        "{name} doesn't sync from {start} to {end}{days}{until}",
+    ),
+    ("error.failed.find_path_server", "Failed to find {path} on the server: {error}"),
+    ("error.failed.list_path_over_sftp", "Failed to list {path} over SFTP: {error}"),
+    (
+        "warning.large_deletion_of_server_files",
+        "The sync would delete {deleted} of the {files} files on the server",
+    ),
+    (
+        "warning.large_deletion_of_local_files",
+        "The sync would delete {deleted} of the {files} files on this machine",
+    ),
+    (
+        "warning.large_deletion_from_server",
+        "The sync would delete {deleted} files from the server",
+    ),
+    (
+        "warning.large_deletion_from_local",
+        "The sync would delete {deleted} files from this machine",
+    ),
+    (
+        "warning.server_changed_tables_and_files",
+        "The server changed after the last sync, at {since} UTC: the push will overwrite its \
+         tables {tables} and files",
+    ),
+    (
+        "warning.server_changed_tables",
+        "The server changed after the last sync, at {since} UTC: the push will overwrite its \
+         tables {tables}",
+    ),
+    (
+        "warning.server_changed_files",
+        "The server changed after the last sync, at {since} UTC: the push will overwrite its files",
+    ),
+    (
+        "warning.remote_rsync_missing_using_sftp",
+        "{program} didn't run as rsync on the server, so files are copied over SFTP instead",
+    ),
+    ("warning.health_check_failed", "Health check of {url} failed: {error}"),
+    ("warning.screenshot_failed", "The screenshot of {path} failed: {error}"),
+    (
+        "warning.screenshot_looks_different",
+        "{path} looks {changed}% different after the push",
+    ),
+    (
+        "warning.page_differs_between_sites",
+        "{path} differs between the sites: {differences}",
+    ),
+    ("warning.cdns_cache_was_not_purged", "The CDN's cache was not purged: {error}"),
+    ("error.url_has_no_host_name", "{url} has no host name"),
+    (
+        "error.cloudflare_api_token_cant_see_zone",
+        "The Cloudflare API token can't see a zone for {host}; give the zone's id",
+    ),
+    (
+        "error.cloudflare_answered_status_something_unexpected",
+        "Cloudflare answered {status} with something unexpected: {error}",
+    ),
+    ("error.cloudflare_answered_status_errors", "Cloudflare answered {status}: {errors}"),
+    (
+        "warning.destinations_database_server_has_no_collation",
+        "The destination's database server has no {collation} or other {from} collation, which the \
+         source's tables use; the import may fail",
+    ),
+    (
+        "warning.destinations_database_server_has_no_collation_2",
+        "The destination's database server has no {collation} nor a stand-in for it, which the \
+         source's tables use; the import may fail",
+    ),
+    (
+        "warning.destination_has_no_collation_normalize_off",
+        "The destination's database server has no {collation}, which the source's tables use; \
+         the import may fail (normalize_collations is off)",
+    ),
+    (
+        "warning.destination_has_no_charset",
+        "The destination's database server has no {charset}; its tables will use {to}, which \
+         can't store emoji and other 4-byte characters",
+    ),
+    (
+        "warning.destination_has_newer_orders",
+        "The destination has orders up to {newest} UTC, which the sync will replace; the \
+         source's newest is from {source} UTC",
+    ),
+    (
+        "warning.destination_has_orders_source_has_none",
+        "The destination has orders up to {newest} UTC, which the sync will replace; the source \
+         has none",
+    ),
+    (
+        "warning.payment_gateways_not_switched_no_wp_cli",
+        "The destination's payment gateways were NOT switched to test mode: it has no wp-cli, so \
+         it may take live payments",
+    ),
+    (
+        "warning.composer_install_failed_destination",
+        "`composer install` failed on the destination: {error}",
+    ),
+    ("error.type_name_confirm_pushing_url", "Type {name} to confirm pushing to {url}"),
+    ("error.push_confirmation_has_expired", "The push confirmation has expired"),
+    ("error.push_confirmation_another_profile", "The push confirmation is for another profile"),
+    (
+        "error.name_protected_so_pushing_needs_confirming",
+        "{name} is protected, so pushing to it needs confirming first",
+    ),
+    ("error.there_no_query_run", "There is no query to run"),
+    ("error.console_runs_one_statement_time", "The console runs one statement at a time"),
+    (
+        "error.read_only_query_cant_have_mysqls",
+        "A read-only query can't have MySQL's executable comments in it",
+    ),
+    (
+        "error.read_only_query_can_reading_turn",
+        "A read-only query can only be {reading}; turn read-only off to run {first}",
+    ),
+    (
+        "error.read_only_query_cant_use_word",
+        "A read-only query can't use {word}; turn read-only off to run it",
+    ),
+    ("error.query_has_unclosed_c", "The query has an unclosed {c}"),
+    ("error.query_has_unclosed_comment", "The query has an unclosed comment"),
+    (
+        "error.last_days_have_count_posts_attachments",
+        "The last {days} days have {count} posts and attachments, more than the {max_rows} a \
+         content sample takes; sample fewer days",
+    ),
+    (
+        "warning.no_posts_were_modified_server_last",
+        "No posts were modified on the server in the last {days} days, so the local site will have \
+         none",
+    ),
+    (
+        "error.sampled_posts_have_count_comments_more",
+        "The sampled posts have {count} comments, more than the {max_rows} a content sample takes; \
+         sample fewer days",
+    ),
+    ("error.there_no_plugin_named_id", "There is no plugin named {id}"),
+    ("error.invalid_plugin_manifest_path", "Invalid plugin manifest {path}: {error}"),
+    ("error.plugin_id_has_no_script", "The plugin {id} has no {script}"),
+    ("error.invalid_plugin_name_id", "Invalid plugin name {id}"),
+    ("error.name_has_no_step", "{name} has no step {step}"),
+    ("error.script_stopped_unexpectedly", "Its script stopped unexpectedly"),
+    (
+        "error.profile_has_no_shell_server_run",
+        "The profile has no shell on the server to run commands in",
+    ),
+    ("error.invalid_path", "Invalid {path}: {error}"),
+    (
+        "error.profiles_table_rules_dont_select_any",
+        "The profile's table rules don't select any tables",
+    ),
+    ("error.failed.save_database_dump", "Failed to save the database dump: {error}"),
+    ("error.database_dump_server_was_cut_short", "The database dump from the server was cut short"),
+    ("error.failed.send_database_dump", "Failed to send the database dump: {error}"),
+    (
+        "error.unexpected_table_sizes_database_rows",
+        "Unexpected table sizes from the database: {rows}",
+    ),
+    (
+        "error.unexpected_table_stats_database_rows",
+        "Unexpected table stats from the database: {rows}",
+    ),
+    ("error.stored_secret_didnt_read_back", "A stored secret didn't read back"),
+    ("error.path_isnt_writable", "{path} isn't writable: {error}"),
+    ("error.failed.list_profiles", "Failed to list the profiles: {error}"),
+    ("error.invalid_server_fingerprint_path", "Invalid server fingerprint {path}: {error}"),
+    (
+        "error.failed.serialize_server_fingerprint",
+        "Failed to serialize the server fingerprint: {error}",
+    ),
+    (
+        "warning.servers_state_wasnt_recorded_so_next",
+        "The server's state wasn't recorded, so the next push can't tell whether it changed: \
+         {error}",
+    ),
+    ("error.this_local_install", "This is the local install"),
+    ("error.failed.read_remote_environment", "Failed to read the remote environment: {error}"),
+    ("error.job_id_has_no_events_too", "Job {job_id} has no events, or is too old"),
+    ("error.failed.serialize_job_event", "Failed to serialize a job event: {error}"),
+    (
+        "error.unexpected_output_wp_command_list",
+        "Unexpected output from wp {command} list: {error}",
+    ),
+    ("error.invalid_pattern", "Invalid pattern {pattern}: {error}"),
+    (
+        "error.job_id_has_no_file_manifest",
+        "Job {job_id} has no file manifest: it copied no files, or is too old",
+    ),
+    ("error.invalid_file_manifest_path", "Invalid file manifest {path}: {error}"),
+    ("error.failed.serialize_file_manifest", "Failed to serialize the file manifest: {error}"),
+    (
+        "error.failed.check_local_git_repository",
+        "Failed to check the local git repository: {error}",
+    ),
+    (
+        "error.path_has_uncommitted_changes_changed_files",
+        "{path} has uncommitted changes to {changed} files, which the pull could overwrite",
+    ),
+    (
+        "warning.stashed_uncommitted_changes_changed_files_path",
+        "Stashed the uncommitted changes to {changed} files in {path}; `git stash pop` brings them \
+         back",
+    ),
+    (
+        "warning.committed_uncommitted_changes_changed_files_path",
+        "Committed the uncommitted changes to {changed} files in {path} before pulling",
+    ),
+    ("error.request_failed", "Request failed: {error}"),
+    ("error.failed.read_response", "Failed to read the response: {error}"),
+    (
+        "error.command_isnt_one_profiles_vetted_remote",
+        "`{command}` isn't one of the profile's vetted remote commands",
+    ),
+    (
+        "warning.total_references_other_hosts_over_http",
+        "{total} references to other hosts over http:// remain, which browsers block or flag as \
+         mixed content on an HTTPS site: {named}",
+    ),
+    ("error.failed.start_what", "Failed to start {what}: {error}"),
+    ("error.failed.read_what_output", "Failed to read {what} output: {error}"),
+    ("error.failed.save_what_output", "Failed to save {what} output: {error}"),
+    ("error.failed.send_path_what", "Failed to send {path} to {what}: {error}"),
+    ("error.failed.send_input_what", "Failed to send input to {what}: {error}"),
+    ("error.failed.wait_what", "Failed to wait for {what}: {error}"),
+    ("error.what_failed_status_stderr", "{what} failed ({status}): {stderr}"),
+    ("error.what_returned_unexpected_output", "{what} returned unexpected output: {error}"),
+    ("error.failed.lock_path", "Failed to lock {path}: {error}"),
+    (
+        "error.destination_still_maintenance_mode",
+        "The destination is still in maintenance mode: {error}",
+    ),
+    (
+        "error.this_profiles_media_offloaded_bucket_so",
+        "This profile's media is offloaded to a bucket, so it has no uploads to sync",
+    ),
+    ("warning.old_restore_points_were_not_pruned", "Old restore points were not pruned: {error}"),
+    (
+        "warning.count_files_werent_copied_their_names",
+        "{count} files weren't copied: their names aren't UTF-8, which SFTP and FTP can't send, \
+         though rsync can: {shown}{value2}",
+    ),
+    (
+        "warning.count_symlinks_werent_copied_shown_value2",
+        "{count} symlinks weren't copied: {shown}{value2}",
+    ),
+    (
+        "warning.count_copied_files_dont_match_their",
+        "{count} copied files don't match their source: {shown}{value2}",
+    ),
+    ("warning.wp_args_failed_destination", "`wp {args}` failed on the destination: {error}"),
+    ("warning.object_cache_was_not_flushed", "The object cache was not flushed: {error}"),
+    (
+        "error.source_other_but_destination_network",
+        "The source is {other} but the destination is {network}",
+    ),
+    (
+        "error.profile_syncs_subsite_id_but_installs",
+        "The profile syncs subsite {id}, but the installs aren't a multisite network",
+    ),
+    ("error.source_has_no_tables_subsite_id", "The source has no tables for subsite {id}"),
+    ("error.program_arg_failed", "{program} {arg} failed"),
+    (
+        "warning.destinations_options_were_not_set_has",
+        "The destination's options were not set: it has no wp-cli",
+    ),
+    (
+        "warning.option_name_was_not_set_destination",
+        "The option {name} was not set on the destination: {error}",
+    ),
+    ("error.failed.split_database_dump", "Failed to split the database dump: {error}"),
+    ("error.failed.import_table", "Failed to import table {table}: {error}"),
+    (
+        "warning.copied_files_modes_owner_werent_set",
+        "The copied files' modes and owner weren't set: the server has no shell",
+    ),
+    (
+        "warning.copied_files_modes_owner_werent_all",
+        "The copied files' modes and owner weren't all set: {error}",
+    ),
+    ("error.invalid_plan_path", "Invalid plan {path}: {error}"),
+    ("error.failed.serialize_plan", "Failed to serialize the plan: {error}"),
+    (
+        "error.dir_missing_not_writable_destination",
+        "{dir} is missing or not writable by {destination}",
+    ),
+    ("error.path_isnt_empty", "{path} isn't empty"),
+    ("error.no_queued_sync_id", "No queued sync with id {id}"),
+    (
+        "warning.redis_object_cache_was_not_flushed",
+        "The Redis object cache was not flushed: {error}",
+    ),
+    ("error.redis_answered_reply", "Redis answered {reply}"),
+    ("error.name_name2_are_same_install", "{name} and {name2} are the same install"),
+    (
+        "warning.name_auth_keys_salts_werent_rotated",
+        "{name}'s auth keys and salts weren't rotated: {error}",
+    ),
+    ("warning.failed.flush_name_object_cache", "Failed to flush {name}'s object cache: {error}"),
+    ("error.name_source_but_name2_dest", "{name} is {source} but {name2} is {dest}"),
+    (
+        "error.name_reached_through_jump_host_which",
+        "{name} is reached through a jump host, which its server can't use",
+    ),
+    ("error.path_isnt_utf_8", "{path} isn't UTF-8"),
+    ("error.jump_host", "{error} (jump host {host})"),
+    ("error.no_ssh_password_stored", "No SSH password is stored"),
+    (
+        "error.sync_job_id_cant_resumed_finished",
+        "Sync {job_id} can't be resumed: it finished, was cancelled, or never ran",
+    ),
+    ("error.invalid_job_manifest_path", "Invalid job manifest {path}: {error}"),
+    ("error.failed.serialize_job_manifest", "Failed to serialize the job manifest: {error}"),
+    (
+        "warning.servers_auth_keys_salts_werent_rotated",
+        "The server's auth keys and salts weren't rotated: {error}",
+    ),
+    (
+        "warning.kept_destinations_wp_config_sources_names",
+        "Kept the destination's {wp_config}: the source's names a different database",
+    ),
+    ("warning.kept_destinations_wp_config", "Kept the destination's {wp_config}: {error}"),
+    (
+        "error.servers_wp_config_cant_read_without",
+        "The server's {wp_config} can't be read without SSH",
+    ),
+    ("error.url_took_over_timeout_load", "{url} took over {timeout}s to load"),
+    ("error.browser_made_no_screenshot_url", "The browser made no screenshot of {url}"),
+    (
+        "error.no_chrome_chromium_edge_was_found",
+        "No Chrome, Chromium or Edge was found; set the browser in the profile",
+    ),
+    (
+        "warning.site_search_index_was_not_rebuilt",
+        "The site search index was not rebuilt: the destination has no wp-cli",
+    ),
+    (
+        "warning.name_search_index_was_not_rebuilt",
+        "The {name} search index was not rebuilt: {error}",
+    ),
+    (
+        "error.not_enough_disk_space_machine_what",
+        "Not enough disk space on {machine} for {what}: about {wanted} is needed in {path} but \
+         only {free} is free",
+    ),
+    ("error.path_doesnt_exist", "{path} doesn't exist"),
+    ("error.failed.read_free_space_path", "Failed to read free space of {path}: {error}"),
+    ("error.unexpected_df_output", "Unexpected `df` output: {output}"),
+    ("error.invalid_two_way_sync_state_path", "Invalid two-way sync state {path}: {error}"),
+    (
+        "error.failed.serialize_two_way_sync_state",
+        "Failed to serialize the two-way sync state: {error}",
+    ),
+    (
+        "warning.count_new_changed_files_werent_copied",
+        "{count} new or changed files weren't copied to the server: {name} is protected, so only a \
+         confirmed push writes to it",
+    ),
+    (
+        "warning.count_uploads_changed_both_sides_were",
+        "{count} uploads changed on both sides were left as they are: {shown}{value2}",
+    ),
+    ("error.has_no_wp_cli", "it has no wp-cli"),
+    (
+        "warning.search_engines_werent_discouraged_destination",
+        "Search engines weren't discouraged from the destination: {error}",
+    ),
+    (
+        "warning.destinations_robots_txt_wasnt_written",
+        "The destination's robots.txt wasn't written: {error}",
+    ),
+    (
+        "error.name_protected_so_files_cant_pushed",
+        "{name} is protected, so files can't be pushed to it as they change",
+    ),
+    (
+        "error.profiles_that_sync_over_rest_api",
+        "Profiles that sync over the REST API don't transfer files",
+    ),
+    ("error.failed.watch_local_files", "Failed to watch the local files: {error}"),
+    ("error.failed.watch_path", "Failed to watch {path}: {error}"),
+    ("error.failed.reach_webhook_without_url", "Failed to reach the webhook: {without_url}"),
+    ("error.webhook_answered_status", "The webhook answered {status}"),
+    (
+        "warning.name_destination_was_not_switched_test",
+        "{name} on the destination was NOT switched to test mode, so it may take live payments: \
+         {error}",
+    ),
+    ("error.option_isnt_settings_array", "{option} isn't a settings array"),
+    (
+        "error.unexpected_output_wp_option_get",
+        "Unexpected output from wp option get {option}: {error}",
+    ),
+    ("error.server_has_no_php_wp_cli", "The server has no `php` for wp-cli to run with"),
+    (
+        "error.ssh_user_has_no_home_directory",
+        "The SSH user has no home directory to install wp-cli in",
+    ),
+    (
+        "error.downloaded_wp_cli_doesnt_match_published",
+        "The downloaded wp-cli doesn't match its published checksum (expected {expected}, got \
+         {actual})",
+    ),
+    ("error.no_application_password_stored_name", "No Application Password is stored for {name}"),
+    (
+        "error.user_cant_edit_pages_server_so",
+        "{user} can't edit pages on the server, so can't sync its content",
+    ),
+    ("error.route_returned_other_instead_list", "`{route}` returned {other} instead of a list"),
+    ("error.request_route_failed", "Request to `{route}` failed: {error}"),
+    ("error.route_failed_status_message", "`{route}` failed ({status}): {message}"),
+    (
+        "error.route_returned_something_other_than_json",
+        "`{route}` returned something other than JSON: {error}",
+    ),
+    (
+        "error.wp_post_list_returned_unexpected_output",
+        "wp post list returned unexpected output: {error}",
+    ),
+    ("error.servers_wp_config_php", "The server's wp-config.php: {error}"),
+    ("error.failed.connect_database", "Failed to connect to the database: {error}"),
+    (
+        "error.database_didnt_say_how_view_defined",
+        "The database didn't say how view {view} is defined",
+    ),
+    (
+        "error.database_didnt_say_how_table_defined",
+        "The database didn't say how table {table} is defined",
+    ),
+    ("error.failed.read_database_dump", "Failed to read the database dump: {error}"),
+    ("error.database_dump_ends_inside_string", "The database dump ends inside a string"),
+    ("error.hashing_local_files_failed", "Hashing local files failed: {error}"),
+    ("error.comparing_local_failed", "Comparing {local} failed: {error}"),
+    ("error.failed.compare_local", "Failed to compare {local}: {error}"),
+    ("error.failed.open_path_over_sftp", "Failed to open {path} over SFTP: {error}"),
+    ("error.failed.seek_path", "Failed to seek in {path}: {error}"),
+    ("error.failed.read_path_over_sftp", "Failed to read {path} over SFTP: {error}"),
+    ("error.unexpected_block_signature_path", "Unexpected block signature for {path}"),
+    ("error.failed.action_path_server_stderr", "Failed to {action} {path} on the server: {stderr}"),
+    (
+        "error.invalid_exclude_pattern_raw_nothing_match",
+        "Invalid exclude pattern {raw}: nothing to match",
+    ),
+    ("error.invalid_exclude_pattern_raw", "Invalid exclude pattern {raw}: {error}"),
+    ("error.no_ftp_password_stored_name", "No FTP password is stored for {name}"),
+    ("error.failed.upload_dest", "Failed to upload {dest}: {error}"),
+    (
+        "error.failed.move_written_dest_over_ftp",
+        "Failed to move {written} to {dest} over FTP: {error}",
+    ),
+    (
+        "error.failed.set_modification_time_dest",
+        "Failed to set modification time of {dest}: {error}",
+    ),
+    ("error.failed.resume_downloading_source", "Failed to resume downloading {source}: {error}"),
+    ("error.failed.move_written_dest", "Failed to move {written} to {dest}: {error}"),
+    ("error.failed.list_path_over_ftp", "Failed to list {path} over FTP: {error}"),
+    ("error.failed.create_path_over_ftp", "Failed to create {path} over FTP: {error}"),
+    ("error.timed_out_connecting_host_port", "Timed out connecting to {host}:{port}"),
+    ("error.failed.connect_host_port", "Failed to connect to {host}:{port}: {error}"),
+    ("error.ftp_login_failed_user_host", "FTP login failed for {user}@{host}: {error}"),
+    ("error.failed.switch_ftp_binary_mode", "Failed to switch FTP to binary mode: {error}"),
+    ("error.method_transfers_need_ssh_connection", "{method} transfers need an SSH connection"),
+    ("error.failed.list_root_server_stderr", "Failed to list {root} on the server: {stderr}"),
+    ("error.unexpected_listing_root_record", "Unexpected listing of {root}: {record}"),
+    ("error.failed.write_path_over_sftp", "Failed to write {path} over SFTP: {error}"),
+    ("error.failed.create_path_over_sftp", "Failed to create {path} over SFTP: {error}"),
+    (
+        "error.failed.move_written_dest_over_sftp",
+        "Failed to move {written} to {dest} over SFTP: {error}",
+    ),
+    ("error.failed.stat_source_over_sftp", "Failed to stat {source} over SFTP: {error}"),
+    ("error.failed.read_link_source", "Failed to read the link {source}: {error}"),
+    (
+        "error.failed.create_link_dest_server_stderr",
+        "Failed to create the link {dest} on the server: {stderr}",
+    ),
+    (
+        "error.failed.read_link_path_over_sftp",
+        "Failed to read the link {path} over SFTP: {error}",
+    ),
+    ("error.failed.create_link_dest", "Failed to create the link {dest}: {error}"),
+    ("error.failed.read_while_copying_dest", "Failed to read while copying to {dest}: {error}"),
+    ("error.failed.start_sftp_session", "Failed to start SFTP session: {error}"),
+    ("error.failed.stat_path", "Failed to stat {path}: {error}"),
+    ("issue.required", "Required"),
+    ("issue.must_be_absolute_path", "Must be an absolute path"),
+    (
+        "issue.rest_api_cant_use_container",
+        "REST API syncs run wp-cli on files here, so can't run it in a container",
+    ),
+    ("issue.custom_roots_scope_needs_root", "A custom_roots scope needs at least one root to sync"),
+    ("issue.subsite_ids_start_at_1", "Subsite ids start at 1"),
+    (
+        "issue.server_url_isnt_https",
+        "The server's URL isn't https://, so pushes won't move the site to HTTPS",
+    ),
+    ("issue.remote_hooks_need_ssh", "Remote hooks need SSH access to the server"),
+    (
+        "issue.hook_command_not_vetted",
+        "This command isn't one of the profile's vetted remote commands, so it won't run",
+    ),
+    ("issue.purge_url_isnt_http", "The purge URL isn't an http(s) URL"),
+    (
+        "issue.checksums_need_ssh",
+        "Checksums need SSH access to the server; FTP transfers compare sizes and times",
+    ),
+    (
+        "issue.permissions_need_ssh",
+        "Setting modes and owners needs SSH access to the server; they'll be left as they are",
+    ),
+    (
+        "issue.sudo_needs_ssh",
+        "sudo needs SSH access to the server; these steps will run without it, if at all",
+    ),
+    (
+        "issue.rsync_sudo_needs_no_password",
+        "rsync can't be given sudo's password; transfers need passwordless sudo",
+    ),
+    ("issue.only_rsync_runs_through_sudo", "Only rsync transfers run through sudo"),
+    ("issue.tunnel_needs_ssh", "The database tunnel runs over SSH, which this profile doesn't use"),
+    ("issue.tunnel_needs_database", "The database tunnel needs the server's database settings"),
+    ("issue.must_be_http_status", "Must be an HTTP status code, 100 to 599"),
+    ("issue.must_be_site_path", "Must be a path on the site, starting with /"),
+    (
+        "issue.composer_install_needs_composer",
+        "Only a Composer or Bedrock project runs composer install",
+    ),
+    ("issue.required_when_set", "Required when set"),
+    ("issue.must_be_pixel_size", "Must be 100 to 10000 pixels"),
+    ("issue.must_be_percentage", "Must be a percentage, 0 to 100"),
+    ("issue.must_be_nonzero_percentage", "Must be a percentage, 1 to 100"),
+    ("issue.must_be_at_least_a_day", "Must be at least 1 day"),
+    ("issue.must_be_http_url", "Must be an http:// or https:// URL"),
+    ("issue.invalid_port", "Port must be between 1 and 65535"),
+    (
+        "issue.flush_command_not_vetted",
+        "This command isn't one of the profile's vetted remote commands, so pushes won't run it",
+    ),
+    ("issue.no_profile_base_named", "There is no profile base named {name}"),
+    ("issue.path_doesnt_exist", "{path} does not exist"),
+    ("issue.path_isnt_directory", "{path} is not a directory"),
+    ("issue.no_wp_config_in_path", "No {file} in {path}"),
+    ("issue.invalid_memory_limit", "{limit} isn't a PHP memory limit, such as 512M, 2G, or -1"),
+    ("issue.duplicate_preset_name", "Another preset is named {name}"),
+    (
+        "issue.invalid_partition",
+        "{partition} isn't an uploads year (2024) or month (2024/05) folder",
+    ),
+    ("issue.invalid_time", "{time} isn't a time of day as HH:MM, such as 09:00"),
+    ("issue.invalid_table_pattern", "Invalid table pattern {pattern}: {error}"),
+    (
+        "issue.store_tables_left_out",
+        "The table rules leave out {tables}, which hold the store's products and settings",
+    ),
+    ("issue.plugin_has_no_step", "{plugin} has no step {step}"),
+    ("issue.invalid_mode", "{mode} isn't an octal mode such as 644"),
+    ("issue.invalid_owner", "{owner} isn't a user or user:group"),
+    ("issue.invalid_sudo_user", "{user} isn't a user name"),
+    ("issue.invalid_url", "Invalid URL: {error}"),
+    ("issue.no_family_addresses_found", "no {family} addresses found"),
+    ("issue.no_addresses_found", "no addresses found"),
+    ("issue.lookup_timed_out", "lookup timed out"),
+    ("issue.couldnt_resolve", "Could not resolve {host}: {error}"),
+    (
+        "issue.couldnt_resolve_alias",
+        "Could not resolve {host}: {error}. It is an alias in ~/.ssh/config for {host_name}; use \
+         that host name instead",
+    ),
+    ("preflight.connected_over_ssh", "Connected to {destination} over SSH"),
+    ("preflight.needs_connection", "Needs a connection to the server"),
+    ("preflight.logged_in_over_rest_api", "Logged in to {url} as {name} over the REST API"),
+    (
+        "preflight.rest_api_has_no_ssh",
+        "This profile syncs over the REST API, without SSH access to the server",
+    ),
+    ("preflight.logged_in_over_ftp", "Logged in to {host} over FTP"),
+    ("preflight.wp_cli_with_php", "{version} with {php}"),
+    (
+        "preflight.wp_cli_missing_database_direct",
+        "Not available on the server, so the database is reached directly: {error}",
+    ),
+    (
+        "preflight.wp_cli_can_be_installed",
+        "{error}; it can be installed on the server for this profile",
+    ),
+    ("preflight.rsync_not_needed", "Not needed for {method} transfers"),
+    ("preflight.wordpress_version_in", "WordPress {version} in {root}"),
+    ("preflight.wp_config_found", "{file} found for {root}"),
+    ("preflight.needs_wp_cli", "Needs wp-cli on the server"),
+    ("preflight.database_tables", "Connected; {count} tables"),
+    ("preflight.needs_wordpress", "Needs a WordPress install to read its settings"),
+    ("preflight.server_limits_unread", "The server's limits couldn't be read: {error}"),
+    (
+        "preflight.database_tunnel_mysql",
+        "Connected through a tunnel with this machine's mysql; {count} tables",
+    ),
+    (
+        "preflight.database_tunnel_native",
+        "Connected through a tunnel with the built-in MySQL client; {count} tables",
+    ),
+    ("preflight.local_rsync_didnt_run", "{path} didn't run as rsync on this machine"),
+    ("preflight.local_rsync_missing", "rsync isn't installed on this machine"),
+    ("preflight.remote_rsync_missing", "rsync isn't installed on the server"),
+    ("preflight.rsync_versions", "rsync {here} here ({program}), {there} on the server"),
+    (
+        "preflight.rsync_versions_without_progress",
+        "rsync {here} here ({program}), {there} on the server; without progress for the whole \
+         transfer, which needs 3.1 here",
+    ),
+    (
+        "preflight.rsync_versions_without_protected_paths",
+        "rsync {here} here ({program}), {there} on the server; without protected paths, which \
+         need 3.0 on both ends",
+    ),
+    (
+        "preflight.rsync_versions_without_either",
+        "rsync {here} here ({program}), {there} on the server; without progress for the whole \
+         transfer, which needs 3.1 here, or protected paths, which need 3.0 on both ends",
+    ),
+    ("preflight.dirs_writable", "{root} and {content_dir} are writable"),
+    ("preflight.no_inode_limit", "no inode limit"),
+    (
+        "preflight.few_inodes_free",
+        "Only {free} inodes are free on the server's file system for {root}, and each file copied \
+         takes one; ask the host to raise the quota, or delete old files such as unused image \
+         sizes",
+    ),
+    ("preflight.inodes_free", "{free} inodes free"),
+    ("preflight.no_inode_count", "no inode count"),
+    (
+        "preflight.few_open_files",
+        "The server lets a process open only {files} files (`ulimit -n`), which rsync and wp-cli \
+         can run out of in a large directory; ask the host to raise it",
+    ),
+    ("preflight.open_files", "{files} open files"),
+    ("preflight.no_open_file_limit", "no open-file limit"),
+    (
+        "preflight.few_processes",
+        "The server allows only {processes} processes (`ulimit -u`), and this profile's transfers \
+         and imports may need about {wanted}; lower `parallel_transfers` or `import_workers`",
+    ),
+    ("preflight.processes", "{processes} processes"),
+    ("preflight.no_process_limit", "no process limit"),
+    ("compare.source_didnt_answer", "The source's site didn't answer: {error}"),
+    ("compare.destination_didnt_answer", "The destination's site didn't answer: {error}"),
+    ("compare.statuses_differ", "The source answered {source}, the destination {destination}"),
+    (
+        "compare.redirects_differ",
+        "The source ended up at {source}, the destination at {destination}",
+    ),
+    ("compare.destination_shows_error", "The destination shows an error: {marker}"),
+    ("compare.titles_differ", "The source's title is {source}, the destination's {destination}"),
+    (
+        "compare.image_counts_differ",
+        "The source's page has {source} images, the destination's {destination}",
+    ),
+    ("compare.image_answers", "The destination's image {path} answers {status}"),
+    ("compare.image_didnt_load", "The destination's image {path} didn't load: {error}"),
+    ("health.unexpected_status", "Expected status {expected}, got {status}"),
+    ("health.page_shows_error", "The page shows an error: {marker}"),
+    ("doctor.rsync_version_at", "rsync {version} at {path}"),
+    ("doctor.rsync_missing_needed", "rsync isn't installed, and profiles transfer with it"),
+    ("doctor.rsync_missing_unneeded", "rsync isn't installed; no profile transfers with it"),
+    ("doctor.ssh_missing", "ssh isn't installed; syncs use their own SSH client"),
+    ("doctor.secrets_work", "Secrets can be stored, read, and removed"),
+    ("doctor.free_in_temp_dir", "{size} free in the temporary directory"),
+    ("doctor.free_in_data_dir", "{size} free in the app data directory"),
+    ("doctor.data_dir_shared", "{path} is writable by other users, who could change your profiles"),
+    ("doctor.path_writable", "{path} is writable"),
+    ("doctor.no_servers", "No profile has a server to reach"),
+    ("doctor.server_unreachable", "{host}:{port} ({error})"),
+    ("doctor.server_timed_out", "{host}:{port} (timed out)"),
+    ("doctor.reached_all_servers", "Reached all {count} servers"),
+    ("doctor.couldnt_reach_servers", "Couldn't reach {unreachable} of {count} servers: {servers}"),
+    ("doctor.profile_has_errors", "{name} has {count} errors"),
+    (
+        "doctor.config_valid",
+        "{count} profiles, the schedules, and the email and proxy settings are valid",
+    ),
+    ("error.database_query_failed", "The database query failed: {error}"),
+    ("error.custom_step_failed", "The custom step {plugin}/{step} failed: {error}"),
+    ("progress.custom_step", "{step}: {message}"),
+    ("warning.custom_step_plugin", "{plugin}: {message}"),
+    ("error.sync_has_stopped", "The sync has stopped"),
+    ("error.name_too_big_for_archive", "{name} is too big for the archive"),
+    ("error.too_many_files_for_archive", "Too many files for the archive"),
+    ("error.archive_too_big", "The archive is too big"),
+    ("error.email_reports_arent_set_up", "Email reports aren't set up"),
+    ("email.test_subject", "WordPress Sync test email"),
+    (
+        "email.test_body",
+        "This is a test from WordPress Sync. Reports of scheduled syncs will arrive like this one.",
+    ),
+    ("error.remote_command_exited", "Remote command `{command}` failed (exit {code}): {stderr}"),
+    (
+        "error.remote_command_no_exit_status",
+        "Remote command `{command}` failed (no exit status): {stderr}",
+    ),
+    ("error.constant_not_plain_string", "{constant} isn't defined as a plain string"),
+    ("error.scheduled_command_empty", "The scheduled command is empty"),
+    ("error.upload_made_no_attachment", "The server didn't say which attachment the upload made"),
+    ("error.failed.start_runtime", "Failed to start the runtime: {error}"),
+    ("error.hook_failed", "The {point} hook `{command}` failed: {error}"),
+    ("proxy.not_allowed_to", "isn't allowed to {host}:{port}"),
+    ("proxy.no_route_to_network_of", "has no route to the network of {host}:{port}"),
+    ("proxy.no_route_to", "has no route to {host}:{port}"),
+    ("proxy.refused_by", "was refused by {host}:{port}"),
+    ("proxy.timed_out_reaching", "timed out reaching {host}:{port}"),
+    ("proxy.failed_to_reach", "failed to reach {host}:{port}"),
+];
+
+/// A locale's messages, over English.
+struct Catalog {
+    locale: String,
+    texts: BTreeMap<String, String>,
+}
+
+/// A message and its parameters: what the frontend needs to show it in a
+/// locale of its own.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Message {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub params: BTreeMap<String, String>,
+}
+
+impl Message {
+    pub fn new(id: &str, params: &[(&str, &dyn Display)]) -> Self {
+        Self {
+            id: id.to_string(),
+            params: params
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Text no catalog message makes, as the [`TEXT`] message.
+    pub fn plain(text: impl Into<String>) -> Self {
+        Self {
+            id: TEXT.to_string(),
+            params: BTreeMap::from([("text".to_string(), text.into())]),
+        }
+    }
+}
+
+impl From<String> for Message {
+    fn from(text: String) -> Self {
+        Self::plain(text)
+    }
+}
+
+impl From<&str> for Message {
+    fn from(text: &str) -> Self {
+        Self::plain(text)
+    }
+}
+
+impl Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params: Vec<(&str, &dyn Display)> = self
+            .params
+            .iter()
+            .map(|(name, value)| (name.as_str(), value as &dyn Display))
+            .collect();
+        f.write_str(&text(&self.id, &params))
+    }
+}
+
+/// Message `id` in the current locale, with each `{name}` in it replaced by
+/// the parameter of that name. An id no catalog has is shown as it is.
+pub fn text(id: &str, params: &[(&str, &dyn Display)]) -> String {
+    let catalog = current();
+    let template = catalog
+        .texts
+        .get(id)
+        .map(String::as_str)
+        .or_else(|| english(id))
+        .unwrap_or(id);
+    let mut text = template.to_string();
+    for (name, value) in params {
+        text = text.replace(&format!("{{{name}}}"), &value.to_string());
+    }
+    text
+}
+
+/// The locale messages are shown in.
+pub fn locale() -> String {
+    current().locale.clone()
+}
+
+/// Show messages in `locale` from now on, and keep it for the next time the app starts.
+pub fn set_locale(locale: &str) -> Result<String, String> {
+    settings::set_setting(
+        settings::SettingKey::Locale,
+        None,
+        serde_json::Value::from(locale),
+    )?;
+    Ok(self::locale())
+}
+
+/// The locales there are catalogs for, English first.
+pub fn locales() -> Vec<String> {
+    let mut locales: Vec<String> = paths::locales_dir()
+        .ok()
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let locale = name.strip_suffix(".json")?;
+            (is_locale(locale) && locale != DEFAULT_LOCALE).then(|| locale.to_string())
+        })
+        .collect();
+    locales.sort();
+    locales.insert(0, DEFAULT_LOCALE.to_string());
+    locales
+}
+
+/// Every message in `locale`, or with `None` the current one, by id: the
+/// locale's own, and English for the rest.
+pub fn catalog(locale: Option<&str>) -> Result<BTreeMap<String, String>, String> {
+    let own = match locale {
+        Some(locale) => load(locale)?,
+        None => current().texts.clone(),
+    };
+    let mut texts: BTreeMap<String, String> = EN
+        .iter()
+        .map(|(id, text)| (id.to_string(), text.to_string()))
+        .collect();
+    texts.extend(own);
+    Ok(texts)
+}
+
+/// Whether `locale` looks like a language tag, such as `de` or `pt-BR`.
+pub fn is_locale(locale: &str) -> bool {
+    let mut parts = locale.split(['-', '_']);
+    let language = parts.next().unwrap_or_default();
+    (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && parts.all(|part| {
+            (1..=8).contains(&part.len()) && part.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// Read the locale's catalog again the next time a message is shown, as
+/// after the locale setting changes.
+pub(crate) fn reload() {
+    *CURRENT.write().unwrap() = None;
+}
+
+fn current() -> Arc<Catalog> {
+    thread_local! {
+        /// Whether this thread is loading the catalog, whose own errors are
+        /// messages too: those are in English.
+        static LOADING: Cell<bool> = const { Cell::new(false) };
+    }
+    if let Some(catalog) = CURRENT.read().unwrap().as_ref() {
+        return Arc::clone(catalog);
+    }
+    if LOADING.replace(true) {
+        return Arc::new(Catalog {
+            locale: DEFAULT_LOCALE.to_string(),
+            texts: BTreeMap::new(),
+        });
+    }
+    let locale = settings::locale();
+    let texts = load(&locale).unwrap_or_else(|e| {
+        tracing::warn!(error = %e, locale, "showing messages in English");
+        BTreeMap::new()
+    });
+    LOADING.set(false);
+    let catalog = Arc::new(Catalog { locale, texts });
+    *CURRENT.write().unwrap() = Some(Arc::clone(&catalog));
+    catalog
+}
+
+fn english(id: &str) -> Option<&'static str> {
+    static ENGLISH: OnceLock<HashMap<&str, &str>> = OnceLock::new();
+    ENGLISH
+        .get_or_init(|| EN.iter().copied().collect())
+        .get(id)
+        .copied()
+}
+
+/// The messages of `locale`'s own catalog, which English has none of.
+fn load(locale: &str) -> Result<BTreeMap<String, String>, String> {
+    if !is_locale(locale) {
+        return Err(text(
+            "error.invalid_locale",
+            &[("locale", &format_args!("{locale:?}"))],
+        ));
+    }
+    if locale == DEFAULT_LOCALE {
+        return Ok(BTreeMap::new());
+    }
+    let path = paths::locales_dir()?.join(format!("{locale}.json"));
+    let raw = fs::read_to_string(&path).map_err(|e| {
+        text(
+            "error.failed.read_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })?;
+    serde_json::from_str(&raw).map_err(|e| {
+        text(
+            "error.invalid_catalog_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_show_their_text() {
+        let message = Message::new(
+            "error.failed.read_path",
+            &[("path", &"/tmp/a"), ("error", &"denied")],
+        );
+        assert_eq!(message.to_string(), "Failed to read /tmp/a: denied");
+    }
+
+    #[test]
+    fn plain_text_is_the_text_message() {
+        let message = Message::from("Something no catalog says");
+        assert_eq!(message.id, TEXT);
+        assert_eq!(message.to_string(), "Something no catalog says");
+    }
+}
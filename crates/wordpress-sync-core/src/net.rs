@@ -0,0 +1,144 @@
+//! Opening TCP connections to hosts with more than one address.
+//!
+//! Every address a host resolves to is tried, IPv6 and IPv4 in turn, and a new
+//! attempt starts whenever the last one fails or has gone unanswered for
+//! [`ATTEMPT_DELAY`], as in Happy Eyeballs (RFC 8305). The first to connect
+//! wins, so a host with a broken IPv6 address is reached over IPv4 a moment
+//! later instead of after the system's connect timeout.
+
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+
+use crate::messages;
+
+/// How long an attempt is waited on before the next address is tried
+/// alongside it; RFC 8305's recommended delay.
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Which of a host's addresses are connected to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AddressFamily {
+    /// Both, IPv6 first.
+    #[default]
+    Any,
+    /// Only IPv4 addresses, for networks whose IPv6 is broken.
+    Ipv4,
+    /// Only IPv6 addresses.
+    Ipv6,
+}
+
+impl AddressFamily {
+    /// The family's name, as in "no IPv4 addresses".
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Any => "IP",
+            Self::Ipv4 => "IPv4",
+            Self::Ipv6 => "IPv6",
+        }
+    }
+
+    /// The unspecified address of this family, for binding a client to, or
+    /// `None` for either.
+    pub(crate) fn unspecified(self) -> Option<std::net::IpAddr> {
+        match self {
+            Self::Any => None,
+            Self::Ipv4 => Some(std::net::Ipv4Addr::UNSPECIFIED.into()),
+            Self::Ipv6 => Some(std::net::Ipv6Addr::UNSPECIFIED.into()),
+        }
+    }
+}
+
+/// `host`'s addresses of `family`, in the order they are tried: IPv6 and
+/// IPv4 taking turns, IPv6 first.
+pub(crate) async fn addresses(
+    host: &str,
+    port: u16,
+    family: AddressFamily,
+) -> io::Result<Vec<SocketAddr>> {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let mut ipv6 = Vec::new();
+    let mut ipv4 = Vec::new();
+    for addr in tokio::net::lookup_host((host, port)).await? {
+        let list = match addr {
+            SocketAddr::V6(_) => &mut ipv6,
+            SocketAddr::V4(_) => &mut ipv4,
+        };
+        if !list.contains(&addr) {
+            list.push(addr);
+        }
+    }
+    Ok(match family {
+        AddressFamily::Ipv4 => ipv4,
+        AddressFamily::Ipv6 => ipv6,
+        AddressFamily::Any => {
+            let mut ipv6 = ipv6.into_iter();
+            let mut ipv4 = ipv4.into_iter();
+            let mut ordered = Vec::new();
+            loop {
+                match (ipv6.next(), ipv4.next()) {
+                    (None, None) => break ordered,
+                    (first, second) => ordered.extend(first.into_iter().chain(second)),
+                }
+            }
+        }
+    })
+}
+
+/// Connect to `host` on `port`, over whichever of its `family` addresses
+/// answers first.
+pub(crate) async fn connect(
+    host: &str,
+    port: u16,
+    family: AddressFamily,
+) -> Result<TcpStream, String> {
+    let addrs = addresses(host, port, family).await.map_err(|e| {
+        messages::text(
+            "error.could_not_resolve_host",
+            &[("host", &host), ("error", &e)],
+        )
+    })?;
+    if addrs.is_empty() {
+        return Err(messages::text(
+            "error.host_has_no_name_addresses",
+            &[("host", &host), ("name", &family.name())],
+        ));
+    }
+
+    let mut waiting = addrs.into_iter();
+    let mut attempts = FuturesUnordered::new();
+    let attempt = |addr: SocketAddr| async move { (addr, TcpStream::connect(addr).await) };
+    attempts.extend(waiting.next().map(attempt));
+    let mut errors = Vec::new();
+    loop {
+        tokio::select! {
+            Some((addr, result)) = attempts.next() => match result {
+                Ok(stream) => return Ok(stream),
+                Err(e) => {
+                    tracing::debug!(%addr, error = %e, "connection attempt failed");
+                    errors.push((addr, e));
+                    attempts.extend(waiting.next().map(attempt));
+                    if attempts.is_empty() {
+                        break;
+                    }
+                }
+            },
+            () = tokio::time::sleep(ATTEMPT_DELAY), if waiting.len() > 0 => {
+                attempts.extend(waiting.next().map(attempt));
+            }
+        }
+    }
+    Err(match errors.as_slice() {
+        [(_, e)] => e.to_string(),
+        errors => errors
+            .iter()
+            .map(|(addr, e)| format!("{addr}: {e}"))
+            .collect::<Vec<_>>()
+            .join("; "),
+    })
+}
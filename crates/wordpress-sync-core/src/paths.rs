@@ -0,0 +1,215 @@
+//! Locations of the app's files under `~/.wordpress-sync`.
+//!
+//! The GUI, the CLI, and the Rust backend all share this directory, so the layout
+//! here must stay in step with `gui/src/lib/services/config.ts`.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::messages;
+
+const CONFIG_DIR: &str = ".wordpress-sync";
+const PROFILES_DIR: &str = "profiles";
+const PROFILE_BASES_DIR: &str = "bases";
+const BACKUPS_DIR: &str = "backups";
+const RESTORE_POINTS_DIR: &str = "restore-points";
+const HISTORY_DB: &str = "history.sqlite";
+const METRICS_DB: &str = "metrics.sqlite";
+const SCHEDULES_FILE: &str = "schedules.json";
+const EMAIL_FILE: &str = "email.json";
+const PROXY_FILE: &str = "proxy.json";
+const LOGS_DIR: &str = "logs";
+const LOCKS_DIR: &str = "locks";
+const JOBS_DIR: &str = "jobs";
+const TWO_WAY_DIR: &str = "two-way";
+const DRIFT_DIR: &str = "drift";
+const PLANS_DIR: &str = "plans";
+const INSTANCE_FILE: &str = "instance.json";
+const UPDATES_FILE: &str = "updates.json";
+const PLUGINS_DIR: &str = "plugins";
+const SCREENSHOTS_DIR: &str = "screenshots";
+const AUDIT_DIR: &str = "audit";
+const FILE_MANIFESTS_DIR: &str = "file-manifests";
+const EVENTS_DIR: &str = "events";
+const LOCALES_DIR: &str = "locales";
+const APP_SETTINGS_FILE: &str = "app-settings.json";
+const WP_CONFIG_TEMPLATE: &str = "wp-config-template.php";
+
+/// The base config directory (`~/.wordpress-sync`).
+pub fn config_dir() -> Result<PathBuf, String> {
+    dirs::home_dir()
+        .map(|home| home.join(CONFIG_DIR))
+        .ok_or_else(|| messages::text("error.could_not_determine_home_directory", &[]))
+}
+
+/// Directory holding one JSON file per sync profile.
+pub fn profiles_dir() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(PROFILES_DIR))
+}
+
+/// Directory holding the shared bases profiles can extend, one JSON file each.
+pub fn profile_bases_dir() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(PROFILE_BASES_DIR))
+}
+
+/// Directory holding the backups made before pulls, one subdirectory each.
+pub fn backups_dir() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(BACKUPS_DIR))
+}
+
+/// Directory holding one JSON record per restore point, for both directions.
+pub fn restore_points_dir() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(RESTORE_POINTS_DIR))
+}
+
+/// The SQLite database of past sync jobs.
+pub fn history_db() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(HISTORY_DB))
+}
+
+/// The SQLite database of usage metrics, counted per day.
+pub fn metrics_db() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(METRICS_DB))
+}
+
+/// The JSON list of scheduled syncs.
+pub fn schedules_file() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(SCHEDULES_FILE))
+}
+
+/// The SMTP settings for emailing the results of scheduled syncs.
+pub fn email_file() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(EMAIL_FILE))
+}
+
+/// The app's settings, for the whole app and per profile.
+pub fn app_settings_file() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(APP_SETTINGS_FILE))
+}
+
+/// The proxy every profile connects through unless it says otherwise.
+pub fn proxy_file() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(PROXY_FILE))
+}
+
+/// Directory holding the app log and one log per sync job.
+pub fn logs_dir() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(LOGS_DIR))
+}
+
+/// Directory holding the lock file of each profile with a sync running.
+pub fn locks_dir() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(LOCKS_DIR))
+}
+
+/// Directory holding the manifest of each sync that can still be resumed.
+pub fn job_manifests_dir() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(JOBS_DIR))
+}
+
+/// Directory holding what each profile's last two-way uploads sync left on both sides.
+pub fn two_way_dir() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(TWO_WAY_DIR))
+}
+
+/// Directory holding the fingerprint each profile's last sync left of its server.
+pub fn drift_dir() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(DRIFT_DIR))
+}
+
+/// Directory holding what each profile's last dry run in each direction expected.
+pub fn plans_dir() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(PLANS_DIR))
+}
+
+/// Where the running copy of the app listens for copies started after it.
+pub fn instance_file() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(INSTANCE_FILE))
+}
+
+/// The app's update settings: its channel, and its place in staged rollouts.
+pub fn updates_file() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(UPDATES_FILE))
+}
+
+/// Directory holding the plugins that add steps to syncs, one directory each.
+pub fn plugins_dir() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(PLUGINS_DIR))
+}
+
+/// Directory holding the screenshots taken of pages before and after pushes,
+/// one directory per job.
+pub fn screenshots_dir() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(SCREENSHOTS_DIR))
+}
+
+/// Directory holding the audit log of each protected profile's pushes.
+pub fn audit_dir() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(AUDIT_DIR))
+}
+
+/// Directory holding the files each recent job added, changed, and deleted,
+/// one JSON file per job.
+pub fn file_manifests_dir() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(FILE_MANIFESTS_DIR))
+}
+
+/// Directory holding each recent job's events, one NDJSON file per job.
+pub fn events_dir() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(EVENTS_DIR))
+}
+
+/// Directory holding the message catalogs of locales besides English, one
+/// `<locale>.json` file each.
+pub fn locales_dir() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(LOCALES_DIR))
+}
+
+/// The user's template for the `wp-config.php` of a cloned site.
+pub fn wp_config_template() -> Result<PathBuf, String> {
+    Ok(config_dir()?.join(WP_CONFIG_TEMPLATE))
+}
+
+/// Write `contents` to `path` by way of a sibling renamed over it, so a
+/// crash can't leave it half-written, nor another copy of the app read it so.
+pub fn atomic_write(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), String> {
+    write_via_sibling(path, contents.as_ref(), false)
+}
+
+/// [`atomic_write`], for a file only this user may read, as one holding secrets.
+pub fn atomic_write_private(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), String> {
+    write_via_sibling(path, contents.as_ref(), true)
+}
+
+fn write_via_sibling(path: &Path, contents: &[u8], private: bool) -> Result<(), String> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut options = fs::File::options();
+    options.write(true).create(true).truncate(true);
+    // The mode only applies to a new file, so a sibling left behind goes first.
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        let _ = fs::remove_file(&tmp);
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+    let written = options
+        .open(&tmp)
+        .and_then(|mut file| file.write_all(contents));
+    written.map_err(|e| {
+        messages::text(
+            "error.failed.write_path",
+            &[("path", &tmp.display()), ("error", &e)],
+        )
+    })?;
+    fs::rename(&tmp, path).map_err(|e| {
+        messages::text(
+            "error.failed.write_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })
+}
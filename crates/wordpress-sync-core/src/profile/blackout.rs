@@ -0,0 +1,67 @@
+//! Times a profile mustn't sync, such as production's business hours.
+//!
+//! A window is a span of local time on some days of the week. One that ends
+//! earlier than it starts, such as `22:00` to `02:00`, runs past midnight into
+//! the next day, and belongs to the day it starts on. Bandwidth windows are
+//! reckoned the same way.
+
+use chrono::{DateTime, Datelike, Duration, Local, NaiveTime, TimeZone, Weekday};
+use serde::{Deserialize, Serialize};
+
+/// A span of local time the profile doesn't sync in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Blackout {
+    /// The days it starts on, as `Mon` to `Sun`; empty for every day.
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    /// When it starts, as `HH:MM` in local time.
+    pub start: String,
+    /// When it ends, as `HH:MM`; the same as `start` for the whole day.
+    pub end: String,
+}
+
+impl Blackout {
+    /// Whether `at` falls in the window. A window whose times don't parse
+    /// covers nothing, as validation reports.
+    pub fn covers<Tz: TimeZone>(&self, at: &DateTime<Tz>) -> bool {
+        covers(&self.days, &self.start, &self.end, at)
+    }
+
+    /// When the window covering `at` ends.
+    pub fn ends_after<Tz: TimeZone>(&self, at: &DateTime<Tz>) -> Option<DateTime<Local>> {
+        let end = parse_time(&self.end)?;
+        let at = at.with_timezone(&Local);
+        let today = at.date_naive().and_time(end);
+        let ends = match today > at.naive_local() {
+            true => today,
+            false => today + Duration::days(1),
+        };
+        Local.from_local_datetime(&ends).earliest()
+    }
+}
+
+/// Whether `at` falls in the window from `start` to `end` on `days`, as
+/// [`Blackout::covers`] and the other windows of the week reckon it.
+pub(super) fn covers<Tz: TimeZone>(
+    days: &[Weekday],
+    start: &str,
+    end: &str,
+    at: &DateTime<Tz>,
+) -> bool {
+    let (Some(start), Some(end)) = (parse_time(start), parse_time(end)) else {
+        return false;
+    };
+    let at = at.with_timezone(&Local);
+    let (day, time) = (at.weekday(), at.time());
+    let on = |day: Weekday| days.is_empty() || days.contains(&day);
+    if start < end {
+        return on(day) && start <= time && time < end;
+    }
+    // Past midnight, the window started the day before.
+    (on(day) && time >= start) || (on(day.pred()) && time < end)
+}
+
+/// `HH:MM`, as a blackout's times are written.
+pub(super) fn parse_time(time: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(time.trim(), "%H:%M").ok()
+}
@@ -0,0 +1,250 @@
+//! Profiles that extend a shared base, such as a company's defaults.
+//!
+//! A base is a JSON file in `~/.wordpress-sync/bases/<name>.json` holding any
+//! of a profile's settings, and perhaps an `extends` of its own. A profile
+//! with `extends` set to its name is saved with only what it sets differently,
+//! and merged over the base each time it is loaded: settings it leaves out
+//! come from the base, settings within a section merge one by one, and lists,
+//! such as the excludes and hooks, are the base's followed by the profile's
+//! own. A profile's id, name, and version are never inherited.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::Profile;
+use crate::messages;
+use crate::paths;
+
+/// The settings a base never gives the profiles extending it.
+const OWN_KEYS: &[&str] = &["version", "id", "name", "extends"];
+
+/// How deep bases may extend one another, so a cycle fails instead of looping.
+const MAX_DEPTH: usize = 8;
+
+/// A profile as it is synced, merged over its bases, and which of its
+/// settings it takes from them.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedProfile {
+    pub profile: Profile,
+    /// Dotted paths of the settings, or of the lists with entries, that come
+    /// from a base, as in `hooks` or `remote.transfer`.
+    pub inherited: Vec<String>,
+}
+
+/// Load the profile `id` merged over its bases, listing what it inherits.
+pub fn resolve(id: &str) -> Result<ResolvedProfile, String> {
+    let path = super::path_for(id)?;
+    let own = super::read_json(&path)?;
+    let (merged, inherited) = merge_bases(own)?;
+    let profile = super::from_json(merged, &path)?;
+    Ok(ResolvedProfile { profile, inherited })
+}
+
+/// `own`, a profile's JSON, merged over the bases it extends, and the paths
+/// of what came from them. Profiles that extend nothing are returned as they are.
+pub(super) fn merge_bases(own: Value) -> Result<(Value, Vec<String>), String> {
+    let mut merged = own;
+    let mut inherited = Vec::new();
+    let mut next = extends(&merged);
+    let mut depth = 0;
+    while let Some(name) = next {
+        depth += 1;
+        if depth > MAX_DEPTH {
+            return Err(messages::text(
+                "error.profiles_bases_extend_each_other_more",
+                &[("max_depth", &MAX_DEPTH)],
+            ));
+        }
+        let base = load_base(&name)?;
+        next = extends(&base);
+        merged = merge(base, merged, "", &mut inherited);
+    }
+    inherited.sort();
+    inherited.dedup();
+    Ok((merged, inherited))
+}
+
+/// `value`, a whole profile's JSON, less what it would inherit anyway from
+/// the bases it extends, for saving.
+pub(super) fn strip_inherited(value: Value) -> Result<Value, String> {
+    let Some(name) = extends(&value) else {
+        return Ok(value);
+    };
+    let (base, _) = merge_bases(load_base(&name)?)?;
+    Ok(difference(value, &base, true).unwrap_or(Value::Object(Map::new())))
+}
+
+/// The base `value` extends, if any.
+fn extends(value: &Value) -> Option<String> {
+    value.get("extends")?.as_str().map(str::to_string)
+}
+
+fn load_base(name: &str) -> Result<Value, String> {
+    read_base(&base_path(name)?, name)
+}
+
+/// The base `name` saved at `path`, upgraded to this build's format as the
+/// profiles extending it are, so what it adds is seen to as theirs is.
+fn read_base(path: &Path, name: &str) -> Result<Value, String> {
+    let raw = match fs::read_to_string(path) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(messages::text(
+                "error.there_no_profile_base_named_name",
+                &[("name", &format_args!("{name:?}"))],
+            ))
+        }
+        Err(e) => {
+            return Err(messages::text(
+                "error.failed.read_path",
+                &[("path", &path.display()), ("error", &e)],
+            ))
+        }
+    };
+    let mut base: Value = serde_json::from_str(&raw).map_err(|e| {
+        messages::text(
+            "error.invalid_profile_base_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })?;
+    if !base.is_object() {
+        return Err(messages::text(
+            "error.invalid_profile_base_path_expected_object",
+            &[("path", &path.display())],
+        ));
+    }
+    super::migrate::migrate(&mut base, path)?;
+    Ok(base)
+}
+
+/// Whether a base named `name` exists.
+pub(super) fn base_exists(name: &str) -> bool {
+    base_path(name).is_ok_and(|path| path.is_file())
+}
+
+/// The file for a base, refusing names that could escape the bases directory.
+fn base_path(name: &str) -> Result<PathBuf, String> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(messages::text(
+            "error.invalid_profile_base_name",
+            &[("name", &format_args!("{name:?}"))],
+        ));
+    }
+    Ok(paths::profile_bases_dir()?.join(format!("{name}.json")))
+}
+
+/// `own` over `base`, noting the paths below `path` taken from `base`.
+fn merge(base: Value, own: Value, path: &str, inherited: &mut Vec<String>) -> Value {
+    match (base, own) {
+        (Value::Object(base), Value::Object(mut own)) => {
+            for (key, base_value) in base {
+                if path.is_empty() && OWN_KEYS.contains(&key.as_str()) {
+                    continue;
+                }
+                let key_path = join(path, &key);
+                let value = match own.remove(&key) {
+                    Some(own_value) => merge(base_value, own_value, &key_path, inherited),
+                    None => {
+                        inherited.push(key_path);
+                        base_value
+                    }
+                };
+                own.insert(key, value);
+            }
+            Value::Object(own)
+        }
+        (Value::Array(base), Value::Array(own)) => {
+            if !base.is_empty() {
+                inherited.push(path.to_string());
+            }
+            let own: Vec<Value> = own
+                .into_iter()
+                .filter(|item| !base.contains(item))
+                .collect();
+            Value::Array(base.into_iter().chain(own).collect())
+        }
+        (_, own) => own,
+    }
+}
+
+/// What of `value` differs from `base`, or `None` if nothing does. At the
+/// `top` level a profile's own settings are always kept.
+fn difference(value: Value, base: &Value, top: bool) -> Option<Value> {
+    match (value, base) {
+        (Value::Object(value), Value::Object(base)) => {
+            let differs: Map<String, Value> = value
+                .into_iter()
+                .filter_map(|(key, value)| {
+                    if top && OWN_KEYS.contains(&key.as_str()) {
+                        return Some((key, value));
+                    }
+                    match base.get(&key) {
+                        Some(base_value) => {
+                            difference(value, base_value, false).map(|value| (key, value))
+                        }
+                        None => Some((key, value)),
+                    }
+                })
+                .collect();
+            (top || !differs.is_empty()).then_some(Value::Object(differs))
+        }
+        (Value::Array(value), Value::Array(base)) => {
+            let own: Vec<Value> = value
+                .into_iter()
+                .filter(|item| !base.contains(item))
+                .collect();
+            (!own.is_empty()).then_some(Value::Array(own))
+        }
+        (value, base) => (value != *base).then_some(value),
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    match path {
+        "" => key.to_string(),
+        _ => format!("{path}.{key}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_bases_remote_hooks_are_vetted_as_it_is_upgraded() {
+        let dir = std::env::temp_dir().join(format!("wpsync-bases-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("company.json");
+        let hook = serde_json::json!({"side": "remote", "command": "wp rewrite flush"});
+        fs::write(&path, serde_json::json!({ "hooks": [hook] }).to_string()).unwrap();
+
+        let base = read_base(&path, "company");
+        let saved = fs::read_to_string(&path);
+        let _ = fs::remove_dir_all(&dir);
+        let base = base.unwrap();
+        assert_eq!(base["version"], crate::profile::PROFILE_VERSION);
+        assert!(saved.unwrap().contains("vetted_only"));
+
+        // A profile of its own upgraded the same way keeps the base's command vetted.
+        let own = serde_json::json!({
+            "version": crate::profile::PROFILE_VERSION,
+            "extends": "company",
+            "remote_commands": {"vetted_only": true, "allowed": []},
+        });
+        let merged = merge(base, own, "", &mut Vec::new());
+        assert_eq!(merged["remote_commands"]["vetted_only"], true);
+        assert_eq!(
+            merged["remote_commands"]["allowed"],
+            serde_json::json!(["wp rewrite flush"])
+        );
+        assert_eq!(merged["hooks"], serde_json::json!([hook]));
+    }
+}
@@ -0,0 +1,170 @@
+//! Upgrading profiles saved in an older format.
+//!
+//! A profile's `version` is the format it was saved in. When a change to the
+//! format would leave older files misread, such as a setting renamed or
+//! moved, [`PROFILE_VERSION`] goes up with it and a step is added to
+//! [`MIGRATIONS`] that rewrites a file of the version before into the new
+//! one. A profile from an older build is then run through every step it is
+//! behind as it is loaded, and saved again upgraded, after its original is
+//! kept beside it as `<id>.v<version>.json.bak`. The bases profiles extend
+//! are upgraded the same way as they're read.
+
+use std::fs;
+use std::path::Path;
+
+use serde_json::{Map, Value};
+
+use crate::{messages, paths};
+
+/// Rewrites the JSON of a profile saved in one version into the next.
+type Migration = fn(&mut Map<String, Value>) -> Result<(), String>;
+
+/// The steps between versions: the first upgrades version 1 to 2, the next
+/// 2 to 3, and so on.
+const MIGRATIONS: &[Migration] = &[block_large_deletions, vet_remote_commands];
+
+/// Version of the on-disk profile format written by this build.
+pub const PROFILE_VERSION: u32 = 1 + MIGRATIONS.len() as u32;
+
+/// Upgrade `value`, the profile saved at `path`, to [`PROFILE_VERSION`],
+/// backing up the file and saving the upgrade over it. Profiles saved by a
+/// newer build are refused rather than read with settings missing.
+pub(super) fn migrate(value: &mut Value, path: &Path) -> Result<(), String> {
+    let Value::Object(profile) = value else {
+        return Err(messages::text(
+            "error.invalid_profile_path_expected_object_settings",
+            &[("path", &path.display())],
+        ));
+    };
+    // Files from before versioning count as 1.
+    let version = match profile.get("version") {
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| {
+                messages::text(
+                    "error.invalid_profile_path_version",
+                    &[("path", &path.display())],
+                )
+            })?,
+        None => 1,
+    };
+    if version > PROFILE_VERSION {
+        return Err(messages::text(
+            "error.profile_path_was_saved_newer_version",
+            &[
+                ("path", &path.display()),
+                ("version", &version),
+                ("profile_version", &PROFILE_VERSION),
+            ],
+        ));
+    }
+    if version == PROFILE_VERSION {
+        return Ok(());
+    }
+    let backup = path.with_extension(format!("v{version}.json.bak"));
+    if !backup.exists() {
+        fs::copy(path, &backup).map_err(|e| {
+            messages::text(
+                "error.failed.back_up_path",
+                &[("path", &path.display()), ("error", &e)],
+            )
+        })?;
+    }
+    for (from, step) in (version..).zip(&MIGRATIONS[version.saturating_sub(1) as usize..]) {
+        step(profile).map_err(|e| {
+            messages::text(
+                "error.failed.upgrade_profile_path_format",
+                &[("path", &path.display()), ("from", &from), ("error", &e)],
+            )
+        })?;
+    }
+    profile.insert("version".to_string(), PROFILE_VERSION.into());
+    let json = serde_json::to_string_pretty(value)
+        .map_err(|e| messages::text("error.failed.serialize_profile", &[("error", &e)]))?;
+    paths::atomic_write(path, json)?;
+    tracing::info!(path = %path.display(), from = version, to = PROFILE_VERSION, "upgraded profile");
+    Ok(())
+}
+
+/// Version 2: a large deletion blocks the sync unless it's allowed. Profiles
+/// saved before kept the `ignore` that used to be the default, so it's
+/// taken as not having been chosen.
+fn block_large_deletions(profile: &mut Map<String, Value>) -> Result<(), String> {
+    if let Some(Value::Object(warnings)) = profile.get_mut("warnings") {
+        if warnings.get("large_deletion") == Some(&Value::from("ignore")) {
+            warnings.insert("large_deletion".to_string(), "block".into());
+        }
+    }
+    Ok(())
+}
+
+/// Version 3: the commands a profile adds run on the server only once
+/// vetted, where before they ran unless `vetted_only` was set. Those it
+/// already has are vetted for it, so its syncs go on as they did, and named
+/// in a warning to look over.
+fn vet_remote_commands(profile: &mut Map<String, Value>) -> Result<(), String> {
+    let hooks = profile
+        .get("hooks")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|hook| hook.get("side").and_then(Value::as_str) == Some("remote"))
+        .filter_map(|hook| hook.get("command"));
+    let flushes = profile
+        .get("post_sync")
+        .and_then(|post_sync| post_sync.get("object_cache_commands"))
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten();
+    let commands: Vec<String> = hooks
+        .chain(flushes)
+        .filter_map(Value::as_str)
+        .map(str::to_string)
+        .collect();
+    let name = profile
+        .get("name")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    let remote_commands = profile
+        .entry("remote_commands")
+        .or_insert_with(|| Value::Object(Map::new()));
+    let Value::Object(remote_commands) = remote_commands else {
+        return Err(messages::text(
+            "error.profile_field_wrong_kind",
+            &[("field", &"remote_commands")],
+        ));
+    };
+    if remote_commands.get("vetted_only") == Some(&Value::Bool(true)) {
+        return Ok(());
+    }
+    remote_commands.insert("vetted_only".to_string(), true.into());
+    let allowed = remote_commands
+        .entry("allowed")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    let Value::Array(allowed) = allowed else {
+        return Err(messages::text(
+            "error.profile_field_wrong_kind",
+            &[("field", &"remote_commands.allowed")],
+        ));
+    };
+    let mut vetted = Vec::new();
+    for command in commands {
+        if !allowed
+            .iter()
+            .any(|allowed| allowed.as_str() == Some(command.as_str()))
+        {
+            allowed.push(command.clone().into());
+            vetted.push(command);
+        }
+    }
+    if !vetted.is_empty() {
+        tracing::warn!(
+            profile = %name,
+            commands = ?vetted,
+            "the profile's remote commands now need vetting; its existing ones were vetted for it, so check them"
+        );
+    }
+    Ok(())
+}
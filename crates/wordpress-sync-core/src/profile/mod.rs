@@ -0,0 +1,1919 @@
+//! Sync profiles: everything the sync engine needs to know about one site.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::messages;
+use crate::net::AddressFamily;
+use crate::paths;
+use crate::proxy::ProxyChoice;
+
+mod blackout;
+mod inherit;
+mod migrate;
+mod offload;
+mod preset;
+mod share;
+mod site;
+mod template;
+mod validate;
+mod woocommerce;
+pub mod wp_config;
+
+pub use blackout::Blackout;
+pub use inherit::{resolve, ResolvedProfile};
+pub use migrate::PROFILE_VERSION;
+pub use offload::MediaOffload;
+pub use preset::Preset;
+pub use share::{export, import};
+pub use site::{
+    list_environments, list_sites, EnvironmentPair, Site, SiteEnvironment, LOCAL_ENVIRONMENT,
+};
+pub use template::ProfileTemplate;
+pub use validate::{validate, Issue, Severity};
+pub use woocommerce::{PaymentTestMode, WooCommerce};
+
+/// A local/remote pair of WordPress installs that can be synced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    /// Format version the profile was saved with. Files from before versioning count as 1.
+    #[serde(default = "initial_version")]
+    pub version: u32,
+    /// File-name-safe identifier. Left empty by the frontend for a new profile.
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    /// The shared base, in `~/.wordpress-sync/bases/<name>.json`, whose
+    /// settings this profile takes wherever it doesn't set its own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+    /// The site this profile syncs one environment of. Unset, the profiles
+    /// sharing its local install make up its site.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub site: Option<String>,
+    /// The name of the server's environment within the site, such as
+    /// `staging` or `production`; its host when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
+    pub local: LocalEnvironment,
+    pub remote: RemoteEnvironment,
+    /// The server is a site, such as production, that mustn't be overwritten
+    /// by a misclick: every push to it needs a confirmation asked for first.
+    #[serde(default)]
+    pub protected: bool,
+    /// The server is a staging or development site rather than production,
+    /// so it's kept out of search engines after a sync as this machine's
+    /// site is.
+    #[serde(default)]
+    pub non_production: bool,
+    /// Times of the week the profile doesn't sync, such as the server's
+    /// business hours. Scheduled syncs skip them; a sync started by hand in
+    /// one needs telling to go ahead anyway.
+    #[serde(default)]
+    pub blackouts: Vec<Blackout>,
+    /// How the site's project is laid out, and what of it Composer installs.
+    #[serde(default)]
+    pub project: ProjectType,
+    /// Which of the install's files the file phase mirrors.
+    #[serde(default)]
+    pub scope: SyncScope,
+    /// The directories or files, relative to the WordPress root, a
+    /// `custom_roots` scope mirrors.
+    #[serde(default)]
+    pub custom_roots: Vec<String>,
+    /// Individual plugins to sync or leave alone, by directory name.
+    #[serde(default)]
+    pub plugins: ExtensionSelection,
+    /// Individual themes to sync or leave alone, by directory name.
+    #[serde(default)]
+    pub themes: ExtensionSelection,
+    /// Sync the uploads directory both ways: an uploads-only sync, in either
+    /// direction, copies each side's new and changed files to the other and
+    /// reports files changed on both instead of overwriting either.
+    #[serde(default)]
+    pub two_way_uploads: bool,
+    /// The year (`2024`) and month (`2023/11`) folders of the uploads
+    /// directory file transfers include, leaving out the others; empty
+    /// includes them all. Uploads outside such folders are always included.
+    #[serde(default)]
+    pub upload_partitions: Vec<String>,
+    /// Which of the source's uploads a one-way uploads-only sync copies.
+    #[serde(default)]
+    pub uploads_since: UploadsSince,
+    /// `.gitignore`-style exclude rules, relative to the WordPress root.
+    #[serde(default)]
+    pub excludes: Vec<String>,
+    /// Extra substitutions made in the database alongside the site URL, such as
+    /// an uploads path or CDN URL.
+    #[serde(default)]
+    pub replacements: Vec<Replacement>,
+    /// Which forms of each replacement, the site URL's included, are looked for.
+    #[serde(default)]
+    pub replacement_strategy: ReplacementStrategy,
+    /// When the destination's URL is `https://`, also rewrite `http://` links
+    /// to either site's domain to it, and warn about `http://` links to
+    /// other hosts that would load as mixed content.
+    #[serde(default)]
+    pub https_migration: bool,
+    /// Which database tables are synced.
+    #[serde(default)]
+    pub tables: TableSelection,
+    /// Keep the destination's own users and user meta across the database
+    /// import, so pulling production doesn't lock developers out of their
+    /// local admin accounts.
+    #[serde(default)]
+    pub preserve_users: bool,
+    /// When the installs' `$table_prefix` values differ, rename the synced
+    /// tables, and the option and user meta keys named after the prefix, to
+    /// the destination's prefix during the sync.
+    #[serde(default = "default_true")]
+    pub remap_table_prefix: bool,
+    /// When the source's tables use a character set or collation the
+    /// destination's server doesn't have, rename it in the dump to the nearest
+    /// one it does, instead of letting the import fail.
+    #[serde(default = "default_true")]
+    pub normalize_collations: bool,
+    /// Leave transients, and the rows cache and session plugins keep among
+    /// the options, out of the dump. The destination builds them again.
+    #[serde(default)]
+    pub skip_cache_rows: bool,
+    /// On a pull, only the posts modified on the server in the last this many
+    /// days come into the local database, with their attachments, meta, terms
+    /// and comments; the other tables come whole. `None` pulls every post.
+    #[serde(default)]
+    pub content_sample_days: Option<u32>,
+    /// Personal data scrambled in the dump when it is imported into the local
+    /// site, so a pull doesn't leave live customers' details on a laptop.
+    #[serde(default)]
+    pub anonymize: Anonymize,
+    /// Record the server's tables and files at the end of each sync, so a
+    /// push's plan can warn of edits made there since.
+    #[serde(default = "default_true")]
+    pub detect_drift: bool,
+    /// Put the destination into maintenance mode while its files and database
+    /// are replaced, so visitors never see a half-synced site.
+    #[serde(default)]
+    pub maintenance_mode: bool,
+    /// What is cleared on the destination once a sync has put everything in place.
+    #[serde(default)]
+    pub post_sync: PostSync,
+    /// The profile's own commands, run at fixed points of every sync.
+    #[serde(default)]
+    pub hooks: Vec<Hook>,
+    /// Steps plugins add to every sync, run after the hooks of their point.
+    #[serde(default)]
+    pub custom_steps: Vec<CustomStep>,
+    /// Save what each sync overwrites on the destination first, as a restore
+    /// point it can be rolled back to.
+    #[serde(default = "default_true")]
+    pub backup_before_sync: bool,
+    /// Which restore points are kept once newer ones are made.
+    #[serde(default)]
+    pub retention: Retention,
+    /// How a multisite network is synced; ignored for single sites.
+    #[serde(default)]
+    pub multisite: Multisite,
+    /// What a WooCommerce store leaves out of its syncs; `None` for a site
+    /// synced like any other.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub woocommerce: Option<WooCommerce>,
+    /// Where a site's media is kept in a bucket instead of its uploads
+    /// directory; `None` for a site whose uploads are files like any other.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_offload: Option<MediaOffload>,
+    /// How often a phase is tried again after what looks like a dropped connection.
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    /// How long connections may take to open and to go quiet, and how SSH
+    /// keeps them alive.
+    #[serde(default)]
+    pub timeouts: Timeouts,
+    /// How fast file transfers may go.
+    #[serde(default)]
+    pub bandwidth: Bandwidth,
+    /// Integrity mode: decide which files to copy by comparing SHA-256
+    /// checksums rather than size and modification time, and check the copies
+    /// against the source afterwards. Slower, and it needs SSH.
+    #[serde(default)]
+    pub checksums: bool,
+    /// How hard transfers compress what they send.
+    #[serde(default)]
+    pub compression: Compression,
+    /// The rsync binaries rsync transfers run, when not those found by default.
+    #[serde(default)]
+    pub rsync: RsyncBinaries,
+    /// The modes and owner a push gives the files it copies to the server.
+    #[serde(default)]
+    pub permissions: Permissions,
+    /// Which of the server's steps run through `sudo`, and how.
+    #[serde(default)]
+    pub sudo: Sudo,
+    /// Named secrets to use in place of the passwords stored for this profile.
+    #[serde(default)]
+    pub secrets: Secrets,
+    /// Which of the commands this profile adds may run on the server.
+    #[serde(default)]
+    pub remote_commands: RemoteCommands,
+    /// What file transfers do with symbolic links, such as the plugins and
+    /// themes a Composer-based install links into `wp-content`.
+    #[serde(default)]
+    pub symlinks: SymlinkPolicy,
+    /// What a pull of files does when the local site is in a git repository
+    /// with changes that aren't committed.
+    #[serde(default)]
+    pub local_git: GitPolicy,
+    /// Which warnings a sync passes over, reports, or stops for.
+    #[serde(default)]
+    pub warnings: WarningPolicy,
+    /// Tables imported at once, each from its own part of the dump over its
+    /// own connection. 1 imports the dump whole, as it was exported.
+    #[serde(default = "default_import_workers")]
+    pub import_workers: u32,
+    /// Requests made to the destination site once a sync has finished.
+    #[serde(default)]
+    pub health_checks: HealthChecks,
+    /// Pages fetched from both sites once a sync has finished, to compare.
+    #[serde(default)]
+    pub response_diff: ResponseDiff,
+    /// Screenshots of the server's pages before and after each push.
+    #[serde(default)]
+    pub screenshots: Screenshots,
+    /// URLs told when a sync of this profile finishes, such as a team's
+    /// Slack or Discord channel.
+    #[serde(default)]
+    pub webhooks: Vec<Webhook>,
+    /// Named syncs with their options set, ready to run in one go.
+    #[serde(default)]
+    pub presets: Vec<Preset>,
+}
+
+impl Profile {
+    /// A new, unsaved profile for `local` and `remote`, with every other
+    /// setting at its default.
+    pub fn new(name: String, local: LocalEnvironment, remote: RemoteEnvironment) -> Self {
+        Self {
+            version: PROFILE_VERSION,
+            id: String::new(),
+            name,
+            extends: None,
+            site: None,
+            environment: None,
+            local,
+            remote,
+            protected: false,
+            non_production: false,
+            blackouts: Vec::new(),
+            project: ProjectType::default(),
+            scope: SyncScope::default(),
+            custom_roots: Vec::new(),
+            plugins: ExtensionSelection::default(),
+            themes: ExtensionSelection::default(),
+            two_way_uploads: false,
+            upload_partitions: Vec::new(),
+            uploads_since: UploadsSince::default(),
+            excludes: Vec::new(),
+            replacements: Vec::new(),
+            replacement_strategy: ReplacementStrategy::default(),
+            https_migration: false,
+            tables: TableSelection::default(),
+            preserve_users: false,
+            remap_table_prefix: default_true(),
+            normalize_collations: default_true(),
+            skip_cache_rows: false,
+            content_sample_days: None,
+            anonymize: Anonymize::default(),
+            detect_drift: true,
+            maintenance_mode: false,
+            post_sync: PostSync::default(),
+            hooks: Vec::new(),
+            custom_steps: Vec::new(),
+            backup_before_sync: default_true(),
+            retention: Retention::default(),
+            multisite: Multisite::default(),
+            woocommerce: None,
+            media_offload: None,
+            retry: RetryPolicy::default(),
+            timeouts: Timeouts::default(),
+            bandwidth: Bandwidth::default(),
+            checksums: false,
+            compression: Compression::default(),
+            rsync: RsyncBinaries::default(),
+            permissions: Permissions::default(),
+            sudo: Sudo::default(),
+            secrets: Secrets::default(),
+            remote_commands: RemoteCommands::default(),
+            symlinks: SymlinkPolicy::default(),
+            local_git: GitPolicy::default(),
+            warnings: WarningPolicy::default(),
+            import_workers: default_import_workers(),
+            health_checks: HealthChecks::default(),
+            response_diff: ResponseDiff::default(),
+            screenshots: Screenshots::default(),
+            webhooks: Vec::new(),
+            presets: Vec::new(),
+        }
+    }
+
+    /// The profile's table rules, with what its WooCommerce settings leave
+    /// out added to the excludes.
+    pub fn table_selection(&self) -> TableSelection {
+        let mut selection = self.tables.clone();
+        if let Some(woocommerce) = &self.woocommerce {
+            selection.exclude.extend(woocommerce.excluded_tables());
+        }
+        if let Some(offload) = &self.media_offload {
+            selection.exclude.extend(offload.excluded_tables());
+        }
+        selection
+    }
+}
+
+/// How much of the install the file phase mirrors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncScope {
+    /// Just `wp-content`: themes, plugins, and uploads, leaving core to be
+    /// installed on each side, by Composer for instance.
+    #[default]
+    WpContent,
+    /// The whole WordPress root, core included. `wp-config.php` comes along
+    /// only when both installs' copies name the same database, so it can't
+    /// point the destination at the source's.
+    FullInstall,
+    /// Only the profile's `custom_roots`.
+    CustomRoots,
+}
+
+/// How a site's project is laid out. The paths in a profile, its `local.path`
+/// and `remote.path` among them, are relative to the project's root, which for
+/// a Composer project is where `composer.json` is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProjectType {
+    /// A plain install: core at the root, content in `wp-content`.
+    #[default]
+    Standard,
+    /// An install managed with Composer: core in `wp`, content in
+    /// `wp-content`, and packages in `vendor`.
+    Composer,
+    /// A Bedrock project: core in `web/wp`, content in `web/app`, packages in
+    /// `vendor`, and the environment's settings in `.env`.
+    Bedrock,
+}
+
+impl ProjectType {
+    /// The content directory, relative to the root.
+    pub fn content_dir(self) -> &'static str {
+        match self {
+            Self::Standard | Self::Composer => "wp-content",
+            Self::Bedrock => "web/app",
+        }
+    }
+
+    /// The uploads directory, relative to the root.
+    pub fn uploads_dir(self) -> &'static str {
+        match self {
+            Self::Standard | Self::Composer => "wp-content/uploads",
+            Self::Bedrock => "web/app/uploads",
+        }
+    }
+
+    /// The `wp-config.php` that says the install is WordPress, relative to the root.
+    pub fn wp_config(self) -> &'static str {
+        match self {
+            Self::Standard | Self::Composer => "wp-config.php",
+            Self::Bedrock => "web/wp-config.php",
+        }
+    }
+
+    /// Whether Composer manages the install.
+    pub fn uses_composer(self) -> bool {
+        self != Self::Standard
+    }
+
+    /// Exclude rules for what Composer installs, and what belongs to one
+    /// environment alone, which are never transferred.
+    pub fn excludes(self) -> &'static [&'static str] {
+        match self {
+            Self::Standard => &[],
+            Self::Composer => &["/vendor/", "/wp/"],
+            Self::Bedrock => &["/vendor/", "/web/wp/", "/.env"],
+        }
+    }
+}
+
+/// Options for multisite networks. Whether an install is one is detected, not configured.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Multisite {
+    /// Sync only this subsite, by blog id, which must be the same on both
+    /// installs: its tables and its uploads, and nothing else. `None` syncs
+    /// the whole network.
+    #[serde(default)]
+    pub subsite: Option<u64>,
+}
+
+/// What the anonymize phase scrambles. It runs only when the database is
+/// imported into the local site, and does nothing until a preset or rule is
+/// added. Within one sync a value is scrambled the same way wherever it
+/// appears, so an email address stays the same fake one across tables.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Anonymize {
+    #[serde(default)]
+    pub presets: Vec<AnonymizePreset>,
+    /// The profile's own columns to scramble, beyond the presets'.
+    #[serde(default)]
+    pub rules: Vec<AnonymizeRule>,
+}
+
+impl Anonymize {
+    pub fn is_empty(&self) -> bool {
+        self.presets.is_empty() && self.rules.is_empty()
+    }
+}
+
+/// A built-in set of [`AnonymizeRule`]s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnonymizePreset {
+    /// Users' email addresses, display and first and last names, websites,
+    /// and biographies. Logins are kept, so developers can still sign in.
+    Users,
+    /// Commenters' names, email addresses, websites, and IP addresses.
+    Comments,
+    /// WooCommerce customers' and orders' billing and shipping details.
+    Woocommerce,
+}
+
+/// One column to scramble.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnonymizeRule {
+    /// The table, without the table prefix; it matches every subsite's on a network.
+    pub table: String,
+    pub column: String,
+    /// For a meta table, only the rows whose `meta_key` is this.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta_key: Option<String>,
+    pub with: Scramble,
+}
+
+/// What a scrambled value becomes. Empty values stay empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scramble {
+    /// `user-<hash>@example.com`.
+    Email,
+    /// `Person <hash>`.
+    Name,
+    /// `anon-<hash>`, for addresses and anything else.
+    Text,
+    /// A `555-01xx` number, reserved for fiction.
+    Phone,
+    /// An address in `192.0.2.0/24`, reserved for documentation.
+    Ip,
+    /// Nothing at all.
+    Clear,
+}
+
+/// Caches flushed, plugins switched on or off, and options set on the
+/// destination in the post-sync phase. They need a shell there, so FTP
+/// profiles skip them when pushing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostSync {
+    /// `wp cache flush`.
+    #[serde(default = "default_true")]
+    pub flush_cache: bool,
+    /// `wp transient delete --all`.
+    #[serde(default)]
+    pub delete_transients: bool,
+    /// `wp rewrite flush`.
+    #[serde(default)]
+    pub flush_rewrites: bool,
+    /// Shell commands that flush a persistent object cache, such as
+    /// `redis-cli -n 0 FLUSHDB` or `echo flush_all | nc -q1 localhost 11211`.
+    /// They run in the destination's WordPress root.
+    #[serde(default)]
+    pub object_cache_commands: Vec<String>,
+    /// When a database has been imported, flush the destination's Redis
+    /// object cache, if its `object-cache.php` drop-in is a Redis one.
+    #[serde(default = "default_true")]
+    pub flush_redis: bool,
+    /// Search plugins whose index is rebuilt once a database has been
+    /// imported, if they're active on the destination.
+    #[serde(default)]
+    pub reindex_search: Vec<SearchPlugin>,
+    /// After a push of a Composer project's files, `composer install` in the
+    /// server's project root, so it has the packages the pushed
+    /// `composer.lock` names.
+    #[serde(default)]
+    pub composer_install: bool,
+    /// After a push, give the server's `wp-config.php` new auth keys and
+    /// salts, logging everyone out of it, as for a push that sets up a new
+    /// environment.
+    #[serde(default)]
+    pub rotate_salts: bool,
+    /// On a non-production destination (this machine's site, or the server's
+    /// when the profile says it isn't production), set `blog_public` to 0
+    /// after an import, so search engines are asked not to index it.
+    #[serde(default = "default_true")]
+    pub discourage_search_engines: bool,
+    /// On a non-production destination, also write a `robots.txt` turning
+    /// every crawler away, for crawlers that don't read WordPress's own.
+    #[serde(default)]
+    pub robots_txt: bool,
+    /// Plugins switched on or off on this machine's site once a sync has
+    /// imported a database into it, such as its mail and analytics plugins.
+    #[serde(default)]
+    pub local_plugins: PluginActivation,
+    /// Plugins switched on or off on the server's site once a sync has
+    /// imported a database into it, such as a staging site's maintenance plugin.
+    #[serde(default)]
+    pub remote_plugins: PluginActivation,
+    /// WordPress options set on each side once a sync has imported a
+    /// database into it.
+    #[serde(default)]
+    pub options_overrides: OptionsOverrides,
+    /// Purge the CDN in front of the server's site once a push is through.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cdn_purge: Option<CdnPurge>,
+}
+
+/// A search plugin that keeps its own index of the site's posts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchPlugin {
+    /// `wp elasticpress sync --setup`.
+    #[serde(rename = "elasticpress")]
+    ElasticPress,
+    /// `wp relevanssi index`.
+    Relevanssi,
+}
+
+/// The CDN a push's destination sits behind, to purge its cache. Its API
+/// token is stored under `CDN_TOKEN_SERVICE` for the profile, unless
+/// [`Secrets::cdn_token`] names one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CdnPurge {
+    /// Purge after each push. Off keeps the settings for later.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub target: CdnTarget,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "provider", rename_all = "snake_case")]
+pub enum CdnTarget {
+    /// Cloudflare, whose API token needs the zone's Cache Purge permission.
+    Cloudflare {
+        /// The zone's id. Without one, the zone is looked up by the server's
+        /// domain, which needs the token to have Zone Read permission too.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        zone_id: Option<String>,
+    },
+    /// Any other CDN or cache with a purge URL: it's sent a POST, with the
+    /// token, if one is stored, as a bearer token.
+    Url { url: String },
+}
+
+/// WordPress options to set after an import, by name, for each side. A value
+/// is stored as `wp option update` would store it: strings and numbers as
+/// they are, arrays and objects serialized. A string that is a secret
+/// reference, such as `keychain:stripe-test-key`, stands for that secret.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OptionsOverrides {
+    /// For this machine's site, such as `blog_public` set to `0`.
+    #[serde(default)]
+    pub local: BTreeMap<String, serde_json::Value>,
+    /// For the server's site.
+    #[serde(default)]
+    pub remote: BTreeMap<String, serde_json::Value>,
+}
+
+/// Plugins to activate or deactivate, by directory name as wp-cli lists them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PluginActivation {
+    #[serde(default)]
+    pub activate: Vec<String>,
+    #[serde(default)]
+    pub deactivate: Vec<String>,
+}
+
+impl PluginActivation {
+    pub fn is_empty(&self) -> bool {
+        self.activate.is_empty() && self.deactivate.is_empty()
+    }
+}
+
+impl Default for PostSync {
+    fn default() -> Self {
+        Self {
+            flush_cache: true,
+            delete_transients: false,
+            flush_rewrites: false,
+            object_cache_commands: Vec::new(),
+            flush_redis: true,
+            reindex_search: Vec::new(),
+            composer_install: false,
+            rotate_salts: false,
+            discourage_search_engines: true,
+            robots_txt: false,
+            local_plugins: PluginActivation::default(),
+            remote_plugins: PluginActivation::default(),
+            options_overrides: OptionsOverrides::default(),
+            cdn_purge: None,
+        }
+    }
+}
+
+/// HTTP requests to the destination's site after a sync, to catch a site the
+/// sync broke straight away. A check fails on an unexpected status or on a page
+/// showing a PHP fatal error; failures are warnings, since the sync is done.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthChecks {
+    /// Check after each sync.
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_health_check_urls")]
+    pub urls: Vec<HealthCheck>,
+    /// Reject a certificate that doesn't check out. Turn off for local sites
+    /// with self-signed certificates.
+    #[serde(default = "default_true")]
+    pub verify_tls: bool,
+}
+
+impl Default for HealthChecks {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            urls: default_health_check_urls(),
+            verify_tls: true,
+        }
+    }
+}
+
+/// A comparison of the source's and destination's sites after a sync, to
+/// catch permalinks or media the sync broke before a visitor does. Each page
+/// is fetched from both, and the two are expected to answer with the same
+/// status, end up at the same path, have the same title and as many images,
+/// and, on the destination, to have those images. Differences are warnings,
+/// since the sync is done.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseDiff {
+    /// Compare the sites after each sync.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Paths compared on both sites, e.g. `/about/`.
+    #[serde(default = "default_response_diff_paths")]
+    pub paths: Vec<String>,
+    /// How many pages of the source's sitemap to compare as well, spread
+    /// across it. 0 compares only `paths`.
+    #[serde(default = "default_response_diff_sample")]
+    pub sitemap_sample: u32,
+    /// Reject a certificate that doesn't check out. Turn off for local sites
+    /// with self-signed certificates.
+    #[serde(default = "default_true")]
+    pub verify_tls: bool,
+}
+
+impl Default for ResponseDiff {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            paths: default_response_diff_paths(),
+            sitemap_sample: default_response_diff_sample(),
+            verify_tls: true,
+        }
+    }
+}
+
+/// Screenshots of the server's pages, taken with a headless Chrome before and
+/// after each push and compared pixel by pixel, so a push that changed how
+/// the site looks is plain straight away. A page that changed more than the
+/// threshold is a warning; the screenshots are kept for the sync's report.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Screenshots {
+    /// Take them around each push.
+    #[serde(default)]
+    pub enabled: bool,
+    /// The Chrome, Chromium or Edge to take them with, if not the first of
+    /// those installed in the usual places.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub browser: Option<PathBuf>,
+    /// Paths of the pages to take, e.g. `/shop/`.
+    #[serde(default = "default_screenshot_paths")]
+    pub paths: Vec<String>,
+    /// The size of the browser's window, in pixels.
+    #[serde(default = "default_screenshot_width")]
+    pub width: u32,
+    #[serde(default = "default_screenshot_height")]
+    pub height: u32,
+    /// The percentage of a page's pixels that may change before it's a warning.
+    #[serde(default = "default_screenshot_threshold")]
+    pub threshold: f64,
+    /// Reject a certificate that doesn't check out.
+    #[serde(default = "default_true")]
+    pub verify_tls: bool,
+}
+
+impl Default for Screenshots {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            browser: None,
+            paths: default_screenshot_paths(),
+            width: default_screenshot_width(),
+            height: default_screenshot_height(),
+            threshold: default_screenshot_threshold(),
+            verify_tls: true,
+        }
+    }
+}
+
+/// A URL sent a message when a sync finishes or fails; a cancelled sync
+/// sends none. Failing to send it is only logged, since the sync is over.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    /// Where the message is posted, or a reference to a named secret holding
+    /// that, such as `keychain:team-slack`, since a chat's webhook URL lets
+    /// anyone who has it post there.
+    pub url: String,
+    #[serde(default)]
+    pub kind: WebhookKind,
+    #[serde(default = "default_true")]
+    pub on_success: bool,
+    #[serde(default = "default_true")]
+    pub on_failure: bool,
+    /// The message, with `{profile}`, `{direction}`, `{status}`,
+    /// `{duration}`, `{bytes}`, `{files}`, `{warnings}`, `{error}`, and
+    /// `{job_id}` filled in. Unset, it says what the app's notification does.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+}
+
+/// What a [`Webhook`] posts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookKind {
+    /// `{"message": ..., "job": ...}`, with the job's history entry.
+    #[default]
+    Json,
+    /// A Slack incoming webhook's `{"text": ...}`.
+    Slack,
+    /// A Discord webhook's `{"content": ...}`.
+    Discord,
+}
+
+/// One page to request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthCheck {
+    /// A path below the destination's site URL, such as `/wp-admin/`, or a full URL.
+    pub url: String,
+    /// The status the response must have once redirects are followed.
+    #[serde(default = "default_health_check_status")]
+    pub status: u16,
+}
+
+/// A command run at one point of a sync, with `WORDPRESS_SYNC_*` environment
+/// variables describing the job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hook {
+    pub point: HookPoint,
+    /// Which machine the command runs on, in that install's WordPress root.
+    pub side: HookSide,
+    /// A shell command line: `sh` locally, or `cmd` on Windows, and the
+    /// login shell on the server.
+    pub command: String,
+    /// Fail the sync when the command fails. Otherwise the failure is a warning.
+    #[serde(default = "default_true")]
+    pub required: bool,
+}
+
+/// A step one of the installed plugins adds to the profile's syncs. It runs
+/// at the point the plugin gives it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomStep {
+    /// The plugin's directory name under `~/.wordpress-sync/plugins`.
+    pub plugin: String,
+    /// The step's id in the plugin's manifest.
+    pub step: String,
+    /// Settings the plugin reads, as its documentation describes them.
+    #[serde(default)]
+    pub config: serde_json::Map<String, serde_json::Value>,
+    /// Fail the sync when the step fails. Otherwise the failure is a warning.
+    #[serde(default = "default_true")]
+    pub required: bool,
+}
+
+/// Where in the pipeline a [`Hook`] runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookPoint {
+    /// Once both installs are reachable, before anything is changed.
+    BeforeSync,
+    /// Once the destination's database has been replaced.
+    AfterDbImport,
+    /// Once the files have been transferred.
+    AfterFiles,
+    /// Once the sync has succeeded and the destination is out of maintenance mode.
+    AfterSync,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookSide {
+    Local,
+    /// The server, over SSH.
+    Remote,
+}
+
+/// How long restore points are kept. A point survives pruning if either rule keeps it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Retention {
+    /// The newest this many restore points are kept.
+    #[serde(default = "default_keep_last")]
+    pub keep_last: u32,
+    /// The newest restore point of each of the last this many days is kept too.
+    #[serde(default = "default_keep_daily_days")]
+    pub keep_daily_days: u32,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            keep_last: default_keep_last(),
+            keep_daily_days: default_keep_daily_days(),
+        }
+    }
+}
+
+/// Rate limits for file transfers, in KiB per second; `None` is unlimited.
+/// They apply to the files a sync copies, not to database dumps.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Bandwidth {
+    /// The limit when pushing to the server.
+    #[serde(default)]
+    pub upload_kib_per_sec: Option<u64>,
+    /// The limit when pulling from it.
+    #[serde(default)]
+    pub download_kib_per_sec: Option<u64>,
+    /// Times of the week with limits of their own, such as full speed
+    /// overnight and a trickle in working hours. The first covering the
+    /// moment applies, for as long as it does, while a job runs; outside
+    /// them, the limits above do.
+    #[serde(default)]
+    pub windows: Vec<BandwidthWindow>,
+}
+
+/// A span of local time with its own transfer limits, reckoned like a
+/// [`Blackout`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BandwidthWindow {
+    /// The days it starts on, as `Mon` to `Sun`; empty for every day.
+    #[serde(default)]
+    pub days: Vec<chrono::Weekday>,
+    /// When it starts, as `HH:MM` in local time.
+    pub start: String,
+    /// When it ends, as `HH:MM`; the same as `start` for the whole day.
+    pub end: String,
+    /// The limit when pushing in the window; `None` is unlimited.
+    #[serde(default)]
+    pub upload_kib_per_sec: Option<u64>,
+    /// The limit when pulling in it.
+    #[serde(default)]
+    pub download_kib_per_sec: Option<u64>,
+    /// How many files SFTP and FTP copy at once in it, up to the profile's
+    /// `parallel_transfers`; `None` for that many.
+    #[serde(default)]
+    pub parallel_transfers: Option<u32>,
+}
+
+impl BandwidthWindow {
+    /// Whether `at` falls in the window.
+    pub fn covers<Tz: chrono::TimeZone>(&self, at: &DateTime<Tz>) -> bool {
+        blackout::covers(&self.days, &self.start, &self.end, at)
+    }
+}
+
+/// Compression of what crosses the network: rsync's `-z` (which rsync 3.2
+/// and later do with zstd where both ends can), the whole SSH connection for
+/// SFTP, and the gzip stream database dumps travel in. More compression sends
+/// fewer bytes for more CPU on both machines. FTP transfers aren't compressed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    /// Send everything as it is, for fast links or busy servers.
+    Off,
+    /// As quick as compression gets; the default.
+    #[default]
+    Fast,
+    /// As small as compression gets, for slow links.
+    Best,
+}
+
+impl Compression {
+    /// The zlib level to compress at (rsync's `--compress-level`, gzip's `-1`
+    /// to `-9`), or `None` when off.
+    pub fn level(self) -> Option<u32> {
+        match self {
+            Compression::Off => None,
+            Compression::Fast => Some(1),
+            Compression::Best => Some(9),
+        }
+    }
+}
+
+/// The rsync each end of an rsync transfer runs. Unset, this machine's is
+/// the newest of the one on the `PATH` and Homebrew's, since macOS's own is
+/// the 2.6.9 of 2006, and the server's is the `rsync` on its `PATH`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RsyncBinaries {
+    /// Such as `/opt/homebrew/bin/rsync`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_path: Option<PathBuf>,
+    /// The server's, such as `/usr/local/bin/rsync`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_path: Option<String>,
+}
+
+/// What a push sets on the files it copies to the server, and the
+/// directories they are in, so PHP can read them without a manual `chmod -R`.
+/// Whatever is unset is left as the copy made it. It takes a shell there, so
+/// FTP profiles can't set them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Permissions {
+    /// The octal mode given to files, such as `644`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_mode: Option<String>,
+    /// The octal mode given to directories, such as `755`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir_mode: Option<String>,
+    /// `user` or `user:group` to `chown` them to, which the SSH user usually
+    /// needs to be root to do.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+}
+
+impl Permissions {
+    pub fn is_set(&self) -> bool {
+        self.file_mode.is_some() || self.dir_mode.is_some() || self.owner.is_some()
+    }
+}
+
+/// Running some of the server's steps as another user through `sudo`, for
+/// setting owners, reloading services, or writing where the SSH user can't.
+/// Only the steps listed do; everything else runs as the SSH user.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Sudo {
+    /// The user the steps run as; root when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Give sudo the password stored in the keychain under
+    /// `SUDO_PASSWORD_SERVICE` (`sudo -S`), rather than needing sudo to ask
+    /// for none (`sudo -n`).
+    #[serde(default)]
+    pub password: bool,
+    #[serde(default)]
+    pub steps: Vec<SudoStep>,
+}
+
+impl Sudo {
+    pub fn allows(&self, step: SudoStep) -> bool {
+        self.steps.contains(&step)
+    }
+}
+
+/// A step on the server that may run through `sudo`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SudoStep {
+    /// The server's end of rsync transfers, as `--rsync-path`. Only with
+    /// passwordless sudo, since rsync's input is its own.
+    Files,
+    /// Setting the modes and owner of pushed files.
+    Permissions,
+    /// Hooks that run on the server.
+    Hooks,
+    /// The object cache flush commands, when pushing.
+    ObjectCache,
+}
+
+/// Secrets a profile refers to by name, such as `keychain:prod-db`, rather
+/// than the passwords stored in the credential store for the profile itself,
+/// so a profile shared between machines or people says which secret it needs
+/// without containing it. Each names an entry under `NAMED_SECRET_SERVICE`,
+/// which every machine stores for itself; unset ones use the profile's own.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Secrets {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh_password: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jump_password: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ftp_password: Option<String>,
+    /// The WordPress Application Password for REST API syncs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rest_password: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database_password: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sudo_password: Option<String>,
+    /// The API token for purging the CDN.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cdn_token: Option<String>,
+}
+
+impl Secrets {
+    /// Every reference set, with the field it is in.
+    pub fn references(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [
+            ("ssh_password", &self.ssh_password),
+            ("jump_password", &self.jump_password),
+            ("ftp_password", &self.ftp_password),
+            ("rest_password", &self.rest_password),
+            ("database_password", &self.database_password),
+            ("sudo_password", &self.sudo_password),
+            ("cdn_token", &self.cdn_token),
+        ]
+        .into_iter()
+        .filter_map(|(field, reference)| Some((field, reference.as_deref()?)))
+    }
+}
+
+/// Holding what runs on the server to commands someone has vetted. The ones
+/// wordpress-sync makes itself are fixed templates with every value quoted;
+/// the free-form ones a profile adds, its remote hooks and object cache
+/// flushes, are refused unless listed here exactly, as they are by default.
+/// Every command run is recorded in the job's log either way.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteCommands {
+    #[serde(default = "default_true")]
+    pub vetted_only: bool,
+    /// The profile's commands allowed to run, as the hook or flush gives them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed: Vec<String>,
+}
+
+impl Default for RemoteCommands {
+    fn default() -> Self {
+        Self {
+            vetted_only: true,
+            allowed: Vec::new(),
+        }
+    }
+}
+
+impl RemoteCommands {
+    /// Whether the profile's `command` may run on the server.
+    pub fn allows(&self, command: &str) -> bool {
+        !self.vetted_only
+            || self
+                .allowed
+                .iter()
+                .any(|allowed| allowed.trim() == command.trim())
+    }
+}
+
+/// What the file phase does with a symbolic link in the source.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymlinkPolicy {
+    /// Leave links out, and the destination's links alone.
+    Skip,
+    /// Copy each link as a link to the same place, as rsync's `-a` does; the
+    /// default. Links the destination can't have, over FTP or onto Windows,
+    /// are skipped.
+    #[default]
+    Preserve,
+    /// Copy what each link points to in its place. Over FTP, only this
+    /// machine's links can be followed.
+    Follow,
+}
+
+/// What a pull of files does when the local site is in a git repository with
+/// uncommitted changes the pull could overwrite.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GitPolicy {
+    /// Refuse to start until they are committed or stashed; the default.
+    #[default]
+    Block,
+    /// Stash them, untracked files included, for `git stash pop` to bring back.
+    Stash,
+    /// Commit them first.
+    Commit,
+    /// Pull over them.
+    Ignore,
+}
+
+/// What a sync does about one kind of warning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningAction {
+    /// Go on without a word, beyond the log.
+    Ignore,
+    /// Go on, and list it among the job's warnings.
+    Warn,
+    /// Stop the sync before it changes anything.
+    Block,
+}
+
+/// What a sync does about each kind of warning it can run into, so a team's
+/// tolerance for them is settled once rather than at each sync. The defaults
+/// are what syncs did before the policy could be set, but for a large
+/// deletion, which blocks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WarningPolicy {
+    /// The server has no rsync for an rsync transfer. Ignored or warned
+    /// about, the files are copied over SFTP instead.
+    #[serde(default = "default_block")]
+    pub remote_rsync_missing: WarningAction,
+    /// The source's tables use a character set or collation the
+    /// destination's server hasn't got.
+    #[serde(default = "default_warn")]
+    pub charset_mismatch: WarningAction,
+    /// A push would overwrite what changed on the server since the last
+    /// sync. Checking reads each synced table, as a plan does.
+    #[serde(default = "default_ignore")]
+    pub drift: WarningAction,
+    /// The file phase would delete more than `large_deletion_threshold`
+    /// files from the destination, or more than `large_deletion_percent` of
+    /// them. Blocked, the sync goes ahead only when told to, as after a plan
+    /// that showed the deletions. Checking lists both sides first.
+    #[serde(default = "default_block")]
+    pub large_deletion: WarningAction,
+    /// The most files a sync deletes before it counts as a large deletion.
+    #[serde(default = "default_large_deletion_threshold")]
+    pub large_deletion_threshold: u64,
+    /// The most of the destination's files, in percent, a sync deletes
+    /// before it counts as a large deletion; unset goes by the count alone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub large_deletion_percent: Option<u32>,
+}
+
+impl Default for WarningPolicy {
+    fn default() -> Self {
+        Self {
+            remote_rsync_missing: default_block(),
+            charset_mismatch: default_warn(),
+            drift: default_ignore(),
+            large_deletion: default_block(),
+            large_deletion_threshold: default_large_deletion_threshold(),
+            large_deletion_percent: None,
+        }
+    }
+}
+
+/// Retries of a phase that failed with a connection reset, a timeout, or a
+/// similar error that may not happen again. The waits between attempts double
+/// each time, up to `max_backoff_ms`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Attempts at a phase, the first included. 1 turns retries off.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// The wait before the first retry.
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    /// The longest wait between attempts.
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    /// Wait a random 50–100% of each backoff instead, so syncs that failed
+    /// together don't all retry at the same moment.
+    #[serde(default = "default_true")]
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            initial_backoff_ms: default_initial_backoff_ms(),
+            max_backoff_ms: default_max_backoff_ms(),
+            jitter: true,
+        }
+    }
+}
+
+/// Limits on connections to the server, so a sync over a flaky VPN fails
+/// instead of hanging, while one waiting on a long database import survives.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Timeouts {
+    /// How long opening a connection and logging in may take.
+    #[serde(default = "default_connect_secs")]
+    pub connect_secs: u64,
+    /// How long an SFTP request, an HTTP response, or rsync may go without
+    /// anything arriving. 0 waits for as long as it takes.
+    #[serde(default = "default_read_secs")]
+    pub read_secs: u64,
+    /// How long an SSH connection may be quiet before the server is asked
+    /// whether it is still there. 0 never asks.
+    #[serde(default = "default_keepalive_secs")]
+    pub keepalive_secs: u64,
+    /// How many of those asks may go unanswered in a row before the
+    /// connection counts as dropped.
+    #[serde(default = "default_keepalive_max")]
+    pub keepalive_max: u32,
+    /// How long an SSH connection stays open after a job, for the next one
+    /// to the same server, such as the push after a dry run, to go on with
+    /// instead of logging in again. 0 closes it as the job ends.
+    #[serde(default = "default_linger_secs")]
+    pub linger_secs: u64,
+}
+
+impl Timeouts {
+    pub fn connect(&self) -> Duration {
+        Duration::from_secs(self.connect_secs.max(1))
+    }
+
+    /// `None` with no read timeout.
+    pub fn read(&self) -> Option<Duration> {
+        (self.read_secs > 0).then(|| Duration::from_secs(self.read_secs))
+    }
+
+    /// `None` with keepalives off.
+    pub fn keepalive(&self) -> Option<Duration> {
+        (self.keepalive_secs > 0).then(|| Duration::from_secs(self.keepalive_secs))
+    }
+
+    pub fn linger(&self) -> Duration {
+        Duration::from_secs(self.linger_secs)
+    }
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect_secs: default_connect_secs(),
+            read_secs: default_read_secs(),
+            keepalive_secs: default_keepalive_secs(),
+            keepalive_max: default_keepalive_max(),
+            linger_secs: default_linger_secs(),
+        }
+    }
+}
+
+/// Plugins or themes, by the name of their directory (`woocommerce`, or
+/// `hello` for a single-file `hello.php`). With any includes, of plugins or of
+/// themes, the file phase mirrors only the included ones, plus a
+/// `custom_roots` scope's roots; excludes are left alone either way.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExtensionSelection {
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+/// Include and exclude rules for database tables. Entries are table names or
+/// glob patterns such as `wp_wc_*`. With no includes every table is synced;
+/// excludes then remove tables from that set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TableSelection {
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+impl TableSelection {
+    /// Whether every table is synced.
+    pub fn is_all(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// The tables of `all` that the rules select, in their original order.
+    pub fn select<'a>(&self, all: &'a [String]) -> Result<Vec<&'a str>, String> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|raw| {
+                    glob::Pattern::new(raw).map_err(|e| {
+                        messages::text(
+                            "error.invalid_table_pattern_raw",
+                            &[("raw", &format_args!("{raw:?}")), ("error", &e)],
+                        )
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()
+        };
+        let (include, exclude) = (compile(&self.include)?, compile(&self.exclude)?);
+        Ok(all
+            .iter()
+            .map(String::as_str)
+            .filter(|table| include.is_empty() || include.iter().any(|p| p.matches(table)))
+            .filter(|table| !exclude.iter().any(|p| p.matches(table)))
+            .collect())
+    }
+}
+
+/// A string that differs between the two installs, replaced in the database
+/// in the direction of the sync.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replacement {
+    /// The value on the local install.
+    pub local: String,
+    /// The value on the remote install.
+    pub remote: String,
+}
+
+/// Which forms of a replacement's strings the search-replace looks for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplacementStrategy {
+    /// The strings as they are, like `wp search-replace`.
+    #[default]
+    Plain,
+    /// Also their JSON-escaped (`https:\/\/example.com`) and URL-encoded
+    /// (`https%3A%2F%2Fexample.com`) forms, as Elementor, Beaver Builder, and
+    /// block attributes store URLs. A plain search-replace misses those, and
+    /// the layouts keep pointing at the old site.
+    PageBuilder,
+}
+
+/// Which of the source's uploads an uploads-only sync copies.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadsSince {
+    /// All of them: the destination's uploads are made a copy of the source's.
+    #[default]
+    Everything,
+    /// Those modified since the last successful sync of the profile's files,
+    /// or all of them before the first.
+    LastSync,
+    /// Those modified since this time.
+    Date(DateTime<Utc>),
+}
+
+/// The WordPress install on this machine.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalEnvironment {
+    /// WordPress root directory (the one containing `wp-config.php`).
+    pub path: PathBuf,
+    /// Site URL, e.g. `http://mysite.test`.
+    pub url: String,
+    #[serde(default)]
+    pub database: Option<DatabaseSettings>,
+    /// The container the install runs in, for stacks that live entirely in
+    /// Docker: wp-cli, and the `mysql` it runs, are run inside it rather than
+    /// here. Files are still copied at `path`, so that must be where the
+    /// container's WordPress directory is mounted.
+    #[serde(default)]
+    pub container: Option<LocalContainer>,
+}
+
+/// A container wp-cli is run inside for the local install.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalContainer {
+    pub runtime: ContainerRuntime,
+    /// The Docker container's name or id. DDEV finds its project from the
+    /// local install's path instead, so leaves this empty.
+    #[serde(default)]
+    pub name: String,
+    /// The WordPress root inside the container, such as `/var/www/html`.
+    pub path: String,
+}
+
+/// How commands get into a [`LocalContainer`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContainerRuntime {
+    /// `docker exec`, as root unless the image says otherwise.
+    #[default]
+    Docker,
+    /// `ddev exec`, in the web container of the project at the local path.
+    Ddev,
+}
+
+/// The WordPress install on the server, reached over SSH.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteEnvironment {
+    pub host: String,
+    #[serde(default = "default_ssh_port")]
+    pub port: u16,
+    pub user: String,
+    /// How to authenticate.
+    #[serde(default)]
+    pub auth: AuthMethod,
+    /// Private key to authenticate with. When unset, the standard `~/.ssh/id_*` keys are tried.
+    #[serde(default)]
+    pub key_path: Option<PathBuf>,
+    /// Forward the local SSH agent so remote commands can use local keys (e.g. for git).
+    #[serde(default)]
+    pub forward_agent: bool,
+    /// How files are copied to and from the server.
+    #[serde(default)]
+    pub transfer: TransferMethod,
+    /// Files SFTP and FTPS transfers copy at once, each over its own channel or
+    /// login. rsync pipelines files itself and ignores this.
+    #[serde(default = "default_parallel_transfers")]
+    pub parallel_transfers: u32,
+    /// Connection details used when `transfer` is FTPS; host and user are shared with SSH.
+    #[serde(default)]
+    pub ftp: FtpSettings,
+    /// Bastion to tunnel through when the server isn't directly reachable (like `ProxyJump`).
+    #[serde(default)]
+    pub jump_host: Option<JumpHost>,
+    /// The proxy connections to the server, or to its jump host, go through.
+    #[serde(default)]
+    pub proxy: ProxyChoice,
+    /// Which of the server's addresses are connected to when there is no
+    /// proxy: both, IPv6 first, unless its IPv6 or IPv4 is known to be broken.
+    #[serde(default)]
+    pub address_family: AddressFamily,
+    /// WordPress root directory on the server.
+    pub path: String,
+    /// Site URL, e.g. `https://www.mysite.com`.
+    pub url: String,
+    #[serde(default)]
+    pub database: Option<DatabaseSettings>,
+    /// How the database phases reach the server's database.
+    #[serde(default)]
+    pub database_access: DatabaseAccess,
+    /// The wp-cli to run on the server, when it isn't the `wp` on the PATH:
+    /// one installed for this profile, or a host's own under another name.
+    #[serde(default)]
+    pub wp_cli: Option<String>,
+    /// The PHP wp-cli runs with on the server.
+    #[serde(default)]
+    pub php: PhpSettings,
+}
+
+/// How a sync reads and writes the server's database.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DatabaseAccess {
+    /// wp-cli on the server, which needs nothing more than SSH.
+    #[default]
+    WpCli,
+    /// `mysqldump` and `mysql` on this machine, connected through a port
+    /// forward over the SSH connection, for servers without wp-cli or whose
+    /// wp-cli can't dump. Needs the remote `database` settings, with a TCP
+    /// host, and its password stored under `DATABASE_PASSWORD_SERVICE`, keyed
+    /// by profile id. Other steps, such as restore points and detecting a
+    /// multisite, still read the database with wp-cli.
+    Tunnel,
+}
+
+impl RemoteEnvironment {
+    /// A server reached over SSH, with every other setting at its default.
+    pub fn over_ssh(host: String, port: u16, user: String, path: String, url: String) -> Self {
+        Self {
+            host,
+            port,
+            user,
+            auth: AuthMethod::default(),
+            key_path: None,
+            forward_agent: false,
+            transfer: TransferMethod::default(),
+            parallel_transfers: default_parallel_transfers(),
+            ftp: FtpSettings::default(),
+            jump_host: None,
+            proxy: ProxyChoice::default(),
+            address_family: AddressFamily::default(),
+            path,
+            url,
+            database: None,
+            database_access: DatabaseAccess::default(),
+            wp_cli: None,
+            php: PhpSettings::default(),
+        }
+    }
+}
+
+/// How wp-cli's PHP is run on the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PhpSettings {
+    /// The PHP binary, such as `php81` or a full path. When unset, the first
+    /// command-line PHP found on the server is used, trying `php` before the
+    /// versioned names and cPanel's and Plesk's builds.
+    #[serde(default)]
+    pub binary: Option<String>,
+    /// PHP's `memory_limit` for wp-cli, such as `512M`, or `-1` for none.
+    /// Shared hosts often give command-line PHP too little for `wp db export`
+    /// of a large database. Empty keeps the host's own.
+    #[serde(default = "default_memory_limit")]
+    pub memory_limit: String,
+}
+
+impl Default for PhpSettings {
+    fn default() -> Self {
+        Self {
+            binary: None,
+            memory_limit: default_memory_limit(),
+        }
+    }
+}
+
+/// Database connection details for one install.
+///
+/// wp-cli reads these from `wp-config.php`, so they are optional; when set they
+/// document what the install is expected to use. The password is never stored
+/// here, only in the credential store.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseSettings {
+    pub name: String,
+    pub user: String,
+    #[serde(default = "default_db_host")]
+    pub host: String,
+    #[serde(default = "default_table_prefix")]
+    pub table_prefix: String,
+}
+
+impl DatabaseSettings {
+    /// The host and port of `host`, as WordPress reads `DB_HOST`: `host`,
+    /// `host:port`, or `[ipv6]:port`, with MySQL's port 3306 by default.
+    /// Fails for a socket, such as `localhost:/run/mysqld/mysqld.sock`.
+    pub fn tcp_address(&self) -> Result<(String, u16), String> {
+        let host = self.host.trim();
+        let (name, port) = match host.strip_prefix('[') {
+            Some(rest) => match rest.split_once(']') {
+                Some((name, port)) => (name, port.strip_prefix(':')),
+                None => {
+                    return Err(messages::text(
+                        "error.host_missing_closing",
+                        &[("host", &format_args!("{host:?}"))],
+                    ))
+                }
+            },
+            None => match host.rsplit_once(':') {
+                Some((name, port)) => (name, Some(port)),
+                None => (host, None),
+            },
+        };
+        let port = match port {
+            Some(port) if port.starts_with('/') => {
+                return Err(messages::text(
+                    "error.host_socket_which_cant_reached_through",
+                    &[("host", &format_args!("{host:?}"))],
+                ))
+            }
+            Some(port) => port.parse().map_err(|_| {
+                messages::text(
+                    "error.port_host_not",
+                    &[
+                        ("port", &format_args!("{port:?}")),
+                        ("host", &format_args!("{host:?}")),
+                    ],
+                )
+            })?,
+            None => 3306,
+        };
+        let name = if name.is_empty() { "localhost" } else { name };
+        Ok((name.to_string(), port))
+    }
+}
+
+/// An intermediate SSH server the connection to the real server is tunnelled through.
+///
+/// It authenticates independently of the server; a password for it is stored
+/// under the profile's [`JumpHost::password_account`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JumpHost {
+    pub host: String,
+    #[serde(default = "default_ssh_port")]
+    pub port: u16,
+    pub user: String,
+    #[serde(default)]
+    pub auth: AuthMethod,
+    #[serde(default)]
+    pub key_path: Option<PathBuf>,
+}
+
+impl JumpHost {
+    /// Credential store account holding the jump host password for a profile.
+    pub fn password_account(profile_id: &str) -> String {
+        format!("{profile_id}/jump")
+    }
+}
+
+/// SSH authentication strategy for a remote environment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    /// Try the SSH agent, then key files, then a stored password, then
+    /// answering the server's questions.
+    #[default]
+    Auto,
+    /// Only keys held by the SSH agent.
+    Agent,
+    /// Only the configured (or default) key file.
+    Key,
+    /// Only the password stored in the credential store for this profile.
+    Password,
+    /// Answering the server's questions, as for a one-time code or a Duo
+    /// push, in the app. A stored password answers one asking for it.
+    KeyboardInteractive,
+}
+
+/// File transfer implementation for a remote environment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferMethod {
+    /// rsync on both machines; fastest, and the default.
+    #[default]
+    Rsync,
+    /// The server's SFTP subsystem, for hosts without rsync.
+    Sftp,
+    /// FTP (normally over TLS), for hosts with no SSH access at all. Syncs are
+    /// then files-only, since wp-cli can't be run on the server, and `path`
+    /// is the WordPress root as seen by the FTP login.
+    Ftps,
+    /// The WordPress REST API, for hosts with neither SSH nor FTP. Syncs then
+    /// move content rather than files and tables: posts, pages, media, and the
+    /// site settings the API offers. `user` is the WordPress user whose
+    /// Application Password is stored under `REST_PASSWORD_SERVICE`, keyed by
+    /// profile id; `host`, `port`, and `path` aren't used.
+    RestApi,
+}
+
+impl TransferMethod {
+    /// Whether the method runs over SSH, and so gives the sync a remote shell.
+    pub fn uses_ssh(self) -> bool {
+        !matches!(self, Self::Ftps | Self::RestApi)
+    }
+}
+
+/// How to reach an FTP server. The password lives in the credential store under
+/// `FTP_PASSWORD_SERVICE`, keyed by profile id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FtpSettings {
+    #[serde(default = "default_ftp_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub tls: FtpTls,
+    /// Passive mode (the server opens data ports), which works through
+    /// client-side NAT and firewalls. Active mode is rarely needed.
+    #[serde(default = "default_true")]
+    pub passive: bool,
+}
+
+impl Default for FtpSettings {
+    fn default() -> Self {
+        Self {
+            port: default_ftp_port(),
+            tls: FtpTls::default(),
+            passive: true,
+        }
+    }
+}
+
+/// How an FTP connection is secured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FtpTls {
+    /// Plain connection upgraded with `AUTH TLS` (FTPES); the usual choice.
+    #[default]
+    Explicit,
+    /// TLS from the first byte, usually on port 990.
+    Implicit,
+    /// No encryption. The password and every file cross the network in the clear.
+    None,
+}
+
+fn default_ftp_port() -> u16 {
+    21
+}
+
+fn default_health_check_urls() -> Vec<HealthCheck> {
+    ["/", "/wp-admin/", "/wp-json/"]
+        .into_iter()
+        .map(|url| HealthCheck {
+            url: url.to_string(),
+            status: default_health_check_status(),
+        })
+        .collect()
+}
+
+fn default_response_diff_paths() -> Vec<String> {
+    vec!["/".to_string()]
+}
+
+fn default_response_diff_sample() -> u32 {
+    20
+}
+
+fn default_screenshot_paths() -> Vec<String> {
+    vec!["/".to_string()]
+}
+
+fn default_screenshot_width() -> u32 {
+    1280
+}
+
+fn default_screenshot_height() -> u32 {
+    900
+}
+
+fn default_screenshot_threshold() -> f64 {
+    1.0
+}
+
+fn default_health_check_status() -> u16 {
+    200
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_ignore() -> WarningAction {
+    WarningAction::Ignore
+}
+
+fn default_warn() -> WarningAction {
+    WarningAction::Warn
+}
+
+fn default_block() -> WarningAction {
+    WarningAction::Block
+}
+
+fn default_large_deletion_threshold() -> u64 {
+    100
+}
+
+fn default_keep_last() -> u32 {
+    5
+}
+
+fn default_keep_daily_days() -> u32 {
+    7
+}
+
+fn default_parallel_transfers() -> u32 {
+    4
+}
+
+fn default_import_workers() -> u32 {
+    4
+}
+
+fn default_max_attempts() -> u32 {
+    4
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    2_000
+}
+
+fn default_max_backoff_ms() -> u64 {
+    60_000
+}
+
+fn default_connect_secs() -> u64 {
+    30
+}
+
+fn default_read_secs() -> u64 {
+    60
+}
+
+fn default_keepalive_secs() -> u64 {
+    30
+}
+
+fn default_keepalive_max() -> u32 {
+    3
+}
+
+fn default_linger_secs() -> u64 {
+    120
+}
+
+fn initial_version() -> u32 {
+    1
+}
+
+fn default_memory_limit() -> String {
+    "512M".to_string()
+}
+
+fn default_db_host() -> String {
+    "localhost".to_string()
+}
+
+fn default_table_prefix() -> String {
+    "wp_".to_string()
+}
+
+fn default_ssh_port() -> u16 {
+    22
+}
+
+/// Load a profile by its id from `~/.wordpress-sync/profiles/<id>.json`.
+pub fn load(id: &str) -> Result<Profile, String> {
+    read(&path_for(id)?)
+}
+
+/// Every saved profile, sorted by name. Unreadable files are skipped rather than
+/// hiding all the others.
+pub fn list() -> Result<Vec<Profile>, String> {
+    let dir = paths::profiles_dir()?;
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(messages::text(
+                "error.failed.list_path",
+                &[("path", &dir.display()), ("error", &e)],
+            ))
+        }
+    };
+    let mut profiles: Vec<Profile> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| read(&path).ok())
+        .collect();
+    profiles.sort_by_cached_key(|profile| profile.name.to_lowercase());
+    Ok(profiles)
+}
+
+/// Write a profile, creating it if it has no id yet, and return it as saved.
+pub fn save(mut profile: Profile) -> Result<Profile, String> {
+    if profile.id.is_empty() {
+        profile.id = uuid::Uuid::new_v4().to_string();
+    }
+    profile.version = PROFILE_VERSION;
+    let path = path_for(&profile.id)?;
+    let value = serde_json::to_value(&profile)
+        .map_err(|e| messages::text("error.failed.serialize_profile", &[("error", &e)]))?;
+    let json = serde_json::to_string_pretty(&inherit::strip_inherited(value)?)
+        .map_err(|e| messages::text("error.failed.serialize_profile", &[("error", &e)]))?;
+
+    let dir = paths::profiles_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| {
+        messages::text(
+            "error.failed.create_path",
+            &[("path", &dir.display()), ("error", &e)],
+        )
+    })?;
+    paths::atomic_write(&path, json)?;
+    Ok(profile)
+}
+
+/// Delete a profile. Succeeds if it doesn't exist.
+pub fn delete(id: &str) -> Result<(), String> {
+    let path = path_for(id)?;
+    match fs::remove_file(&path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(messages::text(
+            "error.failed.delete_path",
+            &[("path", &path.display()), ("error", &e)],
+        )),
+    }
+}
+
+fn read(path: &Path) -> Result<Profile, String> {
+    let (merged, _) = inherit::merge_bases(read_json(path)?)?;
+    from_json(merged, path)
+}
+
+/// The profile saved at `path`, as it is saved, upgraded to this build's format.
+fn read_json(path: &Path) -> Result<serde_json::Value, String> {
+    let raw = fs::read_to_string(path).map_err(|e| {
+        messages::text(
+            "error.failed.read_profile_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })?;
+    let mut value = serde_json::from_str(&raw).map_err(|e| {
+        messages::text(
+            "error.invalid_profile_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })?;
+    migrate::migrate(&mut value, path)?;
+    Ok(value)
+}
+
+/// The profile saved at `path`, from its JSON merged over any bases.
+fn from_json(value: serde_json::Value, path: &Path) -> Result<Profile, String> {
+    serde_json::from_value(value).map_err(|e| {
+        messages::text(
+            "error.invalid_profile_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })
+}
+
+/// The file for a profile id, refusing ids that could escape the profiles directory.
+fn path_for(id: &str) -> Result<PathBuf, String> {
+    let valid = !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(messages::text(
+            "error.invalid_profile_id",
+            &[("id", &format_args!("{id:?}"))],
+        ));
+    }
+    Ok(paths::profiles_dir()?.join(format!("{id}.json")))
+}
@@ -0,0 +1,44 @@
+//! Offloaded media: sites whose uploads live in an S3, Spaces, or other
+//! bucket and are served from there or a CDN, rather than from the
+//! `uploads` directory.
+//!
+//! Such a profile leaves `wp-content/uploads` out of file transfers, since
+//! the bucket already has the media, and replaces each side's bucket or CDN
+//! URL in the database. WP Offload Media's `as3cf_items` table, which records
+//! the bucket each attachment is in, can be left out so each side keeps its
+//! own; the plugin's settings are options, and are best kept per environment
+//! by defining `AS3CF_SETTINGS` in each `wp-config.php`.
+
+use serde::{Deserialize, Serialize};
+
+use super::Replacement;
+
+/// WP Offload Media's tables, without the table prefix.
+pub const OFFLOAD_TABLES: &[&str] = &["as3cf_items"];
+
+/// How a site with offloaded media is synced.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MediaOffload {
+    /// Each side's bucket or CDN URL, such as
+    /// `https://cdn-staging.example.com` and `https://cdn.example.com`,
+    /// replaced after the profile's own replacements.
+    #[serde(default)]
+    pub urls: Vec<Replacement>,
+    /// Leave the offload plugin's tables out of the sync, so each side keeps
+    /// its own record of where its media is.
+    #[serde(default)]
+    pub keep_offload_tables: bool,
+}
+
+impl MediaOffload {
+    /// Table exclude patterns for what the profile leaves out, matching any
+    /// prefix and subsite.
+    pub fn excluded_tables(&self) -> Vec<String> {
+        self.keep_offload_tables
+            .then_some(OFFLOAD_TABLES)
+            .into_iter()
+            .flatten()
+            .map(|table| format!("*{table}"))
+            .collect()
+    }
+}
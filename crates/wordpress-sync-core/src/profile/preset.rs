@@ -0,0 +1,68 @@
+//! Named syncs a profile keeps ready to run, such as a quick content refresh
+//! that pulls only the database and keeps the local users, so a common sync
+//! is one click instead of the same options picked again each time.
+
+use serde::{Deserialize, Serialize};
+
+use super::Profile;
+use crate::messages;
+use crate::sync::{Components, Direction};
+
+/// A sync of a profile with its options set, run with `run_preset`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Preset {
+    /// What it's run by, unique within the profile.
+    pub name: String,
+    pub direction: Direction,
+    #[serde(default)]
+    pub components: Components,
+    /// Each of these set in place of the profile's own for the preset's syncs;
+    /// unset, the profile's is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preserve_users: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maintenance_mode: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_before_sync: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksums: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skip_cache_rows: Option<bool>,
+    /// Exclude rules added to the profile's for the preset's syncs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub excludes: Vec<String>,
+}
+
+impl Preset {
+    /// Change `profile`'s settings to those the preset sets, leaving the rest.
+    pub fn apply(&self, profile: &mut Profile) {
+        let overrides = [
+            (self.preserve_users, &mut profile.preserve_users),
+            (self.maintenance_mode, &mut profile.maintenance_mode),
+            (self.backup_before_sync, &mut profile.backup_before_sync),
+            (self.checksums, &mut profile.checksums),
+            (self.skip_cache_rows, &mut profile.skip_cache_rows),
+        ];
+        for (value, setting) in overrides {
+            if let Some(value) = value {
+                *setting = value;
+            }
+        }
+        profile.excludes.extend(self.excludes.iter().cloned());
+    }
+}
+
+impl Profile {
+    /// The preset named `name`.
+    pub fn preset(&self, name: &str) -> Result<&Preset, String> {
+        self.presets
+            .iter()
+            .find(|preset| preset.name == name)
+            .ok_or_else(|| {
+                messages::text(
+                    "error.name_has_no_preset_named_name2",
+                    &[("name", &self.name), ("name2", &format_args!("{name:?}"))],
+                )
+            })
+    }
+}
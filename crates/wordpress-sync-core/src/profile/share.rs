@@ -0,0 +1,334 @@
+//! Profiles as single portable files, for sharing a site's setup with a team
+//! or moving it to another machine.
+//!
+//! The profile itself is written as it is synced, less its id. Its passwords
+//! live in the credential store rather than the profile, so they are left
+//! out, unless the export is given a passphrase: then the ones stored for the
+//! profile, and the named secrets it refers to, go in too, and the whole of
+//! it is encrypted with AES-256-GCM under a key derived from the passphrase
+//! with Argon2id, so the file gives away nothing of the site without it. The
+//! machine's own key, which local backups are encrypted with, would keep the
+//! file from being imported anywhere else.
+
+use std::fs;
+use std::path::Path;
+
+use aes_gcm::aead::{Aead, Generate, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use super::{save, JumpHost, Profile};
+
+use crate::credentials::{
+    self, platform_store, CredentialStore, CDN_TOKEN_SERVICE, DATABASE_PASSWORD_SERVICE,
+    FTP_PASSWORD_SERVICE, NAMED_SECRET_SERVICE, REST_PASSWORD_SERVICE, SSH_PASSWORD_SERVICE,
+    SUDO_PASSWORD_SERVICE,
+};
+use crate::{messages, paths};
+
+/// What an exported profile's `format` says, so other JSON isn't mistaken for one.
+const FORMAT: &str = "wordpress-sync-profile";
+
+/// The version of the exported file's layout this build writes and reads.
+/// Version 1 left the profile out of what the passphrase encrypts.
+const FORMAT_VERSION: u32 = 2;
+
+/// Argon2id's memory cost in KiB, passes, and lanes, as [`seal`] uses them:
+/// the least a file may ask to be opened with.
+const MIN_COSTS: [u32; 3] = [
+    Params::DEFAULT_M_COST,
+    Params::DEFAULT_T_COST,
+    Params::DEFAULT_P_COST,
+];
+
+/// The most a file may ask for, so opening one can't take all of the
+/// machine's memory or the rest of the day.
+const MAX_COSTS: [u32; 3] = [256 * 1024, 16, 8];
+
+/// The services whose entries for a profile are keyed by its id.
+const PROFILE_SERVICES: &[&str] = &[
+    SSH_PASSWORD_SERVICE,
+    FTP_PASSWORD_SERVICE,
+    REST_PASSWORD_SERVICE,
+    DATABASE_PASSWORD_SERVICE,
+    SUDO_PASSWORD_SERVICE,
+    CDN_TOKEN_SERVICE,
+];
+
+#[derive(Serialize, Deserialize)]
+struct ExportedProfile {
+    format: String,
+    version: u32,
+    /// The profile, unless it's sealed in `secrets`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    profile: Option<Profile>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    secrets: Option<Sealed>,
+}
+
+/// What a passphrase seals.
+#[derive(Serialize, Deserialize)]
+struct Contents {
+    profile: Profile,
+    secrets: Vec<Secret>,
+}
+
+/// The profile and its secrets, encrypted: [`Contents`], or only the list of
+/// [`Secret`]s in a version 1 file.
+#[derive(Serialize, Deserialize)]
+struct Sealed {
+    /// Argon2id's memory cost in KiB, passes, and lanes.
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    salt: String,
+    nonce: String,
+    /// The JSON, encrypted and authenticated.
+    ciphertext: String,
+}
+
+/// One secret from the credential store.
+#[derive(Serialize, Deserialize)]
+struct Secret {
+    service: String,
+    /// The account, with the profile's id left off the front for the entries
+    /// keyed by it, so they can be stored again under the imported profile's.
+    account: String,
+    secret: String,
+}
+
+/// Write the saved profile `id` to `path` as a single file. With a
+/// `passphrase`, it's encrypted with it, its secrets included; without, they
+/// are left out, to be stored again wherever it is imported.
+pub fn export(id: &str, path: &Path, passphrase: Option<&str>) -> Result<(), String> {
+    let mut profile = super::load(id)?;
+    let secrets = match passphrase {
+        Some(_) => secrets_of(&profile)?,
+        None => Vec::new(),
+    };
+    profile.id = String::new();
+    // Its bases are merged in, since the machine it goes to may not have them.
+    profile.extends = None;
+    let (profile, secrets) = match passphrase {
+        Some(passphrase) => (
+            None,
+            Some(seal(&Contents { profile, secrets }, passphrase)?),
+        ),
+        None => (Some(profile), None),
+    };
+    let exported = ExportedProfile {
+        format: FORMAT.to_string(),
+        version: FORMAT_VERSION,
+        profile,
+        secrets,
+    };
+    let json = serde_json::to_string_pretty(&exported)
+        .map_err(|e| messages::text("error.failed.serialize_profile", &[("error", &e)]))?;
+    paths::atomic_write_private(path, json)
+}
+
+/// Save the profile exported to `path` as a new one, and store the secrets
+/// exported with it, which need the `passphrase` it was exported with. A
+/// named secret this machine already stores keeps its own value.
+pub fn import(path: &Path, passphrase: Option<&str>) -> Result<Profile, String> {
+    let raw = fs::read_to_string(path).map_err(|e| {
+        messages::text(
+            "error.failed.read_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })?;
+    let exported: ExportedProfile = serde_json::from_str(&raw).map_err(|e| {
+        messages::text(
+            "error.path_isnt_exported_profile",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })?;
+    if exported.format != FORMAT {
+        return Err(messages::text(
+            "error.path_isnt_exported_profile_2",
+            &[("path", &path.display())],
+        ));
+    }
+    if exported.version > FORMAT_VERSION {
+        return Err(messages::text(
+            "error.path_was_exported_newer_version_app",
+            &[
+                ("path", &path.display()),
+                ("version", &exported.version),
+                ("format_version", &FORMAT_VERSION),
+            ],
+        ));
+    }
+    let (mut profile, secrets) = match (exported.profile, &exported.secrets, passphrase) {
+        (_, Some(_), None) => {
+            return Err(messages::text(
+                "error.profile_was_exported_secrets_give_passphrase",
+                &[],
+            ))
+        }
+        // Only version 1 left the profile out of what's sealed; a later file
+        // with it in the clear has been tampered with.
+        (Some(profile), Some(sealed), Some(passphrase)) if exported.version == 1 => {
+            (profile, open(sealed, passphrase)?)
+        }
+        (Some(_), Some(_), Some(_)) => {
+            return Err(messages::text(
+                "error.exported_profile_outside_seal",
+                &[("path", &path.display())],
+            ))
+        }
+        (None, Some(sealed), Some(passphrase)) => {
+            let contents: Contents = open(sealed, passphrase)?;
+            (contents.profile, contents.secrets)
+        }
+        (Some(profile), None, _) => (profile, Vec::new()),
+        (None, None, _) => {
+            return Err(messages::text(
+                "error.path_isnt_exported_profile_2",
+                &[("path", &path.display())],
+            ))
+        }
+    };
+    // The secrets go in first, under the id the profile is saved with, so a
+    // profile is never left saved without them.
+    profile.id = uuid::Uuid::new_v4().to_string();
+    let store = platform_store();
+    let mut stored = Vec::new();
+    let saved =
+        store_secrets(&store, &profile.id, &secrets, &mut stored).and_then(|()| save(profile));
+    if saved.is_err() {
+        for (service, account) in &stored {
+            if let Err(e) = store.delete(service, account) {
+                tracing::warn!(%service, %account, error = %e, "failed to remove an imported secret");
+            }
+        }
+    }
+    saved
+}
+
+/// Store the imported `secrets` for the profile `id`, noting in `stored` the
+/// entries written, for taking them out again if the import fails.
+fn store_secrets(
+    store: &impl CredentialStore,
+    id: &str,
+    secrets: &[Secret],
+    stored: &mut Vec<(String, String)>,
+) -> Result<(), String> {
+    for secret in secrets {
+        let account = if secret.service == NAMED_SECRET_SERVICE {
+            if store.get(&secret.service, &secret.account)?.is_some() {
+                continue;
+            }
+            secret.account.clone()
+        } else if PROFILE_SERVICES.contains(&secret.service.as_str()) {
+            format!("{id}{}", secret.account)
+        } else {
+            continue;
+        };
+        store.set(&secret.service, &account, &secret.secret)?;
+        stored.push((secret.service.clone(), account));
+    }
+    Ok(())
+}
+
+/// Every secret stored for `profile` and every named secret it refers to.
+fn secrets_of(profile: &Profile) -> Result<Vec<Secret>, String> {
+    let store = platform_store();
+    let mut secrets = Vec::new();
+    let jump = JumpHost::password_account(&profile.id);
+    for service in PROFILE_SERVICES {
+        for account in [profile.id.as_str(), jump.as_str()] {
+            if account == jump && *service != SSH_PASSWORD_SERVICE {
+                continue;
+            }
+            if let Some(secret) = store.get(service, account)? {
+                secrets.push(Secret {
+                    service: service.to_string(),
+                    account: account[profile.id.len()..].to_string(),
+                    secret,
+                });
+            }
+        }
+    }
+    for (_, reference) in profile.secrets.references() {
+        let name = credentials::secret_name(reference)?;
+        if let Some(secret) = store.get(NAMED_SECRET_SERVICE, name)? {
+            secrets.push(Secret {
+                service: NAMED_SECRET_SERVICE.to_string(),
+                account: name.to_string(),
+                secret,
+            });
+        }
+    }
+    Ok(secrets)
+}
+
+fn seal(contents: &impl Serialize, passphrase: &str) -> Result<Sealed, String> {
+    let [m_cost, t_cost, p_cost] = MIN_COSTS;
+    let salt = <[u8; 16]>::generate();
+    let nonce = Nonce::generate();
+    let cipher = cipher(passphrase, &salt, m_cost, t_cost, p_cost)?;
+    let plaintext = serde_json::to_vec(contents)
+        .map_err(|e| messages::text("error.failed.serialize_secrets", &[("error", &e)]))?;
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext.as_slice())
+        .map_err(|_| messages::text("error.failed.encrypt_secrets", &[]))?;
+    Ok(Sealed {
+        m_cost,
+        t_cost,
+        p_cost,
+        salt: BASE64.encode(salt),
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+    })
+}
+
+fn open<T: DeserializeOwned>(sealed: &Sealed, passphrase: &str) -> Result<T, String> {
+    let costs = [sealed.m_cost, sealed.t_cost, sealed.p_cost];
+    let in_range = costs
+        .iter()
+        .zip(MIN_COSTS.iter().zip(MAX_COSTS))
+        .all(|(cost, (min, max))| (*min..=max).contains(cost));
+    if !in_range {
+        return Err(messages::text("error.exported_key_costs_out_of_range", &[]));
+    }
+    let corrupt = |_| messages::text("error.exported_secrets_are_corrupt", &[]);
+    let salt = BASE64.decode(&sealed.salt).map_err(corrupt)?;
+    let nonce = BASE64.decode(&sealed.nonce).map_err(corrupt)?;
+    let nonce = Nonce::try_from(nonce.as_slice())
+        .map_err(|_| messages::text("error.exported_secrets_are_corrupt", &[]))?;
+    let ciphertext = BASE64.decode(&sealed.ciphertext).map_err(corrupt)?;
+    let cipher = cipher(
+        passphrase,
+        &salt,
+        sealed.m_cost,
+        sealed.t_cost,
+        sealed.p_cost,
+    )?;
+    let plaintext = cipher
+        .decrypt(&nonce, ciphertext.as_slice())
+        .map_err(|_| messages::text("error.passphrase_wrong_exported_secrets_are_corrupt", &[]))?;
+    serde_json::from_slice(&plaintext)
+        .map_err(|_| messages::text("error.exported_secrets_are_corrupt", &[]))
+}
+
+/// The cipher keyed by `passphrase` under Argon2id with these parameters.
+fn cipher(
+    passphrase: &str,
+    salt: &[u8],
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+) -> Result<Aes256Gcm, String> {
+    let params = Params::new(m_cost, t_cost, p_cost, Some(32))
+        .map_err(|e| messages::text("error.invalid_key_derivation_parameters", &[("error", &e)]))?;
+    let mut key = Key::<Aes256Gcm>::default();
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| messages::text("error.failed.derive_key_passphrase", &[("error", &e)]))?;
+    Ok(Aes256Gcm::new(&key))
+}
@@ -0,0 +1,181 @@
+//! Profiles grouped into sites, each with the environments it runs in.
+//!
+//! A profile syncs one local install with one server. The profiles sharing a
+//! local install, or naming the same `site`, are one site: its environments
+//! are the local install and each profile's server, such as staging and
+//! production, and every sync is a pair of them, as in staging → local for a
+//! pull, local → staging for a push, or staging → production between two
+//! servers.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use super::Profile;
+use crate::messages;
+use crate::sync::Direction;
+
+/// The id of every site's local environment.
+pub const LOCAL_ENVIRONMENT: &str = "local";
+
+/// One site, and the environments it is synced between.
+#[derive(Debug, Clone, Serialize)]
+pub struct Site {
+    pub id: String,
+    pub name: String,
+    /// The local install every environment is synced with.
+    pub local_path: PathBuf,
+    /// The local install first, then the servers by name.
+    pub environments: Vec<SiteEnvironment>,
+    /// Every sync the site's profiles can run, as from → to pairs.
+    pub pairs: Vec<EnvironmentPair>,
+}
+
+/// One place a site runs.
+#[derive(Debug, Clone, Serialize)]
+pub struct SiteEnvironment {
+    /// [`LOCAL_ENVIRONMENT`] for the local install, otherwise the id of the
+    /// profile that syncs it.
+    pub id: String,
+    /// The profile's `environment`, or else its server's host or its own
+    /// name; `local` for the local install.
+    pub name: String,
+    pub url: String,
+    /// Pushes to it need a confirmation.
+    pub protected: bool,
+}
+
+/// A sync from one of a site's environments to another.
+#[derive(Debug, Clone, Serialize)]
+pub struct EnvironmentPair {
+    pub from: String,
+    pub to: String,
+    /// The profile that runs it, in `direction`. Between two servers, it's the
+    /// destination's, pushed to from `source_profile_id`'s.
+    pub profile_id: String,
+    pub direction: Direction,
+    /// The source's profile, for a sync between two servers run with
+    /// `sync_servers`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_profile_id: Option<String>,
+    /// It writes to a protected server, so is only run once confirmed.
+    pub needs_confirmation: bool,
+}
+
+/// Every site the saved profiles make up, sorted by name.
+pub fn list_sites() -> Result<Vec<Site>, String> {
+    let mut groups: BTreeMap<String, Vec<Profile>> = BTreeMap::new();
+    for profile in super::list()? {
+        groups.entry(site_key(&profile)).or_default().push(profile);
+    }
+    let mut sites: Vec<Site> = groups
+        .into_iter()
+        .map(|(key, profiles)| site(&key, profiles))
+        .collect();
+    sites.sort_by_cached_key(|site| site.name.to_lowercase());
+    Ok(sites)
+}
+
+/// The environments of the site `site_id`, the local install first.
+pub fn list_environments(site_id: &str) -> Result<Vec<SiteEnvironment>, String> {
+    list_sites()?
+        .into_iter()
+        .find(|site| site.id == site_id)
+        .map(|site| site.environments)
+        .ok_or_else(|| {
+            messages::text(
+                "error.there_no_site_id",
+                &[("site_id", &format_args!("{site_id:?}"))],
+            )
+        })
+}
+
+/// What groups `profile` with the others of its site.
+fn site_key(profile: &Profile) -> String {
+    match &profile.site {
+        Some(site) => format!("site:{}", site.trim().to_lowercase()),
+        None => format!("path:{}", profile.local.path.display()),
+    }
+}
+
+/// The site made of `profiles`, grouped under `key`, which share a local
+/// install unless they name their site.
+fn site(key: &str, mut profiles: Vec<Profile>) -> Site {
+    profiles.sort_by_cached_key(|profile| environment_name(profile).to_lowercase());
+    let first = &profiles[0];
+    let name = first.site.clone().unwrap_or_else(|| {
+        first.local.path.file_name().map_or_else(
+            || first.name.clone(),
+            |name| name.to_string_lossy().into_owned(),
+        )
+    });
+    let mut environments = vec![SiteEnvironment {
+        id: LOCAL_ENVIRONMENT.to_string(),
+        name: LOCAL_ENVIRONMENT.to_string(),
+        url: first.local.url.clone(),
+        protected: false,
+    }];
+    let mut pairs = Vec::new();
+    for profile in &profiles {
+        environments.push(SiteEnvironment {
+            id: profile.id.clone(),
+            name: environment_name(profile),
+            url: profile.remote.url.clone(),
+            protected: profile.protected,
+        });
+        pairs.push(EnvironmentPair {
+            from: profile.id.clone(),
+            to: LOCAL_ENVIRONMENT.to_string(),
+            profile_id: profile.id.clone(),
+            direction: Direction::Pull,
+            source_profile_id: None,
+            needs_confirmation: false,
+        });
+        pairs.push(EnvironmentPair {
+            from: LOCAL_ENVIRONMENT.to_string(),
+            to: profile.id.clone(),
+            profile_id: profile.id.clone(),
+            direction: Direction::Push,
+            source_profile_id: None,
+            needs_confirmation: profile.protected,
+        });
+    }
+    for from in &profiles {
+        for to in profiles.iter().filter(|to| to.id != from.id) {
+            pairs.push(EnvironmentPair {
+                from: from.id.clone(),
+                to: to.id.clone(),
+                profile_id: to.id.clone(),
+                direction: Direction::Push,
+                source_profile_id: Some(from.id.clone()),
+                needs_confirmation: to.protected,
+            });
+        }
+    }
+    Site {
+        id: site_id(key),
+        name,
+        local_path: first.local.path.clone(),
+        environments,
+        pairs,
+    }
+}
+
+fn environment_name(profile: &Profile) -> String {
+    let host = &profile.remote.host;
+    match &profile.environment {
+        Some(environment) => environment.clone(),
+        None if host.is_empty() => profile.name.clone(),
+        None => host.clone(),
+    }
+}
+
+/// A short, stable id for the site grouped under `key`.
+fn site_id(key: &str) -> String {
+    Sha256::digest(key.as_bytes())[..8]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
@@ -0,0 +1,35 @@
+//! Starting points for a new profile, applied on top of its connection details.
+
+use serde::{Deserialize, Serialize};
+
+use super::{PaymentTestMode, Profile, WooCommerce};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileTemplate {
+    /// A WooCommerce store: products and settings sync, while each side keeps
+    /// its own orders, customers, and user accounts, a sync that would
+    /// replace newer orders is flagged, and a pulled copy takes only test
+    /// payments.
+    Woocommerce,
+}
+
+impl ProfileTemplate {
+    /// Change `profile`'s settings to the template's, leaving the rest as they are.
+    pub fn apply(self, profile: &mut Profile) {
+        match self {
+            ProfileTemplate::Woocommerce => {
+                profile.woocommerce = Some(WooCommerce {
+                    skip_orders: true,
+                    skip_customers: true,
+                    check_orders: true,
+                    payment_test_mode: PaymentTestMode::Local,
+                });
+                profile.preserve_users = true;
+                profile.skip_cache_rows = true;
+                // Only the store's skips narrow the tables.
+                profile.tables.include.clear();
+            }
+        }
+    }
+}
@@ -0,0 +1,878 @@
+//! Checks a profile before it is saved or synced, so problems can be shown next
+//! to the field that causes them instead of surfacing halfway through a sync.
+
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Serialize;
+use url::Url;
+
+use super::blackout;
+use super::inherit;
+use super::woocommerce::STORE_TABLES;
+use super::{
+    CdnPurge, CdnTarget, ContainerRuntime, DatabaseAccess, HookSide, Profile, SudoStep, SyncScope,
+    TransferMethod,
+};
+use crate::credentials;
+use crate::messages;
+use crate::net::{self, AddressFamily};
+use crate::proxy::{self, ProxyChoice};
+use crate::ssh;
+use crate::sync::{self, Excludes};
+
+/// How long a host name lookup may take before the host is reported unresolvable.
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// The sync can't work until this is fixed.
+    Error,
+    /// The sync may work, but probably not as intended.
+    Warning,
+}
+
+/// What kind of problem an [`Issue`] is, for the frontend to match on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueCode {
+    Required,
+    BaseNotFound,
+    PathNotFound,
+    NotADirectory,
+    NotWordPress,
+    RelativePath,
+    InvalidUrl,
+    InvalidPort,
+    InvalidExclude,
+    InvalidTablePattern,
+    InvalidSubsite,
+    StoreTableExcluded,
+    HookNeedsSsh,
+    ChecksumsNeedSsh,
+    InvalidMode,
+    InvalidOwner,
+    PermissionsNeedSsh,
+    SudoUnsupported,
+    InvalidSudoUser,
+    CommandNotVetted,
+    InvalidSecretReference,
+    ContainerUnsupported,
+    TunnelNeedsSsh,
+    InvalidHost,
+    InvalidStatus,
+    UnresolvableHost,
+    KeyNotFound,
+    InvalidMemoryLimit,
+    InvalidPartition,
+    InvalidTime,
+    PluginNotFound,
+    InvalidSize,
+    ComposerUnsupported,
+    DuplicateName,
+}
+
+/// One problem with a profile.
+#[derive(Debug, Clone, Serialize)]
+pub struct Issue {
+    pub severity: Severity,
+    pub code: IssueCode,
+    /// Dotted path of the offending field, e.g. `remote.jump_host.host` or `replacements.0.local`.
+    pub field: String,
+    pub message: String,
+}
+
+/// Run every structural and semantic check on `profile`. An empty list means
+/// the profile looks ready to sync.
+pub async fn validate(profile: &Profile) -> Vec<Issue> {
+    let mut issues = Issues::default();
+    let local = &profile.local;
+    let remote = &profile.remote;
+
+    issues.required("name", &profile.name);
+    if let Some(name) = profile
+        .extends
+        .as_deref()
+        .filter(|name| !inherit::base_exists(name))
+    {
+        issues.error(
+            IssueCode::BaseNotFound,
+            "extends",
+            messages::text(
+                "issue.no_profile_base_named",
+                &[("name", &format_args!("{name:?}"))],
+            ),
+        );
+    }
+
+    if local.path.as_os_str().is_empty() {
+        issues.error(
+            IssueCode::Required,
+            "local.path",
+            messages::text("issue.required", &[]),
+        );
+    } else if !local.path.exists() {
+        issues.error(
+            IssueCode::PathNotFound,
+            "local.path",
+            messages::text(
+                "issue.path_doesnt_exist",
+                &[("path", &local.path.display())],
+            ),
+        );
+    } else if !local.path.is_dir() {
+        issues.error(
+            IssueCode::NotADirectory,
+            "local.path",
+            messages::text(
+                "issue.path_isnt_directory",
+                &[("path", &local.path.display())],
+            ),
+        );
+    } else if !local.path.join(profile.project.wp_config()).is_file() {
+        issues.warning(
+            IssueCode::NotWordPress,
+            "local.path",
+            messages::text(
+                "issue.no_wp_config_in_path",
+                &[
+                    ("file", &profile.project.wp_config()),
+                    ("path", &local.path.display()),
+                ],
+            ),
+        );
+    }
+    issues.url("local.url", &local.url);
+
+    // The REST API is reached at the site URL, with no host or path of its own.
+    let rest = remote.transfer == TransferMethod::RestApi;
+    if let Some(container) = &local.container {
+        if container.runtime == ContainerRuntime::Docker {
+            issues.required("local.container.name", &container.name);
+        }
+        if issues.required("local.container.path", &container.path)
+            && !container.path.starts_with('/')
+        {
+            issues.error(
+                IssueCode::RelativePath,
+                "local.container.path",
+                messages::text("issue.must_be_absolute_path", &[]),
+            );
+        }
+        if rest {
+            // Its media imports and post contents are files on this machine.
+            issues.error(
+                IssueCode::ContainerUnsupported,
+                "local.container",
+                messages::text("issue.rest_api_cant_use_container", &[]),
+            );
+        }
+    }
+    let limit = remote.php.memory_limit.trim();
+    if !limit.is_empty() && !is_memory_limit(limit) {
+        issues.error(
+            IssueCode::InvalidMemoryLimit,
+            "remote.php.memory_limit",
+            messages::text(
+                "issue.invalid_memory_limit",
+                &[("limit", &format_args!("{limit:?}"))],
+            ),
+        );
+    }
+    // Behind a proxy, the proxy looks up the first host connected to, which
+    // this machine may not be able to.
+    let first_hop = match &remote.jump_host {
+        Some(jump) if remote.transfer.uses_ssh() => &jump.host,
+        _ => &remote.host,
+    };
+    let proxied = remote.transfer != TransferMethod::Ftps
+        && proxy::effective(&remote.proxy)
+            .ok()
+            .flatten()
+            .is_some_and(|proxy| !proxy.bypasses(first_hop));
+    if let ProxyChoice::Custom(proxy) = &remote.proxy {
+        issues.required("remote.proxy.host", &proxy.host);
+        issues.port("remote.proxy.port", proxy.port);
+    }
+    if !rest && issues.required("remote.host", &remote.host) && !proxied {
+        issues
+            .host(
+                "remote.host",
+                &remote.host,
+                remote_port(profile),
+                remote.address_family,
+            )
+            .await;
+    }
+    issues.required("remote.user", &remote.user);
+    if remote.transfer.uses_ssh() {
+        issues.port("remote.port", remote.port);
+        issues.key("remote.key_path", remote.key_path.as_deref());
+    } else if !rest {
+        issues.port("remote.ftp.port", remote.ftp.port);
+    }
+    if !rest && issues.required("remote.path", &remote.path) && !remote.path.starts_with('/') {
+        issues.error(
+            IssueCode::RelativePath,
+            "remote.path",
+            messages::text("issue.must_be_absolute_path", &[]),
+        );
+    }
+    issues.url("remote.url", &remote.url);
+
+    if let Some(jump) = remote
+        .jump_host
+        .as_ref()
+        .filter(|_| remote.transfer.uses_ssh())
+    {
+        if issues.required("remote.jump_host.host", &jump.host) && !proxied {
+            issues
+                .host(
+                    "remote.jump_host.host",
+                    &jump.host,
+                    jump.port,
+                    remote.address_family,
+                )
+                .await;
+        }
+        issues.required("remote.jump_host.user", &jump.user);
+        issues.port("remote.jump_host.port", jump.port);
+        issues.key("remote.jump_host.key_path", jump.key_path.as_deref());
+    }
+
+    if profile.scope == SyncScope::CustomRoots && profile.custom_roots.is_empty() {
+        issues.error(
+            IssueCode::Required,
+            "custom_roots",
+            messages::text("issue.custom_roots_scope_needs_root", &[]),
+        );
+    }
+
+    for (i, pattern) in profile.excludes.iter().enumerate() {
+        if let Err(e) = Excludes::new(std::slice::from_ref(pattern)) {
+            issues.error(IssueCode::InvalidExclude, &format!("excludes.{i}"), e);
+        }
+    }
+
+    for (i, preset) in profile.presets.iter().enumerate() {
+        issues.required(&format!("presets.{i}.name"), &preset.name);
+        if profile.presets[..i]
+            .iter()
+            .any(|other| other.name == preset.name)
+        {
+            issues.error(
+                IssueCode::DuplicateName,
+                &format!("presets.{i}.name"),
+                messages::text(
+                    "issue.duplicate_preset_name",
+                    &[("name", &format_args!("{:?}", preset.name))],
+                ),
+            );
+        }
+        for (j, pattern) in preset.excludes.iter().enumerate() {
+            if let Err(e) = Excludes::new(std::slice::from_ref(pattern)) {
+                issues.error(
+                    IssueCode::InvalidExclude,
+                    &format!("presets.{i}.excludes.{j}"),
+                    e,
+                );
+            }
+        }
+    }
+
+    for (i, partition) in profile.upload_partitions.iter().enumerate() {
+        if !sync::is_partition(partition) {
+            issues.error(
+                IssueCode::InvalidPartition,
+                &format!("upload_partitions.{i}"),
+                messages::text(
+                    "issue.invalid_partition",
+                    &[("partition", &format_args!("{partition:?}"))],
+                ),
+            );
+        }
+    }
+
+    for (i, blackout) in profile.blackouts.iter().enumerate() {
+        for (field, time) in [("start", &blackout.start), ("end", &blackout.end)] {
+            if blackout::parse_time(time).is_none() {
+                issues.error(
+                    IssueCode::InvalidTime,
+                    &format!("blackouts.{i}.{field}"),
+                    messages::text("issue.invalid_time", &[("time", &format_args!("{time:?}"))]),
+                );
+            }
+        }
+    }
+
+    for (i, window) in profile.bandwidth.windows.iter().enumerate() {
+        for (field, time) in [("start", &window.start), ("end", &window.end)] {
+            if blackout::parse_time(time).is_none() {
+                issues.error(
+                    IssueCode::InvalidTime,
+                    &format!("bandwidth.windows.{i}.{field}"),
+                    messages::text("issue.invalid_time", &[("time", &format_args!("{time:?}"))]),
+                );
+            }
+        }
+    }
+
+    let table_rules = [
+        ("tables.include", &profile.tables.include),
+        ("tables.exclude", &profile.tables.exclude),
+    ];
+    for (field, patterns) in table_rules {
+        for (i, pattern) in patterns.iter().enumerate() {
+            if let Err(e) = glob::Pattern::new(pattern) {
+                issues.error(
+                    IssueCode::InvalidTablePattern,
+                    &format!("{field}.{i}"),
+                    messages::text(
+                        "issue.invalid_table_pattern",
+                        &[("pattern", &format_args!("{pattern:?}")), ("error", &e)],
+                    ),
+                );
+            }
+        }
+    }
+
+    if profile.woocommerce.is_some() {
+        // The rules are tried on the local install's names for the tables.
+        let prefix = local
+            .database
+            .as_ref()
+            .map_or("wp_", |database| database.table_prefix.as_str());
+        let store: Vec<String> = STORE_TABLES
+            .iter()
+            .map(|table| format!("{prefix}{table}"))
+            .collect();
+        if let Ok(selected) = profile.tables.select(&store) {
+            let left_out: Vec<&str> = store
+                .iter()
+                .map(String::as_str)
+                .filter(|table| !selected.contains(table))
+                .collect();
+            if !left_out.is_empty() {
+                issues.warning(
+                    IssueCode::StoreTableExcluded,
+                    "tables",
+                    messages::text(
+                        "issue.store_tables_left_out",
+                        &[("tables", &left_out.join(", "))],
+                    ),
+                );
+            }
+        }
+    }
+
+    if profile.multisite.subsite == Some(0) {
+        issues.error(
+            IssueCode::InvalidSubsite,
+            "multisite.subsite",
+            messages::text("issue.subsite_ids_start_at_1", &[]),
+        );
+    }
+
+    for (i, pair) in profile.replacements.iter().enumerate() {
+        issues.required(&format!("replacements.{i}.local"), &pair.local);
+        issues.required(&format!("replacements.{i}.remote"), &pair.remote);
+    }
+    if let Some(offload) = &profile.media_offload {
+        for (i, pair) in offload.urls.iter().enumerate() {
+            issues.required(&format!("media_offload.urls.{i}.local"), &pair.local);
+            issues.required(&format!("media_offload.urls.{i}.remote"), &pair.remote);
+        }
+    }
+    if profile.https_migration && !remote.url.starts_with("https://") {
+        issues.warning(
+            IssueCode::InvalidUrl,
+            "https_migration",
+            messages::text("issue.server_url_isnt_https", &[]),
+        );
+    }
+
+    for (i, hook) in profile.hooks.iter().enumerate() {
+        issues.required(&format!("hooks.{i}.command"), &hook.command);
+        if hook.side == HookSide::Remote && !remote.transfer.uses_ssh() {
+            issues.error(
+                IssueCode::HookNeedsSsh,
+                &format!("hooks.{i}.side"),
+                messages::text("issue.remote_hooks_need_ssh", &[]),
+            );
+        }
+        if hook.side == HookSide::Remote && !profile.remote_commands.allows(&hook.command) {
+            issues.error(
+                IssueCode::CommandNotVetted,
+                &format!("hooks.{i}.command"),
+                messages::text("issue.hook_command_not_vetted", &[]),
+            );
+        }
+    }
+    if let Some(CdnPurge {
+        enabled: true,
+        target: CdnTarget::Url { url },
+    }) = &profile.post_sync.cdn_purge
+    {
+        let field = "post_sync.cdn_purge.url";
+        issues.required(field, url);
+        if !url.is_empty() && !Url::parse(url).is_ok_and(|url| url.scheme().starts_with("http")) {
+            issues.error(
+                IssueCode::InvalidUrl,
+                field,
+                messages::text("issue.purge_url_isnt_http", &[]),
+            );
+        }
+    }
+    for (i, step) in profile.custom_steps.iter().enumerate() {
+        issues.required(&format!("custom_steps.{i}.plugin"), &step.plugin);
+        issues.required(&format!("custom_steps.{i}.step"), &step.step);
+        if step.plugin.is_empty() || step.step.is_empty() {
+            continue;
+        }
+        match sync::step_plugin(&step.plugin) {
+            Ok(plugin) if !plugin.steps.iter().any(|info| info.id == step.step) => {
+                issues.error(
+                    IssueCode::PluginNotFound,
+                    &format!("custom_steps.{i}.step"),
+                    messages::text(
+                        "issue.plugin_has_no_step",
+                        &[
+                            ("plugin", &plugin.name),
+                            ("step", &format_args!("{:?}", step.step)),
+                        ],
+                    ),
+                );
+            }
+            Ok(_) => {}
+            Err(e) => issues.error(
+                IssueCode::PluginNotFound,
+                &format!("custom_steps.{i}.plugin"),
+                e,
+            ),
+        }
+    }
+    for (field, reference) in profile.secrets.references() {
+        if let Err(e) = credentials::secret_name(reference) {
+            issues.error(
+                IssueCode::InvalidSecretReference,
+                &format!("secrets.{field}"),
+                e,
+            );
+        }
+    }
+
+    let flushes = &profile.post_sync.object_cache_commands;
+    for (i, command) in flushes.iter().enumerate() {
+        if !profile.remote_commands.allows(command) {
+            issues.warning(
+                IssueCode::CommandNotVetted,
+                &format!("post_sync.object_cache_commands.{i}"),
+                messages::text("issue.flush_command_not_vetted", &[]),
+            );
+        }
+    }
+
+    if profile.checksums && !remote.transfer.uses_ssh() {
+        issues.warning(
+            IssueCode::ChecksumsNeedSsh,
+            "checksums",
+            messages::text("issue.checksums_need_ssh", &[]),
+        );
+    }
+
+    let permissions = &profile.permissions;
+    let modes = [
+        ("permissions.file_mode", &permissions.file_mode),
+        ("permissions.dir_mode", &permissions.dir_mode),
+    ];
+    for (field, mode) in modes {
+        let Some(mode) = mode else {
+            continue;
+        };
+        let octal = (3..=4).contains(&mode.len()) && mode.chars().all(|c| ('0'..='7').contains(&c));
+        if !octal {
+            issues.error(
+                IssueCode::InvalidMode,
+                field,
+                messages::text("issue.invalid_mode", &[("mode", &format_args!("{mode:?}"))]),
+            );
+        }
+    }
+    if let Some(owner) = &permissions.owner {
+        let valid = match owner.split_once(':') {
+            Some((user, group)) => is_account_name(user) && is_account_name(group),
+            None => is_account_name(owner),
+        };
+        if !valid {
+            issues.error(
+                IssueCode::InvalidOwner,
+                "permissions.owner",
+                messages::text(
+                    "issue.invalid_owner",
+                    &[("owner", &format_args!("{owner:?}"))],
+                ),
+            );
+        }
+    }
+    if permissions.is_set() && !remote.transfer.uses_ssh() {
+        issues.warning(
+            IssueCode::PermissionsNeedSsh,
+            "permissions",
+            messages::text("issue.permissions_need_ssh", &[]),
+        );
+    }
+
+    let sudo = &profile.sudo;
+    if let Some(user) = sudo.user.as_deref().filter(|user| !is_account_name(user)) {
+        issues.error(
+            IssueCode::InvalidSudoUser,
+            "sudo.user",
+            messages::text(
+                "issue.invalid_sudo_user",
+                &[("user", &format_args!("{user:?}"))],
+            ),
+        );
+    }
+    if !sudo.steps.is_empty() && !remote.transfer.uses_ssh() {
+        issues.warning(
+            IssueCode::SudoUnsupported,
+            "sudo.steps",
+            messages::text("issue.sudo_needs_ssh", &[]),
+        );
+    }
+    if sudo.allows(SudoStep::Files) {
+        if sudo.password {
+            issues.error(
+                IssueCode::SudoUnsupported,
+                "sudo.steps",
+                messages::text("issue.rsync_sudo_needs_no_password", &[]),
+            );
+        } else if remote.transfer != TransferMethod::Rsync {
+            issues.warning(
+                IssueCode::SudoUnsupported,
+                "sudo.steps",
+                messages::text("issue.only_rsync_runs_through_sudo", &[]),
+            );
+        }
+    }
+
+    if remote.database_access == DatabaseAccess::Tunnel {
+        if !remote.transfer.uses_ssh() {
+            issues.error(
+                IssueCode::TunnelNeedsSsh,
+                "remote.database_access",
+                messages::text("issue.tunnel_needs_ssh", &[]),
+            );
+        }
+        match &remote.database {
+            Some(database) => {
+                issues.required("remote.database.name", &database.name);
+                issues.required("remote.database.user", &database.user);
+                if let Err(e) = database.tcp_address() {
+                    issues.error(IssueCode::InvalidHost, "remote.database.host", e);
+                }
+            }
+            None => issues.error(
+                IssueCode::Required,
+                "remote.database",
+                messages::text("issue.tunnel_needs_database", &[]),
+            ),
+        }
+    }
+
+    if profile.health_checks.enabled {
+        for (i, check) in profile.health_checks.urls.iter().enumerate() {
+            let field = format!("health_checks.urls.{i}.url");
+            if !check.url.starts_with('/') {
+                issues.url(&field, &check.url);
+            }
+            if !(100..=599).contains(&check.status) {
+                issues.error(
+                    IssueCode::InvalidStatus,
+                    &format!("health_checks.urls.{i}.status"),
+                    messages::text("issue.must_be_http_status", &[]),
+                );
+            }
+        }
+    }
+
+    if profile.response_diff.enabled {
+        for (i, path) in profile.response_diff.paths.iter().enumerate() {
+            if !path.starts_with('/') {
+                issues.error(
+                    IssueCode::InvalidUrl,
+                    &format!("response_diff.paths.{i}"),
+                    messages::text("issue.must_be_site_path", &[]),
+                );
+            }
+        }
+    }
+
+    if profile.post_sync.composer_install && !profile.project.uses_composer() {
+        issues.warning(
+            IssueCode::ComposerUnsupported,
+            "post_sync.composer_install",
+            messages::text("issue.composer_install_needs_composer", &[]),
+        );
+    }
+
+    if let Some(path) = profile
+        .rsync
+        .local_path
+        .as_ref()
+        .filter(|path| !path.exists())
+    {
+        issues.error(
+            IssueCode::PathNotFound,
+            "rsync.local_path",
+            messages::text("issue.path_doesnt_exist", &[("path", &path.display())]),
+        );
+    }
+    if profile
+        .rsync
+        .remote_path
+        .as_ref()
+        .is_some_and(|path| path.trim().is_empty())
+    {
+        issues.error(
+            IssueCode::Required,
+            "rsync.remote_path",
+            messages::text("issue.required_when_set", &[]),
+        );
+    }
+
+    let screenshots = &profile.screenshots;
+    if screenshots.enabled {
+        for (i, path) in screenshots.paths.iter().enumerate() {
+            if !path.starts_with('/') {
+                issues.error(
+                    IssueCode::InvalidUrl,
+                    &format!("screenshots.paths.{i}"),
+                    messages::text("issue.must_be_site_path", &[]),
+                );
+            }
+        }
+        if let Some(browser) = screenshots.browser.as_ref().filter(|path| !path.exists()) {
+            issues.error(
+                IssueCode::PathNotFound,
+                "screenshots.browser",
+                messages::text("issue.path_doesnt_exist", &[("path", &browser.display())]),
+            );
+        }
+        for (field, size) in [
+            ("screenshots.width", screenshots.width),
+            ("screenshots.height", screenshots.height),
+        ] {
+            if !(100..=10_000).contains(&size) {
+                issues.error(
+                    IssueCode::InvalidSize,
+                    field,
+                    messages::text("issue.must_be_pixel_size", &[]),
+                );
+            }
+        }
+        if !(0.0..=100.0).contains(&screenshots.threshold) {
+            issues.error(
+                IssueCode::InvalidSize,
+                "screenshots.threshold",
+                messages::text("issue.must_be_percentage", &[]),
+            );
+        }
+    }
+
+    if matches!(
+        profile.warnings.large_deletion_percent,
+        Some(0) | Some(101..)
+    ) {
+        issues.error(
+            IssueCode::InvalidSize,
+            "warnings.large_deletion_percent",
+            messages::text("issue.must_be_nonzero_percentage", &[]),
+        );
+    }
+
+    if profile.content_sample_days == Some(0) {
+        issues.error(
+            IssueCode::InvalidSize,
+            "content_sample_days",
+            messages::text("issue.must_be_at_least_a_day", &[]),
+        );
+    }
+
+    let overrides = &profile.post_sync.options_overrides;
+    for (side, options) in [("local", &overrides.local), ("remote", &overrides.remote)] {
+        for (name, value) in options {
+            let Some(reference) = value
+                .as_str()
+                .filter(|value| value.starts_with(credentials::SECRET_REFERENCE_PREFIX))
+            else {
+                continue;
+            };
+            if let Err(e) = credentials::secret_name(reference) {
+                issues.error(
+                    IssueCode::InvalidSecretReference,
+                    &format!("post_sync.options_overrides.{side}.{name}"),
+                    e,
+                );
+            }
+        }
+    }
+
+    for (i, webhook) in profile.webhooks.iter().enumerate() {
+        let field = format!("webhooks.{i}.url");
+        match webhook
+            .url
+            .starts_with(credentials::SECRET_REFERENCE_PREFIX)
+        {
+            true => {
+                if let Err(e) = credentials::secret_name(&webhook.url) {
+                    issues.error(IssueCode::InvalidSecretReference, &field, e);
+                }
+            }
+            false => issues.url(&field, &webhook.url),
+        }
+    }
+
+    issues.0
+}
+
+/// The port the remote host is reached on for the profile's transfer method.
+fn remote_port(profile: &Profile) -> u16 {
+    match profile.remote.transfer {
+        TransferMethod::Rsync | TransferMethod::Sftp => profile.remote.port,
+        TransferMethod::Ftps => profile.remote.ftp.port,
+        TransferMethod::RestApi => Url::parse(&profile.remote.url)
+            .ok()
+            .and_then(|url| url.port_or_known_default())
+            .unwrap_or(443),
+    }
+}
+
+/// Whether `name` looks like a Unix user or group name, and can't be taken
+/// for an option.
+/// A PHP `memory_limit`: bytes, with an optional `K`, `M`, or `G`, or `-1`.
+fn is_memory_limit(limit: &str) -> bool {
+    let digits = limit.trim_end_matches(['K', 'M', 'G', 'k', 'm', 'g']);
+    limit == "-1"
+        || (!digits.is_empty()
+            && limit.len() - digits.len() <= 1
+            && digits.bytes().all(|b| b.is_ascii_digit()))
+}
+
+fn is_account_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+}
+
+#[derive(Default)]
+struct Issues(Vec<Issue>);
+
+impl Issues {
+    fn error(&mut self, code: IssueCode, field: &str, message: impl Into<String>) {
+        self.push(Severity::Error, code, field, message.into());
+    }
+
+    fn warning(&mut self, code: IssueCode, field: &str, message: impl Into<String>) {
+        self.push(Severity::Warning, code, field, message.into());
+    }
+
+    fn push(&mut self, severity: Severity, code: IssueCode, field: &str, message: String) {
+        self.0.push(Issue {
+            severity,
+            code,
+            field: field.to_string(),
+            message,
+        });
+    }
+
+    /// Report `value` if it is blank; returns whether it wasn't.
+    fn required(&mut self, field: &str, value: &str) -> bool {
+        let present = !value.trim().is_empty();
+        if !present {
+            self.error(
+                IssueCode::Required,
+                field,
+                messages::text("issue.required", &[]),
+            );
+        }
+        present
+    }
+
+    fn url(&mut self, field: &str, value: &str) {
+        if !self.required(field, value) {
+            return;
+        }
+        match Url::parse(value) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") && url.host().is_some() => {}
+            Ok(_) => self.error(
+                IssueCode::InvalidUrl,
+                field,
+                messages::text("issue.must_be_http_url", &[]),
+            ),
+            Err(e) => self.error(
+                IssueCode::InvalidUrl,
+                field,
+                messages::text("issue.invalid_url", &[("error", &e)]),
+            ),
+        }
+    }
+
+    fn port(&mut self, field: &str, port: u16) {
+        if port == 0 {
+            self.error(
+                IssueCode::InvalidPort,
+                field,
+                messages::text("issue.invalid_port", &[]),
+            );
+        }
+    }
+
+    fn key(&mut self, field: &str, path: Option<&Path>) {
+        if let Some(path) = path.filter(|path| !path.is_file()) {
+            self.error(
+                IssueCode::KeyNotFound,
+                field,
+                messages::text("issue.path_doesnt_exist", &[("path", &path.display())]),
+            );
+        }
+    }
+
+    /// Check the host name resolves to an address of `family`. Connections
+    /// use it as given, so an `~/.ssh/config` alias that only OpenSSH
+    /// understands gets a hint.
+    async fn host(&mut self, field: &str, host: &str, port: u16, family: AddressFamily) {
+        let lookup = tokio::time::timeout(LOOKUP_TIMEOUT, net::addresses(host, port, family));
+        let error = match lookup.await {
+            Ok(Ok(addrs)) if !addrs.is_empty() => return,
+            Ok(Ok(_)) if family != AddressFamily::Any => messages::text(
+                "issue.no_family_addresses_found",
+                &[("family", &family.name())],
+            ),
+            Ok(Ok(_)) => messages::text("issue.no_addresses_found", &[]),
+            Ok(Err(e)) => e.to_string(),
+            Err(_) => messages::text("issue.lookup_timed_out", &[]),
+        };
+        let alias = ssh::resolve_host(host)
+            .ok()
+            .filter(|resolved| resolved.host_name != host);
+        let message = match alias {
+            Some(resolved) => messages::text(
+                "issue.couldnt_resolve_alias",
+                &[
+                    ("host", &host),
+                    ("error", &error),
+                    ("host_name", &resolved.host_name),
+                ],
+            ),
+            None => messages::text(
+                "issue.couldnt_resolve",
+                &[("host", &host), ("error", &error)],
+            ),
+        };
+        self.error(IssueCode::UnresolvableHost, field, message);
+    }
+}
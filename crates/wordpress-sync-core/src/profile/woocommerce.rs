@@ -0,0 +1,99 @@
+//! WooCommerce's tables, and which of them a store profile leaves alone.
+//!
+//! Products and the store's settings live in WordPress's own posts, terms, and
+//! options tables and in the product lookup tables, and always sync. Orders
+//! (with HPOS, in tables of their own) and the customer lookup can be left out
+//! so each side keeps its own.
+
+use serde::{Deserialize, Serialize};
+
+/// Orders and what WooCommerce derives from them, without the table prefix.
+pub const ORDER_TABLES: &[&str] = &[
+    "wc_orders",
+    "wc_orders_meta",
+    "wc_order_addresses",
+    "wc_order_operational_data",
+    "wc_order_stats",
+    "wc_order_product_lookup",
+    "wc_order_tax_lookup",
+    "wc_order_coupon_lookup",
+    "woocommerce_order_items",
+    "woocommerce_order_itemmeta",
+    "woocommerce_downloadable_product_permissions",
+    "wc_download_log",
+];
+
+/// Customers and their shopping sessions, without the table prefix.
+pub const CUSTOMER_TABLES: &[&str] = &["wc_customer_lookup", "woocommerce_sessions"];
+
+/// Tables holding the products and the store's settings, without the table prefix.
+pub const STORE_TABLES: &[&str] = &[
+    "posts",
+    "postmeta",
+    "options",
+    "terms",
+    "termmeta",
+    "term_taxonomy",
+    "term_relationships",
+    "wc_product_meta_lookup",
+    "wc_product_attributes_lookup",
+    "woocommerce_attribute_taxonomies",
+    "woocommerce_tax_rates",
+    "woocommerce_tax_rate_locations",
+    "woocommerce_shipping_zones",
+    "woocommerce_shipping_zone_locations",
+    "woocommerce_shipping_zone_methods",
+];
+
+/// How a WooCommerce store's data is synced.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WooCommerce {
+    /// Leave orders out of the sync, so the destination keeps its own. Only
+    /// orders stored in WooCommerce's own tables (HPOS) can be; older stores
+    /// keep them among the posts.
+    #[serde(default)]
+    pub skip_orders: bool,
+    /// Leave the customer lookup and shoppers' sessions out. Customers'
+    /// accounts are users, which `preserve_users` keeps.
+    #[serde(default)]
+    pub skip_customers: bool,
+    /// Warn when the destination has orders newer than any on the source,
+    /// which the sync would replace.
+    #[serde(default)]
+    pub check_orders: bool,
+    /// Which sides have their payment gateways switched to test mode, and
+    /// their webhook secrets cleared, once a sync has imported a database
+    /// into them.
+    #[serde(default)]
+    pub payment_test_mode: PaymentTestMode,
+}
+
+/// Where a store's payment gateways are forced into test mode, so a copy of
+/// a live store can't take real payments.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentTestMode {
+    /// Nowhere: the gateways keep the source's settings.
+    #[default]
+    Off,
+    /// This machine's site, after a pull.
+    Local,
+    /// Either side, after a pull or a push, for a profile whose server is a
+    /// staging site.
+    Both,
+}
+
+impl WooCommerce {
+    /// Table exclude patterns for what the profile leaves out, matching any
+    /// prefix and subsite.
+    pub fn excluded_tables(&self) -> Vec<String> {
+        let orders = self.skip_orders.then_some(ORDER_TABLES);
+        let customers = self.skip_customers.then_some(CUSTOMER_TABLES);
+        orders
+            .into_iter()
+            .chain(customers)
+            .flatten()
+            .map(|table| format!("*{table}"))
+            .collect()
+    }
+}
@@ -0,0 +1,216 @@
+//! Reads the database settings out of a `wp-config.php`, so they can be filled
+//! into a profile instead of copied by hand.
+//!
+//! The file isn't run: only `define('DB_…', '…')` calls and the
+//! `$table_prefix` assignment with literal string values are understood, and
+//! anything inside a comment is ignored. The password is deliberately left out
+//! of the settings; [`password`] reads it only for connecting with, when there
+//! is no stored one.
+
+use std::path::{Path, PathBuf};
+
+use super::{default_db_host, default_table_prefix, DatabaseSettings};
+
+use crate::messages;
+
+/// The name WordPress gives its configuration file.
+pub const WP_CONFIG: &str = "wp-config.php";
+
+/// The `wp-config.php` for `path`, which is either the file itself or a
+/// WordPress root. Like WordPress, a root without one falls back to the file
+/// in its parent directory.
+fn locate(path: &Path) -> PathBuf {
+    if path.is_file() {
+        return path.to_path_buf();
+    }
+    let own = path.join(WP_CONFIG);
+    match path.parent() {
+        Some(parent) if !own.is_file() && parent.join(WP_CONFIG).is_file() => {
+            parent.join(WP_CONFIG)
+        }
+        _ => own,
+    }
+}
+
+/// Read the database settings from the `wp-config.php` for `path` on this machine.
+pub fn read(path: &Path) -> Result<DatabaseSettings, String> {
+    let file = locate(path);
+    let source = std::fs::read_to_string(&file).map_err(|e| {
+        messages::text(
+            "error.failed.read_path",
+            &[("path", &file.display()), ("error", &e)],
+        )
+    })?;
+    parse(&source).map_err(|e| {
+        messages::text(
+            "error.invalid_path",
+            &[("path", &file.display()), ("error", &e)],
+        )
+    })
+}
+
+/// Extract the database settings from the PHP source of a `wp-config.php`.
+/// `DB_HOST` and `$table_prefix` fall back to WordPress's defaults.
+pub fn parse(source: &str) -> Result<DatabaseSettings, String> {
+    use Token::{Punct, Str, Variable, Word};
+
+    let tokens = tokenize(source);
+    let mut name = None;
+    let mut user = None;
+    let mut host = None;
+    let mut table_prefix = None;
+    for rest in (0..tokens.len()).map(|i| &tokens[i..]) {
+        match rest {
+            [Word(define), Punct('('), Str(constant), Punct(','), Str(value), Punct(')' | ','), ..]
+                if define.eq_ignore_ascii_case("define") =>
+            {
+                let slot = match constant.as_str() {
+                    "DB_NAME" => &mut name,
+                    "DB_USER" => &mut user,
+                    "DB_HOST" => &mut host,
+                    _ => continue,
+                };
+                // PHP keeps the first definition of a constant.
+                slot.get_or_insert_with(|| value.clone());
+            }
+            [Variable(variable), Punct('='), Str(value), Punct(';'), ..]
+                if variable == "table_prefix" =>
+            {
+                // A later assignment overrides an earlier one.
+                table_prefix = Some(value.clone());
+            }
+            _ => {}
+        }
+    }
+
+    Ok(DatabaseSettings {
+        name: name.ok_or_else(|| not_plain_string("DB_NAME"))?,
+        user: user.ok_or_else(|| not_plain_string("DB_USER"))?,
+        host: host.unwrap_or_else(default_db_host),
+        table_prefix: table_prefix.unwrap_or_else(default_table_prefix),
+    })
+}
+
+fn not_plain_string(constant: &str) -> String {
+    messages::text(
+        "error.constant_not_plain_string",
+        &[("constant", &constant)],
+    )
+}
+
+/// The `DB_PASSWORD` defined in the PHP source of a `wp-config.php`, if it is
+/// a plain string.
+pub fn password(source: &str) -> Option<String> {
+    use Token::{Punct, Str, Word};
+
+    let tokens = tokenize(source);
+    (0..tokens.len()).find_map(|i| match &tokens[i..] {
+        [Word(define), Punct('('), Str(constant), Punct(','), Str(value), Punct(')' | ','), ..]
+            if define.eq_ignore_ascii_case("define") && constant == "DB_PASSWORD" =>
+        {
+            Some(value.clone())
+        }
+        _ => None,
+    })
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    /// A name such as `define`, or any other bare word.
+    Word(String),
+    /// `$name`, without the `$`.
+    Variable(String),
+    /// A string literal's value, with escapes resolved.
+    Str(String),
+    Punct(char),
+}
+
+/// Split PHP source into the tokens [`parse`] looks at, dropping comments and
+/// whitespace. Outside `<?php … ?>` everything is ignored.
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    let mut in_php = false;
+    while let Some(c) = chars.next() {
+        if !in_php {
+            if c == '<' && chars.next_if_eq(&'?').is_some() {
+                in_php = true;
+            }
+            continue;
+        }
+        match c {
+            '?' if chars.next_if_eq(&'>').is_some() => in_php = false,
+            '#' => skip_line(&mut chars),
+            '/' if chars.next_if_eq(&'/').is_some() => skip_line(&mut chars),
+            '/' if chars.next_if_eq(&'*').is_some() => {
+                let mut last = '\0';
+                for c in chars.by_ref() {
+                    if last == '*' && c == '/' {
+                        break;
+                    }
+                    last = c;
+                }
+            }
+            '\'' | '"' => tokens.push(Token::Str(string(&mut chars, c))),
+            '$' => tokens.push(Token::Variable(word(&mut chars, String::new()))),
+            c if c.is_alphanumeric() || c == '_' => {
+                tokens.push(Token::Word(word(&mut chars, c.to_string())));
+            }
+            c if c.is_whitespace() => {}
+            c => tokens.push(Token::Punct(c)),
+        }
+    }
+    tokens
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+
+/// Skip a `//` or `#` comment, which ends at the line or at `?>`.
+fn skip_line(chars: &mut Chars) {
+    while let Some(&c) = chars.peek() {
+        if c == '\n' || (c == '?' && chars.clone().nth(1) == Some('>')) {
+            return;
+        }
+        chars.next();
+    }
+}
+
+fn word(chars: &mut Chars, mut word: String) -> String {
+    while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+        word.push(c);
+    }
+    word
+}
+
+/// The rest of a string literal opened by `quote`. Single-quoted strings only
+/// escape `\'` and `\\`; double-quoted ones also get the common escapes, but
+/// variables in them are left as written.
+fn string(chars: &mut Chars, quote: char) -> String {
+    let mut value = String::new();
+    while let Some(c) = chars.next() {
+        if c == quote {
+            break;
+        }
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        let Some(escaped) = chars.next() else {
+            value.push('\\');
+            break;
+        };
+        match (quote, escaped) {
+            (_, '\\') => value.push('\\'),
+            (_, e) if e == quote => value.push(e),
+            ('"', 'n') => value.push('\n'),
+            ('"', 't') => value.push('\t'),
+            ('"', 'r') => value.push('\r'),
+            ('"', '$') => value.push('$'),
+            (_, e) => {
+                value.push('\\');
+                value.push(e);
+            }
+        }
+    }
+    value
+}
@@ -0,0 +1,73 @@
+//! Kinsta, whose API lists a company's sites and then each site's
+//! environments, with the address and port their SSH server listens on.
+
+use serde_json::Value;
+
+use super::{fetch, text, token_pair, Provider, ProviderSite};
+use crate::error::SyncError;
+
+const API: &str = "https://api.kinsta.com/v2";
+
+pub(super) async fn sites(
+    client: &reqwest::Client,
+    token: &str,
+) -> Result<Vec<ProviderSite>, SyncError> {
+    let (company, key) = token_pair(Provider::Kinsta, token)?;
+    let listing = fetch(
+        Provider::Kinsta,
+        client
+            .get(format!("{API}/sites"))
+            .query(&[("company", &company)])
+            .bearer_auth(&key),
+    )
+    .await?;
+    let mut sites = Vec::new();
+    for site in listing["company"]["sites"].as_array().into_iter().flatten() {
+        let id = text(&site["id"]);
+        let environments = fetch(
+            Provider::Kinsta,
+            client
+                .get(format!("{API}/sites/{id}/environments"))
+                .bearer_auth(&key),
+        )
+        .await?;
+        let environments = environments["site"]["environments"].as_array();
+        sites.extend(
+            environments
+                .into_iter()
+                .flatten()
+                .map(|environment| self::site(site, environment)),
+        );
+    }
+    Ok(sites)
+}
+
+fn site(site: &Value, environment: &Value) -> ProviderSite {
+    let ssh = &environment["ssh_connection"];
+    let display_name = text(&site["display_name"]);
+    // The SFTP and SSH user is named after the site.
+    let user = text(&site["name"]);
+    let web_root = text(&environment["web_root"]);
+    ProviderSite {
+        provider: Provider::Kinsta,
+        id: text(&environment["id"]),
+        name: if display_name.is_empty() {
+            user.clone()
+        } else {
+            display_name
+        },
+        environment: Some(text(&environment["display_name"])).filter(|env| !env.is_empty()),
+        host: text(&ssh["ssh_ip"]["external_ip"]),
+        port: ssh["ssh_port"]
+            .as_u64()
+            .and_then(|port| u16::try_from(port).ok())
+            .unwrap_or(22),
+        user,
+        path: if web_root.starts_with('/') {
+            web_root
+        } else {
+            String::new()
+        },
+        url: format!("https://{}", text(&environment["primaryDomain"]["name"])),
+    }
+}
@@ -0,0 +1,188 @@
+//! Managed WordPress hosts whose APIs list the sites of an account, so a
+//! profile can be made by picking one instead of typing in its SSH details.
+//!
+//! Each provider's API credentials live in the credential store under
+//! [`PROVIDER_TOKEN_SERVICE`], keyed by [`Provider::id`], in the form
+//! [`Provider::token_format`] gives. Sites come back as [`ProviderSite`]s,
+//! one per environment, which [`ProviderSite::profile`] turns into a new
+//! profile to review and save.
+
+mod kinsta;
+mod spinupwp;
+mod wp_engine;
+
+use reqwest::{RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::credentials::{platform_store, CredentialStore, PROVIDER_TOKEN_SERVICE};
+use crate::error::SyncError;
+use crate::messages::Message;
+use crate::net::AddressFamily;
+use crate::profile::{LocalEnvironment, Profile, RemoteEnvironment};
+use crate::proxy::{self, ProxyChoice};
+use crate::sync::http;
+
+/// A managed host with a supported API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Provider {
+    WpEngine,
+    Kinsta,
+    #[serde(rename = "spinupwp")]
+    SpinupWp,
+}
+
+impl Provider {
+    pub const ALL: [Provider; 3] = [Provider::WpEngine, Provider::Kinsta, Provider::SpinupWp];
+
+    /// The account its credentials are stored under.
+    pub fn id(self) -> &'static str {
+        match self {
+            Provider::WpEngine => "wp_engine",
+            Provider::Kinsta => "kinsta",
+            Provider::SpinupWp => "spinupwp",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Provider::WpEngine => "WP Engine",
+            Provider::Kinsta => "Kinsta",
+            Provider::SpinupWp => "SpinupWP",
+        }
+    }
+
+    /// How its stored credentials are written.
+    pub fn token_format(self) -> &'static str {
+        match self {
+            Provider::WpEngine => "API user id and password, as `user_id:password`",
+            Provider::Kinsta => "company id and API key, as `company_id:api_key`",
+            Provider::SpinupWp => "API token",
+        }
+    }
+}
+
+/// One environment of a site on a managed host, with what is needed to reach
+/// it over SSH.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderSite {
+    pub provider: Provider,
+    /// The provider's id for the environment.
+    pub id: String,
+    /// The site's name.
+    pub name: String,
+    /// Such as `production` or `staging`, for hosts with environments.
+    pub environment: Option<String>,
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    /// The WordPress root on the server, or empty if the API doesn't say.
+    pub path: String,
+    pub url: String,
+}
+
+impl ProviderSite {
+    /// A new, unsaved profile syncing this site with `local`.
+    pub fn profile(&self, local: LocalEnvironment) -> Profile {
+        let name = match &self.environment {
+            Some(environment) => format!("{} ({environment})", self.name),
+            None => self.name.clone(),
+        };
+        let remote = RemoteEnvironment::over_ssh(
+            self.host.clone(),
+            self.port,
+            self.user.clone(),
+            self.path.clone(),
+            self.url.clone(),
+        );
+        Profile::new(name, local, remote)
+    }
+}
+
+/// Every site environment of the account whose credentials are stored for
+/// `provider`, sorted by name.
+pub async fn list_sites(provider: Provider) -> Result<Vec<ProviderSite>, SyncError> {
+    let token = platform_store()
+        .get(PROVIDER_TOKEN_SERVICE, provider.id())
+        .map_err(SyncError::config)?
+        .ok_or_else(|| {
+            SyncError::Config(Message::new(
+                "error.no_name_credentials_are_stored_store",
+                &[
+                    ("name", &provider.name()),
+                    ("token_format", &provider.token_format()),
+                ],
+            ))
+        })?;
+    let route = proxy::route(&ProxyChoice::Global).map_err(SyncError::config)?;
+    let client = http::builder(route.as_ref(), AddressFamily::Any)
+        .map_err(SyncError::config)?
+        .timeout(std::time::Duration::from_secs(30))
+        .build()
+        .map_err(|e| {
+            SyncError::Connection(Message::new("error.failed.set_up_http", &[("error", &e)]))
+        })?;
+    let mut sites = match provider {
+        Provider::WpEngine => wp_engine::sites(&client, &token).await?,
+        Provider::Kinsta => kinsta::sites(&client, &token).await?,
+        Provider::SpinupWp => spinupwp::sites(&client, &token).await?,
+    };
+    sites.sort_by_cached_key(|site| (site.name.to_lowercase(), site.environment.clone()));
+    Ok(sites)
+}
+
+/// Split stored credentials written as `first:second`.
+fn token_pair(provider: Provider, token: &str) -> Result<(String, String), SyncError> {
+    token
+        .trim()
+        .split_once(':')
+        .map(|(first, second)| (first.to_string(), second.to_string()))
+        .ok_or_else(|| {
+            SyncError::Config(Message::new(
+                "error.stored_name_credentials_should_token_format",
+                &[
+                    ("name", &provider.name()),
+                    ("token_format", &provider.token_format()),
+                ],
+            ))
+        })
+}
+
+/// Send a request to `provider`'s API and read its JSON answer.
+async fn fetch(provider: Provider, request: RequestBuilder) -> Result<Value, SyncError> {
+    let response = request.send().await.map_err(|e| {
+        SyncError::Connection(Message::new(
+            "error.failed.reach_name_api",
+            &[("name", &provider.name()), ("error", &e)],
+        ))
+    })?;
+    let status = response.status();
+    if matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+        return Err(SyncError::Auth(Message::new(
+            "error.name_api_turned_down_stored_credentials",
+            &[("name", &provider.name()), ("status", &status)],
+        )));
+    }
+    if !status.is_success() {
+        return Err(SyncError::Connection(Message::new(
+            "error.name_api_failed_status",
+            &[("name", &provider.name()), ("status", &status)],
+        )));
+    }
+    response.json().await.map_err(|e| {
+        SyncError::Connection(Message::new(
+            "error.name_api_returned_something_other_than",
+            &[("name", &provider.name()), ("error", &e)],
+        ))
+    })
+}
+
+/// A string field, or empty if it's missing.
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Number(number) => number.to_string(),
+        _ => String::new(),
+    }
+}
@@ -0,0 +1,64 @@
+//! SpinupWP, whose API lists the sites on an account's servers. Each site
+//! has its own system user, and its files in `/sites/<domain>/files`.
+
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use super::{fetch, text, Provider, ProviderSite};
+use crate::error::SyncError;
+
+const API: &str = "https://api.spinupwp.app/v1";
+
+pub(super) async fn sites(
+    client: &reqwest::Client,
+    token: &str,
+) -> Result<Vec<ProviderSite>, SyncError> {
+    let token = token.trim();
+    let servers: HashMap<String, Value> = list(client, token, "servers")
+        .await?
+        .into_iter()
+        .map(|server| (text(&server["id"]), server))
+        .collect();
+    Ok(list(client, token, "sites")
+        .await?
+        .iter()
+        .map(|site| {
+            let server = servers.get(&text(&site["server_id"]));
+            let domain = text(&site["domain"]);
+            let scheme = if site["https"]["enabled"].as_bool().unwrap_or(true) {
+                "https"
+            } else {
+                "http"
+            };
+            ProviderSite {
+                provider: Provider::SpinupWp,
+                id: text(&site["id"]),
+                name: domain.clone(),
+                environment: None,
+                host: server
+                    .map(|server| text(&server["ip_address"]))
+                    .unwrap_or_default(),
+                port: server
+                    .and_then(|server| server["ssh_port"].as_u64())
+                    .and_then(|port| u16::try_from(port).ok())
+                    .unwrap_or(22),
+                user: text(&site["site_user"]),
+                path: format!("/sites/{domain}/files"),
+                url: format!("{scheme}://{domain}"),
+            }
+        })
+        .collect())
+}
+
+/// Every item of a listing, following its pages.
+async fn list(client: &reqwest::Client, token: &str, route: &str) -> Result<Vec<Value>, SyncError> {
+    let mut items = Vec::new();
+    let mut next = Some(format!("{API}/{route}"));
+    while let Some(url) = next {
+        let page = fetch(Provider::SpinupWp, client.get(&url).bearer_auth(token)).await?;
+        items.extend(page["data"].as_array().into_iter().flatten().cloned());
+        next = page["pagination"]["next"].as_str().map(str::to_string);
+    }
+    Ok(items)
+}
@@ -0,0 +1,49 @@
+//! WP Engine, whose API lists installs: one per environment, each reached
+//! through the SSH gateway as `<install>@<install>.ssh.wpengine.net`.
+
+use serde_json::Value;
+
+use super::{fetch, text, token_pair, Provider, ProviderSite};
+use crate::error::SyncError;
+
+const API: &str = "https://api.wpengineapi.com/v1";
+
+pub(super) async fn sites(
+    client: &reqwest::Client,
+    token: &str,
+) -> Result<Vec<ProviderSite>, SyncError> {
+    let (user, password) = token_pair(Provider::WpEngine, token)?;
+    let mut sites = Vec::new();
+    let mut next = Some(format!("{API}/installs?limit=100"));
+    while let Some(url) = next {
+        let page = fetch(
+            Provider::WpEngine,
+            client.get(&url).basic_auth(&user, Some(&password)),
+        )
+        .await?;
+        let installs = page["results"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+        sites.extend(installs.iter().map(site));
+        next = page["next"].as_str().map(str::to_string);
+    }
+    Ok(sites)
+}
+
+fn site(install: &Value) -> ProviderSite {
+    let name = text(&install["name"]);
+    let domain = [&install["primary_domain"], &install["cname"]]
+        .into_iter()
+        .map(text)
+        .find(|domain| !domain.is_empty())
+        .unwrap_or_else(|| format!("{name}.wpengine.com"));
+    ProviderSite {
+        provider: Provider::WpEngine,
+        id: text(&install["id"]),
+        environment: Some(text(&install["environment"])).filter(|env| !env.is_empty()),
+        host: format!("{name}.ssh.wpengine.net"),
+        port: 22,
+        user: name.clone(),
+        path: format!("/sites/{name}"),
+        url: format!("https://{domain}"),
+        name,
+    }
+}
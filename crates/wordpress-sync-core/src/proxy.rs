@@ -0,0 +1,434 @@
+//! Reaching servers through an HTTP or SOCKS5 proxy, for networks that allow
+//! no other way out.
+//!
+//! One proxy can be set for the whole app, in `~/.wordpress-sync/proxy.json`,
+//! and each profile either uses it, connects directly, or has its own. SSH
+//! (and so SFTP and rsync, which run over it), the REST API, health checks,
+//! webhooks, managed hosts' APIs, and email all go through it. FTP doesn't:
+//! its data connections are opened separately and can't be tunnelled.
+//!
+//! A proxy's password is never saved with it, but kept in the credential
+//! store under [`PROXY_PASSWORD_SERVICE`], for [`Proxy::password_account`].
+
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::credentials::{platform_store, CredentialStore, PROXY_PASSWORD_SERVICE};
+use crate::messages;
+use crate::net::{self, AddressFamily};
+use crate::paths;
+
+/// The longest reply to an HTTP `CONNECT` that is read.
+const MAX_CONNECT_REPLY: usize = 16 * 1024;
+
+/// How a proxy is spoken to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyKind {
+    /// An HTTP proxy, asked to tunnel with `CONNECT`.
+    #[default]
+    Http,
+    /// A SOCKS5 proxy, which is handed host names to look up itself.
+    Socks5,
+}
+
+/// A proxy server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Proxy {
+    #[serde(default)]
+    pub kind: ProxyKind,
+    pub host: String,
+    pub port: u16,
+    /// Who to log in to the proxy as, if it asks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Hosts reached directly instead: a name such as `intranet.example.com`,
+    /// `*.example.com` or `.example.com` for its subdomains, or an address.
+    #[serde(default)]
+    pub bypass: Vec<String>,
+}
+
+/// Which proxy a profile's connections go through.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum ProxyChoice {
+    /// The proxy set for the whole app, if there is one.
+    #[default]
+    Global,
+    /// None, whatever the app's is.
+    Direct,
+    /// This profile's own.
+    Custom(Proxy),
+}
+
+impl Proxy {
+    /// Credential store account holding the password for this proxy's user.
+    pub fn password_account(&self) -> String {
+        format!(
+            "{}@{}:{}",
+            self.username.as_deref().unwrap_or_default(),
+            self.host,
+            self.port
+        )
+    }
+
+    /// Check the proxy can be connected to as written.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.host.trim().is_empty() {
+            return Err(messages::text("error.proxys_host_required", &[]));
+        }
+        if self.port == 0 {
+            return Err(messages::text(
+                "error.proxys_port_must_between_1_65535",
+                &[],
+            ));
+        }
+        if self
+            .username
+            .as_deref()
+            .is_some_and(|user| user.len() > 255)
+        {
+            return Err(messages::text("error.proxys_username_too_long", &[]));
+        }
+        Ok(())
+    }
+
+    /// Whether `host` is reached directly rather than through this proxy.
+    pub fn bypasses(&self, host: &str) -> bool {
+        let host = host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_ascii_lowercase();
+        self.bypass.iter().any(|rule| {
+            let rule = rule.trim().to_ascii_lowercase();
+            match rule.strip_prefix("*.").or_else(|| rule.strip_prefix('.')) {
+                Some(domain) => host == domain || host.ends_with(&format!(".{domain}")),
+                None => host == rule,
+            }
+        })
+    }
+}
+
+/// A proxy to connect through, with its password read from the credential
+/// store.
+#[derive(Debug, Clone)]
+pub struct Route {
+    pub proxy: Proxy,
+    password: Option<String>,
+}
+
+impl Route {
+    fn new(proxy: Proxy) -> Result<Self, String> {
+        proxy.validate()?;
+        let password = match &proxy.username {
+            Some(_) => Some(
+                platform_store()
+                    .get(PROXY_PASSWORD_SERVICE, &proxy.password_account())?
+                    .ok_or_else(|| {
+                        messages::text(
+                            "error.no_password_stored_proxy_account",
+                            &[("password_account", &proxy.password_account())],
+                        )
+                    })?,
+            ),
+            None => None,
+        };
+        Ok(Self { proxy, password })
+    }
+
+    /// The proxy's URL, with its credentials.
+    pub fn url(&self) -> Result<url::Url, String> {
+        let proxy = &self.proxy;
+        let scheme = match proxy.kind {
+            ProxyKind::Http => "http",
+            ProxyKind::Socks5 => "socks5h",
+        };
+        let mut url = url::Url::parse(&format!("{scheme}://{}:{}", host(&proxy.host), proxy.port))
+            .map_err(|e| {
+                messages::text(
+                    "error.invalid_proxy_host",
+                    &[("host", &proxy.host), ("error", &e)],
+                )
+            })?;
+        if let Some(user) = &proxy.username {
+            // The URL percent-encodes them, and `reqwest` sends them as given.
+            url.set_username(user)
+                .and_then(|()| url.set_password(self.password.as_deref()))
+                .map_err(|()| {
+                    messages::text("error.invalid_proxy_host_2", &[("host", &proxy.host)])
+                })?;
+        }
+        Ok(url)
+    }
+
+    /// The same proxy, for `reqwest` to send requests through.
+    pub(crate) fn reqwest(&self) -> Result<reqwest::Proxy, String> {
+        let proxy = &self.proxy;
+        let url = self.url()?;
+        let mut built = reqwest::Proxy::all(url.as_str()).map_err(|e| {
+            messages::text(
+                "error.invalid_proxy_host",
+                &[("host", &proxy.host), ("error", &e)],
+            )
+        })?;
+        if !proxy.bypass.is_empty() {
+            built = built.no_proxy(reqwest::NoProxy::from_string(&proxy.bypass.join(",")));
+        }
+        Ok(built)
+    }
+}
+
+/// The proxy set for the whole app, or `None` if there isn't one.
+pub fn settings() -> Result<Option<Proxy>, String> {
+    let path = paths::proxy_file()?;
+    let raw = match fs::read_to_string(&path) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(messages::text(
+                "error.failed.read_path",
+                &[("path", &path.display()), ("error", &e)],
+            ))
+        }
+    };
+    serde_json::from_str(&raw).map(Some).map_err(|e| {
+        messages::text(
+            "error.invalid_proxy_settings_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })
+}
+
+/// Set the proxy for the whole app, or with `None`, connect directly. The
+/// password is stored separately, under [`PROXY_PASSWORD_SERVICE`].
+pub fn set_settings(proxy: Option<&Proxy>) -> Result<(), String> {
+    let path = paths::proxy_file()?;
+    let Some(proxy) = proxy else {
+        return match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(messages::text(
+                "error.failed.remove_path",
+                &[("path", &path.display()), ("error", &e)],
+            )),
+            _ => Ok(()),
+        };
+    };
+    proxy.validate()?;
+    let json = serde_json::to_string_pretty(proxy)
+        .map_err(|e| messages::text("error.failed.serialize_proxy_settings", &[("error", &e)]))?;
+    let dir = paths::config_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| {
+        messages::text(
+            "error.failed.create_path",
+            &[("path", &dir.display()), ("error", &e)],
+        )
+    })?;
+    paths::atomic_write(&path, json)
+}
+
+/// The proxy `choice` comes to: its own, the app's, or none.
+pub fn effective(choice: &ProxyChoice) -> Result<Option<Proxy>, String> {
+    match choice {
+        ProxyChoice::Global => settings(),
+        ProxyChoice::Direct => Ok(None),
+        ProxyChoice::Custom(proxy) => Ok(Some(proxy.clone())),
+    }
+}
+
+/// The route `choice` comes to, its password read, if it goes through a proxy.
+pub fn route(choice: &ProxyChoice) -> Result<Option<Route>, String> {
+    effective(choice)?.map(Route::new).transpose()
+}
+
+/// Open a TCP connection to `host` on `port`, through `route` unless it is
+/// `None` or bypasses `host`. Only a direct connection keeps to `family`; a
+/// proxy reaches `host` however it likes.
+pub async fn connect(
+    route: Option<&Route>,
+    host: &str,
+    port: u16,
+    family: AddressFamily,
+) -> Result<TcpStream, String> {
+    let Some(route) = route.filter(|route| !route.proxy.bypasses(host)) else {
+        return net::connect(host, port, family).await;
+    };
+    let proxy = &route.proxy;
+    let mut stream = net::connect(&proxy.host, proxy.port, AddressFamily::Any)
+        .await
+        .map_err(|e| {
+            messages::text(
+                "error.failed.reach_proxy_host_port",
+                &[("host", &proxy.host), ("port", &proxy.port), ("error", &e)],
+            )
+        })?;
+    let tunnelled = match proxy.kind {
+        ProxyKind::Http => http_connect(&mut stream, route, host, port).await,
+        ProxyKind::Socks5 => socks5_connect(&mut stream, route, host, port).await,
+    };
+    tunnelled.map_err(|e| {
+        messages::text(
+            "error.proxy_host_port",
+            &[("host", &proxy.host), ("port", &proxy.port), ("error", &e)],
+        )
+    })?;
+    Ok(stream)
+}
+
+/// `host` as it is written before a port, with brackets around an IPv6 address.
+fn host(host: &str) -> String {
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(_)) => format!("[{host}]"),
+        _ => host.to_string(),
+    }
+}
+
+async fn http_connect(
+    stream: &mut TcpStream,
+    route: &Route,
+    host: &str,
+    port: u16,
+) -> Result<(), String> {
+    let target = format!("{}:{port}", self::host(host));
+    let mut request = format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n");
+    if let Some(user) = &route.proxy.username {
+        let credentials = format!("{user}:{}", route.password.as_deref().unwrap_or_default());
+        let encoded = base64::engine::general_purpose::STANDARD.encode(credentials);
+        request.push_str(&format!("Proxy-Authorization: Basic {encoded}\r\n"));
+    }
+    request.push_str("\r\n");
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .map_err(|e| messages::text("error.dropped_connection", &[("error", &e)]))?;
+
+    // A byte at a time, so nothing the server sends after the reply, such as
+    // an SSH banner, is read along with it.
+    let mut reply = Vec::new();
+    while !reply.ends_with(b"\r\n\r\n") {
+        if reply.len() >= MAX_CONNECT_REPLY {
+            return Err(messages::text("error.sent_too_long_reply", &[]));
+        }
+        match stream.read_u8().await {
+            Ok(byte) => reply.push(byte),
+            Err(e) => return Err(messages::text("error.dropped_connection", &[("error", &e)])),
+        }
+    }
+    let reply = String::from_utf8_lossy(&reply);
+    let status_line = reply.lines().next().unwrap_or_default();
+    let status: Option<u16> = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok());
+    match status {
+        Some(200..=299) => Ok(()),
+        Some(407) => Err(messages::text("error.refused_username_password", &[])),
+        _ => Err(messages::text(
+            "error.refused_connect_target_status_line",
+            &[("target", &target), ("status_line", &status_line)],
+        )),
+    }
+}
+
+async fn socks5_connect(
+    stream: &mut TcpStream,
+    route: &Route,
+    host: &str,
+    port: u16,
+) -> Result<(), String> {
+    let io_error = |e: io::Error| format!("dropped the connection: {e}");
+    // No authentication, or with a username, that or a username and password.
+    let greeting: &[u8] = match route.proxy.username {
+        Some(_) => &[5, 2, 0, 2],
+        None => &[5, 1, 0],
+    };
+    stream.write_all(greeting).await.map_err(io_error)?;
+    let mut chosen = [0u8; 2];
+    stream.read_exact(&mut chosen).await.map_err(io_error)?;
+    match chosen {
+        [5, 0] => {}
+        [5, 2] => {
+            let user = route.proxy.username.as_deref().unwrap_or_default();
+            let password = route.password.as_deref().unwrap_or_default();
+            if password.len() > 255 {
+                return Err(messages::text("error.cant_sent_password_that_long", &[]));
+            }
+            let mut login = vec![1, user.len() as u8];
+            login.extend_from_slice(user.as_bytes());
+            login.push(password.len() as u8);
+            login.extend_from_slice(password.as_bytes());
+            stream.write_all(&login).await.map_err(io_error)?;
+            let mut status = [0u8; 2];
+            stream.read_exact(&mut status).await.map_err(io_error)?;
+            if status[1] != 0 {
+                return Err(messages::text("error.refused_username_password", &[]));
+            }
+        }
+        [5, 0xff] if route.proxy.username.is_none() => {
+            return Err(messages::text("error.wants_username_password", &[]));
+        }
+        [5, _] => {
+            return Err(messages::text(
+                "error.accepts_none_ways_logging_offered",
+                &[],
+            ))
+        }
+        _ => return Err(messages::text("error.isnt_socks5_proxy", &[])),
+    }
+
+    let mut request = vec![5, 1, 0];
+    match host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+    {
+        Ok(IpAddr::V4(ip)) => {
+            request.push(1);
+            request.extend_from_slice(&ip.octets());
+        }
+        Ok(IpAddr::V6(ip)) => {
+            request.push(4);
+            request.extend_from_slice(&ip.octets());
+        }
+        Err(_) if host.len() > 255 => {
+            return Err(messages::text(
+                "error.cant_sent_name_long_host",
+                &[("host", &host)],
+            ))
+        }
+        Err(_) => {
+            request.extend_from_slice(&[3, host.len() as u8]);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await.map_err(io_error)?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await.map_err(io_error)?;
+    if reply[1] != 0 {
+        let why = match reply[1] {
+            2 => "proxy.not_allowed_to",
+            3 => "proxy.no_route_to_network_of",
+            4 => "proxy.no_route_to",
+            5 => "proxy.refused_by",
+            6 => "proxy.timed_out_reaching",
+            _ => "proxy.failed_to_reach",
+        };
+        return Err(messages::text(why, &[("host", &host), ("port", &port)]));
+    }
+    // The address the proxy connected from, then its port, which aren't needed.
+    let address = match reply[3] {
+        1 => 4,
+        4 => 16,
+        3 => stream.read_u8().await.map_err(io_error)? as usize,
+        _ => return Err(messages::text("error.sent_reply_that_isnt_socks5", &[])),
+    };
+    let mut bound = vec![0u8; address + 2];
+    stream.read_exact(&mut bound).await.map_err(io_error)?;
+    Ok(())
+}
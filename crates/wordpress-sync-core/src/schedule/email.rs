@@ -0,0 +1,450 @@
+//! Emailing the results of syncs nobody is watching, such as a nightly
+//! scheduled refresh, over SMTP.
+//!
+//! The mail server is set up once, in `~/.wordpress-sync/email.json`; its
+//! password is kept in the credential store under [`SMTP_PASSWORD_SERVICE`]
+//! for the SMTP username. A report gives the job's summary, and when it
+//! failed, the end of its log.
+
+use std::fs;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufStream};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+use crate::credentials::{platform_store, CredentialStore, SMTP_PASSWORD_SERVICE};
+use crate::history::{self, HistoryEntry, JobStatus};
+use crate::messages;
+use crate::net::AddressFamily;
+use crate::proxy::{self, ProxyChoice, Route};
+use crate::sync::JobNotification;
+use crate::{logging, paths};
+
+/// How long delivering one email may take, from connecting to `QUIT`.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How many of a failed job's last log lines a report includes.
+const LOG_TAIL: usize = 50;
+
+/// The mail server reports are sent through, and who they go to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailSettings {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub host: String,
+    /// By default 465 with [`SmtpSecurity::Tls`], 587 with
+    /// [`SmtpSecurity::StartTls`], and 25 without encryption.
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    /// Who to log in as, with the password stored for them; empty for a
+    /// server that takes mail without logging in.
+    #[serde(default)]
+    pub username: String,
+    /// The address reports are sent from.
+    pub from: String,
+    /// The addresses reports are sent to.
+    pub to: Vec<String>,
+    /// Report syncs that succeeded, not only those that failed.
+    #[serde(default)]
+    pub on_success: bool,
+    #[serde(default = "default_true")]
+    pub on_failure: bool,
+}
+
+/// How the connection to the mail server is encrypted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpSecurity {
+    /// Connect in plain text, then upgrade with `STARTTLS`.
+    #[default]
+    StartTls,
+    /// Encrypted from the start, as on port 465.
+    Tls,
+    /// Never encrypted, for a relay on this machine or network. It can't log in.
+    None,
+}
+
+impl EmailSettings {
+    fn port(&self) -> u16 {
+        self.port.unwrap_or(match self.security {
+            SmtpSecurity::StartTls => 587,
+            SmtpSecurity::Tls => 465,
+            SmtpSecurity::None => 25,
+        })
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.host.trim().is_empty() {
+            return Err(messages::text("error.mail_servers_host_required", &[]));
+        }
+        if self.to.is_empty() {
+            return Err(messages::text(
+                "error.least_one_address_send_reports_required",
+                &[],
+            ));
+        }
+        for address in std::iter::once(&self.from).chain(&self.to) {
+            let safe = !address.contains(['\r', '\n', '<', '>', ',', ' ']);
+            if !safe || !address.contains('@') {
+                return Err(messages::text(
+                    "error.address_not_email",
+                    &[("address", &format_args!("{address:?}"))],
+                ));
+            }
+        }
+        if self.security == SmtpSecurity::None && !self.username.is_empty() {
+            return Err(messages::text(
+                "error.logging_mail_server_needs_encrypted_connection",
+                &[],
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The saved email settings, or `None` if reports were never set up.
+pub fn settings() -> Result<Option<EmailSettings>, String> {
+    let path = paths::email_file()?;
+    let raw = match fs::read_to_string(&path) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(messages::text(
+                "error.failed.read_path",
+                &[("path", &path.display()), ("error", &e)],
+            ))
+        }
+    };
+    serde_json::from_str(&raw).map(Some).map_err(|e| {
+        messages::text(
+            "error.invalid_email_settings_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })
+}
+
+/// Save `settings`, or with `None`, stop emailing reports. The password is
+/// stored separately, under [`SMTP_PASSWORD_SERVICE`].
+pub fn set_settings(settings: Option<&EmailSettings>) -> Result<(), String> {
+    let path = paths::email_file()?;
+    let Some(settings) = settings else {
+        return match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(messages::text(
+                "error.failed.remove_path",
+                &[("path", &path.display()), ("error", &e)],
+            )),
+            _ => Ok(()),
+        };
+    };
+    settings.validate()?;
+    let json = serde_json::to_string_pretty(settings)
+        .map_err(|e| messages::text("error.failed.serialize_email_settings", &[("error", &e)]))?;
+    let dir = paths::config_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| {
+        messages::text(
+            "error.failed.create_path",
+            &[("path", &dir.display()), ("error", &e)],
+        )
+    })?;
+    paths::atomic_write(&path, json)
+}
+
+/// Send a test email with the saved settings, to check them.
+pub async fn send_test() -> Result<(), String> {
+    let settings =
+        settings()?.ok_or_else(|| messages::text("error.email_reports_arent_set_up", &[]))?;
+    let body = messages::text("email.test_body", &[]) + "\n";
+    send(&settings, &messages::text("email.test_subject", &[]), &body).await
+}
+
+/// Email the result of the finished job `job_id`, if email reports are set up
+/// and want to hear of how it ended. Failures are logged, not returned, as
+/// the sync itself is over.
+pub async fn report(job_id: &str) {
+    let settings = match settings() {
+        Ok(Some(settings)) if settings.enabled => settings,
+        Ok(_) => return,
+        Err(e) => {
+            tracing::warn!(error = %e, "no email report was sent");
+            return;
+        }
+    };
+    let entry = match history::get(job_id) {
+        Ok(entry) => entry,
+        Err(e) => {
+            tracing::warn!(job_id = %job_id, error = %e, "no email report was sent");
+            return;
+        }
+    };
+    let wanted = match entry.status {
+        JobStatus::Succeeded => settings.on_success,
+        JobStatus::Failed => settings.on_failure,
+        JobStatus::Cancelled => false,
+    };
+    if !wanted {
+        return;
+    }
+    let notification = JobNotification::of(&entry);
+    match send(&settings, &notification.title, &body(&entry, &notification)).await {
+        Ok(()) => tracing::info!(job_id = %job_id, "emailed the sync's result"),
+        Err(e) => tracing::warn!(job_id = %job_id, error = %e, "no email report was sent"),
+    }
+}
+
+/// What a report of `entry` says.
+fn body(entry: &HistoryEntry, notification: &JobNotification) -> String {
+    let mut body = format!(
+        "{}\n\n{}\n\nProfile: {}\nStarted: {}\nJob: {}\n",
+        notification.title,
+        notification.body,
+        entry.profile_name,
+        entry
+            .started_at
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M:%S"),
+        entry.job_id,
+    );
+    if !entry.warnings.is_empty() {
+        body.push_str("\nWarnings:\n");
+        for warning in &entry.warnings {
+            body.push_str(&format!("- {warning}\n"));
+        }
+    }
+    if entry.status == JobStatus::Failed {
+        if let Ok(log) = logging::job_log(&entry.job_id, Some(LOG_TAIL)) {
+            body.push_str(&format!("\nThe last {LOG_TAIL} lines of its log:\n\n{log}"));
+        }
+    }
+    body
+}
+
+/// Deliver one email over SMTP, through the app's proxy if it has one.
+async fn send(settings: &EmailSettings, subject: &str, body: &str) -> Result<(), String> {
+    settings.validate()?;
+    // The credential store blocks, and on Linux runs a runtime of its own.
+    let username = settings.username.clone();
+    let (password, route) = tokio::task::spawn_blocking(move || {
+        let password = match username.is_empty() {
+            true => None,
+            false => Some(
+                platform_store()
+                    .get(SMTP_PASSWORD_SERVICE, &username)?
+                    .ok_or_else(|| {
+                        messages::text(
+                            "error.no_smtp_password_stored_username",
+                            &[("username", &username)],
+                        )
+                    })?,
+            ),
+        };
+        Ok::<_, String>((password, proxy::route(&ProxyChoice::Global)?))
+    })
+    .await
+    .map_err(|e| messages::text("error.failed.read_smtp_password", &[("error", &e)]))??;
+    let message = message(settings, subject, body);
+    let delivery = deliver(settings, password.as_deref(), route.as_ref(), &message);
+    tokio::time::timeout(TIMEOUT, delivery).await.map_err(|_| {
+        messages::text(
+            "error.mail_server_took_more_than_timeout",
+            &[("timeout", &TIMEOUT.as_secs())],
+        )
+    })?
+}
+
+async fn deliver(
+    settings: &EmailSettings,
+    password: Option<&str>,
+    route: Option<&Route>,
+    message: &str,
+) -> Result<(), String> {
+    let host = settings.host.trim();
+    let tcp = proxy::connect(route, host, settings.port(), AddressFamily::Any)
+        .await
+        .map_err(|e| {
+            messages::text(
+                "error.failed.reach_mail_server_host",
+                &[("host", &host), ("error", &e)],
+            )
+        })?;
+    match settings.security {
+        SmtpSecurity::Tls => {
+            let mut smtp = Smtp::new(tls(host, tcp).await?);
+            smtp.expect(2).await?;
+            smtp.command("EHLO localhost", 2).await?;
+            smtp.transaction(settings, password, message).await
+        }
+        SmtpSecurity::StartTls => {
+            let mut smtp = Smtp::new(tcp);
+            smtp.expect(2).await?;
+            smtp.command("EHLO localhost", 2).await?;
+            smtp.command("STARTTLS", 2).await?;
+            let mut smtp = Smtp::new(tls(host, smtp.stream.into_inner()).await?);
+            smtp.command("EHLO localhost", 2).await?;
+            smtp.transaction(settings, password, message).await
+        }
+        SmtpSecurity::None => {
+            let mut smtp = Smtp::new(tcp);
+            smtp.expect(2).await?;
+            smtp.command("EHLO localhost", 2).await?;
+            smtp.transaction(settings, password, message).await
+        }
+    }
+}
+
+/// Encrypt `tcp`, checking the server is `host` against the bundled Mozilla
+/// root certificates.
+async fn tls(
+    host: &str,
+    tcp: TcpStream,
+) -> Result<tokio_rustls::client::TlsStream<TcpStream>, String> {
+    let roots = RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let config = ClientConfig::builder_with_provider(Arc::new(
+        tokio_rustls::rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .expect("ring supports the default protocol versions")
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+    let name = ServerName::try_from(host.to_string()).map_err(|e| {
+        messages::text(
+            "error.invalid_mail_server_host",
+            &[("host", &host), ("error", &e)],
+        )
+    })?;
+    TlsConnector::from(Arc::new(config))
+        .connect(name, tcp)
+        .await
+        .map_err(|e| {
+            messages::text(
+                "error.failed.set_up_tls_host",
+                &[("host", &host), ("error", &e)],
+            )
+        })
+}
+
+/// The email, headers and body, ready for `DATA`.
+fn message(settings: &EmailSettings, subject: &str, body: &str) -> String {
+    let subject = match subject.is_ascii() {
+        true => subject.to_string(),
+        false => format!("=?UTF-8?B?{}?=", BASE64.encode(subject)),
+    };
+    // Base64 keeps long lines and lines starting with `.` from upsetting the server.
+    let encoded = BASE64.encode(body.replace('\n', "\r\n"));
+    let lines: Vec<&str> = encoded
+        .as_bytes()
+        .chunks(76)
+        .map(|chunk| std::str::from_utf8(chunk).unwrap_or_default())
+        .collect();
+    format!(
+        "From: {}\r\nTo: {}\r\nSubject: {subject}\r\nDate: {}\r\nMessage-ID: <{}@wordpress-sync>\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: base64\r\n\r\n{}\r\n",
+        settings.from,
+        settings.to.join(", "),
+        Local::now().to_rfc2822(),
+        uuid::Uuid::new_v4(),
+        lines.join("\r\n"),
+    )
+}
+
+/// One conversation with a mail server.
+struct Smtp<S> {
+    stream: BufStream<S>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Smtp<S> {
+    fn new(stream: S) -> Self {
+        Self {
+            stream: BufStream::new(stream),
+        }
+    }
+
+    /// Log in if `password` is given, send `message`, and say goodbye.
+    async fn transaction(
+        &mut self,
+        settings: &EmailSettings,
+        password: Option<&str>,
+        message: &str,
+    ) -> Result<(), String> {
+        if let Some(password) = password {
+            let credentials = BASE64.encode(format!("\0{}\0{password}", settings.username));
+            self.command(&format!("AUTH PLAIN {credentials}"), 2)
+                .await
+                .map_err(|e| messages::text("error.failed.log_mail_server", &[("error", &e)]))?;
+        }
+        self.command(&format!("MAIL FROM:<{}>", settings.from), 2)
+            .await?;
+        for to in &settings.to {
+            self.command(&format!("RCPT TO:<{to}>"), 2).await?;
+        }
+        self.command("DATA", 3).await?;
+        self.command(&format!("{message}."), 2).await?;
+        // The email is sent; the server hanging up rudely doesn't unsend it.
+        let _ = self.command("QUIT", 2).await;
+        Ok(())
+    }
+
+    /// Send `line` and expect a reply of the given class, as 2 for `250`.
+    async fn command(&mut self, line: &str, class: u16) -> Result<String, String> {
+        self.stream
+            .write_all(format!("{line}\r\n").as_bytes())
+            .await
+            .map_err(|e| messages::text("error.failed.write_mail_server", &[("error", &e)]))?;
+        self.stream
+            .flush()
+            .await
+            .map_err(|e| messages::text("error.failed.write_mail_server", &[("error", &e)]))?;
+        self.expect(class).await
+    }
+
+    /// Read the server's reply, which must be of the given class.
+    async fn expect(&mut self, class: u16) -> Result<String, String> {
+        let mut text = String::new();
+        loop {
+            let mut line = String::new();
+            let read =
+                self.stream.read_line(&mut line).await.map_err(|e| {
+                    messages::text("error.failed.read_mail_server", &[("error", &e)])
+                })?;
+            if read == 0 {
+                return Err(messages::text("error.mail_server_closed_connection", &[]));
+            }
+            let line = line.trim_end();
+            let code: u16 = line
+                .get(..3)
+                .and_then(|code| code.parse().ok())
+                .ok_or_else(|| {
+                    messages::text(
+                        "error.mail_server_answered_line",
+                        &[("line", &format_args!("{line:?}"))],
+                    )
+                })?;
+            text.push_str(line.get(4..).unwrap_or_default());
+            text.push('\n');
+            // `250-` continues the reply; `250 ` ends it.
+            if line.as_bytes().get(3) == Some(&b'-') {
+                continue;
+            }
+            if code / 100 != class {
+                return Err(messages::text(
+                    "error.mail_server_answered_code_text",
+                    &[("code", &code), ("text", &text.trim())],
+                ));
+            }
+            return Ok(text);
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
@@ -0,0 +1,128 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use chrono::{DateTime, Datelike, Local, Timelike};
+
+use super::{task_name, OsScheduler};
+
+use crate::messages;
+
+/// Per-user launch agents in `~/Library/LaunchAgents`, loaded with `launchctl`.
+///
+/// launchd has no one-off trigger, so the agent's `StartCalendarInterval` names
+/// the month, day, hour, and minute of the run; the run itself replaces the
+/// agent before that date comes round again. A firing missed while the Mac was
+/// asleep happens when it wakes.
+pub struct LaunchdScheduler;
+
+fn plist_path(profile_id: &str) -> Result<PathBuf, String> {
+    dirs::home_dir()
+        .map(|home| {
+            home.join("Library/LaunchAgents")
+                .join(format!("{}.plist", task_name(profile_id)))
+        })
+        .ok_or_else(|| messages::text("error.could_not_determine_home_directory", &[]))
+}
+
+fn launchctl(args: &[&str]) -> Result<(), String> {
+    let output = Command::new("launchctl")
+        .args(args)
+        .output()
+        .map_err(|e| messages::text("error.failed.run_launchctl", &[("error", &e)]))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(messages::text(
+            "error.launchctl_args_failed_stderr",
+            &[
+                ("args", &args.join(" ")),
+                ("stderr", &String::from_utf8_lossy(&output.stderr).trim()),
+            ],
+        ))
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+impl OsScheduler for LaunchdScheduler {
+    fn install(
+        &self,
+        profile_id: &str,
+        at: DateTime<Local>,
+        command: &[String],
+    ) -> Result<(), String> {
+        let path = plist_path(profile_id)?;
+        let arguments: String = command
+            .iter()
+            .map(|arg| format!("        <string>{}</string>\n", escape(arg)))
+            .collect();
+        let plist = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+{arguments}    </array>
+    <key>StartCalendarInterval</key>
+    <dict>
+        <key>Month</key>
+        <integer>{month}</integer>
+        <key>Day</key>
+        <integer>{day}</integer>
+        <key>Hour</key>
+        <integer>{hour}</integer>
+        <key>Minute</key>
+        <integer>{minute}</integer>
+    </dict>
+</dict>
+</plist>
+"#,
+            label = escape(&task_name(profile_id)),
+            month = at.month(),
+            day = at.day(),
+            hour = at.hour(),
+            minute = at.minute(),
+        );
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| {
+                messages::text(
+                    "error.failed.create_path",
+                    &[("path", &dir.display()), ("error", &e)],
+                )
+            })?;
+        }
+        // An agent that is already loaded keeps its old trigger until it is unloaded.
+        let path_arg = path.display().to_string();
+        let _ = launchctl(&["unload", &path_arg]);
+        fs::write(&path, plist).map_err(|e| {
+            messages::text(
+                "error.failed.write_path",
+                &[("path", &path.display()), ("error", &e)],
+            )
+        })?;
+        launchctl(&["load", "-w", &path_arg])
+    }
+
+    fn uninstall(&self, profile_id: &str) -> Result<(), String> {
+        let path = plist_path(profile_id)?;
+        if !path.exists() {
+            return Ok(());
+        }
+        let _ = launchctl(&["unload", &path.display().to_string()]);
+        fs::remove_file(&path).map_err(|e| {
+            messages::text(
+                "error.failed.delete_path",
+                &[("path", &path.display()), ("error", &e)],
+            )
+        })
+    }
+}
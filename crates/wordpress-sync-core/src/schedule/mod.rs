@@ -0,0 +1,420 @@
+//! Syncs that run on a cron schedule while the app is open.
+//!
+//! Each profile can have one schedule: a cron expression in local time and the
+//! sync to run whenever it fires. Schedules are kept in
+//! `~/.wordpress-sync/schedules.json`. The scheduler looks for due ones every
+//! [`CHECK_INTERVAL`] and runs them one after another, so a sync that outlasts
+//! the next firing of another schedule delays it rather than overlapping it. A
+//! run missed while the app was closed happens once, when it next starts.
+//!
+//! A schedule can instead be handed to the operating system's scheduler —
+//! launchd on macOS, a systemd timer on Linux, the Task Scheduler on Windows —
+//! so it also fires while the app is closed. The OS task starts this binary
+//! with [`SCHEDULED_SYNC_FLAG`] at the schedule's next run; that headless sync
+//! then moves the task on to the run after.
+//!
+//! Either way, a firing in one of the profile's blackout windows is skipped,
+//! and nobody may be watching when a scheduled sync ends, so its result can
+//! be emailed; see [`email`].
+
+pub mod email;
+#[cfg(target_os = "macos")]
+mod launchd;
+#[cfg(target_os = "linux")]
+mod systemd;
+#[cfg(target_os = "windows")]
+mod task_scheduler;
+
+use std::fs;
+use std::io;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Local, Utc};
+use croner::parser::{CronParser, Seconds, Year};
+use croner::Cron;
+use serde::{Deserialize, Serialize};
+
+use crate::messages;
+use crate::profile;
+use crate::sync::{
+    self, Components, Confirmation, Direction, JobRegistry, ProgressSink, SyncProgress,
+};
+use crate::telemetry::{self, Feature};
+use crate::{logging, paths};
+
+#[cfg(target_os = "macos")]
+use launchd::LaunchdScheduler as PlatformScheduler;
+#[cfg(target_os = "linux")]
+use systemd::SystemdScheduler as PlatformScheduler;
+#[cfg(target_os = "windows")]
+use task_scheduler::TaskSchedulerService as PlatformScheduler;
+
+/// First argument that makes the binary run one profile's scheduled sync
+/// without a window, followed by the profile id.
+pub const SCHEDULED_SYNC_FLAG: &str = "--scheduled-sync";
+
+/// How often the scheduler looks for due schedules.
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Held while the schedules file is read, changed, and written back, so the
+/// scheduler and the commands don't overwrite each other's changes.
+static FILE_LOCK: Mutex<()> = Mutex::new(());
+
+/// An operating system service that starts a command at a given time.
+trait OsScheduler {
+    /// Run `command` once at `at`, replacing the profile's earlier task.
+    fn install(
+        &self,
+        profile_id: &str,
+        at: DateTime<Local>,
+        command: &[String],
+    ) -> Result<(), String>;
+
+    /// Remove the profile's task. Succeeds if it has none.
+    fn uninstall(&self, profile_id: &str) -> Result<(), String>;
+}
+
+/// The name of a profile's OS task, unique to the profile.
+fn task_name(profile_id: &str) -> String {
+    format!("com.wordpress-sync.schedule.{profile_id}")
+}
+
+/// When a profile syncs by itself, and what that sync does.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    pub profile_id: String,
+    /// A five-field cron expression in local time, e.g. `0 3 * * *` for 03:00
+    /// every night. Names (`MON`, `JAN`) and aliases such as `@daily` work too.
+    pub cron: String,
+    pub direction: Direction,
+    #[serde(default)]
+    pub components: Components,
+    pub enabled: bool,
+    /// When the schedule fires next; `None` while it is disabled.
+    pub next_run: Option<DateTime<Utc>>,
+    /// When the schedule last started a sync.
+    #[serde(default)]
+    pub last_run: Option<DateTime<Utc>>,
+    /// Whether the operating system's scheduler runs this schedule instead of
+    /// the app's, so that it fires while the app is closed too.
+    #[serde(default)]
+    pub os_task: bool,
+}
+
+/// Every schedule, soonest first, then the disabled ones.
+pub fn list() -> Result<Vec<Schedule>, String> {
+    let mut schedules = read()?;
+    schedules.sort_by_key(|schedule| (schedule.next_run.is_none(), schedule.next_run));
+    Ok(schedules)
+}
+
+/// Create or replace the schedule for a saved profile and return it.
+pub fn set(
+    profile_id: &str,
+    cron: &str,
+    direction: Direction,
+    components: Components,
+    enabled: bool,
+) -> Result<Schedule, String> {
+    let parsed = parse(cron)?;
+    profile::load(profile_id)?;
+    let _lock = FILE_LOCK.lock().unwrap();
+    let mut schedules = read()?;
+    let existing = schedules
+        .iter()
+        .find(|schedule| schedule.profile_id == profile_id);
+    let last_run = existing.and_then(|schedule| schedule.last_run);
+    let os_task = existing.is_some_and(|schedule| schedule.os_task);
+    let schedule = Schedule {
+        profile_id: profile_id.to_string(),
+        cron: cron.trim().to_string(),
+        direction,
+        components,
+        enabled,
+        next_run: enabled.then(|| next_after(&parsed, Utc::now())).flatten(),
+        last_run,
+        os_task,
+    };
+    schedules.retain(|other| other.profile_id != profile_id);
+    schedules.push(schedule.clone());
+    write(&schedules)?;
+    update_os_task(&schedule)?;
+    Ok(schedule)
+}
+
+/// Remove a profile's schedule. Succeeds if it has none.
+pub fn remove(profile_id: &str) -> Result<(), String> {
+    let _lock = FILE_LOCK.lock().unwrap();
+    let mut schedules = read()?;
+    let Some(index) = schedules
+        .iter()
+        .position(|schedule| schedule.profile_id == profile_id)
+    else {
+        return Ok(());
+    };
+    let removed = schedules.remove(index);
+    write(&schedules)?;
+    if removed.os_task {
+        PlatformScheduler.uninstall(profile_id)?;
+    }
+    Ok(())
+}
+
+/// Hand a profile's schedule to the operating system's scheduler, or (with
+/// `os_task` false) take it back, and return the schedule.
+pub fn set_os_task(profile_id: &str, os_task: bool) -> Result<Schedule, String> {
+    let _lock = FILE_LOCK.lock().unwrap();
+    let mut schedules = read()?;
+    let schedule = schedules
+        .iter_mut()
+        .find(|schedule| schedule.profile_id == profile_id)
+        .ok_or_else(|| {
+            messages::text(
+                "error.profile_id_has_no_schedule",
+                &[("profile_id", &profile_id)],
+            )
+        })?;
+    schedule.os_task = os_task;
+    let schedule = schedule.clone();
+    if os_task {
+        update_os_task(&schedule)?;
+    } else {
+        PlatformScheduler.uninstall(profile_id)?;
+    }
+    write(&schedules)?;
+    Ok(schedule)
+}
+
+/// Make a schedule's OS task, if it has one, start at its next run, or remove
+/// the task while the schedule has none.
+fn update_os_task(schedule: &Schedule) -> Result<(), String> {
+    if !schedule.os_task {
+        return Ok(());
+    }
+    let Some(next_run) = schedule.next_run else {
+        return PlatformScheduler.uninstall(&schedule.profile_id);
+    };
+    let program = std::env::current_exe()
+        .map_err(|e| messages::text("error.failed.locate_app_binary", &[("error", &e)]))?;
+    let command = [
+        program.display().to_string(),
+        SCHEDULED_SYNC_FLAG.to_string(),
+        schedule.profile_id.clone(),
+    ];
+    PlatformScheduler.install(
+        &schedule.profile_id,
+        next_run.with_timezone(&Local),
+        &command,
+    )
+}
+
+/// Run due schedules, every [`CHECK_INTERVAL`], for as long as the app runs.
+/// Their syncs are registered in `jobs`, so they can be cancelled like any
+/// other, and report to `sink`. Schedules the OS runs are left to it.
+pub async fn run_scheduled(jobs: &JobRegistry, sink: &dyn ProgressSink) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let due = match take_due(Utc::now(), |schedule| !schedule.os_task) {
+            Ok(due) => due,
+            Err(e) => {
+                tracing::warn!(error = %e, "failed to read the schedules");
+                continue;
+            }
+        };
+        for schedule in due {
+            let profile = match profile::load(&schedule.profile_id) {
+                Ok(profile) => profile,
+                Err(e) => {
+                    tracing::warn!(profile_id = %schedule.profile_id, error = %e, "skipped a scheduled sync");
+                    continue;
+                }
+            };
+            if let Err(e) = sync::check_blackouts(&profile) {
+                tracing::warn!(profile = %profile.name, error = %e, "skipped a scheduled sync");
+                continue;
+            }
+            tracing::info!(profile = %profile.name, cron = %schedule.cron, "starting a scheduled sync");
+            let mut handle = jobs.start();
+            handle.confirmation = Confirmation::Scheduled;
+            // The outcome is logged and recorded in the history by the sync itself.
+            let _ = sync::run(
+                &profile,
+                schedule.direction,
+                schedule.components,
+                &handle,
+                sink,
+            )
+            .await;
+            jobs.finish(&handle.id);
+            email::report(&handle.id).await;
+        }
+    }
+}
+
+/// Run one profile's scheduled sync with no window, as the OS task does with
+/// `args` following [`SCHEDULED_SYNC_FLAG`]: `<profile-id>`. The task is then
+/// moved on to the schedule's next run. Returns the process exit code.
+pub fn run_scheduled_sync(args: &[String]) -> i32 {
+    logging::init();
+    let Some(profile_id) = args.first() else {
+        eprintln!("usage: {SCHEDULED_SYNC_FLAG} <profile-id>");
+        return 2;
+    };
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!(
+                "{}",
+                messages::text("error.failed.start_runtime", &[("error", &e)])
+            );
+            return 1;
+        }
+    };
+    match runtime.block_on(scheduled_sync(profile_id)) {
+        Ok(()) => 0,
+        Err(e) => {
+            tracing::error!(profile_id = %profile_id, error = %e, "scheduled sync failed");
+            eprintln!("{e}");
+            1
+        }
+    }
+}
+
+async fn scheduled_sync(profile_id: &str) -> Result<(), String> {
+    telemetry::record(Feature::ScheduledSync);
+    let due = take_due(Utc::now(), |schedule| {
+        schedule.os_task && schedule.profile_id == profile_id
+    })?;
+    let result = match due.first() {
+        Some(schedule) => 'run: {
+            let profile = profile::load(profile_id)?;
+            if let Err(e) = sync::check_blackouts(&profile) {
+                tracing::warn!(profile = %profile.name, error = %e, "skipped a scheduled sync");
+                break 'run Ok(());
+            }
+            let jobs = JobRegistry::default();
+            let mut handle = jobs.start();
+            handle.confirmation = Confirmation::Scheduled;
+            let result = sync::run(
+                &profile,
+                schedule.direction,
+                schedule.components,
+                &handle,
+                &Headless,
+            )
+            .await
+            .map(drop)
+            .map_err(|e| e.to_string());
+            email::report(&handle.id).await;
+            result
+        }
+        // Started early, or late after the schedule changed: just re-arm the task.
+        None => Ok(()),
+    };
+    let rearmed = {
+        let _lock = FILE_LOCK.lock().unwrap();
+        let schedule = read()?
+            .into_iter()
+            .find(|schedule| schedule.profile_id == profile_id);
+        match schedule {
+            Some(schedule) => update_os_task(&schedule),
+            None => PlatformScheduler.uninstall(profile_id),
+        }
+    };
+    result.and(rearmed)
+}
+
+/// A sink for syncs nobody is watching. An unknown host key fails them, as
+/// there is no one to accept it.
+struct Headless;
+
+impl ProgressSink for Headless {
+    fn progress(&self, _update: &SyncProgress) {}
+}
+
+/// The enabled schedules picked by `filter` that are due at `now`, each moved
+/// on to its next firing after `now` so that missed firings collapse into one run.
+fn take_due(
+    now: DateTime<Utc>,
+    filter: impl Fn(&Schedule) -> bool,
+) -> Result<Vec<Schedule>, String> {
+    let _lock = FILE_LOCK.lock().unwrap();
+    let mut schedules = read()?;
+    let mut due = Vec::new();
+    for schedule in &mut schedules {
+        if !schedule.enabled || !filter(schedule) || schedule.next_run.is_none_or(|next| next > now)
+        {
+            continue;
+        }
+        due.push(schedule.clone());
+        schedule.last_run = Some(now);
+        schedule.next_run = parse(&schedule.cron)
+            .ok()
+            .and_then(|cron| next_after(&cron, now));
+    }
+    if !due.is_empty() {
+        write(&schedules)?;
+    }
+    Ok(due)
+}
+
+/// Parse a five-field cron expression.
+fn parse(cron: &str) -> Result<Cron, String> {
+    CronParser::builder()
+        .seconds(Seconds::Disallowed)
+        .year(Year::Disallowed)
+        .build()
+        .parse(cron)
+        .map_err(|e| {
+            messages::text(
+                "error.invalid_cron_expression",
+                &[("cron", &format_args!("{cron:?}")), ("error", &e)],
+            )
+        })
+}
+
+/// The first firing of `cron` after `time`, reckoned in local time.
+fn next_after(cron: &Cron, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    cron.find_next_occurrence(&time.with_timezone(&Local), false)
+        .ok()
+        .map(|next| next.with_timezone(&Utc))
+}
+
+fn read() -> Result<Vec<Schedule>, String> {
+    let path = paths::schedules_file()?;
+    let raw = match fs::read_to_string(&path) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(messages::text(
+                "error.failed.read_path",
+                &[("path", &path.display()), ("error", &e)],
+            ))
+        }
+    };
+    serde_json::from_str(&raw).map_err(|e| {
+        messages::text(
+            "error.invalid_schedules_file_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })
+}
+
+fn write(schedules: &[Schedule]) -> Result<(), String> {
+    let path = paths::schedules_file()?;
+    let json = serde_json::to_string_pretty(schedules)
+        .map_err(|e| messages::text("error.failed.serialize_schedules", &[("error", &e)]))?;
+    let dir = paths::config_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| {
+        messages::text(
+            "error.failed.create_path",
+            &[("path", &dir.display()), ("error", &e)],
+        )
+    })?;
+    paths::atomic_write(&path, json)
+}
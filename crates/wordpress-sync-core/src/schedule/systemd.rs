@@ -0,0 +1,119 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use chrono::{DateTime, Local};
+
+use super::{task_name, OsScheduler};
+
+use crate::messages;
+
+/// A per-user systemd timer and the oneshot service it starts, in
+/// `~/.config/systemd/user`.
+///
+/// The timer fires once, at the run's date and time. It is persistent, so a
+/// firing missed while the machine was off happens at the next login.
+pub struct SystemdScheduler;
+
+fn unit_dir() -> Result<PathBuf, String> {
+    dirs::config_dir()
+        .map(|dir| dir.join("systemd/user"))
+        .ok_or_else(|| messages::text("error.could_not_determine_config_directory", &[]))
+}
+
+fn systemctl(args: &[&str]) -> Result<(), String> {
+    let output = Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .output()
+        .map_err(|e| messages::text("error.failed.run_systemctl", &[("error", &e)]))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(messages::text(
+            "error.systemctl_user_args_failed_stderr",
+            &[
+                ("args", &args.join(" ")),
+                ("stderr", &String::from_utf8_lossy(&output.stderr).trim()),
+            ],
+        ))
+    }
+}
+
+/// Quote an `ExecStart=` argument. `%` starts a specifier in unit files.
+fn quote(arg: &str) -> String {
+    format!(
+        "\"{}\"",
+        arg.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('%', "%%")
+    )
+}
+
+impl OsScheduler for SystemdScheduler {
+    fn install(
+        &self,
+        profile_id: &str,
+        at: DateTime<Local>,
+        command: &[String],
+    ) -> Result<(), String> {
+        let dir = unit_dir()?;
+        let name = task_name(profile_id);
+        let exec: Vec<String> = command.iter().map(|arg| quote(arg)).collect();
+        let service = format!(
+            "[Unit]\nDescription=WordPress Sync scheduled sync ({profile_id})\n\n\
+             [Service]\nType=oneshot\nExecStart={}\n",
+            exec.join(" ")
+        );
+        let timer = format!(
+            "[Unit]\nDescription=WordPress Sync schedule ({profile_id})\n\n\
+             [Timer]\nOnCalendar={}\nPersistent=true\n\n\
+             [Install]\nWantedBy=timers.target\n",
+            at.format("%Y-%m-%d %H:%M:00")
+        );
+        fs::create_dir_all(&dir).map_err(|e| {
+            messages::text(
+                "error.failed.create_path",
+                &[("path", &dir.display()), ("error", &e)],
+            )
+        })?;
+        for (extension, contents) in [("service", service), ("timer", timer)] {
+            let path = dir.join(format!("{name}.{extension}"));
+            fs::write(&path, contents).map_err(|e| {
+                messages::text(
+                    "error.failed.write_path",
+                    &[("path", &path.display()), ("error", &e)],
+                )
+            })?;
+        }
+        systemctl(&["daemon-reload"])?;
+        // Restarting picks up the new calendar if the timer was already active.
+        let timer = format!("{name}.timer");
+        systemctl(&["enable", &timer])?;
+        systemctl(&["restart", &timer])
+    }
+
+    fn uninstall(&self, profile_id: &str) -> Result<(), String> {
+        let dir = unit_dir()?;
+        let name = task_name(profile_id);
+        let timer = dir.join(format!("{name}.timer"));
+        if !timer.exists() {
+            return Ok(());
+        }
+        let _ = systemctl(&["disable", "--now", &format!("{name}.timer")]);
+        for extension in ["timer", "service"] {
+            let path = dir.join(format!("{name}.{extension}"));
+            match fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(messages::text(
+                        "error.failed.delete_path",
+                        &[("path", &path.display()), ("error", &e)],
+                    ))
+                }
+            }
+        }
+        systemctl(&["daemon-reload"])
+    }
+}
@@ -0,0 +1,159 @@
+use std::fs;
+use std::process::Command;
+
+use chrono::{DateTime, Local};
+
+use super::{task_name, OsScheduler};
+
+use crate::messages;
+
+/// Tasks in a `WordPress Sync` folder of the Windows Task Scheduler, created
+/// with `schtasks` from a task definition.
+///
+/// The task's one trigger is the run's date and time. It starts as soon as it
+/// can after a missed start, e.g. once the machine is back on.
+pub struct TaskSchedulerService;
+
+/// The task's path in the Task Scheduler library.
+fn task_path(profile_id: &str) -> String {
+    format!("\\WordPress Sync\\{}", task_name(profile_id))
+}
+
+fn schtasks(args: &[&str]) -> Result<(), String> {
+    let output = Command::new("schtasks")
+        .args(args)
+        .output()
+        .map_err(|e| messages::text("error.failed.run_schtasks", &[("error", &e)]))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(messages::text(
+            "error.schtasks_args_failed_stderr",
+            &[
+                ("args", &args.join(" ")),
+                ("stderr", &String::from_utf8_lossy(&output.stderr).trim()),
+            ],
+        ))
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Quote one program argument the way the C runtime splits a command line.
+fn quote_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                quoted.push('"');
+                backslashes = 0;
+            }
+            c => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+impl OsScheduler for TaskSchedulerService {
+    fn install(
+        &self,
+        profile_id: &str,
+        at: DateTime<Local>,
+        command: &[String],
+    ) -> Result<(), String> {
+        let (program, args) = command
+            .split_first()
+            .ok_or_else(|| messages::text("error.scheduled_command_empty", &[]))?;
+        let args: Vec<String> = args.iter().map(|arg| quote_arg(arg)).collect();
+        let xml = format!(
+            r#"<?xml version="1.0" encoding="UTF-16"?>
+<Task version="1.2" xmlns="http://schemas.microsoft.com/windows/2004/02/mit/task">
+  <RegistrationInfo>
+    <Description>WordPress Sync scheduled sync ({profile_id})</Description>
+  </RegistrationInfo>
+  <Triggers>
+    <TimeTrigger>
+      <StartBoundary>{start}</StartBoundary>
+      <Enabled>true</Enabled>
+    </TimeTrigger>
+  </Triggers>
+  <Principals>
+    <Principal id="Author">
+      <LogonType>InteractiveToken</LogonType>
+      <RunLevel>LeastPrivilege</RunLevel>
+    </Principal>
+  </Principals>
+  <Settings>
+    <MultipleInstancesPolicy>IgnoreNew</MultipleInstancesPolicy>
+    <DisallowStartIfOnBatteries>false</DisallowStartIfOnBatteries>
+    <StopIfGoingOnBatteries>false</StopIfGoingOnBatteries>
+    <StartWhenAvailable>true</StartWhenAvailable>
+    <ExecutionTimeLimit>PT0S</ExecutionTimeLimit>
+    <Enabled>true</Enabled>
+  </Settings>
+  <Actions Context="Author">
+    <Exec>
+      <Command>{program}</Command>
+      <Arguments>{arguments}</Arguments>
+    </Exec>
+  </Actions>
+</Task>
+"#,
+            profile_id = escape(profile_id),
+            start = at.format("%Y-%m-%dT%H:%M:00"),
+            program = escape(program),
+            arguments = escape(&args.join(" ")),
+        );
+        // schtasks only reads definitions in UTF-16 reliably.
+        let bytes: Vec<u8> = std::iter::once('\u{feff}')
+            .chain(xml.chars())
+            .collect::<String>()
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let file = std::env::temp_dir().join(format!("{}.xml", task_name(profile_id)));
+        fs::write(&file, bytes).map_err(|e| {
+            messages::text(
+                "error.failed.write_path",
+                &[("path", &file.display()), ("error", &e)],
+            )
+        })?;
+        let result = schtasks(&[
+            "/Create",
+            "/F",
+            "/TN",
+            &task_path(profile_id),
+            "/XML",
+            &file.display().to_string(),
+        ]);
+        let _ = fs::remove_file(&file);
+        result
+    }
+
+    fn uninstall(&self, profile_id: &str) -> Result<(), String> {
+        let path = task_path(profile_id);
+        // Querying first tells a missing task apart from a failed deletion.
+        if schtasks(&["/Query", "/TN", &path]).is_err() {
+            return Ok(());
+        }
+        schtasks(&["/Delete", "/F", "/TN", &path])
+    }
+}
@@ -0,0 +1,200 @@
+//! Scrambling personal data in a mysqldump file, for [`Anonymize`] settings.
+//!
+//! Each scrambled value is derived from a hash of the original and a salt
+//! drawn for the sync, so the same address becomes the same fake one in every
+//! table it appears in, but can't be worked back out by hashing guesses.
+//! Only string values are touched; numbers and `NULL`s, like empty strings,
+//! are left as they are.
+
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use super::dump::{rewrite_lines, rewrite_values, unescape, Tables};
+use crate::profile::{Anonymize, AnonymizePreset, AnonymizeRule, Scramble};
+
+/// The address fields WooCommerce keeps for billing and shipping alike.
+const ADDRESS_FIELDS: &[(&str, Scramble)] = &[
+    ("first_name", Scramble::Name),
+    ("last_name", Scramble::Name),
+    ("company", Scramble::Text),
+    ("address_1", Scramble::Text),
+    ("address_2", Scramble::Text),
+    ("city", Scramble::Text),
+    ("postcode", Scramble::Text),
+    ("email", Scramble::Email),
+    ("phone", Scramble::Phone),
+];
+
+fn rule(table: &str, column: &str, meta_key: Option<String>, with: Scramble) -> AnonymizeRule {
+    AnonymizeRule {
+        table: table.to_string(),
+        column: column.to_string(),
+        meta_key,
+        with,
+    }
+}
+
+/// The rules `preset` stands for.
+fn preset_rules(preset: AnonymizePreset) -> Vec<AnonymizeRule> {
+    let meta = |table, key: &str, with| rule(table, "meta_value", Some(key.to_string()), with);
+    match preset {
+        AnonymizePreset::Users => vec![
+            rule("users", "user_email", None, Scramble::Email),
+            rule("users", "display_name", None, Scramble::Name),
+            rule("users", "user_url", None, Scramble::Clear),
+            meta("usermeta", "first_name", Scramble::Name),
+            meta("usermeta", "last_name", Scramble::Name),
+            meta("usermeta", "nickname", Scramble::Name),
+            meta("usermeta", "description", Scramble::Clear),
+        ],
+        AnonymizePreset::Comments => vec![
+            rule("comments", "comment_author", None, Scramble::Name),
+            rule("comments", "comment_author_email", None, Scramble::Email),
+            rule("comments", "comment_author_url", None, Scramble::Clear),
+            rule("comments", "comment_author_IP", None, Scramble::Ip),
+            rule("comments", "comment_agent", None, Scramble::Clear),
+        ],
+        AnonymizePreset::Woocommerce => {
+            let mut rules = Vec::new();
+            for &(field, with) in ADDRESS_FIELDS {
+                for kind in ["billing", "shipping"] {
+                    rules.push(meta("usermeta", &format!("{kind}_{field}"), with));
+                    rules.push(meta("postmeta", &format!("_{kind}_{field}"), with));
+                }
+                rules.push(rule("wc_order_addresses", field, None, with));
+            }
+            rules.extend([
+                meta("postmeta", "_customer_ip_address", Scramble::Ip),
+                meta("postmeta", "_customer_user_agent", Scramble::Clear),
+                rule("wc_orders", "billing_email", None, Scramble::Email),
+                rule("wc_orders", "ip_address", None, Scramble::Ip),
+                rule("wc_orders", "user_agent", None, Scramble::Clear),
+                rule("wc_customer_lookup", "first_name", None, Scramble::Name),
+                rule("wc_customer_lookup", "last_name", None, Scramble::Name),
+                rule("wc_customer_lookup", "email", None, Scramble::Email),
+                rule("wc_customer_lookup", "city", None, Scramble::Text),
+                rule("wc_customer_lookup", "postcode", None, Scramble::Text),
+            ]);
+            rules
+        }
+    }
+}
+
+/// Anonymize settings, with their presets spelled out, for a dump whose
+/// tables start with `prefix`.
+pub struct Anonymizer {
+    prefix: String,
+    rules: Vec<AnonymizeRule>,
+    salt: [u8; 16],
+}
+
+impl Anonymizer {
+    pub fn new(settings: &Anonymize, prefix: &str) -> Self {
+        let rules = settings
+            .presets
+            .iter()
+            .flat_map(|&preset| preset_rules(preset))
+            .chain(settings.rules.iter().cloned())
+            .collect();
+        Self {
+            prefix: prefix.to_string(),
+            rules,
+            salt: uuid::Uuid::new_v4().into_bytes(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The rules for `table`, matched after the prefix and any subsite's blog id.
+    fn rules_for(&self, table: &[u8]) -> Vec<&AnonymizeRule> {
+        let Some(name) = table.strip_prefix(self.prefix.as_bytes()) else {
+            return Vec::new();
+        };
+        let site_name = name
+            .iter()
+            .position(|&b| b == b'_')
+            .filter(|&end| end > 0 && name[..end].iter().all(u8::is_ascii_digit))
+            .map(|end| &name[end + 1..]);
+        self.rules
+            .iter()
+            .filter(|rule| {
+                let table = rule.table.as_bytes();
+                name == table || site_name == Some(table)
+            })
+            .collect()
+    }
+
+    /// What `value` becomes, or `None` if it stays as it is.
+    fn scramble(&self, with: Scramble, value: &[u8]) -> Option<Vec<u8>> {
+        if value.is_empty() {
+            return None;
+        }
+        let hash = Sha256::new()
+            .chain_update(self.salt)
+            .chain_update(value)
+            .finalize();
+        let token: String = hash[..4].iter().map(|b| format!("{b:02x}")).collect();
+        let scrambled = match with {
+            Scramble::Email => format!("user-{token}@example.com"),
+            Scramble::Name => format!("Person {token}"),
+            Scramble::Text => format!("anon-{token}"),
+            Scramble::Phone => format!("555-01{:02}", hash[4] % 100),
+            Scramble::Ip => format!("192.0.2.{}", hash[4] % 254 + 1),
+            Scramble::Clear => String::new(),
+        };
+        Some(scrambled.into_bytes())
+    }
+}
+
+/// Scramble the columns `anonymizer`'s rules name throughout the dump at
+/// `path`, in place. `on_progress` gets the number of bytes read so far after
+/// each line.
+pub async fn anonymize_dump(
+    path: &Path,
+    anonymizer: &Anonymizer,
+    on_progress: impl FnMut(u64),
+) -> Result<(), String> {
+    if anonymizer.is_empty() {
+        return Ok(());
+    }
+    let mut tables = Tables::default();
+    let rewrite = |line: &[u8]| {
+        let insert = tables.insert(line)?;
+        let index = |name: &str| {
+            insert
+                .columns
+                .iter()
+                .position(|column| column == name.as_bytes())
+        };
+        let targets: Vec<(usize, &AnonymizeRule)> = anonymizer
+            .rules_for(&insert.table)
+            .into_iter()
+            .filter_map(|rule| Some((index(&rule.column)?, rule)))
+            .collect();
+        if targets.is_empty() {
+            return None;
+        }
+        // A meta table's key comes before its value, so each row's is known in time.
+        let key_column = index("meta_key");
+        let mut key: Option<(usize, Vec<u8>)> = None;
+        rewrite_values(line, insert.values_start, |row, column, raw| {
+            if Some(column) == key_column {
+                key = Some((row, unescape(raw)));
+                return None;
+            }
+            let (_, rule) = targets.iter().find(|(index, rule)| {
+                *index == column
+                    && rule.meta_key.as_ref().is_none_or(|wanted| {
+                        key.as_ref().is_some_and(|(key_row, key)| {
+                            *key_row == row && key == wanted.as_bytes()
+                        })
+                    })
+            })?;
+            anonymizer.scramble(rule.with, &unescape(raw))
+        })
+    };
+    rewrite_lines(path, rewrite, on_progress).await
+}
@@ -0,0 +1,606 @@
+//! Applying a [`Replacer`] to a mysqldump file.
+//!
+//! mysqldump writes each `INSERT` on a single line (string values have their
+//! newlines escaped), so the dump is processed a line at a time, and however
+//! large the dump, only a line or two of it is in memory: mysqldump keeps
+//! them near its net buffer length, unless a single value is larger. Only quoted
+//! string values inside `INSERT` statements are touched; `CREATE TABLE`
+//! statements are read to learn each table's column names, so named columns can
+//! be skipped.
+//!
+//! A [`PrefixRemap`] also renames the dump's tables, and the option and user
+//! meta keys WordPress derives from the table prefix, so a dump from an install
+//! using `wp_` can be imported into one using `wp7f_`. [`CollationRemap`]s
+//! rename character sets and collations everywhere but the inserts, so the
+//! definitions only name ones the destination's server has.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
+
+use super::Replacer;
+
+use crate::messages;
+use crate::sync::at_rest;
+
+/// Statements and comments mysqldump starts with the name of the table they are about.
+const TABLE_STATEMENTS: &[&[u8]] = &[
+    b"-- Table structure for table ",
+    b"-- Dumping data for table ",
+    b"DROP TABLE IF EXISTS ",
+    b"CREATE TABLE ",
+    b"LOCK TABLES ",
+    b"/*!40000 ALTER TABLE ",
+    b"INSERT INTO ",
+];
+
+/// How much memory the line buffer keeps from one line to the next; an
+/// outsized row's is given back, rather than held for the rest of the dump.
+const KEPT_LINE: usize = 4 << 20;
+
+/// A change of `$table_prefix`, from the source install's to the destination's.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PrefixRemap {
+    pub from: String,
+    pub to: String,
+}
+
+impl PrefixRemap {
+    /// `line` with the table it is about renamed, and in a table definition any
+    /// table it references; `None` if nothing changes.
+    fn rename_tables(&self, line: &[u8]) -> Option<Vec<u8>> {
+        let from = [b"`", self.from.as_bytes()].concat();
+        let to = [b"`", self.to.as_bytes()].concat();
+        if let Some(head) = TABLE_STATEMENTS.iter().find(|head| line.starts_with(head)) {
+            let rest = line[head.len()..].strip_prefix(from.as_slice())?;
+            return Some([*head, &to, rest].concat());
+        }
+        // Foreign keys, in the indented body of a `CREATE TABLE`.
+        if !line.starts_with(b"  ") {
+            return None;
+        }
+        let references = (
+            format!("REFERENCES `{}", self.from),
+            format!("REFERENCES `{}", self.to),
+        );
+        if !super::contains(line, references.0.as_bytes()) {
+            return None;
+        }
+        Some(Replacer::new([references]).plain(line))
+    }
+
+    /// What kind of key column `column` of (already renamed) `table` is.
+    fn key_column(&self, table: &[u8], column: &[u8]) -> Option<Column> {
+        let name = table.strip_prefix(self.to.as_bytes())?;
+        match column {
+            b"option_name" if name == b"options" || site_table(name, b"options") => {
+                Some(Column::OptionKey)
+            }
+            b"meta_key" if name == b"usermeta" => Some(Column::MetaKey),
+            _ => None,
+        }
+    }
+
+    /// The renamed key, if `value` is one WordPress derives from the prefix.
+    fn rekey(&self, column: Column, value: &[u8]) -> Option<Vec<u8>> {
+        let rest = value.strip_prefix(self.from.as_bytes())?;
+        // Roles are the one option named after the prefix, per site on a network.
+        let derived = match column {
+            Column::OptionKey => rest == b"user_roles" || site_table(rest, b"user_roles"),
+            _ => true,
+        };
+        derived.then(|| [self.to.as_bytes(), rest].concat())
+    }
+}
+
+/// A character set or collation the source's tables use, and the one the
+/// destination's server has that stands in for it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CollationRemap {
+    pub from: String,
+    pub to: String,
+}
+
+/// `line` with every character set and collation in `collations` renamed,
+/// unless it is an insert, whose values are data; `None` if nothing changes.
+fn rename_collations(line: &[u8], collations: &[CollationRemap]) -> Option<Vec<u8>> {
+    if collations.is_empty() || line.starts_with(b"INSERT INTO ") {
+        return None;
+    }
+    let is_word = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+    let mut out = Vec::new();
+    let mut copied = 0;
+    let mut i = 0;
+    while i < line.len() {
+        if !is_word(line[i]) {
+            i += 1;
+            continue;
+        }
+        // Whole names only, so `utf8mb4` doesn't match inside `utf8mb4_bin`.
+        let end = line[i..]
+            .iter()
+            .position(|&b| !is_word(b))
+            .map_or(line.len(), |n| i + n);
+        let word = &line[i..end];
+        if let Some(remap) = collations
+            .iter()
+            .find(|remap| remap.from.as_bytes().eq_ignore_ascii_case(word))
+        {
+            out.extend_from_slice(&line[copied..i]);
+            out.extend_from_slice(remap.to.as_bytes());
+            copied = end;
+        }
+        i = end;
+    }
+    (copied > 0).then(|| {
+        out.extend_from_slice(&line[copied..]);
+        out
+    })
+}
+
+/// Whether `name`, after the table prefix, is `<blog id>_<suffix>`, as a
+/// multisite subsite's tables and roles option are.
+fn site_table(name: &[u8], suffix: &[u8]) -> bool {
+    name.strip_suffix(suffix)
+        .and_then(|blog| blog.strip_suffix(b"_"))
+        .is_some_and(|blog| !blog.is_empty() && blog.iter().all(u8::is_ascii_digit))
+}
+
+/// How the values of one column are rewritten.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Column {
+    Replace,
+    Skip,
+    /// `option_name` in an options table, when the prefix is remapped.
+    OptionKey,
+    /// `meta_key` in the user meta table, when the prefix is remapped.
+    MetaKey,
+}
+
+/// Run `replacer` over every string value in the dump at `path`, in place,
+/// leaving columns named in `skip_columns` alone, and apply `prefix` if the
+/// tables are being renamed and `collations` to the definitions. `on_progress`
+/// gets the number of bytes read so far after each line.
+pub async fn rewrite_dump(
+    path: &Path,
+    replacer: &Replacer,
+    skip_columns: &[&str],
+    prefix: Option<&PrefixRemap>,
+    collations: &[CollationRemap],
+    on_progress: impl FnMut(u64),
+) -> Result<(), String> {
+    if replacer.is_empty() && prefix.is_none() && collations.is_empty() {
+        return Ok(());
+    }
+    let mut tables = Tables::default();
+    rewrite_lines(
+        path,
+        |line| tables.line(line, replacer, skip_columns, prefix, collations),
+        on_progress,
+    )
+    .await
+}
+
+/// Call `visit` with the table, column, and value of every string in the
+/// inserts of the dump at `path`, changing nothing.
+pub(crate) async fn scan_dump(
+    path: &Path,
+    mut visit: impl FnMut(&[u8], &[u8], &[u8]),
+) -> Result<(), String> {
+    let input = at_rest::open(path).await?;
+    let mut reader = BufReader::new(input);
+    let mut tables = Tables::default();
+    let mut line = Vec::new();
+    loop {
+        next_line(&mut line);
+        let n = reader.read_until(b'\n', &mut line).await.map_err(|e| {
+            messages::text(
+                "error.failed.read_path",
+                &[("path", &path.display()), ("error", &e)],
+            )
+        })?;
+        if n == 0 {
+            return Ok(());
+        }
+        let Some(insert) = tables.insert(&line) else {
+            continue;
+        };
+        rewrite_values(&line, insert.values_start, |_, column, raw| {
+            let column = insert.columns.get(column).map_or(&[][..], Vec::as_slice);
+            visit(&insert.table, column, &unescape(raw));
+            None
+        });
+    }
+}
+
+/// Put what `rewrite` makes of each line of the dump at `path` in its place,
+/// by way of a temporary file beside it. `on_progress` gets the number of
+/// bytes read so far after each line.
+pub(super) async fn rewrite_lines(
+    path: &Path,
+    mut rewrite: impl FnMut(&[u8]) -> Option<Vec<u8>>,
+    mut on_progress: impl FnMut(u64),
+) -> Result<(), String> {
+    let read_failed = |e: std::io::Error| {
+        messages::text(
+            "error.failed.read_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    };
+    let tmp = path.with_extension("sql.tmp");
+    let write_failed = |e: std::io::Error| {
+        messages::text(
+            "error.failed.write_path",
+            &[("path", &tmp.display()), ("error", &e)],
+        )
+    };
+
+    let input = at_rest::open(path).await?;
+    let output = at_rest::create(&tmp).await?;
+    let mut reader = BufReader::new(input);
+    let mut writer = BufWriter::new(output);
+    let mut line = Vec::new();
+    let mut read = 0;
+    loop {
+        next_line(&mut line);
+        let n = reader
+            .read_until(b'\n', &mut line)
+            .await
+            .map_err(read_failed)?;
+        if n == 0 {
+            break;
+        }
+        read += n as u64;
+        let rewritten = rewrite(&line);
+        writer
+            .write_all(rewritten.as_deref().unwrap_or(&line))
+            .await
+            .map_err(write_failed)?;
+        on_progress(read);
+    }
+    writer.flush().await.map_err(write_failed)?;
+    writer.into_inner().finish().await?;
+    tokio::fs::rename(&tmp, path).await.map_err(|e| {
+        messages::text(
+            "error.failed.replace_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })
+}
+
+/// Empty `line` for the next one.
+fn next_line(line: &mut Vec<u8>) {
+    if line.capacity() > KEPT_LINE {
+        *line = Vec::new();
+    } else {
+        line.clear();
+    }
+}
+
+/// Column names of the tables seen so far in the dump.
+#[derive(Default)]
+pub(super) struct Tables {
+    columns: HashMap<Vec<u8>, Vec<Vec<u8>>>,
+    /// The table whose `CREATE TABLE` statement is being read.
+    creating: Option<Vec<u8>>,
+}
+
+impl Tables {
+    /// Process one line of the dump, returning its replacement if it changed.
+    fn line(
+        &mut self,
+        line: &[u8],
+        replacer: &Replacer,
+        skip_columns: &[&str],
+        prefix: Option<&PrefixRemap>,
+        collations: &[CollationRemap],
+    ) -> Option<Vec<u8>> {
+        let recollated = rename_collations(line, collations);
+        let line = recollated.as_deref().unwrap_or(line);
+        let renamed = prefix.and_then(|prefix| prefix.rename_tables(line));
+        let line = renamed.as_deref().unwrap_or(line);
+        self.values(line, replacer, skip_columns, prefix)
+            .or(renamed)
+            .or(recollated)
+    }
+
+    /// Rewrite the values of an insert, after noting the columns of any table
+    /// being defined.
+    fn values(
+        &mut self,
+        line: &[u8],
+        replacer: &Replacer,
+        skip_columns: &[&str],
+        prefix: Option<&PrefixRemap>,
+    ) -> Option<Vec<u8>> {
+        let insert = self.insert(line)?;
+        let kinds: Vec<Column> = insert
+            .columns
+            .iter()
+            .map(|column| {
+                if let Some(key) =
+                    prefix.and_then(|prefix| prefix.key_column(&insert.table, column))
+                {
+                    key
+                } else if skip_columns
+                    .iter()
+                    .any(|skip| skip.as_bytes() == column.as_slice())
+                {
+                    Column::Skip
+                } else {
+                    Column::Replace
+                }
+            })
+            .collect();
+        rewrite_values(line, insert.values_start, |_, column, raw| {
+            let kind = kinds.get(column).copied().unwrap_or(Column::Replace);
+            match (kind, prefix) {
+                (Column::Skip, _) => None,
+                (Column::OptionKey | Column::MetaKey, Some(prefix)) => {
+                    prefix.rekey(kind, &unescape(raw))
+                }
+                _ => replacer.value(&unescape(raw)),
+            }
+        })
+    }
+
+    /// The table, columns, and start of the values of `line` if it is an
+    /// insert, after noting the columns of any table being defined.
+    pub(super) fn insert(&mut self, line: &[u8]) -> Option<Insert> {
+        if let Some(rest) = line.strip_prefix(b"CREATE TABLE ") {
+            let name = identifier(rest)?.0;
+            self.columns.insert(name.clone(), Vec::new());
+            self.creating = Some(name);
+            return None;
+        }
+        if let Some(table) = &self.creating {
+            // Column definitions are indented and start with the quoted name;
+            // keys and constraints don't start with a backtick.
+            if let Some(rest) = line.strip_prefix(b"  ") {
+                if let Some((column, _)) = identifier(rest) {
+                    self.columns.get_mut(table)?.push(column);
+                }
+                return None;
+            }
+            self.creating = None;
+        }
+        let rest = line.strip_prefix(b"INSERT INTO ")?;
+        let (table, after) = identifier(rest)?;
+        let values_start = line.len() - after.len();
+        let (columns, values_start) = match explicit_columns(after) {
+            Some((columns, consumed)) => (columns, values_start + consumed),
+            None => (
+                self.columns.get(&table).cloned().unwrap_or_default(),
+                values_start,
+            ),
+        };
+        Some(Insert {
+            table,
+            columns,
+            values_start,
+        })
+    }
+}
+
+/// An insert in the dump.
+pub(super) struct Insert {
+    pub table: Vec<u8>,
+    pub columns: Vec<Vec<u8>>,
+    /// Where its `VALUES (...),(...)` part starts in the line.
+    pub values_start: usize,
+}
+
+/// Rewrite the string literals in the `VALUES (...),(...)` part of an insert,
+/// which starts at `line[start]`. `rewrite` gets each literal's row and column
+/// (counting from 0) and its body as it is in the dump, still escaped, and
+/// returns its new value if it changes.
+pub(super) fn rewrite_values(
+    line: &[u8],
+    start: usize,
+    mut rewrite: impl FnMut(usize, usize, &[u8]) -> Option<Vec<u8>>,
+) -> Option<Vec<u8>> {
+    let mut out = line[..start].to_vec();
+    let mut changed = false;
+    let mut depth = 0;
+    let mut row = 0;
+    let mut column = 0;
+    let mut i = start;
+    while i < line.len() {
+        match line[i] {
+            b'\'' => {
+                let end = literal_end(line, i)?;
+                let raw = &line[i..end];
+                // `_binary '...'` values are raw bytes, not text.
+                let binary = out.ends_with(b"_binary ");
+                let replaced = if depth != 1 || binary {
+                    None
+                } else {
+                    rewrite(row, column, &raw[1..raw.len() - 1])
+                };
+                match replaced {
+                    Some(value) => {
+                        out.push(b'\'');
+                        escape_into(&mut out, &value);
+                        out.push(b'\'');
+                        changed = true;
+                    }
+                    None => out.extend_from_slice(raw),
+                }
+                i = end;
+                continue;
+            }
+            b'(' => {
+                depth += 1;
+                if depth == 1 {
+                    column = 0;
+                }
+            }
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    row += 1;
+                }
+            }
+            b',' if depth == 1 => column += 1,
+            _ => {}
+        }
+        out.push(line[i]);
+        i += 1;
+    }
+    changed.then_some(out)
+}
+
+/// The index just past the string literal opening at `line[start]`.
+fn literal_end(line: &[u8], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    while i < line.len() {
+        match line[i] {
+            b'\\' => i += 2,
+            b'\'' if line.get(i + 1) == Some(&b'\'') => i += 2,
+            b'\'' => return Some(i + 1),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// Decode the body of a MySQL string literal.
+pub(super) fn unescape(raw: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(raw.len());
+    let mut bytes = raw.iter().copied();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'\\' => match bytes.next() {
+                Some(b'0') => out.push(0),
+                Some(b'n') => out.push(b'\n'),
+                Some(b'r') => out.push(b'\r'),
+                Some(b't') => out.push(b'\t'),
+                Some(b'b') => out.push(0x08),
+                Some(b'Z') => out.push(0x1a),
+                Some(other) => out.push(other),
+                None => out.push(b'\\'),
+            },
+            b'\'' => {
+                // A doubled quote stands for one.
+                bytes.next();
+                out.push(b'\'');
+            }
+            _ => out.push(b),
+        }
+    }
+    out
+}
+
+/// Encode a value as the body of a string literal, the way mysqldump does.
+pub(crate) fn escape_into(out: &mut Vec<u8>, value: &[u8]) {
+    for &b in value {
+        match b {
+            0 => out.extend_from_slice(b"\\0"),
+            b'\n' => out.extend_from_slice(b"\\n"),
+            b'\r' => out.extend_from_slice(b"\\r"),
+            0x1a => out.extend_from_slice(b"\\Z"),
+            b'\\' | b'\'' | b'"' => out.extend_from_slice(&[b'\\', b]),
+            _ => out.push(b),
+        }
+    }
+}
+
+/// A backtick-quoted identifier at the start of `input`, and what follows it.
+fn identifier(input: &[u8]) -> Option<(Vec<u8>, &[u8])> {
+    let rest = input.strip_prefix(b"`")?;
+    let mut name = Vec::new();
+    let mut i = 0;
+    loop {
+        match rest.get(i)? {
+            b'`' if rest.get(i + 1) == Some(&b'`') => {
+                name.push(b'`');
+                i += 2;
+            }
+            b'`' => return Some((name, &rest[i + 1..])),
+            &b => {
+                name.push(b);
+                i += 1;
+            }
+        }
+    }
+}
+
+/// The column list of `INSERT INTO t (`a`, `b`) VALUES`, if there is one, and
+/// how many bytes of `after` (what follows the table name) it spans.
+fn explicit_columns(after: &[u8]) -> Option<(Vec<Vec<u8>>, usize)> {
+    let mut rest = after.strip_prefix(b" (")?;
+    let mut columns = Vec::new();
+    loop {
+        let (column, next) = identifier(rest)?;
+        columns.push(column);
+        rest = next
+            .strip_prefix(b", ")
+            .or_else(|| next.strip_prefix(b","))
+            .unwrap_or(next);
+        if let Some(next) = rest.strip_prefix(b")") {
+            return Some((columns, after.len() - next.len()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite(replacer: &Replacer, line: &str) -> Option<String> {
+        let out = Tables::default().line(line.as_bytes(), replacer, &[], None, &[])?;
+        Some(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn escaped_quotes_in_values() {
+        let replacer = Replacer::new([("http://a.test", "https://b.example")]);
+        let line = concat!(
+            r#"INSERT INTO `wp_posts` VALUES (1,'It\'s at \"http://a.test\"','C:\\',"#,
+            r#"'http://a.test/it''s'),(2,'\'),(\'','http://a.test');"#,
+            "\n",
+        );
+        let expected = concat!(
+            r#"INSERT INTO `wp_posts` VALUES (1,'It\'s at \"https://b.example\"','C:\\',"#,
+            r#"'https://b.example/it\'s'),(2,'\'),(\'','https://b.example');"#,
+            "\n",
+        );
+        assert_eq!(rewrite(&replacer, line).as_deref(), Some(expected));
+    }
+
+    #[test]
+    fn serialized_values_keep_their_lengths_right() {
+        let replacer = Replacer::new([("http://a.test", "https://b.example")]);
+        let line = concat!(
+            r#"INSERT INTO `wp_options` VALUES (1,'widget','a:1:{s:3:\"url\";"#,
+            r#"s:16:\"http://a.test/é\";}','yes');"#,
+        );
+        let expected = concat!(
+            r#"INSERT INTO `wp_options` VALUES (1,'widget','a:1:{s:3:\"url\";"#,
+            r#"s:20:\"https://b.example/é\";}','yes');"#,
+        );
+        assert_eq!(rewrite(&replacer, line).as_deref(), Some(expected));
+    }
+
+    #[test]
+    fn damaged_lines_are_left_alone() {
+        let replacer = Replacer::new([("http://a.test", "https://b.example")]);
+        // A literal that never closes, as in a dump cut short.
+        let line = r#"INSERT INTO `wp_posts` VALUES (1,'http://a.test\"#;
+        for len in 0..line.len() {
+            assert_eq!(rewrite(&replacer, &line[..len]), None, "{len} bytes");
+        }
+        assert_eq!(rewrite(&replacer, "-- http://a.test\n"), None);
+    }
+
+    #[test]
+    fn escaping_round_trips() {
+        let value = b"it's \"quoted\"\\\n\r\0\x1a\tend";
+        let mut escaped = Vec::new();
+        escape_into(&mut escaped, value);
+        assert_eq!(unescape(&escaped), value);
+        assert_eq!(unescape(b"it''s"), b"it's");
+    }
+}
@@ -0,0 +1,127 @@
+//! The encoded forms of a replacement, for page builders that store URLs as
+//! JSON or URL-encoded strings.
+//!
+//! Elementor's `_elementor_data` and Beaver Builder's settings are JSON, whose
+//! encoder escapes `/` as `\/`; links and redirects inside them are often
+//! URL-encoded as well. Each pair brings its forms along, right after it, so
+//! they keep its place in the order of precedence.
+
+/// `pairs`, each followed by its JSON-escaped and URL-encoded forms where
+/// those differ from it.
+pub fn with_encoded_forms(pairs: Vec<(String, String)>) -> Vec<(String, String)> {
+    let mut out: Vec<(String, String)> = Vec::with_capacity(pairs.len() * 3);
+    for (from, to) in pairs {
+        let forms = [
+            (json_escaped(&from), json_escaped(&to)),
+            (url_encoded(&from), url_encoded(&to)),
+        ];
+        out.push((from, to));
+        for form in forms {
+            if !out.contains(&form) {
+                out.push(form);
+            }
+        }
+    }
+    out
+}
+
+/// `value` with its slashes escaped, as PHP's `json_encode` writes them.
+fn json_escaped(value: &str) -> String {
+    value.replace('/', "\\/")
+}
+
+/// `value` as PHP's `rawurlencode` writes it: everything but letters, digits,
+/// and `-_.~` as `%XX`.
+fn url_encoded(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(from: &str, to: &str) -> (String, String) {
+        (from.to_string(), to.to_string())
+    }
+
+    #[test]
+    fn json_escapes_slashes_only() {
+        assert_eq!(
+            json_escaped("https://example.test/blog/"),
+            r"https:\/\/example.test\/blog\/"
+        );
+        assert_eq!(json_escaped("example.test:8080"), "example.test:8080");
+    }
+
+    #[test]
+    fn url_encodes_like_rawurlencode() {
+        assert_eq!(
+            url_encoded("http://localhost:8080/wp/"),
+            "http%3A%2F%2Flocalhost%3A8080%2Fwp%2F"
+        );
+        assert_eq!(url_encoded("a-b_c.d~e f"), "a-b_c.d~e%20f");
+        assert_eq!(url_encoded("café"), "caf%C3%A9");
+    }
+
+    #[test]
+    fn each_pair_is_followed_by_its_forms() {
+        let pairs = with_encoded_forms(vec![
+            pair("https://old.test/", "https://new.test/"),
+            pair("old.test", "new.test"),
+        ]);
+        assert_eq!(
+            pairs,
+            [
+                pair("https://old.test/", "https://new.test/"),
+                pair(r"https:\/\/old.test\/", r"https:\/\/new.test\/"),
+                pair("https%3A%2F%2Fold.test%2F", "https%3A%2F%2Fnew.test%2F"),
+                pair("old.test", "new.test"),
+            ]
+        );
+    }
+
+    #[test]
+    fn forms_keep_the_path_and_port() {
+        let pairs = with_encoded_forms(vec![pair(
+            "http://localhost:8080/site",
+            "https://example.test/site",
+        )]);
+        assert_eq!(
+            pairs,
+            [
+                pair("http://localhost:8080/site", "https://example.test/site"),
+                pair(
+                    r"http:\/\/localhost:8080\/site",
+                    r"https:\/\/example.test\/site"
+                ),
+                pair(
+                    "http%3A%2F%2Flocalhost%3A8080%2Fsite",
+                    "https%3A%2F%2Fexample.test%2Fsite"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn forms_no_different_are_left_out() {
+        assert_eq!(
+            with_encoded_forms(vec![pair("old.test", "new.test")]),
+            [pair("old.test", "new.test")]
+        );
+        assert_eq!(
+            with_encoded_forms(vec![pair("old.test:8080", "new.test:8080")]),
+            [
+                pair("old.test:8080", "new.test:8080"),
+                pair("old.test%3A8080", "new.test%3A8080"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,362 @@
+//! Search and replace over database contents that keeps PHP-serialized data valid.
+//!
+//! WordPress stores options, widgets, and post meta as PHP `serialize()` output,
+//! where every string carries its byte length (`s:18:"http://mysite.test";`).
+//! A plain text replacement that changes a string's length makes the whole value
+//! unreadable to PHP, so serialized values are parsed, replaced string by string,
+//! and written back with corrected lengths. Like `wp search-replace`, array keys
+//! and object property names are left alone, and a value that doesn't parse as
+//! serialized data gets a plain replacement, unless it starts as serialized
+//! data does: one that's damaged is left as it is rather than damaged more.
+//!
+//! All pairs are applied in a single pass: at each position the first pair
+//! whose search string matches wins, and replaced text is never searched again.
+//! A more specific pair (`https://mysite.com/wp-content/uploads`) therefore has
+//! to come before a more general one (`https://mysite.com`).
+
+mod anonymize;
+mod dump;
+mod encoded;
+
+pub use anonymize::{anonymize_dump, Anonymizer};
+pub(crate) use dump::{escape_into, scan_dump};
+pub use dump::{rewrite_dump, CollationRemap, PrefixRemap};
+pub use encoded::with_encoded_forms;
+
+/// Deepest nesting of serialized arrays and objects that is rewritten; anything
+/// deeper is left as it is.
+const MAX_DEPTH: usize = 64;
+
+/// An ordered list of replacements, applied together to every value.
+#[derive(Debug, Clone, Default)]
+pub struct Replacer {
+    pairs: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Replacer {
+    /// Pairs with an empty search string, or that replace a string with itself, are dropped.
+    pub fn new<S: AsRef<str>>(pairs: impl IntoIterator<Item = (S, S)>) -> Self {
+        let pairs = pairs
+            .into_iter()
+            .map(|(from, to)| {
+                (
+                    from.as_ref().as_bytes().to_vec(),
+                    to.as_ref().as_bytes().to_vec(),
+                )
+            })
+            .filter(|(from, to)| !from.is_empty() && from != to)
+            .collect();
+        Self { pairs }
+    }
+
+    /// Whether there is nothing to replace.
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Apply the replacements to one database value, or return `None` if it
+    /// doesn't change.
+    pub fn value(&self, value: &[u8]) -> Option<Vec<u8>> {
+        if !self.pairs.iter().any(|(from, _)| contains(value, from)) {
+            return None;
+        }
+        let mut out = Vec::with_capacity(value.len());
+        let mut pos = 0;
+        if self.serialized(value, &mut pos, &mut out, 0).is_some() && pos == value.len() {
+            return Some(out);
+        }
+        if looks_serialized(value) {
+            return None;
+        }
+        Some(self.plain(value))
+    }
+
+    /// Which pairs replace something in `value`, by index, each with where it
+    /// first does. Like [`plain`](Self::plain) it ignores any structure, so
+    /// text in a serialized array's keys, or in damaged serialized data,
+    /// counts, though it is never replaced.
+    pub fn matches(&self, value: &[u8]) -> Vec<(usize, usize)> {
+        let mut found: Vec<(usize, usize)> = Vec::new();
+        let mut i = 0;
+        while i < value.len() {
+            let rest = &value[i..];
+            match self
+                .pairs
+                .iter()
+                .position(|(from, _)| rest.starts_with(from))
+            {
+                Some(pair) => {
+                    if !found.iter().any(|(seen, _)| *seen == pair) {
+                        found.push((pair, i));
+                    }
+                    i += self.pairs[pair].0.len();
+                }
+                None => i += 1,
+            }
+        }
+        found
+    }
+
+    /// Replace every occurrence of every pair, ignoring any structure.
+    fn plain(&self, value: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(value.len());
+        let mut i = 0;
+        while i < value.len() {
+            let rest = &value[i..];
+            match self.pairs.iter().find(|(from, _)| rest.starts_with(from)) {
+                Some((from, to)) => {
+                    out.extend_from_slice(to);
+                    i += from.len();
+                }
+                None => {
+                    out.push(value[i]);
+                    i += 1;
+                }
+            }
+        }
+        out
+    }
+
+    /// Rewrite one serialized value starting at `input[*pos]` into `out`,
+    /// advancing `pos` past it. `None` means the input isn't valid serialized data.
+    fn serialized(
+        &self,
+        input: &[u8],
+        pos: &mut usize,
+        out: &mut Vec<u8>,
+        depth: usize,
+    ) -> Option<()> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let start = *pos;
+        let kind = *input.get(start)?;
+        match kind {
+            b'N' => {
+                expect(input, pos, b"N;")?;
+                out.extend_from_slice(&input[start..*pos]);
+            }
+            // Scalars and references: copied through untouched.
+            b'b' | b'i' | b'd' | b'r' | b'R' => {
+                *pos += 1;
+                expect(input, pos, b":")?;
+                let end = find(input, *pos, b';')?;
+                *pos = end + 1;
+                out.extend_from_slice(&input[start..*pos]);
+            }
+            b's' => {
+                let string = read_string(input, pos)?;
+                expect(input, pos, b";")?;
+                let replaced = self.value(string);
+                let string = replaced.as_deref().unwrap_or(string);
+                write_string(out, string);
+                out.push(b';');
+            }
+            b'a' => {
+                *pos += 1;
+                expect(input, pos, b":")?;
+                let count = read_number(input, pos)?;
+                expect(input, pos, b":{")?;
+                out.extend_from_slice(&input[start..*pos]);
+                self.members(input, pos, out, count, depth)?;
+                expect(input, pos, b"}")?;
+                out.push(b'}');
+            }
+            b'O' => {
+                read_string(input, pos)?;
+                expect(input, pos, b":")?;
+                let count = read_number(input, pos)?;
+                expect(input, pos, b":{")?;
+                out.extend_from_slice(&input[start..*pos]);
+                self.members(input, pos, out, count, depth)?;
+                expect(input, pos, b"}")?;
+                out.push(b'}');
+            }
+            // Custom-serialized objects (and enums) have a format only their class
+            // knows, so they are copied through untouched.
+            b'C' => {
+                read_string(input, pos)?;
+                expect(input, pos, b":")?;
+                let len = read_number(input, pos)?;
+                expect(input, pos, b":{")?;
+                *pos = pos.checked_add(len).filter(|&end| end <= input.len())?;
+                expect(input, pos, b"}")?;
+                out.extend_from_slice(&input[start..*pos]);
+            }
+            b'E' => {
+                read_string(input, pos)?;
+                expect(input, pos, b";")?;
+                out.extend_from_slice(&input[start..*pos]);
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+
+    /// The `count` key/value pairs of an array or object. Keys are copied as they are.
+    fn members(
+        &self,
+        input: &[u8],
+        pos: &mut usize,
+        out: &mut Vec<u8>,
+        count: usize,
+        depth: usize,
+    ) -> Option<()> {
+        for _ in 0..count {
+            let key_start = *pos;
+            // A replacer with no pairs just checks the key and moves past it.
+            Replacer::default().serialized(input, pos, &mut Vec::new(), depth + 1)?;
+            out.extend_from_slice(&input[key_start..*pos]);
+            self.serialized(input, pos, out, depth + 1)?;
+        }
+        Some(())
+    }
+}
+
+/// Whether `value` starts as a serialized string, array, or object does.
+fn looks_serialized(value: &[u8]) -> bool {
+    let mut pos = 1;
+    matches!(value.first(), Some(b's' | b'a' | b'O' | b'C'))
+        && expect(value, &mut pos, b":").is_some()
+        && read_number(value, &mut pos).is_some()
+        && expect(value, &mut pos, b":").is_some()
+        && matches!(value.get(pos), Some(b'"' | b'{'))
+}
+
+/// Read `<kind>:<len>:"<bytes>"` and return the bytes, leaving `pos` after the closing quote.
+fn read_string<'a>(input: &'a [u8], pos: &mut usize) -> Option<&'a [u8]> {
+    *pos += 1;
+    expect(input, pos, b":")?;
+    let len = read_number(input, pos)?;
+    expect(input, pos, b":\"")?;
+    let start = *pos;
+    let end = start.checked_add(len).filter(|&end| end <= input.len())?;
+    *pos = end;
+    expect(input, pos, b"\"")?;
+    Some(&input[start..end])
+}
+
+fn write_string(out: &mut Vec<u8>, string: &[u8]) {
+    out.extend_from_slice(format!("s:{}:\"", string.len()).as_bytes());
+    out.extend_from_slice(string);
+    out.push(b'"');
+}
+
+/// Read an unsigned decimal number, leaving `pos` after it.
+fn read_number(input: &[u8], pos: &mut usize) -> Option<usize> {
+    let start = *pos;
+    while input.get(*pos).is_some_and(u8::is_ascii_digit) {
+        *pos += 1;
+    }
+    std::str::from_utf8(&input[start..*pos]).ok()?.parse().ok()
+}
+
+/// Consume `expected` at `pos`.
+fn expect(input: &[u8], pos: &mut usize, expected: &[u8]) -> Option<()> {
+    if input.get(*pos..)?.starts_with(expected) {
+        *pos += expected.len();
+        Some(())
+    } else {
+        None
+    }
+}
+
+fn find(input: &[u8], from: usize, byte: u8) -> Option<usize> {
+    input
+        .get(from..)?
+        .iter()
+        .position(|&b| b == byte)
+        .map(|i| from + i)
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replace(replacer: &Replacer, value: &str) -> Option<String> {
+        let out = replacer.value(value.as_bytes())?;
+        Some(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn lengths_count_bytes() {
+        let replacer = Replacer::new([("http://a.test", "https://bücher.test")]);
+        assert_eq!(
+            replace(&replacer, r#"s:13:"http://a.test";"#).as_deref(),
+            Some(r#"s:20:"https://bücher.test";"#)
+        );
+        // And the other way, from a multibyte string to a shorter one.
+        let replacer = Replacer::new([("bücher", "b")]);
+        assert_eq!(
+            replace(&replacer, r#"s:22:"https://www.bücher.de";"#).as_deref(),
+            Some(r#"s:16:"https://www.b.de";"#)
+        );
+    }
+
+    #[test]
+    fn nested_arrays_and_objects() {
+        let replacer = Replacer::new([("http://a.test", "https://b.example")]);
+        let value = concat!(
+            r#"a:2:{s:13:"http://a.test";s:13:"http://a.test";"#,
+            r#"s:4:"list";a:1:{i:0;O:8:"stdClass":2:{"#,
+            r#"s:4:"home";s:18:"see http://a.test.";s:2:"on";b:1;}}}"#,
+        );
+        let expected = concat!(
+            r#"a:2:{s:13:"http://a.test";s:17:"https://b.example";"#,
+            r#"s:4:"list";a:1:{i:0;O:8:"stdClass":2:{"#,
+            r#"s:4:"home";s:22:"see https://b.example.";s:2:"on";b:1;}}}"#,
+        );
+        assert_eq!(replace(&replacer, value).as_deref(), Some(expected));
+    }
+
+    #[test]
+    fn serialized_inside_serialized() {
+        let replacer = Replacer::new([("a.test", "b.example")]);
+        assert_eq!(
+            replace(&replacer, r#"s:23:"a:1:{i:0;s:6:"a.test";}";"#).as_deref(),
+            Some(r#"s:26:"a:1:{i:0;s:9:"b.example";}";"#)
+        );
+    }
+
+    #[test]
+    fn damaged_serialized_data_is_left_alone() {
+        let replacer = Replacer::new([("http://a.test", "https://b.example")]);
+        for value in [
+            r#"s:13:"http://a.test""#,
+            r#"s:99:"http://a.test";"#,
+            r#"s:13:"http://a.test";and more"#,
+            r#"a:2:{i:0;s:13:"http://a.test";}"#,
+            r#"O:8:"stdClass":1:{s:4:"home";s:13:"http://a.test";"#,
+        ] {
+            assert_eq!(replace(&replacer, value), None, "{value}");
+        }
+        // Cut short anywhere, with or without the search string.
+        let whole = r#"a:1:{s:3:"url";O:1:"X":1:{s:1:"u";s:13:"http://a.test";}}"#;
+        for len in 0..whole.len() {
+            assert_eq!(replace(&replacer, &whole[..len]), None, "{len} bytes");
+        }
+        let deep = [
+            "a:1:{i:0;".repeat(MAX_DEPTH + 1),
+            r#"s:13:"http://a.test";"#.to_string(),
+            "}".repeat(MAX_DEPTH + 1),
+        ]
+        .concat();
+        assert_eq!(replace(&replacer, &deep), None);
+    }
+
+    #[test]
+    fn text_gets_a_plain_replacement() {
+        let replacer = Replacer::new([("http://a.test", "https://b.example")]);
+        assert_eq!(
+            replace(&replacer, "s: see http://a.test").as_deref(),
+            Some("s: see https://b.example")
+        );
+        assert_eq!(replace(&replacer, "nothing to see"), None);
+    }
+}
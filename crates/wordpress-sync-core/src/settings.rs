@@ -0,0 +1,434 @@
+//! The app's own settings: how many queued syncs run at once and what they
+//! share, the bandwidth limits of profiles that set none, desktop notifications, logging, and the
+//! language messages are shown in, and whether usage metrics are sent.
+//!
+//! They are kept in `~/.wordpress-sync/app-settings.json`, for the whole app
+//! and, for the keys that allow it, per profile, which overrides the app's.
+//! A key that's never been set has its default. Each change is read the next
+//! time the setting is used: the queue's concurrency as soon as it's woken,
+//! the bandwidth limits at a job's start, the log level at the app's, the locale straight away.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{messages, paths};
+
+/// Serializes read-modify-write cycles of the settings file within this process.
+static FILE_LOCK: Mutex<()> = Mutex::new(());
+
+/// The frontend's own settings file, whose notification switch came first.
+const FRONTEND_SETTINGS_FILE: &str = "settings.json";
+
+/// The levels the app log can be kept at.
+const LOG_LEVELS: &[&str] = &["error", "warn", "info", "debug", "trace"];
+
+/// A setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettingKey {
+    /// How many queued syncs run at the same time, of different profiles.
+    QueueConcurrency,
+    /// The upload limit, in KiB per second, of profiles that set none; null
+    /// for unlimited.
+    UploadKibPerSec,
+    /// The download limit, likewise.
+    DownloadKibPerSec,
+    /// The limit, in KiB per second, all running jobs are held to between
+    /// them; null for none.
+    TotalKibPerSec,
+    /// How many SSH sessions jobs may have open at once.
+    MaxSshSessions,
+    /// Whether finished jobs are announced with desktop notifications.
+    NotificationsEnabled,
+    /// Whether only failed jobs are.
+    NotifyFailuresOnly,
+    /// How much the app log records for the app itself: `error`, `warn`,
+    /// `info`, `debug`, or `trace`. The `WORDPRESS_SYNC_LOG` variable, if set,
+    /// overrides it.
+    LogLevel,
+    /// How many job logs are kept, newest first.
+    JobLogsKept,
+    /// How many days of the app log are kept.
+    AppLogDays,
+    /// The locale the backend's messages are shown in, such as `en` or `de`.
+    Locale,
+    /// Whether the daily totals of usage metrics are sent; they're counted
+    /// here either way.
+    UsageMetrics,
+}
+
+impl SettingKey {
+    pub const ALL: [SettingKey; 12] = [
+        SettingKey::QueueConcurrency,
+        SettingKey::UploadKibPerSec,
+        SettingKey::DownloadKibPerSec,
+        SettingKey::TotalKibPerSec,
+        SettingKey::MaxSshSessions,
+        SettingKey::NotificationsEnabled,
+        SettingKey::NotifyFailuresOnly,
+        SettingKey::LogLevel,
+        SettingKey::JobLogsKept,
+        SettingKey::AppLogDays,
+        SettingKey::Locale,
+        SettingKey::UsageMetrics,
+    ];
+
+    /// The key's name in the settings file.
+    fn name(self) -> &'static str {
+        match self {
+            SettingKey::QueueConcurrency => "queue_concurrency",
+            SettingKey::UploadKibPerSec => "upload_kib_per_sec",
+            SettingKey::DownloadKibPerSec => "download_kib_per_sec",
+            SettingKey::TotalKibPerSec => "total_kib_per_sec",
+            SettingKey::MaxSshSessions => "max_ssh_sessions",
+            SettingKey::NotificationsEnabled => "notifications_enabled",
+            SettingKey::NotifyFailuresOnly => "notify_failures_only",
+            SettingKey::LogLevel => "log_level",
+            SettingKey::JobLogsKept => "job_logs_kept",
+            SettingKey::AppLogDays => "app_log_days",
+            SettingKey::Locale => "locale",
+            SettingKey::UsageMetrics => "usage_metrics",
+        }
+    }
+
+    /// Whether a profile can have its own value. The bandwidth limits are a
+    /// profile's own already, in its `bandwidth`.
+    pub fn per_profile(self) -> bool {
+        matches!(
+            self,
+            SettingKey::NotificationsEnabled | SettingKey::NotifyFailuresOnly
+        )
+    }
+
+    /// The value the key has when it isn't set.
+    pub fn default_value(self) -> Value {
+        match self {
+            SettingKey::QueueConcurrency => Value::from(2),
+            SettingKey::UploadKibPerSec
+            | SettingKey::DownloadKibPerSec
+            | SettingKey::TotalKibPerSec => Value::Null,
+            SettingKey::MaxSshSessions => Value::from(8),
+            SettingKey::NotificationsEnabled => Value::from(frontend_notifications()),
+            SettingKey::NotifyFailuresOnly | SettingKey::UsageMetrics => Value::from(false),
+            SettingKey::LogLevel => Value::from("info"),
+            SettingKey::JobLogsKept => Value::from(100),
+            SettingKey::AppLogDays => Value::from(14),
+            SettingKey::Locale => Value::from(messages::DEFAULT_LOCALE),
+        }
+    }
+
+    /// Check `value` is one the key can have.
+    fn check(self, value: &Value) -> Result<(), String> {
+        let name = self.name();
+        let count = |min: u64, max: u64| match value.as_u64() {
+            Some(n) if (min..=max).contains(&n) => Ok(()),
+            _ => Err(messages::text(
+                "error.name_must_whole_number_min_max",
+                &[("name", &name), ("min", &min), ("max", &max)],
+            )),
+        };
+        match self {
+            SettingKey::QueueConcurrency => count(1, 8),
+            SettingKey::UploadKibPerSec
+            | SettingKey::DownloadKibPerSec
+            | SettingKey::TotalKibPerSec => match value {
+                Value::Null => Ok(()),
+                _ => count(1, u64::from(u32::MAX)),
+            },
+            SettingKey::MaxSshSessions => count(1, 64),
+            SettingKey::NotificationsEnabled
+            | SettingKey::NotifyFailuresOnly
+            | SettingKey::UsageMetrics => match value.is_boolean() {
+                true => Ok(()),
+                false => Err(messages::text(
+                    "error.name_must_true_false",
+                    &[("name", &name)],
+                )),
+            },
+            SettingKey::LogLevel => match value.as_str() {
+                Some(level) if LOG_LEVELS.contains(&level) => Ok(()),
+                _ => Err(messages::text(
+                    "error.name_must_one_log_levels",
+                    &[("name", &name), ("log_levels", &LOG_LEVELS.join(", "))],
+                )),
+            },
+            SettingKey::JobLogsKept => count(1, 10_000),
+            SettingKey::AppLogDays => count(1, 365),
+            SettingKey::Locale => match value.as_str() {
+                Some(locale) if messages::locales().iter().any(|known| known == locale) => Ok(()),
+                Some(locale) if messages::is_locale(locale) => Err(messages::text(
+                    "error.theres_no_message_catalog_locale_add",
+                    &[("locale", &locale)],
+                )),
+                _ => Err(messages::text(
+                    "error.name_must_locale_such_en_de",
+                    &[("name", &name)],
+                )),
+            },
+        }
+    }
+}
+
+/// Where a setting's value comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettingSource {
+    Default,
+    /// Set for the whole app.
+    App,
+    /// Set for the profile asked about.
+    Profile,
+}
+
+/// A setting's value, for the whole app or one profile.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Setting {
+    pub key: SettingKey,
+    /// The profile it's the value for, or `None` for the whole app's.
+    pub profile_id: Option<String>,
+    pub value: Value,
+    pub source: SettingSource,
+    pub default: Value,
+    pub per_profile: bool,
+}
+
+/// The settings file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Store {
+    /// Keys by name, so a file from a later version with keys this one
+    /// doesn't know still loads.
+    #[serde(default)]
+    app: BTreeMap<String, Value>,
+    #[serde(default)]
+    profiles: BTreeMap<String, BTreeMap<String, Value>>,
+}
+
+impl Store {
+    fn resolve(&self, key: SettingKey, profile_id: Option<&str>) -> Setting {
+        let set = |values: &BTreeMap<String, Value>| {
+            values
+                .get(key.name())
+                .filter(|value| key.check(value).is_ok())
+                .cloned()
+        };
+        let own = profile_id
+            .filter(|_| key.per_profile())
+            .and_then(|id| self.profiles.get(id))
+            .and_then(set);
+        let default = key.default_value();
+        let (value, source) = match (own, set(&self.app)) {
+            (Some(value), _) => (value, SettingSource::Profile),
+            (None, Some(value)) => (value, SettingSource::App),
+            (None, None) => (default.clone(), SettingSource::Default),
+        };
+        Setting {
+            key,
+            profile_id: profile_id.map(String::from),
+            value,
+            source,
+            default,
+            per_profile: key.per_profile(),
+        }
+    }
+}
+
+/// The value of `key` for `profile_id`, or with `None` for the whole app.
+pub fn get_setting(key: SettingKey, profile_id: Option<&str>) -> Result<Setting, String> {
+    Ok(load()?.resolve(key, profile_id))
+}
+
+/// Every setting, for `profile_id` or the whole app.
+pub fn all_settings(profile_id: Option<&str>) -> Result<Vec<Setting>, String> {
+    let store = load()?;
+    Ok(SettingKey::ALL
+        .iter()
+        .map(|&key| store.resolve(key, profile_id))
+        .collect())
+}
+
+/// Set `key` for `profile_id`, or with `None` for the whole app, to `value`.
+/// A null `value` for a key that can't be null unsets it, back to the app's
+/// value for a profile and the default for the app.
+pub fn set_setting(
+    key: SettingKey,
+    profile_id: Option<&str>,
+    value: Value,
+) -> Result<Setting, String> {
+    if profile_id.is_some() && !key.per_profile() {
+        return Err(messages::text(
+            "error.name_set_whole_app_not_per",
+            &[("name", &key.name())],
+        ));
+    }
+    let unset = value.is_null() && key.check(&value).is_err();
+    if !unset {
+        key.check(&value)?;
+    }
+    let _lock = FILE_LOCK.lock().unwrap();
+    let mut store = load()?;
+    let values = match profile_id {
+        Some(id) => store.profiles.entry(id.to_string()).or_default(),
+        None => &mut store.app,
+    };
+    match unset {
+        true => values.remove(key.name()),
+        false => values.insert(key.name().to_string(), value),
+    };
+    store.profiles.retain(|_, values| !values.is_empty());
+    save(&store)?;
+    match key {
+        SettingKey::Locale => messages::reload(),
+        SettingKey::TotalKibPerSec | SettingKey::MaxSshSessions => crate::sync::rebalance(),
+        _ => {}
+    }
+    tracing::info!(key = key.name(), profile_id, "setting changed");
+    Ok(store.resolve(key, profile_id))
+}
+
+/// Forget the settings of the profile `profile_id`, once it's deleted.
+pub fn remove_profile_settings(profile_id: &str) -> Result<(), String> {
+    let _lock = FILE_LOCK.lock().unwrap();
+    let mut store = load()?;
+    if store.profiles.remove(profile_id).is_some() {
+        save(&store)?;
+    }
+    Ok(())
+}
+
+/// How many queued syncs may run at once.
+pub fn queue_concurrency() -> usize {
+    number(SettingKey::QueueConcurrency, None)
+        .unwrap_or(2)
+        .max(1) as usize
+}
+
+/// The limit, in KiB per second, running jobs share.
+pub fn total_bandwidth_limit() -> Option<u64> {
+    number(SettingKey::TotalKibPerSec, None)
+}
+
+/// How many SSH sessions jobs may have open at once.
+pub fn max_ssh_sessions() -> usize {
+    number(SettingKey::MaxSshSessions, None).unwrap_or(8).max(1) as usize
+}
+
+/// The limits, in KiB per second, of uploads and downloads for a profile
+/// that sets none of its own.
+pub fn bandwidth_limits() -> (Option<u64>, Option<u64>) {
+    (
+        number(SettingKey::UploadKibPerSec, None),
+        number(SettingKey::DownloadKibPerSec, None),
+    )
+}
+
+/// Whether a finished job of `profile_id` that `failed`, or didn't, is to be
+/// announced with a desktop notification.
+pub fn notifies(profile_id: Option<&str>, failed: bool) -> bool {
+    let enabled = flag(SettingKey::NotificationsEnabled, profile_id);
+    enabled && (failed || !flag(SettingKey::NotifyFailuresOnly, profile_id))
+}
+
+/// The level the app log records the app at.
+pub fn log_level() -> String {
+    value(SettingKey::LogLevel, None)
+        .as_str()
+        .unwrap_or("info")
+        .to_string()
+}
+
+/// How many job logs are kept.
+pub fn job_logs_kept() -> usize {
+    number(SettingKey::JobLogsKept, None).unwrap_or(100) as usize
+}
+
+/// How many days of the app log are kept.
+pub fn app_log_days() -> usize {
+    number(SettingKey::AppLogDays, None).unwrap_or(14) as usize
+}
+
+/// Whether usage metrics may be sent.
+pub fn usage_metrics() -> bool {
+    value(SettingKey::UsageMetrics, None)
+        .as_bool()
+        .unwrap_or(false)
+}
+
+/// The locale messages are shown in.
+pub fn locale() -> String {
+    value(SettingKey::Locale, None)
+        .as_str()
+        .unwrap_or(messages::DEFAULT_LOCALE)
+        .to_string()
+}
+
+/// A setting's value where it's used, which is its default if the settings
+/// can't be read: the app goes on as it would with none.
+fn value(key: SettingKey, profile_id: Option<&str>) -> Value {
+    match get_setting(key, profile_id) {
+        Ok(setting) => setting.value,
+        Err(e) => {
+            tracing::warn!(error = %e, "using the default settings");
+            key.default_value()
+        }
+    }
+}
+
+fn number(key: SettingKey, profile_id: Option<&str>) -> Option<u64> {
+    value(key, profile_id).as_u64()
+}
+
+fn flag(key: SettingKey, profile_id: Option<&str>) -> bool {
+    value(key, profile_id).as_bool().unwrap_or(true)
+}
+
+fn load() -> Result<Store, String> {
+    let path = paths::app_settings_file()?;
+    let raw = match fs::read_to_string(&path) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Store::default()),
+        Err(e) => {
+            return Err(messages::text(
+                "error.failed.read_path",
+                &[("path", &path.display()), ("error", &e)],
+            ))
+        }
+    };
+    serde_json::from_str(&raw).map_err(|e| {
+        messages::text(
+            "error.invalid_settings_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })
+}
+
+fn save(store: &Store) -> Result<(), String> {
+    let path = paths::app_settings_file()?;
+    let json = serde_json::to_string_pretty(store)
+        .map_err(|e| messages::text("error.failed.serialize_settings", &[("error", &e)]))?;
+    let dir = paths::config_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| {
+        messages::text(
+            "error.failed.create_path",
+            &[("path", &dir.display()), ("error", &e)],
+        )
+    })?;
+    paths::atomic_write(&path, json)
+}
+
+/// Whether the frontend's settings leave notifications on, as they are by
+/// default: what they were before they were a setting here.
+fn frontend_notifications() -> bool {
+    let Ok(dir) = paths::config_dir() else {
+        return true;
+    };
+    fs::read_to_string(dir.join(FRONTEND_SETTINGS_FILE))
+        .ok()
+        .and_then(|raw| serde_json::from_str::<Value>(&raw).ok())
+        .and_then(|settings| settings.get("notifications_enabled")?.as_bool())
+        .unwrap_or(true)
+}
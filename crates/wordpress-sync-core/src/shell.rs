@@ -0,0 +1,29 @@
+//! POSIX shell quoting for commands sent to the remote server.
+
+/// Quote a single argument for a POSIX shell.
+///
+/// Safe characters pass through untouched so logged commands stay readable;
+/// anything else is wrapped in single quotes with embedded quotes escaped.
+pub fn quote(arg: &str) -> String {
+    let safe = !arg.is_empty()
+        && arg
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_./=:,@%+".contains(&b));
+    if safe {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// Join arguments into a single shell command line.
+pub fn join<I, S>(args: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    args.into_iter()
+        .map(|a| quote(a.as_ref()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
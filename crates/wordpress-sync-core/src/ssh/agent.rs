@@ -0,0 +1,59 @@
+//! Connecting to the local SSH agent.
+
+use russh::keys::agent::client::AgentClient;
+use russh::keys::agent::client::AgentStream;
+
+use crate::messages;
+
+/// A connection to the agent, boxed so Unix sockets and Windows pipes share a type.
+pub type DynAgent = AgentClient<Box<dyn AgentStream + Send + Unpin + 'static>>;
+
+/// The SSH agent socket, recovered from launchd when the app wasn't started from a shell.
+#[cfg(unix)]
+pub fn agent_socket() -> Option<String> {
+    use std::sync::OnceLock;
+
+    static SOCK: OnceLock<Option<String>> = OnceLock::new();
+    SOCK.get_or_init(|| {
+        std::env::var("SSH_AUTH_SOCK").ok().or_else(|| {
+            std::process::Command::new("launchctl")
+                .args(["getenv", "SSH_AUTH_SOCK"])
+                .output()
+                .ok()
+                .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+                .filter(|sock| !sock.is_empty())
+        })
+    })
+    .clone()
+}
+
+/// The OpenSSH for Windows agent's named pipe.
+#[cfg(windows)]
+pub fn agent_socket() -> Option<String> {
+    Some(
+        std::env::var("SSH_AUTH_SOCK")
+            .unwrap_or_else(|_| r"\\.\pipe\openssh-ssh-agent".to_string()),
+    )
+}
+
+/// Open a raw stream to the agent, for forwarding to the server.
+pub async fn connect_stream() -> Result<Box<dyn AgentStream + Send + Unpin + 'static>, String> {
+    let sock = agent_socket().ok_or_else(|| messages::text("error.no_ssh_agent_running", &[]))?;
+    #[cfg(unix)]
+    let stream = tokio::net::UnixStream::connect(&sock).await;
+    #[cfg(windows)]
+    let stream = tokio::net::windows::named_pipe::ClientOptions::new().open(&sock);
+    stream
+        .map(|s| Box::new(s) as Box<dyn AgentStream + Send + Unpin>)
+        .map_err(|e| {
+            messages::text(
+                "error.failed.connect_ssh_agent_sock",
+                &[("sock", &sock), ("error", &e)],
+            )
+        })
+}
+
+/// Open an agent client, for authenticating with the agent's keys.
+pub async fn connect() -> Result<DynAgent, String> {
+    Ok(AgentClient::connect(connect_stream().await?))
+}
@@ -0,0 +1,160 @@
+//! Tunnelling rsync through a managed [`Session`].
+//!
+//! rsync always launches its own remote shell (`rsync -e <shell>`). We point that
+//! at this app's own binary started with [`BRIDGE_FLAG`]: in that mode it connects
+//! to a loopback port served by the running app, announces the command rsync wanted
+//! to run remotely, and then shovels stdin/stdout across. The app executes the
+//! command on an SSH channel and joins the two streams.
+
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr};
+use std::sync::Arc;
+
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+use super::Session;
+use crate::messages;
+use crate::shell;
+
+/// First argument that switches the binary into bridge-client mode.
+pub const BRIDGE_FLAG: &str = "--ssh-bridge";
+
+/// A loopback listener forwarding bridge clients onto a session.
+pub struct Bridge {
+    addr: SocketAddr,
+    /// Shared secret so other local processes can't use our connection.
+    token: String,
+    task: JoinHandle<()>,
+}
+
+impl Bridge {
+    pub async fn start(session: Arc<Session>) -> Result<Self, String> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| messages::text("error.failed.start_ssh_bridge", &[("error", &e)]))?;
+        let addr = listener
+            .local_addr()
+            .map_err(|e| messages::text("error.failed.start_ssh_bridge", &[("error", &e)]))?;
+        let token = uuid::Uuid::new_v4().simple().to_string();
+
+        let expected = token.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let session = session.clone();
+                let expected = expected.clone();
+                tokio::spawn(async move {
+                    let _ = serve(stream, &session, &expected).await;
+                });
+            }
+        });
+        Ok(Self { addr, token, task })
+    }
+
+    /// The `-e` value that makes rsync use this bridge as its remote shell.
+    pub fn rsync_shell(&self) -> Result<String, String> {
+        let exe = std::env::current_exe()
+            .map_err(|e| messages::text("error.failed.locate_app_binary", &[("error", &e)]))?;
+        Ok(shell::join([
+            exe.display().to_string(),
+            BRIDGE_FLAG.to_string(),
+            self.addr.to_string(),
+            self.token.clone(),
+        ]))
+    }
+}
+
+impl Drop for Bridge {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Handle one bridge client: check its token, run its command, and join the streams.
+async fn serve(stream: TcpStream, session: &Session, expected: &str) -> Result<(), String> {
+    let mut stream = BufReader::new(stream);
+    let mut token = String::new();
+    let mut command = String::new();
+    stream
+        .read_line(&mut token)
+        .await
+        .map_err(|e| e.to_string())?;
+    if token.trim_end() != expected {
+        return Err(messages::text(
+            "error.rejected_ssh_bridge_client_bad_token",
+            &[],
+        ));
+    }
+    stream
+        .read_line(&mut command)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let command = command.trim_end();
+    let channel = session.open_exec(command).await?;
+    let mut remote = channel.into_stream();
+    let copied = tokio::io::copy_bidirectional(&mut stream, &mut remote).await;
+    // The stream doesn't carry the exit status; rsync reports it on this side.
+    super::audit_exit(command, None);
+    copied.map(drop).map_err(|e| e.to_string())
+}
+
+/// Entry point when the binary runs as rsync's remote shell.
+///
+/// `args` follow [`BRIDGE_FLAG`]: `<addr> <token> [-l user] <host> <command...>`.
+/// Returns the process exit code.
+pub fn run_bridge_client(args: &[String]) -> i32 {
+    match bridge_client(args) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("wordpress-sync ssh bridge: {e}");
+            255 // What ssh exits with on connection errors
+        }
+    }
+}
+
+fn bridge_client(args: &[String]) -> std::io::Result<()> {
+    let [addr, token, rest @ ..] = args else {
+        return Err(std::io::Error::other(
+            "usage: --ssh-bridge <addr> <token> <host> <command>",
+        ));
+    };
+    let mut stream = std::net::TcpStream::connect(addr.as_str())?;
+    stream.write_all(format!("{token}\n{}\n", remote_command(rest)).as_bytes())?;
+
+    let mut upstream = stream.try_clone()?;
+    std::thread::spawn(move || {
+        let _ = std::io::copy(&mut std::io::stdin().lock(), &mut upstream);
+        let _ = upstream.shutdown(Shutdown::Write);
+    });
+
+    let mut stdout = std::io::stdout().lock();
+    let mut buf = [0u8; 32 * 1024];
+    loop {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        stdout.write_all(&buf[..n])?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+/// Extract the remote command from the arguments rsync gives its shell.
+///
+/// rsync calls `<shell> [-l user] <host> <command words...>`. Like ssh, the command
+/// words are joined with single spaces and not re-quoted — rsync has already
+/// escaped them for the remote shell.
+fn remote_command(args: &[String]) -> String {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "-l" {
+            args.next();
+        } else if !arg.starts_with('-') {
+            break; // The host; everything after it is the command
+        }
+    }
+    args.map(String::as_str).collect::<Vec<_>>().join(" ")
+}
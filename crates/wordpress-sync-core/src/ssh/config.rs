@@ -0,0 +1,294 @@
+//! A reader for the user's OpenSSH client configuration (`~/.ssh/config`).
+//!
+//! Only the options that matter for reaching a server are understood. Like
+//! OpenSSH, the first value found for an option wins, so more specific `Host`
+//! blocks are expected to come before general ones.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::messages;
+
+/// Maximum `Include` nesting, the same limit OpenSSH enforces.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// What `~/.ssh/config` says about connecting to a host alias.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedHost {
+    pub alias: String,
+    /// The real host name, or the alias itself if no `HostName` is set.
+    pub host_name: String,
+    pub user: Option<String>,
+    pub port: u16,
+    /// Keys from `IdentityFile`, with `~` and `%` tokens expanded.
+    pub identity_files: Vec<PathBuf>,
+    /// Raw `ProxyJump` value, e.g. `admin@bastion:2222`.
+    pub proxy_jump: Option<String>,
+}
+
+/// Look up `alias` in `~/.ssh/config`. A missing config file resolves every alias to itself.
+pub fn resolve_host(alias: &str) -> Result<ResolvedHost, String> {
+    let home = dirs::home_dir()
+        .ok_or_else(|| messages::text("error.could_not_determine_home_directory", &[]))?;
+    let mut options = Options::default();
+    let path = home.join(".ssh").join("config");
+    if path.is_file() {
+        read_file(&path, &home, alias, &mut options, 0)?;
+    }
+
+    let host_name = options
+        .host_name
+        .map(|name| name.replace("%h", alias))
+        .unwrap_or_else(|| alias.to_string());
+    let port = match options.port {
+        Some(port) => port.parse().map_err(|_| {
+            messages::text(
+                "error.invalid_port_alias_ssh_config",
+                &[("port", &format_args!("{port:?}")), ("alias", &alias)],
+            )
+        })?,
+        None => 22,
+    };
+    let tokens = Tokens {
+        home: &home,
+        host_name: &host_name,
+        user: options.user.as_deref(),
+    };
+    let identity_files = options
+        .identity_files
+        .iter()
+        .map(|file| PathBuf::from(tokens.expand(file)))
+        .collect();
+    Ok(ResolvedHost {
+        alias: alias.to_string(),
+        host_name,
+        user: options.user,
+        port,
+        identity_files,
+        proxy_jump: options.proxy_jump.filter(|jump| jump != "none"),
+    })
+}
+
+/// Options gathered so far; `None` means not yet set by any matching block.
+#[derive(Default)]
+struct Options {
+    host_name: Option<String>,
+    user: Option<String>,
+    port: Option<String>,
+    identity_files: Vec<String>,
+    proxy_jump: Option<String>,
+}
+
+fn read_file(
+    path: &Path,
+    home: &Path,
+    alias: &str,
+    options: &mut Options,
+    depth: usize,
+) -> Result<(), String> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(messages::text(
+            "error.too_many_nested_includes_path",
+            &[("path", &path.display())],
+        ));
+    }
+    let contents = fs::read_to_string(path).map_err(|e| {
+        messages::text(
+            "error.failed.read_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })?;
+
+    // Options before the first Host line apply to every host.
+    let mut active = true;
+    for line in contents.lines() {
+        let Some((keyword, args)) = split_line(line) else {
+            continue;
+        };
+        match keyword.to_ascii_lowercase().as_str() {
+            "host" => active = host_matches(alias, &args),
+            // Match criteria aren't evaluated; treat those blocks as not applying.
+            "match" => active = false,
+            _ if !active => {}
+            "include" => {
+                for pattern in &args {
+                    for included in include_paths(pattern, home) {
+                        read_file(&included, home, alias, options, depth + 1)?;
+                    }
+                }
+            }
+            "hostname" => set_once(&mut options.host_name, &args),
+            "user" => set_once(&mut options.user, &args),
+            "port" => set_once(&mut options.port, &args),
+            "proxyjump" => set_once(&mut options.proxy_jump, &args),
+            "identityfile" => options.identity_files.extend(args.first().cloned()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn set_once(option: &mut Option<String>, args: &[String]) {
+    if option.is_none() {
+        *option = args.first().cloned();
+    }
+}
+
+/// Split a config line into its keyword and arguments, dropping comments and blanks.
+/// Keywords may be separated from their arguments by whitespace or `=`, and
+/// arguments may be double-quoted.
+fn split_line(line: &str) -> Option<(String, Vec<String>)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let split = line
+        .find(|c: char| c.is_whitespace() || c == '=')
+        .unwrap_or(line.len());
+    let (keyword, rest) = line.split_at(split);
+    let rest = rest.trim_start().strip_prefix('=').unwrap_or(rest).trim();
+
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut in_word = false;
+    for c in rest.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_word = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if in_word {
+                    args.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        args.push(current);
+    }
+    Some((keyword.to_string(), args))
+}
+
+/// Whether a `Host` line's patterns select `alias`. A matching negated
+/// pattern (`!pattern`) excludes the host whatever else matches.
+fn host_matches(alias: &str, patterns: &[String]) -> bool {
+    let mut matched = false;
+    for pattern in patterns {
+        match pattern.strip_prefix('!') {
+            Some(negated) if wildcard_match(negated, alias) => return false,
+            Some(_) => {}
+            None => matched |= wildcard_match(pattern, alias),
+        }
+    }
+    matched
+}
+
+/// Glob matching with `*` (any run of characters) and `?` (any one character).
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` seen, and where in the text it started matching
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c.eq_ignore_ascii_case(&text[t]) => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Files named by an `Include` argument. Relative paths are under `~/.ssh`, and
+/// wildcards are allowed in the file name.
+fn include_paths(pattern: &str, home: &Path) -> Vec<PathBuf> {
+    let path = match pattern.strip_prefix("~/") {
+        Some(rest) => home.join(rest),
+        None if Path::new(pattern).is_absolute() => PathBuf::from(pattern),
+        None => home.join(".ssh").join(pattern),
+    };
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return Vec::new();
+    };
+    if !name.contains(['*', '?']) {
+        return vec![path];
+    }
+    let Some(dir) = path.parent() else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut matches: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.is_file())
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| wildcard_match(name, n))
+        })
+        .collect();
+    // OpenSSH reads glob matches in lexical order
+    matches.sort();
+    matches
+}
+
+/// Values for the `%` tokens OpenSSH expands in paths.
+struct Tokens<'a> {
+    home: &'a Path,
+    host_name: &'a str,
+    user: Option<&'a str>,
+}
+
+impl Tokens<'_> {
+    fn expand(&self, value: &str) -> String {
+        let home = self.home.to_string_lossy();
+        let value = match value.strip_prefix("~/") {
+            Some(rest) => format!("{home}/{rest}"),
+            None => value.to_string(),
+        };
+        let mut expanded = String::with_capacity(value.len());
+        let mut chars = value.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                expanded.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('%') => expanded.push('%'),
+                Some('d') => expanded.push_str(&home),
+                Some('h') => expanded.push_str(self.host_name),
+                Some('r') => expanded.push_str(self.user.unwrap_or_default()),
+                Some(other) => {
+                    expanded.push('%');
+                    expanded.push(other);
+                }
+                None => expanded.push('%'),
+            }
+        }
+        expanded
+    }
+}
@@ -0,0 +1,120 @@
+//! Host key verification against `~/.ssh/known_hosts`, with trust on first use.
+//!
+//! A key that isn't recorded yet (or that no longer matches what is recorded) fails
+//! the connection and is parked here until the user confirms its fingerprint. Once
+//! confirmed it is written to known_hosts, so OpenSSH and later syncs trust it too.
+
+use std::fs;
+use std::sync::Mutex;
+
+use russh::keys::{self, known_hosts, HashAlg, PublicKey};
+use serde::Serialize;
+
+use crate::messages;
+
+/// A server key nobody has vouched for yet.
+#[derive(Debug, Clone, Serialize)]
+pub struct UnverifiedHostKey {
+    pub host: String,
+    pub port: u16,
+    pub algorithm: String,
+    /// OpenSSH-style `SHA256:...` fingerprint; this is what `confirm` takes.
+    pub fingerprint: String,
+    /// Whether known_hosts already holds a different key of this type for the host,
+    /// i.e. the server's identity has changed since it was last trusted.
+    pub changed: bool,
+}
+
+struct Pending {
+    info: UnverifiedHostKey,
+    key: PublicKey,
+}
+
+/// Keys rejected during this run of the app, waiting for the user's decision.
+static PENDING: Mutex<Vec<Pending>> = Mutex::new(Vec::new());
+
+/// Check `key` against known_hosts. Returns the details to show the user if it
+/// isn't trusted, and remembers it so [`confirm`] can accept it later.
+pub(super) fn verify(host: &str, port: u16, key: &PublicKey) -> Option<UnverifiedHostKey> {
+    let changed = match keys::check_known_hosts(host, port, key) {
+        Ok(true) => return None,
+        Ok(false) => false,
+        Err(keys::Error::KeyChanged { .. }) => true,
+        // An unreadable known_hosts is treated like an empty one; confirming will
+        // surface the underlying problem when it tries to write.
+        Err(_) => false,
+    };
+    let info = UnverifiedHostKey {
+        host: host.to_string(),
+        port,
+        algorithm: key.algorithm().to_string(),
+        fingerprint: key.fingerprint(HashAlg::Sha256).to_string(),
+        changed,
+    };
+    let mut pending = PENDING.lock().unwrap();
+    pending.retain(|p| p.info.fingerprint != info.fingerprint);
+    pending.push(Pending {
+        info: info.clone(),
+        key: key.clone(),
+    });
+    Some(info)
+}
+
+/// Trust a key previously rejected by [`verify`], identified by its fingerprint.
+///
+/// If the host's key changed, the stale known_hosts entry is replaced.
+pub fn confirm(fingerprint: &str) -> Result<UnverifiedHostKey, String> {
+    let Pending { info, key } = {
+        let mut pending = PENDING.lock().unwrap();
+        let index = pending
+            .iter()
+            .position(|p| p.info.fingerprint == fingerprint)
+            .ok_or_else(|| {
+                messages::text(
+                    "error.no_host_key_fingerprint_awaiting_confirmation",
+                    &[("fingerprint", &fingerprint)],
+                )
+            })?;
+        pending.remove(index)
+    };
+    if info.changed {
+        forget(&info.host, info.port, &key)?;
+    }
+    known_hosts::learn_known_hosts(&info.host, info.port, &key)
+        .map_err(|e| messages::text("error.failed.update_known_hosts", &[("error", &e)]))?;
+    Ok(info)
+}
+
+/// Remove known_hosts entries for the host that hold a different key of the same type.
+fn forget(host: &str, port: u16, key: &PublicKey) -> Result<(), String> {
+    let stale: Vec<usize> = known_hosts::known_host_keys(host, port)
+        .map_err(|e| messages::text("error.failed.read_known_hosts", &[("error", &e)]))?
+        .into_iter()
+        .filter(|(_, recorded)| recorded.algorithm() == key.algorithm() && recorded != key)
+        .map(|(line, _)| line)
+        .collect();
+    if stale.is_empty() {
+        return Ok(());
+    }
+
+    let path = dirs::home_dir()
+        .ok_or_else(|| messages::text("error.could_not_determine_home_directory", &[]))?
+        .join(".ssh")
+        .join("known_hosts");
+    let contents = fs::read_to_string(&path)
+        .map_err(|e| messages::text("error.failed.read_known_hosts", &[("error", &e)]))?;
+    // russh numbers entries from 1 without counting comment lines; match that.
+    let mut entry = 0;
+    let kept: String = contents
+        .split_inclusive('\n')
+        .filter(|line| {
+            if line.starts_with('#') {
+                return true;
+            }
+            entry += 1;
+            !stale.contains(&entry)
+        })
+        .collect();
+    fs::write(&path, kept)
+        .map_err(|e| messages::text("error.failed.update_known_hosts", &[("error", &e)]))
+}
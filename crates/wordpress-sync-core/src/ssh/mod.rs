@@ -0,0 +1,81 @@
+//! Managed SSH connections to remote servers.
+//!
+//! Every remote operation in a sync — wp-cli commands and rsync transfers alike —
+//! runs over a single [`Session`] authenticated by us, rather than relying on the
+//! user's `ssh` binary and `~/.ssh/config` being set up correctly. rsync, which
+//! insists on launching its own transport, is pointed at a [`Bridge`] that tunnels
+//! its stream through that session.
+//!
+//! A session connects again by itself if its connection drops, and one a job
+//! is done with is kept open a while for the next job to the same server.
+
+mod agent;
+mod bridge;
+mod config;
+pub mod host_keys;
+mod passphrase;
+mod platform;
+mod pool;
+mod prompt;
+mod session;
+mod tunnel;
+
+pub use agent::agent_socket;
+pub use bridge::{run_bridge_client, Bridge, BRIDGE_FLAG};
+pub use config::{resolve_host, ResolvedHost};
+pub use host_keys::UnverifiedHostKey;
+pub use passphrase::unlock_key;
+pub use platform::{Platform, Userland};
+pub use pool::{connect_shared, release};
+pub use prompt::{answer_auth_prompt, Ask, AuthPrompt, AuthPromptField};
+pub use session::{audit_exit, ConnectError, Session, AUDIT_TARGET};
+pub use tunnel::Tunnel;
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::net::AddressFamily;
+use crate::proxy::Route;
+
+/// Where and as whom to connect.
+#[derive(Clone)]
+pub struct SshTarget {
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    /// Authentication methods, tried in order until one succeeds.
+    pub auth: Vec<Auth>,
+    /// Forward the local SSH agent on every command channel.
+    pub forward_agent: bool,
+    /// Ask the server to zlib-compress the connection.
+    pub compress: bool,
+    /// Reach this host through a tunnel from another one, like OpenSSH's `ProxyJump`.
+    pub jump: Option<Box<SshTarget>>,
+    /// The HTTP or SOCKS5 proxy the TCP connection goes through, unless it
+    /// is tunnelled from a jump host.
+    pub proxy: Option<Route>,
+    /// Which of the host's addresses are connected to.
+    pub family: AddressFamily,
+    /// How long the TCP connection and key exchange may take.
+    pub connect_timeout: Duration,
+    /// How long the connection may be quiet before a keepalive is sent, or
+    /// `None` to send none.
+    pub keepalive_interval: Option<Duration>,
+    /// Keepalives unanswered in a row before the connection is closed.
+    pub keepalive_max: usize,
+}
+
+/// One way of proving who we are to the server.
+#[derive(Clone)]
+pub enum Auth {
+    /// Keys held by the running SSH agent.
+    Agent,
+    /// A private key file, decrypted with the passphrase stored for it if it
+    /// is encrypted.
+    Key(PathBuf),
+    /// A password, usually fetched from the credential store.
+    Password(String),
+    /// Answering the server's questions, such as a one-time code. A stored
+    /// password answers one that asks for it.
+    KeyboardInteractive { password: Option<String> },
+}
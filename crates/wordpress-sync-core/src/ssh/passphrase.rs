@@ -0,0 +1,72 @@
+//! Passphrases of encrypted private keys, kept in the credential store under
+//! the key file's path, so such keys work without an SSH agent holding them.
+
+use std::path::Path;
+
+use russh::keys::{self, PrivateKey};
+
+use crate::credentials::{platform_store, CredentialStore, SSH_KEY_PASSPHRASE_SERVICE};
+use crate::messages;
+
+/// Check that the key at `path` can be used and remember its passphrase.
+///
+/// With `passphrase`, it must decrypt the key, and is then stored for syncs to
+/// use. Without one, the key must either be unencrypted or decrypt with the
+/// passphrase stored before. A key that turns out not to be encrypted has any
+/// stored passphrase removed.
+pub fn unlock_key(path: &Path, passphrase: Option<&str>) -> Result<(), String> {
+    let store = platform_store();
+    let account = account(path);
+    match keys::load_secret_key(path, None) {
+        Ok(_) => return store.delete(SSH_KEY_PASSPHRASE_SERVICE, &account),
+        Err(keys::Error::KeyIsEncrypted) => {}
+        Err(e) => {
+            return Err(messages::text(
+                "error.failed.read_key_path",
+                &[("path", &path.display()), ("error", &e)],
+            ))
+        }
+    }
+    let stored;
+    let passphrase = match passphrase {
+        Some(passphrase) => passphrase,
+        None => {
+            stored = store.get(SSH_KEY_PASSPHRASE_SERVICE, &account)?;
+            stored.as_deref().ok_or_else(|| {
+                messages::text(
+                    "error.key_path_passphrase_protected_enter",
+                    &[("path", &path.display())],
+                )
+            })?
+        }
+    };
+    keys::load_secret_key(path, Some(passphrase)).map_err(|_| {
+        messages::text(
+            "error.wrong_passphrase_key_path",
+            &[("path", &path.display())],
+        )
+    })?;
+    store.set(SSH_KEY_PASSPHRASE_SERVICE, &account, passphrase)
+}
+
+/// The private key at `path`, decrypted with its stored passphrase if it is
+/// encrypted. The credential store is only asked for encrypted keys.
+pub(super) fn load(path: &Path) -> Result<PrivateKey, String> {
+    match keys::load_secret_key(path, None) {
+        Err(keys::Error::KeyIsEncrypted) => {}
+        result => return result.map_err(|e| e.to_string()),
+    }
+    let passphrase = platform_store()
+        .get(SSH_KEY_PASSPHRASE_SERVICE, &account(path))?
+        .ok_or_else(|| messages::text("error.key_passphrase_protected_unlock_app_add", &[]))?;
+    keys::load_secret_key(path, Some(&passphrase))
+        .map_err(|_| messages::text("error.stored_passphrase_doesnt_decrypt_key_unlock", &[]))
+}
+
+/// The credential store account for the key at `path`: its absolute path.
+fn account(path: &Path) -> String {
+    std::path::absolute(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .display()
+        .to_string()
+}
@@ -0,0 +1,132 @@
+//! What kind of server a session is on: its login shell, and whose userland
+//! its tools come from.
+//!
+//! Commands are written for a POSIX shell, but sshd starts them with the
+//! user's login shell, which on some hosts is csh, tcsh or fish. Their lines
+//! are handed to `/bin/sh` instead, encoded so those shells' quoting can't
+//! touch them. The few tools whose options differ between GNU, the BSDs and
+//! BusyBox (`stat`, `tar`) are run the way the server's understand.
+
+use std::borrow::Cow;
+
+/// Login shells that take a POSIX command line as it is.
+const POSIX_SHELLS: &[&str] = &[
+    "sh", "bash", "dash", "ash", "ksh", "ksh93", "mksh", "pdksh", "oksh", "yash", "zsh", "busybox",
+];
+
+/// Systems whose userland is BSD's.
+const BSD_SYSTEMS: &[&str] = &["FreeBSD", "OpenBSD", "NetBSD", "DragonFly", "Darwin"];
+
+/// Run on every kind of login shell: a single-quoted `/bin/sh` script with
+/// nothing in it csh or fish would read differently. Prints `key=value` lines.
+pub(super) const PROBE: &str = "/bin/sh -c 'echo \"shell=$SHELL\"; echo \"os=$(uname -s)\"; \
+     echo \"stat=$(stat --version 2>&1 | head -n 1)\"; \
+     if command -v perl >/dev/null 2>&1; then echo perl=yes; fi'";
+
+/// Whose versions of the standard tools a server has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Userland {
+    Gnu,
+    /// FreeBSD's, the other BSDs' or macOS's.
+    Bsd,
+    /// BusyBox, toybox, or another with few of GNU's options.
+    Minimal,
+}
+
+/// The server a session is on.
+#[derive(Debug, Clone)]
+pub struct Platform {
+    /// The user's login shell, as `$SHELL` names it; empty if it isn't set.
+    pub shell: String,
+    /// The system, as `uname -s` names it.
+    pub os: String,
+    pub userland: Userland,
+    /// Whether the server has Perl, which listing and delta copies use.
+    pub perl: bool,
+}
+
+impl Default for Platform {
+    /// What's assumed of a server that couldn't be probed: a POSIX shell and GNU's tools.
+    fn default() -> Self {
+        Self {
+            shell: String::new(),
+            os: "Linux".to_string(),
+            userland: Userland::Gnu,
+            perl: true,
+        }
+    }
+}
+
+impl Platform {
+    /// Read the output of [`PROBE`].
+    pub(super) fn parse(output: &str) -> Self {
+        let mut platform = Self {
+            perl: false,
+            ..Self::default()
+        };
+        let mut stat = "";
+        for line in output.lines() {
+            match line.split_once('=') {
+                Some(("shell", shell)) => platform.shell = shell.trim().to_string(),
+                Some(("os", os)) if !os.trim().is_empty() => platform.os = os.trim().to_string(),
+                Some(("stat", version)) => stat = version,
+                Some(("perl", _)) => platform.perl = true,
+                _ => {}
+            }
+        }
+        platform.userland = if BSD_SYSTEMS.contains(&platform.os.as_str()) {
+            Userland::Bsd
+        } else if stat.contains("GNU") {
+            Userland::Gnu
+        } else {
+            Userland::Minimal
+        };
+        platform
+    }
+
+    /// Whether the login shell takes POSIX command lines. One that isn't set
+    /// is taken to, as it always used to be.
+    pub fn posix_shell(&self) -> bool {
+        let name = self.shell.rsplit('/').next().unwrap_or_default();
+        name.is_empty() || POSIX_SHELLS.contains(&name)
+    }
+
+    /// `command` as it has to be sent for the login shell to run it with `/bin/sh`.
+    pub fn command_line<'a>(&self, command: &'a str) -> Cow<'a, str> {
+        if self.posix_shell() {
+            Cow::Borrowed(command)
+        } else {
+            Cow::Owned(for_any_shell(command))
+        }
+    }
+
+    /// A `stat` printing `<mode> <size> <mtime> <path>` on a line for each
+    /// file it's given, the mode as `ls -l` shows it.
+    pub fn stat_listing(&self) -> &'static str {
+        match self.userland {
+            Userland::Bsd => "stat -f '%Sp %z %m %N'",
+            Userland::Gnu | Userland::Minimal => "stat -c '%A %s %Y %n'",
+        }
+    }
+
+    /// Whether `tar -T` can read a NUL-terminated list, as GNU's and BSD's
+    /// can; others only read one name a line.
+    pub fn tar_null_list(&self) -> bool {
+        self.userland != Userland::Minimal
+    }
+}
+
+/// `command` as a `/bin/sh -c` line any shell passes through untouched: every
+/// character but the plainest is an octal escape that `printf` decodes, so
+/// the line has no quote, backslash pair or newline for csh or fish to read.
+fn for_any_shell(command: &str) -> String {
+    let mut encoded = String::with_capacity(command.len() * 2);
+    for byte in command.bytes() {
+        if byte.is_ascii_alphanumeric() || b" -_./,:=@+".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("\\{byte:03o}"));
+        }
+    }
+    format!("/bin/sh -c 'eval \"$(printf \"{encoded}\")\"'")
+}
@@ -0,0 +1,105 @@
+//! Sessions kept open between jobs.
+//!
+//! A dry run and the push after it, or the syncs of a queue to one server,
+//! would each connect and log in again: another handshake, another look in
+//! the credential store for a key's passphrase, another round of whatever the
+//! server asks. A job done with its session parks it here instead, and the
+//! next job to the same server as the same user takes it up if it's still
+//! open. One left unused for its profile's linger time is closed.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use sha2::{Digest, Sha256};
+
+use super::{Ask, Auth, ConnectError, Session, SshTarget};
+
+/// A session no job is using.
+struct Parked {
+    key: String,
+    session: Arc<Session>,
+}
+
+static PARKED: Mutex<Vec<Parked>> = Mutex::new(Vec::new());
+
+/// A session to `target`: one an earlier job parked, if it's still open, or
+/// a new one.
+pub async fn connect_shared(
+    target: &SshTarget,
+    ask: Ask<'_>,
+) -> Result<Arc<Session>, ConnectError> {
+    let key = key(target);
+    loop {
+        let taken = {
+            let mut parked = PARKED.lock().unwrap();
+            let index = parked.iter().position(|parked| parked.key == key);
+            index.map(|index| parked.remove(index).session)
+        };
+        let Some(session) = taken else {
+            break;
+        };
+        if !session.is_closed().await {
+            tracing::info!(user = %target.user, host = %target.host, "reusing the SSH connection");
+            return Ok(session);
+        }
+    }
+    Ok(Arc::new(Session::connect(target, ask).await?))
+}
+
+/// Be done with `session` for now: keep it open for `linger` for the next
+/// job to its server, then close it if none has taken it up.
+pub async fn release(session: Arc<Session>, linger: Duration) {
+    if linger.is_zero() || session.is_closed().await {
+        session.close().await;
+        return;
+    }
+    PARKED.lock().unwrap().push(Parked {
+        key: session.key(),
+        session: session.clone(),
+    });
+    // A process that ends before then drops it unclosed, which the server copes with.
+    tokio::spawn(async move {
+        tokio::time::sleep(linger).await;
+        let expired = {
+            let mut parked = PARKED.lock().unwrap();
+            let before = parked.len();
+            parked.retain(|parked| !Arc::ptr_eq(&parked.session, &session));
+            parked.len() < before
+        };
+        if expired {
+            tracing::debug!("closing an SSH connection no job took up");
+            session.close().await;
+        }
+    });
+}
+
+/// What a session has to have been opened with to serve a connection to
+/// `target`. Its login is in it as a digest, so a session opened with a
+/// password since changed isn't handed to the next job.
+pub(super) fn key(target: &SshTarget) -> String {
+    let mut login = Sha256::new();
+    for auth in &target.auth {
+        match auth {
+            Auth::Agent => login.update(b"agent\0"),
+            Auth::Key(path) => login.update(format!("key {}\0", path.display())),
+            Auth::Password(password) => login.update(format!("password {password}\0")),
+            Auth::KeyboardInteractive { password } => {
+                login.update(format!("keyboard-interactive {password:?}\0"))
+            }
+        }
+    }
+    let login: String = login.finalize()[..8]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    let proxy = target
+        .proxy
+        .as_ref()
+        .map(|route| format!("{}:{}", route.proxy.host, route.proxy.port))
+        .unwrap_or_default();
+    let jump = target.jump.as_deref().map(key).unwrap_or_default();
+    format!(
+        "{}@{}:{} login {login} via [{jump}] proxy [{proxy}] family {:?} agent {} compress {}",
+        target.user, target.host, target.port, target.family, target.forward_agent, target.compress
+    )
+}
@@ -0,0 +1,183 @@
+//! Keyboard-interactive logins: the questions a server asks before it lets us
+//! in, such as a one-time code or whether to send a Duo push.
+//!
+//! Each round of questions is handed to whoever started the connection as an
+//! [`AuthPrompt`], to be put to the user, and the login waits for the answers
+//! to come back through [`answer_auth_prompt`]. A password the profile has
+//! stored answers a lone password question without asking.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use russh::client::{Handle, KeyboardInteractiveAuthResponse, Prompt};
+use serde::Serialize;
+use tokio::sync::oneshot;
+
+use super::session::{Client, Login};
+use super::SshTarget;
+
+use crate::messages;
+
+/// How long the user has to answer, which a Duo push needs some of.
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Rounds of questions one login gets through before it's given up on.
+const MAX_ROUNDS: usize = 10;
+
+/// Where a prompt's answers go, or `None` if the login is given up on.
+type Answers = oneshot::Sender<Option<Vec<String>>>;
+
+/// Questions waiting for their answers, by id.
+static PENDING: Mutex<Vec<(String, Answers)>> = Mutex::new(Vec::new());
+
+/// A round of questions a server asks during login.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuthPrompt {
+    /// What the answers are sent back with.
+    pub id: String,
+    pub host: String,
+    pub user: String,
+    /// The server's title and instructions for the round; often empty.
+    pub name: String,
+    pub instructions: String,
+    /// The questions, in the order their answers are given.
+    pub fields: Vec<AuthPromptField>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AuthPromptField {
+    pub prompt: String,
+    /// Whether the answer may be shown as it's typed; a password or code isn't.
+    pub echo: bool,
+}
+
+/// Whoever can put an [`AuthPrompt`] to the user: it returns false if no one
+/// can, as in a scheduled sync, which fails the login.
+pub type Ask<'a> = &'a (dyn Fn(&AuthPrompt) -> bool + Sync);
+
+/// Answer the questions of the prompt `id`, in order, or with `None` give up
+/// on the login.
+pub fn answer_auth_prompt(id: &str, answers: Option<Vec<String>>) -> Result<(), String> {
+    let sender = {
+        let mut pending = PENDING.lock().unwrap();
+        let index = pending
+            .iter()
+            .position(|(pending, _)| pending == id)
+            .ok_or_else(|| messages::text("error.login_isnt_waiting_these_answers_any", &[]))?;
+        pending.remove(index).1
+    };
+    sender
+        .send(answers)
+        .map_err(|_| messages::text("error.login_isnt_waiting_these_answers_any", &[]))
+}
+
+/// Log in by answering the server's questions: a stored `password` answers
+/// the first that asks for one, and `ask` puts the rest to the user.
+pub(super) async fn authenticate(
+    handle: &mut Handle<Client>,
+    target: &SshTarget,
+    mut password: Option<&str>,
+    ask: Ask<'_>,
+) -> Result<Login, String> {
+    let mut response = handle
+        .authenticate_keyboard_interactive_start(target.user.as_str(), None)
+        .await
+        .map_err(|e| e.to_string())?;
+    for _ in 0..MAX_ROUNDS {
+        let (name, instructions, prompts) = match response {
+            KeyboardInteractiveAuthResponse::Success => return Ok(Login::Accepted),
+            KeyboardInteractiveAuthResponse::Failure {
+                partial_success, ..
+            } => return Ok(Login::failed(partial_success)),
+            KeyboardInteractiveAuthResponse::InfoRequest {
+                name,
+                instructions,
+                prompts,
+            } => (name, instructions, prompts),
+        };
+        let answers = match prompts.as_slice() {
+            // Some servers send an empty round before or after the real one.
+            [] => Vec::new(),
+            [only] if !only.echo && is_password(only) && password.is_some() => {
+                vec![password.take().unwrap_or_default().to_string()]
+            }
+            _ => put(target, name, instructions, &prompts, ask).await?,
+        };
+        response = handle
+            .authenticate_keyboard_interactive_respond(answers)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    Err(messages::text("error.server_kept_asking_questions", &[]))
+}
+
+/// Put a round of questions to the user and wait for the answers.
+async fn put(
+    target: &SshTarget,
+    name: String,
+    instructions: String,
+    prompts: &[Prompt],
+    ask: Ask<'_>,
+) -> Result<Vec<String>, String> {
+    let prompt = AuthPrompt {
+        id: uuid::Uuid::new_v4().to_string(),
+        host: target.host.clone(),
+        user: target.user.clone(),
+        name,
+        instructions,
+        fields: prompts
+            .iter()
+            .map(|prompt| AuthPromptField {
+                prompt: prompt.prompt.clone(),
+                echo: prompt.echo,
+            })
+            .collect(),
+    };
+    let (sender, receiver) = oneshot::channel();
+    PENDING.lock().unwrap().push((prompt.id.clone(), sender));
+    // Forgotten however the wait ends, a cancelled job's included.
+    let _waiting = Waiting(&prompt.id);
+    if !ask(&prompt) {
+        return Err(messages::text(
+            "error.server_asks_value_which_needs_answering",
+            &[(
+                "value",
+                &format_args!("{:?}", prompt.fields[0].prompt.trim()),
+            )],
+        ));
+    }
+    let answers = match tokio::time::timeout(ANSWER_TIMEOUT, receiver).await {
+        Ok(Ok(Some(answers))) => answers,
+        Ok(Ok(None)) | Ok(Err(_)) => return Err(messages::text("error.login_was_cancelled", &[])),
+        Err(_) => {
+            return Err(messages::text(
+                "error.no_answer_came_servers_questions",
+                &[],
+            ))
+        }
+    };
+    if answers.len() != prompts.len() {
+        return Err(messages::text(
+            "error.server_asked_count_questions_but_count2",
+            &[("count", &prompts.len()), ("count2", &answers.len())],
+        ));
+    }
+    Ok(answers)
+}
+
+/// A prompt's place among those waiting, given up when it's dropped.
+struct Waiting<'a>(&'a str);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        PENDING
+            .lock()
+            .unwrap()
+            .retain(|(pending, _)| pending != self.0);
+    }
+}
+
+/// Whether a question asks for the account's password.
+fn is_password(prompt: &Prompt) -> bool {
+    prompt.prompt.to_lowercase().contains("password")
+}
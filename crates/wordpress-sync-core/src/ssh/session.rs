@@ -0,0 +1,645 @@
+//! An authenticated SSH connection and the commands run over it.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use russh::client::{self, AuthResult, ChannelOpenHandle, Handle, Msg};
+use russh::keys::agent::AgentIdentity;
+use russh::keys::{self, PrivateKeyWithHashAlg, PublicKeyOrCertificate};
+use russh::{compression, Channel, ChannelMsg, Disconnect, Preferred};
+use tokio::io::{AsyncRead, AsyncWriteExt};
+use tokio::sync::OnceCell;
+
+use super::host_keys::{self, UnverifiedHostKey};
+use super::platform::{Platform, PROBE};
+use super::prompt::{self, Ask};
+use super::{agent, passphrase, Auth, SshTarget};
+use crate::byte_path;
+use crate::messages::{self, Message};
+use crate::{proxy, stderr};
+
+/// Compression algorithms to offer when a target asks for compression, by
+/// preference. Servers without zlib get an uncompressed connection.
+const COMPRESSED: &[compression::Name] = &[
+    compression::ZLIB_LEGACY,
+    compression::ZLIB,
+    compression::NONE,
+];
+
+/// The log target of the audit trail: every command run on a server, when it
+/// started, and how it exited. Job logs keep it with the rest of a job's events.
+pub const AUDIT_TARGET: &str = "wordpress_sync_core::audit";
+
+/// Add to the audit trail that `command` has finished, with the status it
+/// exited with, if it reported one.
+pub fn audit_exit(command: &str, exit_status: Option<u32>) {
+    tracing::info!(target: AUDIT_TARGET, command, exit_status, "remote command finished");
+}
+
+/// Everything a remote command wrote, and how it exited.
+#[derive(Debug, Default)]
+pub struct ExecOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// None if the channel closed without reporting a status (e.g. the command was killed).
+    pub exit_status: Option<u32>,
+}
+
+impl ExecOutput {
+    pub fn success(&self) -> bool {
+        self.exit_status == Some(0)
+    }
+}
+
+/// Why [`Session::connect`] failed.
+#[derive(Debug)]
+pub enum ConnectError {
+    /// The server's host key isn't trusted yet. The user can accept it with
+    /// [`host_keys::confirm`] and retry.
+    UnverifiedHostKey(UnverifiedHostKey),
+    /// The server rejected every authentication method tried.
+    AuthFailed(String),
+    Failed(String),
+}
+
+impl ConnectError {
+    pub fn message(&self) -> Message {
+        match self {
+            Self::UnverifiedHostKey(key) => Message::new(
+                if key.changed {
+                    "error.host_key_changed"
+                } else {
+                    "error.host_key_unverified"
+                },
+                &[
+                    ("host", &key.host),
+                    ("algorithm", &key.algorithm),
+                    ("fingerprint", &key.fingerprint),
+                ],
+            ),
+            Self::AuthFailed(message) | Self::Failed(message) => Message::plain(message.as_str()),
+        }
+    }
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.message().fmt(f)
+    }
+}
+
+impl From<String> for ConnectError {
+    fn from(message: String) -> Self {
+        Self::Failed(message)
+    }
+}
+
+impl From<ConnectError> for String {
+    fn from(error: ConnectError) -> Self {
+        error.to_string()
+    }
+}
+
+/// russh event handler: verifies the host key and serves agent forwarding.
+pub(super) struct Client {
+    host: String,
+    port: u16,
+    forward_agent: bool,
+    /// Why the host key was rejected, since russh only reports a generic error.
+    host_key_error: Arc<Mutex<Option<ConnectError>>>,
+}
+
+impl client::Handler for Client {
+    type Error = russh::Error;
+
+    async fn check_server_key(
+        &mut self,
+        server_public_key: &PublicKeyOrCertificate,
+    ) -> Result<bool, Self::Error> {
+        let PublicKeyOrCertificate::PublicKey { key, .. } = server_public_key else {
+            *self.host_key_error.lock().unwrap() = Some(ConnectError::Failed(messages::text(
+                "error.host_certificate_unsupported",
+                &[("host", &self.host)],
+            )));
+            return Ok(false);
+        };
+        *self.host_key_error.lock().unwrap() =
+            host_keys::verify(&self.host, self.port, key).map(ConnectError::UnverifiedHostKey);
+        Ok(self.host_key_error.lock().unwrap().is_none())
+    }
+
+    async fn server_channel_open_agent_forward(
+        &mut self,
+        channel: Channel<Msg>,
+        reply: ChannelOpenHandle,
+        _session: &mut client::Session,
+    ) -> Result<(), Self::Error> {
+        if !self.forward_agent {
+            return Ok(()); // Dropping `reply` rejects the channel
+        }
+        reply.accept().await;
+        tokio::spawn(async move {
+            if let Ok(mut agent) = agent::connect_stream().await {
+                let mut channel = channel.into_stream();
+                let _ = tokio::io::copy_bidirectional(&mut channel, &mut agent).await;
+            }
+        });
+        Ok(())
+    }
+}
+
+/// A live, authenticated connection to one server. If the connection drops,
+/// the next channel opened on it connects and logs in again first, with the
+/// same methods, so the commands and transfers after a blip go on over the
+/// new one without anything being asked of the user again.
+pub struct Session {
+    /// Where it connects, kept for connecting again.
+    target: SshTarget,
+    link: tokio::sync::Mutex<Arc<Link>>,
+    /// The server's shell and tools, probed before the first command.
+    platform: OnceCell<Platform>,
+}
+
+/// One connection of a session, and the bastion it's tunnelled through.
+struct Link {
+    handle: Handle<Client>,
+    jump: Option<Session>,
+}
+
+impl Session {
+    /// Connect, verify the host key, and authenticate with the target's methods
+    /// in order, any questions the server asks put to the user with `ask`.
+    pub async fn connect(target: &SshTarget, ask: Ask<'_>) -> Result<Self, ConnectError> {
+        let link = Link::connect(target, ask).await?;
+        Ok(Self {
+            target: target.clone(),
+            link: tokio::sync::Mutex::new(Arc::new(link)),
+            platform: OnceCell::new(),
+        })
+    }
+
+    /// What [`pool`](super::pool) tells its connections apart by.
+    pub(super) fn key(&self) -> String {
+        super::pool::key(&self.target)
+    }
+
+    /// Whether the connection has dropped, and won't be back until a channel
+    /// is next opened.
+    pub async fn is_closed(&self) -> bool {
+        self.link.lock().await.handle.is_closed()
+    }
+
+    /// The current connection, a new one if it had dropped.
+    async fn link(&self) -> Result<Arc<Link>, String> {
+        let mut link = self.link.lock().await;
+        if link.handle.is_closed() {
+            tracing::warn!(host = %self.target.host, "the SSH connection dropped; reconnecting");
+            // No one is there to answer a server's questions now; keys and passwords do without.
+            *link = Arc::new(Link::connect(&self.target, &|_| false).await?);
+            tracing::info!(host = %self.target.host, "reconnected");
+        }
+        Ok(link.clone())
+    }
+
+    /// Open a channel with `open`, connecting again and opening it once more
+    /// if the connection turns out to have dropped.
+    async fn open<F, Fut>(&self, open: F) -> Result<Channel<Msg>, String>
+    where
+        F: Fn(Arc<Link>) -> Fut,
+        Fut: Future<Output = Result<Channel<Msg>, String>>,
+    {
+        let link = self.link().await?;
+        match open(link.clone()).await {
+            Err(_) if link.handle.is_closed() => open(self.link().await?).await,
+            result => result,
+        }
+    }
+
+    /// The server's shell and tools. A server that can't be probed is assumed
+    /// to have a POSIX shell and GNU's tools.
+    pub async fn platform(&self) -> &Platform {
+        self.platform
+            .get_or_init(|| async {
+                let probed = async {
+                    let mut channel = self.open_channel(PROBE).await?;
+                    let mut stdout = Vec::new();
+                    while let Some(msg) = channel.wait().await {
+                        if let ChannelMsg::Data { data } = msg {
+                            stdout.extend_from_slice(&data);
+                        }
+                    }
+                    Ok::<_, String>(Platform::parse(&String::from_utf8_lossy(&stdout)))
+                };
+                match probed.await {
+                    Ok(platform) => {
+                        tracing::info!(
+                            shell = %platform.shell,
+                            os = %platform.os,
+                            userland = ?platform.userland,
+                            perl = platform.perl,
+                            "probed the server"
+                        );
+                        platform
+                    }
+                    Err(e) => {
+                        tracing::warn!("couldn't probe the server's shell: {e}");
+                        Platform::default()
+                    }
+                }
+            })
+            .await
+    }
+
+    /// Start `command` on a new channel and hand back the channel for streaming.
+    /// It runs under `/bin/sh` whatever the user's login shell is.
+    pub async fn open_exec(&self, command: &str) -> Result<Channel<Msg>, String> {
+        let line = self.platform().await.command_line(command);
+        let channel = self.open_channel(&line).await?;
+        tracing::info!(target: AUDIT_TARGET, command, "remote command started");
+        Ok(channel)
+    }
+
+    /// Start `line` on a new channel, as the login shell takes it.
+    async fn open_channel(&self, line: &str) -> Result<Channel<Msg>, String> {
+        let forward_agent = self.target.forward_agent;
+        self.open(|link| async move {
+            let channel =
+                link.handle.channel_open_session().await.map_err(|e| {
+                    messages::text("error.failed.open_ssh_channel", &[("error", &e)])
+                })?;
+            if forward_agent {
+                channel.agent_forward(false).await.map_err(|e| {
+                    messages::text("error.failed.request_agent_forwarding", &[("error", &e)])
+                })?;
+            }
+            // Names that aren't UTF-8 go to the server as the bytes they were listed with.
+            channel
+                .exec(true, byte_path::to_bytes(line).into_owned())
+                .await
+                .map_err(|e| {
+                    messages::text("error.failed.start_remote_command", &[("error", &e)])
+                })?;
+            Ok(channel)
+        })
+        .await
+    }
+
+    /// Open a channel to `host:port` as the server sees it, for a connection
+    /// from `origin` on this machine.
+    pub async fn open_direct_tcpip(
+        &self,
+        host: &str,
+        port: u16,
+        origin: SocketAddr,
+    ) -> Result<Channel<Msg>, String> {
+        self.open(|link| async move {
+            link.handle
+                .channel_open_direct_tcpip(
+                    host,
+                    port.into(),
+                    origin.ip().to_string(),
+                    origin.port().into(),
+                )
+                .await
+                .map_err(|e| {
+                    messages::text(
+                        "error.server_wouldnt_forward_host_port",
+                        &[("host", &host), ("port", &port), ("error", &e)],
+                    )
+                })
+        })
+        .await
+    }
+
+    /// Start a subsystem (e.g. `sftp`) on a new channel.
+    pub async fn open_subsystem(&self, name: &str) -> Result<Channel<Msg>, String> {
+        let channel = self
+            .open(|link| async move {
+                let channel = link.handle.channel_open_session().await.map_err(|e| {
+                    messages::text("error.failed.open_ssh_channel", &[("error", &e)])
+                })?;
+                channel.request_subsystem(true, name).await.map_err(|e| {
+                    messages::text(
+                        "error.failed.start_name_subsystem",
+                        &[("name", &name), ("error", &e)],
+                    )
+                })?;
+                Ok(channel)
+            })
+            .await?;
+        tracing::info!(target: AUDIT_TARGET, subsystem = name, "remote subsystem started");
+        Ok(channel)
+    }
+
+    /// Run `command` to completion and collect its output.
+    pub async fn exec(&self, command: &str) -> Result<ExecOutput, String> {
+        let mut channel = self.open_exec(command).await?;
+        let mut output = ExecOutput::default();
+        while let Some(msg) = channel.wait().await {
+            match msg {
+                ChannelMsg::Data { data } => output.stdout.extend_from_slice(&data),
+                // Extended data type 1 is stderr
+                ChannelMsg::ExtendedData { data, ext: 1 } => {
+                    stderr::push(&mut output.stderr, &data)
+                }
+                ChannelMsg::ExitStatus { exit_status } => output.exit_status = Some(exit_status),
+                _ => {}
+            }
+        }
+        audit_exit(command, output.exit_status);
+        Ok(output)
+    }
+
+    /// Run `command` to completion with everything `input` yields as its stdin,
+    /// collecting its output.
+    pub async fn exec_with_reader(
+        &self,
+        command: &str,
+        input: &mut (impl AsyncRead + Unpin),
+    ) -> Result<ExecOutput, String> {
+        let mut channel = self.open_exec(command).await?;
+        let mut writer = channel.make_writer();
+        let send = async {
+            tokio::io::copy(input, &mut writer).await?;
+            // Shutting the writer down sends EOF.
+            writer.shutdown().await
+        };
+        let receive = async {
+            let mut output = ExecOutput::default();
+            while let Some(msg) = channel.wait().await {
+                match msg {
+                    ChannelMsg::Data { data } => output.stdout.extend_from_slice(&data),
+                    // Extended data type 1 is stderr
+                    ChannelMsg::ExtendedData { data, ext: 1 } => {
+                        stderr::push(&mut output.stderr, &data)
+                    }
+                    ChannelMsg::ExitStatus { exit_status } => {
+                        output.exit_status = Some(exit_status)
+                    }
+                    _ => {}
+                }
+            }
+            output
+        };
+        let (sent, output) = tokio::join!(send, receive);
+        audit_exit(command, output.exit_status);
+        // A command that exits early breaks the write; its own status says why.
+        if output.success() {
+            sent.map_err(|e| {
+                messages::text(
+                    "error.failed.send_input_command",
+                    &[("command", &command), ("error", &e)],
+                )
+            })?;
+        }
+        Ok(output)
+    }
+
+    /// Politely end the connection, and then the one it was tunnelled through.
+    pub async fn close(&self) {
+        let link = self.link.lock().await.clone();
+        let _ = link
+            .handle
+            .disconnect(Disconnect::ByApplication, "", "en")
+            .await;
+        if let Some(jump) = &link.jump {
+            Box::pin(jump.close()).await;
+        }
+    }
+}
+
+impl Link {
+    async fn connect(target: &SshTarget, ask: Ask<'_>) -> Result<Self, ConnectError> {
+        let mut preferred = Preferred::default();
+        if target.compress {
+            preferred.compression = Cow::Borrowed(COMPRESSED);
+        }
+        let config = Arc::new(client::Config {
+            // So long-running remote commands (a big `wp db export`) don't let
+            // NAT or firewall state expire, and a dead server is noticed.
+            keepalive_interval: target.keepalive_interval,
+            keepalive_max: target.keepalive_max,
+            preferred,
+            ..Default::default()
+        });
+        let host_key_error = Arc::new(Mutex::new(None));
+        let handler = Client {
+            host: target.host.clone(),
+            port: target.port,
+            forward_agent: target.forward_agent,
+            host_key_error: host_key_error.clone(),
+        };
+
+        // The jump session has to stay up for as long as the tunnel through it.
+        let jump = match &target.jump {
+            Some(jump) => Some(Box::pin(Session::connect(jump, ask)).await?),
+            None => None,
+        };
+        let connecting = async {
+            match &jump {
+                Some(jump) => {
+                    let channel = Box::pin(jump.link())
+                        .await
+                        .map_err(|e| russh::Error::IO(std::io::Error::other(e)))?
+                        .handle
+                        .channel_open_direct_tcpip(
+                            target.host.as_str(),
+                            target.port.into(),
+                            "127.0.0.1",
+                            0,
+                        )
+                        .await?;
+                    client::connect_stream(config, channel.into_stream(), handler).await
+                }
+                None => {
+                    let stream = proxy::connect(
+                        target.proxy.as_ref(),
+                        &target.host,
+                        target.port,
+                        target.family,
+                    )
+                    .await
+                    .map_err(|e| russh::Error::IO(std::io::Error::other(e)))?;
+                    client::connect_stream(config, stream, handler).await
+                }
+            }
+        };
+        let via = match (&target.jump, &target.proxy) {
+            (Some(jump), _) => format!(" via {}", jump.host),
+            (None, Some(route)) if !route.proxy.bypasses(&target.host) => {
+                format!(" via the proxy {}", route.proxy.host)
+            }
+            _ => String::new(),
+        };
+        let connected = tokio::time::timeout(target.connect_timeout, connecting)
+            .await
+            .map_err(|_| {
+                messages::text(
+                    "error.timed_out_connecting_host_port_via",
+                    &[
+                        ("host", &target.host),
+                        ("port", &target.port),
+                        ("via", &via),
+                    ],
+                )
+            })?;
+        let mut handle = connected.map_err(|e| {
+            host_key_error.lock().unwrap().take().unwrap_or_else(|| {
+                ConnectError::Failed(messages::text(
+                    "error.failed.connect_host_port_via",
+                    &[
+                        ("host", &target.host),
+                        ("port", &target.port),
+                        ("via", &via),
+                        ("error", &e),
+                    ],
+                ))
+            })
+        })?;
+
+        authenticate(&mut handle, target, ask)
+            .await
+            .map_err(ConnectError::AuthFailed)?;
+        Ok(Self { handle, jump })
+    }
+}
+
+/// How one way of logging in went.
+pub(super) enum Login {
+    Accepted,
+    /// Accepted, but the server wants another as well, as a key and then a code.
+    Partial,
+    Refused,
+}
+
+impl Login {
+    fn of(result: AuthResult) -> Self {
+        match result {
+            AuthResult::Success => Self::Accepted,
+            AuthResult::Failure {
+                partial_success, ..
+            } => Self::failed(partial_success),
+        }
+    }
+
+    pub(super) fn failed(partial_success: bool) -> Self {
+        if partial_success {
+            Self::Partial
+        } else {
+            Self::Refused
+        }
+    }
+}
+
+async fn authenticate(
+    handle: &mut Handle<Client>,
+    target: &SshTarget,
+    ask: Ask<'_>,
+) -> Result<(), String> {
+    let mut tried = Vec::new();
+    let mut methods: Vec<Cow<Auth>> = target.auth.iter().map(Cow::Borrowed).collect();
+    let mut at = 0;
+    while let Some(method) = methods.get(at) {
+        at += 1;
+        let (name, result) = match method.as_ref() {
+            Auth::Agent => (
+                "SSH agent".to_string(),
+                auth_agent(handle, &target.user).await,
+            ),
+            Auth::Key(path) => (
+                format!("key {}", path.display()),
+                auth_key(handle, &target.user, path).await,
+            ),
+            Auth::Password(password) => (
+                "password".to_string(),
+                handle
+                    .authenticate_password(&target.user, password)
+                    .await
+                    .map(Login::of)
+                    .map_err(|e| e.to_string()),
+            ),
+            Auth::KeyboardInteractive { password } => (
+                "keyboard-interactive".to_string(),
+                prompt::authenticate(handle, target, password.as_deref(), ask).await,
+            ),
+        };
+        match result {
+            Ok(Login::Accepted) => return Ok(()),
+            Ok(Login::Partial) => {
+                tried.push(format!("{name} (accepted, but the server wants more)"));
+                // What a second factor is usually asked with, if it isn't to be tried anyway.
+                let asks = |method: &Cow<Auth>| {
+                    matches!(method.as_ref(), Auth::KeyboardInteractive { .. })
+                };
+                if !methods[at..].iter().any(asks) {
+                    methods.push(Cow::Owned(Auth::KeyboardInteractive { password: None }));
+                }
+            }
+            Ok(Login::Refused) => tried.push(name),
+            Err(e) => tried.push(format!("{name} ({e})")),
+        }
+    }
+    if tried.is_empty() {
+        return Err(messages::text(
+            "error.no_ssh_authentication_methods_are_available",
+            &[("user", &target.user), ("host", &target.host)],
+        ));
+    }
+    Err(messages::text(
+        "error.authentication_failed_user_host_tried",
+        &[
+            ("user", &target.user),
+            ("host", &target.host),
+            ("tried", &tried.join(", ")),
+        ],
+    ))
+}
+
+async fn auth_agent(handle: &mut Handle<Client>, user: &str) -> Result<Login, String> {
+    let mut agent = agent::connect().await?;
+    let identities = agent
+        .request_identities()
+        .await
+        .map_err(|e| messages::text("error.failed.list_agent_keys", &[("error", &e)]))?;
+    for identity in identities {
+        let AgentIdentity::PublicKey { key, .. } = identity else {
+            continue; // Certificates aren't supported
+        };
+        let hash_alg = rsa_hash(handle, key.algorithm().is_rsa()).await?;
+        let result = handle
+            .authenticate_publickey_with(user, key, hash_alg, &mut agent)
+            .await
+            .map_err(|e| e.to_string())?;
+        match Login::of(result) {
+            Login::Refused => {}
+            login => return Ok(login),
+        }
+    }
+    Ok(Login::Refused)
+}
+
+async fn auth_key(handle: &mut Handle<Client>, user: &str, path: &Path) -> Result<Login, String> {
+    let key = passphrase::load(path)?;
+    let hash_alg = rsa_hash(handle, key.algorithm().is_rsa()).await?;
+    handle
+        .authenticate_publickey(user, PrivateKeyWithHashAlg::new(Arc::new(key), hash_alg))
+        .await
+        .map(Login::of)
+        .map_err(|e| e.to_string())
+}
+
+/// The strongest RSA signature hash the server accepts, for RSA keys only.
+async fn rsa_hash(handle: &Handle<Client>, is_rsa: bool) -> Result<Option<keys::HashAlg>, String> {
+    if !is_rsa {
+        return Ok(None);
+    }
+    handle
+        .best_supported_rsa_hash()
+        .await
+        .map(Option::flatten)
+        .map_err(|e| e.to_string())
+}
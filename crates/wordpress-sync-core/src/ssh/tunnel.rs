@@ -0,0 +1,79 @@
+//! Local port forwards over a managed [`Session`], like `ssh -L`: a loopback
+//! port on this machine whose connections are carried to a host and port as
+//! the server sees them, such as its MySQL server.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+use super::Session;
+
+use crate::messages;
+
+/// A listening forward, closed when dropped.
+pub struct Tunnel {
+    addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl Tunnel {
+    /// Listen on a free loopback port and forward each connection to
+    /// `host:port` from the server.
+    pub async fn open(session: Arc<Session>, host: &str, port: u16) -> Result<Self, String> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.map_err(|e| {
+            messages::text(
+                "error.failed.open_tunnel_host_port",
+                &[("host", &host), ("port", &port), ("error", &e)],
+            )
+        })?;
+        let addr = listener.local_addr().map_err(|e| {
+            messages::text(
+                "error.failed.open_tunnel_host_port",
+                &[("host", &host), ("port", &port), ("error", &e)],
+            )
+        })?;
+        let host = host.to_string();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, origin)) = listener.accept().await {
+                let (session, host) = (session.clone(), host.clone());
+                tokio::spawn(async move {
+                    if let Err(e) = forward(stream, origin, &session, &host, port).await {
+                        tracing::warn!(%host, port, "tunnelled connection failed: {e}");
+                    }
+                });
+            }
+        });
+        tracing::debug!(%addr, "tunnel open");
+        Ok(Self { addr, task })
+    }
+
+    /// The loopback address to connect to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for Tunnel {
+    fn drop(&mut self) {
+        // Connections already carried finish on their own.
+        self.task.abort();
+    }
+}
+
+/// Carry one local connection to `host:port` on a channel of its own.
+async fn forward(
+    mut stream: TcpStream,
+    origin: SocketAddr,
+    session: &Session,
+    host: &str,
+    port: u16,
+) -> Result<(), String> {
+    let channel = session.open_direct_tcpip(host, port, origin).await?;
+    let mut remote = channel.into_stream();
+    tokio::io::copy_bidirectional(&mut stream, &mut remote)
+        .await
+        .map(drop)
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,34 @@
+//! Collecting what commands write to stderr in bounded memory. A wp-cli
+//! import that warns about each of millions of rows would otherwise hold every
+//! warning; only the end is kept, where the reason a command failed is.
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// How many bytes of a command's stderr are kept.
+pub(crate) const KEPT: usize = 64 * 1024;
+
+/// Add `data` to `stderr`, dropping its start past [`KEPT`] bytes.
+pub(crate) fn push(stderr: &mut Vec<u8>, data: &[u8]) {
+    if data.len() >= KEPT {
+        stderr.clear();
+        stderr.extend_from_slice(&data[data.len() - KEPT..]);
+        return;
+    }
+    stderr.extend_from_slice(data);
+    if stderr.len() > KEPT {
+        stderr.drain(..stderr.len() - KEPT);
+    }
+}
+
+/// Read `reader` to its end, keeping the last [`KEPT`] bytes.
+pub(crate) async fn read(reader: &mut (impl AsyncRead + Unpin)) -> std::io::Result<Vec<u8>> {
+    let mut stderr = Vec::new();
+    let mut buf = [0u8; 8192];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(stderr);
+        }
+        push(&mut stderr, &buf[..n]);
+    }
+}
@@ -0,0 +1,120 @@
+//! Support bundles: one zip file a user can attach to a bug report, holding
+//! what's needed to see what went wrong without asking them for it piece by
+//! piece.
+//!
+//! A bundle has `about.json` (the versions and platform), `doctor.json` (the
+//! [`doctor`](crate::sync::doctor) checklist), every profile under
+//! `profiles/`, the most recent job logs under `logs/jobs/`, and the latest
+//! `app.log`. Profiles and logs pass through [`redact`] first.
+
+mod redact;
+mod zip;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use chrono::Utc;
+use serde_json::json;
+
+use crate::messages;
+use crate::paths;
+use crate::profile;
+use crate::sync;
+
+use self::zip::ZipWriter;
+
+/// How many job logs, newest first, go into a bundle.
+const JOB_LOGS: usize = 20;
+
+/// Write a support bundle to `path`. `app_version` is that of the app or
+/// CLI making it, which may differ from this crate's.
+pub async fn export_bundle(path: &Path, app_version: &str) -> Result<(), String> {
+    let mut zip = ZipWriter::default();
+
+    let about = json!({
+        "app_version": app_version,
+        "core_version": env!("CARGO_PKG_VERSION"),
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+        "created_at": Utc::now(),
+    });
+    zip.add("about.json", &pretty(&about)?)?;
+
+    let doctor = sync::doctor().await;
+    zip.add("doctor.json", &pretty(&doctor)?)?;
+
+    for profile in profile::list()? {
+        let mut value = serde_json::to_value(&profile).map_err(|e| e.to_string())?;
+        redact::profile(&mut value);
+        zip.add(&format!("profiles/{}.json", profile.id), &pretty(&value)?)?;
+    }
+
+    let logs = paths::logs_dir()?;
+    for log in newest(&logs.join("jobs"), |_| true)
+        .into_iter()
+        .take(JOB_LOGS)
+    {
+        add_log(&mut zip, "logs/jobs", &log)?;
+    }
+    let app_log = newest(&logs, |name| name.starts_with("app."));
+    if let Some(log) = app_log.first() {
+        add_log(&mut zip, "logs", log)?;
+    }
+
+    let bytes = zip.finish()?;
+    let tmp = path.with_extension("zip.tmp");
+    fs::write(&tmp, bytes).map_err(|e| {
+        messages::text(
+            "error.failed.write_path",
+            &[("path", &tmp.display()), ("error", &e)],
+        )
+    })?;
+    fs::rename(&tmp, path).map_err(|e| {
+        messages::text(
+            "error.failed.write_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })
+}
+
+fn pretty(value: &impl serde::Serialize) -> Result<Vec<u8>, String> {
+    serde_json::to_vec_pretty(value).map_err(|e| e.to_string())
+}
+
+/// The `.log` files in `dir` whose names pass `wanted`, newest first. A
+/// directory that isn't there has none.
+fn newest(dir: &Path, wanted: impl Fn(&str) -> bool) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut logs: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.ends_with(".log") && wanted(&name)
+        })
+        .filter_map(|entry| {
+            let modified = entry.metadata().and_then(|meta| meta.modified()).ok()?;
+            Some((modified, entry.path()))
+        })
+        .collect();
+    logs.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    logs.into_iter().map(|(_, path)| path).collect()
+}
+
+fn add_log(zip: &mut ZipWriter, dir: &str, log: &Path) -> Result<(), String> {
+    let Some(name) = log.file_name().and_then(|name| name.to_str()) else {
+        return Ok(());
+    };
+    let content = match fs::read(log) {
+        Ok(content) => content,
+        Err(e) => {
+            tracing::warn!(error = %e, path = %log.display(), "Failed to read a log for the support bundle");
+            return Ok(());
+        }
+    };
+    let content = redact::text(&String::from_utf8_lossy(&content));
+    zip.add(&format!("{dir}/{name}"), content.as_bytes())
+}
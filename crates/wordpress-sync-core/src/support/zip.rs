@@ -0,0 +1,114 @@
+//! Just enough of the zip format to write an archive of a few small files.
+
+use std::io::Write;
+
+use chrono::{Datelike, Local, Timelike};
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+use crate::messages;
+
+/// Compression method 8, deflate.
+const DEFLATE: u16 = 8;
+
+/// The version of the format the entries need: 2.0, for deflate.
+const VERSION: u16 = 20;
+
+/// Flag bit 11: names are UTF-8.
+const UTF8_NAMES: u16 = 1 << 11;
+
+/// A zip archive, built in memory.
+#[derive(Default)]
+pub(super) struct ZipWriter {
+    out: Vec<u8>,
+    central: Vec<u8>,
+    entries: u16,
+}
+
+impl ZipWriter {
+    /// Add a file named `name`, which may contain `/` for directories.
+    pub(super) fn add(&mut self, name: &str, data: &[u8]) -> Result<(), String> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).map_err(|e| {
+            messages::text(
+                "error.failed.compress_name",
+                &[("name", &name), ("error", &e)],
+            )
+        })?;
+        let compressed = encoder.finish().map_err(|e| {
+            messages::text(
+                "error.failed.compress_name",
+                &[("name", &name), ("error", &e)],
+            )
+        })?;
+        let too_big = || messages::text("error.name_too_big_for_archive", &[("name", &name)]);
+        let size = u32::try_from(data.len()).map_err(|_| too_big())?;
+        let compressed_size = u32::try_from(compressed.len()).map_err(|_| too_big())?;
+        let offset = u32::try_from(self.out.len()).map_err(|_| too_big())?;
+        let name_len = u16::try_from(name.len())
+            .map_err(|_| messages::text("error.name_too_long", &[("name", &name)]))?;
+        let crc = crc32fast::hash(data);
+        let (time, date) = dos_now();
+
+        // Local file header.
+        self.out.extend(0x0403_4b50u32.to_le_bytes());
+        for field in [VERSION, UTF8_NAMES, DEFLATE, time, date] {
+            self.out.extend(field.to_le_bytes());
+        }
+        for field in [crc, compressed_size, size] {
+            self.out.extend(field.to_le_bytes());
+        }
+        self.out.extend(name_len.to_le_bytes());
+        self.out.extend(0u16.to_le_bytes());
+        self.out.extend(name.as_bytes());
+        self.out.extend(&compressed);
+
+        // Its central directory record.
+        self.central.extend(0x0201_4b50u32.to_le_bytes());
+        for field in [VERSION, VERSION, UTF8_NAMES, DEFLATE, time, date] {
+            self.central.extend(field.to_le_bytes());
+        }
+        for field in [crc, compressed_size, size] {
+            self.central.extend(field.to_le_bytes());
+        }
+        // Name length; no extra field, comment, or disk number; no attributes.
+        for field in [name_len, 0, 0, 0, 0] {
+            self.central.extend(field.to_le_bytes());
+        }
+        self.central.extend(0u32.to_le_bytes());
+        self.central.extend(offset.to_le_bytes());
+        self.central.extend(name.as_bytes());
+
+        self.entries = self
+            .entries
+            .checked_add(1)
+            .ok_or_else(|| messages::text("error.too_many_files_for_archive", &[]))?;
+        Ok(())
+    }
+
+    /// The finished archive.
+    pub(super) fn finish(mut self) -> Result<Vec<u8>, String> {
+        let too_big = || messages::text("error.archive_too_big", &[]);
+        let central_offset = u32::try_from(self.out.len()).map_err(|_| too_big())?;
+        let central_size = u32::try_from(self.central.len()).map_err(|_| too_big())?;
+        self.out.append(&mut self.central);
+        // End of central directory: one disk, no comment.
+        self.out.extend(0x0605_4b50u32.to_le_bytes());
+        for field in [0, 0, self.entries, self.entries] {
+            self.out.extend(u16::to_le_bytes(field));
+        }
+        self.out.extend(central_size.to_le_bytes());
+        self.out.extend(central_offset.to_le_bytes());
+        self.out.extend(0u16.to_le_bytes());
+        Ok(self.out)
+    }
+}
+
+/// The local time in MS-DOS form, as zip stores it: `(time, date)`.
+fn dos_now() -> (u16, u16) {
+    let now = Local::now();
+    let time = (now.hour() << 11) | (now.minute() << 5) | (now.second() / 2);
+    let years = (now.year() - 1980).clamp(0, 127) as u32;
+    let date = (years << 9) | (now.month() << 5) | now.day();
+    (time as u16, date as u16)
+}
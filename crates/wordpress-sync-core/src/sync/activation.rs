@@ -0,0 +1,105 @@
+//! Switching the destination's plugins on or off once a sync has imported a
+//! database into it, as the profile's post-sync settings say for that side:
+//! a site pulled to this machine can have its mail, analytics, and caching
+//! plugins deactivated before it emails a real customer, and a staging server
+//! can come up behind a maintenance plugin.
+//!
+//! Only plugins in the wrong state are touched, so a rule naming a plugin the
+//! site doesn't have to deactivate is quietly satisfied.
+
+use super::endpoint::Endpoint;
+use super::extensions::{self, ExtensionKind};
+use super::{Direction, Job};
+use crate::messages::Message;
+use crate::profile::PluginActivation;
+
+impl Job<'_> {
+    /// Apply the profile's plugin rules for the destination. A plugin left in
+    /// the wrong state doesn't undo the sync, so failures are warnings.
+    pub(super) async fn switch_plugins(&self, dest: &Endpoint<'_>) {
+        let settings = &self.profile.post_sync;
+        let rules = match self.direction {
+            Direction::Push => &settings.remote_plugins,
+            Direction::Pull => &settings.local_plugins,
+        };
+        // The activation state is kept in the database the sync just replaced.
+        if rules.is_empty() || !self.syncs_database() {
+            return;
+        }
+        if !self.has_wp_cli(dest).await.unwrap_or(true) {
+            self.warn(Message::new(
+                "warning.destinations_plugins_were_not_switched_off",
+                &[],
+            ));
+            return;
+        }
+        if let Err(e) = self.apply_plugin_rules(dest, rules).await {
+            self.warn(Message::new(
+                "warning.destinations_plugins_were_not_all_switched",
+                &[("error", &e)],
+            ));
+        }
+    }
+
+    async fn apply_plugin_rules(
+        &self,
+        dest: &Endpoint<'_>,
+        rules: &PluginActivation,
+    ) -> Result<(), String> {
+        let installed = extensions::list(dest, ExtensionKind::Plugin).await?;
+        let status = |name: &str| {
+            installed
+                .iter()
+                .find(|plugin| plugin.name == name)
+                .map(|plugin| plugin.status.as_str())
+        };
+        let in_state = |names: &[String], wanted: &str| -> Vec<String> {
+            names
+                .iter()
+                .filter(|name| status(name) == Some(wanted))
+                .cloned()
+                .collect()
+        };
+        let missing: Vec<&str> = rules
+            .activate
+            .iter()
+            .filter(|name| status(name).is_none())
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            self.warn(Message::new(
+                "warning.plugins_activate_destination_arent_installed_there",
+                &[("missing", &missing.join(", "))],
+            ));
+        }
+        // A network-activated plugin is deactivated for the whole network.
+        let steps = [
+            (
+                &["plugin", "deactivate"][..],
+                in_state(&rules.deactivate, "active"),
+            ),
+            (
+                &["plugin", "deactivate", "--network"][..],
+                in_state(&rules.deactivate, "active-network"),
+            ),
+            (
+                &["plugin", "activate"][..],
+                in_state(&rules.activate, "inactive"),
+            ),
+        ];
+        for (command, plugins) in steps {
+            if plugins.is_empty() {
+                continue;
+            }
+            let mut args: Vec<&str> = command.to_vec();
+            args.extend(plugins.iter().map(String::as_str));
+            dest.wp(&args).await?;
+            tracing::info!(
+                ?plugins,
+                "ran `wp {}` on the destination",
+                command.join(" ")
+            );
+        }
+        Ok(())
+    }
+}
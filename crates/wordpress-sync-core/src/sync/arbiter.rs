@@ -0,0 +1,128 @@
+//! Sharing this machine between jobs that run at once, as when the queue
+//! runs several profiles' syncs side by side.
+//!
+//! The app-wide bandwidth limit, if the `total_kib_per_sec` setting gives
+//! one, is split between the running jobs: each gets an equal share, or its
+//! own limit if that's lower, with what it leaves going to the others. Shares
+//! are worked out again whenever a job starts or finishes, and rsync picks a
+//! new one up the way it does a changed limit.
+//!
+//! SSH sessions are capped too, at the `max_ssh_sessions` setting: a
+//! connection past the cap waits for another job to close one, in its
+//! connect phase, where it can still be cancelled. Sessions parked for reuse
+//! between jobs don't count.
+
+use std::sync::{Arc, Mutex, Weak};
+
+use tokio::sync::Notify;
+
+use super::Throttle;
+use crate::settings;
+
+/// The throttles of the jobs now running.
+static THROTTLES: Mutex<Vec<Weak<Throttle>>> = Mutex::new(Vec::new());
+
+/// How many SSH sessions jobs have open.
+static SESSIONS: Mutex<usize> = Mutex::new(0);
+
+/// Woken when a session closes, or the cap changes.
+static SESSION_FREED: Notify = Notify::const_new();
+
+/// A job's place in the split of the app-wide bandwidth limit, given up when
+/// it's dropped.
+pub(super) struct BandwidthShare(Weak<Throttle>);
+
+impl Drop for BandwidthShare {
+    fn drop(&mut self) {
+        if let Some(throttle) = self.0.upgrade() {
+            throttle.set_share(None);
+        }
+        THROTTLES
+            .lock()
+            .unwrap()
+            .retain(|throttle| throttle.strong_count() > 0 && !throttle.ptr_eq(&self.0));
+        rebalance();
+    }
+}
+
+/// An open SSH session counted against the cap, until it's dropped.
+pub(super) struct SessionSlot(());
+
+impl Drop for SessionSlot {
+    fn drop(&mut self) {
+        let mut open = SESSIONS.lock().unwrap();
+        *open = open.saturating_sub(1);
+        drop(open);
+        SESSION_FREED.notify_waiters();
+    }
+}
+
+/// Hold the job with `throttle` to its share of the app-wide bandwidth limit
+/// for as long as the returned share is kept.
+pub(super) fn share_bandwidth(throttle: &Arc<Throttle>) -> BandwidthShare {
+    let weak = Arc::downgrade(throttle);
+    THROTTLES.lock().unwrap().push(weak.clone());
+    rebalance();
+    BandwidthShare(weak)
+}
+
+/// Wait for room under the cap on SSH sessions, and take it.
+pub(super) async fn session_slot() -> SessionSlot {
+    let mut waited = false;
+    loop {
+        let freed = SESSION_FREED.notified();
+        tokio::pin!(freed);
+        freed.as_mut().enable();
+        {
+            let mut open = SESSIONS.lock().unwrap();
+            if *open < settings::max_ssh_sessions() {
+                *open += 1;
+                return SessionSlot(());
+            }
+        }
+        if !waited {
+            tracing::info!("waiting for another job to close its SSH session");
+            waited = true;
+        }
+        freed.await;
+    }
+}
+
+/// Work the running jobs' bandwidth shares out again, and let a connection
+/// waiting for a session look again, as after their settings change.
+pub(crate) fn rebalance() {
+    let throttles: Vec<Arc<Throttle>> = {
+        let mut throttles = THROTTLES.lock().unwrap();
+        throttles.retain(|throttle| throttle.strong_count() > 0);
+        throttles.iter().filter_map(Weak::upgrade).collect()
+    };
+    let total = settings::total_bandwidth_limit();
+    let own: Vec<Option<u64>> = throttles
+        .iter()
+        .map(|throttle| throttle.own_limit())
+        .collect();
+    for (throttle, share) in throttles.iter().zip(shares(total, &own)) {
+        throttle.set_share(share);
+    }
+    SESSION_FREED.notify_waiters();
+}
+
+/// Each job's share of `total` KiB per second given their `own` limits: an
+/// equal split, where a job held below its part by its own limit leaves the
+/// rest to the others.
+fn shares(total: Option<u64>, own: &[Option<u64>]) -> Vec<Option<u64>> {
+    let Some(total) = total else {
+        return vec![None; own.len()];
+    };
+    let mut order: Vec<usize> = (0..own.len()).collect();
+    order.sort_by_key(|&i| own[i].unwrap_or(u64::MAX));
+    let mut shares = vec![None; own.len()];
+    let mut left = total;
+    for (placed, &i) in order.iter().enumerate() {
+        let even = (left / (own.len() - placed) as u64).max(1);
+        let share = own[i].map_or(even, |own| own.min(even));
+        left = left.saturating_sub(share);
+        shares[i] = Some(share);
+    }
+    shares
+}
@@ -0,0 +1,656 @@
+//! Encryption of the copies of a site's data the app keeps on this machine:
+//! the database and files of a pull's restore points, and the dumps a job
+//! stages here, the one a failed job keeps for its resume among them. They're
+//! encrypted as they're written and decrypted as they're read, so what's on
+//! disk never is plaintext.
+//!
+//! They are encrypted with AES-256-GCM under a key of the machine's own, made
+//! the first time one is and kept in the credential store, so another user or
+//! program that can read the app's directories can't read them. Files are
+//! encrypted in chunks, each authenticated with its position and whether it's
+//! the last, so one that's been cut short or reordered doesn't decrypt.
+//! Without the key nothing is written, and a file without the header isn't
+//! read, so one swapped in can't pass for the app's. Restore points saved
+//! before encryption are encrypted with [`seal_file`] before they're read.
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{ready, Context, Poll};
+
+use aes_gcm::aead::{AeadInOut, Generate, KeyInit, Nonce};
+use aes_gcm::{Aes256Gcm, Key};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+use crate::credentials::{platform_store, CredentialStore, ARTIFACT_KEY_SERVICE};
+use crate::messages;
+
+/// The key's account under [`ARTIFACT_KEY_SERVICE`].
+const KEY_ACCOUNT: &str = "local";
+
+/// How an encrypted file starts, before the nonce prefix.
+const MAGIC: &[u8; 8] = b"WPSYNC\x00\x01";
+
+/// The random start of each chunk's nonce, the rest being the chunk's number
+/// and whether it's the last.
+const PREFIX_LEN: usize = 7;
+
+/// Plaintext per chunk. Every chunk but the last is this long, so the last is
+/// always shorter, if need be empty.
+const CHUNK: usize = 64 * 1024;
+
+const TAG_LEN: usize = 16;
+
+/// Whether the machine's key can be had, so that what's written is encrypted.
+pub(crate) async fn available() -> Result<(), String> {
+    tokio::task::spawn_blocking(|| cipher().map(drop))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Whether `path` is encrypted.
+pub(crate) fn is_sealed(path: &Path) -> bool {
+    let mut magic = [0; MAGIC.len()];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok_and(|()| magic == *MAGIC)
+}
+
+/// Create `path` to be written encrypted, failing without the key.
+pub(crate) async fn create(path: &Path) -> Result<Writer, String> {
+    let create_failed = |e: io::Error| {
+        messages::text(
+            "error.failed.create_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    };
+    let cipher = tokio::task::spawn_blocking(cipher)
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| {
+            messages::text(
+                "error.failed.encrypt_path",
+                &[("path", &path.display()), ("error", &e)],
+            )
+        })?;
+    let file = create_file(path).map_err(create_failed)?;
+    let mut out = Vec::new();
+    let sealing = Sealing::new(cipher, &mut out);
+    Ok(Writer {
+        path: path.to_path_buf(),
+        file: tokio::fs::File::from_std(file),
+        sealing,
+        out,
+        sent: 0,
+        finished: false,
+    })
+}
+
+/// Open `path` to be read decrypted, failing if it isn't encrypted.
+pub(crate) async fn open(path: &Path) -> Result<Reader, String> {
+    let open_failed = |e: io::Error| {
+        messages::text(
+            "error.failed.open_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    };
+    let mut file = tokio::fs::File::open(path).await.map_err(open_failed)?;
+    let mut header = [0; MAGIC.len() + PREFIX_LEN];
+    let read = file.read_exact(&mut header).await;
+    if read.is_err() || header[..MAGIC.len()] != *MAGIC {
+        return Err(messages::text(
+            "error.path_isnt_encrypted",
+            &[("path", &path.display())],
+        ));
+    }
+    let cipher = tokio::task::spawn_blocking(cipher)
+        .await
+        .map_err(|e| e.to_string())?;
+    let cipher = cipher.map_err(|e| {
+        messages::text(
+            "error.failed.decrypt_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })?;
+    let opening = Opening::new(cipher, &header);
+    Ok(Reader {
+        file,
+        opening,
+        raw: vec![0; CHUNK],
+        plain: Vec::new(),
+        read: 0,
+        done: false,
+    })
+}
+
+/// Create `path` for [`archive`](super::backup)'s blocking writes, encrypted
+/// as [`create`] does.
+pub(crate) fn create_blocking(path: &Path) -> io::Result<SyncWriter<File>> {
+    let cipher = cipher().map_err(io::Error::other)?;
+    SyncWriter::new(create_file(path)?, cipher)
+}
+
+/// Open `path` for [`extract`](super::backup)'s blocking reads, decrypted as
+/// [`open`] does.
+pub(crate) fn open_blocking(path: &Path) -> io::Result<SyncReader<File>> {
+    if !is_sealed(path) {
+        return Err(io::Error::other(messages::text(
+            "error.path_isnt_encrypted",
+            &[("path", &path.display())],
+        )));
+    }
+    let cipher = cipher().map_err(io::Error::other)?;
+    let mut file = File::open(path)?;
+    let mut header = [0; MAGIC.len() + PREFIX_LEN];
+    file.read_exact(&mut header)?;
+    Ok(SyncReader {
+        inner: file,
+        opening: Opening::new(cipher, &header),
+        raw: vec![0; CHUNK],
+        plain: Vec::new(),
+        read: 0,
+        done: false,
+    })
+}
+
+/// Encrypt `path` in place, if it isn't already, for a file saved before
+/// the app encrypted what it keeps.
+pub(crate) fn seal_file(path: &Path) -> io::Result<()> {
+    if is_sealed(path) {
+        return Ok(());
+    }
+    let mut sealed = path.as_os_str().to_owned();
+    sealed.push(".sealing");
+    let sealed = PathBuf::from(sealed);
+    let mut writer = create_blocking(&sealed)?;
+    io::copy(&mut File::open(path)?, &mut writer)?;
+    writer.finish()?.sync_all()?;
+    std::fs::rename(&sealed, path)
+}
+
+/// Encrypts what's written, a chunk at a time.
+struct Sealing {
+    cipher: Aes256Gcm,
+    prefix: [u8; PREFIX_LEN],
+    counter: u32,
+    /// The plaintext of the chunk being filled.
+    chunk: Vec<u8>,
+}
+
+impl Sealing {
+    /// One that starts a file, putting its header in `out`.
+    fn new(cipher: Aes256Gcm, out: &mut Vec<u8>) -> Self {
+        let prefix = <[u8; PREFIX_LEN]>::generate();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&prefix);
+        Self {
+            cipher,
+            prefix,
+            counter: 0,
+            chunk: Vec::with_capacity(CHUNK + TAG_LEN),
+        }
+    }
+
+    /// Take in `data`, putting each chunk it fills in `out`, encrypted.
+    fn update(&mut self, mut data: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+        while !data.is_empty() {
+            let n = (CHUNK - self.chunk.len()).min(data.len());
+            self.chunk.extend_from_slice(&data[..n]);
+            data = &data[n..];
+            if self.chunk.len() == CHUNK {
+                self.seal(false, out)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Put the last chunk, shorter than the rest, in `out`.
+    fn finish(&mut self, out: &mut Vec<u8>) -> io::Result<()> {
+        self.seal(true, out)
+    }
+
+    fn seal(&mut self, last: bool, out: &mut Vec<u8>) -> io::Result<()> {
+        let nonce = nonce(&self.prefix, self.counter, last);
+        let tag = self
+            .cipher
+            .encrypt_inout_detached(&nonce, &[], self.chunk.as_mut_slice().into())
+            .map_err(|_| io::Error::other("encryption failed"))?;
+        out.extend_from_slice(&self.chunk);
+        out.extend_from_slice(&tag);
+        self.chunk.clear();
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .ok_or_else(|| io::Error::other("the file is too large to encrypt"))?;
+        Ok(())
+    }
+}
+
+/// Decrypts what's read, a chunk at a time.
+struct Opening {
+    cipher: Aes256Gcm,
+    prefix: [u8; PREFIX_LEN],
+    counter: u32,
+    /// The ciphertext of the chunk being read.
+    chunk: Vec<u8>,
+}
+
+impl Opening {
+    /// One for the file that starts with `header`.
+    fn new(cipher: Aes256Gcm, header: &[u8; MAGIC.len() + PREFIX_LEN]) -> Self {
+        Self {
+            cipher,
+            prefix: header[MAGIC.len()..].try_into().unwrap(),
+            counter: 0,
+            chunk: Vec::with_capacity(CHUNK + TAG_LEN),
+        }
+    }
+
+    /// Take in `data`, putting the plaintext of each chunk it fills in `out`.
+    /// A full one is never the last.
+    fn update(&mut self, mut data: &[u8], out: &mut Vec<u8>) -> io::Result<()> {
+        while !data.is_empty() {
+            let n = (CHUNK + TAG_LEN - self.chunk.len()).min(data.len());
+            self.chunk.extend_from_slice(&data[..n]);
+            data = &data[n..];
+            if self.chunk.len() == CHUNK + TAG_LEN {
+                self.open(false, out)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Put the last chunk's plaintext in `out`, at the end of the file.
+    fn finish(&mut self, out: &mut Vec<u8>) -> io::Result<()> {
+        self.open(true, out)
+    }
+
+    fn open(&mut self, last: bool, out: &mut Vec<u8>) -> io::Result<()> {
+        let n = self.chunk.len();
+        if n < TAG_LEN {
+            return Err(io::Error::other("it's been cut short"));
+        }
+        let (data, tag) = self.chunk.split_at_mut(n - TAG_LEN);
+        self.cipher
+            .decrypt_inout_detached(
+                &nonce(&self.prefix, self.counter, last),
+                &[],
+                data.into(),
+                (&*tag).try_into().unwrap(),
+            )
+            .map_err(|_| {
+                io::Error::other("it's corrupt, or was encrypted with another machine's key")
+            })?;
+        out.extend_from_slice(data);
+        self.chunk.clear();
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .ok_or_else(|| io::Error::other("it's too large"))?;
+        Ok(())
+    }
+}
+
+/// A file being written by [`create`]. It's only whole once
+/// [`finish`](Self::finish)ed.
+pub(crate) struct Writer {
+    path: PathBuf,
+    file: tokio::fs::File,
+    sealing: Sealing,
+    /// Ciphertext not yet written to the file, from `sent` on.
+    out: Vec<u8>,
+    sent: usize,
+    finished: bool,
+}
+
+impl Writer {
+    /// Write the end of the file.
+    pub(crate) async fn finish(&mut self) -> Result<(), String> {
+        self.shutdown().await.map_err(|e| {
+            messages::text(
+                "error.failed.write_path",
+                &[("path", &self.path.display()), ("error", &e)],
+            )
+        })
+    }
+
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.sent < self.out.len() {
+            let n = ready!(Pin::new(&mut self.file).poll_write(cx, &self.out[self.sent..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.sent += n;
+        }
+        self.out.clear();
+        self.sent = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for Writer {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        this.sealing.update(data, &mut this.out)?;
+        Poll::Ready(Ok(data.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.file).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.finished {
+            this.sealing.finish(&mut this.out)?;
+            this.finished = true;
+        }
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.file).poll_shutdown(cx)
+    }
+}
+
+/// A file being read by [`open`].
+pub(crate) struct Reader {
+    file: tokio::fs::File,
+    opening: Opening,
+    raw: Vec<u8>,
+    /// Plaintext not yet read, from `read` on.
+    plain: Vec<u8>,
+    read: usize,
+    done: bool,
+}
+
+impl AsyncRead for Reader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.read < this.plain.len() {
+                let n = (this.plain.len() - this.read).min(buf.remaining());
+                buf.put_slice(&this.plain[this.read..this.read + n]);
+                this.read += n;
+                return Poll::Ready(Ok(()));
+            }
+            if this.done {
+                return Poll::Ready(Ok(()));
+            }
+            let mut raw = ReadBuf::new(&mut this.raw);
+            ready!(Pin::new(&mut this.file).poll_read(cx, &mut raw))?;
+            this.plain.clear();
+            this.read = 0;
+            match raw.filled() {
+                [] => {
+                    this.opening.finish(&mut this.plain)?;
+                    this.done = true;
+                }
+                data => this.opening.update(data, &mut this.plain)?,
+            }
+        }
+    }
+}
+
+/// A file being written by [`create_blocking`]. It's only whole once
+/// [`finish`](Self::finish)ed.
+pub(crate) struct SyncWriter<W: Write> {
+    inner: W,
+    sealing: Sealing,
+    out: Vec<u8>,
+}
+
+impl<W: Write> SyncWriter<W> {
+    fn new(mut inner: W, cipher: Aes256Gcm) -> io::Result<Self> {
+        let mut out = Vec::new();
+        let sealing = Sealing::new(cipher, &mut out);
+        inner.write_all(&out)?;
+        out.clear();
+        Ok(Self {
+            inner,
+            sealing,
+            out,
+        })
+    }
+
+    /// Write the end of the file, returning what it was written to.
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        self.sealing.finish(&mut self.out)?;
+        self.inner.write_all(&self.out)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for SyncWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.sealing.update(data, &mut self.out)?;
+        self.inner.write_all(&self.out)?;
+        self.out.clear();
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A file being read by [`open_blocking`].
+pub(crate) struct SyncReader<R: Read> {
+    inner: R,
+    opening: Opening,
+    raw: Vec<u8>,
+    /// Plaintext not yet read, from `read` on.
+    plain: Vec<u8>,
+    read: usize,
+    done: bool,
+}
+
+impl<R: Read> Read for SyncReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.read < self.plain.len() {
+                let n = (self.plain.len() - self.read).min(buf.len());
+                buf[..n].copy_from_slice(&self.plain[self.read..self.read + n]);
+                self.read += n;
+                return Ok(n);
+            }
+            if self.done {
+                return Ok(0);
+            }
+            let n = self.inner.read(&mut self.raw)?;
+            self.plain.clear();
+            self.read = 0;
+            match n {
+                0 => {
+                    self.opening.finish(&mut self.plain)?;
+                    self.done = true;
+                }
+                n => self.opening.update(&self.raw[..n], &mut self.plain)?,
+            }
+        }
+    }
+}
+
+/// The nonce of chunk `counter` of a file.
+fn nonce(prefix: &[u8; PREFIX_LEN], counter: u32, last: bool) -> Nonce<Aes256Gcm> {
+    let mut nonce = Nonce::<Aes256Gcm>::default();
+    nonce[..PREFIX_LEN].copy_from_slice(prefix);
+    nonce[PREFIX_LEN..PREFIX_LEN + 4].copy_from_slice(&counter.to_be_bytes());
+    nonce[PREFIX_LEN + 4] = u8::from(last);
+    nonce
+}
+
+/// Create `path` for only this user to read.
+fn create_file(path: &Path) -> io::Result<File> {
+    let mut options = File::options();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+/// The machine's key, made and stored the first time it's needed.
+fn cipher() -> Result<Aes256Gcm, String> {
+    // Held while a key is made, so two jobs don't each store their own.
+    static KEY: Mutex<Option<Key<Aes256Gcm>>> = Mutex::new(None);
+    let mut cached = KEY.lock().unwrap();
+    if let Some(key) = cached.as_ref() {
+        return Ok(Aes256Gcm::new(key));
+    }
+    let store = platform_store();
+    let unavailable =
+        |e: String| messages::text("error.encryption_key_isnt_available", &[("error", &e)]);
+    let key = match store
+        .get(ARTIFACT_KEY_SERVICE, KEY_ACCOUNT)
+        .map_err(unavailable)?
+    {
+        Some(stored) => BASE64
+            .decode(stored.trim())
+            .ok()
+            .and_then(|key| Key::<Aes256Gcm>::try_from(key.as_slice()).ok())
+            .ok_or_else(|| messages::text("error.stored_encryption_key_invalid", &[]))?,
+        None => {
+            let key = Key::<Aes256Gcm>::generate();
+            store
+                .set(ARTIFACT_KEY_SERVICE, KEY_ACCOUNT, &BASE64.encode(key))
+                .map_err(unavailable)?;
+            tracing::info!("made the key for encrypting local backups");
+            key
+        }
+    };
+    *cached = Some(key);
+    Ok(Aes256Gcm::new(&key))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn seal(cipher: &Aes256Gcm, plain: &[u8]) -> Vec<u8> {
+        let mut writer = SyncWriter::new(Vec::new(), cipher.clone()).unwrap();
+        // Written unevenly, as a gzip encoder would.
+        for part in plain.chunks(1000) {
+            writer.write_all(part).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    fn open(cipher: &Aes256Gcm, sealed: &[u8]) -> io::Result<Vec<u8>> {
+        let mut inner = Cursor::new(sealed);
+        let mut header = [0; MAGIC.len() + PREFIX_LEN];
+        Read::read_exact(&mut inner, &mut header)?;
+        let mut reader = SyncReader {
+            inner,
+            opening: Opening::new(cipher.clone(), &header),
+            raw: vec![0; 5000],
+            plain: Vec::new(),
+            read: 0,
+            done: false,
+        };
+        let mut plain = Vec::new();
+        Read::read_to_end(&mut reader, &mut plain)?;
+        Ok(plain)
+    }
+
+    #[test]
+    fn round_trip() {
+        let cipher = Aes256Gcm::new(&Key::<Aes256Gcm>::generate());
+        for len in [0, 1, CHUNK - 1, CHUNK, CHUNK + 1, 2 * CHUNK, 3 * CHUNK + 7] {
+            let plain: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let sealed = seal(&cipher, &plain);
+            assert!(sealed.starts_with(MAGIC));
+            assert_eq!(
+                sealed.len(),
+                MAGIC.len() + PREFIX_LEN + len + (len / CHUNK + 1) * TAG_LEN
+            );
+            assert_eq!(open(&cipher, &sealed).unwrap(), plain, "{len} bytes");
+        }
+    }
+
+    #[test]
+    fn cut_short() {
+        let cipher = Aes256Gcm::new(&Key::<Aes256Gcm>::generate());
+        let sealed = seal(&cipher, &vec![7; 2 * CHUNK + 10]);
+        // At a chunk's end too, where the rest would otherwise look whole.
+        for len in [
+            sealed.len() - 1,
+            MAGIC.len() + PREFIX_LEN + 2 * (CHUNK + TAG_LEN),
+        ] {
+            assert!(open(&cipher, &sealed[..len]).is_err(), "{len} bytes");
+        }
+    }
+
+    #[tokio::test]
+    async fn async_round_trip() {
+        let cipher = Aes256Gcm::new(&Key::<Aes256Gcm>::generate());
+        let path = std::env::temp_dir().join(format!("wpsync-at-rest-{}", std::process::id()));
+        let plain: Vec<u8> = (0..CHUNK * 2 + 5).map(|i| (i % 251) as u8).collect();
+        let mut out = Vec::new();
+        let sealing = Sealing::new(cipher.clone(), &mut out);
+        let mut writer = Writer {
+            path: path.clone(),
+            file: tokio::fs::File::from_std(create_file(&path).unwrap()),
+            sealing,
+            out,
+            sent: 0,
+            finished: false,
+        };
+        for part in plain.chunks(3000) {
+            writer.write_all(part).await.unwrap();
+        }
+        writer.finish().await.unwrap();
+
+        let mut file = tokio::fs::File::open(&path).await.unwrap();
+        let mut header = [0; MAGIC.len() + PREFIX_LEN];
+        file.read_exact(&mut header).await.unwrap();
+        let mut reader = Reader {
+            file,
+            opening: Opening::new(cipher, &header),
+            raw: vec![0; CHUNK],
+            plain: Vec::new(),
+            read: 0,
+            done: false,
+        };
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).await.unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(read, plain);
+    }
+
+    #[test]
+    fn other_key() {
+        let cipher = Aes256Gcm::new(&Key::<Aes256Gcm>::generate());
+        let sealed = seal(&cipher, b"SELECT 1;");
+        let other = Aes256Gcm::new(&Key::<Aes256Gcm>::generate());
+        assert!(open(&other, &sealed).is_err());
+    }
+
+    #[tokio::test]
+    async fn plaintext_isnt_read() {
+        let path = std::env::temp_dir().join(format!("wpsync-plain-{}", std::process::id()));
+        std::fs::write(&path, b"DROP TABLE wp_users;").unwrap();
+        let blocking = open_blocking(&path).map(drop);
+        let streamed = super::open(&path).await.map(drop);
+        let _ = std::fs::remove_file(&path);
+        assert!(blocking.is_err());
+        assert!(streamed.is_err());
+    }
+}
@@ -0,0 +1,182 @@
+//! The audit log of pushes to protected profiles: who pushed, from which
+//! machine, when, how the push was confirmed, and what it was expected to
+//! change, for studios sharing a workstation that have to answer for what
+//! reached a client's server.
+//!
+//! Each profile's log is a file of JSON lines in `~/.wordpress-sync/audit`,
+//! only ever appended to. Unlike the history, which keeps a job's latest
+//! attempt, every attempt of a push is there, and entries outlive the profile.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::plan::PlannedTotals;
+use super::{Components, Direction};
+use crate::history::{HistoryEntry, JobStatus};
+use crate::messages;
+use crate::paths;
+use crate::profile::Profile;
+
+/// How a push to a protected profile was allowed to go ahead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Confirmation {
+    /// The user typed the profile's name, in the app or with `wpsync --confirm`.
+    #[default]
+    TypedName,
+    /// It was one of the profile's scheduled syncs, which need no confirming.
+    Scheduled,
+    /// It carried on a push that was confirmed when it first started.
+    Resumed,
+}
+
+/// One push to a protected profile.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub job_id: String,
+    pub profile_id: String,
+    /// The profile's name and server at the time.
+    pub profile_name: String,
+    pub server: String,
+    /// The account on this machine that ran the push, as `user@host`.
+    pub user: String,
+    pub components: Components,
+    pub confirmation: Confirmation,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub status: JobStatus,
+    pub error: Option<String>,
+    /// What the dry run before the push expected it to change, if one was made.
+    pub plan: Option<PlannedTotals>,
+    pub files_transferred: u64,
+    pub bytes_transferred: u64,
+    pub restore_point_id: Option<String>,
+}
+
+/// Log the finished job `entry` if it was a push to the protected `profile`.
+pub(super) fn record(profile: &Profile, entry: &HistoryEntry, confirmation: Confirmation) {
+    if !profile.protected || entry.direction != Direction::Push {
+        return;
+    }
+    let plan = PlannedTotals::load(&profile.id, Direction::Push)
+        .unwrap_or_default()
+        .filter(|plan| plan.components == entry.components && plan.planned_at <= entry.started_at);
+    let audit = AuditEntry {
+        job_id: entry.job_id.clone(),
+        profile_id: profile.id.clone(),
+        profile_name: profile.name.clone(),
+        server: profile.remote.url.clone(),
+        user: user(),
+        components: entry.components,
+        confirmation,
+        started_at: entry.started_at,
+        finished_at: entry.finished_at,
+        status: entry.status,
+        error: entry.error.as_ref().map(ToString::to_string),
+        plan,
+        files_transferred: entry.files_transferred,
+        bytes_transferred: entry.bytes_transferred,
+        restore_point_id: entry.restore_point_id.clone(),
+    };
+    if let Err(e) = append(&audit) {
+        tracing::error!(error = %e, "the push wasn't written to the audit log");
+    }
+}
+
+/// Every push to the protected profile `profile_id` that was logged, oldest first.
+pub fn audit_log(profile_id: &str) -> Result<Vec<AuditEntry>, String> {
+    let path = path_for(profile_id)?;
+    let raw = match fs::read_to_string(&path) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(messages::text(
+                "error.failed.read_path",
+                &[("path", &path.display()), ("error", &e)],
+            ))
+        }
+    };
+    raw.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line).map_err(|e| {
+                messages::text(
+                    "error.invalid_audit_log_path",
+                    &[("path", &path.display()), ("error", &e)],
+                )
+            })
+        })
+        .collect()
+}
+
+fn append(entry: &AuditEntry) -> Result<(), String> {
+    let path = path_for(&entry.profile_id)?;
+    let dir = paths::audit_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| {
+        messages::text(
+            "error.failed.create_path",
+            &[("path", &dir.display()), ("error", &e)],
+        )
+    })?;
+    let mut line = serde_json::to_string(entry)
+        .map_err(|e| messages::text("error.failed.serialize_audit_entry", &[("error", &e)]))?;
+    line.push('\n');
+    // One write of a whole line, so two windows logging at once can't interleave.
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .map_err(|e| {
+            messages::text(
+                "error.failed.write_path",
+                &[("path", &path.display()), ("error", &e)],
+            )
+        })
+}
+
+fn path_for(profile_id: &str) -> Result<PathBuf, String> {
+    if profile_id.is_empty()
+        || !profile_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(messages::text(
+            "error.invalid_profile_id",
+            &[("id", &format_args!("{profile_id:?}"))],
+        ));
+    }
+    Ok(paths::audit_dir()?.join(format!("{profile_id}.jsonl")))
+}
+
+/// The account running this process, and the machine it is on.
+fn user() -> String {
+    let name = ["USER", "USERNAME", "LOGNAME"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|name| !name.is_empty()))
+        .unwrap_or_else(|| "unknown".to_string());
+    match host() {
+        Some(host) => format!("{name}@{host}"),
+        None => name,
+    }
+}
+
+fn host() -> Option<String> {
+    #[cfg(unix)]
+    {
+        let mut buffer = [0u8; 256];
+        let named = unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) } == 0;
+        let end = buffer.iter().position(|&byte| byte == 0)?;
+        (named && end > 0).then(|| String::from_utf8_lossy(&buffer[..end]).into_owned())
+    }
+    #[cfg(not(unix))]
+    {
+        std::env::var("COMPUTERNAME")
+            .ok()
+            .filter(|host| !host.is_empty())
+    }
+}
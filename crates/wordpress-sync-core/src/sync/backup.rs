@@ -0,0 +1,732 @@
+//! Restore points: what a sync is about to overwrite, saved first so a bad
+//! sync can be undone.
+//!
+//! Just before the first destructive phase, the destination's database and the
+//! files the sync will update or delete are saved on the destination's own
+//! disk — under `~/.wordpress-sync/backups/<id>` on the server for a push, or
+//! on this machine for a pull — so a backup never has to cross the network.
+//! Each restore point is recorded on this machine, whichever side holds the
+//! backup, under `~/.wordpress-sync/restore-points`.
+//!
+//! A pull's backup is encrypted with this machine's key once it's saved, and
+//! decrypted into the restore's temporary directory to be put back.
+//!
+//! Profiles that transfer over FTP have no shell on the server to make a backup
+//! with, so their pushes get no restore point.
+//!
+//! Old restore points are pruned by the profile's [`Retention`] rules after each
+//! sync and periodically while the app runs.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Local, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+
+use super::endpoint::Endpoint;
+use super::mysql::{self, Mysql};
+use super::remote::Remote;
+use super::restore_check::{self, RestoreVerification};
+use super::temp::{self, TempDir};
+use super::{
+    at_rest, connect_ssh, db, local_path, multisite, Direction, Job, ProgressSink, SyncProgress,
+};
+use crate::byte_path;
+use crate::error::SyncError;
+use crate::messages::{self, Message};
+use crate::paths;
+use crate::profile::{self, Profile, Retention, TableSelection};
+use crate::shell;
+use crate::telemetry::{self, Feature};
+
+/// Where pushes keep their backups on the server, relative to the SSH user's
+/// home directory (where remote commands start).
+const REMOTE_BACKUPS_DIR: &str = ".wordpress-sync/backups";
+
+/// How often restore points are pruned while the app is running.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// The archive of overwritten and deleted files inside a backup directory.
+const FILES_ARCHIVE: &str = "files.tar.gz";
+
+/// Everything needed to put a destination back the way it was before one sync.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestorePoint {
+    /// The id of the sync job that made it.
+    pub id: String,
+    pub profile_id: String,
+    /// The sync's direction, which says which side holds the backup.
+    pub direction: Direction,
+    pub created_at: DateTime<Utc>,
+    /// The backup directory, as an absolute path on the destination.
+    pub location: String,
+    /// Whether the destination's database was saved.
+    pub database: bool,
+    /// Files in the archive, relative to the WordPress root.
+    pub files: Vec<String>,
+    /// Files and directories (ending in `/`) the sync added, which a rollback removes.
+    pub added: Vec<String>,
+    /// Each saved table's exact row count when it was saved.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub table_rows: BTreeMap<String, u64>,
+    /// The SHA-256 of a few of `files`, as they were saved.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checksums: BTreeMap<String, String>,
+    /// Whether its files were encrypted as they were saved, which those saved
+    /// on this machine before encryption weren't.
+    #[serde(default)]
+    pub encrypted: bool,
+    #[serde(default)]
+    pub rolled_back_at: Option<DateTime<Utc>>,
+    /// How the destination compared with this after the last rollback to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verification: Option<RestoreVerification>,
+}
+
+impl Job<'_> {
+    /// Whether this job makes a restore point before changing the destination.
+    pub(super) fn backs_up(&self) -> bool {
+        self.profile.backup_before_sync && self.destination().has_shell()
+    }
+
+    /// Back up what the sync is about to overwrite and record the restore point,
+    /// whose id is the job's.
+    pub(super) async fn back_up(&self) -> Result<(), String> {
+        let dest = self.destination();
+        let (files, added) = if self.transfers_files() {
+            let changes = match self.server_changes.get() {
+                Some(changes) => changes.clone(),
+                None => {
+                    self.transfer
+                        .plan(
+                            self.direction,
+                            &self.files_root(&self.source()),
+                            &self.files_root(&dest),
+                            &self.excludes().await?,
+                        )
+                        .await?
+                }
+            };
+            // Tree paths are relative to the directory being mirrored; the
+            // restore point's are relative to the WordPress root.
+            let prefix = multisite::files_dir(self.profile, self.components)
+                .map(|dir| format!("{dir}/"))
+                .unwrap_or_default();
+            let rooted = |path: String| format!("{prefix}{path}");
+            let files = changes
+                .updated
+                .into_iter()
+                .chain(changes.deleted)
+                .filter(|path| !path.ends_with('/'))
+                .map(rooted)
+                .collect();
+            let added = changes.added.into_iter().map(rooted).collect();
+            (files, added)
+        } else {
+            (Vec::new(), Vec::new())
+        };
+
+        // Saved here, its files are encrypted as they're written, or not saved.
+        let encrypted = matches!(dest, Endpoint::Local(_));
+        if encrypted {
+            at_rest::available().await.map_err(|e| {
+                messages::text("error.restore_point_cant_be_encrypted", &[("error", &e)])
+            })?;
+        }
+        let location = create_dir(&dest, &self.id).await?;
+        // A REST API sync changes the local database too, if not by importing it.
+        let database =
+            self.syncs_database() || (self.syncs_content() && self.components.database());
+        let mut table_rows = BTreeMap::new();
+        if database {
+            let path = join(&dest, &location, database_file(&dest));
+            let mysql = self.database_without_wp_cli(&dest).await?;
+            match mysql {
+                Some(mysql) => {
+                    // Dumped here and sent back gzipped, for want of wp-cli to do it there.
+                    let staged = staging_path(&self.id);
+                    let saved = match mysql
+                        .export(&TableSelection::default(), &[], &staged, &mut |_| {})
+                        .await
+                    {
+                        Ok(()) => db::upload_backup(&dest, &staged, &path).await,
+                        Err(e) => Err(e),
+                    };
+                    let _ = fs::remove_file(&staged);
+                    saved?;
+                }
+                None => db::back_up(&dest, &path).await?,
+            }
+            match restore_check::row_counts(&dest, mysql).await {
+                Ok(rows) => table_rows = rows,
+                Err(e) => self.warn(Message::new(
+                    "warning.restore_points_row_counts_werent_recorded",
+                    &[("error", &e)],
+                )),
+            }
+        }
+        let checksums = match restore_check::spot_hashes(&dest, &files).await {
+            Ok(checksums) => checksums,
+            Err(e) => {
+                tracing::warn!("restore point files weren't hashed: {e}");
+                BTreeMap::new()
+            }
+        };
+        let archived = join(&dest, &location, FILES_ARCHIVE);
+        archive(&dest, &archived, &files).await?;
+        tracing::info!(%location, database, files = files.len(), "restore point saved");
+        save(&RestorePoint {
+            id: self.id.clone(),
+            profile_id: self.profile.id.clone(),
+            direction: self.direction,
+            created_at: Utc::now(),
+            location,
+            database,
+            files,
+            added,
+            table_rows,
+            checksums,
+            encrypted,
+            rolled_back_at: None,
+            verification: None,
+        })
+    }
+
+    /// Remove a backup this job started but never recorded, after the job failed.
+    pub(super) async fn remove_incomplete_backup(&self) -> Result<(), String> {
+        if !self.backs_up() || path_for(&self.id)?.exists() {
+            return Ok(());
+        }
+        remove_backup(&self.destination(), &self.id).await
+    }
+
+    /// Remove the profile's restore points its retention rules no longer keep,
+    /// using this job's connection for those held on the server.
+    pub(super) async fn prune_backups(&self) -> Result<Vec<RestorePoint>, String> {
+        remove_expired(self.profile, self.remote.as_ref()).await
+    }
+}
+
+/// Put the destination of restore point `id` back the way it was before its
+/// sync: remove what the sync added, restore the files it overwrote or
+/// deleted, and import the saved database. Then its tables' rows and some of
+/// its files are checked against what the restore point recorded. An
+/// untrusted host key is passed to `sink`, as for a sync.
+pub async fn rollback(id: &str, sink: &dyn ProgressSink) -> Result<RestorePoint, SyncError> {
+    telemetry::record(Feature::Rollback);
+    let mut point = load(id).map_err(SyncError::config)?;
+    let profile = profile::load(&point.profile_id).map_err(SyncError::config)?;
+    let (remote, mysql) = connect_to_backup(&profile, &point, sink).await?;
+    let side = side(&profile, &point, remote.as_ref());
+    let result = match restore(&side, &point, mysql.as_ref()).await {
+        Ok(()) => Ok(restore_check::verify(&side, &point, mysql.as_ref()).await),
+        Err(e) => Err(e),
+    };
+    drop(mysql);
+    if let Some(remote) = &remote {
+        remote.close().await;
+    }
+    let verification = result?;
+    tracing::info!(restore_point = %point.id, profile = %profile.name, "rolled back");
+    point.rolled_back_at = Some(Utc::now());
+    match verification {
+        Ok(verification) => {
+            if verification.as_ref().is_some_and(|check| !check.passed) {
+                tracing::warn!(restore_point = %point.id, "rollback didn't match its restore point");
+            }
+            point.verification = verification;
+        }
+        Err(e) => tracing::warn!(restore_point = %point.id, "rollback wasn't checked: {e}"),
+    }
+    save(&point).map_err(SyncError::config)?;
+    Ok(point)
+}
+
+/// The connection to the server a push's restore point is held on, and to
+/// its database directly if it has no wp-cli; neither for a pull's.
+pub(super) async fn connect_to_backup(
+    profile: &Profile,
+    point: &RestorePoint,
+    sink: &dyn ProgressSink,
+) -> Result<(Option<Remote>, Option<Mysql>), SyncError> {
+    let remote = match point.direction {
+        Direction::Push => connect_ssh(profile, sink).await?,
+        Direction::Pull => return Ok((None, None)),
+    };
+    let mysql = match point.database {
+        true => mysql::open_without_wp_cli(profile, &remote).await,
+        false => Ok(None),
+    };
+    match mysql {
+        Ok(mysql) => Ok((Some(remote), mysql)),
+        Err(e) => {
+            remote.close().await;
+            Err(SyncError::database(e))
+        }
+    }
+}
+
+/// The side of `profile` that `point`'s backup is held on and puts back.
+pub(super) fn side<'a>(
+    profile: &'a Profile,
+    point: &RestorePoint,
+    remote: Option<&'a Remote>,
+) -> Endpoint<'a> {
+    match point.direction {
+        Direction::Push => Endpoint::Remote {
+            remote,
+            env: &profile.remote,
+        },
+        Direction::Pull => Endpoint::Local(&profile.local),
+    }
+}
+
+/// Whether the backup directory `location` is still on `side`.
+pub(super) async fn exists(side: &Endpoint<'_>, location: &str) -> Result<bool, String> {
+    match side {
+        Endpoint::Local(_) => Ok(Path::new(location).is_dir()),
+        Endpoint::Remote { .. } => {
+            let command = format!("test -d {} && echo found", shell::quote(location));
+            let output = side.session()?.exec(&format!("{command}; true")).await?;
+            Ok(output.trim() == "found")
+        }
+    }
+}
+
+async fn restore(
+    side: &Endpoint<'_>,
+    point: &RestorePoint,
+    mysql: Option<&Mysql>,
+) -> Result<(), SyncError> {
+    let _temp = TempDir::hold(&point.id);
+    if let (Endpoint::Local(_), false) = (side, point.encrypted) {
+        seal(side, point).await.map_err(SyncError::transfer)?;
+    }
+    // Added paths go first: one may be a file where the backup has a directory.
+    remove_added(side, &point.added)
+        .await
+        .map_err(SyncError::transfer)?;
+    if !point.files.is_empty() {
+        let archive = join(side, &point.location, FILES_ARCHIVE);
+        extract(side, &archive).await.map_err(SyncError::transfer)?;
+    }
+    if point.database {
+        let path = join(side, &point.location, database_file(side));
+        match mysql {
+            Some(mysql) => {
+                let staged = staging_path(&point.id);
+                let restored = match db::download_backup(side, &path, &staged).await {
+                    Ok(()) => mysql.import(&staged).await,
+                    Err(e) => Err(e),
+                };
+                let _ = fs::remove_file(&staged);
+                restored
+            }
+            // One saved here is decrypted as it's imported.
+            None => db::restore(side, &path).await,
+        }
+        .map_err(SyncError::database)?;
+    }
+    // A failed cache flush leaves a working (if briefly stale) site.
+    let _ = side.wp(&["cache", "flush"]).await;
+    Ok(())
+}
+
+/// Remove `profile`'s restore points that its retention rules no longer keep,
+/// returning them. Connects to the server only if some of them are held there.
+pub async fn prune(
+    profile: &Profile,
+    sink: &dyn ProgressSink,
+) -> Result<Vec<RestorePoint>, SyncError> {
+    let points = list(&profile.id).map_err(SyncError::config)?;
+    let expired = expired(&points, &profile.retention, Utc::now());
+    if expired.is_empty() {
+        return Ok(Vec::new());
+    }
+    let needs_server = expired
+        .iter()
+        .any(|point| point.direction == Direction::Push);
+    let remote = if needs_server {
+        Some(connect_ssh(profile, sink).await?)
+    } else {
+        None
+    };
+    let result = remove_expired(profile, remote.as_ref()).await;
+    if let Some(remote) = &remote {
+        remote.close().await;
+    }
+    result.map_err(SyncError::transfer)
+}
+
+/// Prune every profile's restore points now and then every [`PRUNE_INTERVAL`],
+/// for as long as the app runs. Failures are left for the next round; a profile
+/// whose server can't be reached keeps its backups there until it can.
+pub async fn prune_periodically() {
+    let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+    loop {
+        interval.tick().await;
+        for profile in profile::list().unwrap_or_default() {
+            let _ = prune(&profile, &Unattended).await;
+        }
+    }
+}
+
+/// A sink for pruning nobody is watching.
+struct Unattended;
+
+impl ProgressSink for Unattended {
+    fn progress(&self, _update: &SyncProgress) {}
+}
+
+async fn remove_expired(
+    profile: &Profile,
+    remote: Option<&Remote>,
+) -> Result<Vec<RestorePoint>, String> {
+    let points = list(&profile.id)?;
+    let mut removed = Vec::new();
+    for point in expired(&points, &profile.retention, Utc::now()) {
+        let side = match point.direction {
+            Direction::Push => Endpoint::Remote {
+                remote,
+                env: &profile.remote,
+            },
+            Direction::Pull => Endpoint::Local(&profile.local),
+        };
+        remove_backup(&side, &point.id).await?;
+        let path = path_for(&point.id)?;
+        fs::remove_file(&path).map_err(|e| {
+            messages::text(
+                "error.failed.delete_path",
+                &[("path", &path.display()), ("error", &e)],
+            )
+        })?;
+        tracing::info!(restore_point = %point.id, "pruned restore point");
+        removed.push(point.clone());
+    }
+    Ok(removed)
+}
+
+/// The restore points in `points` (newest first) that `retention` doesn't keep
+/// as of `now`. Days are counted in local time.
+fn expired<'a>(
+    points: &'a [RestorePoint],
+    retention: &Retention,
+    now: DateTime<Utc>,
+) -> Vec<&'a RestorePoint> {
+    let today = now.with_timezone(&Local).date_naive();
+    let mut days_seen = HashSet::new();
+    points
+        .iter()
+        .enumerate()
+        .filter(|(i, point)| {
+            let day = point.created_at.with_timezone(&Local).date_naive();
+            let newest_of_day = days_seen.insert(day);
+            let recent = (today - day).num_days() < i64::from(retention.keep_daily_days);
+            let kept = *i < retention.keep_last as usize || (recent && newest_of_day);
+            !kept
+        })
+        .map(|(_, point)| point)
+        .collect()
+}
+
+/// Delete the backup directory of restore point `id` from the side holding it.
+async fn remove_backup(side: &Endpoint<'_>, id: &str) -> Result<(), String> {
+    match side {
+        Endpoint::Local(_) => {
+            let dir = paths::backups_dir()?.join(id);
+            match tokio::fs::remove_dir_all(&dir).await {
+                Ok(()) => Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                Err(e) => Err(messages::text(
+                    "error.failed.remove_path",
+                    &[("path", &dir.display()), ("error", &e)],
+                )),
+            }
+        }
+        Endpoint::Remote { .. } => {
+            let dir = format!("{REMOTE_BACKUPS_DIR}/{id}");
+            let command = shell::join(["rm", "-rf", dir.as_str()]);
+            side.session()?.exec(&command).await.map(drop)
+        }
+    }
+}
+
+/// Create the backup directory for restore point `id`, returning its absolute path.
+async fn create_dir(side: &Endpoint<'_>, id: &str) -> Result<String, String> {
+    match side {
+        Endpoint::Local(_) => {
+            let dir = paths::backups_dir()?.join(id);
+            tokio::fs::create_dir_all(&dir).await.map_err(|e| {
+                messages::text(
+                    "error.failed.create_path",
+                    &[("path", &dir.display()), ("error", &e)],
+                )
+            })?;
+            Ok(dir.display().to_string())
+        }
+        Endpoint::Remote { .. } => {
+            let dir = shell::quote(&format!("{REMOTE_BACKUPS_DIR}/{id}"));
+            let command = format!("mkdir -p {dir} && cd {dir} && pwd");
+            let output = side.session()?.exec(&command).await?;
+            Ok(output.trim().to_string())
+        }
+    }
+}
+
+/// Where a server's database backup passes through this machine, in the
+/// temporary directory of the job saving it or the restore of point `id`, when
+/// there's no wp-cli on the server to save or restore it.
+fn staging_path(id: &str) -> PathBuf {
+    temp::path(id, "backup.sql")
+}
+
+/// The database backup's file name; the server's copy is gzipped.
+fn database_file(side: &Endpoint<'_>) -> &'static str {
+    match side {
+        Endpoint::Local(_) => "database.sql",
+        Endpoint::Remote { .. } => "database.sql.gz",
+    }
+}
+
+/// A file inside a backup directory, as seen on its side.
+fn join(side: &Endpoint<'_>, dir: &str, name: &str) -> String {
+    match side {
+        Endpoint::Local(_) => Path::new(dir).join(name).display().to_string(),
+        Endpoint::Remote { .. } => format!("{dir}/{name}"),
+    }
+}
+
+/// Write `files`, relative to the WordPress root, into a gzipped tar at `archive`.
+async fn archive(side: &Endpoint<'_>, archive: &str, files: &[String]) -> Result<(), String> {
+    if files.is_empty() {
+        return Ok(());
+    }
+    match side {
+        Endpoint::Local(env) => {
+            let (root, path, files) = (env.path.clone(), PathBuf::from(archive), files.to_vec());
+            blocking(move || {
+                let file = at_rest::create_blocking(&path)?;
+                let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+                builder.follow_symlinks(false);
+                for relative in &files {
+                    builder.append_path_with_name(local_path::join(&root, relative), relative)?;
+                }
+                builder.into_inner()?.finish()?.finish()?;
+                Ok(())
+            })
+            .await
+            .map_err(|e| {
+                messages::text(
+                    "error.failed.write_path",
+                    &[("path", &archive), ("error", &e)],
+                )
+            })
+        }
+        Endpoint::Remote { .. } => {
+            let remote = side.session()?;
+            // BusyBox's tar only reads a name a line; one with a newline in it
+            // can't be backed up there.
+            let (null, list) = if remote.ssh().platform().await.tar_null_list() {
+                (" --null", null_separated(files))
+            } else {
+                ("", byte_path::to_bytes(&files.join("\n")).into_owned())
+            };
+            let command = format!(
+                "tar -czf {} -C {}{null} -T -",
+                shell::quote(archive),
+                shell::quote(&side.root())
+            );
+            remote.exec_with_input(&command, &list).await.map(drop)
+        }
+    }
+}
+
+/// Unpack an archive written by [`archive`] over the WordPress root.
+async fn extract(side: &Endpoint<'_>, archive: &str) -> Result<(), String> {
+    match side {
+        Endpoint::Local(env) => {
+            let (root, path) = (env.path.clone(), PathBuf::from(archive));
+            blocking(move || {
+                tar::Archive::new(GzDecoder::new(at_rest::open_blocking(&path)?)).unpack(&root)
+            })
+            .await
+            .map_err(|e| {
+                messages::text(
+                    "error.failed.restore_files_archive",
+                    &[("archive", &archive), ("error", &e)],
+                )
+            })
+        }
+        Endpoint::Remote { .. } => {
+            let command = shell::join(["tar", "-xzf", archive, "-C", &side.root()]);
+            side.session()?.exec(&command).await.map(drop)
+        }
+    }
+}
+
+/// Remove the files a sync added, then the directories it added that are empty
+/// again. Directories that have gained other files since are left alone.
+async fn remove_added(side: &Endpoint<'_>, added: &[String]) -> Result<(), String> {
+    let (mut dirs, files): (Vec<&String>, Vec<&String>) =
+        added.iter().partition(|path| path.ends_with('/'));
+    // Children sort after their parents, so this puts them first.
+    dirs.sort_by(|a, b| b.cmp(a));
+    match side {
+        Endpoint::Local(env) => {
+            for relative in files {
+                let path = local_path::join(&env.path, relative);
+                match tokio::fs::remove_file(&path).await {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => {
+                        return Err(messages::text(
+                            "error.failed.remove_path",
+                            &[("path", &path.display()), ("error", &e)],
+                        ))
+                    }
+                }
+            }
+            for relative in dirs {
+                let _ = tokio::fs::remove_dir(local_path::join(&env.path, relative)).await;
+            }
+            Ok(())
+        }
+        Endpoint::Remote { .. } => {
+            let session = side.session()?;
+            let cd = shell::join(["cd", &side.root()]);
+            if !files.is_empty() {
+                let command = format!("{cd} && xargs -0 rm -f --");
+                session
+                    .exec_with_input(&command, &null_separated(&files))
+                    .await?;
+            }
+            if !dirs.is_empty() {
+                let command = format!("{cd} && {{ xargs -0 rmdir 2>/dev/null || true; }}");
+                session
+                    .exec_with_input(&command, &null_separated(&dirs))
+                    .await?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Paths as NUL-terminated input for `tar -T` and `xargs -0`.
+fn null_separated(paths: &[impl AsRef<str>]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for path in paths {
+        out.extend_from_slice(&byte_path::to_bytes(path.as_ref()));
+        out.push(0);
+    }
+    out
+}
+
+/// Encrypt the files of a restore point saved on this machine before the app
+/// encrypted them, so they can be read back.
+async fn seal(side: &Endpoint<'_>, point: &RestorePoint) -> Result<(), String> {
+    let mut paths = Vec::new();
+    if !point.files.is_empty() {
+        paths.push(PathBuf::from(join(side, &point.location, FILES_ARCHIVE)));
+    }
+    if point.database {
+        paths.push(PathBuf::from(join(
+            side,
+            &point.location,
+            database_file(side),
+        )));
+    }
+    for path in paths {
+        let sealed = path.clone();
+        blocking(move || at_rest::seal_file(&sealed))
+            .await
+            .map_err(|e| {
+                messages::text(
+                    "error.failed.encrypt_path",
+                    &[("path", &path.display()), ("error", &e)],
+                )
+            })?;
+    }
+    Ok(())
+}
+
+async fn blocking(f: impl FnOnce() -> io::Result<()> + Send + 'static) -> io::Result<()> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(io::Error::other)?
+}
+
+/// Load a restore point by its id.
+pub fn load(id: &str) -> Result<RestorePoint, String> {
+    let path = path_for(id)?;
+    let raw = fs::read_to_string(&path).map_err(|e| {
+        messages::text(
+            "error.failed.read_restore_point_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })?;
+    serde_json::from_str(&raw).map_err(|e| {
+        messages::text(
+            "error.invalid_restore_point_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })
+}
+
+/// A profile's restore points, newest first. Unreadable records are skipped.
+pub fn list(profile_id: &str) -> Result<Vec<RestorePoint>, String> {
+    let dir = paths::restore_points_dir()?;
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(messages::text(
+                "error.failed.list_path",
+                &[("path", &dir.display()), ("error", &e)],
+            ))
+        }
+    };
+    let mut points: Vec<RestorePoint> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .filter_map(|raw| serde_json::from_str::<RestorePoint>(&raw).ok())
+        .filter(|point| point.profile_id == profile_id)
+        .collect();
+    points.sort_by_key(|point| std::cmp::Reverse(point.created_at));
+    Ok(points)
+}
+
+fn save(point: &RestorePoint) -> Result<(), String> {
+    let path = path_for(&point.id)?;
+    let json = serde_json::to_string_pretty(point)
+        .map_err(|e| messages::text("error.failed.serialize_restore_point", &[("error", &e)]))?;
+    let dir = paths::restore_points_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| {
+        messages::text(
+            "error.failed.create_path",
+            &[("path", &dir.display()), ("error", &e)],
+        )
+    })?;
+    paths::atomic_write(&path, json)
+}
+
+/// The record for a restore point id, refusing ids that could escape its directory.
+fn path_for(id: &str) -> Result<PathBuf, String> {
+    let valid = !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(messages::text(
+            "error.invalid_restore_point_id",
+            &[("id", &format_args!("{id:?}"))],
+        ));
+    }
+    Ok(paths::restore_points_dir()?.join(format!("{id}.json")))
+}
@@ -0,0 +1,46 @@
+//! Keeping syncs out of a profile's blackout windows.
+//!
+//! The scheduler skips a firing that lands in one. A sync started by hand is
+//! refused with [`SyncError::Blackout`] unless the user says to go ahead,
+//! as with a push to a protected profile; resuming a job that already
+//! started needs no telling.
+
+use chrono::Utc;
+
+use crate::error::SyncError;
+use crate::messages::Message;
+use crate::profile::Profile;
+
+/// Fail with [`SyncError::Blackout`] if it's now one of `profile`'s blackout
+/// windows.
+pub fn check_blackouts(profile: &Profile) -> Result<(), SyncError> {
+    let at = Utc::now();
+    let Some(blackout) = profile
+        .blackouts
+        .iter()
+        .find(|blackout| blackout.covers(&at))
+    else {
+        return Ok(());
+    };
+    let days = match blackout.days.is_empty() {
+        true => String::new(),
+        false => {
+            let days: Vec<String> = blackout.days.iter().map(ToString::to_string).collect();
+            format!(" on {}", days.join(", "))
+        }
+    };
+    let until = blackout
+        .ends_after(&at)
+        .map(|ends| format!("; this one ends at {}", ends.format("%a %H:%M")))
+        .unwrap_or_default();
+    Err(SyncError::Blackout(Message::new(
+        "error.name_doesnt_sync_start_end_days",
+        &[
+            ("name", &profile.name),
+            ("start", &blackout.start),
+            ("end", &blackout.end),
+            ("days", &days),
+            ("until", &until),
+        ],
+    )))
+}
@@ -0,0 +1,223 @@
+//! Looking around a profile's server without changing anything, as when
+//! picking its WordPress root or checking a path: a directory's entries, or
+//! one path's details, read over SFTP on the SSH connection syncs use.
+//!
+//! A relative path, the empty one included, is taken from the SSH user's
+//! home directory.
+
+use chrono::{DateTime, Utc};
+use russh_sftp::client::SftpSession;
+use russh_sftp::protocol::FileAttributes;
+use serde::Serialize;
+
+use super::{connect_ssh, transfer, ProgressSink};
+use crate::error::SyncError;
+use crate::messages::{self, Message};
+use crate::profile::wp_config::WP_CONFIG;
+use crate::profile::Profile;
+
+/// What a path on the server is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemoteEntryKind {
+    File,
+    Dir,
+    Symlink,
+    /// A socket, device, or pipe.
+    Other,
+}
+
+/// One path on the server.
+#[derive(Debug, Clone, Serialize)]
+pub struct RemoteEntry {
+    pub name: String,
+    /// Absolute.
+    pub path: String,
+    pub kind: RemoteEntryKind,
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+    /// The mode's permission bits in octal, such as `755`.
+    pub mode: Option<String>,
+}
+
+/// One path on the server, looked at more closely.
+#[derive(Debug, Clone, Serialize)]
+pub struct RemoteFileStat {
+    #[serde(flatten)]
+    pub entry: RemoteEntry,
+    /// Where a symlink points.
+    pub link_target: Option<String>,
+    /// For a symlink, what it points to; `None` when that's missing.
+    pub target_kind: Option<RemoteEntryKind>,
+    /// Whether it's a directory with a `wp-config.php`, as a WordPress root has.
+    pub wordpress_root: bool,
+}
+
+/// The entries of the directory `path` on `profile`'s server, directories
+/// first, then by name. An untrusted host key is announced to `sink` just as
+/// it is for a real sync.
+pub async fn list_remote_dir(
+    profile: &Profile,
+    path: &str,
+    sink: &dyn ProgressSink,
+) -> Result<Vec<RemoteEntry>, SyncError> {
+    browse(profile, sink, |sftp| async move {
+        let dir = sftp
+            .canonicalize(relative_to_home(path))
+            .await
+            .map_err(|e| {
+                messages::text(
+                    "error.failed.find_path_server",
+                    &[("path", &path), ("error", &e)],
+                )
+            })?;
+        let entries = sftp.read_dir(&dir).await.map_err(|e| {
+            messages::text(
+                "error.failed.list_path_over_sftp",
+                &[("path", &dir), ("error", &e)],
+            )
+        })?;
+        let mut listing: Vec<RemoteEntry> = entries
+            .filter(|entry| !matches!(entry.file_name().as_str(), "." | ".."))
+            .map(|entry| {
+                let name = entry.file_name();
+                entry_of(&join(&dir, &name), name, &entry.metadata())
+            })
+            .collect();
+        listing.sort_by(|a, b| {
+            (a.kind != RemoteEntryKind::Dir, &a.name)
+                .cmp(&(b.kind != RemoteEntryKind::Dir, &b.name))
+        });
+        Ok(listing)
+    })
+    .await
+}
+
+/// The details of `path` on `profile`'s server.
+pub async fn stat_remote_file(
+    profile: &Profile,
+    path: &str,
+    sink: &dyn ProgressSink,
+) -> Result<RemoteFileStat, SyncError> {
+    browse(profile, sink, |sftp| async move {
+        let relative = relative_to_home(path);
+        let attrs = sftp.symlink_metadata(relative.clone()).await.map_err(|e| {
+            messages::text(
+                "error.failed.find_path_server",
+                &[("path", &path), ("error", &e)],
+            )
+        })?;
+        // Absolute, without following a symlink at the end of it.
+        let (parent, name) = match relative.rsplit_once('/') {
+            Some(("", name)) => ("/", name),
+            Some((parent, name)) => (parent, name),
+            None => (".", relative.as_str()),
+        };
+        let absolute = match name {
+            "" | "." | ".." => sftp.canonicalize(relative.clone()).await.map_err(|e| {
+                messages::text(
+                    "error.failed.find_path_server",
+                    &[("path", &path), ("error", &e)],
+                )
+            })?,
+            name => {
+                let parent = sftp.canonicalize(parent).await.map_err(|e| {
+                    messages::text(
+                        "error.failed.find_path_server",
+                        &[("path", &parent), ("error", &e)],
+                    )
+                })?;
+                join(&parent, name)
+            }
+        };
+        let name = absolute
+            .rsplit('/')
+            .find(|name| !name.is_empty())
+            .unwrap_or("/")
+            .to_string();
+        let entry = entry_of(&absolute, name, &attrs);
+        let (link_target, target_kind) = match entry.kind {
+            RemoteEntryKind::Symlink => (
+                sftp.read_link(absolute.clone()).await.ok(),
+                sftp.metadata(absolute.clone())
+                    .await
+                    .ok()
+                    .map(|target| kind(&target)),
+            ),
+            _ => (None, None),
+        };
+        let wordpress_root = matches!(target_kind.unwrap_or(entry.kind), RemoteEntryKind::Dir)
+            && sftp
+                .try_exists(join(&absolute, WP_CONFIG))
+                .await
+                .unwrap_or(false);
+        Ok(RemoteFileStat {
+            entry,
+            link_target,
+            target_kind,
+            wordpress_root,
+        })
+    })
+    .await
+}
+
+/// Connect to `profile`'s server, run `look` with an SFTP session there, and
+/// disconnect.
+async fn browse<T, F>(
+    profile: &Profile,
+    sink: &dyn ProgressSink,
+    look: impl FnOnce(SftpSession) -> F,
+) -> Result<T, SyncError>
+where
+    F: std::future::Future<Output = Result<T, String>>,
+{
+    if !profile.remote.transfer.uses_ssh() {
+        return Err(SyncError::Config(Message::new("error.no_ssh.browse", &[])));
+    }
+    let remote = connect_ssh(profile, sink).await?;
+    let result = match transfer::start_session(&remote, profile.timeouts.read()).await {
+        Ok(sftp) => look(sftp).await,
+        Err(e) => Err(e),
+    };
+    remote.close().await;
+    result.map_err(SyncError::remote_command)
+}
+
+/// `path` as SFTP takes one relative to the home directory.
+fn relative_to_home(path: &str) -> String {
+    match path.trim() {
+        "" => ".".to_string(),
+        path => path.to_string(),
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    format!("{}/{name}", dir.trim_end_matches('/'))
+}
+
+fn entry_of(path: &str, name: String, attrs: &FileAttributes) -> RemoteEntry {
+    RemoteEntry {
+        name,
+        path: path.to_string(),
+        kind: kind(attrs),
+        size: attrs.size.unwrap_or(0),
+        modified: attrs
+            .mtime
+            .and_then(|mtime| DateTime::from_timestamp(i64::from(mtime), 0)),
+        mode: attrs
+            .permissions
+            .map(|permissions| format!("{:o}", permissions & 0o7777)),
+    }
+}
+
+fn kind(attrs: &FileAttributes) -> RemoteEntryKind {
+    if attrs.is_symlink() {
+        RemoteEntryKind::Symlink
+    } else if attrs.is_dir() {
+        RemoteEntryKind::Dir
+    } else if attrs.is_regular() {
+        RemoteEntryKind::File
+    } else {
+        RemoteEntryKind::Other
+    }
+}
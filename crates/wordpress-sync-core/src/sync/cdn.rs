@@ -0,0 +1,201 @@
+//! Purging the CDN in front of the server's site once a push is through, so
+//! visitors see what was pushed rather than the cached pages from before.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::json;
+use url::Url;
+
+use super::{http, Direction, Job};
+use crate::credentials::{self, CDN_TOKEN_SERVICE};
+use crate::messages::{self, Message};
+use crate::net::AddressFamily;
+use crate::profile::CdnTarget;
+use crate::proxy;
+
+/// How long one request to the CDN may take.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+const CLOUDFLARE_API: &str = "https://api.cloudflare.com/client/v4";
+
+/// The envelope every Cloudflare API response comes in.
+#[derive(Deserialize)]
+struct CloudflareResponse<T> {
+    success: bool,
+    #[serde(default)]
+    errors: Vec<CloudflareError>,
+    result: Option<T>,
+}
+
+#[derive(Deserialize)]
+struct CloudflareError {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct Zone {
+    id: String,
+    name: String,
+}
+
+impl Job<'_> {
+    /// Whether this job purges a CDN once it's through: it's a push, and the
+    /// profile has a CDN purge turned on.
+    pub(super) fn purges_cdn(&self) -> bool {
+        self.direction == Direction::Push
+            && self
+                .profile
+                .post_sync
+                .cdn_purge
+                .as_ref()
+                .is_some_and(|purge| purge.enabled)
+    }
+
+    /// Purge the CDN the profile's pushes go behind, if it has one turned on.
+    /// A failed purge leaves a working (if stale) site, so it's only a warning.
+    pub(super) async fn purge_cdn(&self) {
+        let Some(purge) = &self.profile.post_sync.cdn_purge else {
+            return;
+        };
+        if !self.purges_cdn() {
+            return;
+        }
+        match self.purge(&purge.target).await {
+            Ok(()) => tracing::info!("purged the CDN's cache"),
+            Err(e) => self.warn(Message::new(
+                "warning.cdns_cache_was_not_purged",
+                &[("error", &e)],
+            )),
+        }
+    }
+
+    async fn purge(&self, target: &CdnTarget) -> Result<(), String> {
+        let token = credentials::resolve(
+            self.profile.secrets.cdn_token.as_deref(),
+            CDN_TOKEN_SERVICE,
+            &self.profile.id,
+        )?;
+        let client = proxy::route(&self.profile.remote.proxy).and_then(|route| {
+            http::builder(route.as_ref(), AddressFamily::Any)?
+                .timeout(TIMEOUT)
+                .build()
+                .map_err(|e| messages::text("error.failed.set_up_http", &[("error", &e)]))
+        })?;
+        let host = Url::parse(&self.profile.remote.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .ok_or_else(|| {
+                messages::text(
+                    "error.url_has_no_host_name",
+                    &[("url", &self.profile.remote.url)],
+                )
+            })?;
+        match target {
+            CdnTarget::Cloudflare { zone_id } => {
+                let token = token.ok_or_else(|| {
+                    messages::text(
+                        "error.no_cloudflare_api_token_stored_name",
+                        &[("name", &self.profile.name)],
+                    )
+                })?;
+                purge_cloudflare(&client, &token, zone_id.as_deref(), &host).await
+            }
+            CdnTarget::Url { url } => {
+                let mut request = client.post(url).json(&json!({
+                    "host": host,
+                    "url": self.profile.remote.url,
+                }));
+                if let Some(token) = token {
+                    request = request.bearer_auth(token);
+                }
+                request
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map(drop)
+                    .map_err(|e| e.without_url().to_string())
+            }
+        }
+    }
+}
+
+/// Purge what Cloudflare caches for `host`: the whole zone if `host` is the
+/// zone's own domain, or otherwise just that host's pages.
+async fn purge_cloudflare(
+    client: &reqwest::Client,
+    token: &str,
+    zone_id: Option<&str>,
+    host: &str,
+) -> Result<(), String> {
+    let zone = match zone_id {
+        Some(id) => Zone {
+            id: id.to_string(),
+            name: String::new(),
+        },
+        None => find_zone(client, token, host).await?,
+    };
+    let body = if zone.name.eq_ignore_ascii_case(host) {
+        json!({ "purge_everything": true })
+    } else {
+        json!({ "hosts": [host] })
+    };
+    let url = format!("{CLOUDFLARE_API}/zones/{}/purge_cache", zone.id);
+    let response = client
+        .post(url)
+        .bearer_auth(token)
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| e.without_url().to_string())?;
+    cloudflare_result::<serde_json::Value>(response)
+        .await
+        .map(drop)
+}
+
+/// The Cloudflare zone `host` is in: the one named after it or after the
+/// nearest domain above it.
+async fn find_zone(client: &reqwest::Client, token: &str, host: &str) -> Result<Zone, String> {
+    let labels: Vec<&str> = host.split('.').collect();
+    // The last label alone is a top-level domain, which is never a zone.
+    for start in 0..labels.len().saturating_sub(1) {
+        let name = labels[start..].join(".");
+        let response = client
+            .get(format!("{CLOUDFLARE_API}/zones"))
+            .query(&[("name", name.as_str())])
+            .bearer_auth(token)
+            .send()
+            .await
+            .map_err(|e| e.without_url().to_string())?;
+        let zones: Vec<Zone> = cloudflare_result(response).await?;
+        if let Some(zone) = zones.into_iter().next() {
+            return Ok(zone);
+        }
+    }
+    Err(messages::text(
+        "error.cloudflare_api_token_cant_see_zone",
+        &[("host", &host)],
+    ))
+}
+
+async fn cloudflare_result<T: serde::de::DeserializeOwned>(
+    response: reqwest::Response,
+) -> Result<T, String> {
+    let status = response.status();
+    let body: CloudflareResponse<T> = response.json().await.map_err(|e| {
+        messages::text(
+            "error.cloudflare_answered_status_something_unexpected",
+            &[("status", &status), ("error", &e)],
+        )
+    })?;
+    match body.result {
+        Some(result) if body.success => Ok(result),
+        _ => {
+            let errors: Vec<String> = body.errors.into_iter().map(|e| e.message).collect();
+            Err(messages::text(
+                "error.cloudflare_answered_status_errors",
+                &[("status", &status), ("errors", &errors.join("; "))],
+            ))
+        }
+    }
+}
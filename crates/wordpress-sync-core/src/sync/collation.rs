@@ -0,0 +1,179 @@
+//! Finding the character sets and collations the source's tables use that the
+//! destination's server doesn't have, and the nearest ones it does to rename
+//! them to in the dump.
+//!
+//! A dump from MySQL 8 names `utf8mb4_0900_ai_ci`, and one from recent MariaDB
+//! `utf8mb4_uca1400_ai_ci`; older servers refuse to create tables with either.
+//! A stand-in keeps the character set and, where it can, the rest of the name,
+//! then falls back to the set's Unicode collations and finally its default.
+//! A server without `utf8mb4` gets `utf8mb3`, which can't hold emoji.
+
+use std::collections::BTreeSet;
+
+use super::endpoint::Endpoint;
+use super::Job;
+use crate::messages::Message;
+use crate::search_replace::CollationRemap;
+
+/// Each of the source's tables and the collations its definitions name.
+const USED: &str = "SELECT table_name, table_collation FROM information_schema.TABLES \
+                    WHERE table_schema = DATABASE() AND table_collation IS NOT NULL \
+                    UNION ALL SELECT table_name, collation_name FROM information_schema.COLUMNS \
+                    WHERE table_schema = DATABASE() AND collation_name IS NOT NULL";
+
+/// What the destination's server has: each collation, its character set, and
+/// whether it is that set's default.
+const AVAILABLE: &str = "SHOW COLLATION";
+
+/// What the dump's definitions need changed for the destination.
+#[derive(Debug, Default)]
+pub(super) struct Collations {
+    /// The renames, with the profile's `normalize_collations` on.
+    pub renames: Vec<CollationRemap>,
+    /// What the import may get wrong or fail on all the same.
+    pub warnings: Vec<Message>,
+}
+
+/// A collation the destination's server has.
+struct Available {
+    name: String,
+    charset: String,
+    default: bool,
+}
+
+impl Job<'_> {
+    /// The source's character sets and collations, in the tables being
+    /// synced, that the destination's server lacks. Nothing is checked on a
+    /// side without a shell to ask its database.
+    pub(super) async fn collations(&self) -> Result<Collations, String> {
+        let (source, dest) = (self.source(), self.destination());
+        if !source.has_shell() || !dest.has_shell() {
+            return Ok(Collations::default());
+        }
+        let selection = self.table_selection(&source).await?;
+        let tables = self.tables(&source).await?;
+        let selected = selection.select(&tables)?;
+        let used: BTreeSet<String> = self
+            .query(&source, USED)
+            .await?
+            .into_iter()
+            .filter_map(|row| match <[String; 2]>::try_from(row) {
+                Ok([table, collation]) if selected.contains(&table.as_str()) => Some(collation),
+                _ => None,
+            })
+            .collect();
+        let available = self.available_collations(&dest).await?;
+        let mut collations = stand_ins(&used, &available);
+        let normalize = self.profile.normalize_collations;
+        let renames = if normalize {
+            collations.renames.clone()
+        } else {
+            std::mem::take(&mut collations.renames)
+        };
+        for remap in renames {
+            let warning = if !normalize {
+                Message::new(
+                    "warning.destination_has_no_collation_normalize_off",
+                    &[("collation", &remap.from)],
+                )
+            } else if !remap.from.contains('_') {
+                Message::new(
+                    "warning.destination_has_no_charset",
+                    &[("charset", &remap.from), ("to", &remap.to)],
+                )
+            } else {
+                continue;
+            };
+            collations.warnings.push(warning);
+        }
+        Ok(collations)
+    }
+
+    async fn available_collations(&self, dest: &Endpoint<'_>) -> Result<Vec<Available>, String> {
+        let rows = self.query(dest, AVAILABLE).await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                let mut values = row.into_iter();
+                let name = values.next()?;
+                let charset = values.next()?;
+                let default = values.nth(1).is_some_and(|value| value == "Yes");
+                Some(Available {
+                    name,
+                    charset,
+                    default,
+                })
+            })
+            .collect())
+    }
+}
+
+/// The character set a collation belongs to, the start of its name.
+fn charset(collation: &str) -> &str {
+    collation.split('_').next().unwrap_or(collation)
+}
+
+/// A stand-in from `available` for each of the `used` collations it lacks.
+fn stand_ins(used: &BTreeSet<String>, available: &[Available]) -> Collations {
+    let has = |name: &str| available.iter().any(|a| a.name.eq_ignore_ascii_case(name));
+    let has_charset = |name: &str| {
+        available
+            .iter()
+            .any(|a| a.charset.eq_ignore_ascii_case(name))
+    };
+    let mut collations = Collations::default();
+    for collation in used {
+        if has(collation) {
+            continue;
+        }
+        let from = charset(collation);
+        let to = if has_charset(from) {
+            from.to_string()
+        } else if let Some(utf8) = (from.eq_ignore_ascii_case("utf8mb4"))
+            .then(|| ["utf8mb3", "utf8"].into_iter().find(|set| has_charset(set)))
+            .flatten()
+        {
+            if !collations
+                .renames
+                .iter()
+                .any(|remap| remap.from.eq_ignore_ascii_case(from))
+            {
+                collations.renames.push(CollationRemap {
+                    from: from.to_string(),
+                    to: utf8.to_string(),
+                });
+            }
+            utf8.to_string()
+        } else {
+            collations.warnings.push(Message::new(
+                "warning.destinations_database_server_has_no_collation",
+                &[("collation", &collation), ("from", &from)],
+            ));
+            continue;
+        };
+        let rest = &collation[from.len()..];
+        let default = available
+            .iter()
+            .find(|a| a.default && a.charset.eq_ignore_ascii_case(&to))
+            .map(|a| a.name.clone());
+        let stand_in = [
+            format!("{to}{rest}"),
+            format!("{to}_unicode_520_ci"),
+            format!("{to}_unicode_ci"),
+        ]
+        .into_iter()
+        .find(|name| has(name))
+        .or(default);
+        match stand_in {
+            Some(stand_in) => collations.renames.push(CollationRemap {
+                from: collation.clone(),
+                to: stand_in,
+            }),
+            None => collations.warnings.push(Message::new(
+                "warning.destinations_database_server_has_no_collation_2",
+                &[("collation", &collation)],
+            )),
+        }
+    }
+    collations
+}
@@ -0,0 +1,35 @@
+//! Installing a Composer project's packages on the server once a push has
+//! brought its `composer.lock` there, since the packages themselves, core
+//! among them, are never pushed.
+
+use super::endpoint::Endpoint;
+use super::{Direction, Job};
+
+use crate::messages::Message;
+
+/// The install, as a deploy would run it: the locked versions, without
+/// development packages, and never asking anything.
+const INSTALL: &str =
+    "composer install --no-dev --no-interaction --prefer-dist --optimize-autoloader";
+
+impl Job<'_> {
+    /// Run `composer install` in the destination's project root, if the
+    /// profile asks for it and this job pushed its files. A failure is a
+    /// warning: the files are through, and the install can be run by hand.
+    pub(super) async fn composer_install(&self, dest: &Endpoint<'_>) {
+        if !self.profile.post_sync.composer_install
+            || !self.profile.project.uses_composer()
+            || self.direction != Direction::Push
+            || !self.transfers_files()
+        {
+            return;
+        }
+        tracing::info!("installing the project's Composer packages");
+        if let Err(e) = dest.sh(INSTALL, &[]).await {
+            self.warn(Message::new(
+                "warning.composer_install_failed_destination",
+                &[("error", &e)],
+            ));
+        }
+    }
+}
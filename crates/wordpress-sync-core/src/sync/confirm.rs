@@ -0,0 +1,103 @@
+//! Confirmations for pushes to protected profiles.
+//!
+//! A push to a profile marked [`protected`](Profile::protected) only starts
+//! with a token from [`PushConfirmations::issue`], which takes the profile's
+//! name typed by the user. A token is good for one push of that profile, for a
+//! couple of minutes, so a stray click on an old confirmation dialog can't
+//! overwrite the server either. Pulls, pushes of other profiles, and scheduled
+//! syncs, which are set up on purpose, need none.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use super::Direction;
+use crate::error::SyncError;
+use crate::messages::Message;
+use crate::profile::Profile;
+
+/// How long a token stays good.
+const LIFETIME: Duration = Duration::minutes(2);
+
+/// Permission for one push of a protected profile.
+#[derive(Debug, Clone, Serialize)]
+pub struct PushConfirmation {
+    pub token: String,
+    pub profile_id: String,
+    /// When the token stops being accepted.
+    pub expires_at: DateTime<Utc>,
+}
+
+/// The confirmations issued in this process and not yet used.
+#[derive(Default)]
+pub struct PushConfirmations {
+    pending: Mutex<HashMap<String, PushConfirmation>>,
+}
+
+impl PushConfirmations {
+    /// Fail unless `typed` is the name of `profile`, without issuing anything,
+    /// for checking it before asking for more.
+    pub fn check(&self, profile: &Profile, typed: &str) -> Result<(), SyncError> {
+        if typed.trim() != profile.name.trim() {
+            return Err(SyncError::ConfirmationRequired(Message::new(
+                "error.type_name_confirm_pushing_url",
+                &[
+                    ("name", &format_args!("{:?}", profile.name)),
+                    ("url", &profile.remote.url),
+                ],
+            )));
+        }
+        Ok(())
+    }
+
+    /// Issue a token for pushing `profile`, if `typed` is its name.
+    pub fn issue(&self, profile: &Profile, typed: &str) -> Result<PushConfirmation, SyncError> {
+        self.check(profile, typed)?;
+        let confirmation = PushConfirmation {
+            token: uuid::Uuid::new_v4().to_string(),
+            profile_id: profile.id.clone(),
+            expires_at: Utc::now() + LIFETIME,
+        };
+        let mut pending = self.pending.lock().unwrap();
+        let now = Utc::now();
+        pending.retain(|_, issued| issued.expires_at > now);
+        pending.insert(confirmation.token.clone(), confirmation.clone());
+        Ok(confirmation)
+    }
+
+    /// Let a sync of `profile` in `direction` go ahead, using up `token`, or
+    /// fail with [`SyncError::ConfirmationRequired`] if it is a push the
+    /// profile's protection needs a valid token for.
+    pub fn redeem(
+        &self,
+        profile: &Profile,
+        direction: Direction,
+        token: Option<&str>,
+    ) -> Result<(), SyncError> {
+        if !profile.protected || direction != Direction::Push {
+            return Ok(());
+        }
+        let issued = token.and_then(|token| self.pending.lock().unwrap().remove(token));
+        match issued {
+            Some(issued) if issued.profile_id == profile.id && issued.expires_at > Utc::now() => {
+                Ok(())
+            }
+            Some(issued) if issued.profile_id == profile.id => {
+                Err(SyncError::ConfirmationRequired(Message::new(
+                    "error.push_confirmation_has_expired",
+                    &[],
+                )))
+            }
+            Some(_) => Err(SyncError::ConfirmationRequired(Message::new(
+                "error.push_confirmation_another_profile",
+                &[],
+            ))),
+            None => Err(SyncError::ConfirmationRequired(Message::new(
+                "error.name_protected_so_pushing_needs_confirming",
+                &[("name", &profile.name)],
+            ))),
+        }
+    }
+}
@@ -0,0 +1,300 @@
+//! The SQL console: one statement at a time against the database of either
+//! of a profile's sites, for checking a row count or an option's value after
+//! a sync without opening a database client.
+//!
+//! The server's database is reached the way a sync reaches it, through a
+//! tunnel when the profile or the server calls for one and with wp-cli over
+//! SSH otherwise; the local one with the local wp-cli. A read-only query has
+//! to be a statement that only reads, and runs in a session that can't
+//! write, so one that slips past the first check still can't change anything.
+
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use super::endpoint::Endpoint;
+use super::mysql::Mysql;
+use super::{connect_ssh, db, preflight, ProgressSink};
+use crate::error::SyncError;
+use crate::messages::{self, Message};
+use crate::profile::Profile;
+use crate::telemetry::{self, Feature};
+
+/// The most rows the console returns from a query; the rest are left out.
+const MAX_ROWS: usize = 1_000;
+
+/// The rows read from a query's result and kept, one more than are returned
+/// to tell whether any were left out; the rest are read past.
+const KEPT: usize = MAX_ROWS + 1;
+
+/// The statements a read-only query may start with.
+const READING: &[&str] = &["SELECT", "SHOW", "DESCRIBE", "DESC", "EXPLAIN", "WITH"];
+
+/// Words that make a statement starting with one of [`READING`] write or lock
+/// all the same: `SELECT ... INTO OUTFILE`, `SELECT ... FOR UPDATE`, or a
+/// `WITH` before a `DELETE`.
+const WRITING: &[&str] = &[
+    "INTO", "UPDATE", "DELETE", "INSERT", "REPLACE", "LOCK", "SHARE",
+];
+
+/// Which of a profile's sites a query runs against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryEnvironment {
+    Local,
+    Remote,
+}
+
+/// What a query returned.
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryResult {
+    /// The result's column names, empty for a statement that returns no rows.
+    pub columns: Vec<String>,
+    /// Each row's values as text, with NULLs as empty strings.
+    pub rows: Vec<Vec<String>>,
+    /// Whether there were more than [`MAX_ROWS`] rows, and the rest were left out.
+    pub truncated: bool,
+    pub read_only: bool,
+    pub duration_ms: u64,
+}
+
+/// Run the single SQL statement `sql` against the database of `profile`'s
+/// `environment`. With `read_only` it has to be one that only reads, and
+/// can't write even if it's not.
+pub async fn run_query(
+    profile: &Profile,
+    environment: QueryEnvironment,
+    sql: &str,
+    read_only: bool,
+    sink: &dyn ProgressSink,
+) -> Result<QueryResult, SyncError> {
+    telemetry::record(Feature::SqlConsole);
+    let sql = statement(sql, read_only).map_err(SyncError::config)?;
+    let started = Instant::now();
+    let (columns, mut rows) = match environment {
+        QueryEnvironment::Local => db::query_table(
+            &Endpoint::Local(&profile.local),
+            &guard(sql, read_only),
+            KEPT,
+        )
+        .await
+        .map_err(SyncError::database)?,
+        QueryEnvironment::Remote => {
+            if !profile.remote.transfer.uses_ssh() {
+                return Err(SyncError::Config(Message::new(
+                    "error.no_ssh.database_query",
+                    &[],
+                )));
+            }
+            let remote = connect_ssh(profile, sink).await?;
+            let result = async {
+                match preflight::database_route(profile, &remote).await? {
+                    Some(route) => {
+                        let mysql =
+                            Mysql::open(profile, &remote, route.client, !route.wp_cli).await?;
+                        mysql.query_table(sql, read_only, KEPT).await
+                    }
+                    None => {
+                        let endpoint = Endpoint::Remote {
+                            remote: Some(&remote),
+                            env: &profile.remote,
+                        };
+                        db::query_table(&endpoint, &guard(sql, read_only), KEPT).await
+                    }
+                }
+            }
+            .await;
+            remote.close().await;
+            result.map_err(SyncError::database)?
+        }
+    };
+    let truncated = rows.len() > MAX_ROWS;
+    rows.truncate(MAX_ROWS);
+    tracing::info!(
+        ?environment,
+        read_only,
+        rows = rows.len(),
+        truncated,
+        "ran a query from the SQL console"
+    );
+    Ok(QueryResult {
+        columns,
+        rows,
+        truncated,
+        read_only,
+        duration_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+/// `sql` for wp-cli, which hands it to `mysql`: after a statement that keeps
+/// the session from writing, with `read_only`.
+fn guard(sql: &str, read_only: bool) -> String {
+    match read_only {
+        true => format!("SET SESSION TRANSACTION READ ONLY; {sql}"),
+        false => sql.to_string(),
+    }
+}
+
+/// The one statement in `sql`, without the semicolon it may end with, if it
+/// is one and, with `read_only`, only reads.
+fn statement(sql: &str, read_only: bool) -> Result<&str, String> {
+    let sql = sql.trim();
+    let sql = sql.strip_suffix(';').unwrap_or(sql).trim_end();
+    let code = code(sql)?;
+    if code.trim().is_empty() {
+        return Err(messages::text("error.there_no_query_run", &[]));
+    }
+    if code.contains(';') {
+        return Err(messages::text("error.console_runs_one_statement_time", &[]));
+    }
+    if !read_only {
+        return Ok(sql);
+    }
+    if code.contains("/*!") {
+        return Err(messages::text(
+            "error.read_only_query_cant_have_mysqls",
+            &[],
+        ));
+    }
+    let words: Vec<String> = code
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .filter(|word| !word.is_empty())
+        .map(str::to_ascii_uppercase)
+        .collect();
+    let first = words.first().map(String::as_str).unwrap_or_default();
+    if !READING.contains(&first) {
+        return Err(messages::text(
+            "error.read_only_query_can_reading_turn",
+            &[("reading", &READING.join(", ")), ("first", &first)],
+        ));
+    }
+    if let Some(word) = words.iter().find(|word| WRITING.contains(&word.as_str())) {
+        return Err(messages::text(
+            "error.read_only_query_cant_use_word",
+            &[("word", &word)],
+        ));
+    }
+    Ok(sql)
+}
+
+/// `sql` with its strings, quoted names, and comments blanked out, leaving
+/// the words and punctuation MySQL reads as the statement. An executable
+/// comment's `/*!` is kept, as it's the statement too.
+fn code(sql: &str) -> Result<String, String> {
+    let mut out = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' | '`' => {
+                let mut closed = false;
+                while let Some(next) = chars.next() {
+                    if next == '\\' && c != '`' {
+                        chars.next();
+                    } else if next == c {
+                        // A doubled quote is one quote inside the string.
+                        if chars.peek() == Some(&c) {
+                            chars.next();
+                        } else {
+                            closed = true;
+                            break;
+                        }
+                    }
+                }
+                if !closed {
+                    return Err(messages::text("error.query_has_unclosed_c", &[("c", &c)]));
+                }
+                out.push(' ');
+            }
+            '#' => {
+                chars.by_ref().find(|&next| next == '\n');
+                out.push(' ');
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                chars.next();
+                match chars.peek() {
+                    Some(next) if next.is_whitespace() => {
+                        chars.by_ref().find(|&next| next == '\n');
+                        out.push(' ');
+                    }
+                    None => out.push(' '),
+                    Some(_) => out.push_str("--"),
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'!') {
+                    out.push_str("/*!");
+                    continue;
+                }
+                let mut previous = None;
+                let closed = chars.by_ref().any(|next| {
+                    let end = previous == Some('*') && next == '/';
+                    previous = Some(next);
+                    end
+                });
+                if !closed {
+                    return Err(messages::text("error.query_has_unclosed_comment", &[]));
+                }
+                out.push(' ');
+            }
+            c => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reads(sql: &str) -> bool {
+        statement(sql, true).is_ok()
+    }
+
+    #[test]
+    fn one_statement() {
+        assert_eq!(statement("  SELECT 1 ;  ", true), Ok("SELECT 1"));
+        assert!(statement("SELECT 1; SELECT 2", true).is_err());
+        assert!(statement("UPDATE t SET a = 1; DELETE FROM t", false).is_err());
+        assert!(statement(" ; ", false).is_err());
+        assert!(reads("SELECT ';' AS semicolon"));
+        assert!(reads("SELECT `a;b` FROM t"));
+        assert!(!reads("SELECT 'unclosed"));
+    }
+
+    #[test]
+    fn comments() {
+        assert!(reads("SELECT 1 -- ; DROP TABLE wp_users"));
+        assert!(reads("SELECT 1 /* ; DROP TABLE wp_users */"));
+        assert!(reads("# a note\nSELECT 1"));
+        assert!(reads("/* DELETE */ SELECT 1"));
+        assert!(!reads("/* SELECT */ DELETE FROM wp_users"));
+        assert!(!reads("SELECT 1 --; DROP TABLE wp_users"));
+        assert!(!reads("SELECT 1 /* unclosed"));
+        assert!(!reads("SELECT 1 /*! INTO OUTFILE '/tmp/x' */"));
+    }
+
+    #[test]
+    fn reading_only() {
+        assert!(reads("SHOW TABLES"));
+        assert!(reads("select option_value from wp_options"));
+        assert!(reads("SELECT 'into' AS word, `update` FROM t"));
+        assert!(!reads("DELETE FROM wp_users"));
+        assert!(!reads("WITH old AS (SELECT 1) DELETE FROM wp_posts"));
+        assert!(statement("DELETE FROM wp_users", false).is_ok());
+    }
+
+    #[test]
+    fn into_outfile() {
+        assert!(!reads("SELECT * FROM wp_users INTO OUTFILE '/tmp/users'"));
+        assert!(!reads("SELECT user_pass INTO @pass FROM wp_users"));
+        assert!(!reads("select * from wp_users into dumpfile '/tmp/users'"));
+    }
+
+    #[test]
+    fn locking_reads() {
+        assert!(!reads("SELECT * FROM wp_options FOR UPDATE"));
+        assert!(!reads("SELECT * FROM wp_options FOR SHARE"));
+        assert!(!reads("SELECT * FROM wp_options LOCK IN SHARE MODE"));
+    }
+}
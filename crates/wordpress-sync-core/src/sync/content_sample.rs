@@ -0,0 +1,171 @@
+//! Pulling a sample of the server's content instead of all of it: the posts
+//! modified in the profile's last so many days, with the attachments they
+//! use, their meta, terms and comments. The other tables come whole, so the
+//! local site has the server's settings, users and taxonomies, just fewer
+//! posts, which for a large store or publication is most of the database.
+//!
+//! The posts are found with a few queries first, and the dump then keeps only
+//! the rows of the post tables that belong to them.
+
+use chrono::{Duration, Utc};
+
+use super::db::RowFilter;
+use super::endpoint::Endpoint;
+use super::mysql::identifier;
+use super::{Direction, Job};
+
+use crate::messages::{self, Message};
+
+/// The most posts, attachments included, or comments one sample takes. Their
+/// ids go on the dump's command line, which has a limit.
+const MAX_ROWS: usize = 10_000;
+
+impl Job<'_> {
+    /// The filters that keep a pull's post tables to the profile's content
+    /// sample, or none if it pulls everything.
+    pub(super) async fn content_sample_filters(
+        &self,
+        source: &Endpoint<'_>,
+    ) -> Result<Vec<RowFilter>, String> {
+        let Some(days) = self.profile.content_sample_days else {
+            return Ok(Vec::new());
+        };
+        if self.direction != Direction::Pull {
+            return Ok(Vec::new());
+        }
+        let mut prefix = self.table_prefix(source).await?;
+        if let Some(id) = self.profile.multisite.subsite.filter(|&id| id > 1) {
+            prefix = format!("{prefix}{id}_");
+        }
+        let tables = self.tables(source).await?;
+        let table = |name: &str| {
+            let table = format!("{prefix}{name}");
+            tables.contains(&table).then_some(table)
+        };
+        let Some(posts) = table("posts") else {
+            return Ok(Vec::new());
+        };
+        let cutoff = (Utc::now() - Duration::days(i64::from(days)))
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+
+        let mut ids = self
+            .ids(
+                source,
+                &format!(
+                    "SELECT ID FROM {} WHERE post_modified_gmt >= '{cutoff}' \
+                     AND post_type NOT IN ('attachment', 'revision') \
+                     AND post_status <> 'auto-draft'",
+                    identifier(&posts)
+                ),
+            )
+            .await?;
+        let recent = ids.len();
+        if !ids.is_empty() {
+            // Their media, and the featured images they use from elsewhere.
+            let list = id_list(&ids);
+            let thumbnails = match table("postmeta") {
+                Some(meta) => format!(
+                    " OR ID IN (SELECT meta_value FROM {} \
+                     WHERE meta_key = '_thumbnail_id' AND post_id IN ({list}))",
+                    identifier(&meta)
+                ),
+                None => String::new(),
+            };
+            let attachments = self
+                .ids(
+                    source,
+                    &format!(
+                        "SELECT ID FROM {} WHERE post_type = 'attachment' \
+                         AND (post_parent IN ({list}){thumbnails})",
+                        identifier(&posts)
+                    ),
+                )
+                .await?;
+            ids.extend(attachments);
+            ids.sort_unstable();
+            ids.dedup();
+        }
+        if ids.len() > MAX_ROWS {
+            return Err(messages::text(
+                "error.last_days_have_count_posts_attachments",
+                &[
+                    ("days", &days),
+                    ("count", &ids.len()),
+                    ("max_rows", &MAX_ROWS),
+                ],
+            ));
+        }
+        tracing::info!(
+            days,
+            posts = recent,
+            attachments = ids.len() - recent,
+            "pulling a content sample"
+        );
+        if recent == 0 {
+            self.warn(Message::new(
+                "warning.no_posts_were_modified_server_last",
+                &[("days", &days)],
+            ));
+        }
+
+        let list = id_list(&ids);
+        let mut filters = vec![RowFilter {
+            table: posts,
+            condition: format!("ID IN ({list})"),
+        }];
+        for (name, column) in [("postmeta", "post_id"), ("term_relationships", "object_id")] {
+            if let Some(table) = table(name) {
+                filters.push(RowFilter {
+                    table,
+                    condition: format!("{column} IN ({list})"),
+                });
+            }
+        }
+        if let Some(comments) = table("comments") {
+            let comment_ids = self
+                .ids(
+                    source,
+                    &format!(
+                        "SELECT comment_ID FROM {} WHERE comment_post_ID IN ({list})",
+                        identifier(&comments)
+                    ),
+                )
+                .await?;
+            if comment_ids.len() > MAX_ROWS {
+                return Err(messages::text(
+                    "error.sampled_posts_have_count_comments_more",
+                    &[("count", &comment_ids.len()), ("max_rows", &MAX_ROWS)],
+                ));
+            }
+            filters.push(RowFilter {
+                table: comments,
+                condition: format!("comment_post_ID IN ({list})"),
+            });
+            if let Some(meta) = table("commentmeta") {
+                filters.push(RowFilter {
+                    table: meta,
+                    condition: format!("comment_id IN ({})", id_list(&comment_ids)),
+                });
+            }
+        }
+        Ok(filters)
+    }
+
+    /// The ids a query for one column of them returns.
+    async fn ids(&self, side: &Endpoint<'_>, sql: &str) -> Result<Vec<u64>, String> {
+        let rows = self.query(side, sql).await?;
+        Ok(rows
+            .iter()
+            .filter_map(|row| row.first()?.trim().parse().ok())
+            .collect())
+    }
+}
+
+/// `ids` for an SQL `IN (...)`. There is no row 0, so no ids match nothing.
+fn id_list(ids: &[u64]) -> String {
+    if ids.is_empty() {
+        return "0".to_string();
+    }
+    ids.iter().map(u64::to_string).collect::<Vec<_>>().join(",")
+}
@@ -0,0 +1,436 @@
+//! Plugins: scripts that add steps of their own to syncs, such as purging a
+//! CDN's cache once a push is through, without changing the app.
+//!
+//! Each plugin is a directory under `~/.wordpress-sync/plugins` holding a
+//! `plugin.json` manifest, which names the plugin and the steps it offers,
+//! each at one of the points hooks run at, and a `plugin.rhai` script with a
+//! function for each step. A profile picks the steps it wants, with settings
+//! for each, in its `custom_steps`.
+//!
+//! The script is [Rhai](https://rhai.rs), run inside the app with nothing
+//! but the functions below: it can't read or write files, start programs, or
+//! reach the network. A step's function is called with the job and the
+//! step's settings:
+//!
+//! ```rhai
+//! fn purge(job, config) {
+//!     progress(`Purging the cache of ${job.destination_url}`);
+//!     let output = run("wp cache flush");
+//!     if output.contains("Warning") { warning(output); }
+//! }
+//! ```
+//!
+//! `job` has the `id`, `profile_id`, `profile_name`, `direction`,
+//! `components`, `source_url`, and `destination_url` of the sync, and
+//! `config` is the step's `config` in the profile.
+//!
+//! - `progress(message)` shows what the step is doing;
+//! - `warning(message)` adds a warning to the sync's result;
+//! - `run(command)` runs a command on the server, which must be one of the
+//!   profile's vetted remote commands, and returns its output.
+//!
+//! A `run` that fails throws, which the script may catch. The step has
+//! succeeded if its function returns; one that throws, or that takes more
+//! than [`MAX_OPERATIONS`], fails.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc as blocking, Arc};
+use std::time::Instant;
+
+use rhai::packages::{Package, StandardPackage};
+use rhai::{Dynamic, Engine, EvalAltResult, Scope};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::{mpsc, oneshot};
+
+use super::endpoint::remote_sh_line;
+use super::transfer::TransferStats;
+use super::{Job, Phase};
+use crate::history::text;
+use crate::messages::{self, Message};
+use crate::paths;
+use crate::profile::{CustomStep, HookPoint, SudoStep};
+
+/// The plugin's manifest in its directory.
+const MANIFEST: &str = "plugin.json";
+
+/// The plugin's script in its directory.
+const SCRIPT: &str = "plugin.rhai";
+
+/// How many operations a step's script may take before it's stopped, so one
+/// that loops forever can't hold up the sync.
+pub const MAX_OPERATIONS: u64 = 100_000_000;
+
+/// The largest string, array, or map a script may build.
+const MAX_SIZE: usize = 16 * 1024 * 1024;
+
+/// An installed plugin, as its manifest describes it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepPlugin {
+    /// The name of its directory, which profiles refer to it by.
+    #[serde(skip_deserializing)]
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub steps: Vec<PluginStepInfo>,
+}
+
+/// A step a plugin offers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginStepInfo {
+    /// The id profiles pick it by, which is also the name of its function in the script.
+    pub id: String,
+    /// What it does, shown while it runs, e.g. `Purging Cloudflare's cache`.
+    pub title: String,
+    pub point: HookPoint,
+}
+
+/// What a step's script asks of the job.
+enum Request {
+    Progress(String),
+    Warning(String),
+    Run(String, blocking::Sender<Result<String, String>>),
+}
+
+/// Stops a step's script once dropped, as when the sync is cancelled.
+struct Stop(Arc<AtomicBool>);
+
+impl Drop for Stop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Every installed plugin, by name. Directories without a readable manifest
+/// are skipped.
+pub fn step_plugins() -> Result<Vec<StepPlugin>, String> {
+    let dir = paths::plugins_dir()?;
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(messages::text(
+                "error.failed.list_path",
+                &[("path", &dir.display()), ("error", &e)],
+            ))
+        }
+    };
+    let mut plugins: Vec<StepPlugin> = entries
+        .flatten()
+        .filter_map(|entry| load(&entry.file_name().to_string_lossy()).ok())
+        .collect();
+    plugins.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(plugins)
+}
+
+/// The installed plugin `id`.
+pub fn step_plugin(id: &str) -> Result<StepPlugin, String> {
+    load(id)
+}
+
+fn load(id: &str) -> Result<StepPlugin, String> {
+    let dir = dir_for(id)?;
+    let path = dir.join(MANIFEST);
+    let raw = match fs::read_to_string(&path) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(messages::text(
+                "error.there_no_plugin_named_id",
+                &[("id", &format_args!("{id:?}"))],
+            ))
+        }
+        Err(e) => {
+            return Err(messages::text(
+                "error.failed.read_path",
+                &[("path", &path.display()), ("error", &e)],
+            ))
+        }
+    };
+    let mut plugin: StepPlugin = serde_json::from_str(&raw).map_err(|e| {
+        messages::text(
+            "error.invalid_plugin_manifest_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })?;
+    if !dir.join(SCRIPT).is_file() {
+        return Err(messages::text(
+            "error.plugin_id_has_no_script",
+            &[("id", &format_args!("{id:?}")), ("script", &SCRIPT)],
+        ));
+    }
+    plugin.id = id.to_string();
+    Ok(plugin)
+}
+
+/// The directory of plugin `id`, refusing ids that could escape the plugins directory.
+fn dir_for(id: &str) -> Result<PathBuf, String> {
+    let valid = !id.is_empty()
+        && !id.starts_with('.')
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(messages::text(
+            "error.invalid_plugin_name_id",
+            &[("id", &format_args!("{id:?}"))],
+        ));
+    }
+    Ok(paths::plugins_dir()?.join(id))
+}
+
+/// The phase whose end a hook point follows, which a step's progress is shown under.
+fn phase(point: HookPoint) -> Phase {
+    match point {
+        HookPoint::BeforeSync => Phase::Connect,
+        HookPoint::AfterFiles => Phase::TransferFiles,
+        HookPoint::AfterDbImport => Phase::ImportDatabase,
+        HookPoint::AfterSync => Phase::Finalize,
+    }
+}
+
+impl Job<'_> {
+    /// Run the profile's custom steps for `point`, in the order they are
+    /// listed. A required step that fails stops the rest and fails the sync;
+    /// any other failure is a warning.
+    pub(super) async fn run_custom_steps(&self, point: HookPoint) -> Result<(), String> {
+        for step in &self.profile.custom_steps {
+            let found = load(&step.plugin).and_then(|plugin| {
+                let info = plugin
+                    .steps
+                    .iter()
+                    .find(|info| info.id == step.step)
+                    .cloned()
+                    .ok_or_else(|| {
+                        messages::text(
+                            "error.name_has_no_step",
+                            &[
+                                ("name", &plugin.name),
+                                ("step", &format_args!("{:?}", step.step)),
+                            ],
+                        )
+                    })?;
+                Ok((plugin, info))
+            });
+            let ran = match found {
+                Ok((plugin, info)) if info.point == point => {
+                    self.run_custom_step(&plugin, &info, step).await
+                }
+                Ok(_) => continue,
+                // One that can't be found is reported once, before the sync changes anything.
+                Err(e) if point == HookPoint::BeforeSync => Err(e),
+                Err(_) => continue,
+            };
+            let Err(e) = ran else {
+                continue;
+            };
+            let message = messages::text(
+                "error.custom_step_failed",
+                &[
+                    ("plugin", &step.plugin),
+                    ("step", &step.step),
+                    ("error", &e),
+                ],
+            );
+            if step.required {
+                return Err(message);
+            }
+            self.warn(message);
+        }
+        Ok(())
+    }
+
+    async fn run_custom_step(
+        &self,
+        plugin: &StepPlugin,
+        info: &PluginStepInfo,
+        step: &CustomStep,
+    ) -> Result<(), String> {
+        tracing::info!(plugin = %plugin.id, step = %info.id, "running custom step");
+        let path = dir_for(&plugin.id)?.join(SCRIPT);
+        let source = fs::read_to_string(&path).map_err(|e| {
+            messages::text(
+                "error.failed.read_path",
+                &[("path", &path.display()), ("error", &e)],
+            )
+        })?;
+        let dest = self.destination();
+        let job = json!({
+            "id": self.id,
+            "profile_id": self.profile.id,
+            "profile_name": self.profile.name,
+            "direction": text(&self.direction),
+            "components": text(&self.components),
+            "source_url": self.source_url(),
+            "destination_url": dest.url(),
+        });
+        let config = serde_json::Value::Object(step.config.clone());
+
+        // The script runs on a thread of its own, asking for what it needs
+        // over `requests` and waiting for the answer.
+        let (requests, mut asked) = mpsc::unbounded_channel();
+        let (done, finished) = oneshot::channel();
+        let stop = Stop(Arc::default());
+        let stopped = Arc::clone(&stop.0);
+        let (plugin_id, function) = (plugin.id.clone(), info.id.clone());
+        std::thread::spawn(move || {
+            let ran = run_script(
+                &plugin_id, &source, &function, job, config, requests, stopped,
+            );
+            let _ = done.send(ran);
+        });
+
+        let phase = phase(info.point);
+        let started = Instant::now();
+        self.step_progress(phase, &info.title, started, true);
+        while let Some(request) = asked.recv().await {
+            match request {
+                Request::Progress(message) => {
+                    let detail = messages::text(
+                        "progress.custom_step",
+                        &[("step", &info.title), ("message", &message)],
+                    );
+                    self.step_progress(phase, &detail, started, false)
+                }
+                Request::Warning(message) => self.warn(Message::new(
+                    "warning.custom_step_plugin",
+                    &[("plugin", &plugin.name), ("message", &message)],
+                )),
+                Request::Run(command, reply) => {
+                    let _ = reply.send(self.run_for_plugin(&command).await);
+                }
+            }
+        }
+        drop(stop);
+        finished
+            .await
+            .unwrap_or_else(|_| Err(messages::text("error.script_stopped_unexpectedly", &[])))
+    }
+
+    /// Run `command` on the server for a plugin, as a remote hook would be.
+    async fn run_for_plugin(&self, command: &str) -> Result<String, String> {
+        if !self.remote().has_shell() {
+            return Err(messages::text("error.profile_has_no_shell_server_run", &[]));
+        }
+        self.vetted(command)?;
+        let line = remote_sh_line(&self.profile.remote, command, &[]);
+        self.run_remote(SudoStep::Hooks, &line, &[]).await
+    }
+
+    /// Show `message` as what the step is doing. Unless `force`, at most as
+    /// often as other progress is.
+    fn step_progress(&self, phase: Phase, message: &str, started: Instant, force: bool) {
+        let stats = TransferStats {
+            current_file: Some(message.to_string()),
+            ..TransferStats::default()
+        };
+        self.reporter.report(phase, &stats, started, force);
+    }
+}
+
+/// Run the step `function` of a plugin's script, with no more of the app
+/// than the functions the module describes.
+fn run_script(
+    plugin: &str,
+    source: &str,
+    function: &str,
+    job: serde_json::Value,
+    config: serde_json::Value,
+    requests: mpsc::UnboundedSender<Request>,
+    stopped: Arc<AtomicBool>,
+) -> Result<(), String> {
+    // With no module resolver, which the crate is built without, `import`
+    // can't read other scripts off the disk.
+    let mut engine = Engine::new_raw();
+    engine.register_global_module(StandardPackage::new().as_shared_module());
+    engine
+        .set_max_operations(MAX_OPERATIONS)
+        .set_max_call_levels(64)
+        .set_max_string_size(MAX_SIZE)
+        .set_max_array_size(MAX_SIZE)
+        .set_max_map_size(MAX_SIZE);
+    engine.disable_symbol("eval");
+    engine.on_progress(move |_| stopped.load(Ordering::Relaxed).then_some(Dynamic::UNIT));
+    let (printed, debugged) = (plugin.to_string(), plugin.to_string());
+    engine.on_print(move |line| tracing::info!(plugin = %printed, "{line}"));
+    engine.on_debug(move |line, _, _| tracing::debug!(plugin = %debugged, "{line}"));
+
+    let sender = requests.clone();
+    engine.register_fn("progress", move |message: &str| {
+        let _ = sender.send(Request::Progress(message.to_string()));
+    });
+    let sender = requests.clone();
+    engine.register_fn("warning", move |message: &str| {
+        let _ = sender.send(Request::Warning(message.to_string()));
+    });
+    engine.register_fn(
+        "run",
+        move |command: &str| -> Result<String, Box<EvalAltResult>> {
+            let (reply, answer) = blocking::channel();
+            let gone = || messages::text("error.sync_has_stopped", &[]);
+            requests
+                .send(Request::Run(command.to_string(), reply))
+                .map_err(|_| gone())?;
+            Ok(answer.recv().map_err(|_| gone())??)
+        },
+    );
+
+    let to_dynamic = |value| rhai::serde::to_dynamic(value).map_err(|e| e.to_string());
+    let args = (to_dynamic(&job)?, to_dynamic(&config)?);
+    let ast = engine
+        .compile(source)
+        .map_err(|e| messages::text("error.invalid_path", &[("path", &SCRIPT), ("error", &e)]))?;
+    engine
+        .call_fn::<Dynamic>(&mut Scope::new(), &ast, function, args)
+        .map(drop)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(source: &str) -> Result<(), String> {
+        let (requests, _) = mpsc::unbounded_channel();
+        run_script(
+            "test",
+            source,
+            "step",
+            json!({}),
+            json!({}),
+            requests,
+            Arc::new(AtomicBool::new(false)),
+        )
+    }
+
+    #[test]
+    fn scripts_run_with_the_standard_functions() {
+        assert_eq!(
+            run(r#"fn step(job, config) { "a,b".split(",").len() }"#),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn scripts_cant_import_others() {
+        let dir = std::env::temp_dir().join(format!("wpsync-import-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let other = dir.join("other.rhai");
+        fs::write(&other, "fn hello() { 1 }").unwrap();
+        let source = format!(
+            r#"import {:?} as other; fn step(job, config) {{ other::hello() }}"#,
+            other.with_extension("").display().to_string()
+        );
+        let imported = run(&source);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(imported.is_err());
+    }
+
+    #[test]
+    fn eval_is_off() {
+        assert!(run(r#"fn step(job, config) { eval("1") }"#).is_err());
+    }
+}
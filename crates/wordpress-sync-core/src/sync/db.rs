@@ -0,0 +1,618 @@
+//! Database phases: export from the source, URL replacement, and import on the destination.
+//!
+//! The dump always ends up complete on this machine before anything is imported,
+//! so an export that fails part way can't leave the destination half-overwritten.
+//! It crosses the SSH channel gzip-compressed, unless the profile turns
+//! compression off, and never touches the server's disk:
+//! a pull streams `wp db export -` from the server into a local file, and a push
+//! streams the local file into `wp db import -` on the server.
+
+use std::io::Write;
+use std::path::Path;
+
+use flate2::write::{GzDecoder, GzEncoder};
+use russh::ChannelMsg;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use super::at_rest;
+use super::endpoint::Endpoint;
+use super::local;
+use super::mysql;
+use super::remote::{self, Remote};
+use super::transfer::{OnProgress, TransferStats};
+use crate::messages;
+use crate::profile::{Compression, TableSelection};
+use crate::search_replace::{self, CollationRemap, PrefixRemap, Replacer};
+use crate::{shell, ssh, stderr};
+
+/// Read size when streaming a dump to the server; also how often progress is reported.
+const CHUNK_SIZE: usize = 256 * 1024;
+
+/// Dump the source database into `dump_path` on this machine. Progress counts
+/// bytes as they arrive: as sent over the wire, at `compression`, for a remote
+/// source, raw SQL for a local one.
+///
+/// Only the tables `selection` picks are dumped, so the destination keeps its
+/// own copy of the rest, and of a table one of the `filters` names only the
+/// rows it keeps. Each filtered table is dumped on its own after the others.
+pub async fn export(
+    source: &Endpoint<'_>,
+    selection: &TableSelection,
+    filters: &[RowFilter],
+    dump_path: &Path,
+    compression: Compression,
+    on_progress: OnProgress<'_>,
+) -> Result<(), String> {
+    let base = ["db", "export", "-", "--add-drop-table"].map(String::from);
+    let selected = if selection.is_all() {
+        None
+    } else {
+        let all = tables(source).await?;
+        let selected: Vec<String> = selection
+            .select(&all)?
+            .into_iter()
+            .map(String::from)
+            .collect();
+        if selected.is_empty() {
+            return Err(messages::text(
+                "error.profiles_table_rules_dont_select_any",
+                &[],
+            ));
+        }
+        Some(selected)
+    };
+    let filters: Vec<&RowFilter> = filters
+        .iter()
+        .filter(|filter| {
+            selected
+                .as_ref()
+                .is_none_or(|tables| tables.contains(&filter.table))
+        })
+        .collect();
+    let filtered = |table: &String| filters.iter().any(|filter| &filter.table == table);
+    let mut exports = Vec::new();
+    match &selected {
+        None if filters.is_empty() => exports.push(base.to_vec()),
+        None => {
+            let mut args = base.to_vec();
+            let excluded: Vec<&str> = filters.iter().map(|filter| filter.table.as_str()).collect();
+            args.push(format!("--exclude_tables={}", excluded.join(",")));
+            exports.push(args);
+        }
+        Some(selected) => {
+            let rest: Vec<&str> = selected
+                .iter()
+                .filter(|table| !filtered(table))
+                .map(String::as_str)
+                .collect();
+            if !rest.is_empty() {
+                let mut args = base.to_vec();
+                args.push(format!("--tables={}", rest.join(",")));
+                exports.push(args);
+            }
+        }
+    }
+    for filter in &filters {
+        let mut args = base.to_vec();
+        args.push(format!("--tables={}", filter.table));
+        args.push(format!("--where={}", filter.condition));
+        exports.push(args);
+    }
+    let mut file = at_rest::create(dump_path).await?;
+    match source {
+        Endpoint::Local(_) => {
+            let mut stats = TransferStats::default();
+            for args in &exports {
+                let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                let cmd = source.wp_command(&args);
+                let before = stats.bytes_transferred;
+                local::copy_stdout(cmd, "wp db export", &mut file, |bytes| {
+                    stats.bytes_transferred = before + bytes;
+                    on_progress(&stats);
+                })
+                .await?;
+            }
+            file.finish().await
+        }
+        Endpoint::Remote { .. } => {
+            let mut lines = Vec::new();
+            for args in &exports {
+                let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                lines.push(source.wp_line(&args).await?);
+            }
+            let export = lines.join(" && ");
+            let level = compression.level();
+            let command = match level {
+                Some(level) => first_status(&export, &format!("gzip -{level} -c")),
+                None => export,
+            };
+            let gzipped = level.is_some();
+            receive_dump(source.session()?, &command, &mut file, gzipped, on_progress).await
+        }
+    }
+}
+
+/// Rows of `table` left out of a dump: only those matching the SQL
+/// `condition` are exported.
+#[derive(Debug, Clone)]
+pub struct RowFilter {
+    pub table: String,
+    pub condition: String,
+}
+
+/// Option names, as `LIKE` patterns, of rows that only cache what WordPress or
+/// a plugin builds again: transients, and the sessions older WooCommerce and WP
+/// Session Manager kept among the options.
+const CACHE_OPTIONS: &[&str] = &[
+    "\\_transient\\_%",
+    "\\_site\\_transient\\_%",
+    "\\_wc\\_session\\_%",
+    "\\_wp\\_session\\_%",
+];
+
+/// Filters leaving cache rows out of each options table among `tables`, a
+/// network subsite's included, and site transients out of the network's meta.
+pub fn cache_row_filters(tables: &[String], prefix: &str) -> Vec<RowFilter> {
+    let not_like = |column: &str, patterns: &[&str]| {
+        patterns
+            .iter()
+            .map(|pattern| format!("{column} NOT LIKE '{pattern}'"))
+            .collect::<Vec<_>>()
+            .join(" AND ")
+    };
+    tables
+        .iter()
+        .filter_map(|table| {
+            let name = table.strip_prefix(prefix)?;
+            let site_options = name
+                .strip_suffix("_options")
+                .is_some_and(|blog| !blog.is_empty() && blog.bytes().all(|b| b.is_ascii_digit()));
+            let condition = if name == "options" || site_options {
+                not_like("option_name", CACHE_OPTIONS)
+            } else if name == "sitemeta" {
+                not_like("meta_key", &[CACHE_OPTIONS[1]])
+            } else {
+                return None;
+            };
+            Some(RowFilter {
+                table: table.clone(),
+                condition,
+            })
+        })
+        .collect()
+}
+
+/// Replace the destination database with the dump at `dump_path` on this
+/// machine. Progress counts the bytes sent, at `compression`, for a remote
+/// destination.
+pub async fn import(
+    dest: &Endpoint<'_>,
+    dump_path: &Path,
+    compression: Compression,
+    on_progress: OnProgress<'_>,
+) -> Result<(), String> {
+    match dest {
+        Endpoint::Local(_) => import_local(dest, dump_path).await,
+        Endpoint::Remote { .. } => {
+            let import = dest.wp_line(&["db", "import", "-"]).await?;
+            let level = compression.level();
+            let command = match level {
+                Some(_) => format!("gunzip -c | {import}"),
+                None => import,
+            };
+            send_dump(dest.session()?, &command, dump_path, level, on_progress).await
+        }
+    }
+}
+
+/// [`import`], keeping the destination's user tables as they were: they are
+/// saved to `snapshot_path` on this machine first and restored afterwards.
+pub async fn import_preserving_users(
+    dest: &Endpoint<'_>,
+    dump_path: &Path,
+    snapshot_path: &Path,
+    compression: Compression,
+    on_progress: OnProgress<'_>,
+) -> Result<(), String> {
+    let users = user_tables(dest).await?;
+    if users.is_empty() {
+        return import(dest, dump_path, compression, on_progress).await;
+    }
+    let selection = TableSelection {
+        include: users,
+        exclude: Vec::new(),
+    };
+    export(
+        dest,
+        &selection,
+        &[],
+        snapshot_path,
+        compression,
+        &mut |_| {},
+    )
+    .await?;
+    let imported = import(dest, dump_path, compression, on_progress).await;
+    // Restore even after a failed import, which may already have dropped them.
+    let restored = import(dest, snapshot_path, compression, &mut |_| {}).await;
+    imported.and(restored)
+}
+
+/// The install's `users` and `usermeta` tables, those of them that exist.
+pub async fn user_tables(side: &Endpoint<'_>) -> Result<Vec<String>, String> {
+    let prefix = table_prefix(side).await?;
+    let existing = tables(side).await?;
+    Ok(["users", "usermeta"]
+        .into_iter()
+        .map(|name| format!("{prefix}{name}"))
+        .filter(|table| existing.contains(table))
+        .collect())
+}
+
+/// The install's `$table_prefix`.
+pub async fn table_prefix(side: &Endpoint<'_>) -> Result<String, String> {
+    let prefix = side.wp(&["config", "get", "table_prefix"]).await?;
+    Ok(prefix.trim().to_string())
+}
+
+/// Whether the install is a multisite network.
+pub async fn is_multisite(side: &Endpoint<'_>) -> Result<bool, String> {
+    let output = side
+        .wp(&["eval", "echo is_multisite() ? 'yes' : 'no';"])
+        .await?;
+    Ok(output.trim_end().ends_with("yes"))
+}
+
+/// Tables of a multisite network that belong to the network rather than to
+/// any one site, after the table prefix. Users are shared by every site.
+const NETWORK_TABLES: &[&str] = &[
+    "blogs",
+    "blogmeta",
+    "blog_versions",
+    "registration_log",
+    "signups",
+    "site",
+    "sitemeta",
+    "users",
+    "usermeta",
+];
+
+/// The tables of `all` that belong to subsite `id` of a network whose table
+/// prefix is `prefix`. The main site (id 1) has the unnumbered tables, less the
+/// network's own.
+pub fn subsite_tables<'a>(all: &'a [String], prefix: &str, id: u64) -> Vec<&'a str> {
+    all.iter()
+        .map(String::as_str)
+        .filter(|table| {
+            let Some(name) = table.strip_prefix(prefix) else {
+                return false;
+            };
+            if id != 1 {
+                return name.starts_with(&format!("{id}_"));
+            }
+            let numbered = name.split_once('_').is_some_and(|(blog, _)| {
+                !blog.is_empty() && blog.bytes().all(|b| b.is_ascii_digit())
+            });
+            !numbered && !NETWORK_TABLES.contains(&name)
+        })
+        .collect()
+}
+
+/// Save the whole database to `path` on the install's own side, where it stays
+/// as a restore point. The server's copy is gzipped.
+pub async fn back_up(side: &Endpoint<'_>, path: &str) -> Result<(), String> {
+    match side {
+        // Through this process, to be encrypted as it's written.
+        Endpoint::Local(_) => {
+            let mut file = at_rest::create(Path::new(path)).await?;
+            let cmd = side.wp_command(&["db", "export", "-", "--add-drop-table"]);
+            local::copy_stdout(cmd, "wp db export", &mut file, |_| {}).await?;
+            file.finish().await
+        }
+        Endpoint::Remote { .. } => {
+            let export = side
+                .wp_line(&["db", "export", "-", "--add-drop-table"])
+                .await?;
+            let path = shell::quote(path);
+            // The pipeline reports wp-cli's status, so gzip is checked separately.
+            let save = format!("{{ gzip -c > {path}; }}");
+            let command = format!("{} && gzip -t {path}", first_status(&export, &save));
+            side.session()?.exec(&command).await.map(drop)
+        }
+    }
+}
+
+/// Save the dump at `dump_path` on this machine to `path` on the server,
+/// gzipped as [`back_up`] leaves one there, for a server without wp-cli.
+pub async fn upload_backup(
+    side: &Endpoint<'_>,
+    dump_path: &Path,
+    path: &str,
+) -> Result<(), String> {
+    let command = format!("cat > {}", shell::quote(path));
+    let level = Compression::default().level();
+    send_dump(side.session()?, &command, dump_path, level, &mut |_| {}).await
+}
+
+/// Fetch the server's backup at `path`, saved by [`back_up`] or
+/// [`upload_backup`], into `dump_path` on this machine as plain SQL.
+pub async fn download_backup(
+    side: &Endpoint<'_>,
+    path: &str,
+    dump_path: &Path,
+) -> Result<(), String> {
+    let mut file = at_rest::create(dump_path).await?;
+    let command = format!("cat {}", shell::quote(path));
+    receive_dump(side.session()?, &command, &mut file, true, &mut |_| {}).await
+}
+
+/// Replace the database with one saved by [`back_up`].
+pub async fn restore(side: &Endpoint<'_>, path: &str) -> Result<(), String> {
+    match side {
+        Endpoint::Local(_) => import_local(side, Path::new(path)).await,
+        Endpoint::Remote { .. } => {
+            let path = shell::quote(path);
+            let import = side.wp_line(&["db", "import", "-"]).await?;
+            // Test the whole file first so a damaged backup can't be half imported.
+            let command = format!("gzip -t {path} && gunzip -c {path} | {import}");
+            side.session()?.exec(&command).await.map(drop)
+        }
+    }
+}
+
+/// Import the encrypted SQL file at `path` on this machine into the local
+/// install, decrypted into wp-cli's stdin.
+async fn import_local(side: &Endpoint<'_>, path: &Path) -> Result<(), String> {
+    let cmd = side.wp_command(&["db", "import", "-"]);
+    local::feed_stdin(cmd, "wp db import", path).await
+}
+
+/// Run `command` on the server and save its output into `file`, gunzipping it
+/// if it is `gzipped`.
+async fn receive_dump(
+    remote: &Remote,
+    command: &str,
+    file: &mut at_rest::Writer,
+    gzipped: bool,
+    on_progress: OnProgress<'_>,
+) -> Result<(), String> {
+    let write_failed =
+        |e: std::io::Error| messages::text("error.failed.save_database_dump", &[("error", &e)]);
+    let mut channel = remote.open_exec(command).await?;
+    let mut decoder = gzipped.then(|| GzDecoder::new(Vec::new()));
+    let mut stats = TransferStats::default();
+    let mut stderr = Vec::new();
+    let mut exit_status = None;
+    while let Some(msg) = channel.wait().await {
+        match msg {
+            ChannelMsg::Data { data } => {
+                let sql = match &mut decoder {
+                    Some(decoder) => {
+                        decoder.write_all(&data).map_err(|e| {
+                            messages::text("error.database_dump_server_corrupt", &[("error", &e)])
+                        })?;
+                        std::mem::take(decoder.get_mut())
+                    }
+                    None => data.to_vec(),
+                };
+                file.write_all(&sql).await.map_err(write_failed)?;
+                stats.bytes_transferred += data.len() as u64;
+                on_progress(&stats);
+            }
+            // Extended data type 1 is stderr
+            ChannelMsg::ExtendedData { data, ext: 1 } => stderr::push(&mut stderr, &data),
+            ChannelMsg::ExitStatus { exit_status: code } => exit_status = Some(code),
+            _ => {}
+        }
+    }
+    ssh::audit_exit(command, exit_status);
+    if exit_status != Some(0) {
+        return Err(remote::command_failed(command, exit_status, &stderr));
+    }
+    // The gzip trailer is the only proof the whole dump arrived; uncompressed,
+    // wp-cli's exit status has to do.
+    if let Some(mut decoder) = decoder {
+        decoder
+            .try_finish()
+            .map_err(|_| messages::text("error.database_dump_server_was_cut_short", &[]))?;
+        file.write_all(decoder.get_ref())
+            .await
+            .map_err(write_failed)?;
+    }
+    file.finish().await
+}
+
+/// Run `command` on the server with the contents of `path` as its stdin,
+/// gzipped at `level` if there is one.
+async fn send_dump(
+    remote: &Remote,
+    command: &str,
+    path: &Path,
+    level: Option<u32>,
+    on_progress: OnProgress<'_>,
+) -> Result<(), String> {
+    let mut channel = remote.open_exec(command).await?;
+    let mut writer = channel.make_writer();
+    let send = async {
+        let mut file = at_rest::open(path).await?;
+        let send_failed =
+            |e: std::io::Error| messages::text("error.failed.send_database_dump", &[("error", &e)]);
+        let mut encoder =
+            level.map(|level| GzEncoder::new(Vec::new(), flate2::Compression::new(level)));
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        let mut stats = TransferStats::default();
+        loop {
+            let n = file.read(&mut buf).await.map_err(|e| {
+                messages::text(
+                    "error.failed.read_path",
+                    &[("path", &path.display()), ("error", &e)],
+                )
+            })?;
+            if n == 0 {
+                break;
+            }
+            let chunk = match &mut encoder {
+                Some(encoder) => {
+                    encoder.write_all(&buf[..n]).map_err(send_failed)?;
+                    std::mem::take(encoder.get_mut())
+                }
+                None => buf[..n].to_vec(),
+            };
+            writer.write_all(&chunk).await.map_err(send_failed)?;
+            stats.bytes_transferred += chunk.len() as u64;
+            on_progress(&stats);
+        }
+        if let Some(encoder) = encoder {
+            let rest = encoder.finish().map_err(send_failed)?;
+            writer.write_all(&rest).await.map_err(send_failed)?;
+        }
+        // Shutting the writer down sends EOF, which lets the import finish.
+        writer.shutdown().await.map_err(send_failed)
+    };
+    let receive = async {
+        let mut stderr = Vec::new();
+        let mut exit_status = None;
+        while let Some(msg) = channel.wait().await {
+            match msg {
+                ChannelMsg::ExtendedData { data, ext: 1 } => stderr::push(&mut stderr, &data),
+                ChannelMsg::ExitStatus { exit_status: code } => exit_status = Some(code),
+                _ => {}
+            }
+        }
+        (exit_status, stderr)
+    };
+    let (sent, (exit_status, stderr)) = tokio::join!(send, receive);
+    ssh::audit_exit(command, exit_status);
+    // A command that dies early also breaks the upload; its own error says why.
+    if exit_status != Some(0) {
+        return Err(remote::command_failed(command, exit_status, &stderr));
+    }
+    sent
+}
+
+/// `first | second`, exiting with `first`'s status rather than `second`'s.
+///
+/// POSIX sh has no `pipefail`, so the status travels out of the pipeline on
+/// file descriptor 3 while `second`'s output goes to the real stdout on 4.
+fn first_status(first: &str, second: &str) -> String {
+    format!("{{ status=$( {{ {{ {first}; echo $? >&3; }} | {second} >&4; }} 3>&1 ); exit $status; }} 4>&1")
+}
+
+/// Columns never rewritten. GUIDs are left alone, as WordPress recommends.
+pub(super) const SKIP_COLUMNS: &[&str] = &["guid"];
+
+/// The `wp search-replace` option equivalent to [`SKIP_COLUMNS`].
+const SKIP_COLUMNS_OPTION: &str = "--skip-columns=guid";
+
+/// Apply the `(from, to)` pairs throughout the dump at `dump_path`, before it
+/// is imported, rename its tables if `prefix` is given, and apply
+/// `collations` to its definitions. PHP-serialized values are kept valid.
+/// Progress counts bytes of the dump processed.
+pub async fn search_replace(
+    dump_path: &Path,
+    pairs: &[(String, String)],
+    prefix: Option<&PrefixRemap>,
+    collations: &[CollationRemap],
+    on_progress: OnProgress<'_>,
+) -> Result<(), String> {
+    let replacer = Replacer::new(pairs.iter().map(|(from, to)| (from, to)));
+    let mut stats = TransferStats::default();
+    search_replace::rewrite_dump(
+        dump_path,
+        &replacer,
+        SKIP_COLUMNS,
+        prefix,
+        collations,
+        |bytes| {
+            stats.bytes_transferred = bytes;
+            on_progress(&stats);
+        },
+    )
+    .await
+}
+
+/// How many values replacing `from` with `to` will change, counted with wp-cli
+/// on the source database before it is exported. Each pair is counted on its
+/// own, so values matched by several pairs are counted for each.
+///
+/// All tables with the install's prefix are searched, across the whole network
+/// for a multisite, unless `tables` names the only ones to search.
+pub async fn count_replacements(
+    source: &Endpoint<'_>,
+    from: &str,
+    to: &str,
+    tables: Option<&[String]>,
+) -> Result<u64, String> {
+    if from == to {
+        return Ok(0);
+    }
+    let mut args = vec!["search-replace", from, to, SKIP_COLUMNS_OPTION];
+    match tables {
+        Some(tables) => args.extend(tables.iter().map(String::as_str)),
+        None => args.push("--all-tables-with-prefix"),
+    }
+    args.extend(["--dry-run", "--format=count"]);
+    let output = source.wp(&args).await?;
+    output.trim().parse().map_err(|_| {
+        messages::text(
+            "error.unexpected_output_wp_search_replace",
+            &[("output", &output.trim())],
+        )
+    })
+}
+
+/// The size of each table in the install's database, data and indexes, as
+/// `information_schema` reckons it. A dump usually comes out a little smaller.
+pub async fn table_sizes(side: &Endpoint<'_>) -> Result<Vec<(String, u64)>, String> {
+    let query = "SELECT table_name, COALESCE(data_length + index_length, 0) \
+                 FROM information_schema.TABLES WHERE table_schema = DATABASE()";
+    let rows = self::query(side, query).await?;
+    rows.iter()
+        .map(|row| match row.as_slice() {
+            [table, size] => Some((table.clone(), size.trim().parse().ok()?)),
+            _ => None,
+        })
+        .collect::<Option<_>>()
+        .ok_or_else(|| {
+            messages::text(
+                "error.unexpected_table_sizes_database_rows",
+                &[("rows", &format_args!("{rows:?}"))],
+            )
+        })
+}
+
+/// The rows an SQL `query` returns from the install's database, through
+/// wp-cli, each a list of its values as text.
+pub async fn query(side: &Endpoint<'_>, query: &str) -> Result<Vec<Vec<String>>, String> {
+    let output = side
+        .wp(&["db", "query", query, "--skip-column-names"])
+        .await?;
+    Ok(output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.split('\t').map(String::from).collect())
+        .collect())
+}
+
+/// The column names and first `limit` rows an SQL `query` returns from the
+/// install's database, through wp-cli, for the SQL console.
+pub async fn query_table(
+    side: &Endpoint<'_>,
+    query: &str,
+    limit: usize,
+) -> Result<(Vec<String>, Vec<Vec<String>>), String> {
+    let mut table = mysql::BatchTable::new(limit);
+    side.wp_stdout(&["db", "query", query], |data| table.push(data))
+        .await?;
+    Ok(table.finish())
+}
+
+/// Every table in the install's database, which is what [`export`] dumps.
+pub async fn tables(side: &Endpoint<'_>) -> Result<Vec<String>, String> {
+    let output = side.wp(&["db", "tables", "--all-tables"]).await?;
+    Ok(output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(String::from)
+        .collect())
+}
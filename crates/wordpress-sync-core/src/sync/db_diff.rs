@@ -0,0 +1,180 @@
+//! How the local and the server's databases differ, table by table: which
+//! tables each has, and about how many rows and bytes are in them, for the
+//! app to show before a sync what it would change, or after one that it did.
+//!
+//! Both sides are asked `information_schema`, which answers straight away on
+//! the largest database. Its row counts are InnoDB's estimates, though, close
+//! enough to spot a table missing most of its rows but not one or two.
+//! Tables are matched by their names after each side's table prefix, so two
+//! sites with different prefixes compare the same tables.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::endpoint::Endpoint;
+use super::mysql::{self, Mysql};
+use super::{connect_ssh, db, ProgressSink};
+use crate::error::SyncError;
+use crate::messages::{self, Message};
+use crate::profile::Profile;
+use crate::telemetry::{self, Feature};
+
+const TABLE_STATS: &str = "SELECT table_name, COALESCE(table_rows, 0), \
+                           COALESCE(data_length + index_length, 0) \
+                           FROM information_schema.TABLES WHERE table_schema = DATABASE()";
+
+/// The two databases, side by side.
+#[derive(Debug, Clone, Serialize)]
+pub struct DatabaseDiff {
+    pub local_prefix: String,
+    pub remote_prefix: String,
+    /// Every table either side has, by name after its prefix.
+    pub tables: Vec<TableDiff>,
+    pub local_bytes: u64,
+    pub remote_bytes: u64,
+    pub compared_at: DateTime<Utc>,
+}
+
+/// One table, on whichever sides have it.
+#[derive(Debug, Clone, Serialize)]
+pub struct TableDiff {
+    /// The table's name after the table prefix, or its whole name if it
+    /// hasn't the prefix.
+    pub name: String,
+    pub local: Option<TableStats>,
+    pub remote: Option<TableStats>,
+    /// Whether only one side has the table, or the sides' row counts differ.
+    pub differs: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TableStats {
+    /// The table's name in full.
+    pub table: String,
+    /// About how many rows it has.
+    pub rows: u64,
+    /// Its data and indexes.
+    pub bytes: u64,
+}
+
+/// Compare `profile`'s local database with the server's.
+pub async fn diff_databases(
+    profile: &Profile,
+    sink: &dyn ProgressSink,
+) -> Result<DatabaseDiff, SyncError> {
+    telemetry::record(Feature::DatabaseDiff);
+    if !profile.remote.transfer.uses_ssh() {
+        return Err(SyncError::Config(Message::new(
+            "error.no_ssh.database_read",
+            &[],
+        )));
+    }
+    let local = Endpoint::Local(&profile.local);
+    let local_prefix = db::table_prefix(&local)
+        .await
+        .map_err(SyncError::remote_command)?;
+    let local_stats = db::query(&local, TABLE_STATS)
+        .await
+        .and_then(|rows| table_stats(&rows))
+        .map_err(SyncError::database)?;
+
+    let remote = connect_ssh(profile, sink).await?;
+    let result = async {
+        match mysql::open_without_wp_cli(profile, &remote).await? {
+            Some(mysql) => remote_stats(&mysql).await,
+            None => {
+                let endpoint = Endpoint::Remote {
+                    remote: Some(&remote),
+                    env: &profile.remote,
+                };
+                let prefix = db::table_prefix(&endpoint).await?;
+                let rows = db::query(&endpoint, TABLE_STATS).await?;
+                Ok((prefix, table_stats(&rows)?))
+            }
+        }
+    }
+    .await;
+    remote.close().await;
+    let (remote_prefix, remote_stats) = result.map_err(SyncError::database)?;
+
+    let mut tables: BTreeMap<String, TableDiff> = BTreeMap::new();
+    for (prefix, stats, is_local) in [
+        (&local_prefix, local_stats, true),
+        (&remote_prefix, remote_stats, false),
+    ] {
+        for stats in stats {
+            let name = stats
+                .table
+                .strip_prefix(prefix.as_str())
+                .filter(|name| !name.is_empty())
+                .unwrap_or(&stats.table)
+                .to_string();
+            let entry = tables.entry(name.clone()).or_insert_with(|| TableDiff {
+                name,
+                local: None,
+                remote: None,
+                differs: true,
+            });
+            match is_local {
+                true => entry.local = Some(stats),
+                false => entry.remote = Some(stats),
+            }
+        }
+    }
+    let mut tables: Vec<TableDiff> = tables.into_values().collect();
+    for table in &mut tables {
+        table.differs = match (&table.local, &table.remote) {
+            (Some(local), Some(remote)) => local.rows != remote.rows,
+            _ => true,
+        };
+    }
+    let total = |side: fn(&TableDiff) -> Option<&TableStats>| {
+        tables
+            .iter()
+            .filter_map(side)
+            .map(|stats| stats.bytes)
+            .sum()
+    };
+    let diff = DatabaseDiff {
+        local_bytes: total(|table| table.local.as_ref()),
+        remote_bytes: total(|table| table.remote.as_ref()),
+        local_prefix,
+        remote_prefix,
+        tables,
+        compared_at: Utc::now(),
+    };
+    tracing::info!(
+        tables = diff.tables.len(),
+        differing = diff.tables.iter().filter(|table| table.differs).count(),
+        "compared the local and remote databases"
+    );
+    Ok(diff)
+}
+
+/// The server's prefix and tables, from a direct connection to its database.
+async fn remote_stats(mysql: &Mysql) -> Result<(String, Vec<TableStats>), String> {
+    let rows = mysql.query(TABLE_STATS).await?;
+    Ok((mysql.table_prefix().to_string(), table_stats(&rows)?))
+}
+
+/// Each table's stats from the rows of [`TABLE_STATS`].
+fn table_stats(rows: &[Vec<String>]) -> Result<Vec<TableStats>, String> {
+    rows.iter()
+        .map(|row| match row.as_slice() {
+            [table, count, bytes] => Some(TableStats {
+                table: table.clone(),
+                rows: count.trim().parse().ok()?,
+                bytes: bytes.trim().parse().ok()?,
+            }),
+            _ => None,
+        })
+        .collect::<Option<_>>()
+        .ok_or_else(|| {
+            messages::text(
+                "error.unexpected_table_stats_database_rows",
+                &[("rows", &format_args!("{rows:?}"))],
+            )
+        })
+}
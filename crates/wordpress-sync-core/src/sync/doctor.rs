@@ -0,0 +1,429 @@
+//! Self-diagnostics: a checklist of what syncs need from this machine, run
+//! from the Troubleshooting panel when something goes wrong before any one
+//! profile is to blame.
+//!
+//! Nothing is changed, apart from a probe secret and a probe file that are
+//! removed again straight away.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use super::local;
+use super::preflight::CheckStatus;
+use super::space::{local_free, size};
+use super::temp;
+use super::transfer;
+use crate::credentials::{platform_store, CredentialStore, SERVICE_PREFIX};
+use crate::messages;
+use crate::net::AddressFamily;
+use crate::paths;
+use crate::profile::{self, Profile, Severity, TransferMethod};
+use crate::proxy::{self, ProxyChoice};
+use crate::schedule::{self, email};
+
+/// Below this much free space, syncs may still fit, but backups soon won't.
+const LOW_SPACE: u64 = 2 * 1024 * 1024 * 1024;
+
+/// Below this much, a pull's dump or backup is all but sure to fail.
+const NO_SPACE: u64 = 200 * 1024 * 1024;
+
+/// How long reaching each server may take.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What a [`DoctorCheck`] looks at, for the frontend to label and match on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DoctorCheckKind {
+    /// rsync is installed here, for profiles that transfer with it.
+    Rsync,
+    /// The OpenSSH client, which syncs don't need but terminals and keys do.
+    Ssh,
+    /// Secrets can be stored in, read from, and removed from the credential store.
+    Keychain,
+    /// There is room for dumps and backups in the temporary and app data directories.
+    DiskSpace,
+    /// The app data directory is there, writable, and not writable by others.
+    DataDirectory,
+    /// Every profile's server answers on its port, through its proxy if it
+    /// has one.
+    Network,
+    /// Every profile, the schedules, and the email and proxy settings read
+    /// back and are valid.
+    Config,
+}
+
+/// The outcome of one check.
+#[derive(Debug, Clone, Serialize)]
+pub struct DoctorCheck {
+    pub check: DoctorCheckKind,
+    pub status: CheckStatus,
+    /// What was found, e.g. a version, or what is wrong.
+    pub detail: String,
+}
+
+/// Every check, in the order they ran.
+#[derive(Debug, Clone, Serialize)]
+pub struct DoctorReport {
+    /// No check failed; warnings don't count.
+    pub healthy: bool,
+    pub checks: Vec<DoctorCheck>,
+}
+
+/// Check this machine's setup for syncing.
+pub async fn doctor() -> DoctorReport {
+    let profiles = profile::list().unwrap_or_default();
+    let (rsync, ssh, network, config) =
+        tokio::join!(rsync(&profiles), ssh(), network(&profiles), config(),);
+    // The credential store blocks, and on Linux runs a runtime of its own.
+    let keychain = tokio::task::spawn_blocking(keychain)
+        .await
+        .unwrap_or_else(|e| {
+            check(
+                DoctorCheckKind::Keychain,
+                CheckStatus::Failed,
+                e.to_string(),
+            )
+        });
+    let checks = vec![
+        rsync,
+        ssh,
+        keychain,
+        disk_space(),
+        data_directory(),
+        network,
+        config,
+    ];
+    DoctorReport {
+        healthy: checks
+            .iter()
+            .all(|check| check.status != CheckStatus::Failed),
+        checks,
+    }
+}
+
+fn check(check: DoctorCheckKind, status: CheckStatus, detail: impl Into<String>) -> DoctorCheck {
+    DoctorCheck {
+        check,
+        status,
+        detail: detail.into(),
+    }
+}
+
+async fn rsync(profiles: &[Profile]) -> DoctorCheck {
+    let needed = profiles
+        .iter()
+        .any(|profile| profile.remote.transfer == TransferMethod::Rsync);
+    match transfer::local_rsync(None).await {
+        (program, Some(version)) => check(
+            DoctorCheckKind::Rsync,
+            CheckStatus::Passed,
+            messages::text(
+                "doctor.rsync_version_at",
+                &[("version", &version), ("path", &program.display())],
+            ),
+        ),
+        (_, None) if needed => check(
+            DoctorCheckKind::Rsync,
+            CheckStatus::Failed,
+            messages::text("doctor.rsync_missing_needed", &[]),
+        ),
+        (_, None) => check(
+            DoctorCheckKind::Rsync,
+            CheckStatus::Skipped,
+            messages::text("doctor.rsync_missing_unneeded", &[]),
+        ),
+    }
+}
+
+async fn ssh() -> DoctorCheck {
+    // `ssh -V` prints its version to stderr.
+    let output = local::command("ssh").arg("-V").output().await;
+    match output {
+        Ok(output) if output.status.success() => {
+            let version = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            check(DoctorCheckKind::Ssh, CheckStatus::Passed, version)
+        }
+        _ => check(
+            DoctorCheckKind::Ssh,
+            CheckStatus::Skipped,
+            messages::text("doctor.ssh_missing", &[]),
+        ),
+    }
+}
+
+fn keychain() -> DoctorCheck {
+    let store = platform_store();
+    let service = format!("{SERVICE_PREFIX}doctor");
+    let probe = uuid::Uuid::new_v4().to_string();
+    let result = store
+        .set(&service, "probe", &probe)
+        .and_then(|()| store.get(&service, "probe"))
+        .and_then(|read| match read {
+            Some(read) if read == probe => Ok(()),
+            _ => Err(messages::text("error.stored_secret_didnt_read_back", &[])),
+        });
+    let removed = store.delete(&service, "probe");
+    match result.and(removed) {
+        Ok(()) => check(
+            DoctorCheckKind::Keychain,
+            CheckStatus::Passed,
+            messages::text("doctor.secrets_work", &[]),
+        ),
+        Err(e) => check(DoctorCheckKind::Keychain, CheckStatus::Failed, e),
+    }
+}
+
+fn disk_space() -> DoctorCheck {
+    let dirs = [
+        ("doctor.free_in_temp_dir", Ok(temp::root().to_path_buf())),
+        ("doctor.free_in_data_dir", paths::config_dir()),
+    ];
+    let mut status = CheckStatus::Passed;
+    let mut found = Vec::new();
+    for (id, dir) in dirs {
+        let free = dir.and_then(|dir| local_free(&dir));
+        match free {
+            Ok(free) => {
+                let here = match free {
+                    free if free < NO_SPACE => CheckStatus::Failed,
+                    free if free < LOW_SPACE => CheckStatus::Warning,
+                    _ => CheckStatus::Passed,
+                };
+                status = worse(status, here);
+                found.push(messages::text(id, &[("size", &size(free))]));
+            }
+            Err(e) => {
+                status = worse(status, CheckStatus::Warning);
+                found.push(e);
+            }
+        }
+    }
+    check(DoctorCheckKind::DiskSpace, status, found.join("; "))
+}
+
+fn data_directory() -> DoctorCheck {
+    let result = paths::config_dir().and_then(|dir| {
+        fs::create_dir_all(&dir).map_err(|e| {
+            messages::text(
+                "error.failed.create_path",
+                &[("path", &dir.display()), ("error", &e)],
+            )
+        })?;
+        let probe = dir.join(".doctor-probe");
+        fs::write(&probe, b"").map_err(|e| {
+            messages::text(
+                "error.path_isnt_writable",
+                &[("path", &dir.display()), ("error", &e)],
+            )
+        })?;
+        let _ = fs::remove_file(&probe);
+        Ok(dir)
+    });
+    let dir = match result {
+        Ok(dir) => dir,
+        Err(e) => return check(DoctorCheckKind::DataDirectory, CheckStatus::Failed, e),
+    };
+    match shared_with_others(&dir) {
+        true => check(
+            DoctorCheckKind::DataDirectory,
+            CheckStatus::Warning,
+            messages::text("doctor.data_dir_shared", &[("path", &dir.display())]),
+        ),
+        false => check(
+            DoctorCheckKind::DataDirectory,
+            CheckStatus::Passed,
+            messages::text("doctor.path_writable", &[("path", &dir.display())]),
+        ),
+    }
+}
+
+#[cfg(unix)]
+fn shared_with_others(dir: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(dir).is_ok_and(|meta| meta.permissions().mode() & 0o022 != 0)
+}
+
+#[cfg(not(unix))]
+fn shared_with_others(_dir: &Path) -> bool {
+    false
+}
+
+async fn network(profiles: &[Profile]) -> DoctorCheck {
+    let servers: BTreeMap<(String, u16), (ProxyChoice, AddressFamily)> = profiles
+        .iter()
+        .filter_map(|profile| {
+            let proxy = match profile.remote.transfer {
+                // FTP never goes through the proxy.
+                TransferMethod::Ftps => ProxyChoice::Direct,
+                _ => profile.remote.proxy.clone(),
+            };
+            Some((server(profile)?, (proxy, profile.remote.address_family)))
+        })
+        .collect();
+    if servers.is_empty() {
+        return check(
+            DoctorCheckKind::Network,
+            CheckStatus::Skipped,
+            messages::text("doctor.no_servers", &[]),
+        );
+    }
+    let count = servers.len();
+    // Reading a proxy's password blocks, like the keychain check.
+    let routes = tokio::task::spawn_blocking(move || {
+        servers
+            .into_iter()
+            .map(|(server, (proxy, family))| (server, proxy::route(&proxy), family))
+            .collect::<Vec<_>>()
+    })
+    .await
+    .unwrap_or_default();
+    let attempts = routes
+        .iter()
+        .map(|((host, port), route, family)| async move {
+            let route = match route {
+                Ok(route) => route.as_ref(),
+                Err(e) => {
+                    return Some(messages::text(
+                        "doctor.server_unreachable",
+                        &[("host", &host), ("port", &port), ("error", &e)],
+                    ))
+                }
+            };
+            let connected =
+                tokio::time::timeout(CONNECT_TIMEOUT, proxy::connect(route, host, *port, *family))
+                    .await;
+            match connected {
+                Ok(Ok(_)) => None,
+                Ok(Err(e)) => Some(messages::text(
+                    "doctor.server_unreachable",
+                    &[("host", &host), ("port", &port), ("error", &e)],
+                )),
+                Err(_) => Some(messages::text(
+                    "doctor.server_timed_out",
+                    &[("host", &host), ("port", &port)],
+                )),
+            }
+        });
+    let unreachable: Vec<String> = futures_util::future::join_all(attempts)
+        .await
+        .into_iter()
+        .flatten()
+        .collect();
+    match unreachable.len() {
+        0 => check(
+            DoctorCheckKind::Network,
+            CheckStatus::Passed,
+            messages::text("doctor.reached_all_servers", &[("count", &count)]),
+        ),
+        n => check(
+            DoctorCheckKind::Network,
+            CheckStatus::Failed,
+            messages::text(
+                "doctor.couldnt_reach_servers",
+                &[
+                    ("unreachable", &n),
+                    ("count", &count),
+                    ("servers", &unreachable.join(", ")),
+                ],
+            ),
+        ),
+    }
+}
+
+/// The host and port this machine first connects to for `profile`: its jump
+/// host, its FTP or SSH server, or for the REST API, its site.
+fn server(profile: &Profile) -> Option<(String, u16)> {
+    let remote = &profile.remote;
+    let (host, port) = match (&remote.jump_host, remote.transfer) {
+        (Some(jump), TransferMethod::Rsync | TransferMethod::Sftp) => {
+            (jump.host.clone(), jump.port)
+        }
+        (_, TransferMethod::Ftps) => (remote.host.clone(), remote.ftp.port),
+        (_, TransferMethod::RestApi) => {
+            let url = url::Url::parse(&remote.url).ok()?;
+            (url.host_str()?.to_string(), url.port_or_known_default()?)
+        }
+        _ => (remote.host.clone(), remote.port),
+    };
+    (!host.is_empty()).then_some((host, port))
+}
+
+async fn config() -> DoctorCheck {
+    let mut problems = Vec::new();
+    let mut count = 0;
+    match paths::profiles_dir().map(fs::read_dir) {
+        Ok(Ok(entries)) => {
+            for path in entries.filter_map(|entry| entry.ok().map(|e| e.path())) {
+                if path.extension().is_none_or(|ext| ext != "json") {
+                    continue;
+                }
+                let Some(id) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                    continue;
+                };
+                count += 1;
+                match profile::load(id) {
+                    Ok(loaded) => {
+                        let errors = profile::validate(&loaded)
+                            .await
+                            .into_iter()
+                            .filter(|issue| issue.severity == Severity::Error)
+                            .count();
+                        if errors > 0 {
+                            problems.push(messages::text(
+                                "doctor.profile_has_errors",
+                                &[("name", &loaded.name), ("count", &errors)],
+                            ));
+                        }
+                    }
+                    Err(e) => problems.push(e),
+                }
+            }
+        }
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Ok(Err(e)) => problems.push(messages::text(
+            "error.failed.list_profiles",
+            &[("error", &e)],
+        )),
+        Err(e) => problems.push(e),
+    }
+    if let Err(e) = schedule::list() {
+        problems.push(e);
+    }
+    if let Err(e) = email::settings() {
+        problems.push(e);
+    }
+    if let Err(e) = proxy::settings() {
+        problems.push(e);
+    }
+    match problems.is_empty() {
+        true => check(
+            DoctorCheckKind::Config,
+            CheckStatus::Passed,
+            messages::text("doctor.config_valid", &[("count", &count)]),
+        ),
+        false => check(
+            DoctorCheckKind::Config,
+            CheckStatus::Failed,
+            problems.join("; "),
+        ),
+    }
+}
+
+/// The more serious of two outcomes.
+fn worse(a: CheckStatus, b: CheckStatus) -> CheckStatus {
+    let rank = |status| match status {
+        CheckStatus::Passed | CheckStatus::Skipped => 0,
+        CheckStatus::Warning => 1,
+        CheckStatus::Failed => 2,
+    };
+    if rank(b) > rank(a) {
+        b
+    } else {
+        a
+    }
+}
@@ -0,0 +1,284 @@
+//! Noticing that the server changed after the last sync, before a push
+//! overwrites it.
+//!
+//! Each sync that finishes, in either direction, records a fingerprint of the
+//! server in `~/.wordpress-sync/drift/<profile-id>.json`: the `CHECKSUM TABLE`
+//! value of each synced table and a hash of the synced files' names, sizes,
+//! and modification times. A push's plan compares the server with it, so edits
+//! made there since, such as a client's in production, are flagged before the
+//! push replaces them. Checksumming reads every row, which takes a while on a
+//! large database, so `detect_drift` can turn it off.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::mysql::identifier;
+use super::transfer::Entry;
+use super::{db, multisite, Job};
+use crate::messages::{self, Message};
+use crate::paths;
+
+/// Options WordPress rewrites by itself, besides transients, left out of
+/// their table's checksum.
+const VOLATILE_OPTIONS: &[&str] = &["cron"];
+
+/// What changed on the server since the last sync.
+#[derive(Debug, Clone, Serialize)]
+pub struct Drift {
+    /// When the earliest of the fingerprints that no longer match was taken.
+    pub since: DateTime<Utc>,
+    /// Synced tables changed, added, or dropped on the server since.
+    pub tables: Vec<String>,
+    /// Whether any synced file was added, changed, or removed on the server since.
+    pub files_changed: bool,
+}
+
+impl Drift {
+    pub fn warning(&self) -> Message {
+        let since = self.since.format("%Y-%m-%d %H:%M");
+        let tables = self.tables.join(", ");
+        match (self.tables.is_empty(), self.files_changed) {
+            (false, true) => Message::new(
+                "warning.server_changed_tables_and_files",
+                &[("since", &since), ("tables", &tables)],
+            ),
+            (false, false) => Message::new(
+                "warning.server_changed_tables",
+                &[("since", &since), ("tables", &tables)],
+            ),
+            _ => Message::new("warning.server_changed_files", &[("since", &since)]),
+        }
+    }
+}
+
+/// The server as the last sync of a profile left it. Each part is only
+/// replaced by a sync that covers it.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Fingerprint {
+    #[serde(default)]
+    tables: Option<TablesPrint>,
+    #[serde(default)]
+    files: Option<FilesPrint>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TablesPrint {
+    recorded_at: DateTime<Utc>,
+    /// Each synced table's checksum; `NULL` for a view.
+    checksums: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FilesPrint {
+    recorded_at: DateTime<Utc>,
+    /// The directory listed, relative to the WordPress root, since an
+    /// uploads-only sync lists less than a full one.
+    dir: String,
+    /// The SHA-256 of the listing.
+    digest: String,
+}
+
+impl Fingerprint {
+    fn load(profile_id: &str) -> Result<Self, String> {
+        let path = fingerprint_path(profile_id)?;
+        match std::fs::read_to_string(&path) {
+            Ok(raw) => serde_json::from_str(&raw).map_err(|e| {
+                messages::text(
+                    "error.invalid_server_fingerprint_path",
+                    &[("path", &path.display()), ("error", &e)],
+                )
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(messages::text(
+                "error.failed.read_path",
+                &[("path", &path.display()), ("error", &e)],
+            )),
+        }
+    }
+
+    fn save(&self, profile_id: &str) -> Result<(), String> {
+        let path = fingerprint_path(profile_id)?;
+        let json = serde_json::to_string_pretty(self).map_err(|e| {
+            messages::text(
+                "error.failed.serialize_server_fingerprint",
+                &[("error", &e)],
+            )
+        })?;
+        let dir = paths::drift_dir()?;
+        std::fs::create_dir_all(&dir).map_err(|e| {
+            messages::text(
+                "error.failed.create_path",
+                &[("path", &dir.display()), ("error", &e)],
+            )
+        })?;
+        std::fs::write(&path, json).map_err(|e| {
+            messages::text(
+                "error.failed.write_path",
+                &[("path", &path.display()), ("error", &e)],
+            )
+        })
+    }
+}
+
+fn fingerprint_path(profile_id: &str) -> Result<PathBuf, String> {
+    Ok(paths::drift_dir()?.join(format!("{profile_id}.json")))
+}
+
+impl Job<'_> {
+    /// Whether this job's part of the server is fingerprinted: the profile
+    /// asks for it and the sync moves files or tables there.
+    fn detects_drift(&self) -> bool {
+        self.profile.detect_drift && (self.syncs_database() || self.transfers_files())
+    }
+
+    /// Each of the server's synced tables and its checksum. Options tables
+    /// are summed over their rows instead, leaving out the ones WordPress
+    /// rewrites on its own as it runs, which would make every live site look
+    /// edited.
+    async fn table_checksums(&self) -> Result<BTreeMap<String, String>, String> {
+        let remote = self.remote();
+        let all = self.tables(&remote).await?;
+        let tables: Vec<String> = self
+            .table_selection(&remote)
+            .await?
+            .select(&all)?
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let prefix = self.table_prefix(&remote).await?;
+        let filters = db::cache_row_filters(&tables, &prefix);
+        let plain: Vec<&String> = tables
+            .iter()
+            .filter(|table| !filters.iter().any(|filter| filter.table == **table))
+            .collect();
+        let mut checksums = BTreeMap::new();
+        if !plain.is_empty() {
+            let list: Vec<String> = plain.iter().map(|table| identifier(table)).collect();
+            let rows = self
+                .query(&remote, &format!("CHECKSUM TABLE {}", list.join(", ")))
+                .await?;
+            // The rows come back in the order the tables were named.
+            for (table, row) in plain.into_iter().zip(rows) {
+                checksums.insert(table.clone(), row.get(1).cloned().unwrap_or_default());
+            }
+        }
+        let volatile: Vec<String> = VOLATILE_OPTIONS
+            .iter()
+            .map(|name| format!("'{name}'"))
+            .collect();
+        for filter in filters {
+            let (key, value) = if filter.table.ends_with("sitemeta") {
+                ("meta_key", "meta_value")
+            } else {
+                ("option_name", "option_value")
+            };
+            let sql = format!(
+                "SELECT COUNT(*), BIT_XOR(CRC32(CONCAT_WS(' ', {key}, {value}))) FROM {} \
+                 WHERE {} AND {key} NOT IN ({})",
+                identifier(&filter.table),
+                filter.condition,
+                volatile.join(", ")
+            );
+            let rows = self.query(&remote, &sql).await?;
+            let sum = rows.into_iter().flatten().collect::<Vec<_>>().join(" ");
+            checksums.insert(filter.table, sum);
+        }
+        Ok(checksums)
+    }
+
+    /// The directory the file phase mirrors, relative to the WordPress root,
+    /// and the SHA-256 of the server's listing of it.
+    async fn files_digest(&self) -> Result<(String, String), String> {
+        let dir = multisite::files_dir(self.profile, self.components).unwrap_or_default();
+        let excludes = self.excludes().await?;
+        let listing = self
+            .transfer
+            .list(&self.files_root(&self.remote()), &excludes)
+            .await?;
+        let mut hash = Sha256::new();
+        for (path, entry) in &listing {
+            if let Entry::File { size, mtime } = entry {
+                hash.update(format!("{path}\0{size}\0{mtime:?}\n"));
+            }
+        }
+        let digest = hash.finalize().iter().map(|b| format!("{b:02x}")).collect();
+        Ok((dir, digest))
+    }
+
+    /// Record the server as this sync left it, for the next push's plan to
+    /// compare with. Failing to only costs that check, so it is a warning.
+    pub(super) async fn record_fingerprint(&self) {
+        if !self.detects_drift() {
+            return;
+        }
+        let result = async {
+            let mut print = Fingerprint::load(&self.profile.id).unwrap_or_default();
+            let recorded_at = Utc::now();
+            if self.syncs_database() {
+                let checksums = self.table_checksums().await?;
+                print.tables = Some(TablesPrint {
+                    recorded_at,
+                    checksums,
+                });
+            }
+            if self.transfers_files() {
+                let (dir, digest) = self.files_digest().await?;
+                print.files = Some(FilesPrint {
+                    recorded_at,
+                    dir,
+                    digest,
+                });
+            }
+            print.save(&self.profile.id)
+        }
+        .await;
+        if let Err(e) = result {
+            self.warn(Message::new(
+                "warning.servers_state_wasnt_recorded_so_next",
+                &[("error", &e)],
+            ));
+        }
+    }
+
+    /// What changed on the server since the last sync, of what this push
+    /// would overwrite; `None` if nothing did or there is no record to go by.
+    pub(super) async fn drift(&self) -> Result<Option<Drift>, String> {
+        if !self.detects_drift() {
+            return Ok(None);
+        }
+        let print = Fingerprint::load(&self.profile.id)?;
+        let mut since: Option<DateTime<Utc>> = None;
+        let mut tables = Vec::new();
+        if let Some(recorded) = print.tables.filter(|_| self.syncs_database()) {
+            let current = self.table_checksums().await?;
+            let names: BTreeSet<&String> =
+                recorded.checksums.keys().chain(current.keys()).collect();
+            tables = names
+                .into_iter()
+                .filter(|name| recorded.checksums.get(*name) != current.get(*name))
+                .cloned()
+                .collect();
+            if !tables.is_empty() {
+                since = Some(recorded.recorded_at);
+            }
+        }
+        let mut files_changed = false;
+        if let Some(recorded) = print.files.filter(|_| self.transfers_files()) {
+            let (dir, digest) = self.files_digest().await?;
+            if recorded.dir == dir && recorded.digest != digest {
+                files_changed = true;
+                since = Some(since.map_or(recorded.recorded_at, |at| at.min(recorded.recorded_at)));
+            }
+        }
+        Ok(since.map(|since| Drift {
+            since,
+            tables,
+            files_changed,
+        }))
+    }
+}
@@ -0,0 +1,231 @@
+//! One side of a sync — the local install or the remote one — behind a common interface.
+
+use russh::ChannelMsg;
+use tokio::process::Command;
+
+use crate::messages;
+use crate::profile::{ContainerRuntime, LocalEnvironment, RemoteEnvironment};
+
+use super::remote::{self, Remote};
+use super::{local, local_path};
+use crate::{shell, ssh, stderr};
+
+/// `line` run in `env`'s WordPress root with the extra environment variables
+/// `vars`, as one command line for the server's shell.
+pub fn remote_sh_line(env: &RemoteEnvironment, line: &str, vars: &[(&str, String)]) -> String {
+    // Servers rarely accept environment variables over SSH, so they are
+    // exported by the command line itself.
+    let exports: String = vars
+        .iter()
+        .map(|(name, value)| format!("export {name}={}; ", shell::quote(value)))
+        .collect();
+    format!("{exports}cd {} && {line}", shell::quote(&env.path))
+}
+
+/// A WordPress install the pipeline can run wp-cli against and transfer files to or from.
+pub enum Endpoint<'a> {
+    Local(&'a LocalEnvironment),
+    Remote {
+        /// The SSH connection, or `None` when the profile transfers over FTP and
+        /// has no shell on the server.
+        remote: Option<&'a Remote>,
+        env: &'a RemoteEnvironment,
+    },
+}
+
+impl<'a> Endpoint<'a> {
+    /// Whether commands (wp-cli, `rm`) can be run on this side.
+    pub fn has_shell(&self) -> bool {
+        !matches!(self, Endpoint::Remote { remote: None, .. })
+    }
+
+    fn shell(&self, remote: Option<&'a Remote>) -> Result<&'a Remote, String> {
+        remote.ok_or_else(|| messages::text("error.no_ssh.commands", &[]))
+    }
+
+    /// The site URL of this install.
+    pub fn url(&self) -> &str {
+        match self {
+            Endpoint::Local(env) => &env.url,
+            Endpoint::Remote { env, .. } => &env.url,
+        }
+    }
+
+    /// The WordPress root directory.
+    pub fn root(&self) -> String {
+        match self {
+            Endpoint::Local(env) => env.path.display().to_string(),
+            Endpoint::Remote { env, .. } => env.path.clone(),
+        }
+    }
+
+    /// A `/`-separated path below the WordPress root, as seen on this side.
+    pub fn path(&self, relative: &str) -> String {
+        match self {
+            Endpoint::Local(env) => local_path::join(&env.path, relative).display().to_string(),
+            Endpoint::Remote { env, .. } => {
+                format!("{}/{relative}", env.path.trim_end_matches('/'))
+            }
+        }
+    }
+
+    /// The SSH connection to this side, for commands that stream their input or output.
+    pub fn session(&self) -> Result<&'a Remote, String> {
+        match self {
+            Endpoint::Local(_) => Err(messages::text("error.this_local_install", &[])),
+            Endpoint::Remote { remote, .. } => self.shell(*remote),
+        }
+    }
+
+    /// Run wp-cli against this install, returning its stdout.
+    pub async fn wp(&self, args: &[&str]) -> Result<String, String> {
+        match self {
+            Endpoint::Local(_) => {
+                local::output(self.wp_command(args), &format!("wp {}", args.join(" "))).await
+            }
+            Endpoint::Remote { remote, .. } => {
+                self.shell(*remote)?.exec(&self.wp_line(args).await?).await
+            }
+        }
+    }
+
+    /// Run wp-cli against this install with `input` as its stdin, returning
+    /// its stdout.
+    pub async fn wp_with_input(&self, args: &[&str], input: &[u8]) -> Result<String, String> {
+        match self {
+            Endpoint::Local(_) => {
+                let what = format!("wp {}", args.join(" "));
+                local::output_with_input(self.wp_command(args), &what, input).await
+            }
+            Endpoint::Remote { remote, .. } => {
+                self.shell(*remote)?
+                    .exec_with_input(&self.wp_line(args).await?, input)
+                    .await
+            }
+        }
+    }
+
+    /// Run wp-cli against this install, handing each line of its output to
+    /// `on_line` as it arrives, for commands that report their own progress.
+    pub async fn wp_streaming(
+        &self,
+        args: &[&str],
+        mut on_line: impl FnMut(&str),
+    ) -> Result<(), String> {
+        let mut pending = Vec::new();
+        let streamed = self
+            .wp_stdout(args, |data| {
+                local::split_lines(&mut pending, data, &mut on_line)
+            })
+            .await;
+        if !pending.is_empty() {
+            on_line(&String::from_utf8_lossy(&pending));
+        }
+        streamed
+    }
+
+    /// Run wp-cli against this install, handing its output to `on_data` a
+    /// chunk at a time as it arrives.
+    pub async fn wp_stdout(
+        &self,
+        args: &[&str],
+        mut on_data: impl FnMut(&[u8]),
+    ) -> Result<(), String> {
+        let what = format!("wp {}", args.join(" "));
+        let remote = match self {
+            Endpoint::Local(_) => {
+                return local::stream_stdout(self.wp_command(args), &what, on_data).await
+            }
+            Endpoint::Remote { remote, .. } => self.shell(*remote)?,
+        };
+        let command = self.wp_line(args).await?;
+        let mut channel = remote.open_exec(&command).await?;
+        let (mut stderr, mut exit_status) = (Vec::new(), None);
+        while let Some(msg) = channel.wait().await {
+            match msg {
+                ChannelMsg::Data { data } => on_data(&data),
+                // Extended data type 1 is stderr
+                ChannelMsg::ExtendedData { data, ext: 1 } => stderr::push(&mut stderr, &data),
+                ChannelMsg::ExitStatus { exit_status: code } => exit_status = Some(code),
+                _ => {}
+            }
+        }
+        ssh::audit_exit(&command, exit_status);
+        if exit_status != Some(0) {
+            return Err(remote::command_failed(&command, exit_status, &stderr));
+        }
+        Ok(())
+    }
+
+    /// Run a shell command line in this install's WordPress root with the
+    /// extra environment variables `vars`, returning its stdout. Locally it goes
+    /// to `sh`, or `cmd` on Windows.
+    pub async fn sh(&self, line: &str, vars: &[(&str, String)]) -> Result<String, String> {
+        match self {
+            Endpoint::Local(env) => {
+                #[cfg(windows)]
+                let mut cmd = {
+                    let mut cmd = local::command("cmd");
+                    cmd.arg("/C").arg(line);
+                    cmd
+                };
+                #[cfg(not(windows))]
+                let mut cmd = {
+                    let mut cmd = local::command("sh");
+                    cmd.arg("-c").arg(line);
+                    cmd
+                };
+                cmd.current_dir(&env.path).envs(vars.iter().cloned());
+                local::output(cmd, line).await
+            }
+            Endpoint::Remote { remote, env } => {
+                self.shell(*remote)?
+                    .exec(&remote_sh_line(env, line, vars))
+                    .await
+            }
+        }
+    }
+
+    /// A local wp-cli command against this install, ready to run: here, or in
+    /// the install's container with its stdin passed through.
+    pub fn wp_command(&self, args: &[&str]) -> Command {
+        let container = match self {
+            Endpoint::Local(env) => env.container.as_ref(),
+            Endpoint::Remote { .. } => None,
+        };
+        let Some(container) = container else {
+            let mut cmd = local::command("wp");
+            cmd.arg(format!("--path={}", self.root())).args(args);
+            return cmd;
+        };
+        let mut cmd = match container.runtime {
+            ContainerRuntime::Docker => {
+                let mut cmd = local::command("docker");
+                // Containers exec as root, which wp-cli refuses without being told.
+                cmd.args(["exec", "-i", &container.name, "wp", "--allow-root"]);
+                cmd
+            }
+            ContainerRuntime::Ddev => {
+                let mut cmd = local::command("ddev");
+                cmd.args(["exec", "--raw", "wp"]).current_dir(self.root());
+                cmd
+            }
+        };
+        cmd.arg(format!("--path={}", container.path)).args(args);
+        cmd
+    }
+
+    /// A wp-cli command line against this install, quoted for a remote shell,
+    /// with the server's PHP and the profile's memory limit.
+    pub async fn wp_line(&self, args: &[&str]) -> Result<String, String> {
+        let wp = match self {
+            Endpoint::Remote { remote, env } => {
+                self.shell(*remote)?.wp_cli(env).await?.line.clone()
+            }
+            Endpoint::Local(_) => "wp".to_string(),
+        };
+        let path_arg = format!("--path={}", self.root());
+        let args = shell::join(std::iter::once(path_arg.as_str()).chain(args.iter().copied()));
+        Ok(format!("{wp} {args}"))
+    }
+}
@@ -0,0 +1,156 @@
+//! What the remote install is: its WordPress and PHP versions, theme, table
+//! prefix, site URL, and whether it is a multisite, read with one wp-cli call;
+//! and the database settings in its `wp-config.php`.
+
+use serde::{Deserialize, Serialize};
+
+use super::endpoint::Endpoint;
+use super::remote::Remote;
+use super::{connect_ssh, ProgressSink};
+use crate::error::SyncError;
+use crate::messages::Message;
+use crate::profile::wp_config::{self, WP_CONFIG};
+use crate::profile::{DatabaseSettings, Profile};
+use crate::shell;
+
+/// PHP run by `wp eval` inside the remote WordPress, printing everything on one JSON line.
+const PROBE: &str = r#"echo "\n", json_encode(array(
+    "wordpress_version" => get_bloginfo("version"),
+    "php_version" => PHP_VERSION,
+    "active_theme" => get_stylesheet(),
+    "table_prefix" => $GLOBALS["table_prefix"],
+    "site_url" => get_option("siteurl"),
+    "multisite" => is_multisite(),
+));"#;
+
+/// The remote install as wp-cli reports it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Environment {
+    pub profile_id: String,
+    pub wordpress_version: String,
+    /// The PHP that runs wp-cli, which is usually, but not always, the web server's.
+    pub php_version: String,
+    /// The active theme's directory name, e.g. `twentytwentyfour`.
+    pub active_theme: String,
+    pub table_prefix: String,
+    pub site_url: String,
+    pub multisite: bool,
+    /// Profile settings that disagree with what was detected.
+    pub mismatches: Vec<Mismatch>,
+}
+
+/// A profile setting and what the server says it should be.
+#[derive(Debug, Clone, Serialize)]
+pub struct Mismatch {
+    /// Dotted path of the field, as in validation issues, e.g. `remote.url`.
+    pub field: String,
+    pub profile: String,
+    pub detected: String,
+}
+
+#[derive(Deserialize)]
+struct Probe {
+    wordpress_version: String,
+    php_version: String,
+    active_theme: String,
+    table_prefix: String,
+    site_url: String,
+    multisite: bool,
+}
+
+/// Read the database settings from the `wp-config.php` for `path` on
+/// `profile`'s server: the file itself, or a WordPress root. As locally, a root
+/// without one falls back to the file in its parent directory.
+pub async fn remote_wp_config(
+    profile: &Profile,
+    path: &str,
+    sink: &dyn ProgressSink,
+) -> Result<DatabaseSettings, SyncError> {
+    if !profile.remote.transfer.uses_ssh() {
+        return Err(SyncError::Config(Message::new(
+            "error.no_ssh.wp_config",
+            &[],
+        )));
+    }
+    let remote = connect_ssh(profile, sink).await?;
+    let source = read_wp_config(&remote, path).await;
+    remote.close().await;
+    let source = source.map_err(SyncError::remote_command)?;
+    wp_config::parse(&source).map_err(|e| {
+        SyncError::Config(Message::new(
+            "error.invalid_path",
+            &[("path", &path), ("error", &e)],
+        ))
+    })
+}
+
+/// The PHP source of the `wp-config.php` for `path` on the server.
+pub(super) async fn read_wp_config(remote: &Remote, path: &str) -> Result<String, String> {
+    let command = if path.ends_with(WP_CONFIG) {
+        format!("cat {}", shell::quote(path))
+    } else {
+        let root = path.trim_end_matches('/');
+        format!(
+            "cat {} 2>/dev/null || cat {}",
+            shell::quote(&format!("{root}/{WP_CONFIG}")),
+            shell::quote(&format!("{root}/../{WP_CONFIG}"))
+        )
+    };
+    remote.exec(&command).await
+}
+
+/// Connect to `profile`'s server and describe its WordPress install. An
+/// untrusted host key is announced to `sink` just as it is for a real sync.
+pub async fn detect_environment(
+    profile: &Profile,
+    sink: &dyn ProgressSink,
+) -> Result<Environment, SyncError> {
+    if !profile.remote.transfer.uses_ssh() {
+        return Err(SyncError::Config(Message::new(
+            "error.no_ssh.environment",
+            &[],
+        )));
+    }
+    let remote = connect_ssh(profile, sink).await?;
+    let server = Endpoint::Remote {
+        remote: Some(&remote),
+        env: &profile.remote,
+    };
+    let output = server.wp(&["eval", PROBE]).await;
+    remote.close().await;
+    let output = output.map_err(SyncError::remote_command)?;
+
+    // Plugins sometimes print notices first; the JSON is always the last line.
+    let json = output
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or_default();
+    let probe: Probe = serde_json::from_str(json).map_err(|e| {
+        SyncError::RemoteCommand(Message::new(
+            "error.failed.read_remote_environment",
+            &[("error", &e)],
+        ))
+    })?;
+
+    let mut mismatches = Vec::new();
+    let url = profile.remote.url.trim_end_matches('/');
+    if !url.is_empty() && !url.eq_ignore_ascii_case(probe.site_url.trim_end_matches('/')) {
+        mismatches.push(Mismatch {
+            field: "remote.url".to_string(),
+            profile: profile.remote.url.clone(),
+            detected: probe.site_url.clone(),
+        });
+    }
+
+    Ok(Environment {
+        profile_id: profile.id.clone(),
+        wordpress_version: probe.wordpress_version,
+        php_version: probe.php_version,
+        active_theme: probe.active_theme,
+        table_prefix: probe.table_prefix,
+        site_url: probe.site_url,
+        multisite: probe.multisite,
+        mismatches,
+    })
+}
@@ -0,0 +1,148 @@
+//! Estimates: how much a sync would move and roughly how long it would take,
+//! worked out without changing anything and quicker than a full plan.
+//!
+//! Sizes come from listing both sides, as a sync would, and from the source
+//! database's `information_schema`. Durations come from the profile's own
+//! earlier syncs in the same direction: files at the speed they were copied
+//! then, and the database and the fixed phases in the time they took on
+//! average. A profile that has never synced that way gets no duration.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use super::progress::Reporter;
+use super::{connect, Components, Direction, Job, Phase, ProgressSink};
+use crate::error::SyncError;
+use crate::history::{self, HistoryEntry, JobStatus};
+use crate::profile::Profile;
+
+/// Earlier syncs the duration is worked out from, newest first.
+const HISTORY_DEPTH: u32 = 10;
+
+/// What a sync in one direction would move.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncEstimate {
+    pub profile_id: String,
+    pub direction: Direction,
+    pub components: Components,
+    /// Files the transfer would copy.
+    pub files_to_copy: u64,
+    /// Their total size on the source.
+    pub file_bytes: u64,
+    /// Files and directories it would delete on the destination.
+    pub files_to_delete: u64,
+    /// The size of the source tables the sync would dump, or `None` when the
+    /// database isn't part of it.
+    pub database_bytes: Option<u64>,
+    /// The files and the database together.
+    pub total_bytes: u64,
+    /// How long the sync would take, or `None` without an earlier sync to go by.
+    pub duration_ms: Option<u64>,
+    /// How many earlier syncs `duration_ms` is worked out from.
+    pub based_on_syncs: u32,
+}
+
+/// Connect to the server and estimate syncing `profile` in `direction`.
+///
+/// Nothing is written on either side. An untrusted host key is announced to
+/// `sink` just as it is for a real sync.
+pub async fn estimate(
+    profile: &Profile,
+    direction: Direction,
+    components: Components,
+    sink: &dyn ProgressSink,
+) -> Result<SyncEstimate, SyncError> {
+    components.check(profile)?;
+    let (remote, transfer, _) = connect(profile, sink).await?;
+    let reporter = Reporter::new(uuid::Uuid::new_v4().to_string(), sink);
+    let job = Job::new(profile, direction, components, remote, transfer, reporter);
+    let result = job.estimate().await;
+    if let Some(remote) = &job.remote {
+        remote.close().await;
+    }
+    result
+}
+
+impl Job<'_> {
+    async fn estimate(&self) -> Result<SyncEstimate, SyncError> {
+        let (files_to_copy, file_bytes, files_to_delete) = if self.transfers_files() {
+            let changes = self.planned_changes().await.map_err(SyncError::transfer)?;
+            let copied = changes
+                .added
+                .iter()
+                .chain(&changes.updated)
+                .filter(|path| !path.ends_with('/'))
+                .count();
+            (copied as u64, changes.bytes, changes.deleted.len() as u64)
+        } else {
+            (0, 0, 0)
+        };
+
+        let database_bytes = if self.syncs_database() {
+            self.detect_network().await.map_err(SyncError::database)?;
+            Some(self.database_bytes().await.map_err(SyncError::database)?)
+        } else {
+            None
+        };
+
+        let past = history::list(Some(&self.profile.id), Some(HISTORY_DEPTH))
+            .map_err(SyncError::config)?
+            .into_iter()
+            .filter(|entry| {
+                entry.status == JobStatus::Succeeded && entry.direction == self.direction
+            })
+            .collect::<Vec<_>>();
+        Ok(SyncEstimate {
+            profile_id: self.profile.id.clone(),
+            direction: self.direction,
+            components: self.components,
+            files_to_copy,
+            file_bytes,
+            files_to_delete,
+            database_bytes,
+            total_bytes: file_bytes + database_bytes.unwrap_or(0),
+            duration_ms: self.duration_ms(&past, file_bytes),
+            based_on_syncs: past.len() as u32,
+        })
+    }
+
+    /// The size of the source tables this job would dump.
+    pub(super) async fn database_bytes(&self) -> Result<u64, String> {
+        let source = self.source();
+        let sizes = self.table_sizes(&source).await?;
+        let names: Vec<String> = sizes.iter().map(|(table, _)| table.clone()).collect();
+        let selection = self.table_selection(&source).await?;
+        let selected = selection.select(&names)?;
+        Ok(sizes
+            .iter()
+            .filter(|(table, _)| selected.contains(&table.as_str()))
+            .map(|(_, size)| size)
+            .sum())
+    }
+
+    /// How long this job's phases would take, going by the `past` syncs, if
+    /// every phase it runs has been timed before.
+    fn duration_ms(&self, past: &[HistoryEntry], file_bytes: u64) -> Option<u64> {
+        let mut timings: HashMap<Phase, (u64, u64, u64)> = HashMap::new();
+        for report in past.iter().flat_map(|entry| &entry.phases) {
+            let (runs, duration, bytes) = timings.entry(report.phase).or_default();
+            *runs += 1;
+            *duration += report.duration_ms;
+            *bytes += report.bytes_transferred;
+        }
+        let mut total = 0;
+        for phase in self.phases() {
+            let &(runs, duration, bytes) = timings.get(&phase)?;
+            total += match phase {
+                // Copies scale with what there is to copy; the rest hardly vary.
+                Phase::TransferFiles if bytes > 0 => {
+                    (u128::from(file_bytes) * u128::from(duration) / u128::from(bytes)) as u64
+                }
+                Phase::TransferFiles if file_bytes > 0 => return None,
+                _ => duration / runs,
+            };
+        }
+        Some(total)
+    }
+}
@@ -0,0 +1,267 @@
+//! A job's lifecycle as machine-readable events, for tooling outside the app
+//! to follow syncs without scraping logs or progress updates meant for the UI.
+//!
+//! Every event is a JSON object with the schema's `version`, the `job_id`,
+//! the time it happened `at`, and a `type` naming one of [`JobEventKind`]'s
+//! variants, whose own fields sit beside those. Fields are only ever added
+//! within a version; one that changes or goes away bumps it.
+//!
+//! Events go to the [`ProgressSink`], which the app sends on as
+//! `sync://event`, and are appended to `~/.wordpress-sync/events/<job-id>.ndjson`,
+//! one per line. Those of the most recent jobs are kept.
+
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{Components, Direction, Phase, ProgressSink, SyncProgress};
+use crate::history::{HistoryEntry, JobStatus};
+use crate::messages;
+use crate::messages::Message;
+use crate::paths;
+
+/// The version of the event schema written now.
+pub const EVENTS_VERSION: u32 = 1;
+
+/// How many jobs' event files are kept, as many as the history lists by default.
+const KEEP_JOBS: usize = crate::history::DEFAULT_LIMIT as usize;
+
+/// Something that happened to a job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobEvent {
+    pub version: u32,
+    pub job_id: String,
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub kind: JobEventKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JobEventKind {
+    /// The job was waiting in the queue, from `at`, and has now started.
+    Queued {
+        queue_id: String,
+        profile_id: String,
+        direction: Direction,
+        components: Components,
+    },
+    /// A phase of the pipeline began.
+    PhaseStarted { phase: Phase },
+    /// How far the phase has got, at most five times a second.
+    Progress {
+        phase: Phase,
+        files_done: u64,
+        files_total: Option<u64>,
+        bytes_transferred: u64,
+        eta_seconds: Option<u64>,
+    },
+    /// A problem that doesn't fail the job, with the catalog message it is.
+    Warning {
+        message: String,
+        #[serde(default)]
+        message_id: String,
+        #[serde(default)]
+        params: BTreeMap<String, String>,
+    },
+    /// The job ended, in success or not.
+    Completed {
+        status: JobStatus,
+        /// Why it failed or stopped, as the history says.
+        error: Option<String>,
+        started_at: DateTime<Utc>,
+        bytes_transferred: u64,
+        files_transferred: u64,
+        warnings: usize,
+    },
+}
+
+impl JobEvent {
+    fn new(job_id: &str, kind: JobEventKind) -> Self {
+        Self {
+            version: EVENTS_VERSION,
+            job_id: job_id.to_string(),
+            at: Utc::now(),
+            kind,
+        }
+    }
+
+    pub(super) fn progress(update: &SyncProgress) -> Self {
+        Self::new(
+            &update.job_id,
+            JobEventKind::Progress {
+                phase: update.phase,
+                files_done: update.files_done,
+                files_total: update.files_total,
+                bytes_transferred: update.bytes_transferred,
+                eta_seconds: update.eta_seconds,
+            },
+        )
+    }
+
+    pub(super) fn phase_started(job_id: &str, phase: Phase) -> Self {
+        Self::new(job_id, JobEventKind::PhaseStarted { phase })
+    }
+
+    pub(super) fn warning(job_id: &str, message: &Message) -> Self {
+        let kind = JobEventKind::Warning {
+            message: message.to_string(),
+            message_id: message.id.clone(),
+            params: message.params.clone(),
+        };
+        Self::new(job_id, kind)
+    }
+
+    pub(super) fn completed(entry: &HistoryEntry) -> Self {
+        let mut event = Self::new(
+            &entry.job_id,
+            JobEventKind::Completed {
+                status: entry.status,
+                error: entry.error.as_ref().map(ToString::to_string),
+                started_at: entry.started_at,
+                bytes_transferred: entry.bytes_transferred,
+                files_transferred: entry.files_transferred,
+                warnings: entry.warnings.len(),
+            },
+        );
+        event.at = entry.finished_at;
+        event
+    }
+
+    /// That the queue entry `queue_id`, waiting since `enqueued_at`, started
+    /// as job `job_id`.
+    pub(super) fn queued(
+        job_id: &str,
+        queue_id: &str,
+        profile_id: &str,
+        direction: Direction,
+        components: Components,
+        enqueued_at: DateTime<Utc>,
+    ) -> Self {
+        let mut event = Self::new(
+            job_id,
+            JobEventKind::Queued {
+                queue_id: queue_id.to_string(),
+                profile_id: profile_id.to_string(),
+                direction,
+                components,
+            },
+        );
+        event.at = enqueued_at;
+        event
+    }
+}
+
+/// Send `event` to `sink` and append it to its job's file. The job goes on
+/// without it in the file if it can't be written.
+pub(super) fn emit(sink: &dyn ProgressSink, event: JobEvent) {
+    if let Err(e) = append(&event) {
+        tracing::debug!(error = %e, "a job event wasn't saved");
+    }
+    sink.event(&event);
+}
+
+/// Job `job_id`'s events so far, oldest first.
+pub fn job_events(job_id: &str) -> Result<Vec<JobEvent>, String> {
+    let path = path_for(job_id)?;
+    let file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(messages::text(
+                "error.job_id_has_no_events_too",
+                &[("job_id", &job_id)],
+            ))
+        }
+        Err(e) => {
+            return Err(messages::text(
+                "error.failed.read_path",
+                &[("path", &path.display()), ("error", &e)],
+            ))
+        }
+    };
+    let mut events = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| {
+            messages::text(
+                "error.failed.read_path",
+                &[("path", &path.display()), ("error", &e)],
+            )
+        })?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(event) => events.push(event),
+            // A line cut short by a crash is the last; the rest still stand.
+            Err(e) => tracing::debug!(error = %e, "skipping an unreadable job event"),
+        }
+    }
+    Ok(events)
+}
+
+fn append(event: &JobEvent) -> Result<(), String> {
+    let path = path_for(&event.job_id)?;
+    if !path.exists() {
+        let dir = paths::events_dir()?;
+        fs::create_dir_all(&dir).map_err(|e| {
+            messages::text(
+                "error.failed.create_path",
+                &[("path", &dir.display()), ("error", &e)],
+            )
+        })?;
+        prune(&event.job_id);
+    }
+    let mut line = serde_json::to_vec(event)
+        .map_err(|e| messages::text("error.failed.serialize_job_event", &[("error", &e)]))?;
+    line.push(b'\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(&line))
+        .map_err(|e| {
+            messages::text(
+                "error.failed.write_path",
+                &[("path", &path.display()), ("error", &e)],
+            )
+        })
+}
+
+fn path_for(job_id: &str) -> Result<PathBuf, String> {
+    if job_id.is_empty()
+        || !job_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(messages::text(
+            "error.invalid_job_id",
+            &[("job_id", &format_args!("{job_id:?}"))],
+        ));
+    }
+    Ok(paths::events_dir()?.join(format!("{job_id}.ndjson")))
+}
+
+/// Remove the event files of all but the most recent jobs, `job_id`'s among them.
+fn prune(job_id: &str) {
+    let Ok(dir) = paths::events_dir() else {
+        return;
+    };
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return;
+    };
+    let keep = format!("{job_id}.ndjson");
+    let mut files: Vec<(SystemTime, PathBuf)> = entries
+        .flatten()
+        .filter(|entry| entry.file_name() != keep.as_str())
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .collect();
+    files.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    for (_, path) in files.into_iter().skip(KEEP_JOBS - 1) {
+        let _ = fs::remove_file(path);
+    }
+}
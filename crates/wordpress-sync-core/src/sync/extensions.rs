@@ -0,0 +1,100 @@
+//! The plugins and themes installed on each side, for choosing which to sync.
+
+use serde::{Deserialize, Serialize};
+
+use super::endpoint::Endpoint;
+use super::progress::ProgressSink;
+use super::{connect_ssh, SyncError};
+use crate::messages::{self, Message};
+use crate::profile::Profile;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtensionKind {
+    Plugin,
+    Theme,
+}
+
+/// A plugin or theme as wp-cli lists it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Extension {
+    #[serde(skip_deserializing, default = "plugin")]
+    pub kind: ExtensionKind,
+    /// The name of its directory (or, for a single-file plugin, its file
+    /// without `.php`), as a profile's plugin and theme selections use it.
+    pub name: String,
+    #[serde(default)]
+    pub title: String,
+    /// `active`, `inactive`, or for a multisite network `active-network`;
+    /// a theme may also be a `parent`.
+    pub status: String,
+    #[serde(default)]
+    pub version: String,
+}
+
+fn plugin() -> ExtensionKind {
+    ExtensionKind::Plugin
+}
+
+/// Every plugin and theme in `side`'s install. Must-use plugins and drop-ins
+/// live outside the plugins directory, so they aren't listed.
+async fn extensions(side: &Endpoint<'_>) -> Result<Vec<Extension>, String> {
+    let mut all = list(side, ExtensionKind::Plugin).await?;
+    all.extend(list(side, ExtensionKind::Theme).await?);
+    Ok(all)
+}
+
+/// The plugins or themes in `side`'s install, as [`extensions`] lists them.
+pub(super) async fn list(
+    side: &Endpoint<'_>,
+    kind: ExtensionKind,
+) -> Result<Vec<Extension>, String> {
+    let command = match kind {
+        ExtensionKind::Plugin => "plugin",
+        ExtensionKind::Theme => "theme",
+    };
+    let output = side
+        .wp(&[
+            command,
+            "list",
+            "--fields=name,title,status,version",
+            "--format=json",
+        ])
+        .await?;
+    let listed: Vec<Extension> = serde_json::from_str(output.trim()).map_err(|e| {
+        messages::text(
+            "error.unexpected_output_wp_command_list",
+            &[("command", &command), ("error", &e)],
+        )
+    })?;
+    Ok(listed
+        .into_iter()
+        .filter(|extension| !matches!(extension.status.as_str(), "must-use" | "dropin"))
+        .map(|extension| Extension { kind, ..extension })
+        .collect())
+}
+
+/// The plugins and themes installed on the remote site.
+pub async fn remote_extensions(
+    profile: &Profile,
+    sink: &dyn ProgressSink,
+) -> Result<Vec<Extension>, SyncError> {
+    if !profile.remote.transfer.uses_ssh() {
+        return Err(SyncError::Config(Message::new("error.no_ssh.plugins", &[])));
+    }
+    let remote = connect_ssh(profile, sink).await?;
+    let endpoint = Endpoint::Remote {
+        remote: Some(&remote),
+        env: &profile.remote,
+    };
+    let result = extensions(&endpoint).await;
+    remote.close().await;
+    result.map_err(SyncError::remote_command)
+}
+
+/// The plugins and themes installed on the local site.
+pub async fn local_extensions(profile: &Profile) -> Result<Vec<Extension>, SyncError> {
+    extensions(&Endpoint::Local(&profile.local))
+        .await
+        .map_err(SyncError::remote_command)
+}
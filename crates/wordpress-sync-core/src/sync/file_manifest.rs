@@ -0,0 +1,441 @@
+//! Which files each job added, changed, and deleted, kept after it finishes
+//! so any job in the history can be looked into, down to the file.
+//!
+//! The file phase writes what each mirror changed on its destination to
+//! `~/.wordpress-sync/file-manifests/<job-id>.json.partial` as it goes, and
+//! renames that to `<job-id>.json` when the phase ends, whether or not it got
+//! through; a resumed job that runs the phase again replaces it. Those of the
+//! most recent jobs are kept.
+//!
+//! A job that changed millions of files has a manifest to match, so it's
+//! written and read as a stream: neither the job nor browsing or searching
+//! holds its entries, only what the mirror in hand changed and what a search
+//! returns.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use serde::de::{DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use super::transfer::FileChanges;
+use super::{Direction, Job};
+use crate::messages;
+use crate::paths;
+
+/// How many jobs' manifests are kept, as many as the history lists by default.
+const KEEP_JOBS: usize = crate::history::DEFAULT_LIMIT as usize;
+
+/// How long a partial manifest goes unwritten before it's taken to be that of
+/// a job that was killed.
+const ABANDONED_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The most files [`search_manifest`] returns.
+const MAX_MATCHES: usize = 1_000;
+
+/// What happened to a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileChange {
+    Added,
+    Updated,
+    Deleted,
+}
+
+/// One file or directory a job changed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Its path below the synced root; a directory's ends in `/`.
+    pub path: String,
+    pub change: FileChange,
+    /// Which way it went, which in a two-way sync is either.
+    pub direction: Direction,
+}
+
+/// A job's manifest as it's written.
+#[derive(Default)]
+pub(super) enum Spool {
+    #[default]
+    NotStarted,
+    Writing {
+        path: PathBuf,
+        writer: BufWriter<fs::File>,
+        empty: bool,
+    },
+    /// It couldn't be written, and the job goes on without one.
+    Failed,
+}
+
+/// One name in a directory of a job's manifest.
+#[derive(Debug, Clone, Serialize)]
+pub struct ManifestNode {
+    pub name: String,
+    /// Its path below the synced root; a directory's ends in `/`.
+    pub path: String,
+    pub is_dir: bool,
+    /// What happened to it itself, if anything did. A directory that only has
+    /// changes below it has none.
+    pub change: Option<FileChange>,
+    pub direction: Option<Direction>,
+    /// How many files below a directory were added, updated, and deleted.
+    pub added: u64,
+    pub updated: u64,
+    pub deleted: u64,
+}
+
+impl Job<'_> {
+    /// Add what a mirror in `direction` changed to the manifest.
+    pub(super) fn record_file_changes(&self, direction: Direction, changes: &FileChanges) {
+        let mut spool = self.file_manifest.lock().unwrap();
+        let entries = [
+            (&changes.added, FileChange::Added),
+            (&changes.updated, FileChange::Updated),
+            (&changes.deleted, FileChange::Deleted),
+        ]
+        .into_iter()
+        .flat_map(|(paths, change)| {
+            paths.iter().map(move |path| ManifestEntry {
+                path: path.trim_start_matches("./").to_string(),
+                change,
+                direction,
+            })
+        });
+        for entry in entries {
+            self.write_entry(&mut spool, &entry);
+        }
+    }
+
+    /// Save the changes added so far as the job's manifest.
+    pub(super) fn save_file_manifest(&self) {
+        let mut spool = self.file_manifest.lock().unwrap();
+        self.start(&mut spool);
+        let Spool::Writing {
+            path, mut writer, ..
+        } = std::mem::take(&mut *spool)
+        else {
+            return;
+        };
+        let saved = finish(&path, &mut writer);
+        drop(writer);
+        if let Err(e) = saved {
+            let _ = fs::remove_file(&path);
+            tracing::warn!(error = %e, "the job's file manifest wasn't saved");
+            return;
+        }
+        prune(&self.id);
+    }
+
+    fn write_entry(&self, spool: &mut Spool, entry: &ManifestEntry) {
+        self.start(spool);
+        let Spool::Writing {
+            path,
+            writer,
+            empty,
+        } = spool
+        else {
+            return;
+        };
+        let separator: &[u8] = if *empty { b"" } else { b"," };
+        let written = writer
+            .write_all(separator)
+            .map_err(|e| write_failed(path, e))
+            .and_then(|()| {
+                serde_json::to_writer(&mut *writer, entry).map_err(|e| {
+                    messages::text("error.failed.serialize_file_manifest", &[("error", &e)])
+                })
+            });
+        *empty = false;
+        if let Err(e) = written {
+            self.give_up(spool, &e);
+        }
+    }
+
+    /// Begin writing the manifest, unless that's been done or failed.
+    fn start(&self, spool: &mut Spool) {
+        if !matches!(spool, Spool::NotStarted) {
+            return;
+        }
+        match begin(&self.id, &self.profile.id) {
+            Ok((path, writer)) => {
+                *spool = Spool::Writing {
+                    path,
+                    writer,
+                    empty: true,
+                }
+            }
+            Err(e) => self.give_up(spool, &e),
+        }
+    }
+
+    fn give_up(&self, spool: &mut Spool, error: &str) {
+        if let Spool::Writing { path, .. } = std::mem::replace(spool, Spool::Failed) {
+            let _ = fs::remove_file(path);
+        }
+        tracing::warn!(error = %error, "the job's file manifest wasn't saved");
+    }
+}
+
+/// What job `job_id` changed directly in the directory `path` of its synced
+/// root, which is the root itself when empty: directories first, then files.
+pub fn browse_manifest(job_id: &str, path: &str) -> Result<Vec<ManifestNode>, String> {
+    let dir = path.trim_matches('/');
+    let prefix = match dir {
+        "" => String::new(),
+        dir => format!("{dir}/"),
+    };
+    let mut nodes: BTreeMap<(bool, String), ManifestNode> = BTreeMap::new();
+    for_each_entry(job_id, |entry| {
+        let Some(rest) = entry.path.strip_prefix(&prefix) else {
+            return true;
+        };
+        let (name, below) = match rest.split_once('/') {
+            Some((name, below)) => (name, !below.is_empty()),
+            None => (rest, false),
+        };
+        if name.is_empty() {
+            return true;
+        }
+        let is_dir = below || entry.path.ends_with('/');
+        let node = nodes
+            .entry((!is_dir, name.to_string()))
+            .or_insert_with(|| ManifestNode {
+                name: name.to_string(),
+                path: format!("{prefix}{name}{}", if is_dir { "/" } else { "" }),
+                is_dir,
+                change: None,
+                direction: None,
+                added: 0,
+                updated: 0,
+                deleted: 0,
+            });
+        if !below {
+            node.change = Some(entry.change);
+            node.direction = Some(entry.direction);
+        }
+        if below && !entry.path.ends_with('/') {
+            match entry.change {
+                FileChange::Added => node.added += 1,
+                FileChange::Updated => node.updated += 1,
+                FileChange::Deleted => node.deleted += 1,
+            }
+        }
+        true
+    })?;
+    Ok(nodes.into_values().collect())
+}
+
+/// The files and directories job `job_id` changed whose paths match
+/// `pattern`: a glob such as `*.php` or `wp-content/plugins/*/readme.txt`,
+/// matched against whole paths and names, or otherwise any part of a path.
+/// Either way case doesn't matter.
+pub fn search_manifest(job_id: &str, pattern: &str) -> Result<Vec<ManifestEntry>, String> {
+    let pattern = pattern.trim();
+    let matches: Box<dyn Fn(&str) -> bool> = if pattern.contains(['*', '?', '[']) {
+        let glob = glob::Pattern::new(pattern).map_err(|e| {
+            messages::text(
+                "error.invalid_pattern",
+                &[("pattern", &format_args!("{pattern:?}")), ("error", &e)],
+            )
+        })?;
+        let options = glob::MatchOptions {
+            case_sensitive: false,
+            ..glob::MatchOptions::new()
+        };
+        Box::new(move |path: &str| {
+            let path = path.trim_end_matches('/');
+            let name = path.rsplit('/').next().unwrap_or(path);
+            glob.matches_with(path, options) || glob.matches_with(name, options)
+        })
+    } else {
+        let needle = pattern.to_lowercase();
+        Box::new(move |path: &str| path.to_lowercase().contains(&needle))
+    };
+    let mut found = Vec::new();
+    for_each_entry(job_id, |entry| {
+        if matches(&entry.path) {
+            found.push(entry);
+        }
+        found.len() < MAX_MATCHES
+    })?;
+    Ok(found)
+}
+
+/// Hand each entry of job `job_id`'s manifest to `visit` in turn, until it
+/// returns false.
+fn for_each_entry(job_id: &str, visit: impl FnMut(ManifestEntry) -> bool) -> Result<(), String> {
+    let path = path_for(job_id)?;
+    let file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Err(messages::text(
+                "error.job_id_has_no_file_manifest",
+                &[("job_id", &job_id)],
+            ))
+        }
+        Err(e) => {
+            return Err(messages::text(
+                "error.failed.read_path",
+                &[("path", &path.display()), ("error", &e)],
+            ))
+        }
+    };
+    let mut json = serde_json::Deserializer::from_reader(BufReader::new(file));
+    EachEntry(visit).deserialize(&mut json).map_err(|e| {
+        messages::text(
+            "error.invalid_file_manifest_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })
+}
+
+/// Reads a saved manifest, handing its entries over as they're parsed
+/// instead of collecting them.
+struct EachEntry<F>(F);
+
+impl<'de, F: FnMut(ManifestEntry) -> bool> DeserializeSeed<'de> for EachEntry<F> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, F: FnMut(ManifestEntry) -> bool> Visitor<'de> for EachEntry<F> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a file manifest")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            if key != "entries" {
+                map.next_value::<IgnoredAny>()?;
+                continue;
+            }
+            map.next_value_seed(Entries(&mut self.0))?;
+        }
+        Ok(())
+    }
+}
+
+/// The manifest's entries, each handed to the function until it's had
+/// enough; the rest are skipped over.
+struct Entries<'f, F>(&'f mut F);
+
+impl<'de, F: FnMut(ManifestEntry) -> bool> DeserializeSeed<'de> for Entries<'_, F> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, F: FnMut(ManifestEntry) -> bool> Visitor<'de> for Entries<'_, F> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of changed files")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(entry) = seq.next_element::<ManifestEntry>()? {
+            if !(self.0)(entry) {
+                while seq.next_element::<IgnoredAny>()?.is_some() {}
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Create job `job_id`'s partial manifest and write what comes before its
+/// entries.
+fn begin(job_id: &str, profile_id: &str) -> Result<(PathBuf, BufWriter<fs::File>), String> {
+    let mut path = path_for(job_id)?.into_os_string();
+    path.push(".partial");
+    let path = PathBuf::from(path);
+    let dir = paths::file_manifests_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| {
+        messages::text(
+            "error.failed.create_path",
+            &[("path", &dir.display()), ("error", &e)],
+        )
+    })?;
+    let file = fs::File::create(&path).map_err(|e| write_failed(&path, e))?;
+    let mut writer = BufWriter::new(file);
+    write!(
+        writer,
+        "{{\"job_id\":{},\"profile_id\":{},\"entries\":[",
+        serde_json::json!(job_id),
+        serde_json::json!(profile_id),
+    )
+    .map_err(|e| write_failed(&path, e))?;
+    Ok((path, writer))
+}
+
+/// Close the partial manifest at `path` and put it in place of the job's.
+fn finish(path: &Path, writer: &mut BufWriter<fs::File>) -> Result<(), String> {
+    writer
+        .write_all(b"]}")
+        .and_then(|()| writer.flush())
+        .map_err(|e| write_failed(path, e))?;
+    let saved = path.with_extension("");
+    fs::rename(path, &saved).map_err(|e| write_failed(&saved, e))
+}
+
+fn write_failed(path: &Path, e: io::Error) -> String {
+    messages::text(
+        "error.failed.write_path",
+        &[("path", &path.display()), ("error", &e)],
+    )
+}
+
+fn path_for(job_id: &str) -> Result<PathBuf, String> {
+    if job_id.is_empty()
+        || !job_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(messages::text(
+            "error.invalid_job_id",
+            &[("job_id", &format_args!("{job_id:?}"))],
+        ));
+    }
+    Ok(paths::file_manifests_dir()?.join(format!("{job_id}.json")))
+}
+
+/// Remove the manifests of all but the most recent jobs, `job_id`'s among them,
+/// and the partial ones of jobs that stopped writing theirs a day ago.
+fn prune(job_id: &str) {
+    let Ok(dir) = paths::file_manifests_dir() else {
+        return;
+    };
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return;
+    };
+    let keep = format!("{job_id}.json");
+    let abandoned = SystemTime::now() - ABANDONED_AFTER;
+    let mut manifests: Vec<(SystemTime, PathBuf)> = Vec::new();
+    for entry in entries.flatten() {
+        let Some(modified) = entry.metadata().ok().and_then(|m| m.modified().ok()) else {
+            continue;
+        };
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "partial") {
+            if modified < abandoned {
+                let _ = fs::remove_file(path);
+            }
+        } else if entry.file_name() != keep.as_str() {
+            manifests.push((modified, path));
+        }
+    }
+    manifests.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    for (_, path) in manifests.into_iter().skip(KEEP_JOBS - 1) {
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,138 @@
+//! Keeping a pull from overwriting work that isn't committed in the local
+//! site's git repository, whether the repository is the WordPress root or
+//! just its content directory.
+//!
+//! Before a pull copies files, uncommitted changes under the local site block
+//! it, or are stashed or committed, as the profile's [`GitPolicy`] says. The
+//! commit the repository was at then is kept in the job's history, so what the
+//! pull changed can always be diffed or reset.
+
+use std::path::{Path, PathBuf};
+
+use super::{local, Direction, Job};
+use crate::error::SyncError;
+use crate::messages::Message;
+use crate::profile::GitPolicy;
+
+/// Who commits for a [`GitPolicy::Commit`] in a repository with no identity set.
+const COMMITTER_NAME: &str = "WordPress Sync";
+const COMMITTER_EMAIL: &str = "wordpress-sync@localhost";
+
+impl Job<'_> {
+    /// Apply the profile's git policy to the local site, if this job pulls
+    /// files into it and it's in a git repository. Returns the commit the
+    /// repository is at once its changes are dealt with.
+    pub(super) async fn protect_git(&self) -> Result<Option<String>, SyncError> {
+        let policy = self.profile.local_git;
+        if self.direction != Direction::Pull
+            || !self.transfers_files()
+            || policy == GitPolicy::Ignore
+        {
+            return Ok(None);
+        }
+        let content = self.profile.project.content_dir();
+        let Some(dir) = repository(&self.profile.local.path, content).await else {
+            return Ok(None);
+        };
+        let failed = |e: String| {
+            SyncError::Config(Message::new(
+                "error.failed.check_local_git_repository",
+                &[("error", &e)],
+            ))
+        };
+        let changes = git(&dir, &["status", "--porcelain", "--", "."])
+            .await
+            .map_err(failed)?;
+        let changed = changes
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .count();
+        if changed > 0 {
+            let message = format!("wordpress-sync: before pull {}", self.id);
+            match policy {
+                GitPolicy::Block | GitPolicy::Ignore => {
+                    return Err(SyncError::UncommittedChanges(Message::new(
+                        "error.path_has_uncommitted_changes_changed_files",
+                        &[("path", &dir.display()), ("changed", &changed)],
+                    )))
+                }
+                GitPolicy::Stash => {
+                    git(
+                        &dir,
+                        &[
+                            "stash",
+                            "push",
+                            "--include-untracked",
+                            "-m",
+                            &message,
+                            "--",
+                            ".",
+                        ],
+                    )
+                    .await
+                    .map_err(failed)?;
+                    self.warn(Message::new(
+                        "warning.stashed_uncommitted_changes_changed_files_path",
+                        &[("changed", &changed), ("path", &dir.display())],
+                    ));
+                }
+                GitPolicy::Commit => {
+                    git(&dir, &["add", "--all", "--", "."])
+                        .await
+                        .map_err(failed)?;
+                    commit(&dir, &message).await.map_err(failed)?;
+                    self.warn(Message::new(
+                        "warning.committed_uncommitted_changes_changed_files_path",
+                        &[("changed", &changed), ("path", &dir.display())],
+                    ));
+                }
+            }
+        }
+        // A repository without commits has no HEAD to keep.
+        let head = git(&dir, &["rev-parse", "HEAD"])
+            .await
+            .ok()
+            .map(|head| head.trim().to_string());
+        tracing::info!(dir = %dir.display(), head = ?head, "local git repository checked");
+        Ok(head)
+    }
+}
+
+/// The directory under `root` a pull's files are tracked in: `root` itself if
+/// it's in a repository, or else its `content` directory if that's one.
+/// `None` if neither is, or git isn't installed.
+async fn repository(root: &Path, content: &str) -> Option<PathBuf> {
+    for dir in [root.to_path_buf(), root.join(content)] {
+        if dir.is_dir()
+            && git(&dir, &["rev-parse", "--is-inside-work-tree"])
+                .await
+                .is_ok_and(|inside| inside.trim() == "true")
+        {
+            return Some(dir);
+        }
+    }
+    None
+}
+
+/// Commit what's staged under `dir` in its repository, as the user if git knows who
+/// they are.
+async fn commit(dir: &Path, message: &str) -> Result<(), String> {
+    let mut cmd = local::command("git");
+    cmd.arg("-C")
+        .arg(dir)
+        .args(["commit", "--no-verify", "-m", message, "--", "."]);
+    if git(dir, &["config", "user.email"]).await.is_err() {
+        cmd.env("GIT_AUTHOR_NAME", COMMITTER_NAME)
+            .env("GIT_AUTHOR_EMAIL", COMMITTER_EMAIL)
+            .env("GIT_COMMITTER_NAME", COMMITTER_NAME)
+            .env("GIT_COMMITTER_EMAIL", COMMITTER_EMAIL);
+    }
+    local::output(cmd, "git commit").await.map(drop)
+}
+
+/// Run git with `args` in `dir`.
+async fn git(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let mut cmd = local::command("git");
+    cmd.arg("-C").arg(dir).args(args);
+    local::output(cmd, "git").await
+}
@@ -0,0 +1,185 @@
+//! Health checks: requests to the destination's site once a sync is done,
+//! each expected to answer with the status the profile gives and without a PHP
+//! or WordPress error on the page.
+
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use url::Url;
+
+use super::http;
+use crate::messages;
+use crate::net::AddressFamily;
+use crate::profile::{HealthCheck, HealthChecks};
+use crate::proxy::{self, ProxyChoice};
+
+/// How long one request, redirects and body included, may take.
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// How much of a page is searched for errors.
+const MAX_BODY: usize = 1024 * 1024;
+
+/// Text on a page PHP or WordPress failed to render.
+const ERROR_MARKERS: &[&str] = &[
+    "Fatal error</b>:",
+    "PHP Fatal error:",
+    "Parse error</b>:",
+    "There has been a critical error on this website",
+    "Error establishing a database connection",
+];
+
+/// How one health check went.
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthCheckResult {
+    /// The URL requested.
+    pub url: String,
+    /// The status of the response once redirects were followed, or `None` if
+    /// none came.
+    pub status: Option<u16>,
+    pub passed: bool,
+    /// Why the check failed, if it did.
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+/// Run `checks` against the site at `site_url`, one after another, through
+/// the proxy `proxy` chooses, or over `family`'s addresses.
+pub(super) async fn check(
+    site_url: &str,
+    proxy: &ProxyChoice,
+    family: AddressFamily,
+    checks: &HealthChecks,
+) -> Vec<HealthCheckResult> {
+    let client = match client(proxy, family, checks.verify_tls) {
+        Ok(client) => client,
+        Err(e) => {
+            return checks
+                .urls
+                .iter()
+                .map(|check| HealthCheckResult {
+                    url: full_url(site_url, &check.url),
+                    status: None,
+                    passed: false,
+                    error: Some(messages::text("error.failed.set_up_http", &[("error", &e)])),
+                    duration_ms: 0,
+                })
+                .collect()
+        }
+    };
+
+    let mut results = Vec::with_capacity(checks.urls.len());
+    for check in &checks.urls {
+        let result = request(&client, site_url, check).await;
+        match &result.error {
+            Some(error) => tracing::warn!(url = %result.url, "health check failed: {error}"),
+            None => tracing::info!(url = %result.url, "health check passed"),
+        }
+        results.push(result);
+    }
+    results
+}
+
+/// A client for requests to a site, through the proxy `proxy` chooses or
+/// over `family`'s addresses.
+pub(super) fn client(
+    proxy: &ProxyChoice,
+    family: AddressFamily,
+    verify_tls: bool,
+) -> Result<reqwest::Client, String> {
+    proxy::route(proxy).and_then(|route| {
+        http::builder(route.as_ref(), family)?
+            .timeout(TIMEOUT)
+            .tls_danger_accept_invalid_certs(!verify_tls)
+            .build()
+            .map_err(|e| e.to_string())
+    })
+}
+
+async fn request(
+    client: &reqwest::Client,
+    site_url: &str,
+    check: &HealthCheck,
+) -> HealthCheckResult {
+    let url = full_url(site_url, &check.url);
+    let started = Instant::now();
+    let (status, error) = match fetch(client, &url).await {
+        Ok(page) if page.status != check.status => (
+            Some(page.status),
+            Some(messages::text(
+                "health.unexpected_status",
+                &[("expected", &check.status), ("status", &page.status)],
+            )),
+        ),
+        Ok(page) => (
+            Some(page.status),
+            error_marker(&page.body).map(|marker| {
+                messages::text(
+                    "health.page_shows_error",
+                    &[("marker", &format_args!("{marker:?}"))],
+                )
+            }),
+        ),
+        Err(e) => (None, Some(e)),
+    };
+    HealthCheckResult {
+        url,
+        status,
+        passed: error.is_none(),
+        error,
+        duration_ms: started.elapsed().as_millis() as u64,
+    }
+}
+
+/// The PHP or WordPress error `body` shows, if it shows one.
+pub(super) fn error_marker(body: &str) -> Option<&'static str> {
+    ERROR_MARKERS
+        .iter()
+        .find(|marker| body.contains(*marker))
+        .copied()
+}
+
+/// A response, once redirects were followed.
+pub(super) struct Page {
+    pub status: u16,
+    /// Where the redirects ended up.
+    pub url: Url,
+    /// The start of the body.
+    pub body: String,
+}
+
+/// Request `url`.
+pub(super) async fn fetch(client: &reqwest::Client, url: &str) -> Result<Page, String> {
+    let mut response = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| messages::text("error.request_failed", &[("error", &e)]))?;
+    let status = response.status().as_u16();
+    let url = response.url().clone();
+    let mut body = Vec::new();
+    while body.len() < MAX_BODY {
+        match response.chunk().await {
+            Ok(Some(chunk)) => body.extend_from_slice(&chunk),
+            Ok(None) => break,
+            Err(e) => {
+                return Err(messages::text(
+                    "error.failed.read_response",
+                    &[("error", &e)],
+                ))
+            }
+        }
+    }
+    Ok(Page {
+        status,
+        url,
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
+
+/// `url` as given if it is a full URL, or else joined onto `site_url`.
+pub(super) fn full_url(site_url: &str, url: &str) -> String {
+    if url.starts_with('/') {
+        format!("{}{url}", site_url.trim_end_matches('/'))
+    } else {
+        url.to_string()
+    }
+}
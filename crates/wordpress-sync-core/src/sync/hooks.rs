@@ -0,0 +1,105 @@
+//! The profile's own commands, run at fixed points of a sync.
+//!
+//! Each runs in the WordPress root of the side it names, with variables
+//! describing the job in its environment:
+//!
+//! | Variable | Value |
+//! |---|---|
+//! | `WORDPRESS_SYNC_HOOK` | The hook point, e.g. `after_files` |
+//! | `WORDPRESS_SYNC_JOB_ID` | The job's id, as in the sync history |
+//! | `WORDPRESS_SYNC_PROFILE_ID`, `WORDPRESS_SYNC_PROFILE_NAME` | The profile |
+//! | `WORDPRESS_SYNC_DIRECTION` | `push` or `pull` |
+//! | `WORDPRESS_SYNC_COMPONENTS` | `all`, `files`, `uploads_only`, or `database` |
+//! | `WORDPRESS_SYNC_SOURCE_URL`, `WORDPRESS_SYNC_DESTINATION_URL` | The two site URLs |
+//! | `WORDPRESS_SYNC_LOCAL_PATH`, `WORDPRESS_SYNC_REMOTE_PATH` | The two WordPress roots |
+
+use super::endpoint::remote_sh_line;
+use super::{Job, Phase};
+use crate::history::text;
+use crate::messages;
+use crate::profile::{HookPoint, HookSide, SudoStep};
+
+/// The hook point reached when `phase` finishes.
+fn after(phase: Phase) -> Option<HookPoint> {
+    match phase {
+        Phase::Connect => Some(HookPoint::BeforeSync),
+        Phase::TransferFiles => Some(HookPoint::AfterFiles),
+        Phase::ImportDatabase => Some(HookPoint::AfterDbImport),
+        Phase::Finalize => Some(HookPoint::AfterSync),
+        _ => None,
+    }
+}
+
+impl Job<'_> {
+    /// Run the hooks of the point reached when `phase` finishes, if it is one.
+    pub(super) async fn run_hooks_after(&self, phase: Phase) -> Result<(), String> {
+        match after(phase) {
+            Some(point) => self.run_hooks(point).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Run the profile's hooks for `point`, in the order they are listed, then
+    /// its custom steps. A required hook that fails stops the rest and fails
+    /// the sync; any other failure is a warning.
+    pub(super) async fn run_hooks(&self, point: HookPoint) -> Result<(), String> {
+        let vars = self.hook_vars(point);
+        for hook in self.profile.hooks.iter().filter(|hook| hook.point == point) {
+            tracing::info!(point = %text(&point), command = %hook.command, "running hook");
+            let ran = match hook.side {
+                HookSide::Local => self.local().sh(&hook.command, &vars).await,
+                HookSide::Remote => match self.vetted(&hook.command) {
+                    Ok(()) => {
+                        let line = remote_sh_line(&self.profile.remote, &hook.command, &vars);
+                        self.run_remote(SudoStep::Hooks, &line, &[]).await
+                    }
+                    Err(e) => Err(e),
+                },
+            };
+            let Err(e) = ran else {
+                continue;
+            };
+            let message = messages::text(
+                "error.hook_failed",
+                &[
+                    ("point", &text(&point)),
+                    ("command", &hook.command),
+                    ("error", &e),
+                ],
+            );
+            if hook.required {
+                return Err(message);
+            }
+            self.warn(message);
+        }
+        self.run_custom_steps(point).await
+    }
+
+    /// Refuse `command`, one of the profile's own, unless the profile lets
+    /// it run on the server.
+    pub(super) fn vetted(&self, command: &str) -> Result<(), String> {
+        match self.profile.remote_commands.allows(command) {
+            true => Ok(()),
+            false => Err(messages::text(
+                "error.command_isnt_one_profiles_vetted_remote",
+                &[("command", &command)],
+            )),
+        }
+    }
+
+    fn hook_vars(&self, point: HookPoint) -> Vec<(&'static str, String)> {
+        let dest = self.destination();
+        vec![
+            ("WORDPRESS_SYNC_HOOK", text(&point)),
+            ("WORDPRESS_SYNC_JOB_ID", self.id.clone()),
+            ("WORDPRESS_SYNC_PROFILE_ID", self.profile.id.clone()),
+            ("WORDPRESS_SYNC_PROFILE_NAME", self.profile.name.clone()),
+            ("WORDPRESS_SYNC_DIRECTION", text(&self.direction)),
+            ("WORDPRESS_SYNC_COMPONENTS", text(&self.components)),
+            ("WORDPRESS_SYNC_SOURCE_URL", self.source_url().to_string()),
+            ("WORDPRESS_SYNC_DESTINATION_URL", dest.url().to_string()),
+            ("WORDPRESS_SYNC_LOCAL_PATH", self.local().root()),
+            ("WORDPRESS_SYNC_REMOTE_PATH", self.remote().root()),
+        ]
+    }
+}
@@ -0,0 +1,30 @@
+//! The HTTP client setup shared by the health checks, the REST API backend,
+//! webhooks, and the managed host clients.
+
+use std::sync::Once;
+
+use crate::net::AddressFamily;
+use crate::proxy::Route;
+
+/// A client builder that identifies the app, sends its requests through
+/// `route`'s proxy if there is one, or else connects over `family`'s
+/// addresses, and verifies certificates against the system's roots, with TLS
+/// from the same `ring` provider the FTP transfer uses.
+pub(crate) fn builder(
+    route: Option<&Route>,
+    family: AddressFamily,
+) -> Result<reqwest::ClientBuilder, String> {
+    static PROVIDER: Once = Once::new();
+    PROVIDER.call_once(|| {
+        // A provider installed earlier by someone else is just as good.
+        let _ = tokio_rustls::rustls::crypto::ring::default_provider().install_default();
+    });
+    let builder = reqwest::Client::builder()
+        .user_agent(concat!("WordPress Sync/", env!("CARGO_PKG_VERSION")));
+    match route {
+        Some(route) => Ok(builder.proxy(route.reqwest()?)),
+        // Bound to one family's address, the client only connects to that
+        // family's; either way, it races them like `net::connect`.
+        None => Ok(builder.local_address(family.unspecified())),
+    }
+}
@@ -0,0 +1,128 @@
+//! Moving a site to HTTPS along with its domain, as in the usual local
+//! `http://` install pushed to an `https://` production site.
+//!
+//! With the profile's `https_migration` on and an `https://` destination, the
+//! search-replace also rewrites `http://` links to the source's or the
+//! destination's domain to the destination's `https://` origin, and the dump is
+//! then searched for `http://` links to other hosts, which browsers block or
+//! flag as mixed content on an HTTPS page. Those are only warned about: the
+//! other hosts may not serve HTTPS at all.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use url::Url;
+
+use super::Job;
+use crate::messages::Message;
+use crate::search_replace;
+
+/// Hosts whose `http://` URLs are names rather than links, such as XML
+/// namespaces and microformat profiles, which never load anything.
+const NAMESPACE_HOSTS: &[&str] = &[
+    "www.w3.org",
+    "schema.org",
+    "gmpg.org",
+    "purl.org",
+    "ogp.me",
+    "ns.adobe.com",
+    "xmlns.com",
+];
+
+/// Hosts named in the warning, by how many links they have.
+const HOSTS_NAMED: usize = 5;
+
+impl Job<'_> {
+    /// Whether the sync moves the site to HTTPS.
+    pub(super) fn migrates_to_https(&self) -> bool {
+        self.profile.https_migration && self.destination().url().starts_with("https://")
+    }
+
+    /// The pairs that follow the site URL's when the sync moves the site to
+    /// HTTPS: `http://` on either site's domain to the destination's origin.
+    pub(super) fn https_replacements(&self) -> Vec<(String, String)> {
+        if !self.migrates_to_https() {
+            return Vec::new();
+        }
+        let dest = self.destination();
+        let (Some(from), Some(to)) = (authority(self.source_url()), authority(dest.url())) else {
+            return Vec::new();
+        };
+        let origin = format!("https://{to}");
+        vec![
+            (format!("http://{from}"), origin.clone()),
+            (format!("http://{to}"), origin),
+        ]
+    }
+
+    /// After the search-replace, warn about the `http://` links to other hosts
+    /// left in the dump at `dump`.
+    pub(super) async fn warn_mixed_content(&self, dump: &Path) -> Result<(), String> {
+        if !self.migrates_to_https() {
+            return Ok(());
+        }
+        let mut hosts: HashMap<String, u64> = HashMap::new();
+        search_replace::scan_dump(dump, |_, _, value| {
+            for host in insecure_hosts(value) {
+                *hosts.entry(host).or_default() += 1;
+            }
+        })
+        .await?;
+        if hosts.is_empty() {
+            return Ok(());
+        }
+        let total: u64 = hosts.values().sum();
+        let mut hosts: Vec<(String, u64)> = hosts.into_iter().collect();
+        hosts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let mut named: Vec<String> = hosts
+            .iter()
+            .take(HOSTS_NAMED)
+            .map(|(host, count)| format!("{host} ({count})"))
+            .collect();
+        if hosts.len() > HOSTS_NAMED {
+            named.push(format!("{} more", hosts.len() - HOSTS_NAMED));
+        }
+        self.warn(Message::new(
+            "warning.total_references_other_hosts_over_http",
+            &[("total", &total), ("named", &named.join(", "))],
+        ));
+        Ok(())
+    }
+}
+
+/// The host of `url`, with its port if it has one.
+fn authority(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    Some(match url.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host.to_string(),
+    })
+}
+
+/// The hosts linked to over `http://` in `value`, once each, leaving out
+/// [`NAMESPACE_HOSTS`].
+fn insecure_hosts(value: &[u8]) -> Vec<String> {
+    const SCHEME: &[u8] = b"http://";
+    let mut hosts: Vec<String> = Vec::new();
+    let mut rest = value;
+    while let Some(at) = rest
+        .windows(SCHEME.len())
+        .position(|window| window.eq_ignore_ascii_case(SCHEME))
+    {
+        rest = &rest[at + SCHEME.len()..];
+        let len = rest
+            .iter()
+            .position(|&b| !(b.is_ascii_alphanumeric() || b"-.:".contains(&b)))
+            .unwrap_or(rest.len());
+        let host = String::from_utf8_lossy(&rest[..len])
+            .trim_end_matches(['.', ':'])
+            .to_ascii_lowercase();
+        let name = host.split(':').next().unwrap_or_default();
+        if !name.contains('.') || NAMESPACE_HOSTS.contains(&name) || hosts.contains(&host) {
+            continue;
+        }
+        hosts.push(host);
+    }
+    hosts
+}
@@ -0,0 +1,92 @@
+//! Uploads-only syncs that copy just the files modified since a date, or
+//! since the profile's last successful sync, as picked by the profile's
+//! `uploads_since`, so a quick content refresh of a large uploads library
+//! doesn't compare the whole of it on both sides.
+//!
+//! The source's uploads are listed, and those modified since then are copied
+//! as a two-way sync copies its files: with everything else excluded, so the
+//! rest of the destination isn't looked at. Nothing is deleted; a full
+//! uploads sync catches up with files removed from the source.
+
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+
+use super::transfer::{local_tree, Entry, FileChanges};
+use super::{Components, Direction, Job};
+use crate::history::{self, JobStatus};
+use crate::profile::UploadsSince;
+
+/// Earlier syncs searched for the last one that moved files.
+const HISTORY_DEPTH: u32 = 200;
+
+impl Job<'_> {
+    /// The time the source's uploads are copied from changes made since, or
+    /// `None` when they're mirrored in full.
+    fn uploads_cutoff(&self) -> Result<Option<DateTime<Utc>>, String> {
+        if self.components != Components::UploadsOnly || self.two_way() {
+            return Ok(None);
+        }
+        Ok(match self.profile.uploads_since {
+            UploadsSince::Everything => None,
+            UploadsSince::Date(since) => Some(since),
+            UploadsSince::LastSync => last_file_sync(&self.profile.id)?,
+        })
+    }
+
+    /// The source's files, relative to the uploads directory, that an
+    /// incremental uploads sync copies, or `None` when it isn't one.
+    pub(super) async fn changed_uploads(&self) -> Result<Option<Vec<String>>, String> {
+        let Some(cutoff) = self.uploads_cutoff()? else {
+            return Ok(None);
+        };
+        let excludes = self.excludes().await?;
+        let root = self.files_root(&self.source());
+        let tree = match self.direction {
+            Direction::Push => local_tree(Path::new(&root), &excludes).await?,
+            Direction::Pull => self.transfer.list(&root, &excludes).await?,
+        };
+        let since = u64::try_from(cutoff.timestamp()).unwrap_or(0);
+        // A file whose side can't report its time might have changed.
+        let paths: Vec<String> = tree
+            .into_iter()
+            .filter_map(|(path, entry)| match entry {
+                Entry::File { mtime, .. } if mtime.is_none_or(|mtime| mtime >= since) => Some(path),
+                _ => None,
+            })
+            .collect();
+        tracing::info!(%cutoff, files = paths.len(), "copying the uploads modified since");
+        Ok(Some(paths))
+    }
+
+    /// What the file transfer would change, without changing anything.
+    pub(super) async fn planned_changes(&self) -> Result<FileChanges, String> {
+        if let Some(changes) = self.server_changes.get() {
+            return Ok(changes.clone());
+        }
+        let excludes = match self.changed_uploads().await? {
+            Some(paths) if paths.is_empty() => return Ok(FileChanges::default()),
+            Some(paths) => self.only(&paths)?,
+            None => self.excludes().await?,
+        };
+        let (source, dest) = (self.source(), self.destination());
+        self.transfer
+            .plan(
+                self.direction,
+                &self.files_root(&source),
+                &self.files_root(&dest),
+                &excludes,
+            )
+            .await
+    }
+}
+
+/// When the last of `profile_id`'s syncs that moved files and succeeded,
+/// in either direction, started; changes since then are missing from one
+/// side or the other.
+fn last_file_sync(profile_id: &str) -> Result<Option<DateTime<Utc>>, String> {
+    Ok(history::list(Some(profile_id), Some(HISTORY_DEPTH))?
+        .into_iter()
+        .find(|entry| entry.status == JobStatus::Succeeded && entry.components.files())
+        .map(|entry| entry.started_at))
+}
@@ -0,0 +1,99 @@
+//! Tracking of running jobs so they can be cancelled from outside the engine.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio_util::sync::CancellationToken;
+
+use super::arbiter;
+use super::audit::Confirmation;
+use super::transfer::Throttle;
+
+/// Identity, cancellation token, and bandwidth limit of one running sync.
+#[derive(Clone)]
+pub struct JobHandle {
+    pub id: String,
+    pub cancel: CancellationToken,
+    /// The limit its file transfers keep to, starting at the profile's.
+    pub throttle: Arc<Throttle>,
+    /// How a push to a protected profile was confirmed, for its audit log.
+    /// A sync started by hand was confirmed by typing the profile's name.
+    pub confirmation: Confirmation,
+    /// The user saw the sync would delete more files than the profile's
+    /// warning policy allows, and said to go ahead.
+    pub allow_large_deletion: bool,
+}
+
+/// All jobs currently running in this process.
+#[derive(Default)]
+pub struct JobRegistry {
+    jobs: Mutex<HashMap<String, JobHandle>>,
+}
+
+impl JobRegistry {
+    /// Register a new job and return its handle.
+    pub fn start(&self) -> JobHandle {
+        self.start_with_id(&uuid::Uuid::new_v4().to_string())
+            .expect("a fresh job id is never in use")
+    }
+
+    /// Register a job under an existing id, as when an interrupted sync is
+    /// resumed. Returns `None` if a job with that id is already running.
+    pub fn start_with_id(&self, job_id: &str) -> Option<JobHandle> {
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.contains_key(job_id) {
+            return None;
+        }
+        let handle = JobHandle {
+            id: job_id.to_string(),
+            cancel: CancellationToken::new(),
+            throttle: Arc::default(),
+            confirmation: Confirmation::default(),
+            allow_large_deletion: false,
+        };
+        jobs.insert(handle.id.clone(), handle.clone());
+        Some(handle)
+    }
+
+    /// Ask a running job to stop. Returns false if no such job is running.
+    pub fn cancel(&self, job_id: &str) -> bool {
+        match self.jobs.lock().unwrap().get(job_id) {
+            Some(handle) => {
+                handle.cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Change a running job's bandwidth limit, in KiB per second, or lift it
+    /// with `None`. Returns false if no such job is running.
+    pub fn set_bandwidth(&self, job_id: &str, limit: Option<u64>) -> bool {
+        match self.jobs.lock().unwrap().get(job_id) {
+            Some(handle) => {
+                handle.throttle.set(limit);
+                arbiter::rebalance();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Hold a running job's transfers back between files, and its database
+    /// import between tables, or with `paused` false let them go on. Returns
+    /// false if no such job is running.
+    pub fn set_paused(&self, job_id: &str, paused: bool) -> bool {
+        match self.jobs.lock().unwrap().get(job_id) {
+            Some(handle) => {
+                handle.throttle.set_paused(paused);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Forget a job once it has finished, successfully or not.
+    pub fn finish(&self, job_id: &str) {
+        self.jobs.lock().unwrap().remove(job_id);
+    }
+}
@@ -0,0 +1,304 @@
+//! Running programs on this machine.
+
+use std::ffi::OsStr;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::OnceLock;
+
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::process::{Child, Command};
+
+use super::at_rest;
+use crate::messages;
+use crate::stderr;
+
+/// Well-known binary locations used when the login shell's PATH can't be read.
+const FALLBACK_PATH: &str = "/opt/homebrew/bin:/usr/local/bin:/usr/bin:/bin:/usr/sbin:/sbin";
+
+/// The user's full login-shell PATH.
+///
+/// App bundles on macOS inherit a minimal PATH without Homebrew, MAMP, or pipx
+/// locations, so (like `PATH_SETUP` in `cli.ts`) we ask the login shell once and
+/// reuse the answer for every local command.
+fn login_path() -> &'static str {
+    static PATH: OnceLock<String> = OnceLock::new();
+    PATH.get_or_init(|| {
+        let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/zsh".to_string());
+        std::process::Command::new(shell)
+            .args(["-lc", "echo $PATH"])
+            .stdin(Stdio::null())
+            .output()
+            .ok()
+            .filter(|out| out.status.success())
+            .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+            .filter(|path| !path.is_empty())
+            .unwrap_or_else(|| match std::env::var("PATH") {
+                Ok(inherited) => format!("{FALLBACK_PATH}:{inherited}"),
+                Err(_) => FALLBACK_PATH.to_string(),
+            })
+    })
+}
+
+/// Build a command for a local program with the login PATH and agent socket applied.
+pub fn command(program: impl AsRef<OsStr>) -> Command {
+    let mut cmd = Command::new(program);
+    cmd.env("PATH", login_path())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    if let Some(sock) = crate::ssh::agent_socket() {
+        cmd.env("SSH_AUTH_SOCK", sock);
+    }
+    cmd
+}
+
+/// A command as it's logged: its program and arguments, without the
+/// environment, which can hold a password such as `MYSQL_PWD`.
+fn shown(cmd: &Command) -> String {
+    let cmd = cmd.as_std();
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|part| format!("{part:?}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Run a command to completion, returning its stdout or an error containing stderr.
+pub async fn output(mut cmd: Command, what: &str) -> Result<String, String> {
+    tracing::debug!(command = %shown(&cmd), "running {what}");
+    let mut child = cmd.spawn().map_err(|e| {
+        messages::text("error.failed.start_what", &[("what", &what), ("error", &e)])
+    })?;
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+    let mut out = Vec::new();
+    let read = stdout.read_to_end(&mut out);
+    let (read, err) = tokio::join!(read, stderr::read(&mut stderr));
+    finish(child, what, err).await?;
+    read.map_err(|e| {
+        messages::text(
+            "error.failed.read_what_output",
+            &[("what", &what), ("error", &e)],
+        )
+    })?;
+    Ok(String::from_utf8_lossy(&out).into_owned())
+}
+
+/// Run a command to completion, handing each line of stdout to `on_line` as it arrives.
+///
+/// Carriage returns count as line breaks so in-place progress meters (rsync's
+/// `--progress`) are reported as they update rather than when the line finishes.
+pub async fn stream_lines(
+    cmd: Command,
+    what: &str,
+    mut on_line: impl FnMut(&str),
+) -> Result<(), String> {
+    let mut pending = Vec::new();
+    let streamed = stream_stdout(cmd, what, |data| {
+        split_lines(&mut pending, data, &mut on_line)
+    })
+    .await;
+    if !pending.is_empty() {
+        on_line(&String::from_utf8_lossy(&pending));
+    }
+    streamed
+}
+
+/// Hand each line `data` finishes to `on_line`, the way [`stream_lines`]
+/// splits them, keeping the unfinished one in `pending`.
+pub fn split_lines(pending: &mut Vec<u8>, data: &[u8], on_line: &mut impl FnMut(&str)) {
+    for &b in data {
+        if b == b'\n' || b == b'\r' {
+            if !pending.is_empty() {
+                on_line(&String::from_utf8_lossy(pending));
+                pending.clear();
+            }
+        } else {
+            pending.push(b);
+        }
+    }
+}
+
+/// Run a command to completion, handing its stdout to `on_data` a chunk at a
+/// time as it arrives.
+pub async fn stream_stdout(
+    mut cmd: Command,
+    what: &str,
+    mut on_data: impl FnMut(&[u8]),
+) -> Result<(), String> {
+    tracing::debug!(command = %shown(&cmd), "running {what}");
+    let mut child = cmd.spawn().map_err(|e| {
+        messages::text("error.failed.start_what", &[("what", &what), ("error", &e)])
+    })?;
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+
+    let read_stdout = async {
+        let mut buf = [0u8; 8192];
+        loop {
+            let n = stdout.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            on_data(&buf[..n]);
+        }
+        Ok::<(), std::io::Error>(())
+    };
+    let (out, err) = tokio::join!(read_stdout, stderr::read(&mut stderr));
+    out.map_err(|e| {
+        messages::text(
+            "error.failed.read_what_output",
+            &[("what", &what), ("error", &e)],
+        )
+    })?;
+    finish(child, what, err).await
+}
+
+/// Run a command to completion, copying its stdout into `dest` and passing the
+/// running byte count to `on_bytes` after each chunk.
+pub async fn copy_stdout(
+    mut cmd: Command,
+    what: &str,
+    dest: &mut (impl AsyncWrite + Unpin),
+    mut on_bytes: impl FnMut(u64),
+) -> Result<(), String> {
+    tracing::debug!(command = %shown(&cmd), "running {what}");
+    let mut child = cmd.spawn().map_err(|e| {
+        messages::text("error.failed.start_what", &[("what", &what), ("error", &e)])
+    })?;
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+
+    let copy = async {
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut total = 0;
+        loop {
+            let n = stdout.read(&mut buf).await.map_err(|e| {
+                messages::text(
+                    "error.failed.read_what_output",
+                    &[("what", &what), ("error", &e)],
+                )
+            })?;
+            if n == 0 {
+                break;
+            }
+            dest.write_all(&buf[..n]).await.map_err(|e| {
+                messages::text(
+                    "error.failed.save_what_output",
+                    &[("what", &what), ("error", &e)],
+                )
+            })?;
+            total += n as u64;
+            on_bytes(total);
+        }
+        dest.flush().await.map_err(|e| {
+            messages::text(
+                "error.failed.save_what_output",
+                &[("what", &what), ("error", &e)],
+            )
+        })
+    };
+
+    let (copied, err) = tokio::join!(copy, stderr::read(&mut stderr));
+    copied?;
+    finish(child, what, err).await
+}
+
+/// Run a command to completion with the file at `input` as its stdin.
+pub async fn feed_stdin(mut cmd: Command, what: &str, input: &Path) -> Result<(), String> {
+    let mut file = at_rest::open(input).await?;
+    tracing::debug!(command = %shown(&cmd), "running {what}");
+    let mut child = cmd.stdin(Stdio::piped()).spawn().map_err(|e| {
+        messages::text("error.failed.start_what", &[("what", &what), ("error", &e)])
+    })?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+
+    let send = async move {
+        tokio::io::copy(&mut file, &mut stdin).await?;
+        // Closing stdin is what tells the command the input is over.
+        stdin.shutdown().await
+    };
+    let mut discard = tokio::io::sink();
+    let drain = tokio::io::copy(&mut stdout, &mut discard);
+    let (sent, _, err) = tokio::join!(send, drain, stderr::read(&mut stderr));
+    // A command that failed part way closes its stdin, so its own error comes first.
+    finish(child, what, err).await?;
+    sent.map_err(|e| {
+        messages::text(
+            "error.failed.send_path_what",
+            &[("path", &input.display()), ("what", &what), ("error", &e)],
+        )
+    })
+}
+
+/// Run a command to completion with `input` as its stdin, returning its stdout
+/// or an error containing stderr.
+pub async fn output_with_input(
+    mut cmd: Command,
+    what: &str,
+    input: &[u8],
+) -> Result<String, String> {
+    tracing::debug!(command = %shown(&cmd), "running {what}");
+    let mut child = cmd.stdin(Stdio::piped()).spawn().map_err(|e| {
+        messages::text("error.failed.start_what", &[("what", &what), ("error", &e)])
+    })?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let mut stdout = child.stdout.take().expect("stdout is piped");
+    let mut stderr = child.stderr.take().expect("stderr is piped");
+
+    let send = async move {
+        stdin.write_all(input).await?;
+        stdin.shutdown().await
+    };
+    let mut out = Vec::new();
+    let read = stdout.read_to_end(&mut out);
+    let (sent, read, err) = tokio::join!(send, read, stderr::read(&mut stderr));
+    finish(child, what, err).await?;
+    read.map_err(|e| {
+        messages::text(
+            "error.failed.read_what_output",
+            &[("what", &what), ("error", &e)],
+        )
+    })?;
+    sent.map_err(|e| {
+        messages::text(
+            "error.failed.send_input_what",
+            &[("what", &what), ("error", &e)],
+        )
+    })?;
+    Ok(String::from_utf8_lossy(&out).into_owned())
+}
+
+/// Wait for a child whose output has been read, failing with its stderr if it failed.
+async fn finish(
+    mut child: Child,
+    what: &str,
+    stderr: std::io::Result<Vec<u8>>,
+) -> Result<(), String> {
+    let err = stderr.map_err(|e| {
+        messages::text(
+            "error.failed.read_what_output",
+            &[("what", &what), ("error", &e)],
+        )
+    })?;
+    let status = child
+        .wait()
+        .await
+        .map_err(|e| messages::text("error.failed.wait_what", &[("what", &what), ("error", &e)]))?;
+    if status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&err);
+        Err(messages::text(
+            "error.what_failed_status_stderr",
+            &[
+                ("what", &what),
+                ("status", &status),
+                ("stderr", &stderr.trim()),
+            ],
+        ))
+    }
+}
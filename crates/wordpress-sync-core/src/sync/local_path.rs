@@ -0,0 +1,240 @@
+//! Paths on this machine, which on Windows aren't POSIX ones.
+//!
+//! The file phases name everything by its `/`-separated path below a root,
+//! the way the server does. On this machine those are turned into native
+//! paths a component at a time, so Windows gets backslashes throughout, and
+//! the `\\?\` prefix once a path is too long for its old limit. Windows'
+//! names match whatever their case, so trees and prefixes are compared the
+//! same way there.
+
+use std::path::{Component, Path, PathBuf, Prefix};
+
+use crate::byte_path;
+
+/// The longest path Windows takes without the long-path prefix; directories
+/// need room for an 8.3 name after them.
+const MAX_PATH: usize = 248;
+
+/// Whether this machine's file names are the same whatever their case.
+pub const CASE_INSENSITIVE: bool = cfg!(windows);
+
+/// The `/`-separated `relative` below the local directory `root`.
+pub fn join(root: &Path, relative: &str) -> PathBuf {
+    let mut path = root.to_path_buf();
+    for part in relative.split('/').filter(|part| !part.is_empty()) {
+        path.push(byte_path::to_os(part));
+    }
+    long(path)
+}
+
+/// `path`, with Windows' long-path prefix if it's too long to do without.
+pub fn long(path: PathBuf) -> PathBuf {
+    if !cfg!(windows) || path.as_os_str().len() < MAX_PATH {
+        return path;
+    }
+    match path.to_str().and_then(prefixed) {
+        Some(prefixed) => PathBuf::from(prefixed),
+        None => path,
+    }
+}
+
+/// The `/`-separated path of `path` below `root`, or `None` if it isn't below it.
+pub fn relative(root: &Path, path: &Path) -> Option<String> {
+    let mut below = path.components().filter(|part| *part != Component::CurDir);
+    for part in root.components().filter(|part| *part != Component::CurDir) {
+        if !same_name(&name(&part), &name(&below.next()?)) {
+            return None;
+        }
+    }
+    Some(below.map(|part| name(&part)).collect::<Vec<_>>().join("/"))
+}
+
+/// Whether two names on this machine are of the same file.
+pub fn same_name(a: &str, b: &str) -> bool {
+    if CASE_INSENSITIVE {
+        a.to_lowercase() == b.to_lowercase()
+    } else {
+        a == b
+    }
+}
+
+/// A path on this machine, such as where a link points, as the server writes
+/// it: with `/` between its components.
+pub fn for_server(path: &Path) -> String {
+    let path = byte_path::from_os(path.as_os_str());
+    if cfg!(windows) {
+        path.replace('\\', "/")
+    } else {
+        path
+    }
+}
+
+/// A local path as rsync takes it on this machine: as is, except on Windows,
+/// where rsync is Cygwin's and a drive letter would be read as a host.
+pub fn for_rsync(path: &str) -> String {
+    if cfg!(windows) {
+        posix(path)
+    } else {
+        path.to_string()
+    }
+}
+
+/// A Windows path as Cygwin sees it: `C:\Sites\a` is `/cygdrive/c/Sites/a`,
+/// and `\\server\share\a` is `//server/share/a`.
+fn posix(path: &str) -> String {
+    let path = path
+        .strip_prefix(r"\\?\UNC\")
+        .map(|rest| format!(r"\\{rest}"))
+        .unwrap_or_else(|| path.strip_prefix(r"\\?\").unwrap_or(path).to_string());
+    let slashed = path.replace('\\', "/");
+    match slashed.as_bytes() {
+        [drive, b':', rest @ ..] if drive.is_ascii_alphabetic() => {
+            let rest = String::from_utf8_lossy(rest);
+            format!(
+                "/cygdrive/{}/{}",
+                (*drive as char).to_ascii_lowercase(),
+                rest.trim_start_matches('/')
+            )
+        }
+        _ => slashed,
+    }
+}
+
+/// `path` with the long-path prefix, which also turns off Windows' tidying
+/// of it: forward slashes become backslashes here, and a path with `.` or
+/// `..` in it is left as it is.
+fn prefixed(path: &str) -> Option<String> {
+    if path.starts_with(r"\\?\") || path.starts_with(r"\\.\") {
+        return None;
+    }
+    let path = path.replace('/', "\\");
+    if path.split('\\').any(|part| part == "." || part == "..") {
+        return None;
+    }
+    if let Some(share) = path.strip_prefix(r"\\") {
+        return Some(format!(r"\\?\UNC\{share}"));
+    }
+    match path.as_bytes() {
+        [drive, b':', b'\\', ..] if drive.is_ascii_alphabetic() => Some(format!(r"\\?\{path}")),
+        _ => None,
+    }
+}
+
+/// A path component's name. A drive or share is named the same with the
+/// long-path prefix as without it.
+fn name(part: &Component) -> String {
+    match part {
+        Component::Prefix(prefix) => match prefix.kind() {
+            Prefix::Disk(drive) | Prefix::VerbatimDisk(drive) => format!("{}:", drive as char),
+            Prefix::UNC(server, share) | Prefix::VerbatimUNC(server, share) => format!(
+                r"\\{}\{}",
+                server.to_string_lossy(),
+                share.to_string_lossy()
+            ),
+            _ => prefix.as_os_str().to_string_lossy().into_owned(),
+        },
+        _ => byte_path::from_os(part.as_os_str()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::Excludes;
+
+    #[test]
+    fn drive_letters_map_to_cygdrive() {
+        assert_eq!(posix(r"C:\Sites\blog"), "/cygdrive/c/Sites/blog");
+        assert_eq!(posix("D:/Sites/blog/"), "/cygdrive/d/Sites/blog/");
+        assert_eq!(posix(r"e:\"), "/cygdrive/e/");
+    }
+
+    #[test]
+    fn backslashes_become_slashes() {
+        assert_eq!(posix(r"\\server\share\blog"), "//server/share/blog");
+        assert_eq!(posix(r"Sites\blog"), "Sites/blog");
+        assert_eq!(posix("/home/me/blog"), "/home/me/blog");
+    }
+
+    #[test]
+    fn long_path_prefixes_are_dropped_for_rsync() {
+        assert_eq!(posix(r"\\?\C:\Sites\blog"), "/cygdrive/c/Sites/blog");
+        assert_eq!(posix(r"\\?\UNC\server\share\blog"), "//server/share/blog");
+    }
+
+    #[test]
+    fn long_path_prefix() {
+        assert_eq!(
+            prefixed(r"C:\Sites\blog").as_deref(),
+            Some(r"\\?\C:\Sites\blog")
+        );
+        assert_eq!(
+            prefixed("C:/Sites/blog").as_deref(),
+            Some(r"\\?\C:\Sites\blog")
+        );
+        assert_eq!(
+            prefixed(r"\\server\share\blog").as_deref(),
+            Some(r"\\?\UNC\server\share\blog")
+        );
+        assert_eq!(prefixed(r"\\?\C:\Sites"), None);
+        assert_eq!(prefixed(r"C:\Sites\..\blog"), None);
+        assert_eq!(prefixed(r"Sites\blog"), None);
+    }
+
+    #[test]
+    fn joins_server_paths_a_component_at_a_time() {
+        let root = Path::new("site");
+        assert_eq!(
+            join(root, "/wp-content//uploads/"),
+            root.join("wp-content").join("uploads")
+        );
+        assert_eq!(
+            relative(root, &root.join("wp-content").join("a.jpg")).as_deref(),
+            Some("wp-content/a.jpg")
+        );
+        assert_eq!(relative(root, Path::new("other/a.jpg")), None);
+    }
+
+    #[test]
+    fn names_match_by_case_as_this_machine_does() {
+        assert!(same_name("Uploads", "Uploads"));
+        assert_eq!(same_name("Uploads", "uploads"), CASE_INSENSITIVE);
+    }
+
+    #[test]
+    fn excludes_can_match_whatever_the_case() {
+        let patterns = ["Cache/".to_string(), "/wp-content/*.LOG".to_string()];
+        let ignoring = Excludes::matching(&patterns, true).unwrap();
+        assert!(ignoring.is_excluded("wp-content/cache", true));
+        assert!(ignoring.is_excluded("wp-content/debug.log", false));
+        let keeping = Excludes::matching(&patterns, false).unwrap();
+        assert!(!keeping.is_excluded("wp-content/cache", true));
+        assert!(!keeping.is_excluded("wp-content/debug.log", false));
+        assert!(keeping.is_excluded("wp-content/Cache", true));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_paths() {
+        assert_eq!(for_rsync(r"C:\Sites\blog"), "/cygdrive/c/Sites/blog");
+        assert_eq!(
+            for_server(Path::new(r"wp-content\uploads")),
+            "wp-content/uploads"
+        );
+        assert_eq!(
+            relative(
+                Path::new(r"C:\Sites"),
+                Path::new(r"\\?\c:\sites\Blog\a.jpg")
+            )
+            .as_deref(),
+            Some("Blog/a.jpg")
+        );
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn posix_paths_are_left_alone() {
+        assert_eq!(for_rsync(r"/srv/a\b"), r"/srv/a\b");
+        assert_eq!(for_server(Path::new("/srv/blog")), "/srv/blog");
+    }
+}
@@ -0,0 +1,413 @@
+//! WordPress sites set up by the local development tools on this machine, to
+//! offer as a new profile's local install.
+//!
+//! Local (Flywheel) keeps its sites in `sites.json` in its config directory,
+//! and MAMP serves whatever is in its `htdocs`; both are read from disk. DDEV
+//! and Lando are asked through their own CLIs, which only know the projects
+//! they have seen. A tool that isn't installed finds nothing, and a tool that
+//! fails is logged and skipped, so the others still show up.
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use super::local;
+use crate::messages;
+use crate::profile::wp_config::{self, WP_CONFIG};
+use crate::profile::{ContainerRuntime, DatabaseSettings, LocalContainer, LocalEnvironment};
+
+/// Where DDEV mounts a project inside its web container.
+const DDEV_ROOT: &str = "/var/www/html";
+
+/// A local development tool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocalTool {
+    /// Local, by Flywheel (now WP Engine).
+    Local,
+    Ddev,
+    Lando,
+    Mamp,
+}
+
+/// A site one of the tools hosts, with what it says about reaching its database.
+#[derive(Debug, Clone, Serialize)]
+pub struct LocalSite {
+    pub tool: LocalTool,
+    pub name: String,
+    /// The WordPress root.
+    pub path: PathBuf,
+    pub url: String,
+    /// From its `wp-config.php` where that can be read, or else from the tool.
+    pub database: Option<DatabaseSettings>,
+    /// The MySQL socket the tool gives the site, if it uses one.
+    pub db_socket: Option<PathBuf>,
+    /// The port its MySQL listens on from this machine, if the tool says.
+    pub db_port: Option<u16>,
+    /// The container to run wp-cli in, for tools whose sites need one.
+    pub container: Option<LocalContainer>,
+}
+
+impl LocalSite {
+    /// The site as a profile's local install.
+    pub fn environment(&self) -> LocalEnvironment {
+        LocalEnvironment {
+            path: self.path.clone(),
+            url: self.url.clone(),
+            database: self.database.clone(),
+            container: self.container.clone(),
+        }
+    }
+}
+
+/// Every site the local development tools on this machine know of, by tool,
+/// then by name.
+pub async fn detect_local_sites() -> Vec<LocalSite> {
+    let (ddev, lando) = tokio::join!(ddev_sites(), lando_sites());
+    let mut sites = Vec::new();
+    for (tool, found) in [
+        (LocalTool::Local, local_sites()),
+        (LocalTool::Ddev, ddev),
+        (LocalTool::Lando, lando),
+        (LocalTool::Mamp, mamp_sites()),
+    ] {
+        match found {
+            Ok(mut found) => {
+                found.sort_by_cached_key(|site| site.name.to_lowercase());
+                sites.extend(found);
+            }
+            Err(e) => tracing::warn!(?tool, "not listing its sites: {e}"),
+        }
+    }
+    sites
+}
+
+/// Local's sites, from its `sites.json`.
+fn local_sites() -> Result<Vec<LocalSite>, String> {
+    let Some(config) = dirs::config_dir().map(|dir| dir.join("Local")) else {
+        return Ok(Vec::new());
+    };
+    let file = config.join("sites.json");
+    let raw = match std::fs::read_to_string(&file) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(messages::text(
+                "error.failed.read_path",
+                &[("path", &file.display()), ("error", &e)],
+            ))
+        }
+    };
+    let sites: serde_json::Map<String, Value> = serde_json::from_str(&raw).map_err(|e| {
+        messages::text(
+            "error.invalid_path",
+            &[("path", &file.display()), ("error", &e)],
+        )
+    })?;
+    Ok(sites
+        .iter()
+        .map(|(id, site)| {
+            let path = expand_home(&text(&site["path"])).join("app").join("public");
+            let mysql = &site["mysql"];
+            let db_port = site["services"]["mysql"]["ports"]["MYSQL"][0]
+                .as_u64()
+                .and_then(|port| u16::try_from(port).ok());
+            // Windows builds of Local talk to MySQL over TCP only.
+            let db_socket = (!cfg!(windows)).then(|| {
+                config
+                    .join("run")
+                    .join(id)
+                    .join("mysql")
+                    .join("mysqld.sock")
+            });
+            let fallback = DatabaseSettings {
+                name: text(&mysql["database"]),
+                user: text(&mysql["user"]),
+                host: "localhost".to_string(),
+                table_prefix: "wp_".to_string(),
+            };
+            LocalSite {
+                tool: LocalTool::Local,
+                name: text(&site["name"]),
+                url: format!("http://{}", text(&site["domain"])),
+                database: database(&path, Some(fallback)),
+                path,
+                db_socket,
+                db_port,
+                container: None,
+            }
+        })
+        .collect())
+}
+
+/// DDEV's WordPress projects, from `ddev list` and `ddev describe`.
+async fn ddev_sites() -> Result<Vec<LocalSite>, String> {
+    let Some(list) = json_output("ddev", &["list", "--json-output"], None).await? else {
+        return Ok(Vec::new());
+    };
+    let mut sites = Vec::new();
+    for project in list["raw"].as_array().into_iter().flatten() {
+        if project["type"] != "wordpress" {
+            continue;
+        }
+        let name = text(&project["name"]);
+        let approot = PathBuf::from(text(&project["approot"]));
+        let docroot = text(&project["docroot"]);
+        let (path, container_path) = match docroot.as_str() {
+            "" => (approot, DDEV_ROOT.to_string()),
+            docroot => (approot.join(docroot), format!("{DDEV_ROOT}/{docroot}")),
+        };
+        // Only a running project has a database port published.
+        let description = json_output("ddev", &["describe", "--json-output", &name], None)
+            .await
+            .ok()
+            .flatten();
+        let dbinfo = description
+            .as_ref()
+            .map(|description| &description["raw"]["dbinfo"]);
+        let db_port = dbinfo
+            .and_then(|dbinfo| port(&dbinfo["published_port"]))
+            .filter(|&port| port > 0);
+        let fallback = DatabaseSettings {
+            name: "db".to_string(),
+            user: "db".to_string(),
+            host: "db".to_string(),
+            table_prefix: "wp_".to_string(),
+        };
+        sites.push(LocalSite {
+            tool: LocalTool::Ddev,
+            url: text(&project["primary_url"]),
+            database: database(&path, Some(fallback)),
+            name,
+            path,
+            db_socket: None,
+            db_port,
+            // Its database is only reachable from inside the project.
+            container: Some(LocalContainer {
+                runtime: ContainerRuntime::Ddev,
+                name: String::new(),
+                path: container_path,
+            }),
+        });
+    }
+    Ok(sites)
+}
+
+/// Lando's apps, from `lando list` and each app's `lando info`.
+async fn lando_sites() -> Result<Vec<LocalSite>, String> {
+    let Some(containers) = json_output("lando", &["list", "--format", "json"], None).await? else {
+        return Ok(Vec::new());
+    };
+    let mut apps: Vec<(String, PathBuf)> = Vec::new();
+    for container in containers.as_array().into_iter().flatten() {
+        let app = text(&container["app"]);
+        let Some(dir) = container["src"][0]
+            .as_str()
+            .and_then(|src| Path::new(src).parent())
+        else {
+            continue;
+        };
+        if !app.is_empty() && !apps.iter().any(|(name, _)| *name == app) {
+            apps.push((app, dir.to_path_buf()));
+        }
+    }
+
+    let mut sites = Vec::new();
+    for (name, dir) in apps {
+        let Some(services) = json_output("lando", &["info", "--format", "json"], Some(&dir))
+            .await?
+            .and_then(|info| info.as_array().cloned())
+        else {
+            continue;
+        };
+        let service = |kind: &str| {
+            services
+                .iter()
+                .find(|service| service["service"] == kind)
+                .cloned()
+                .unwrap_or(Value::Null)
+        };
+        let (appserver, db) = (service("appserver"), service("database"));
+        let path = match lando_webroot(&dir).as_deref() {
+            None | Some(".") => dir.clone(),
+            Some(webroot) => dir.join(webroot),
+        };
+        // Prefer the proxied `*.lndo.site` address over the bare port.
+        let urls: Vec<String> = appserver["urls"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(text)
+            .collect();
+        let url = urls
+            .iter()
+            .find(|url| url.contains(".lndo.site"))
+            .or(urls.first())
+            .cloned()
+            .unwrap_or_else(|| format!("https://{name}.lndo.site"));
+        let creds = &db["creds"];
+        let fallback = (!creds.is_null()).then(|| DatabaseSettings {
+            name: text(&creds["database"]),
+            user: text(&creds["user"]),
+            host: text(&db["internal_connection"]["host"]),
+            table_prefix: "wp_".to_string(),
+        });
+        sites.push(LocalSite {
+            tool: LocalTool::Lando,
+            url: url.trim_end_matches('/').to_string(),
+            database: database(&path, fallback),
+            db_port: port(&db["external_connection"]["port"]),
+            name,
+            path,
+            db_socket: None,
+            container: None,
+        });
+    }
+    Ok(sites)
+}
+
+/// The `webroot` an app's `.lando.yml` sets, if it sets one.
+fn lando_webroot(dir: &Path) -> Option<String> {
+    let raw = std::fs::read_to_string(dir.join(".lando.yml")).ok()?;
+    raw.lines()
+        .filter_map(|line| line.trim().strip_prefix("webroot:"))
+        .map(|value| value.trim().trim_matches(['"', '\'']).to_string())
+        .find(|value| !value.is_empty())
+}
+
+/// The WordPress installs in MAMP's document root, and the root itself if it
+/// is one.
+fn mamp_sites() -> Result<Vec<LocalSite>, String> {
+    let (root, base_url, socket, port) = if cfg!(windows) {
+        (
+            PathBuf::from(r"C:\MAMP\htdocs"),
+            "http://localhost",
+            None,
+            3306,
+        )
+    } else if cfg!(target_os = "macos") {
+        (
+            PathBuf::from("/Applications/MAMP/htdocs"),
+            "http://localhost:8888",
+            Some(PathBuf::from("/Applications/MAMP/tmp/mysql/mysql.sock")),
+            8889,
+        )
+    } else {
+        return Ok(Vec::new());
+    };
+    let entries = match std::fs::read_dir(&root) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => {
+            return Err(messages::text(
+                "error.failed.list_path",
+                &[("path", &root.display()), ("error", &e)],
+            ))
+        }
+    };
+    let mut dirs: Vec<(String, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().join(WP_CONFIG).is_file())
+        .map(|entry| {
+            (
+                entry.file_name().to_string_lossy().into_owned(),
+                entry.path(),
+            )
+        })
+        .collect();
+    if root.join(WP_CONFIG).is_file() {
+        dirs.push((String::new(), root.clone()));
+    }
+    Ok(dirs
+        .into_iter()
+        .map(|(dir, path)| LocalSite {
+            tool: LocalTool::Mamp,
+            name: if dir.is_empty() {
+                "MAMP".to_string()
+            } else {
+                dir.clone()
+            },
+            url: if dir.is_empty() {
+                base_url.to_string()
+            } else {
+                format!("{base_url}/{dir}")
+            },
+            database: database(&path, None),
+            path,
+            db_socket: socket.clone(),
+            db_port: Some(port),
+            container: None,
+        })
+        .collect())
+}
+
+/// The database settings in the install's `wp-config.php`, or `fallback`.
+fn database(path: &Path, fallback: Option<DatabaseSettings>) -> Option<DatabaseSettings> {
+    wp_config::read(path).ok().or(fallback)
+}
+
+/// The JSON a tool's CLI prints, or `None` if the tool isn't installed.
+async fn json_output(
+    program: &str,
+    args: &[&str],
+    dir: Option<&Path>,
+) -> Result<Option<Value>, String> {
+    let mut cmd = local::command(program);
+    cmd.args(args);
+    if let Some(dir) = dir {
+        cmd.current_dir(dir);
+    }
+    let out = match cmd.output().await {
+        Ok(out) => out,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(messages::text(
+                "error.failed.start_what",
+                &[("what", &program), ("error", &e)],
+            ))
+        }
+    };
+    let what = format!("{program} {}", args.join(" "));
+    if !out.status.success() {
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        return Err(messages::text(
+            "error.what_failed_status_stderr",
+            &[
+                ("what", &what),
+                ("status", &out.status),
+                ("stderr", &stderr.trim()),
+            ],
+        ));
+    }
+    serde_json::from_slice(&out.stdout).map(Some).map_err(|e| {
+        messages::text(
+            "error.what_returned_unexpected_output",
+            &[("what", &what), ("error", &e)],
+        )
+    })
+}
+
+/// `~/` at the start of `path` spelled out.
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Number(number) => number.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// A port, which the tools give as a number or a string.
+fn port(value: &Value) -> Option<u16> {
+    value
+        .as_u64()
+        .or_else(|| value.as_str()?.parse().ok())
+        .and_then(|port| u16::try_from(port).ok())
+}
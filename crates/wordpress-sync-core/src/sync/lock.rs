@@ -0,0 +1,125 @@
+//! One sync per profile at a time, across every process on this machine.
+//!
+//! A sync holds an exclusive advisory lock on `~/.wordpress-sync/locks/<id>.lock`
+//! for as long as it runs. The operating system drops the lock if the process
+//! dies, so a crash never leaves a profile locked. Who holds it is written to
+//! `<id>.json` next to it, since on Windows a locked file can't be read.
+//! A crash leaves that behind, and [`sweep`] removes it from beside a lock
+//! nobody holds.
+
+use std::fs::{self, File, TryLockError};
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::SyncError;
+use crate::messages::Message;
+use crate::paths;
+
+/// The holder of a profile's lock.
+#[derive(Serialize, Deserialize)]
+struct Owner {
+    job_id: String,
+    pid: u32,
+    started_at: DateTime<Utc>,
+}
+
+/// A profile's lock, released when dropped.
+pub(super) struct ProfileLock {
+    _file: File,
+    owner_path: PathBuf,
+}
+
+impl ProfileLock {
+    /// Lock `profile_id` for `job_id`, or fail with [`SyncError::InProgress`]
+    /// naming the job that already holds it.
+    pub(super) fn acquire(profile_id: &str, job_id: &str) -> Result<Self, SyncError> {
+        let dir = paths::locks_dir().map_err(SyncError::config)?;
+        fs::create_dir_all(&dir).map_err(|e| {
+            SyncError::Config(Message::new(
+                "error.failed.create_path",
+                &[("path", &dir.display()), ("error", &e)],
+            ))
+        })?;
+        let path = dir.join(format!("{profile_id}.lock"));
+        let owner_path = path.with_extension("json");
+        let file = File::create(&path).map_err(|e| {
+            SyncError::Config(Message::new(
+                "error.failed.open_path",
+                &[("path", &path.display()), ("error", &e)],
+            ))
+        })?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let job_id = fs::read_to_string(&owner_path)
+                    .ok()
+                    .and_then(|raw| serde_json::from_str::<Owner>(&raw).ok())
+                    .map(|owner| owner.job_id)
+                    .unwrap_or_default();
+                let message = if job_id.is_empty() {
+                    Message::new("error.profile_sync_in_progress", &[])
+                } else {
+                    Message::new("error.profile_sync_in_progress_job", &[("job_id", &job_id)])
+                };
+                return Err(SyncError::InProgress { message, job_id });
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(SyncError::Config(Message::new(
+                    "error.failed.lock_path",
+                    &[("path", &path.display()), ("error", &e)],
+                )));
+            }
+        }
+        let owner = Owner {
+            job_id: job_id.to_string(),
+            pid: std::process::id(),
+            started_at: Utc::now(),
+        };
+        // The lock is what counts; a missing owner file only makes the error vaguer.
+        if let Ok(json) = serde_json::to_string(&owner) {
+            let _ = fs::write(&owner_path, json);
+        }
+        Ok(Self {
+            _file: file,
+            owner_path,
+        })
+    }
+}
+
+impl Drop for ProfileLock {
+    fn drop(&mut self) {
+        // Removed while the lock is still held, so it can't be a later owner's.
+        let _ = fs::remove_file(&self.owner_path);
+    }
+}
+
+/// Remove the owner files crashed jobs left beside locks nobody holds,
+/// returning how many.
+pub(super) fn sweep() -> usize {
+    let Ok(entries) =
+        paths::locks_dir().and_then(|dir| fs::read_dir(&dir).map_err(|e| e.to_string()))
+    else {
+        return 0;
+    };
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let owner_path = entry.path();
+        if owner_path
+            .extension()
+            .is_none_or(|extension| extension != "json")
+        {
+            continue;
+        }
+        let Ok(file) = File::create(owner_path.with_extension("lock")) else {
+            continue;
+        };
+        // Removed while the lock is held, so it can't be a new owner's.
+        if file.try_lock().is_ok() && fs::remove_file(&owner_path).is_ok() {
+            tracing::info!(path = %owner_path.display(), "removed a crashed job's lock owner");
+            removed += 1;
+        }
+    }
+    removed
+}
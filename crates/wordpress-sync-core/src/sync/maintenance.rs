@@ -0,0 +1,131 @@
+//! Maintenance mode on the destination while it is being overwritten.
+//!
+//! WordPress shows its "briefly unavailable" page whenever a `.maintenance`
+//! file in the root sets a recent `$upgrading`. The file written here sets it
+//! to the time of each request, so it holds however long the sync takes. It is
+//! removed when the job ends, whether or not the job succeeded, unless it was
+//! already there before the sync.
+//!
+//! The file is known to be this app's by what's in it. One a crashed job left
+//! is taken over by the profile's next sync and removed with its own; see
+//! [`stale`](super::stale) for the other ways out.
+
+use std::sync::atomic::Ordering;
+
+use super::endpoint::Endpoint;
+use super::Job;
+use crate::messages::{self, Message};
+use crate::shell;
+
+/// The file WordPress checks, relative to the root.
+pub(super) const MAINTENANCE_FILE: &str = ".maintenance";
+
+const MAINTENANCE_PHP: &str = "<?php $upgrading = time(); ?>\n";
+
+/// Whether a maintenance file with `contents` is one this app wrote.
+pub(super) fn is_ours(contents: &str) -> bool {
+    contents == MAINTENANCE_PHP
+}
+
+/// A shell test of whether the maintenance file at `path`, quoted, is one
+/// this app wrote.
+pub(super) fn is_ours_test(path: &str) -> String {
+    format!(
+        "grep -qxF {} {path}",
+        shell::quote(MAINTENANCE_PHP.trim_end())
+    )
+}
+
+impl Job<'_> {
+    /// Put the destination into maintenance mode, if the profile asks for it
+    /// and this job hasn't already.
+    pub(super) async fn begin_maintenance(&self) -> Result<(), String> {
+        if !self.profile.maintenance_mode || self.maintenance.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        let dest = self.destination();
+        let path = dest.path(MAINTENANCE_FILE);
+        match &dest {
+            Endpoint::Local(_) => {
+                let existing = tokio::fs::read_to_string(&path).await;
+                if existing.as_deref().is_ok_and(is_ours) {
+                    self.take_over_maintenance();
+                    return Ok(());
+                }
+                if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+                    return Ok(());
+                }
+                self.maintenance.store(true, Ordering::SeqCst);
+                tokio::fs::write(&path, MAINTENANCE_PHP).await.map_err(|e| {
+                    messages::text("error.failed.write_path", &[("path", &path), ("error", &e)])
+                })
+            }
+            Endpoint::Remote { remote: None, .. } => {
+                self.warn(Message::new("error.no_ssh.maintenance", &[]));
+                // Marked as begun all the same, so the warning is only given once.
+                self.maintenance.store(true, Ordering::SeqCst);
+                Ok(())
+            }
+            Endpoint::Remote {
+                remote: Some(remote),
+                ..
+            } => {
+                let path = shell::quote(&path);
+                let existing = remote
+                    .exec(&format!(
+                        "if [ -e {path} ]; then if {}; then echo ours; else echo exists; fi; fi",
+                        is_ours_test(&path)
+                    ))
+                    .await?;
+                match existing.trim() {
+                    "ours" => {
+                        self.take_over_maintenance();
+                        return Ok(());
+                    }
+                    "exists" => return Ok(()),
+                    _ => {}
+                }
+                self.maintenance.store(true, Ordering::SeqCst);
+                remote
+                    .exec_with_input(&format!("cat > {path}"), MAINTENANCE_PHP.as_bytes())
+                    .await
+                    .map(drop)
+            }
+        }
+    }
+
+    /// Count a maintenance file an earlier job of the profile left behind as
+    /// this one's: the profile's lock means no other job of it is running.
+    fn take_over_maintenance(&self) {
+        tracing::info!("taking over the maintenance mode a stopped job left on");
+        self.maintenance.store(true, Ordering::SeqCst);
+    }
+
+    /// Take the destination out of the maintenance mode this job put it in.
+    pub(super) async fn end_maintenance(&self) -> Result<(), String> {
+        if !self.maintenance.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+        let dest = self.destination();
+        let path = dest.path(MAINTENANCE_FILE);
+        match &dest {
+            Endpoint::Local(_) => match tokio::fs::remove_file(&path).await {
+                Ok(()) => Ok(()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                Err(e) => Err(messages::text(
+                    "error.failed.remove_path",
+                    &[("path", &path), ("error", &e)],
+                )),
+            },
+            Endpoint::Remote { remote: None, .. } => Ok(()),
+            Endpoint::Remote {
+                remote: Some(remote),
+                ..
+            } => remote
+                .exec(&format!("rm -f {}", shell::quote(&path)))
+                .await
+                .map(drop),
+        }
+        .map_err(|e| messages::text("error.destination_still_maintenance_mode", &[("error", &e)]))
+    }
+}
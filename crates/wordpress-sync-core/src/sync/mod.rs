@@ -0,0 +1,1620 @@
+//! Native push/pull pipeline.
+//!
+//! A sync runs a fixed sequence of [`Phase`]s between the local install and the
+//! remote server: connect, dump the source database, rewrite its URLs, back up
+//! the destination, transfer files, import the dump, clear the destination's
+//! caches, and finalize. Each phase is a direct wp-cli, ssh, or rsync
+//! invocation, or native code — there is no intermediate shell script whose
+//! output has to be parsed.
+
+mod activation;
+mod arbiter;
+pub(crate) mod at_rest;
+mod audit;
+mod backup;
+mod blackout;
+mod browse;
+mod cdn;
+mod collation;
+mod composer;
+mod confirm;
+mod console;
+mod content_sample;
+mod custom_steps;
+mod db;
+mod db_diff;
+mod doctor;
+mod drift;
+mod endpoint;
+mod environment;
+mod estimate;
+mod events;
+mod extensions;
+mod file_manifest;
+mod git;
+mod health;
+mod hooks;
+pub(crate) mod http;
+mod https;
+mod incremental;
+mod jobs;
+mod local;
+mod local_path;
+mod local_sites;
+mod lock;
+mod maintenance;
+mod multisite;
+mod mysql;
+mod notify;
+mod onboarding;
+mod options;
+mod pacing;
+mod parallel_import;
+mod partitions;
+mod performance;
+mod permissions;
+mod php;
+mod plan;
+mod preflight;
+mod preview;
+mod progress;
+mod provision;
+mod queue;
+mod redis;
+mod relay;
+mod remote;
+mod report;
+mod resources;
+mod response_diff;
+mod rest;
+mod restore_check;
+mod resume;
+mod retry;
+mod salts;
+mod scope;
+mod screenshots;
+mod search_index;
+mod space;
+mod stale;
+mod sudo;
+mod temp;
+mod transfer;
+mod two_way;
+mod visibility;
+mod warnings;
+mod watch;
+mod webhooks;
+mod woocommerce;
+mod wp_cli;
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::Instrument;
+
+use crate::byte_path;
+use crate::error::SyncError;
+use crate::history::{self, HistoryEntry, JobStatus};
+use crate::logging;
+use crate::messages::{self, Message};
+use crate::net::AddressFamily;
+use crate::profile::{Profile, ReplacementStrategy, SudoStep, TransferMethod, WarningAction};
+use crate::proxy::ProxyChoice;
+use crate::search_replace::{self, Anonymizer, PrefixRemap};
+use crate::ssh::ConnectError;
+use crate::telemetry;
+
+use db::RowFilter;
+use endpoint::Endpoint;
+use mysql::Mysql;
+use progress::Reporter;
+use remote::Remote;
+use resume::Manifest;
+use retry::Backoff;
+use transfer::{Checksums, FileChanges, MirrorOptions, OnProgress, Transfer, TransferStats};
+
+pub(crate) use arbiter::rebalance;
+pub use audit::{audit_log, AuditEntry, Confirmation};
+pub use backup::{list as restore_points, prune, prune_periodically, rollback, RestorePoint};
+pub use blackout::check_blackouts;
+pub use browse::{list_remote_dir, stat_remote_file, RemoteEntry, RemoteEntryKind, RemoteFileStat};
+pub use confirm::{PushConfirmation, PushConfirmations};
+pub use console::{run_query, QueryEnvironment, QueryResult};
+pub use custom_steps::{step_plugin, step_plugins, PluginStepInfo, StepPlugin};
+pub use db_diff::{diff_databases, DatabaseDiff, TableDiff, TableStats};
+pub use doctor::{doctor, DoctorCheck, DoctorCheckKind, DoctorReport};
+pub use drift::Drift;
+pub use environment::{detect_environment, remote_wp_config, Environment};
+pub use estimate::{estimate, SyncEstimate};
+pub use events::{job_events, JobEvent, JobEventKind, EVENTS_VERSION};
+pub use extensions::{local_extensions, remote_extensions, Extension, ExtensionKind};
+pub use file_manifest::{
+    browse_manifest, search_manifest, FileChange, ManifestEntry, ManifestNode,
+};
+pub use health::HealthCheckResult;
+pub use jobs::{JobHandle, JobRegistry};
+pub use local_sites::{detect_local_sites, LocalSite, LocalTool};
+pub use notify::JobNotification;
+pub use onboarding::{
+    create_profile_from_wizard, detect_prerequisites, Prerequisite, PrerequisiteKind,
+    Prerequisites, WizardAnswers, WizardResult,
+};
+pub(crate) use partitions::is_partition;
+pub use partitions::{upload_partitions, PartitionSize, UploadPartition};
+pub use performance::{performance_trends, PerformanceTrends, PerformanceWeek, PhaseTrend};
+pub use plan::{plan, PlannedTotals, SyncPlan};
+pub use preflight::{test_connection, ConnectionTest};
+pub use preview::{
+    preview_replacements, ReplacementMatches, ReplacementPreview, ReplacementSample,
+};
+pub use progress::{ProgressSink, SyncProgress};
+pub use provision::{clone_site, CloneRequest, CloneResult};
+pub use queue::{JobQueue, QueueStatus, QueuedSync};
+pub use relay::{sync_servers, ServerRoute, ServerSyncSummary};
+pub use report::{report, ReportFormat};
+pub use resources::ResourceUsage;
+pub use response_diff::PageComparison;
+pub use restore_check::{
+    plan_rollback, FileCheck, RestoreVerification, RollbackPlan, TableCheck, TableRevert,
+};
+pub use resume::resume;
+pub use retry::SyncRetry;
+pub use screenshots::ScreenshotDiff;
+pub use stale::{cleanup_stale_state, sweep_stale_state, StaleCleanup};
+pub use temp::sweep_temp_files;
+pub use transfer::{Excludes, Throttle};
+pub use two_way::TwoWayPlan;
+pub use warnings::LargeDeletion;
+pub use watch::{watch, WatchEvent, Watches};
+pub use wp_cli::install_remote_wp_cli;
+
+/// Paths that are never transferred, whatever the profile's excludes say.
+/// Overwriting the destination's `wp-config.php` would point it at the wrong database.
+const ALWAYS_EXCLUDED: &[&str] = &["/wp-config.php", ".maintenance"];
+
+/// Which parts of the site a sync moves.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Components {
+    /// Files and database.
+    #[default]
+    All,
+    /// Every file, but not the database.
+    Files,
+    /// Just the uploads directory.
+    UploadsOnly,
+    /// Just the database.
+    Database,
+}
+
+impl Components {
+    fn files(self) -> bool {
+        !matches!(self, Self::Database)
+    }
+
+    fn database(self) -> bool {
+        matches!(self, Self::All | Self::Database)
+    }
+
+    /// Fail early for a combination the profile can't do.
+    fn check(self, profile: &Profile) -> Result<(), SyncError> {
+        if self == Self::Database && profile.remote.transfer == TransferMethod::Ftps {
+            return Err(SyncError::Config(Message::new(
+                "error.no_ssh.database_sync",
+                &[],
+            )));
+        }
+        if self == Self::UploadsOnly && profile.media_offload.is_some() {
+            return Err(SyncError::Config(Message::new(
+                "error.this_profiles_media_offloaded_bucket_so",
+                &[],
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Which way data flows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Local → remote.
+    Push,
+    /// Remote → local.
+    Pull,
+}
+
+/// A step of the sync pipeline, in execution order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Connect,
+    DumpDatabase,
+    SearchReplace,
+    /// Scrambling personal data in the dump, when it is imported into the
+    /// local site and the profile says what to scramble.
+    Anonymize,
+    Backup,
+    TransferFiles,
+    /// Copying posts, media, and settings over the REST API, for profiles that
+    /// sync that way instead of copying files and tables.
+    SyncContent,
+    ImportDatabase,
+    PostSync,
+    Finalize,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 10] = [
+        Phase::Connect,
+        Phase::DumpDatabase,
+        Phase::SearchReplace,
+        Phase::Anonymize,
+        Phase::Backup,
+        Phase::TransferFiles,
+        Phase::SyncContent,
+        Phase::ImportDatabase,
+        Phase::PostSync,
+        Phase::Finalize,
+    ];
+
+    /// The phase's name for people, in the current locale.
+    pub fn label(self) -> String {
+        messages::text(&format!("phase.{}", history::text(&self)), &[])
+    }
+
+    /// Classify a failure of this phase.
+    fn error(self, message: String) -> SyncError {
+        match self {
+            Phase::Connect | Phase::Backup | Phase::PostSync | Phase::Finalize => {
+                SyncError::remote_command(message)
+            }
+            Phase::DumpDatabase
+            | Phase::SearchReplace
+            | Phase::Anonymize
+            | Phase::ImportDatabase => SyncError::database(message),
+            Phase::TransferFiles | Phase::SyncContent => SyncError::transfer(message),
+        }
+    }
+}
+
+/// How long a completed phase took and how much it moved.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PhaseReport {
+    pub phase: Phase,
+    pub duration_ms: u64,
+    pub bytes_transferred: u64,
+    pub files_transferred: u64,
+    /// `bytes_transferred` over `duration_ms`, or `None` when it moved nothing.
+    #[serde(default)]
+    pub bytes_per_second: Option<u64>,
+}
+
+impl PhaseReport {
+    /// `phase`, finished `duration_ms` after it started, having moved what
+    /// `stats` counts.
+    pub(crate) fn new(phase: Phase, duration_ms: u64, stats: &TransferStats) -> Self {
+        Self {
+            phase,
+            duration_ms,
+            bytes_transferred: stats.bytes_transferred,
+            files_transferred: stats.files_done,
+            bytes_per_second: throughput(stats.bytes_transferred, duration_ms),
+        }
+    }
+}
+
+/// `bytes` moved in `duration_ms` as bytes a second, or `None` for none.
+pub(crate) fn throughput(bytes: u64, duration_ms: u64) -> Option<u64> {
+    (bytes > 0).then(|| bytes.saturating_mul(1000) / duration_ms.max(1))
+}
+
+/// The result of a successful sync.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncSummary {
+    pub job_id: String,
+    pub profile_id: String,
+    pub direction: Direction,
+    pub components: Components,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub phases: Vec<PhaseReport>,
+    /// The restore point made before the destination changed, if one was.
+    pub restore_point_id: Option<String>,
+    /// Things that went wrong without failing the sync, such as a cache flush.
+    pub warnings: Vec<String>,
+    /// The catalog messages the warnings are, in the same order.
+    pub warning_messages: Vec<Message>,
+    /// In integrity mode, the copied files, relative to the transfer root,
+    /// whose checksums didn't match their source's afterwards.
+    pub checksum_mismatches: Vec<String>,
+    /// In a two-way uploads sync, the files changed on both sides, relative
+    /// to the uploads directory, which were left as they are.
+    pub upload_conflicts: Vec<String>,
+    /// The payment gateway settings changed on the destination to put its
+    /// store in test mode, as `<gateway>: <change>`.
+    pub test_mode_changes: Vec<String>,
+    /// The destination's health checks, in the profile's order; empty when
+    /// they are off.
+    pub health_checks: Vec<HealthCheckResult>,
+    /// The pages compared on the source's and destination's sites; empty
+    /// when the comparison is off.
+    pub page_comparisons: Vec<PageComparison>,
+    /// The server's pages before and after a push; empty when screenshots
+    /// are off.
+    pub screenshots: Vec<ScreenshotDiff>,
+    /// How much of this machine the job used.
+    pub resources: ResourceUsage,
+}
+
+/// What a job got through, whether or not it finished.
+struct Outcome {
+    manifest: Manifest,
+    warnings: Vec<Message>,
+    checksum_mismatches: Vec<String>,
+    upload_conflicts: Vec<String>,
+    test_mode_changes: Vec<String>,
+}
+
+/// Sync `components` of `profile` in `direction`, reporting progress to `sink`.
+/// Every run, successful or not, is recorded in the sync [`history`](crate::history),
+/// and a push to a protected profile in its [`audit_log`] as well.
+///
+/// Cancelling `handle` stops the job at once: the running phase's future is dropped,
+/// which kills any rsync or wp-cli child process and closes any SSH channel it was
+/// waiting on. The staged database dump is then removed before
+/// [`SyncError::Cancelled`] is returned.
+///
+/// Only one sync of a profile runs at a time, even across processes. Another
+/// fails at once with [`SyncError::InProgress`], and isn't recorded.
+///
+/// A phase that fails with a dropped connection or similar passing problem is
+/// retried, over a new connection, as the profile's [`RetryPolicy`] allows;
+/// `sink` hears of each retry first. A job that fails anyway can be picked up
+/// again with [`resume`].
+///
+/// [`RetryPolicy`]: crate::profile::RetryPolicy
+pub async fn run(
+    profile: &Profile,
+    direction: Direction,
+    components: Components,
+    handle: &JobHandle,
+    sink: &dyn ProgressSink,
+) -> Result<SyncSummary, SyncError> {
+    let manifest = Manifest::new(&handle.id, profile, direction, components);
+    execute(profile, manifest, handle.confirmation, handle, sink).await
+}
+
+/// Run `profile`'s preset named `preset`: its push or pull, with the options
+/// it sets in place of the profile's. Otherwise it's [`run`].
+pub async fn run_preset(
+    profile: &Profile,
+    preset: &str,
+    handle: &JobHandle,
+    sink: &dyn ProgressSink,
+) -> Result<SyncSummary, SyncError> {
+    let preset = profile.preset(preset).map_err(SyncError::config)?;
+    let mut configured = profile.clone();
+    preset.apply(&mut configured);
+    let mut manifest = Manifest::new(&handle.id, &configured, preset.direction, preset.components);
+    manifest.preset = Some(preset.name.clone());
+    tracing::info!(preset = %preset.name, "running a preset");
+    execute(&configured, manifest, handle.confirmation, handle, sink).await
+}
+
+/// Run the job `manifest` describes, skipping the phases it has finished, and
+/// record it. Shared by [`run`] and [`resume`].
+async fn execute(
+    profile: &Profile,
+    manifest: Manifest,
+    confirmation: Confirmation,
+    handle: &JobHandle,
+    sink: &dyn ProgressSink,
+) -> Result<SyncSummary, SyncError> {
+    let _lock = lock::ProfileLock::acquire(&profile.id, &handle.id)?;
+    let (direction, components, started_at) =
+        (manifest.direction, manifest.components, manifest.started_at);
+    let _pacing = pacing::follow(&profile.bandwidth, direction, &handle.throttle);
+    let _share = arbiter::share_bandwidth(&handle.throttle);
+    let monitor = resources::ResourceMonitor::start();
+    let span = tracing::info_span!(
+        logging::JOB_SPAN,
+        job_id = %handle.id,
+        profile = %profile.name,
+        ?direction,
+        ?components,
+    );
+    let mut outcome = Outcome {
+        manifest,
+        warnings: Vec::new(),
+        checksum_mismatches: Vec::new(),
+        upload_conflicts: Vec::new(),
+        test_mode_changes: Vec::new(),
+    };
+    // Only a push from the start sees the server's pages as they were.
+    let mut screenshots = Vec::new();
+    if direction == Direction::Push
+        && profile.screenshots.enabled
+        && outcome.manifest.phases.is_empty()
+    {
+        tokio::select! {
+            shots = screenshots::before(profile, &handle.id).instrument(span.clone()) => screenshots = shots,
+            _ = handle.cancel.cancelled() => {}
+        }
+    }
+    let result = run_job(profile, handle, sink, &mut outcome)
+        .instrument(span.clone())
+        .await;
+    // The job's own warnings have been sent as they happened; those of the
+    // checks after it are sent once they're all in.
+    let live_warnings = outcome.warnings.len();
+    let health_checks = match &result {
+        Ok(()) if profile.health_checks.enabled => {
+            // The local site is never behind the proxy.
+            let remote = &profile.remote;
+            let (site_url, proxy, family) = match direction {
+                Direction::Push => (&remote.url, &remote.proxy, remote.address_family),
+                Direction::Pull => (&profile.local.url, &ProxyChoice::Direct, AddressFamily::Any),
+            };
+            tokio::select! {
+                results = health::check(site_url, proxy, family, &profile.health_checks).instrument(span.clone()) => results,
+                // The sync is done; only the checks are given up.
+                _ = handle.cancel.cancelled() => Vec::new(),
+            }
+        }
+        _ => Vec::new(),
+    };
+    outcome
+        .warnings
+        .extend(health_checks.iter().filter_map(|result| {
+            Some(Message::new(
+                "warning.health_check_failed",
+                &[("url", &result.url), ("error", result.error.as_ref()?)],
+            ))
+        }));
+    let page_comparisons = match &result {
+        Ok(()) if profile.response_diff.enabled => {
+            tokio::select! {
+                results = response_diff::compare(profile, direction).instrument(span.clone()) => results,
+                _ = handle.cancel.cancelled() => Vec::new(),
+            }
+        }
+        _ => Vec::new(),
+    };
+    match &result {
+        Ok(()) if !screenshots.is_empty() => {
+            tokio::select! {
+                () = screenshots::after(profile, &handle.id, &mut screenshots).instrument(span.clone()) => {}
+                _ = handle.cancel.cancelled() => screenshots.clear(),
+            }
+        }
+        _ => screenshots.clear(),
+    }
+    let threshold = profile.screenshots.threshold;
+    outcome
+        .warnings
+        .extend(
+            screenshots
+                .iter()
+                .filter_map(|shot| match (&shot.error, shot.changed) {
+                    (Some(e), _) => Some(Message::new(
+                        "warning.screenshot_failed",
+                        &[("path", &shot.path), ("error", e)],
+                    )),
+                    (None, Some(changed)) if changed > threshold => Some(Message::new(
+                        "warning.screenshot_looks_different",
+                        &[
+                            ("path", &shot.path),
+                            ("changed", &format_args!("{changed:.1}")),
+                        ],
+                    )),
+                    _ => None,
+                }),
+        );
+    outcome.warnings.extend(
+        page_comparisons
+            .iter()
+            .filter(|page| !page.matched)
+            .map(|page| {
+                Message::new(
+                    "warning.page_differs_between_sites",
+                    &[
+                        ("path", &page.path),
+                        ("differences", &page.differences.join("; ")),
+                    ],
+                )
+            }),
+    );
+    let finished_at = Utc::now();
+    span.in_scope(|| match &result {
+        Ok(()) => tracing::info!("sync finished"),
+        Err(e) => tracing::error!(error = %e, "sync failed"),
+    });
+
+    let status = match &result {
+        Ok(()) => JobStatus::Succeeded,
+        Err(SyncError::Cancelled) => JobStatus::Cancelled,
+        Err(_) => JobStatus::Failed,
+    };
+    // A failed job stays resumable; one that finished or was cancelled doesn't.
+    if status != JobStatus::Failed {
+        outcome.manifest.remove();
+    }
+    for warning in &outcome.warnings[live_warnings..] {
+        events::emit(sink, JobEvent::warning(&handle.id, warning));
+    }
+    let Outcome {
+        manifest,
+        warnings,
+        checksum_mismatches,
+        upload_conflicts,
+        test_mode_changes,
+    } = outcome;
+    let resources = monitor.finish(profile, &manifest.phases);
+    span.in_scope(|| tracing::info!(?resources, "resources used"));
+    let entry = HistoryEntry {
+        job_id: handle.id.clone(),
+        profile_id: profile.id.clone(),
+        profile_name: profile.name.clone(),
+        direction,
+        components,
+        started_at,
+        finished_at,
+        status,
+        error: result.as_ref().err().cloned(),
+        bytes_transferred: manifest.phases.iter().map(|p| p.bytes_transferred).sum(),
+        files_transferred: manifest.phases.iter().map(|p| p.files_transferred).sum(),
+        phases: manifest.phases.clone(),
+        warnings: warnings.iter().map(ToString::to_string).collect(),
+        restore_point_id: manifest.restore_point_id.clone(),
+        git_head: manifest.git_head.clone(),
+        screenshots: screenshots.clone(),
+        resources: Some(resources.clone()),
+    };
+    sink.finished(&entry);
+    events::emit(sink, JobEvent::completed(&entry));
+    audit::record(profile, &entry, confirmation);
+    webhooks::send(profile, &entry)
+        .instrument(span.clone())
+        .await;
+    // The sync itself is over; failing to write its history doesn't undo it.
+    let _ = tokio::task::spawn_blocking(move || {
+        telemetry::record_job(&entry);
+        history::record(&entry)
+    })
+    .await;
+    result?;
+
+    Ok(SyncSummary {
+        job_id: handle.id.clone(),
+        profile_id: profile.id.clone(),
+        direction,
+        components,
+        started_at,
+        finished_at,
+        phases: manifest.phases,
+        restore_point_id: manifest.restore_point_id,
+        warnings: warnings.iter().map(ToString::to_string).collect(),
+        warning_messages: warnings,
+        checksum_mismatches,
+        upload_conflicts,
+        test_mode_changes,
+        health_checks,
+        page_comparisons,
+        screenshots,
+        resources,
+    })
+}
+
+/// The body of [`execute`], filling in `outcome` as it goes. A transient
+/// failure is retried as the profile's retry policy allows.
+async fn run_job(
+    profile: &Profile,
+    handle: &JobHandle,
+    sink: &dyn ProgressSink,
+    outcome: &mut Outcome,
+) -> Result<(), SyncError> {
+    outcome.manifest.components.check(profile)?;
+    save_manifest(&outcome.manifest);
+    let mut backoff = Backoff::new(&profile.retry, outcome.manifest.phases.len());
+    // Whether an earlier attempt left the destination in maintenance mode.
+    let mut maintenance = false;
+    loop {
+        let Err(failure) = attempt(profile, handle, sink, outcome, &mut maintenance).await else {
+            return Ok(());
+        };
+        let Some((attempt, delay)) = failure
+            .retry
+            .then(|| backoff.next(outcome.manifest.phases.len()))
+            .flatten()
+        else {
+            // The failure was to be retried, so the attempt left it on.
+            if failure.retry && maintenance {
+                stale::end_maintenance(profile, outcome.manifest.direction, sink).await;
+            }
+            return Err(failure.error);
+        };
+        tracing::warn!(
+            phase = ?failure.phase,
+            attempt,
+            delay_ms = delay.as_millis() as u64,
+            error = %failure.error,
+            "retrying after a transient failure"
+        );
+        sink.retry(&SyncRetry {
+            job_id: handle.id.clone(),
+            phase: failure.phase,
+            attempt,
+            max_attempts: backoff.max_attempts(),
+            delay_ms: delay.as_millis() as u64,
+            error: failure.error,
+        });
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = handle.cancel.cancelled() => {
+                if maintenance {
+                    stale::end_maintenance(profile, outcome.manifest.direction, sink).await;
+                }
+                return Err(SyncError::Cancelled);
+            }
+        }
+    }
+}
+
+/// How an [`attempt`] at a job failed.
+struct Failure {
+    phase: Phase,
+    error: SyncError,
+    /// Whether another attempt may get past it. The attempt has then left the
+    /// staged dump and the maintenance mode in place for it.
+    retry: bool,
+}
+
+/// Connect and run the phases `outcome` hasn't finished, once. `maintenance`
+/// says whether the destination is in maintenance mode, before and after.
+async fn attempt(
+    profile: &Profile,
+    handle: &JobHandle,
+    sink: &dyn ProgressSink,
+    outcome: &mut Outcome,
+    maintenance: &mut bool,
+) -> Result<(), Failure> {
+    let (direction, components) = (outcome.manifest.direction, outcome.manifest.components);
+    let reporter = Reporter::new(handle.id.clone(), sink);
+
+    // Opening the SSH session belongs to the connect phase, but the job can't
+    // exist without it, so it happens here rather than in `run_phase`.
+    let connect_start = Instant::now();
+    reporter.report(
+        Phase::Connect,
+        &TransferStats::default(),
+        connect_start,
+        true,
+    );
+    let connected = tokio::select! {
+        connected = connect(profile, sink) => connected,
+        _ = handle.cancel.cancelled() => Err(SyncError::Cancelled),
+    };
+    let (remote, transfer, warning) = connected.map_err(|error| Failure {
+        phase: Phase::Connect,
+        retry: retry::is_transient(&error),
+        error,
+    })?;
+    let mut job = Job::new(profile, direction, components, remote, transfer, reporter);
+    // A retry connects again, and would say so again.
+    if let Some(warning) = warning.filter(|warning| !outcome.warnings.contains(warning)) {
+        job.warn(warning);
+    }
+    job.throttle = handle.throttle.clone();
+    job.allow_large_deletion = handle.allow_large_deletion;
+    if *maintenance {
+        job.maintenance.store(true, Ordering::SeqCst);
+    }
+
+    let result = async {
+        for phase in job.phases() {
+            // Connect checks the installs and detects a network, which every run needs.
+            if phase != Phase::Connect && outcome.manifest.finished(phase) {
+                tracing::info!(?phase, "phase already finished");
+                continue;
+            }
+            let start = if phase == Phase::Connect {
+                connect_start
+            } else {
+                let start = Instant::now();
+                job.reporter
+                    .report(phase, &TransferStats::default(), start, true);
+                start
+            };
+            tracing::info!(?phase, "phase started");
+            let fail = |error: SyncError, retry: bool| Failure {
+                phase,
+                retry: retry && job.retries(phase) && retry::is_transient(&error),
+                error,
+            };
+            let stats = tokio::select! {
+                result = job.run_phase(phase) => result.map_err(|e| fail(phase.error(e), true))?,
+                _ = handle.cancel.cancelled() => return Err(fail(SyncError::Cancelled, false)),
+            };
+            // Before anything is written, and only the first time round.
+            if phase == Phase::Connect && !outcome.manifest.finished(phase) {
+                tokio::select! {
+                    result = job.check_space() => result.map_err(|e| fail(e, false))?,
+                    _ = handle.cancel.cancelled() => return Err(fail(SyncError::Cancelled, false)),
+                };
+                outcome.manifest.git_head = tokio::select! {
+                    result = job.protect_git() => result.map_err(|e| fail(e, false))?,
+                    _ = handle.cancel.cancelled() => return Err(fail(SyncError::Cancelled, false)),
+                };
+                tokio::select! {
+                    result = job.check_warnings() => result.map_err(|e| fail(e, false))?,
+                    _ = handle.cancel.cancelled() => return Err(fail(SyncError::Cancelled, false)),
+                };
+            }
+            // Connect runs again on each attempt, but its hooks only once. A failed
+            // hook is the profile's own command failing, which isn't retried.
+            if !outcome.manifest.finished(phase) {
+                tokio::select! {
+                    result = job.run_hooks_after(phase) => {
+                        result.map_err(|e| fail(phase.error(e), false))?
+                    }
+                    _ = handle.cancel.cancelled() => return Err(fail(SyncError::Cancelled, false)),
+                };
+            }
+            let duration_ms = start.elapsed().as_millis() as u64;
+            tracing::info!(
+                ?phase,
+                duration_ms,
+                bytes = stats.bytes_transferred,
+                files = stats.files_done,
+                "phase finished"
+            );
+            if phase == Phase::Backup {
+                outcome.manifest.restore_point_id = Some(job.id.clone());
+            }
+            outcome
+                .manifest
+                .finish(PhaseReport::new(phase, duration_ms, &stats));
+            save_manifest(&outcome.manifest);
+        }
+        Ok::<(), Failure>(())
+    }
+    .await;
+
+    if let Err(failure) = &result {
+        // Don't leave dumps lying around after a failure or cancellation, except
+        // a finished dump a retry or resumed job can still import; the original
+        // error wins. This runs outside the cancellation race on purpose.
+        let resumable = !matches!(failure.error, SyncError::Cancelled)
+            && outcome.manifest.finished(Phase::DumpDatabase);
+        let _ = job.remove_dumps(resumable).await;
+        let _ = job.remove_incomplete_backup().await;
+        // A retry takes the site out of maintenance mode once it is through.
+        if !failure.retry {
+            if let Err(e) = job.end_maintenance().await {
+                job.warn(e);
+            }
+        }
+    }
+    *maintenance = job.maintenance.load(Ordering::SeqCst);
+    if let Some(remote) = &job.remote {
+        remote.close().await;
+    }
+    outcome.warnings.extend(job.take_warnings());
+    outcome
+        .checksum_mismatches
+        .extend(std::mem::take(&mut *job.mismatches.lock().unwrap()));
+    outcome
+        .upload_conflicts
+        .extend(std::mem::take(&mut *job.conflicts.lock().unwrap()));
+    outcome
+        .test_mode_changes
+        .extend(std::mem::take(&mut *job.test_mode_changes.lock().unwrap()));
+    result
+}
+
+/// Save a job's progress. A job that can't be resumed can still finish, so a
+/// failure is only logged.
+fn save_manifest(manifest: &Manifest) {
+    if let Err(e) = manifest.save() {
+        tracing::warn!("The job can't be resumed if interrupted: {e}");
+    }
+}
+
+/// Open the SSH connection (unless the profile transfers over FTP) and the
+/// profile's transfer method. An untrusted host key is passed to `sink` first.
+/// If the server has no rsync for an rsync transfer, files go over SFTP
+/// instead, unless the profile's warning policy blocks that; the warning, if
+/// the policy reports it, comes back with the connection.
+///
+/// If this future is dropped part way, the connection is closed without the
+/// polite disconnect, which is fine for a job that never started.
+async fn connect(
+    profile: &Profile,
+    sink: &dyn ProgressSink,
+) -> Result<(Option<Remote>, Box<dyn Transfer>, Option<Message>), SyncError> {
+    let remote = if profile.remote.transfer.uses_ssh() {
+        Some(connect_ssh(profile, sink).await?)
+    } else {
+        None
+    };
+    let mut warning = None;
+    let mut fallback = None;
+    if let (TransferMethod::Rsync, Some(remote)) = (profile.remote.transfer, &remote) {
+        let program = profile.rsync.remote_path.as_deref();
+        if transfer::remote_rsync(remote, program).await.is_none() {
+            let program = program.unwrap_or("rsync");
+            match profile.warnings.remote_rsync_missing {
+                WarningAction::Block => {
+                    return Err(SyncError::WarningBlocked(Message::new(
+                        "error.remote_rsync_missing",
+                        &[("program", &program)],
+                    )))
+                }
+                WarningAction::Warn => {
+                    warning = Some(Message::new(
+                        "warning.remote_rsync_missing_using_sftp",
+                        &[("program", &program)],
+                    ))
+                }
+                WarningAction::Ignore => {
+                    tracing::info!(
+                        program,
+                        "rsync didn't run on the server, copying files over SFTP instead"
+                    )
+                }
+            }
+            let mut sftp = profile.clone();
+            sftp.remote.transfer = TransferMethod::Sftp;
+            fallback = Some(sftp);
+        }
+    }
+    let transfer = transfer::open(fallback.as_ref().unwrap_or(profile), remote.as_ref())
+        .await
+        .map_err(SyncError::connection)?;
+    Ok((remote, transfer, warning))
+}
+
+/// Open the SSH connection, announcing an untrusted host key to `sink`.
+async fn connect_ssh(profile: &Profile, sink: &dyn ProgressSink) -> Result<Remote, SyncError> {
+    Remote::connect(profile, &|prompt| sink.auth_prompt(prompt))
+        .await
+        .map_err(|e| {
+            if let ConnectError::UnverifiedHostKey(key) = &e {
+                sink.host_key(key);
+            }
+            e.into()
+        })
+}
+
+/// Every table in the remote install's database, for choosing which to sync.
+pub async fn remote_tables(
+    profile: &Profile,
+    sink: &dyn ProgressSink,
+) -> Result<Vec<String>, SyncError> {
+    if !profile.remote.transfer.uses_ssh() {
+        return Err(SyncError::Config(Message::new(
+            "error.no_ssh.database_read",
+            &[],
+        )));
+    }
+    let remote = connect_ssh(profile, sink).await?;
+    let endpoint = Endpoint::Remote {
+        remote: Some(&remote),
+        env: &profile.remote,
+    };
+    let result = match mysql::open_without_wp_cli(profile, &remote).await {
+        Ok(Some(mysql)) => mysql.tables().await,
+        Ok(None) => db::tables(&endpoint).await,
+        Err(e) => Err(e),
+    };
+    remote.close().await;
+    result.map_err(SyncError::database)
+}
+
+/// The exclude rules a sync of `components` applies, relative to the directory
+/// being mirrored. `wp-config.php` is only let through with `wp_config`.
+fn excludes(
+    profile: &Profile,
+    components: Components,
+    wp_config: bool,
+) -> Result<Excludes, String> {
+    Excludes::new(&exclude_rules(profile, components, wp_config))
+}
+
+/// The rules behind [`excludes`], as written.
+fn exclude_rules(profile: &Profile, components: Components, wp_config: bool) -> Vec<String> {
+    let scope = scope::scope_rules(profile);
+    let always = ALWAYS_EXCLUDED
+        .iter()
+        .filter(|p| !(wp_config && **p == "/wp-config.php"))
+        .map(|p| p.to_string());
+    // Offloaded media is already in its bucket.
+    let offloaded = profile
+        .media_offload
+        .is_some()
+        .then(|| format!("/{}/", profile.project.uploads_dir()));
+    let composer = profile.project.excludes().iter().map(|p| p.to_string());
+    let patterns = scope
+        .into_iter()
+        .chain(partitions::partition_rules(profile))
+        .chain(always)
+        .chain(composer)
+        .chain(offloaded)
+        .chain(profile.excludes.iter().cloned());
+    let mut patterns: Vec<String> = match multisite::files_dir(profile, components) {
+        // Anchored patterns are relative to the WordPress root, so only those
+        // inside the mirrored directory still apply, minus that prefix.
+        Some(dir) => patterns
+            .filter_map(|pattern| transfer::rebase_exclude(&pattern, &dir))
+            .collect(),
+        None => patterns.collect(),
+    };
+    patterns.extend(multisite::subsite_excludes(profile).map(String::from));
+    patterns
+}
+
+/// Whether a full sync of `profile` would skip one sample path.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExcludeTest {
+    pub path: String,
+    pub excluded: bool,
+    /// The rule that decided, as written; `None` if no rule matches.
+    pub rule: Option<String>,
+}
+
+/// Check sample paths, relative to the WordPress root, against the rules a full
+/// sync of `profile` applies. A path ending in `/` is taken to be a directory.
+pub fn test_excludes(profile: &Profile, paths: &[String]) -> Result<Vec<ExcludeTest>, String> {
+    let excludes = excludes(profile, Components::All, false)?;
+    Ok(paths
+        .iter()
+        .map(|path| {
+            let decision = excludes.decision(path, path.ends_with('/'));
+            ExcludeTest {
+                path: path.clone(),
+                excluded: decision.is_some_and(|(excluded, _)| excluded),
+                rule: decision.map(|(_, rule)| rule.to_string()),
+            }
+        })
+        .collect())
+}
+
+/// `profile`'s own substitutions and its offloaded media's URLs, as `(from,
+/// to)` for a sync in `direction`.
+fn own_replacements(profile: &Profile, direction: Direction) -> Vec<(String, String)> {
+    let offload_urls = profile
+        .media_offload
+        .iter()
+        .flat_map(|offload| &offload.urls);
+    profile
+        .replacements
+        .iter()
+        .chain(offload_urls)
+        .map(|pair| match direction {
+            Direction::Push => (pair.local.clone(), pair.remote.clone()),
+            Direction::Pull => (pair.remote.clone(), pair.local.clone()),
+        })
+        .collect()
+}
+
+/// State for one run of the pipeline.
+struct Job<'a> {
+    id: String,
+    profile: &'a Profile,
+    direction: Direction,
+    components: Components,
+    /// The SSH connection; `None` for files-only profiles that transfer over FTP.
+    remote: Option<Remote>,
+    /// For a sync between servers, the profile of the server it copies from.
+    /// The job is then the destination's side, a push of its own profile
+    /// from there rather than from this machine.
+    from_server: Option<&'a Profile>,
+    /// For a sync between servers, what its file transfer changes, worked
+    /// out before anything is written, since the job's own transfer can't
+    /// compare the two servers.
+    server_changes: OnceLock<FileChanges>,
+    transfer: Box<dyn Transfer>,
+    reporter: Reporter<'a>,
+    warnings: Mutex<Vec<Message>>,
+    /// Whether the installs are a multisite network, once detected.
+    network: OnceLock<bool>,
+    /// Whether this job has put the destination into maintenance mode.
+    maintenance: AtomicBool,
+    /// The bandwidth limit of the file transfer.
+    throttle: Arc<Throttle>,
+    /// Whether a large deletion the profile blocks on may go ahead.
+    allow_large_deletion: bool,
+    /// Copied files whose checksums didn't match their source's afterwards.
+    mismatches: Mutex<Vec<String>>,
+    /// The job's file manifest, written as the file transfer changes files.
+    file_manifest: Mutex<file_manifest::Spool>,
+    /// Uploads a two-way sync left alone because both sides changed them.
+    conflicts: Mutex<Vec<String>>,
+    /// Payment gateway settings changed to put the destination in test mode.
+    test_mode_changes: Mutex<Vec<String>>,
+    /// The tunnelled connection to the server's database, once the preflight
+    /// check has chosen whether there is one.
+    mysql: tokio::sync::OnceCell<Option<Mysql>>,
+    /// Whether `wp-config.php` is mirrored, once checked.
+    wp_config: tokio::sync::OnceCell<bool>,
+    /// The directory its dumps and other temporary files are staged in.
+    _temp: temp::TempDir,
+}
+
+impl<'a> Job<'a> {
+    fn new(
+        profile: &'a Profile,
+        direction: Direction,
+        components: Components,
+        remote: Option<Remote>,
+        transfer: Box<dyn Transfer>,
+        reporter: Reporter<'a>,
+    ) -> Self {
+        let temp = temp::TempDir::hold(reporter.job_id());
+        Self {
+            id: reporter.job_id().to_string(),
+            profile,
+            direction,
+            components,
+            remote,
+            from_server: None,
+            server_changes: OnceLock::new(),
+            transfer,
+            reporter,
+            warnings: Mutex::default(),
+            network: OnceLock::new(),
+            maintenance: AtomicBool::new(false),
+            throttle: Arc::default(),
+            allow_large_deletion: false,
+            mismatches: Mutex::default(),
+            file_manifest: Mutex::default(),
+            conflicts: Mutex::default(),
+            test_mode_changes: Mutex::default(),
+            mysql: tokio::sync::OnceCell::new(),
+            wp_config: tokio::sync::OnceCell::new(),
+            _temp: temp,
+        }
+    }
+
+    /// Note a problem that doesn't fail the job.
+    fn warn(&self, warning: impl Into<Message>) {
+        let warning = warning.into();
+        tracing::warn!("{warning}");
+        self.reporter.warning(&warning);
+        self.warnings.lock().unwrap().push(warning);
+    }
+
+    fn take_warnings(&self) -> Vec<Message> {
+        std::mem::take(&mut self.warnings.lock().unwrap())
+    }
+
+    fn local(&self) -> Endpoint<'_> {
+        Endpoint::Local(&self.profile.local)
+    }
+
+    fn remote(&self) -> Endpoint<'_> {
+        Endpoint::Remote {
+            remote: self.remote.as_ref(),
+            env: &self.profile.remote,
+        }
+    }
+
+    /// The side data is copied from.
+    fn source(&self) -> Endpoint<'_> {
+        match self.direction {
+            Direction::Push => self.local(),
+            Direction::Pull => self.remote(),
+        }
+    }
+
+    /// The side data is copied to.
+    fn destination(&self) -> Endpoint<'_> {
+        match self.direction {
+            Direction::Push => self.remote(),
+            Direction::Pull => self.local(),
+        }
+    }
+
+    /// The site URL of the side data is copied from.
+    fn source_url(&self) -> &str {
+        match (self.from_server, self.direction) {
+            (Some(from), _) => &from.remote.url,
+            (None, Direction::Push) => &self.profile.local.url,
+            (None, Direction::Pull) => &self.profile.remote.url,
+        }
+    }
+
+    /// Whether the database is part of this job. Without a shell on the server
+    /// there's no wp-cli there, so only files can move.
+    fn syncs_database(&self) -> bool {
+        self.components.database() && self.remote.is_some()
+    }
+
+    /// Whether personal data is scrambled in the dump: the database is pulled
+    /// into the local site and the profile says what to scramble.
+    fn anonymizes(&self) -> bool {
+        self.syncs_database()
+            && self.direction == Direction::Pull
+            && !self.profile.anonymize.is_empty()
+    }
+
+    /// Whether files are mirrored across. A REST API sync moves media in its
+    /// content phase instead.
+    fn transfers_files(&self) -> bool {
+        // A subsite's files are only its uploads, which offloaded media leaves
+        // to the bucket.
+        let offloaded = self.profile.media_offload.is_some()
+            && multisite::files_dir(self.profile, self.components)
+                .is_some_and(|dir| dir.starts_with(self.profile.project.uploads_dir()));
+        self.components.files() && !self.syncs_content() && !offloaded
+    }
+
+    /// The phases this job runs, in order.
+    fn phases(&self) -> Vec<Phase> {
+        Phase::ALL
+            .into_iter()
+            .filter(|phase| match phase {
+                Phase::DumpDatabase | Phase::SearchReplace | Phase::ImportDatabase => {
+                    self.syncs_database()
+                }
+                Phase::Anonymize => self.anonymizes(),
+                Phase::Backup => self.backs_up(),
+                Phase::TransferFiles => self.transfers_files(),
+                Phase::SyncContent => self.syncs_content(),
+                Phase::PostSync => self.destination().has_shell() || self.purges_cdn(),
+                Phase::Connect | Phase::Finalize => true,
+            })
+            .collect()
+    }
+
+    /// The directory the file transfer mirrors on one side.
+    fn files_root(&self, side: &Endpoint<'_>) -> String {
+        match multisite::files_dir(self.profile, self.components) {
+            Some(dir) => side.path(&dir),
+            None => side.root(),
+        }
+    }
+
+    /// The profile's excludes plus the paths that are never transferred, relative
+    /// to the directory being mirrored.
+    async fn excludes(&self) -> Result<Excludes, String> {
+        excludes(self.profile, self.components, self.syncs_wp_config().await)
+    }
+
+    /// The `(from, to)` substitutions for the database, in the order they take
+    /// precedence: the profile's own pairs, its offloaded media's URLs, the site
+    /// URL and, moving to HTTPS, its `http://` forms, then for a network its
+    /// host name.
+    ///
+    /// Between servers, the source profile's pairs are pulled to their local
+    /// values and carried on by the destination profile's pairs from those,
+    /// which follow for values that are local already.
+    fn replacements(&self) -> Vec<(String, String)> {
+        let mut pairs = match self.from_server {
+            Some(from) => {
+                let here = own_replacements(self.profile, Direction::Push);
+                let mut pairs: Vec<(String, String)> = own_replacements(from, Direction::Pull)
+                    .into_iter()
+                    .map(|(there, local)| {
+                        let to = here
+                            .iter()
+                            .find(|(from, _)| *from == local)
+                            .map_or(local, |(_, to)| to.clone());
+                        (there, to)
+                    })
+                    .collect();
+                pairs.extend(here);
+                pairs
+            }
+            None => own_replacements(self.profile, self.direction),
+        };
+        pairs.push((
+            self.source_url().to_string(),
+            self.destination().url().to_string(),
+        ));
+        pairs.extend(self.https_replacements());
+        pairs.extend(self.network_replacement());
+        pairs.retain(|(from, to)| !from.is_empty() && from != to);
+        match self.profile.replacement_strategy {
+            ReplacementStrategy::Plain => pairs,
+            ReplacementStrategy::PageBuilder => search_replace::with_encoded_forms(pairs),
+        }
+    }
+
+    /// The rename from the source's table prefix to the destination's, if the
+    /// profile allows it and they differ.
+    async fn prefix_remap(&self) -> Result<Option<PrefixRemap>, String> {
+        if !self.profile.remap_table_prefix {
+            return Ok(None);
+        }
+        let from = self.table_prefix(&self.source()).await?;
+        let to = self.table_prefix(&self.destination()).await?;
+        Ok((from != to).then_some(PrefixRemap { from, to }))
+    }
+
+    /// The rows of `source`'s tables the dump leaves out: its cache rows, if
+    /// the profile skips them, and on a pull the posts its content sample
+    /// doesn't take.
+    async fn row_filters(&self, source: &Endpoint<'_>) -> Result<Vec<RowFilter>, String> {
+        let mut filters = Vec::new();
+        if self.profile.skip_cache_rows {
+            let tables = self.tables(source).await?;
+            let prefix = self.table_prefix(source).await?;
+            filters = db::cache_row_filters(&tables, &prefix);
+        }
+        filters.extend(self.content_sample_filters(source).await?);
+        Ok(filters)
+    }
+
+    /// Where the database dump is staged on this machine.
+    fn dump_path(&self) -> PathBuf {
+        Self::dump_path_for(&self.id)
+    }
+
+    /// Where job `job_id` stages its database dump.
+    fn dump_path_for(job_id: &str) -> PathBuf {
+        temp::path(job_id, "dump.sql")
+    }
+
+    /// Where the destination's user tables are kept while the dump is imported.
+    fn users_snapshot_path(&self) -> PathBuf {
+        temp::path(&self.id, "users.sql")
+    }
+
+    /// Run one phase, returning its final progress totals.
+    async fn run_phase(&self, phase: Phase) -> Result<TransferStats, String> {
+        let (source, dest) = (self.source(), self.destination());
+        let start = Instant::now();
+        let mut last = TransferStats::default();
+        let mut on_progress = |stats: &TransferStats| {
+            last = stats.clone();
+            self.reporter.report(phase, stats, start, false);
+        };
+        if matches!(
+            phase,
+            Phase::TransferFiles | Phase::SyncContent | Phase::ImportDatabase
+        ) {
+            self.begin_maintenance().await?;
+        }
+        match phase {
+            Phase::Connect => {
+                // Open the database tunnel, if there is one, while nothing has changed yet.
+                self.direct_database(&self.remote()).await?;
+                // Both installs must answer wp-cli before anything destructive
+                // happens, unless the server's database does without it.
+                for side in [&source, &dest] {
+                    if self.has_wp_cli(side).await? {
+                        side.wp(&["core", "version"]).await?;
+                    }
+                }
+                if self.syncs_database() || self.profile.multisite.subsite.is_some() {
+                    self.detect_network().await?;
+                }
+                if self.syncs_content() {
+                    self.check_content_api().await?;
+                }
+                Ok(())
+            }
+            Phase::DumpDatabase => {
+                let selection = self.table_selection(&source).await?;
+                let (dump, compression) = (self.dump_path(), self.profile.compression);
+                if let Some(warning) = self.newer_orders_warning().await? {
+                    self.warn(warning);
+                }
+                let filters = self.row_filters(&source).await?;
+                match self.direct_database(&source).await? {
+                    Some(mysql) => {
+                        mysql
+                            .export(&selection, &filters, &dump, &mut on_progress)
+                            .await
+                    }
+                    None => {
+                        db::export(
+                            &source,
+                            &selection,
+                            &filters,
+                            &dump,
+                            compression,
+                            &mut on_progress,
+                        )
+                        .await
+                    }
+                }
+            }
+            Phase::Anonymize => {
+                // The dump's tables, renamed to the destination's prefix if they were.
+                let prefix = match self.prefix_remap().await? {
+                    Some(remap) => remap.to,
+                    None => self.table_prefix(&source).await?,
+                };
+                let anonymizer = Anonymizer::new(&self.profile.anonymize, &prefix);
+                let mut stats = TransferStats::default();
+                search_replace::anonymize_dump(&self.dump_path(), &anonymizer, |bytes| {
+                    stats.bytes_transferred = bytes;
+                    on_progress(&stats);
+                })
+                .await
+            }
+            Phase::Backup => self.back_up().await,
+            Phase::TransferFiles => {
+                let transferred = self.transfer_files(&mut on_progress).await;
+                self.save_file_manifest();
+                transferred
+            }
+            Phase::SyncContent => self.sync_content(&mut on_progress).await,
+            Phase::ImportDatabase => {
+                let (dump, snapshot) = (self.dump_path(), self.users_snapshot_path());
+                let compression = self.profile.compression;
+                let preserve_users = self.profile.preserve_users;
+                match self.direct_database(&dest).await? {
+                    _ if self.profile.import_workers > 1 => {
+                        self.import_tables(&dest, &dump, &snapshot, &mut on_progress)
+                            .await
+                    }
+                    Some(mysql) if preserve_users => {
+                        mysql.import_preserving_users(&dump, &snapshot).await
+                    }
+                    Some(mysql) => mysql.import(&dump).await,
+                    None if preserve_users => {
+                        db::import_preserving_users(
+                            &dest,
+                            &dump,
+                            &snapshot,
+                            compression,
+                            &mut on_progress,
+                        )
+                        .await
+                    }
+                    None => db::import(&dest, &dump, compression, &mut on_progress).await,
+                }
+            }
+            Phase::SearchReplace => {
+                let prefix = self.prefix_remap().await?;
+                let collations = self.collations().await?;
+                for warning in collations.warnings {
+                    match self.profile.warnings.charset_mismatch {
+                        WarningAction::Ignore => {
+                            tracing::info!(%warning, "warning ignored by the profile's policy")
+                        }
+                        // A policy that blocks on them was checked before anything changed.
+                        WarningAction::Warn | WarningAction::Block => self.warn(warning),
+                    }
+                }
+                for remap in &collations.renames {
+                    tracing::info!(from = %remap.from, to = %remap.to, "renaming collation");
+                }
+                let (dump, pairs) = (self.dump_path(), self.replacements());
+                db::search_replace(
+                    &dump,
+                    &pairs,
+                    prefix.as_ref(),
+                    &collations.renames,
+                    &mut on_progress,
+                )
+                .await?;
+                self.warn_mixed_content(&dump).await
+            }
+            Phase::Finalize => {
+                self.remove_dumps(false).await?;
+                // Pruning can wait for the next sync or the periodic round if it fails.
+                if let Err(e) = self.prune_backups().await {
+                    self.warn(Message::new(
+                        "warning.old_restore_points_were_not_pruned",
+                        &[("error", &e)],
+                    ));
+                }
+                self.record_fingerprint().await;
+                self.end_maintenance().await
+            }
+            Phase::PostSync => {
+                // The CDN is purged even when there's no shell there for the rest.
+                if dest.has_shell() {
+                    self.post_sync(&dest).await;
+                }
+                self.purge_cdn().await;
+                Ok(())
+            }
+        }?;
+        Ok(last)
+    }
+
+    /// Mirror the files across, then, in integrity mode, check each copy
+    /// against its source. A two-way uploads sync copies both ways instead,
+    /// and an incremental one just the files modified since its cutoff.
+    async fn transfer_files(&self, on_progress: OnProgress<'_>) -> Result<(), String> {
+        if self.two_way() {
+            return self.sync_both_ways(on_progress).await;
+        }
+        if let Some(paths) = self.changed_uploads().await? {
+            if paths.is_empty() {
+                return Ok(());
+            }
+            return self.copy_only(self.direction, &paths, on_progress).await;
+        }
+        let source_root = self.files_root(&self.source());
+        let dest_root = self.files_root(&self.destination());
+        let excludes = self.excludes().await?;
+        let checksums = (self.profile.checksums && self.remote.is_some())
+            .then(|| Checksums::new(self.source(), self.destination()));
+        let options = MirrorOptions {
+            throttle: &self.throttle,
+            checksums: checksums.as_ref(),
+        };
+        let mirrored = self
+            .transfer
+            .mirror(
+                self.direction,
+                &source_root,
+                &dest_root,
+                &excludes,
+                &options,
+                on_progress,
+            )
+            .await?;
+        self.record_file_changes(self.direction, &mirrored.changes);
+        if self.direction == Direction::Push {
+            self.apply_permissions(&mirrored.copied).await;
+        }
+        if !mirrored.skipped_names.is_empty() {
+            let names = &mirrored.skipped_names;
+            let shown: Vec<String> = names
+                .iter()
+                .take(10)
+                .map(|name| byte_path::display(name).into_owned())
+                .collect();
+            let more = names.len() - shown.len();
+            self.warn(Message::new(
+                "warning.count_files_werent_copied_their_names",
+                &[
+                    ("count", &names.len()),
+                    ("shown", &shown.join(", ")),
+                    (
+                        "value2",
+                        &if more > 0 {
+                            format!(" and {more} more")
+                        } else {
+                            String::new()
+                        },
+                    ),
+                ],
+            ));
+        }
+        if !mirrored.skipped_links.is_empty() {
+            let links = &mirrored.skipped_links;
+            let shown: Vec<&str> = links.iter().take(10).map(String::as_str).collect();
+            let more = links.len() - shown.len();
+            self.warn(Message::new(
+                "warning.count_symlinks_werent_copied_shown_value2",
+                &[
+                    ("count", &links.len()),
+                    ("shown", &shown.join(", ")),
+                    (
+                        "value2",
+                        &if more > 0 {
+                            format!(" and {more} more")
+                        } else {
+                            String::new()
+                        },
+                    ),
+                ],
+            ));
+        }
+        let Some(checksums) = &checksums else {
+            return Ok(());
+        };
+        let mismatched = checksums
+            .differing(&source_root, &dest_root, &mirrored.copied)
+            .await?;
+        if !mismatched.is_empty() {
+            let shown: Vec<&str> = mismatched.iter().take(10).map(String::as_str).collect();
+            let more = mismatched.len() - shown.len();
+            self.warn(Message::new(
+                "warning.count_copied_files_dont_match_their",
+                &[
+                    ("count", &mismatched.len()),
+                    ("shown", &shown.join(", ")),
+                    (
+                        "value2",
+                        &if more > 0 {
+                            format!(" and {more} more")
+                        } else {
+                            String::new()
+                        },
+                    ),
+                ],
+            ));
+            self.mismatches.lock().unwrap().extend(mismatched);
+        }
+        Ok(())
+    }
+
+    /// Put the destination's store in test mode, switch its plugins on or off,
+    /// keep it out of search engines if it isn't production, and set its
+    /// options, then flush the caches the profile asks for there and rebuild
+    /// its search index. A failed flush leaves a working (if briefly stale)
+    /// site, so it is only a warning.
+    async fn post_sync(&self, dest: &Endpoint<'_>) {
+        // The pushed plugins' packages, before the site runs anything.
+        self.composer_install(dest).await;
+        self.rotate_salts(dest).await;
+        self.enforce_test_mode(dest).await;
+        self.switch_plugins(dest).await;
+        self.hide_from_search_engines(dest).await;
+        self.override_options(dest).await;
+        let settings = &self.profile.post_sync;
+        let wp_commands: [(bool, &[&str]); 3] = [
+            (settings.flush_cache, &["cache", "flush"]),
+            (
+                settings.delete_transients,
+                &["transient", "delete", "--all"],
+            ),
+            (settings.flush_rewrites, &["rewrite", "flush"]),
+        ];
+        let wp_cli = self.has_wp_cli(dest).await.unwrap_or(true);
+        if !wp_cli && wp_commands.iter().any(|(enabled, _)| *enabled) {
+            self.warn(
+                "The destination's caches, transients, and rewrite rules were not flushed: \
+                 the server has no wp-cli"
+                    .to_string(),
+            );
+        }
+        for (enabled, args) in wp_commands {
+            if !enabled || !wp_cli {
+                continue;
+            }
+            if let Err(e) = dest.wp(args).await {
+                self.warn(Message::new(
+                    "warning.wp_args_failed_destination",
+                    &[("args", &args.join(" ")), ("error", &e)],
+                ));
+            }
+        }
+        for command in &settings.object_cache_commands {
+            let flushed = match self.direction {
+                Direction::Push => match self.vetted(command) {
+                    Ok(()) => {
+                        let line = endpoint::remote_sh_line(&self.profile.remote, command, &[]);
+                        self.run_remote(SudoStep::ObjectCache, &line, &[]).await
+                    }
+                    Err(e) => Err(e),
+                },
+                Direction::Pull => dest.sh(command, &[]).await,
+            };
+            if let Err(e) = flushed {
+                self.warn(Message::new(
+                    "warning.object_cache_was_not_flushed",
+                    &[("error", &e)],
+                ));
+            }
+        }
+        self.flush_redis(dest).await;
+        self.reindex_search(dest).await;
+    }
+
+    /// Remove the staged database dump, unless `keep_dump`, and any user table
+    /// snapshot.
+    async fn remove_dumps(&self, keep_dump: bool) -> Result<(), String> {
+        let mut paths = vec![self.users_snapshot_path()];
+        if !keep_dump {
+            paths.push(self.dump_path());
+        }
+        for path in paths {
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(messages::text(
+                        "error.failed.remove_path",
+                        &[("path", &path.display()), ("error", &e)],
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+//! Multisite networks.
+//!
+//! A network syncs like a single site, with every site's tables and uploads,
+//! plus one more replacement: the source's host name becomes the
+//! destination's, which moves subdomain sites and the `blogs`/`site` domain
+//! mapping tables along with the main URL. A profile can instead pick one
+//! subsite, in which case only that site's tables and uploads move and the
+//! network's shared tables, users included, are left alone.
+
+use url::Url;
+
+use super::endpoint::Endpoint;
+use super::{db, Components, Job};
+use crate::messages;
+use crate::profile::{Profile, SyncScope, TableSelection};
+
+/// The blog id of a network's main site.
+const MAIN_SITE: u64 = 1;
+
+/// The directory a sync of `components` mirrors, relative to the WordPress
+/// root, or `None` for the root itself.
+pub(super) fn files_dir(profile: &Profile, components: Components) -> Option<String> {
+    let uploads = profile.project.uploads_dir();
+    match (profile.multisite.subsite, components) {
+        (Some(id), _) if id != MAIN_SITE => Some(format!("{uploads}/sites/{id}")),
+        (Some(_), _) | (None, Components::UploadsOnly) => Some(uploads.to_string()),
+        // A Composer project's content goes with its `composer.json`, from the root.
+        (None, _) => (profile.scope == SyncScope::WpContent && !profile.project.uses_composer())
+            .then(|| profile.project.content_dir().to_string()),
+    }
+}
+
+/// The uploads directory of the site a sync moves, relative to the WordPress
+/// root: a subsite's own, or the main site's.
+pub(super) fn uploads_dir(profile: &Profile) -> String {
+    let uploads = profile.project.uploads_dir();
+    match profile.multisite.subsite {
+        Some(id) if id != MAIN_SITE => format!("{uploads}/sites/{id}"),
+        _ => uploads.to_string(),
+    }
+}
+
+/// Exclude rules, relative to [`files_dir`], that keep a main-site sync out
+/// of the other subsites' uploads.
+pub(super) fn subsite_excludes(profile: &Profile) -> Option<&'static str> {
+    (profile.multisite.subsite == Some(MAIN_SITE)).then_some("/sites/")
+}
+
+impl Job<'_> {
+    /// Find out whether the installs are a multisite network, failing if only
+    /// one of them is, or if the profile picks a subsite of a single site.
+    /// Sides without a shell or wp-cli can't be asked and are taken to match.
+    pub(super) async fn detect_network(&self) -> Result<bool, String> {
+        if let Some(&network) = self.network.get() {
+            return Ok(network);
+        }
+        let (source, dest) = (self.source(), self.destination());
+        let mut found = None;
+        for side in [&source, &dest] {
+            if !self.has_wp_cli(side).await? {
+                continue;
+            }
+            let network = db::is_multisite(side).await?;
+            match found {
+                Some(other) if other != network => {
+                    let describe = |network| {
+                        if network {
+                            "a multisite network"
+                        } else {
+                            "a single site"
+                        }
+                    };
+                    return Err(messages::text(
+                        "error.source_other_but_destination_network",
+                        &[("other", &describe(other)), ("network", &describe(network))],
+                    ));
+                }
+                _ => found = Some(network),
+            }
+        }
+        let network = found.unwrap_or(false);
+        if !network {
+            if let Some(id) = self.profile.multisite.subsite {
+                return Err(messages::text(
+                    "error.profile_syncs_subsite_id_but_installs",
+                    &[("id", &id)],
+                ));
+            }
+        }
+        if network && url_path(source.url()) != url_path(dest.url()) {
+            self.warn(
+                "The two site URLs have different paths; the network's site paths are not \
+                 rewritten to match"
+                    .to_string(),
+            );
+        }
+        Ok(*self.network.get_or_init(|| network))
+    }
+
+    /// Whether [`detect_network`](Self::detect_network) found a network.
+    pub(super) fn is_network(&self) -> bool {
+        self.network.get().copied().unwrap_or(false)
+    }
+
+    /// The source tables this job exports: the profile's selection, narrowed
+    /// to one subsite's tables when the profile picks one.
+    pub(super) async fn table_selection(
+        &self,
+        source: &Endpoint<'_>,
+    ) -> Result<TableSelection, String> {
+        let Some(id) = self.profile.multisite.subsite else {
+            return Ok(self.profile.table_selection());
+        };
+        let all = self.tables(source).await?;
+        let prefix = self.table_prefix(source).await?;
+        let selected = self.profile.table_selection().select(&all)?;
+        let include: Vec<String> = db::subsite_tables(&all, &prefix, id)
+            .into_iter()
+            .filter(|table| selected.contains(table))
+            .map(String::from)
+            .collect();
+        if include.is_empty() {
+            return Err(messages::text(
+                "error.source_has_no_tables_subsite_id",
+                &[("id", &id)],
+            ));
+        }
+        Ok(TableSelection {
+            include,
+            exclude: Vec::new(),
+        })
+    }
+
+    /// The replacement that follows the site URL's for a network: its host
+    /// name, so subdomain sites and domain mappings move with it.
+    pub(super) fn network_replacement(&self) -> Option<(String, String)> {
+        if !self.is_network() {
+            return None;
+        }
+        let host = |url: &str| Some(Url::parse(url).ok()?.host_str()?.to_string());
+        Some((host(self.source_url())?, host(self.destination().url())?))
+    }
+}
+
+/// The path of a site URL, without a trailing `/`.
+fn url_path(url: &str) -> String {
+    Url::parse(url)
+        .map(|url| url.path().trim_end_matches('/').to_string())
+        .unwrap_or_default()
+}
@@ -0,0 +1,669 @@
+//! The server's database reached directly through a port forward over the
+//! job's SSH connection, instead of with wp-cli on the server: for profiles
+//! whose [`DatabaseAccess`](crate::profile::DatabaseAccess) is a tunnel, and
+//! for servers without wp-cli or mysqldump.
+//!
+//! The client is `mysqldump` and `mysql` on this machine when they are
+//! installed, and otherwise the built-in one in [`native`]. Either way the
+//! dumps are the plain SQL a wp-cli export makes, so rewriting and importing
+//! them doesn't care which way they came.
+
+mod native;
+mod script;
+
+use std::path::Path;
+
+use tokio::process::Command;
+
+use super::at_rest;
+use super::db::{self, RowFilter};
+use super::endpoint::Endpoint;
+use super::environment::read_wp_config;
+use super::local;
+use super::preflight;
+use super::remote::Remote;
+use super::transfer::{OnProgress, TransferStats};
+use super::Job;
+use crate::credentials::{self, DATABASE_PASSWORD_SERVICE};
+use crate::messages;
+use crate::profile::{wp_config, DatabaseSettings, Profile, TableSelection};
+use crate::ssh::Tunnel;
+
+/// What talks to the database at the far end of the tunnel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Client {
+    /// `mysqldump` and `mysql` on this machine.
+    Programs,
+    /// The built-in client, for when they aren't installed.
+    Native,
+}
+
+/// A connection to the server's database through a tunnel, which stays open
+/// for as long as this is kept.
+pub(super) struct Mysql {
+    tunnel: Tunnel,
+    client: Client,
+    /// Whether the server has no wp-cli either, so everything else the job
+    /// would ask it about the database is asked here instead.
+    replaces_wp_cli: bool,
+    user: String,
+    password: String,
+    database: String,
+    table_prefix: String,
+}
+
+impl Mysql {
+    /// Open a tunnel over `remote` to `profile`'s database, for `client`.
+    ///
+    /// The profile's database settings are used if it has them, and otherwise
+    /// those in the server's `wp-config.php`, whose password is also the
+    /// fallback for a stored one.
+    pub(super) async fn open(
+        profile: &Profile,
+        remote: &Remote,
+        client: Client,
+        replaces_wp_cli: bool,
+    ) -> Result<Self, String> {
+        let (settings, config) = match &profile.remote.database {
+            Some(settings) => (settings.clone(), None),
+            None => {
+                let source = read_wp_config(remote, &profile.remote.path).await?;
+                let settings = wp_config::parse(&source)
+                    .map_err(|e| messages::text("error.servers_wp_config_php", &[("error", &e)]))?;
+                (settings, Some(source))
+            }
+        };
+        let reference = profile.secrets.database_password.as_deref();
+        let password =
+            match credentials::resolve(reference, DATABASE_PASSWORD_SERVICE, &profile.id)? {
+                Some(password) => password,
+                None => config
+                    .as_deref()
+                    .and_then(wp_config::password)
+                    .ok_or_else(|| {
+                        messages::text(
+                            "error.no_database_password_stored_name",
+                            &[("name", &profile.name)],
+                        )
+                    })?,
+            };
+        let (host, port) = settings.tcp_address()?;
+        let tunnel = remote.forward(&host, port).await?;
+        tracing::info!(
+            %host,
+            port,
+            local = %tunnel.local_addr(),
+            ?client,
+            "database tunnel open"
+        );
+        let DatabaseSettings {
+            name,
+            user,
+            table_prefix,
+            ..
+        } = settings;
+        Ok(Self {
+            tunnel,
+            client,
+            replaces_wp_cli,
+            user,
+            password,
+            database: name,
+            table_prefix,
+        })
+    }
+
+    /// Whether the job should ask this, rather than wp-cli on the server,
+    /// everything it wants to know about the database.
+    pub(super) fn replaces_wp_cli(&self) -> bool {
+        self.replaces_wp_cli
+    }
+
+    /// A client `program` connected to the database.
+    fn command(&self, program: &str) -> Command {
+        let addr = self.tunnel.local_addr();
+        let mut cmd = local::command(program);
+        // The password goes in the environment, where other users' `ps` can't see it.
+        cmd.env("MYSQL_PWD", &self.password).args([
+            "--protocol=TCP".to_string(),
+            format!("--host={}", addr.ip()),
+            format!("--port={}", addr.port()),
+            format!("--user={}", self.user),
+        ]);
+        cmd
+    }
+
+    /// The built-in client's connection settings.
+    fn native(&self) -> native::Native {
+        native::Native::new(
+            self.tunnel.local_addr(),
+            &self.user,
+            &self.password,
+            &self.database,
+        )
+    }
+
+    /// The rows `sql` returns, each a list of its values as text, with NULLs
+    /// as empty strings.
+    pub(super) async fn query(&self, sql: &str) -> Result<Vec<Vec<String>>, String> {
+        match self.client {
+            Client::Programs => {
+                let mut cmd = self.command("mysql");
+                cmd.args(["--batch", "--skip-column-names"])
+                    .arg(format!("--execute={sql}"))
+                    .arg(&self.database);
+                let output = local::output(cmd, "mysql").await?;
+                Ok(output
+                    .lines()
+                    .filter(|line| !line.is_empty())
+                    .map(|line| line.split('\t').map(unescape_batch).collect())
+                    .collect())
+            }
+            Client::Native => self.native().query(sql).await,
+        }
+    }
+
+    /// The column names and first `limit` rows `sql` returns, as the SQL
+    /// console shows them. With `read_only` the session can't change anything
+    /// in the database, whatever `sql` is.
+    pub(super) async fn query_table(
+        &self,
+        sql: &str,
+        read_only: bool,
+        limit: usize,
+    ) -> Result<(Vec<String>, Vec<Vec<String>>), String> {
+        match self.client {
+            Client::Programs => {
+                let sql = match read_only {
+                    true => format!("SET SESSION TRANSACTION READ ONLY; {sql}"),
+                    false => sql.to_string(),
+                };
+                let mut cmd = self.command("mysql");
+                cmd.arg("--batch")
+                    .arg(format!("--execute={sql}"))
+                    .arg(&self.database);
+                let mut table = BatchTable::new(limit);
+                local::stream_stdout(cmd, "mysql", |data| table.push(data)).await?;
+                Ok(table.finish())
+            }
+            Client::Native => self.native().query_table(sql, read_only, limit).await,
+        }
+    }
+
+    /// Every table and view in the database.
+    pub(super) async fn tables(&self) -> Result<Vec<String>, String> {
+        let rows = self.query("SHOW TABLES").await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| row.into_iter().next())
+            .filter(|table| !table.is_empty())
+            .collect())
+    }
+
+    /// The database's table prefix, from the profile or `wp-config.php`.
+    pub(super) fn table_prefix(&self) -> &str {
+        &self.table_prefix
+    }
+
+    /// The install's `users` and `usermeta` tables, those of them that exist.
+    pub(super) async fn user_tables(&self) -> Result<Vec<String>, String> {
+        let existing = self.tables().await?;
+        Ok(["users", "usermeta"]
+            .into_iter()
+            .map(|name| format!("{}{name}", self.table_prefix))
+            .filter(|table| existing.contains(table))
+            .collect())
+    }
+
+    /// The size of each table, data and indexes, as `information_schema`
+    /// reckons it.
+    pub(super) async fn table_sizes(&self) -> Result<Vec<(String, u64)>, String> {
+        let rows = self
+            .query(
+                "SELECT table_name, COALESCE(data_length + index_length, 0) \
+                 FROM information_schema.TABLES WHERE table_schema = DATABASE()",
+            )
+            .await?;
+        rows.into_iter()
+            .map(|row| match row.as_slice() {
+                [table, size] => Ok((table.clone(), size.parse().unwrap_or(0))),
+                _ => Err(messages::text(
+                    "error.unexpected_table_sizes_database_rows",
+                    &[("rows", &format_args!("{row:?}"))],
+                )),
+            })
+            .collect()
+    }
+
+    /// How many text values contain `from`, in `tables` or every table with
+    /// the install's prefix, leaving out the columns in `skip_columns`. This
+    /// stands in for `wp search-replace --dry-run`, counting values rather
+    /// than matches within them.
+    pub(super) async fn count_replacements(
+        &self,
+        from: &str,
+        tables: Option<&[String]>,
+        skip_columns: &[&str],
+    ) -> Result<u64, String> {
+        let mut in_scope = match tables {
+            Some(tables) => tables.to_vec(),
+            None => self
+                .tables()
+                .await?
+                .into_iter()
+                .filter(|table| table.starts_with(&self.table_prefix))
+                .collect(),
+        };
+        in_scope.sort();
+        let columns = self
+            .query(
+                "SELECT table_name, column_name FROM information_schema.COLUMNS \
+                 WHERE table_schema = DATABASE() AND data_type IN \
+                 ('char', 'varchar', 'tinytext', 'text', 'mediumtext', 'longtext')",
+            )
+            .await?;
+        let pattern = sql_string(&format!("%{}%", like_escape(from)));
+        let mut count = 0;
+        for table in &in_scope {
+            let matches: Vec<String> = columns
+                .iter()
+                .filter_map(|row| match row.as_slice() {
+                    [t, column] if t == table && !skip_columns.contains(&column.as_str()) => {
+                        Some(format!("({} LIKE BINARY {pattern})", identifier(column)))
+                    }
+                    _ => None,
+                })
+                .collect();
+            if matches.is_empty() {
+                continue;
+            }
+            let sql = format!(
+                "SELECT COALESCE(SUM({}), 0) FROM {}",
+                matches.join(" + "),
+                identifier(table)
+            );
+            let rows = self.query(&sql).await?;
+            let value = rows.first().and_then(|row| row.first());
+            count += value.and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+        }
+        Ok(count)
+    }
+
+    /// Dump the tables `selection` picks to `dump_path` on this machine.
+    pub(super) async fn export(
+        &self,
+        selection: &TableSelection,
+        filters: &[RowFilter],
+        dump_path: &Path,
+        on_progress: OnProgress<'_>,
+    ) -> Result<(), String> {
+        let selected = if selection.is_all() {
+            None
+        } else {
+            let all = self.tables().await?;
+            let selected: Vec<String> = selection
+                .select(&all)?
+                .into_iter()
+                .map(String::from)
+                .collect();
+            if selected.is_empty() {
+                return Err(messages::text(
+                    "error.profiles_table_rules_dont_select_any",
+                    &[],
+                ));
+            }
+            Some(selected)
+        };
+        let mut file = at_rest::create(dump_path).await?;
+        let mut stats = TransferStats::default();
+        let mut on_bytes = |bytes| {
+            stats.bytes_transferred = bytes;
+            on_progress(&stats);
+        };
+        let filters: Vec<&RowFilter> = filters
+            .iter()
+            .filter(|filter| {
+                selected
+                    .as_ref()
+                    .is_none_or(|tables| tables.contains(&filter.table))
+            })
+            .collect();
+        match self.client {
+            Client::Programs => {
+                let mut cmd = self.command("mysqldump");
+                cmd.args(["--single-transaction", "--add-drop-table"]);
+                for filter in &filters {
+                    cmd.arg(format!("--ignore-table={}.{}", self.database, filter.table));
+                }
+                cmd.arg(&self.database).args(
+                    selected
+                        .iter()
+                        .flatten()
+                        .filter(|table| !filters.iter().any(|filter| &&filter.table == table)),
+                );
+                let mut written = 0;
+                let all_filtered = selected
+                    .as_ref()
+                    .is_some_and(|tables| tables.len() == filters.len());
+                if !all_filtered {
+                    local::copy_stdout(cmd, "mysqldump", &mut file, |bytes| {
+                        written = bytes;
+                        on_bytes(bytes);
+                    })
+                    .await?;
+                }
+                // Filtered tables after the rest, each with its own condition.
+                for filter in &filters {
+                    let mut cmd = self.command("mysqldump");
+                    cmd.args(["--single-transaction", "--add-drop-table"])
+                        .arg(format!("--where={}", filter.condition))
+                        .args([&self.database, &filter.table]);
+                    let before = written;
+                    local::copy_stdout(cmd, "mysqldump", &mut file, |bytes| {
+                        written = before + bytes;
+                        on_bytes(written);
+                    })
+                    .await?;
+                }
+            }
+            Client::Native => {
+                self.native()
+                    .export(selected.as_deref(), &filters, &mut file, &mut on_bytes)
+                    .await?
+            }
+        }
+        file.finish().await
+    }
+
+    /// Run the SQL dump at `dump_path` on this machine against the database.
+    pub(super) async fn import(&self, dump_path: &Path) -> Result<(), String> {
+        match self.client {
+            Client::Programs => {
+                let mut cmd = self.command("mysql");
+                cmd.arg(&self.database);
+                local::feed_stdin(cmd, "mysql", dump_path).await
+            }
+            Client::Native => self.native().import(dump_path).await,
+        }
+    }
+
+    /// [`import`](Self::import), keeping the database's user tables as they
+    /// were, by way of a snapshot saved to `snapshot_path` first.
+    pub(super) async fn import_preserving_users(
+        &self,
+        dump_path: &Path,
+        snapshot_path: &Path,
+    ) -> Result<(), String> {
+        let users = self.user_tables().await?;
+        if users.is_empty() {
+            return self.import(dump_path).await;
+        }
+        let selection = TableSelection {
+            include: users,
+            exclude: Vec::new(),
+        };
+        self.export(&selection, &[], snapshot_path, &mut |_| {})
+            .await?;
+        let imported = self.import(dump_path).await;
+        // Restore even after a failed import, which may already have dropped them.
+        let restored = self.import(snapshot_path).await;
+        imported.and(restored)
+    }
+}
+
+/// The column names and rows of `mysql --batch` output with its header line,
+/// read as it arrives.
+pub(super) struct BatchTable {
+    /// The line being read, up to the end of the last chunk.
+    pending: Vec<u8>,
+    columns: Option<Vec<String>>,
+    rows: Vec<Vec<String>>,
+    limit: usize,
+}
+
+impl BatchTable {
+    /// One that keeps the first `limit` rows and reads past the rest.
+    pub(super) fn new(limit: usize) -> Self {
+        Self {
+            pending: Vec::new(),
+            columns: None,
+            rows: Vec::new(),
+            limit,
+        }
+    }
+
+    /// Read the next chunk of the output.
+    pub(super) fn push(&mut self, data: &[u8]) {
+        for &b in data {
+            if b == b'\n' {
+                let line = std::mem::take(&mut self.pending);
+                self.line(&line);
+            } else if self.rows.len() < self.limit {
+                self.pending.push(b);
+            }
+        }
+    }
+
+    /// The column names and first rows of the output read.
+    pub(super) fn finish(mut self) -> (Vec<String>, Vec<Vec<String>>) {
+        let line = std::mem::take(&mut self.pending);
+        self.line(&line);
+        (self.columns.unwrap_or_default(), self.rows)
+    }
+
+    fn line(&mut self, line: &[u8]) {
+        let line = String::from_utf8_lossy(line);
+        let line = line.strip_suffix('\r').unwrap_or(&line);
+        if line.is_empty() || self.rows.len() >= self.limit {
+            return;
+        }
+        let values = line.split('\t').map(unescape_batch).collect();
+        match self.columns {
+            None => self.columns = Some(values),
+            Some(_) => self.rows.push(values),
+        }
+    }
+}
+
+/// A value from `mysql --batch`, which escapes tabs, newlines, and backslashes.
+fn unescape_batch(value: &str) -> String {
+    if value == "NULL" {
+        return String::new();
+    }
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => out.push('\n'),
+                Some('t') => out.push('\t'),
+                Some('0') => out.push('\0'),
+                Some(other) => out.push(other),
+                None => out.push('\\'),
+            },
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// `name` quoted as a MySQL identifier.
+pub(super) fn identifier(name: &str) -> String {
+    format!("`{}`", name.replace('`', "``"))
+}
+
+/// `value` as a MySQL string literal.
+fn sql_string(value: &str) -> String {
+    let mut out = Vec::with_capacity(value.len() + 2);
+    out.push(b'\'');
+    crate::search_replace::escape_into(&mut out, value.as_bytes());
+    out.push(b'\'');
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// `value` with the `LIKE` wildcards in it matching only themselves.
+fn like_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+impl Job<'_> {
+    /// The direct connection to `side`'s database, if the job syncs the
+    /// database, `side` is the server, and the preflight check chose one: the
+    /// profile reaches its database through a tunnel, or the server lacks
+    /// wp-cli or mysqldump. It is opened the first time it's asked for and
+    /// closes with the job.
+    pub(super) async fn direct_database(
+        &self,
+        side: &Endpoint<'_>,
+    ) -> Result<Option<&Mysql>, String> {
+        let Endpoint::Remote {
+            remote: Some(remote),
+            ..
+        } = side
+        else {
+            return Ok(None);
+        };
+        if !self.syncs_database() {
+            return Ok(None);
+        }
+        let mysql = self
+            .mysql
+            .get_or_try_init(|| async {
+                let Some(route) = preflight::database_route(self.profile, remote).await? else {
+                    return Ok::<_, String>(None);
+                };
+                let mysql = Mysql::open(self.profile, remote, route.client, !route.wp_cli).await?;
+                Ok(Some(mysql))
+            })
+            .await?;
+        Ok(mysql.as_ref())
+    }
+
+    /// The direct connection to `side`'s database, if the server has no
+    /// wp-cli to ask about it.
+    pub(super) async fn database_without_wp_cli(
+        &self,
+        side: &Endpoint<'_>,
+    ) -> Result<Option<&Mysql>, String> {
+        let mysql = self.direct_database(side).await?;
+        Ok(mysql.filter(|mysql| mysql.replaces_wp_cli()))
+    }
+
+    /// Whether `side` has wp-cli to ask: it has a shell, and isn't a server
+    /// whose database is reached directly for want of it.
+    pub(super) async fn has_wp_cli(&self, side: &Endpoint<'_>) -> Result<bool, String> {
+        Ok(side.has_shell() && self.database_without_wp_cli(side).await?.is_none())
+    }
+
+    /// Every table in `side`'s database.
+    pub(super) async fn tables(&self, side: &Endpoint<'_>) -> Result<Vec<String>, String> {
+        match self.database_without_wp_cli(side).await? {
+            Some(mysql) => mysql.tables().await,
+            None => db::tables(side).await,
+        }
+    }
+
+    /// `side`'s table prefix.
+    pub(super) async fn table_prefix(&self, side: &Endpoint<'_>) -> Result<String, String> {
+        match self.database_without_wp_cli(side).await? {
+            Some(mysql) => Ok(mysql.table_prefix().to_string()),
+            None => db::table_prefix(side).await,
+        }
+    }
+
+    /// `side`'s `users` and `usermeta` tables, those of them that exist.
+    pub(super) async fn user_tables(&self, side: &Endpoint<'_>) -> Result<Vec<String>, String> {
+        match self.database_without_wp_cli(side).await? {
+            Some(mysql) => mysql.user_tables().await,
+            None => db::user_tables(side).await,
+        }
+    }
+
+    /// The size of each table in `side`'s database.
+    pub(super) async fn table_sizes(
+        &self,
+        side: &Endpoint<'_>,
+    ) -> Result<Vec<(String, u64)>, String> {
+        match self.database_without_wp_cli(side).await? {
+            Some(mysql) => mysql.table_sizes().await,
+            None => db::table_sizes(side).await,
+        }
+    }
+
+    /// The rows an SQL `query` returns from `side`'s database.
+    pub(super) async fn query(
+        &self,
+        side: &Endpoint<'_>,
+        sql: &str,
+    ) -> Result<Vec<Vec<String>>, String> {
+        match self.database_without_wp_cli(side).await? {
+            Some(mysql) => mysql.query(sql).await,
+            None => db::query(side, sql).await,
+        }
+    }
+
+    /// How many values in `source`'s database replacing `from` with `to`
+    /// will change, as [`db::count_replacements`] counts them.
+    pub(super) async fn count_replacements(
+        &self,
+        source: &Endpoint<'_>,
+        from: &str,
+        to: &str,
+        tables: Option<&[String]>,
+    ) -> Result<u64, String> {
+        match self.database_without_wp_cli(source).await? {
+            Some(_) if from == to => Ok(0),
+            Some(mysql) => {
+                mysql
+                    .count_replacements(from, tables, db::SKIP_COLUMNS)
+                    .await
+            }
+            None => db::count_replacements(source, from, to, tables).await,
+        }
+    }
+}
+
+/// A direct connection to `profile`'s database over `remote`, outside a job,
+/// if the server has no wp-cli to reach it with.
+pub(super) async fn open_without_wp_cli(
+    profile: &Profile,
+    remote: &Remote,
+) -> Result<Option<Mysql>, String> {
+    match preflight::database_route(profile, remote).await? {
+        Some(route) if !route.wp_cli => Mysql::open(profile, remote, route.client, true)
+            .await
+            .map(Some),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(chunks: &[&str], limit: usize) -> (Vec<String>, Vec<Vec<String>>) {
+        let mut table = BatchTable::new(limit);
+        for chunk in chunks {
+            table.push(chunk.as_bytes());
+        }
+        table.finish()
+    }
+
+    #[test]
+    fn batch_table() {
+        let (columns, rows) = read(&["id\tna", "me\r\n1\ta\\tb\n2\tNU", "LL"], 10);
+        assert_eq!(columns, ["id", "name"]);
+        assert_eq!(rows, [vec!["1", "a\tb"], vec!["2", ""]]);
+        assert_eq!(read(&[""], 10), (Vec::new(), Vec::new()));
+    }
+
+    #[test]
+    fn batch_table_limit() {
+        let (columns, rows) = read(&["n\n1\n2\n", "3\n4"], 2);
+        assert_eq!(columns, ["n"]);
+        assert_eq!(rows, [vec!["1"], vec!["2"]]);
+    }
+}
@@ -0,0 +1,396 @@
+//! The built-in MySQL client, for when neither the server nor this machine has
+//! the programs to dump and import with.
+//!
+//! Its dumps follow mysqldump's layout, which is what the rest of the sync
+//! reads: each table's `SHOW CREATE TABLE` and extended `INSERT`s one to a
+//! line, then the views, with their definers left out so they can be created
+//! by whoever imports them. Everything is read in one consistent snapshot, as
+//! utf8mb4 whatever the tables' own character sets, with binary values written
+//! as hex so no conversion can touch them.
+
+use std::net::SocketAddr;
+use std::path::Path;
+
+use mysql_async::consts::ColumnType;
+use mysql_async::prelude::Queryable;
+use mysql_async::{Column, Conn, OptsBuilder, Row, Value};
+use tokio::io::{AsyncWrite, AsyncWriteExt, BufReader};
+
+use super::super::at_rest;
+use super::super::db::RowFilter;
+use super::identifier;
+use super::script::Statements;
+use crate::messages;
+use crate::search_replace::escape_into;
+
+/// How long an extended `INSERT` grows before the next row starts another,
+/// like mysqldump's default `net_buffer_length`.
+const INSERT_SIZE: usize = 1024 * 1024;
+
+/// MySQL's number for the `binary` character set.
+const BINARY_CHARSET: u16 = 63;
+
+const HEADER: &str = "/*!40101 SET NAMES utf8mb4 */;\n\
+/*!40103 SET TIME_ZONE='+00:00' */;\n\
+/*!40014 SET FOREIGN_KEY_CHECKS=0 */;\n\
+/*!40101 SET SQL_MODE='NO_AUTO_VALUE_ON_ZERO' */;\n";
+
+const FOOTER: &str = "/*!40014 SET FOREIGN_KEY_CHECKS=1 */;\n";
+
+/// Where the built-in client connects, and as whom.
+pub(super) struct Native {
+    opts: OptsBuilder,
+}
+
+impl Native {
+    pub(super) fn new(addr: SocketAddr, user: &str, password: &str, database: &str) -> Self {
+        let opts = OptsBuilder::default()
+            .ip_or_hostname(addr.ip().to_string())
+            .tcp_port(addr.port())
+            .user(Some(user))
+            .pass(Some(password))
+            .db_name(Some(database))
+            // Its socket would be this machine's, not the server's.
+            .prefer_socket(false)
+            // Timestamps are dumped in UTC and read back the same way.
+            .init(vec!["SET NAMES utf8mb4", "SET time_zone = '+00:00'"]);
+        Self { opts }
+    }
+
+    async fn connect(&self) -> Result<Conn, String> {
+        Conn::new(self.opts.clone())
+            .await
+            .map_err(|e| messages::text("error.failed.connect_database", &[("error", &e)]))
+    }
+
+    /// The rows `sql` returns, as text.
+    pub(super) async fn query(&self, sql: &str) -> Result<Vec<Vec<String>>, String> {
+        let mut conn = self.connect().await?;
+        let rows: Vec<Row> = conn.query(sql).await.map_err(failed)?;
+        let _ = conn.disconnect().await;
+        Ok(rows
+            .into_iter()
+            .map(|row| row.unwrap().into_iter().map(text).collect())
+            .collect())
+    }
+
+    /// The column names and first `limit` rows `sql` returns, as text, in a
+    /// session that can't write with `read_only`.
+    pub(super) async fn query_table(
+        &self,
+        sql: &str,
+        read_only: bool,
+        limit: usize,
+    ) -> Result<(Vec<String>, Vec<Vec<String>>), String> {
+        let mut conn = self.connect().await?;
+        if read_only {
+            conn.query_drop("SET SESSION TRANSACTION READ ONLY")
+                .await
+                .map_err(failed)?;
+        }
+        let mut result = conn.query_iter(sql).await.map_err(failed)?;
+        let columns = result
+            .columns()
+            .map(|columns| columns.iter().map(|c| c.name_str().into_owned()).collect())
+            .unwrap_or_default();
+        let mut rows = Vec::new();
+        while rows.len() < limit {
+            let Some(row) = result.next().await.map_err(failed)? else {
+                break;
+            };
+            rows.push(row.unwrap().into_iter().map(text).collect());
+        }
+        // The rest are read past, not kept, as the connection closes.
+        drop(result);
+        let _ = conn.disconnect().await;
+        Ok((columns, rows))
+    }
+
+    /// Dump the tables and views named in `selected`, or all of them, into
+    /// `out`, passing `on_bytes` the bytes written so far.
+    pub(super) async fn export(
+        &self,
+        selected: Option<&[String]>,
+        filters: &[&RowFilter],
+        out: &mut (impl AsyncWrite + Unpin),
+        on_bytes: &mut impl FnMut(u64),
+    ) -> Result<(), String> {
+        let mut conn = self.connect().await?;
+        // One snapshot for every table, as mysqldump's --single-transaction takes.
+        conn.query_drop("SET SESSION TRANSACTION ISOLATION LEVEL REPEATABLE READ")
+            .await
+            .map_err(failed)?;
+        conn.query_drop("START TRANSACTION WITH CONSISTENT SNAPSHOT")
+            .await
+            .map_err(failed)?;
+        let listing: Vec<(String, String)> =
+            conn.query("SHOW FULL TABLES").await.map_err(failed)?;
+        let (views, tables): (Vec<_>, Vec<_>) = listing
+            .into_iter()
+            .filter(|(name, _)| selected.is_none_or(|selected| selected.contains(name)))
+            .partition(|(_, kind)| kind == "VIEW");
+
+        let mut dump = Dump {
+            out,
+            written: 0,
+            on_bytes,
+        };
+        dump.write(HEADER.as_bytes()).await?;
+        for (table, _) in &tables {
+            let condition = filters
+                .iter()
+                .find(|filter| &filter.table == table)
+                .map(|filter| filter.condition.as_str());
+            dump_table(&mut conn, table, condition, &mut dump).await?;
+        }
+        let mut definitions = Vec::new();
+        for (view, _) in &views {
+            let sql = format!("SHOW CREATE VIEW {}", identifier(view));
+            let row: Option<Row> = conn.query_first(sql).await.map_err(failed)?;
+            let create = row.and_then(|row| row.get::<String, _>(1)).ok_or_else(|| {
+                messages::text(
+                    "error.database_didnt_say_how_view_defined",
+                    &[("view", &view)],
+                )
+            })?;
+            definitions.push((view.clone(), without_definer(&create)));
+        }
+        for (view, create) in dependency_order(definitions) {
+            let view = identifier(&view);
+            let section = format!(
+                "\n--\n-- View structure for view {view}\n--\n\n\
+                 DROP VIEW IF EXISTS {view};\n{create};\n"
+            );
+            dump.write(section.as_bytes()).await?;
+        }
+        dump.write(FOOTER.as_bytes()).await?;
+        dump.out
+            .flush()
+            .await
+            .map_err(|e| messages::text("error.failed.save_database_dump", &[("error", &e)]))?;
+        let _ = conn.disconnect().await;
+        Ok(())
+    }
+
+    /// Run the statements of the SQL dump at `path` against the database.
+    pub(super) async fn import(&self, path: &Path) -> Result<(), String> {
+        let file = at_rest::open(path).await?;
+        let mut statements = Statements::new(BufReader::new(file));
+        let mut conn = self.connect().await?;
+        while let Some(statement) = statements.next().await? {
+            conn.query_drop(statement.as_slice()).await.map_err(|e| {
+                messages::text(
+                    "error.database_import_failed_line_number_dump",
+                    &[("line_number", &statements.line_number()), ("error", &e)],
+                )
+            })?;
+        }
+        let _ = conn.disconnect().await;
+        Ok(())
+    }
+}
+
+/// The dump being written, counting its bytes.
+struct Dump<'a, W, F> {
+    out: &'a mut W,
+    written: u64,
+    on_bytes: &'a mut F,
+}
+
+impl<W: AsyncWrite + Unpin, F: FnMut(u64)> Dump<'_, W, F> {
+    async fn write(&mut self, bytes: &[u8]) -> Result<(), String> {
+        self.out
+            .write_all(bytes)
+            .await
+            .map_err(|e| messages::text("error.failed.save_database_dump", &[("error", &e)]))?;
+        self.written += bytes.len() as u64;
+        (self.on_bytes)(self.written);
+        Ok(())
+    }
+}
+
+/// Write `table`'s definition and its rows, those matching the SQL
+/// `condition` if there is one.
+async fn dump_table<W: AsyncWrite + Unpin, F: FnMut(u64)>(
+    conn: &mut Conn,
+    table: &str,
+    condition: Option<&str>,
+    dump: &mut Dump<'_, W, F>,
+) -> Result<(), String> {
+    let quoted = identifier(table);
+    let row: Option<Row> = conn
+        .query_first(format!("SHOW CREATE TABLE {quoted}"))
+        .await
+        .map_err(failed)?;
+    let create = row.and_then(|row| row.get::<String, _>(1)).ok_or_else(|| {
+        messages::text(
+            "error.database_didnt_say_how_table_defined",
+            &[("table", &table)],
+        )
+    })?;
+    let section = format!(
+        "\n--\n-- Table structure for table {quoted}\n--\n\n\
+         DROP TABLE IF EXISTS {quoted};\n{create};\n\n\
+         --\n-- Dumping data for table {quoted}\n--\n\n\
+         /*!40000 ALTER TABLE {quoted} DISABLE KEYS */;\n"
+    );
+    dump.write(section.as_bytes()).await?;
+
+    // Generated columns are worked out again on import, and can't be inserted into.
+    let columns: Vec<(String, String)> = conn
+        .exec(
+            "SELECT column_name, extra FROM information_schema.COLUMNS \
+             WHERE table_schema = DATABASE() AND table_name = ? ORDER BY ordinal_position",
+            (table,),
+        )
+        .await
+        .map_err(failed)?;
+    let generated = |extra: &str| {
+        [
+            "VIRTUAL GENERATED",
+            "STORED GENERATED",
+            "PERSISTENT GENERATED",
+        ]
+        .iter()
+        .any(|kind| extra.contains(kind))
+    };
+    let (select, insert) = if columns.iter().any(|(_, extra)| generated(extra)) {
+        let list = columns
+            .iter()
+            .filter(|(_, extra)| !generated(extra))
+            .map(|(column, _)| identifier(column))
+            .collect::<Vec<_>>()
+            .join(",");
+        (
+            format!("SELECT {list} FROM {quoted}"),
+            format!("INSERT INTO {quoted} ({list}) VALUES "),
+        )
+    } else {
+        (
+            format!("SELECT * FROM {quoted}"),
+            format!("INSERT INTO {quoted} VALUES "),
+        )
+    };
+
+    let select = match condition {
+        Some(condition) => format!("{select} WHERE {condition}"),
+        None => select,
+    };
+    let mut result = conn.query_iter(select).await.map_err(failed)?;
+    let columns = result.columns().unwrap_or_else(|| Vec::new().into());
+    let mut line = Vec::new();
+    while let Some(row) = result.next().await.map_err(failed)? {
+        line.extend_from_slice(if line.is_empty() {
+            insert.as_bytes()
+        } else {
+            b","
+        });
+        line.push(b'(');
+        for (i, value) in row.unwrap().into_iter().enumerate() {
+            if i > 0 {
+                line.push(b',');
+            }
+            literal(&mut line, value, columns.get(i));
+        }
+        line.push(b')');
+        if line.len() >= INSERT_SIZE {
+            line.extend_from_slice(b";\n");
+            dump.write(&line).await?;
+            line.clear();
+        }
+    }
+    if !line.is_empty() {
+        line.extend_from_slice(b";\n");
+        dump.write(&line).await?;
+    }
+    let section = format!("/*!40000 ALTER TABLE {quoted} ENABLE KEYS */;\n");
+    dump.write(section.as_bytes()).await
+}
+
+/// Append `value`, from a column described by `column`, as an SQL literal.
+fn literal(out: &mut Vec<u8>, value: Value, column: Option<&Column>) {
+    let bytes = match value {
+        Value::NULL => return out.extend_from_slice(b"NULL"),
+        Value::Bytes(bytes) => bytes,
+        other => return out.extend_from_slice(other.as_sql(false).as_bytes()),
+    };
+    let kind = column.map(Column::column_type);
+    if kind.is_some_and(|kind| kind.is_numeric_type()) {
+        out.extend_from_slice(&bytes);
+    } else if column.is_some_and(is_binary) && !bytes.is_empty() {
+        out.extend_from_slice(b"0x");
+        for b in bytes {
+            out.extend_from_slice(format!("{b:02X}").as_bytes());
+        }
+    } else {
+        out.push(b'\'');
+        escape_into(out, &bytes);
+        out.push(b'\'');
+    }
+}
+
+/// Whether a column holds raw bytes rather than text. Dates and numbers have
+/// the binary character set too, but are sent as text.
+fn is_binary(column: &Column) -> bool {
+    use ColumnType::*;
+    match column.column_type() {
+        MYSQL_TYPE_BIT | MYSQL_TYPE_GEOMETRY => true,
+        MYSQL_TYPE_STRING
+        | MYSQL_TYPE_VAR_STRING
+        | MYSQL_TYPE_VARCHAR
+        | MYSQL_TYPE_BLOB
+        | MYSQL_TYPE_TINY_BLOB
+        | MYSQL_TYPE_MEDIUM_BLOB
+        | MYSQL_TYPE_LONG_BLOB => column.character_set() == BINARY_CHARSET,
+        _ => false,
+    }
+}
+
+/// A `CREATE VIEW` statement without its `DEFINER=user@host` clause.
+fn without_definer(create: &str) -> String {
+    let Some(start) = create.find(" DEFINER=") else {
+        return create.to_string();
+    };
+    // The definer is two quoted names joined by `@`, and ends at a space.
+    let rest = &create[start + " DEFINER=".len()..];
+    let mut quoted = false;
+    let end = rest
+        .char_indices()
+        .find(|&(_, c)| {
+            if c == '`' {
+                quoted = !quoted;
+            }
+            c == ' ' && !quoted
+        })
+        .map_or(rest.len(), |(i, _)| i);
+    format!("{}{}", &create[..start], &rest[end..])
+}
+
+/// `views` ordered so each comes after the views its definition names.
+fn dependency_order(mut views: Vec<(String, String)>) -> Vec<(String, String)> {
+    let mut ordered: Vec<(String, String)> = Vec::with_capacity(views.len());
+    while !views.is_empty() {
+        let ready = views.iter().position(|(view, create)| {
+            views
+                .iter()
+                .all(|(other, _)| other == view || !create.contains(&identifier(other)))
+        });
+        // A cycle can't be created anyway; keep the rest in their order.
+        let next = ready.unwrap_or(0);
+        ordered.push(views.remove(next));
+    }
+    ordered
+}
+
+/// A value as text, with NULL as an empty string.
+fn text(value: Value) -> String {
+    match value {
+        Value::NULL => String::new(),
+        Value::Bytes(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+        other => other.as_sql(false),
+    }
+}
+
+fn failed(e: mysql_async::Error) -> String {
+    messages::text("error.database_query_failed", &[("error", &e)])
+}
@@ -0,0 +1,145 @@
+//! Splitting an SQL dump into the statements the built-in client sends one at
+//! a time, as the `mysql` program does: on the delimiter, `;` unless a
+//! `DELIMITER` line (as mysqldump writes around triggers) changes it, and never
+//! inside a quoted string or a comment. Plain comments are dropped; `/*!…*/`
+//! ones are kept, since the server runs what they hold.
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+use crate::messages;
+
+/// Where the scan is.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Code,
+    /// Inside a string or identifier opened by this quote character.
+    Quote(u8),
+    /// Inside a plain `/* … */` comment.
+    Comment,
+}
+
+/// The statements of a dump, read from `reader` as they are asked for.
+pub(super) struct Statements<R> {
+    reader: R,
+    delimiter: Vec<u8>,
+    line: Vec<u8>,
+    /// How much of `line` has been scanned.
+    pos: usize,
+    /// How many lines have been read, for errors.
+    line_number: usize,
+}
+
+impl<R: AsyncBufRead + Unpin> Statements<R> {
+    pub(super) fn new(reader: R) -> Self {
+        Self {
+            reader,
+            delimiter: b";".to_vec(),
+            line: Vec::new(),
+            pos: 0,
+            line_number: 0,
+        }
+    }
+
+    /// The line the last statement ended on.
+    pub(super) fn line_number(&self) -> usize {
+        self.line_number
+    }
+
+    /// The next statement, without its delimiter, or `None` at the end.
+    pub(super) async fn next(&mut self) -> Result<Option<Vec<u8>>, String> {
+        let mut statement = Vec::new();
+        let mut state = State::Code;
+        loop {
+            if self.pos >= self.line.len() {
+                self.line.clear();
+                self.pos = 0;
+                let read = self
+                    .reader
+                    .read_until(b'\n', &mut self.line)
+                    .await
+                    .map_err(|e| {
+                        messages::text("error.failed.read_database_dump", &[("error", &e)])
+                    })?;
+                if read == 0 {
+                    let rest = statement.trim_ascii();
+                    return match state {
+                        State::Quote(_) => Err(messages::text(
+                            "error.database_dump_ends_inside_string",
+                            &[],
+                        )),
+                        _ if rest.is_empty() => Ok(None),
+                        _ => Ok(Some(rest.to_vec())),
+                    };
+                }
+                self.line_number += 1;
+            }
+            if state == State::Code && statement.trim_ascii().is_empty() {
+                let rest = self.line[self.pos..].trim_ascii();
+                if let Some(delimiter) = strip_keyword(rest, b"DELIMITER") {
+                    self.delimiter = delimiter.trim_ascii().to_vec();
+                    self.pos = self.line.len();
+                    continue;
+                }
+            }
+            while self.pos < self.line.len() {
+                let rest = &self.line[self.pos..];
+                let b = rest[0];
+                match state {
+                    State::Quote(quote) => {
+                        // Backslashes escape in strings, not in identifiers.
+                        if b == b'\\' && quote != b'`' && rest.len() > 1 {
+                            statement.extend_from_slice(&rest[..2]);
+                            self.pos += 2;
+                            continue;
+                        }
+                        if b == quote {
+                            state = State::Code;
+                        }
+                    }
+                    State::Comment => {
+                        if rest.starts_with(b"*/") {
+                            state = State::Code;
+                            self.pos += 2;
+                        } else {
+                            self.pos += 1;
+                        }
+                        continue;
+                    }
+                    State::Code if rest.starts_with(&self.delimiter) => {
+                        self.pos += self.delimiter.len();
+                        let statement = statement.trim_ascii();
+                        if statement.is_empty() {
+                            continue;
+                        }
+                        return Ok(Some(statement.to_vec()));
+                    }
+                    State::Code if rest.starts_with(b"-- ") || rest.starts_with(b"--\n") => {
+                        self.pos = self.line.len();
+                        continue;
+                    }
+                    State::Code if b == b'#' => {
+                        self.pos = self.line.len();
+                        continue;
+                    }
+                    State::Code if rest.starts_with(b"/*") && !rest[2..].starts_with(b"!") => {
+                        state = State::Comment;
+                        self.pos += 2;
+                        continue;
+                    }
+                    State::Code if matches!(b, b'\'' | b'"' | b'`') => state = State::Quote(b),
+                    State::Code => {}
+                }
+                statement.push(b);
+                self.pos += 1;
+            }
+        }
+    }
+}
+
+/// What follows `keyword` and whitespace at the start of `line`, ignoring case.
+fn strip_keyword<'a>(line: &'a [u8], keyword: &[u8]) -> Option<&'a [u8]> {
+    let head = line.get(..keyword.len())?;
+    let rest = &line[keyword.len()..];
+    (head.eq_ignore_ascii_case(keyword) && rest.first().is_some_and(u8::is_ascii_whitespace))
+        .then_some(rest)
+}
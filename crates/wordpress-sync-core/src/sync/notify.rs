@@ -0,0 +1,72 @@
+//! What a desktop notification says about a job that has finished.
+
+use serde::Serialize;
+
+use super::space::size;
+use crate::history::{text, HistoryEntry, JobStatus};
+use crate::messages;
+
+/// A finished job, summed up for a notification.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobNotification {
+    /// The history entry the notification is about.
+    pub job_id: String,
+    /// How the job ended, e.g. `Pushed My Site` or `Pull of My Site failed`.
+    pub title: String,
+    /// How long it took, what it moved, and how many warnings it had, or why
+    /// it failed.
+    pub body: String,
+}
+
+impl JobNotification {
+    pub fn of(entry: &HistoryEntry) -> Self {
+        let title = messages::text(
+            &format!(
+                "notification.{}.{}",
+                text(&entry.direction),
+                text(&entry.status)
+            ),
+            &[("name", &entry.profile_name)],
+        );
+        let seconds = (entry.finished_at - entry.started_at).num_seconds().max(0);
+        let mut parts = vec![
+            messages::text(
+                "notification.moved",
+                &[
+                    ("size", &size(entry.bytes_transferred)),
+                    ("duration", &duration(seconds)),
+                ],
+            ),
+            match entry.files_transferred {
+                1 => messages::text("notification.file_changed", &[]),
+                files => messages::text("notification.files_changed", &[("count", &files)]),
+            },
+        ];
+        match entry.warnings.len() {
+            0 => {}
+            1 => parts.push(messages::text("notification.warning", &[])),
+            warnings => parts.push(messages::text(
+                "notification.warnings",
+                &[("count", &warnings)],
+            )),
+        }
+        let mut body = parts.join(", ");
+        if let (JobStatus::Failed, Some(error)) = (entry.status, &entry.error) {
+            body = format!("{}\n{body}", error.message());
+        }
+        Self {
+            job_id: entry.job_id.clone(),
+            title,
+            body,
+        }
+    }
+}
+
+/// `seconds` for a person to read, e.g. `3m 12s`.
+pub(super) fn duration(seconds: i64) -> String {
+    match seconds {
+        0..60 => format!("{seconds}s"),
+        60..3600 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
@@ -0,0 +1,258 @@
+//! First-run setup: what this machine has of the tools syncs use, and a first
+//! profile made from a handful of answers, checked and tested against its
+//! server before it's saved.
+//!
+//! The profile is given its id up front, so the SSH password the answers may
+//! carry is stored where a sync looks for it, and forgotten again if the
+//! profile isn't saved.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use super::local;
+use super::local_sites::{detect_local_sites, LocalSite};
+use super::preflight::{test_connection, ConnectionTest};
+use super::ProgressSink;
+use crate::credentials::{platform_store, CredentialStore, SSH_PASSWORD_SERVICE};
+use crate::error::SyncError;
+use crate::messages;
+use crate::profile::{
+    self, AuthMethod, Issue, LocalEnvironment, Profile, ProfileTemplate, RemoteEnvironment,
+    Severity, TransferMethod,
+};
+use crate::telemetry::{self, Feature};
+
+/// A tool [`detect_prerequisites`] looks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrerequisiteKind {
+    /// OpenSSH, for the user's own `ssh` setup; syncs have their own client.
+    Ssh,
+    /// rsync, the fastest transfer; without it a new profile uses SFTP.
+    Rsync,
+    /// wp-cli, which reads and writes the local database.
+    WpCli,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Prerequisite {
+    pub tool: PrerequisiteKind,
+    pub found: bool,
+    /// The version it reports, if it's found.
+    pub version: Option<String>,
+    /// Whether syncs can't work without it.
+    pub required: bool,
+    pub detail: String,
+}
+
+/// What the setup wizard starts from.
+#[derive(Debug, Clone, Serialize)]
+pub struct Prerequisites {
+    pub tools: Vec<Prerequisite>,
+    /// Whether every required tool was found.
+    pub ready: bool,
+    /// The local development sites found, to pick the first profile's from.
+    pub local_sites: Vec<LocalSite>,
+}
+
+/// The answers the setup wizard collects for a first profile.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WizardAnswers {
+    pub name: String,
+    pub local: LocalEnvironment,
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    pub user: String,
+    /// The WordPress root on the server.
+    pub path: String,
+    pub url: String,
+    #[serde(default)]
+    pub auth: AuthMethod,
+    #[serde(default)]
+    pub key_path: Option<PathBuf>,
+    /// The SSH password, stored for the profile if it's saved.
+    #[serde(default)]
+    pub password: Option<String>,
+    /// How files are copied; rsync if it's installed here, or else SFTP.
+    #[serde(default)]
+    pub transfer: Option<TransferMethod>,
+    #[serde(default)]
+    pub template: Option<ProfileTemplate>,
+}
+
+/// How the wizard's profile came out.
+#[derive(Debug, Clone, Serialize)]
+pub struct WizardResult {
+    /// The profile, saved if `saved`, and otherwise for the wizard to show
+    /// what to change.
+    pub profile: Profile,
+    pub saved: bool,
+    /// What's wrong with the answers or the warnings they leave.
+    pub issues: Vec<Issue>,
+    /// The connection test, unless the answers had errors to fix first.
+    pub connection: Option<ConnectionTest>,
+}
+
+fn default_port() -> u16 {
+    22
+}
+
+/// Look for ssh, rsync, and wp-cli on this machine, and the local
+/// development sites it has.
+pub async fn detect_prerequisites() -> Prerequisites {
+    let (ssh, rsync, wp_cli, local_sites) = tokio::join!(
+        version("ssh", "-V"),
+        version("rsync", "--version"),
+        version("wp", "--version"),
+        detect_local_sites(),
+    );
+    let tools = vec![
+        prerequisite(
+            PrerequisiteKind::Ssh,
+            ssh,
+            false,
+            "ssh isn't installed; syncs use their own SSH client",
+        ),
+        prerequisite(
+            PrerequisiteKind::Rsync,
+            rsync,
+            false,
+            "rsync isn't installed, so files are copied over SFTP, which is slower",
+        ),
+        prerequisite(
+            PrerequisiteKind::WpCli,
+            wp_cli,
+            true,
+            "wp-cli isn't installed, and syncs need it for the local database: \
+             see https://wp-cli.org/#installing",
+        ),
+    ];
+    Prerequisites {
+        ready: tools.iter().all(|tool| tool.found || !tool.required),
+        tools,
+        local_sites,
+    }
+}
+
+/// Make a profile from the wizard's `answers`, check it, and test its
+/// connection, saving it only if nothing's in the way of a sync. Progress
+/// and an untrusted host key go to `sink`, as for a real connection test.
+pub async fn create_profile_from_wizard(
+    answers: WizardAnswers,
+    sink: &dyn ProgressSink,
+) -> Result<WizardResult, SyncError> {
+    telemetry::record(Feature::SetupWizard);
+    let transfer = match answers.transfer {
+        Some(transfer) => transfer,
+        None if version("rsync", "--version").await.is_ok() => TransferMethod::Rsync,
+        None => TransferMethod::Sftp,
+    };
+    let mut remote = RemoteEnvironment::over_ssh(
+        answers.host.trim().to_string(),
+        answers.port,
+        answers.user.trim().to_string(),
+        answers.path.trim().to_string(),
+        answers.url.trim().to_string(),
+    );
+    remote.auth = answers.auth;
+    remote.key_path = answers.key_path.filter(|path| !path.as_os_str().is_empty());
+    remote.transfer = transfer;
+    let mut profile = Profile::new(answers.name.trim().to_string(), answers.local, remote);
+    if let Some(template) = answers.template {
+        template.apply(&mut profile);
+    }
+    profile.id = uuid::Uuid::new_v4().to_string();
+
+    let issues = profile::validate(&profile).await;
+    if issues.iter().any(|issue| issue.severity == Severity::Error) {
+        return Ok(WizardResult {
+            profile,
+            saved: false,
+            issues,
+            connection: None,
+        });
+    }
+
+    let store = platform_store();
+    let password = answers.password.filter(|password| !password.is_empty());
+    if let Some(password) = &password {
+        store
+            .set(SSH_PASSWORD_SERVICE, &profile.id, password)
+            .map_err(SyncError::config)?;
+    }
+    let connection = test_connection(&profile, sink).await;
+    let forget = || {
+        if password.is_some() {
+            let _ = store.delete(SSH_PASSWORD_SERVICE, &profile.id);
+        }
+    };
+    if !connection.passed {
+        forget();
+        return Ok(WizardResult {
+            profile,
+            saved: false,
+            issues,
+            connection: Some(connection),
+        });
+    }
+    let profile = profile::save(profile.clone()).map_err(|e| {
+        forget();
+        SyncError::config(e)
+    })?;
+    tracing::info!(profile = %profile.id, "created a profile with the setup wizard");
+    Ok(WizardResult {
+        profile,
+        saved: true,
+        issues,
+        connection: Some(connection),
+    })
+}
+
+fn prerequisite(
+    tool: PrerequisiteKind,
+    found: Result<String, String>,
+    required: bool,
+    missing: &str,
+) -> Prerequisite {
+    match found {
+        Ok(version) => Prerequisite {
+            tool,
+            found: true,
+            detail: version.clone(),
+            version: Some(version),
+            required,
+        },
+        Err(_) => Prerequisite {
+            tool,
+            found: false,
+            version: None,
+            required,
+            detail: missing.to_string(),
+        },
+    }
+}
+
+/// The first line `program` prints about its version, on stdout or, as
+/// `ssh -V` does, on stderr.
+async fn version(program: &str, arg: &str) -> Result<String, String> {
+    let output = local::command(program)
+        .arg(arg)
+        .output()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(messages::text(
+            "error.program_arg_failed",
+            &[("program", &program), ("arg", &arg)],
+        ));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Ok([stdout, stderr]
+        .iter()
+        .find_map(|out| out.lines().map(str::trim).find(|line| !line.is_empty()))
+        .unwrap_or_default()
+        .to_string())
+}
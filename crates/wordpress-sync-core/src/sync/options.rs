@@ -0,0 +1,66 @@
+//! Setting WordPress options on the destination once a sync has imported a
+//! database into it, as the profile's `options_overrides` say for that side,
+//! so a staging site always ends up hidden from search engines and using
+//! sandbox API keys whatever the database it was given said.
+//!
+//! Values go to `wp option update` on its stdin rather than its command line,
+//! which is logged, since they include license keys and the like.
+
+use serde_json::Value;
+
+use super::endpoint::Endpoint;
+use super::{Direction, Job};
+use crate::credentials;
+use crate::messages::Message;
+
+impl Job<'_> {
+    /// Set the profile's options for the destination. An option left as the
+    /// source had it doesn't undo the sync, so failures are warnings.
+    pub(super) async fn override_options(&self, dest: &Endpoint<'_>) {
+        let overrides = &self.profile.post_sync.options_overrides;
+        let options = match self.direction {
+            Direction::Push => &overrides.remote,
+            Direction::Pull => &overrides.local,
+        };
+        // Options live in the database the sync just replaced.
+        if options.is_empty() || !self.syncs_database() {
+            return;
+        }
+        if !self.has_wp_cli(dest).await.unwrap_or(true) {
+            self.warn(Message::new(
+                "warning.destinations_options_were_not_set_has",
+                &[],
+            ));
+            return;
+        }
+        for (name, value) in options {
+            if let Err(e) = set_option(dest, name, value).await {
+                self.warn(Message::new(
+                    "warning.option_name_was_not_set_destination",
+                    &[("name", &name), ("error", &e)],
+                ));
+            }
+        }
+    }
+}
+
+/// Set `name` to `value` on `dest`.
+async fn set_option(dest: &Endpoint<'_>, name: &str, value: &Value) -> Result<(), String> {
+    let mut args = vec!["option", "update", name];
+    let input = match value {
+        Value::String(reference) if reference.starts_with(credentials::SECRET_REFERENCE_PREFIX) => {
+            credentials::named_secret(reference)?
+        }
+        Value::String(text) => text.clone(),
+        Value::Number(number) => number.to_string(),
+        Value::Bool(flag) => u8::from(*flag).to_string(),
+        Value::Null => String::new(),
+        Value::Array(_) | Value::Object(_) => {
+            args.push("--format=json");
+            value.to_string()
+        }
+    };
+    dest.wp_with_input(&args, input.as_bytes()).await?;
+    tracing::info!(option = name, "set the option on the destination");
+    Ok(())
+}
@@ -0,0 +1,92 @@
+//! Following a profile's bandwidth windows while a job runs, so a long sync
+//! that starts at full speed overnight slows down when working hours begin,
+//! instead of keeping the limits it started with.
+//!
+//! The windows are looked at again every half minute. The limits change only
+//! when the window that applies does, so a limit set on the running job by
+//! hand stands until the next one begins or ends.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use tokio::task::JoinHandle;
+
+use super::{arbiter, Direction, Throttle};
+use crate::profile::Bandwidth;
+use crate::settings;
+
+/// How often the windows are looked at.
+const CHECK_EVERY: Duration = Duration::from_secs(30);
+
+/// A job's throttle kept to its profile's windows, until it's dropped.
+pub(super) struct Pacing(Option<JoinHandle<()>>);
+
+impl Drop for Pacing {
+    fn drop(&mut self) {
+        if let Some(task) = self.0.take() {
+            task.abort();
+        }
+    }
+}
+
+/// Put the limits `bandwidth` gives `direction` now on `throttle`, and keep
+/// them to its windows for as long as the returned pacing is kept. Outside
+/// them the profile's limit applies, or the app's without one.
+pub(super) fn follow(
+    bandwidth: &Bandwidth,
+    direction: Direction,
+    throttle: &Arc<Throttle>,
+) -> Pacing {
+    let mut current = window(bandwidth, &Local::now());
+    apply(bandwidth, direction, current, throttle);
+    if bandwidth.windows.is_empty() {
+        return Pacing(None);
+    }
+    let (bandwidth, throttle) = (bandwidth.clone(), throttle.clone());
+    Pacing(Some(tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(CHECK_EVERY).await;
+            let now = window(&bandwidth, &Local::now());
+            if now == current {
+                continue;
+            }
+            current = now;
+            apply(&bandwidth, direction, current, &throttle);
+            arbiter::rebalance();
+            tracing::info!(
+                window = ?current,
+                limit = ?throttle.own_limit(),
+                files = ?throttle.concurrency(),
+                "bandwidth window changed"
+            );
+        }
+    })))
+}
+
+/// The index of the first of `bandwidth`'s windows covering `at`.
+fn window(bandwidth: &Bandwidth, at: &DateTime<Local>) -> Option<usize> {
+    bandwidth
+        .windows
+        .iter()
+        .position(|window| window.covers(at))
+}
+
+/// Set `throttle` to the limits of the window numbered `window`, or to those
+/// outside any.
+fn apply(bandwidth: &Bandwidth, direction: Direction, window: Option<usize>, throttle: &Throttle) {
+    let Some(window) = window.map(|i| &bandwidth.windows[i]) else {
+        let (upload, download) = settings::bandwidth_limits();
+        throttle.set(match direction {
+            Direction::Push => bandwidth.upload_kib_per_sec.or(upload),
+            Direction::Pull => bandwidth.download_kib_per_sec.or(download),
+        });
+        throttle.set_concurrency(None);
+        return;
+    };
+    throttle.set(match direction {
+        Direction::Push => window.upload_kib_per_sec,
+        Direction::Pull => window.download_kib_per_sec,
+    });
+    throttle.set_concurrency(window.parallel_transfers.map(|files| files as usize));
+}
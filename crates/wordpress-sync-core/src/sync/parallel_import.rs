@@ -0,0 +1,269 @@
+//! Importing the dump a table at a time, several tables at once, for
+//! profiles whose `import_workers` is more than one.
+//!
+//! The dump is split beside itself into one part per table, each starting with
+//! the session settings at the top of the dump so it imports the same way on
+//! its own. Whatever can't go before every table is in — views, and the
+//! stand-in tables mysqldump makes for them — is kept in order in a last part,
+//! imported once the tables are. Each part goes over its own connection, the
+//! largest first, so one big table doesn't hold up the small ones behind it.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use futures_util::future::try_join_all;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
+
+use super::at_rest;
+use super::db;
+use super::endpoint::Endpoint;
+use super::transfer::{OnProgress, TransferStats};
+use super::Job;
+use crate::messages;
+use crate::profile::TableSelection;
+
+/// The comment mysqldump starts each table's section with.
+const TABLE_SECTION: &[u8] = b"-- Table structure for table ";
+
+/// Comments starting sections that must wait for every table.
+const LATER_SECTIONS: &[&[u8]] = &[
+    b"-- Temporary view structure for view ",
+    b"-- Temporary table structure for view ",
+    b"-- View structure for view ",
+    b"-- Final view structure for view ",
+    b"-- Dumping routines",
+    b"-- Dumping events",
+];
+
+/// A dump split into parts, on this machine.
+struct Parts {
+    /// Each table's part and its size, largest first.
+    tables: Vec<(String, PathBuf, u64)>,
+    /// What is imported after the tables, if anything is.
+    last: Option<PathBuf>,
+}
+
+impl Parts {
+    async fn remove(&self) {
+        let paths = self
+            .tables
+            .iter()
+            .map(|(_, path, _)| path)
+            .chain(&self.last);
+        for path in paths {
+            let _ = tokio::fs::remove_file(path).await;
+        }
+    }
+}
+
+/// Split the dump at `dump_path` into [`Parts`], written beside it.
+async fn split(dump_path: &Path) -> Result<Parts, String> {
+    let read_failed = |e: std::io::Error| {
+        messages::text(
+            "error.failed.read_path",
+            &[("path", &dump_path.display()), ("error", &e)],
+        )
+    };
+    let file = at_rest::open(dump_path).await?;
+    let mut reader = BufReader::new(file);
+    let mut header = Vec::new();
+    let mut tables = Vec::new();
+    // The part being written for the current table, and the one for after the tables.
+    let mut table: Option<BufWriter<at_rest::Writer>> = None;
+    let mut last: Option<(BufWriter<at_rest::Writer>, PathBuf)> = None;
+    let mut in_last = false;
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader
+            .read_until(b'\n', &mut line)
+            .await
+            .map_err(read_failed)?
+            == 0
+        {
+            break;
+        }
+        if let Some(rest) = line.strip_prefix(TABLE_SECTION) {
+            let name = rest.trim_ascii_end();
+            let name = name.strip_prefix(b"`").unwrap_or(name);
+            let name = name.strip_suffix(b"`").unwrap_or(name);
+            let name = String::from_utf8_lossy(name).replace("``", "`");
+            if let Some(mut writer) = table.take() {
+                finish(&mut writer).await?;
+            }
+            let path = dump_path.with_extension(format!("{}.sql", tables.len() + 1));
+            table = Some(start(&path, &header).await?);
+            tables.push((name, path, 0));
+            in_last = false;
+        } else if LATER_SECTIONS
+            .iter()
+            .any(|section| line.starts_with(section))
+        {
+            if let Some(mut writer) = table.take() {
+                finish(&mut writer).await?;
+            }
+            if last.is_none() {
+                let path = dump_path.with_extension("last.sql");
+                last = Some((start(&path, &header).await?, path));
+            }
+            in_last = true;
+        }
+        let writer = if in_last {
+            last.as_mut().map(|(writer, _)| writer)
+        } else {
+            table.as_mut()
+        };
+        match writer {
+            Some(writer) => writer.write_all(&line).await.map_err(|e| {
+                messages::text("error.failed.split_database_dump", &[("error", &e)])
+            })?,
+            // Everything before the first section sets up the session.
+            None => header.extend_from_slice(&line),
+        }
+    }
+    if let Some(mut writer) = table.take() {
+        finish(&mut writer).await?;
+    }
+    let last = match last {
+        Some((mut writer, path)) => {
+            finish(&mut writer).await?;
+            Some(path)
+        }
+        None => None,
+    };
+    for (_, path, size) in &mut tables {
+        *size = tokio::fs::metadata(&*path)
+            .await
+            .map(|meta| meta.len())
+            .unwrap_or(0);
+    }
+    tables.sort_by_key(|(_, _, size)| std::cmp::Reverse(*size));
+    Ok(Parts { tables, last })
+}
+
+/// Create the part at `path`, starting with the dump's `header`.
+async fn start(path: &Path, header: &[u8]) -> Result<BufWriter<at_rest::Writer>, String> {
+    let file = at_rest::create(path).await?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(header).await.map_err(|e| {
+        messages::text(
+            "error.failed.write_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })?;
+    Ok(writer)
+}
+
+async fn finish(writer: &mut BufWriter<at_rest::Writer>) -> Result<(), String> {
+    writer
+        .flush()
+        .await
+        .map_err(|e| messages::text("error.failed.split_database_dump", &[("error", &e)]))?;
+    writer.get_mut().finish().await
+}
+
+impl Job<'_> {
+    /// Import the dump at `dump_path` into `dest` table by table, the
+    /// profile's `import_workers` at once, keeping the user tables as they
+    /// were (by way of `snapshot_path`) if the profile says to. Progress counts
+    /// the tables imported and the bytes of the dump they came to, naming the
+    /// last table done.
+    pub(super) async fn import_tables(
+        &self,
+        dest: &Endpoint<'_>,
+        dump_path: &Path,
+        snapshot_path: &Path,
+        on_progress: OnProgress<'_>,
+    ) -> Result<(), String> {
+        let users = if self.profile.preserve_users {
+            self.user_tables(dest).await?
+        } else {
+            Vec::new()
+        };
+        if !users.is_empty() {
+            let selection = TableSelection {
+                include: users.clone(),
+                exclude: Vec::new(),
+            };
+            self.export_part(dest, &selection, snapshot_path).await?;
+        }
+        let parts = split(dump_path).await?;
+        let imported = self
+            .import_parts(dest, dump_path, &parts, on_progress)
+            .await;
+        parts.remove().await;
+        if users.is_empty() {
+            return imported;
+        }
+        // Restore even after a failed import, which may already have dropped them.
+        let restored = self.import_part(dest, snapshot_path).await;
+        imported.and(restored)
+    }
+
+    async fn import_parts(
+        &self,
+        dest: &Endpoint<'_>,
+        dump_path: &Path,
+        parts: &Parts,
+        on_progress: OnProgress<'_>,
+    ) -> Result<(), String> {
+        if parts.tables.is_empty() {
+            // A dump without mysqldump's comments can't be split.
+            return self.import_part(dest, dump_path).await;
+        }
+        let stats = Mutex::new(TransferStats {
+            files_total: Some(parts.tables.len() as u64),
+            ..Default::default()
+        });
+        let on_progress = Mutex::new(on_progress);
+        let queue = Mutex::new(parts.tables.iter());
+        let workers = (0..self.profile.import_workers.max(1)).map(|_| async {
+            loop {
+                self.throttle.wait_while_paused().await;
+                let Some((table, path, size)) = queue.lock().unwrap().next() else {
+                    return Ok::<(), String>(());
+                };
+                self.import_part(dest, path).await.map_err(|e| {
+                    messages::text(
+                        "error.failed.import_table",
+                        &[("table", &table), ("error", &e)],
+                    )
+                })?;
+                let mut stats = stats.lock().unwrap();
+                stats.files_done += 1;
+                stats.bytes_transferred += size;
+                stats.current_file = Some(table.clone());
+                (on_progress.lock().unwrap())(&stats);
+            }
+        });
+        try_join_all(workers).await?;
+        match &parts.last {
+            Some(path) => self.import_part(dest, path).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Import one part, or a whole dump, into `dest`.
+    async fn import_part(&self, dest: &Endpoint<'_>, path: &Path) -> Result<(), String> {
+        match self.direct_database(dest).await? {
+            Some(mysql) => mysql.import(path).await,
+            None => db::import(dest, path, self.profile.compression, &mut |_| {}).await,
+        }
+    }
+
+    /// Export the tables `selection` picks from `side` to `path`.
+    async fn export_part(
+        &self,
+        side: &Endpoint<'_>,
+        selection: &TableSelection,
+        path: &Path,
+    ) -> Result<(), String> {
+        match self.direct_database(side).await? {
+            Some(mysql) => mysql.export(selection, &[], path, &mut |_| {}).await,
+            None => {
+                let compression = self.profile.compression;
+                db::export(side, selection, &[], path, compression, &mut |_| {}).await
+            }
+        }
+    }
+}
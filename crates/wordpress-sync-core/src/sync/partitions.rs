@@ -0,0 +1,191 @@
+//! Syncing only some of the uploads' year and month folders.
+//!
+//! WordPress files uploads under `<year>/<month>/`, so a profile's
+//! `upload_partitions`, such as `2024` or `2023/11`, can leave the rest of a
+//! large media library out of every file transfer. Uploads outside those
+//! folders, such as a plugin's own directory, always go across. The folders
+//! either side has, and their sizes, are listed to pick from.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use serde::Serialize;
+
+use super::progress::Reporter;
+use super::transfer::{local_tree, Entry, Excludes, Tree};
+use super::{connect, multisite, Components, Direction, Job, ProgressSink};
+use crate::error::SyncError;
+use crate::profile::Profile;
+
+/// Matches a year folder, as an exclude pattern.
+const YEAR: &str = "[0-9][0-9][0-9][0-9]";
+
+/// Matches a month folder, as an exclude pattern.
+const MONTH: &str = "[0-9][0-9]";
+
+/// One year or month folder of the uploads directory.
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadPartition {
+    /// `2024`, or `2024/05` for a month.
+    pub path: String,
+    pub local: PartitionSize,
+    pub remote: PartitionSize,
+    /// Whether the profile's file transfers include it.
+    pub selected: bool,
+    /// A year's month folders, in order; empty for a month.
+    pub months: Vec<UploadPartition>,
+}
+
+/// What one side has in a partition.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct PartitionSize {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// Whether `path` names a partition: a year, `2024`, or a month, `2024/05`.
+pub(crate) fn is_partition(path: &str) -> bool {
+    parse(path).is_some()
+}
+
+/// The year and month, if any, of the partition `path` names.
+fn parse(path: &str) -> Option<(&str, Option<&str>)> {
+    let path = path.trim_matches('/');
+    let (year, month) = match path.split_once('/') {
+        Some((year, month)) => (year, Some(month)),
+        None => (path, None),
+    };
+    let digits = |part: &str, len| part.len() == len && part.bytes().all(|b| b.is_ascii_digit());
+    let is_month = |month: &str| digits(month, 2) && ("01"..="12").contains(&month);
+    (digits(year, 4) && month.is_none_or(is_month)).then_some((year, month))
+}
+
+/// Exclude rules, relative to the WordPress root, that leave only the
+/// profile's partitions of the uploads directory; none when it picks none.
+pub(super) fn partition_rules(profile: &Profile) -> Vec<String> {
+    let mut years = BTreeSet::new();
+    let mut months: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for (year, month) in profile
+        .upload_partitions
+        .iter()
+        .filter_map(|path| parse(path))
+    {
+        match month {
+            Some(month) => {
+                months.entry(year).or_default().insert(month);
+            }
+            None => {
+                years.insert(year);
+            }
+        }
+    }
+    if years.is_empty() && months.is_empty() {
+        return Vec::new();
+    }
+    let dir = format!("/{}", multisite::uploads_dir(profile));
+    let mut rules = vec![format!("{dir}/{YEAR}/")];
+    rules.extend(years.iter().map(|year| format!("!{dir}/{year}/")));
+    // A whole year takes in its months.
+    for (year, months) in months.iter().filter(|(year, _)| !years.contains(*year)) {
+        rules.push(format!("!{dir}/{year}/"));
+        rules.push(format!("{dir}/{year}/{MONTH}/"));
+        rules.extend(months.iter().map(|month| format!("!{dir}/{year}/{month}/")));
+    }
+    rules
+}
+
+/// Connect to the server and list the year and month folders of both sides'
+/// uploads, with what each side has in them and whether `profile` syncs them.
+/// An untrusted host key is announced to `sink` just as it is for a real sync.
+pub async fn upload_partitions(
+    profile: &Profile,
+    sink: &dyn ProgressSink,
+) -> Result<Vec<UploadPartition>, SyncError> {
+    let components = Components::UploadsOnly;
+    components.check(profile)?;
+    let (remote, transfer, _) = connect(profile, sink).await?;
+    let reporter = Reporter::new(uuid::Uuid::new_v4().to_string(), sink);
+    let job = Job::new(
+        profile,
+        Direction::Pull,
+        components,
+        remote,
+        transfer,
+        reporter,
+    );
+    let result = job.upload_partitions().await;
+    if let Some(remote) = &job.remote {
+        remote.close().await;
+    }
+    result.map_err(SyncError::transfer)
+}
+
+impl Job<'_> {
+    async fn upload_partitions(&self) -> Result<Vec<UploadPartition>, String> {
+        let dir = multisite::uploads_dir(self.profile);
+        // Everything is counted, whatever the profile leaves out.
+        let everything = Excludes::default();
+        let local = local_tree(Path::new(&self.local().path(&dir)), &everything).await?;
+        let remote = self
+            .transfer
+            .list(&self.remote().path(&dir), &everything)
+            .await?;
+        let mut found: BTreeMap<String, UploadPartition> = BTreeMap::new();
+        for (tree, remote) in [(&local, false), (&remote, true)] {
+            add_sizes(&mut found, tree, remote);
+        }
+        let rules = partition_rules(self.profile);
+        let selection = Excludes::new(&rules)?;
+        let selected = |path: &str| !selection.is_excluded(&format!("{dir}/{path}"), true);
+        let mut partitions: Vec<UploadPartition> = Vec::new();
+        for (path, mut partition) in found {
+            partition.selected = selected(&path);
+            match partitions.last_mut() {
+                Some(year) if path.starts_with(&format!("{}/", year.path)) => {
+                    year.months.push(partition)
+                }
+                _ => partitions.push(partition),
+            }
+        }
+        Ok(partitions)
+    }
+}
+
+/// Add the files of one side's uploads `tree` to the partitions they're in.
+fn add_sizes(found: &mut BTreeMap<String, UploadPartition>, tree: &Tree, remote: bool) {
+    let mut add = |path: &str, bytes: u64| {
+        let partition = found
+            .entry(path.to_string())
+            .or_insert_with(|| UploadPartition {
+                path: path.to_string(),
+                local: PartitionSize::default(),
+                remote: PartitionSize::default(),
+                selected: false,
+                months: Vec::new(),
+            });
+        let size = match remote {
+            true => &mut partition.remote,
+            false => &mut partition.local,
+        };
+        size.files += 1;
+        size.bytes += bytes;
+    };
+    for (path, entry) in tree {
+        let Entry::File { size, .. } = *entry else {
+            continue;
+        };
+        let mut parts = path.split('/');
+        let (Some(year), Some(next)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        if parse(year).is_none() {
+            continue;
+        }
+        add(year, size);
+        // `next` is the file's name for one filed under just its year.
+        let month = format!("{year}/{next}");
+        if parts.next().is_some() && parse(&month).is_some() {
+            add(&month, size);
+        }
+    }
+}
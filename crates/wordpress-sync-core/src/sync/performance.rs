@@ -0,0 +1,141 @@
+//! How a profile's syncs have performed over time, worked out from the
+//! phases the history records of each: week by week, for each direction, how
+//! long a sync took and how fast each of its phases went, so that syncs that
+//! got slower after a change of host show it in numbers.
+//!
+//! Only syncs that succeeded count, since a failed one stopped partway.
+
+use chrono::{NaiveDate, Weekday};
+use serde::Serialize;
+
+use super::{throughput, Direction, Phase};
+use crate::history::{self, HistoryEntry, JobStatus};
+
+/// The most syncs trends are worked out from, newest first.
+const HISTORY_DEPTH: u32 = 1000;
+
+/// A profile's syncs, a week at a time.
+#[derive(Debug, Clone, Serialize)]
+pub struct PerformanceTrends {
+    pub profile_id: String,
+    /// Each week with a successful sync in a direction, oldest first.
+    pub weeks: Vec<PerformanceWeek>,
+}
+
+/// The successful syncs of one week in one direction.
+#[derive(Debug, Clone, Serialize)]
+pub struct PerformanceWeek {
+    /// The Monday the week starts on, in UTC.
+    pub week_of: NaiveDate,
+    pub direction: Direction,
+    pub syncs: u32,
+    /// The median time a sync took, from start to finish.
+    pub median_duration_ms: u64,
+    /// `median_duration_ms` against the last earlier week's in the same
+    /// direction: 3.0 when syncs took three times as long.
+    pub change: Option<f64>,
+    /// Each phase the syncs ran, in the order they first ran it.
+    pub phases: Vec<PhaseTrend>,
+}
+
+/// One phase across a week's syncs.
+#[derive(Debug, Clone, Serialize)]
+pub struct PhaseTrend {
+    pub phase: Phase,
+    pub runs: u32,
+    pub average_duration_ms: u64,
+    pub bytes_transferred: u64,
+    /// What the phase moved a second over all its runs, or `None` when it
+    /// moved nothing.
+    pub bytes_per_second: Option<u64>,
+}
+
+/// The performance of `profile_id`'s recorded syncs, week by week.
+pub fn performance_trends(profile_id: &str) -> Result<PerformanceTrends, String> {
+    let mut entries = history::list(Some(profile_id), Some(HISTORY_DEPTH))?;
+    entries.retain(|entry| entry.status == JobStatus::Succeeded);
+    entries.sort_by_key(|entry| entry.started_at);
+    Ok(PerformanceTrends {
+        profile_id: profile_id.to_string(),
+        weeks: weeks(&entries),
+    })
+}
+
+/// `entries`, oldest first, grouped by week and direction.
+fn weeks(entries: &[HistoryEntry]) -> Vec<PerformanceWeek> {
+    let mut groups: Vec<(NaiveDate, Direction, Vec<&HistoryEntry>)> = Vec::new();
+    for entry in entries {
+        let week_of = entry.started_at.date_naive().week(Weekday::Mon).first_day();
+        match groups
+            .iter_mut()
+            .find(|(week, direction, _)| *week == week_of && *direction == entry.direction)
+        {
+            Some((_, _, group)) => group.push(entry),
+            None => groups.push((week_of, entry.direction, vec![entry])),
+        }
+    }
+    let mut weeks: Vec<PerformanceWeek> = Vec::with_capacity(groups.len());
+    for (week_of, direction, group) in groups {
+        let median_duration_ms = median(group.iter().map(|entry| duration_ms(entry)).collect());
+        let change = weeks
+            .iter()
+            .rev()
+            .find(|week| week.direction == direction)
+            .map(|earlier| median_duration_ms as f64 / earlier.median_duration_ms.max(1) as f64);
+        weeks.push(PerformanceWeek {
+            week_of,
+            direction,
+            syncs: group.len() as u32,
+            median_duration_ms,
+            change,
+            phases: phases(&group),
+        });
+    }
+    weeks
+}
+
+/// Each phase `group` ran, summed over its runs.
+fn phases(group: &[&HistoryEntry]) -> Vec<PhaseTrend> {
+    let mut totals: Vec<(Phase, u32, u64, u64)> = Vec::new();
+    for report in group.iter().flat_map(|entry| &entry.phases) {
+        match totals.iter_mut().find(|(phase, ..)| *phase == report.phase) {
+            Some((_, runs, duration, bytes)) => {
+                *runs += 1;
+                *duration += report.duration_ms;
+                *bytes += report.bytes_transferred;
+            }
+            None => totals.push((
+                report.phase,
+                1,
+                report.duration_ms,
+                report.bytes_transferred,
+            )),
+        }
+    }
+    totals
+        .into_iter()
+        .map(|(phase, runs, duration, bytes)| PhaseTrend {
+            phase,
+            runs,
+            average_duration_ms: duration / u64::from(runs),
+            bytes_transferred: bytes,
+            bytes_per_second: throughput(bytes, duration),
+        })
+        .collect()
+}
+
+fn duration_ms(entry: &HistoryEntry) -> u64 {
+    (entry.finished_at - entry.started_at)
+        .num_milliseconds()
+        .max(0) as u64
+}
+
+/// The middle of `values`, or the mean of the middle two.
+fn median(mut values: Vec<u64>) -> u64 {
+    values.sort_unstable();
+    match values.len() {
+        0 => 0,
+        n if n % 2 == 1 => values[n / 2],
+        n => (values[n / 2 - 1] + values[n / 2]) / 2,
+    }
+}
@@ -0,0 +1,72 @@
+//! Giving the files a push copies the profile's modes and owner on the server.
+
+use std::collections::BTreeSet;
+
+use super::Job;
+use crate::byte_path;
+use crate::messages::Message;
+use crate::profile::SudoStep;
+use crate::shell;
+
+impl Job<'_> {
+    /// Set the profile's modes and owner on the files this push copied, as
+    /// paths relative to the files root, and the directories they are in.
+    /// The files are in place whatever happens, so failing is a warning.
+    pub(super) async fn apply_permissions(&self, copied: &[String]) {
+        let permissions = &self.profile.permissions;
+        if !permissions.is_set() || copied.is_empty() {
+            return;
+        }
+        let remote = self.remote();
+        if remote.session().is_err() {
+            self.warn(Message::new(
+                "warning.copied_files_modes_owner_werent_set",
+                &[],
+            ));
+            return;
+        }
+        let dirs: BTreeSet<&str> = copied
+            .iter()
+            .flat_map(|path| path.match_indices('/').map(|(i, _)| &path[..i]))
+            .collect();
+        let files = nul_separated(copied.iter().map(String::as_str));
+        let dirs = nul_separated(dirs);
+        let mut steps = Vec::new();
+        if let Some(mode) = &permissions.file_mode {
+            steps.push((format!("chmod {}", shell::quote(mode)), files.clone()));
+        }
+        if let Some(mode) = &permissions.dir_mode {
+            steps.push((format!("chmod {}", shell::quote(mode)), dirs.clone()));
+        }
+        if let Some(owner) = &permissions.owner {
+            steps.push((
+                format!("chown {}", shell::quote(owner)),
+                [files, dirs].concat(),
+            ));
+        }
+        let root = self.files_root(&remote);
+        for (command, paths) in steps {
+            if paths.is_empty() {
+                continue;
+            }
+            let line = format!("cd {} && xargs -0 {command}", shell::quote(&root));
+            if let Err(e) = self.run_remote(SudoStep::Permissions, &line, &paths).await {
+                self.warn(Message::new(
+                    "warning.copied_files_modes_owner_werent_all",
+                    &[("error", &e)],
+                ));
+                return;
+            }
+        }
+    }
+}
+
+/// `paths` as `xargs -0` reads them.
+fn nul_separated<'a>(paths: impl IntoIterator<Item = &'a str>) -> Vec<u8> {
+    let mut list = Vec::new();
+    for path in paths {
+        list.extend_from_slice(&byte_path::to_bytes(path));
+        list.push(0);
+    }
+    list
+}
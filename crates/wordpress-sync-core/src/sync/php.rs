@@ -0,0 +1,80 @@
+//! Finding the PHP to run wp-cli with on the server.
+//!
+//! Some hosts' `php` is the CGI build, or an old version kept for sites
+//! that need it, with the command-line PHP called `php81` or only found by
+//! its full path. The first PHP whose SAPI is `cli` is used, unless the
+//! profile names one, and it's given the profile's `memory_limit`, since
+//! the host's default often isn't enough for `wp db export`.
+
+use super::remote::Remote;
+use crate::profile::RemoteEnvironment;
+use crate::shell;
+
+/// PHP binaries tried in order when the profile doesn't name one, newest
+/// first after `php` itself, then cPanel's EasyApache and Plesk builds.
+const CANDIDATES: &str = "php php84 php83 php82 php81 php80 php74 \
+                          $(ls -d /opt/cpanel/ea-php*/root/usr/bin/php /opt/plesk/php/*/bin/php \
+                          2>/dev/null | sort -r)";
+
+/// How wp-cli is started on the server.
+#[derive(Debug, Clone)]
+pub(super) struct Launcher {
+    /// The command line before wp-cli's own arguments.
+    pub(super) line: String,
+    /// The PHP it runs with, when one was found.
+    pub(super) php: Option<String>,
+}
+
+/// Find the PHP and the wp-cli `env` runs on the server, in one command.
+pub(super) async fn launcher(remote: &Remote, env: &RemoteEnvironment) -> Result<Launcher, String> {
+    let wp = shell::quote(env.wp_cli.as_deref().unwrap_or("wp"));
+    let find_php = match &env.php.binary {
+        Some(binary) => format!("echo php={}", shell::quote(binary)),
+        None => format!(
+            "for php in {CANDIDATES}; do \
+             if command -v \"$php\" >/dev/null 2>&1 && \
+             [ \"$(\"$php\" -r 'echo PHP_SAPI;' 2>/dev/null)\" = cli ]; then \
+             echo \"php=$(command -v \"$php\")\"; break; fi; done"
+        ),
+    };
+    // The phar, and Composer's proxy for it, are PHP scripts; wp-cli's own
+    // launcher is a shell script that reads `WP_CLI_PHP` instead.
+    let found = remote
+        .exec(&format!(
+            "{find_php}; wp=$(command -v {wp}) && echo \"wp=$wp\" && \
+             head -n 1 \"$wp\" | grep -q php && echo script; true"
+        ))
+        .await?;
+    let value = |name: &str| {
+        found
+            .lines()
+            .find_map(|line| line.trim().strip_prefix(name)?.strip_prefix('='))
+            .map(str::to_string)
+    };
+    let php = value("php");
+    let script = found.lines().any(|line| line.trim() == "script");
+    let limit = env.php.memory_limit.trim();
+    let line = match (&php, value("wp")) {
+        (Some(php), Some(wp)) if script => {
+            let mut line = vec![php.as_str()];
+            let setting = format!("memory_limit={limit}");
+            if !limit.is_empty() {
+                line.extend(["-d", &setting]);
+            }
+            line.push(&wp);
+            shell::join(line)
+        }
+        (Some(php), Some(wp)) => {
+            let mut line = vec!["env".to_string(), format!("WP_CLI_PHP={php}")];
+            if !limit.is_empty() {
+                line.push(format!("WP_CLI_PHP_ARGS=-d memory_limit={limit}"));
+            }
+            line.push(wp);
+            shell::join(line)
+        }
+        // Left to fail as it would have.
+        _ => wp,
+    };
+    tracing::debug!(?php, %line, "found the server's wp-cli");
+    Ok(Launcher { line, php })
+}
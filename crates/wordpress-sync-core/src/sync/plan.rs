@@ -0,0 +1,304 @@
+//! Dry runs: what a sync would change, worked out without changing anything.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::progress::Reporter;
+use super::transfer::FileChanges;
+use super::{connect, Components, Direction, Drift, Job, LargeDeletion, ProgressSink, TwoWayPlan};
+use crate::error::SyncError;
+use crate::messages;
+use crate::paths;
+use crate::profile::Profile;
+use crate::search_replace::{CollationRemap, PrefixRemap};
+use crate::telemetry::{self, Feature};
+
+/// Everything a sync in one direction would do to the destination.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncPlan {
+    pub profile_id: String,
+    pub direction: Direction,
+    pub components: Components,
+    /// Relative to the directory being mirrored: the WordPress root, or the
+    /// uploads directory for an uploads-only sync.
+    pub files: FileChanges,
+    /// For a two-way uploads sync, what it copies each way, in place of `files`.
+    pub two_way: Option<TwoWayPlan>,
+    /// `None` when the database isn't part of the sync, either by choice or
+    /// because the profile has no shell on the server.
+    pub database: Option<DatabasePlan>,
+    /// For a push, what changed on the server after the last sync and would
+    /// be overwritten.
+    pub drift: Option<Drift>,
+    /// The files deleted, when they're more than the profile's warning
+    /// policy allows. A sync it blocks needs telling to go ahead.
+    pub large_deletion: Option<LargeDeletion>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DatabasePlan {
+    /// Selected source tables that will overwrite a table of the same name on
+    /// the destination, named as they will be there.
+    pub replaced_tables: Vec<String>,
+    /// Source tables the destination doesn't have yet, named as they will be there.
+    pub created_tables: Vec<String>,
+    /// Destination user tables put back after the import, when the profile preserves users.
+    pub preserved_tables: Vec<String>,
+    /// The rename of the source's tables to the destination's table prefix, if they differ.
+    pub prefix_remap: Option<PrefixRemap>,
+    /// Character sets and collations the destination's server lacks, renamed
+    /// in the dump to ones it has.
+    pub collation_remaps: Vec<CollationRemap>,
+    /// In the order they take precedence.
+    pub replacements: Vec<PlannedReplacement>,
+    /// What may go wrong with the import, such as a collation left as it is.
+    pub warnings: Vec<String>,
+}
+
+/// A substitution the sync will make in the database.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedReplacement {
+    pub from: String,
+    pub to: String,
+    /// Values that will change, counted on the source database.
+    pub occurrences: u64,
+}
+
+/// Connect to the server and work out what syncing `profile` in `direction` would change.
+///
+/// Nothing is written on either side. An untrusted host key is announced to
+/// `sink` just as it is for a real sync.
+pub async fn plan(
+    profile: &Profile,
+    direction: Direction,
+    components: Components,
+    sink: &dyn ProgressSink,
+) -> Result<SyncPlan, SyncError> {
+    telemetry::record(Feature::DryRun);
+    components.check(profile)?;
+    let (remote, transfer, _) = connect(profile, sink).await?;
+    let reporter = Reporter::new(uuid::Uuid::new_v4().to_string(), sink);
+    let job = Job::new(profile, direction, components, remote, transfer, reporter);
+    let result = job.plan().await;
+    if let Some(remote) = &job.remote {
+        remote.close().await;
+    }
+    if let Ok(plan) = &result {
+        // Only reports compare against it, so a plan that can't be kept still stands.
+        if let Err(e) = PlannedTotals::of(plan).save() {
+            tracing::warn!(error = %e, "failed to keep the plan for reports");
+        }
+    }
+    result
+}
+
+impl Job<'_> {
+    async fn plan(&self) -> Result<SyncPlan, SyncError> {
+        let two_way = if self.transfers_files() && self.two_way() {
+            Some(self.two_way_plan().await.map_err(SyncError::transfer)?)
+        } else {
+            None
+        };
+        let files = if self.transfers_files() && two_way.is_none() {
+            self.planned_changes().await.map_err(SyncError::transfer)?
+        } else {
+            FileChanges::default()
+        };
+
+        let database = if self.syncs_database() {
+            self.detect_network().await.map_err(SyncError::database)?;
+            Some(self.database_plan().await.map_err(SyncError::database)?)
+        } else {
+            None
+        };
+
+        let drift = match self.direction {
+            Direction::Push => self.drift().await.map_err(SyncError::remote_command)?,
+            Direction::Pull => None,
+        };
+        let large_deletion = self
+            .large_deletion(&files)
+            .await
+            .map_err(SyncError::transfer)?;
+
+        Ok(SyncPlan {
+            profile_id: self.profile.id.clone(),
+            direction: self.direction,
+            components: self.components,
+            files,
+            two_way,
+            database,
+            drift,
+            large_deletion,
+        })
+    }
+
+    async fn database_plan(&self) -> Result<DatabasePlan, String> {
+        let (source, dest) = (self.source(), self.destination());
+        let dest_tables = self.tables(&dest).await?;
+        let source_tables = self.tables(&source).await?;
+        let selection = self.table_selection(&source).await?;
+        let prefix_remap = self.prefix_remap().await?;
+        let mut collations = self.collations().await?;
+        collations
+            .warnings
+            .extend(self.newer_orders_warning().await?);
+        let renamed = |table: &str| match &prefix_remap {
+            Some(remap) => match table.strip_prefix(&remap.from) {
+                Some(rest) => format!("{}{rest}", remap.to),
+                None => table.to_string(),
+            },
+            None => table.to_string(),
+        };
+        let (replaced_tables, created_tables): (Vec<String>, _) = selection
+            .select(&source_tables)?
+            .into_iter()
+            .map(renamed)
+            .partition(|table| dest_tables.contains(table));
+        let preserved_tables = if self.profile.preserve_users {
+            self.user_tables(&dest).await?
+        } else {
+            Vec::new()
+        };
+        let replaced_tables = replaced_tables
+            .into_iter()
+            .filter(|table| !preserved_tables.contains(table))
+            .collect();
+        // A subsite's replacements are only counted in its own tables.
+        let counted = self
+            .profile
+            .multisite
+            .subsite
+            .map(|_| selection.include.as_slice());
+        let mut replacements = Vec::new();
+        for (from, to) in self.replacements() {
+            let occurrences = self
+                .count_replacements(&source, &from, &to, counted)
+                .await?;
+            replacements.push(PlannedReplacement {
+                from,
+                to,
+                occurrences,
+            });
+        }
+        Ok(DatabasePlan {
+            replaced_tables,
+            created_tables,
+            preserved_tables,
+            prefix_remap,
+            collation_remaps: collations.renames,
+            replacements,
+            warnings: collations
+                .warnings
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+        })
+    }
+}
+
+/// What a profile's last dry run in one direction expected, kept so a report
+/// of the sync that followed can set it against what was done.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlannedTotals {
+    pub profile_id: String,
+    pub direction: Direction,
+    pub components: Components,
+    pub planned_at: DateTime<Utc>,
+    /// Files to be added or updated, or for a two-way sync, copied either way.
+    pub files_to_copy: u64,
+    pub files_to_delete: u64,
+    pub bytes: u64,
+    /// Tables to be replaced or created; `None` when the database wasn't planned.
+    pub tables: Option<u64>,
+    /// Values the search-replace was to change.
+    pub replacements: Option<u64>,
+}
+
+impl PlannedTotals {
+    fn of(plan: &SyncPlan) -> Self {
+        let (files_to_copy, files_to_delete, bytes) = match &plan.two_way {
+            Some(two_way) => (
+                (two_way.push.len() + two_way.pull.len()) as u64,
+                0,
+                two_way.bytes,
+            ),
+            None => (
+                (plan.files.added.len() + plan.files.updated.len()) as u64,
+                plan.files.deleted.len() as u64,
+                plan.files.bytes,
+            ),
+        };
+        Self {
+            profile_id: plan.profile_id.clone(),
+            direction: plan.direction,
+            components: plan.components,
+            planned_at: Utc::now(),
+            files_to_copy,
+            files_to_delete,
+            bytes,
+            tables: plan.database.as_ref().map(|database| {
+                (database.replaced_tables.len() + database.created_tables.len()) as u64
+            }),
+            replacements: plan.database.as_ref().map(|database| {
+                database
+                    .replacements
+                    .iter()
+                    .map(|replacement| replacement.occurrences)
+                    .sum()
+            }),
+        }
+    }
+
+    /// The last plan of `profile_id` in `direction`, if one was made.
+    pub(super) fn load(profile_id: &str, direction: Direction) -> Result<Option<Self>, String> {
+        let path = Self::path(profile_id, direction)?;
+        let raw = match fs::read_to_string(&path) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(messages::text(
+                    "error.failed.read_path",
+                    &[("path", &path.display()), ("error", &e)],
+                ))
+            }
+        };
+        serde_json::from_str(&raw).map(Some).map_err(|e| {
+            messages::text(
+                "error.invalid_plan_path",
+                &[("path", &path.display()), ("error", &e)],
+            )
+        })
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let path = Self::path(&self.profile_id, self.direction)?;
+        let dir = paths::plans_dir()?;
+        fs::create_dir_all(&dir).map_err(|e| {
+            messages::text(
+                "error.failed.create_path",
+                &[("path", &dir.display()), ("error", &e)],
+            )
+        })?;
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| messages::text("error.failed.serialize_plan", &[("error", &e)]))?;
+        fs::write(&path, json).map_err(|e| {
+            messages::text(
+                "error.failed.write_path",
+                &[("path", &path.display()), ("error", &e)],
+            )
+        })
+    }
+
+    fn path(profile_id: &str, direction: Direction) -> Result<PathBuf, String> {
+        let direction = match direction {
+            Direction::Push => "push",
+            Direction::Pull => "pull",
+        };
+        Ok(paths::plans_dir()?.join(format!("{profile_id}.{direction}.json")))
+    }
+}
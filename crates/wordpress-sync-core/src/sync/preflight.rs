@@ -0,0 +1,521 @@
+//! Connection tests: a checklist of everything a sync needs from the server,
+//! run before the first sync so a problem shows up as one red line instead of a
+//! phase failing halfway through.
+//!
+//! Nothing is changed on either side. A check that depends on one that failed
+//! is skipped rather than reported as a second failure. Limits that only
+//! bite on a large sync, such as the free inodes on a shared host, are
+//! warnings rather than failures.
+
+use serde::Serialize;
+
+use super::endpoint::Endpoint;
+use super::environment::read_wp_config;
+use super::mysql::{Client, Mysql};
+use super::remote::Remote;
+use super::{connect_ssh, db, local, rest, transfer, ProgressSink};
+use crate::messages;
+use crate::profile::wp_config::WP_CONFIG;
+use crate::profile::{DatabaseAccess, Profile, TransferMethod};
+use crate::shell;
+use crate::telemetry::{self, Feature};
+
+/// Fewer free inodes than this on the server's file system is a warning:
+/// each file copied there takes one.
+const LOW_INODES: u64 = 100_000;
+
+/// Fewer open files than this a process on the server may have is a warning.
+const LOW_OPEN_FILES: u64 = 1024;
+
+/// Processes besides the transfers and imports a sync may need on the server
+/// at once, for its wp-cli and shell commands.
+const SPARE_PROCESSES: u64 = 8;
+
+/// What a [`Check`] looks at, for the frontend to label and match on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckKind {
+    /// The server accepts an SSH (or, for FTP profiles, FTP) login.
+    Connection,
+    /// wp-cli runs on the server. Skipped rather than failed for a server
+    /// whose database can be reached directly without it.
+    WpCli,
+    /// rsync is installed on both machines, for profiles that transfer with it.
+    Rsync,
+    /// The profile's remote path is a WordPress root.
+    WordpressPath,
+    /// wp-cli, or a direct connection through a tunnel, can reach the remote
+    /// site's database.
+    Database,
+    /// The remote WordPress root and `wp-content` are writable.
+    WritePermissions,
+    /// The server's free inodes and its limits on open files and processes,
+    /// which a large uploads library on shared hosting can run out of.
+    ServerLimits,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Passed,
+    Failed,
+    /// Not run, because an earlier check failed or the profile doesn't need it.
+    Skipped,
+    /// Passed, but with something to look into, such as low disk space or
+    /// few free inodes on the server.
+    Warning,
+}
+
+/// The outcome of one check.
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub check: CheckKind,
+    pub status: CheckStatus,
+    /// What was found, e.g. a version, or why the check failed or was skipped.
+    pub detail: String,
+}
+
+/// Every check run for a profile, in the order they ran.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionTest {
+    pub profile_id: String,
+    /// Whether no check failed.
+    pub passed: bool,
+    pub checks: Vec<Check>,
+}
+
+/// Check that `profile`'s server is ready to sync with. An untrusted host key
+/// is announced to `sink` just as it is for a real sync, and fails the
+/// connection check.
+pub async fn test_connection(profile: &Profile, sink: &dyn ProgressSink) -> ConnectionTest {
+    telemetry::record(Feature::ConnectionTest);
+    let mut checks = Checklist::default();
+    if profile.remote.transfer.uses_ssh() {
+        match connect_ssh(profile, sink).await {
+            Ok(remote) => {
+                checks.passed(
+                    CheckKind::Connection,
+                    messages::text(
+                        "preflight.connected_over_ssh",
+                        &[("destination", &remote.destination())],
+                    ),
+                );
+                check_server(profile, &remote, &mut checks).await;
+                remote.close().await;
+            }
+            Err(e) => {
+                checks.failed(CheckKind::Connection, e.message());
+                checks.skip_rest(&messages::text("preflight.needs_connection", &[]));
+            }
+        }
+    } else if profile.remote.transfer == TransferMethod::RestApi {
+        let login = rest::log_in(profile).await.map(|name| {
+            messages::text(
+                "preflight.logged_in_over_rest_api",
+                &[("url", &profile.remote.url), ("name", &name)],
+            )
+        });
+        let connected = checks.record(CheckKind::Connection, login);
+        checks.skip_rest(&messages::text(
+            match connected {
+                true => "preflight.rest_api_has_no_ssh",
+                false => "preflight.needs_connection",
+            },
+            &[],
+        ));
+    } else {
+        let login = transfer::open(profile, None).await.map(|_| {
+            messages::text(
+                "preflight.logged_in_over_ftp",
+                &[("host", &profile.remote.host)],
+            )
+        });
+        let connected = checks.record(CheckKind::Connection, login);
+        checks.skip_rest(&messages::text(
+            match connected {
+                true => "error.no_ssh",
+                false => "preflight.needs_connection",
+            },
+            &[],
+        ));
+    }
+    checks.finish(profile)
+}
+
+/// The checks that run commands on the server.
+async fn check_server(profile: &Profile, remote: &Remote, checks: &mut Checklist) {
+    let server = Endpoint::Remote {
+        remote: Some(remote),
+        env: &profile.remote,
+    };
+
+    let wp_cli = server.wp(&["cli", "version"]).await.map(|version| {
+        // Running it found the PHP it runs with.
+        match remote.found_php() {
+            Some(php) => messages::text(
+                "preflight.wp_cli_with_php",
+                &[("version", &version.trim()), ("php", &php)],
+            ),
+            None => version.trim().to_string(),
+        }
+    });
+    let route = database_route(profile, remote).await;
+    let has_wp_cli = match (wp_cli, &route) {
+        (Ok(version), _) => {
+            checks.passed(CheckKind::WpCli, version);
+            true
+        }
+        // The database can do without it, so its own check decides.
+        (Err(e), Ok(Some(_))) => {
+            checks.skipped(
+                CheckKind::WpCli,
+                messages::text("preflight.wp_cli_missing_database_direct", &[("error", &e)]),
+            );
+            false
+        }
+        (Err(e), _) if profile.remote.wp_cli.is_none() => {
+            checks.failed(
+                CheckKind::WpCli,
+                messages::text("preflight.wp_cli_can_be_installed", &[("error", &e)]),
+            );
+            false
+        }
+        (Err(e), _) => {
+            checks.failed(CheckKind::WpCli, e);
+            false
+        }
+    };
+
+    if profile.remote.transfer == TransferMethod::Rsync {
+        let rsync = rsync_versions(profile, remote).await;
+        checks.record(CheckKind::Rsync, rsync);
+    } else {
+        checks.skipped(
+            CheckKind::Rsync,
+            messages::text(
+                "preflight.rsync_not_needed",
+                &[("method", &format_args!("{:?}", profile.remote.transfer))],
+            ),
+        );
+    }
+
+    let is_wordpress = if has_wp_cli {
+        let version = server.wp(&["core", "version"]).await.map(|version| {
+            messages::text(
+                "preflight.wordpress_version_in",
+                &[("version", &version.trim()), ("root", &server.root())],
+            )
+        });
+        checks.record(CheckKind::WordpressPath, version)
+    } else if matches!(route, Ok(Some(_))) {
+        let config = read_wp_config(remote, &profile.remote.path).await.map(|_| {
+            messages::text(
+                "preflight.wp_config_found",
+                &[("file", &WP_CONFIG), ("root", &server.root())],
+            )
+        });
+        checks.record(CheckKind::WordpressPath, config)
+    } else {
+        checks.skipped(
+            CheckKind::WordpressPath,
+            messages::text("preflight.needs_wp_cli", &[]),
+        );
+        false
+    };
+
+    if is_wordpress {
+        let tables = match route {
+            Ok(None) => db::tables(&server).await.map(|tables| {
+                messages::text("preflight.database_tables", &[("count", &tables.len())])
+            }),
+            Ok(Some(route)) => direct_tables(profile, remote, route).await,
+            Err(e) => Err(e),
+        };
+        checks.record(CheckKind::Database, tables);
+    } else {
+        checks.skipped(
+            CheckKind::Database,
+            messages::text("preflight.needs_wordpress", &[]),
+        );
+    }
+
+    let writable = writable(remote, &server, profile.project.content_dir()).await;
+    checks.record(CheckKind::WritePermissions, writable);
+
+    match server_limits(profile, remote, &server.root()).await {
+        Ok((found, problems)) if problems.is_empty() => {
+            checks.passed(CheckKind::ServerLimits, found)
+        }
+        Ok((_, problems)) => checks.warned(CheckKind::ServerLimits, problems.join("; ")),
+        Err(e) => checks.skipped(
+            CheckKind::ServerLimits,
+            messages::text("preflight.server_limits_unread", &[("error", &e)]),
+        ),
+    }
+}
+
+/// How a sync reaches the server's database when it doesn't leave it all to
+/// wp-cli on the server.
+pub(super) struct DatabaseRoute {
+    pub(super) client: Client,
+    /// Whether the server has wp-cli for everything but dumping and importing.
+    pub(super) wp_cli: bool,
+}
+
+/// Choose how a sync reaches `profile`'s database over `remote`: `None` for
+/// wp-cli on the server, whose exports need mysqldump there too. A profile
+/// that tunnels to its database, or a server missing either program, goes
+/// through a tunnel instead, with this machine's `mysqldump` and `mysql` if it
+/// has them and the built-in client if not.
+pub(super) async fn database_route(
+    profile: &Profile,
+    remote: &Remote,
+) -> Result<Option<DatabaseRoute>, String> {
+    // MariaDB servers may only have its own name for mysqldump, which wp-cli also uses.
+    let wp = shell::quote(profile.remote.wp_cli.as_deref().unwrap_or("wp"));
+    let found = remote
+        .exec(&format!(
+            "command -v {wp} >/dev/null && echo wp; \
+             {{ command -v mysqldump || command -v mariadb-dump; }} >/dev/null && echo dump; true",
+        ))
+        .await?;
+    let has = |tool: &str| found.lines().any(|line| line.trim() == tool);
+    let wp_cli = has("wp");
+    if profile.remote.database_access == DatabaseAccess::WpCli && wp_cli && has("dump") {
+        return Ok(None);
+    }
+    let client = if has_client_programs().await {
+        Client::Programs
+    } else {
+        Client::Native
+    };
+    tracing::info!(wp_cli, ?client, "reaching the server's database directly");
+    Ok(Some(DatabaseRoute { client, wp_cli }))
+}
+
+/// Whether `mysqldump` and `mysql` are installed on this machine.
+async fn has_client_programs() -> bool {
+    for program in ["mysqldump", "mysql"] {
+        let mut cmd = local::command(program);
+        cmd.arg("--version");
+        if local::output(cmd, program).await.is_err() {
+            return false;
+        }
+    }
+    true
+}
+
+/// The database check for a server whose database is reached directly.
+async fn direct_tables(
+    profile: &Profile,
+    remote: &Remote,
+    route: DatabaseRoute,
+) -> Result<String, String> {
+    let id = match route.client {
+        Client::Programs => "preflight.database_tunnel_mysql",
+        Client::Native => "preflight.database_tunnel_native",
+    };
+    let mysql = Mysql::open(profile, remote, route.client, !route.wp_cli).await?;
+    let tables = mysql.tables().await?;
+    Ok(messages::text(id, &[("count", &tables.len())]))
+}
+
+/// The rsync versions here and on the server, or which of them is missing,
+/// and what the older of them keeps transfers from doing.
+async fn rsync_versions(profile: &Profile, remote: &Remote) -> Result<String, String> {
+    let binaries = &profile.rsync;
+    let ((program, here), there) = tokio::join!(
+        transfer::local_rsync(binaries.local_path.as_deref()),
+        transfer::remote_rsync(remote, binaries.remote_path.as_deref()),
+    );
+    let here = here.ok_or_else(|| match &binaries.local_path {
+        Some(path) => messages::text(
+            "preflight.local_rsync_didnt_run",
+            &[("path", &path.display())],
+        ),
+        None => messages::text("preflight.local_rsync_missing", &[]),
+    })?;
+    let there = there.ok_or_else(|| match &binaries.remote_path {
+        Some(path) => messages::text("error.remote_rsync_missing", &[("program", &path)]),
+        None => messages::text("preflight.remote_rsync_missing", &[]),
+    })?;
+    let capabilities = transfer::Capabilities::of(Some(here), Some(there));
+    let id = match (capabilities.progress2, capabilities.protect_args) {
+        (true, true) => "preflight.rsync_versions",
+        (false, true) => "preflight.rsync_versions_without_progress",
+        (true, false) => "preflight.rsync_versions_without_protected_paths",
+        (false, false) => "preflight.rsync_versions_without_either",
+    };
+    Ok(messages::text(
+        id,
+        &[
+            ("here", &here),
+            ("program", &program.display()),
+            ("there", &there),
+        ],
+    ))
+}
+
+async fn writable(
+    remote: &Remote,
+    server: &Endpoint<'_>,
+    content_dir: &str,
+) -> Result<String, String> {
+    let root = server.root();
+    let content = server.path(content_dir);
+    for dir in [&root, &content] {
+        let quoted = shell::quote(dir);
+        let check = format!("test -d {quoted} && test -w {quoted}");
+        if remote.exec(&check).await.is_err() {
+            return Err(messages::text(
+                "error.dir_missing_not_writable_destination",
+                &[("dir", &dir), ("destination", &remote.destination())],
+            ));
+        }
+    }
+    Ok(messages::text(
+        "preflight.dirs_writable",
+        &[("root", &root), ("content_dir", &content_dir)],
+    ))
+}
+
+/// What the server allows at `root`, and what of it a large sync may run out
+/// of, with what to do about it.
+async fn server_limits(
+    profile: &Profile,
+    remote: &Remote,
+    root: &str,
+) -> Result<(String, Vec<String>), String> {
+    // `df -i` isn't POSIX, but GNU, BSD, and BusyBox all have it; `ulimit -u`
+    // is missing from some shells, which then say nothing.
+    let line = format!(
+        "p={}; while [ ! -e \"$p\" ]; do p=$(dirname \"$p\"); done; \
+         echo inodes $(df -Pi \"$p\" 2>/dev/null | tail -n 1); \
+         echo files $(ulimit -n 2>/dev/null); echo processes $(ulimit -u 2>/dev/null)",
+        shell::quote(root)
+    );
+    let output = remote.exec(&line).await?;
+    let value = |label: &str| {
+        output
+            .lines()
+            .find_map(|line| line.strip_prefix(label)?.strip_prefix(' '))
+            .map(|rest| rest.split_whitespace().collect::<Vec<_>>())
+            .unwrap_or_default()
+    };
+    // `Some(None)` for a limit that is `unlimited`, and `None` for one the
+    // shell didn't give.
+    let limit = |label: &str| value(label).first().map(|n| n.parse::<u64>().ok());
+    let (mut found, mut problems) = (Vec::new(), Vec::new());
+
+    // Filesystem, Inodes, IUsed, IFree, IUse%, Mounted on
+    let inodes = value("inodes");
+    match (inodes.get(1), inodes.get(3)) {
+        (Some(&"0"), _) => found.push(messages::text("preflight.no_inode_limit", &[])),
+        (Some(_), Some(free)) => match free.parse::<u64>() {
+            Ok(free) if free < LOW_INODES => problems.push(messages::text(
+                "preflight.few_inodes_free",
+                &[("free", &free), ("root", &root)],
+            )),
+            Ok(free) => found.push(messages::text("preflight.inodes_free", &[("free", &free)])),
+            Err(_) => found.push(messages::text("preflight.no_inode_count", &[])),
+        },
+        _ => found.push(messages::text("preflight.no_inode_count", &[])),
+    }
+
+    match limit("files") {
+        Some(Some(files)) if files < LOW_OPEN_FILES => problems.push(messages::text(
+            "preflight.few_open_files",
+            &[("files", &files)],
+        )),
+        Some(Some(files)) => {
+            found.push(messages::text("preflight.open_files", &[("files", &files)]))
+        }
+        Some(None) => found.push(messages::text("preflight.no_open_file_limit", &[])),
+        None => {}
+    }
+
+    let wanted = u64::from(profile.remote.parallel_transfers.max(1))
+        + u64::from(profile.import_workers.max(1))
+        + SPARE_PROCESSES;
+    match limit("processes") {
+        Some(Some(processes)) if processes < wanted => problems.push(messages::text(
+            "preflight.few_processes",
+            &[("processes", &processes), ("wanted", &wanted)],
+        )),
+        Some(Some(processes)) => found.push(messages::text(
+            "preflight.processes",
+            &[("processes", &processes)],
+        )),
+        Some(None) => found.push(messages::text("preflight.no_process_limit", &[])),
+        None => {}
+    }
+    Ok((found.join(", "), problems))
+}
+
+#[derive(Default)]
+struct Checklist(Vec<Check>);
+
+impl Checklist {
+    /// Add the result of a check, returning whether it passed.
+    fn record(&mut self, check: CheckKind, result: Result<String, String>) -> bool {
+        match result {
+            Ok(detail) => {
+                self.passed(check, detail);
+                true
+            }
+            Err(detail) => {
+                self.failed(check, detail);
+                false
+            }
+        }
+    }
+
+    fn passed(&mut self, check: CheckKind, detail: impl Into<String>) {
+        self.push(check, CheckStatus::Passed, detail);
+    }
+
+    fn failed(&mut self, check: CheckKind, detail: impl Into<String>) {
+        self.push(check, CheckStatus::Failed, detail);
+    }
+
+    fn skipped(&mut self, check: CheckKind, detail: impl Into<String>) {
+        self.push(check, CheckStatus::Skipped, detail);
+    }
+
+    fn warned(&mut self, check: CheckKind, detail: impl Into<String>) {
+        self.push(check, CheckStatus::Warning, detail);
+    }
+
+    /// Skip every check after the connection.
+    fn skip_rest(&mut self, reason: &str) {
+        for check in [
+            CheckKind::WpCli,
+            CheckKind::Rsync,
+            CheckKind::WordpressPath,
+            CheckKind::Database,
+            CheckKind::WritePermissions,
+            CheckKind::ServerLimits,
+        ] {
+            self.skipped(check, reason);
+        }
+    }
+
+    fn push(&mut self, check: CheckKind, status: CheckStatus, detail: impl Into<String>) {
+        self.0.push(Check {
+            check,
+            status,
+            detail: detail.into(),
+        });
+    }
+
+    fn finish(self, profile: &Profile) -> ConnectionTest {
+        ConnectionTest {
+            profile_id: profile.id.clone(),
+            passed: self
+                .0
+                .iter()
+                .all(|check| check.status != CheckStatus::Failed),
+            checks: self.0,
+        }
+    }
+}
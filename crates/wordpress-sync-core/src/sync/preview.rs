@@ -0,0 +1,207 @@
+//! Previews of the search-replace: what each from→to pair would change in the
+//! source database, with a few of the values it changes, so a URL mapping that
+//! misses (or hits too much) shows up before anything is imported.
+//!
+//! The source database is dumped as a sync would dump it, and the dump is
+//! scanned with the sync's own [`Replacer`], so pairs take precedence as they
+//! will in the sync. Nothing is written on either side; the dump is deleted
+//! afterwards.
+
+use std::time::Instant;
+
+use serde::Serialize;
+
+use super::db::{self, SKIP_COLUMNS};
+use super::progress::Reporter;
+use super::{connect, Components, Direction, Job, Phase, ProgressSink, TransferStats};
+use crate::error::SyncError;
+use crate::profile::Profile;
+use crate::search_replace::{self, Replacer};
+use crate::telemetry::{self, Feature};
+
+/// Values kept as samples for each pair.
+const SAMPLES: usize = 5;
+
+/// Bytes of a sampled value shown on each side of the match.
+const CONTEXT: usize = 60;
+
+/// What the search-replace of a sync in one direction would change.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplacementPreview {
+    pub profile_id: String,
+    pub direction: Direction,
+    /// In the order they take precedence.
+    pub replacements: Vec<ReplacementMatches>,
+}
+
+/// What one pair would change.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplacementMatches {
+    pub from: String,
+    pub to: String,
+    /// Values it changes in the dump.
+    pub occurrences: u64,
+    /// The first few of them, in dump order.
+    pub samples: Vec<ReplacementSample>,
+}
+
+/// Part of one value a pair changes, before and after every pair is applied.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplacementSample {
+    pub table: String,
+    pub column: String,
+    /// The text around the pair's first match, cut at [`CONTEXT`] bytes either side.
+    pub before: String,
+    pub after: String,
+}
+
+/// Connect to the server, dump the source database, and preview the
+/// replacements a sync of `profile` in `direction` would make in it.
+///
+/// An untrusted host key is announced to `sink` just as it is for a real
+/// sync, as is the dump's progress.
+pub async fn preview_replacements(
+    profile: &Profile,
+    direction: Direction,
+    sink: &dyn ProgressSink,
+) -> Result<ReplacementPreview, SyncError> {
+    telemetry::record(Feature::ReplacementPreview);
+    let components = Components::Database;
+    components.check(profile)?;
+    let (remote, transfer, _) = connect(profile, sink).await?;
+    let reporter = Reporter::new(uuid::Uuid::new_v4().to_string(), sink);
+    let job = Job::new(profile, direction, components, remote, transfer, reporter);
+    let result = job.preview_replacements().await;
+    if let Some(remote) = &job.remote {
+        remote.close().await;
+    }
+    result.map_err(SyncError::database)
+}
+
+impl Job<'_> {
+    async fn preview_replacements(&self) -> Result<ReplacementPreview, String> {
+        self.detect_network().await?;
+        let pairs = self.replacements();
+        let dump = self.dump_path();
+        let scanned = async {
+            self.dump_for_preview().await?;
+            scan(&dump, &pairs).await
+        }
+        .await;
+        if let Err(e) = tokio::fs::remove_file(&dump).await {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!(error = %e, path = %dump.display(), "failed to remove the preview's dump");
+            }
+        }
+        Ok(ReplacementPreview {
+            profile_id: self.profile.id.clone(),
+            direction: self.direction,
+            replacements: scanned?,
+        })
+    }
+
+    /// Dump the source's selected tables to the job's dump path, as the
+    /// sync's dump phase does.
+    async fn dump_for_preview(&self) -> Result<(), String> {
+        let source = self.source();
+        let start = Instant::now();
+        let mut on_progress = |stats: &TransferStats| {
+            self.reporter
+                .report(Phase::DumpDatabase, stats, start, false);
+        };
+        let selection = self.table_selection(&source).await?;
+        let filters = self.row_filters(&source).await?;
+        let dump = self.dump_path();
+        match self.direct_database(&source).await? {
+            Some(mysql) => {
+                mysql
+                    .export(&selection, &filters, &dump, &mut on_progress)
+                    .await
+            }
+            None => {
+                db::export(
+                    &source,
+                    &selection,
+                    &filters,
+                    &dump,
+                    self.profile.compression,
+                    &mut on_progress,
+                )
+                .await
+            }
+        }
+    }
+}
+
+/// Count and sample what each of `pairs` changes in the dump at `path`.
+async fn scan(
+    path: &std::path::Path,
+    pairs: &[(String, String)],
+) -> Result<Vec<ReplacementMatches>, String> {
+    let replacer = Replacer::new(pairs.iter().map(|(from, to)| (from, to)));
+    let mut found: Vec<ReplacementMatches> = pairs
+        .iter()
+        .map(|(from, to)| ReplacementMatches {
+            from: from.clone(),
+            to: to.clone(),
+            occurrences: 0,
+            samples: Vec::new(),
+        })
+        .collect();
+    search_replace::scan_dump(path, |table, column, value| {
+        if SKIP_COLUMNS.iter().any(|skip| skip.as_bytes() == column) {
+            return;
+        }
+        // Only values the sync would change, as it parses them.
+        if replacer.value(value).is_none() {
+            return;
+        }
+        for (pair, at) in replacer.matches(value) {
+            let Some(matches) = found.get_mut(pair) else {
+                continue;
+            };
+            matches.occurrences += 1;
+            if matches.samples.len() < SAMPLES {
+                let end = at + matches.from.len();
+                let before = excerpt(value, at, end);
+                // Where the match went in the replaced value is lost, so the
+                // excerpt is replaced on its own.
+                let after = replacer
+                    .value(before.as_bytes())
+                    .map(|after| String::from_utf8_lossy(&after).into_owned())
+                    .unwrap_or_else(|| before.clone());
+                matches.samples.push(ReplacementSample {
+                    table: String::from_utf8_lossy(table).into_owned(),
+                    column: String::from_utf8_lossy(column).into_owned(),
+                    before,
+                    after,
+                });
+            }
+        }
+    })
+    .await?;
+    Ok(found)
+}
+
+/// `value[start..end]` with up to [`CONTEXT`] bytes either side, widened to
+/// whole UTF-8 characters, with `…` where it was cut.
+fn excerpt(value: &[u8], start: usize, end: usize) -> String {
+    let boundary = |i: usize| i >= value.len() || (value[i] & 0xc0) != 0x80;
+    let mut from = start.saturating_sub(CONTEXT);
+    while !boundary(from) {
+        from -= 1;
+    }
+    let mut to = (end + CONTEXT).min(value.len());
+    while !boundary(to) {
+        to += 1;
+    }
+    let mut excerpt = String::new();
+    if from > 0 {
+        excerpt.push('…');
+    }
+    excerpt.push_str(&String::from_utf8_lossy(&value[from..to]));
+    if to < value.len() {
+        excerpt.push('…');
+    }
+    excerpt
+}
@@ -0,0 +1,137 @@
+//! Progress reporting from the sync engine.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use super::events::{self, JobEvent};
+use super::queue::QueuedSync;
+use super::retry::SyncRetry;
+use super::transfer::TransferStats;
+use super::watch::WatchEvent;
+use super::Phase;
+use crate::history::HistoryEntry;
+use crate::messages::Message;
+use crate::ssh::{AuthPrompt, UnverifiedHostKey};
+
+/// Minimum gap between progress updates within a phase, so a transfer of many
+/// small files doesn't flood the frontend with events.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// A snapshot of how far a job has got, sent whenever something changes.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncProgress {
+    pub job_id: String,
+    pub phase: Phase,
+    /// Files transferred so far in the current phase.
+    pub files_done: u64,
+    /// Total files the current phase will transfer, once rsync knows.
+    pub files_total: Option<u64>,
+    pub bytes_transferred: u64,
+    pub current_file: Option<String>,
+    /// Estimated seconds until the current phase completes.
+    pub eta_seconds: Option<u64>,
+}
+
+/// Receives progress updates as a job runs.
+///
+/// Implementations are called from the engine's task and should return quickly.
+pub trait ProgressSink: Send + Sync {
+    fn progress(&self, update: &SyncProgress);
+
+    /// The server presented a host key that isn't trusted yet. The job then fails;
+    /// the user can accept the key with `ssh::host_keys::confirm` and retry.
+    fn host_key(&self, _key: &UnverifiedHostKey) {}
+
+    /// The server asks questions before it lets the job log in, such as for
+    /// a one-time code. Returns whether they have been put to the user, whose
+    /// answers come back through `ssh::answer_auth_prompt`; if not, the login
+    /// fails.
+    fn auth_prompt(&self, _prompt: &AuthPrompt) -> bool {
+        false
+    }
+
+    /// The [`JobQueue`](super::JobQueue) changed: an entry started or finished.
+    fn queue(&self, _entries: &[QueuedSync]) {}
+
+    /// A phase failed with what looks like a passing problem, and the job will
+    /// reconnect and try it again after `retry.delay_ms`.
+    fn retry(&self, _retry: &SyncRetry) {}
+
+    /// A watch pushed a batch of changed files, or stopped.
+    fn watch(&self, _event: &WatchEvent) {}
+
+    /// A job has finished, whether or not it succeeded, and is being recorded
+    /// in the history as `entry`.
+    fn finished(&self, _entry: &HistoryEntry) {}
+
+    /// Something happened to a job, told in the versioned schema of
+    /// [`JobEvent`] for tooling rather than the UI.
+    fn event(&self, _event: &JobEvent) {}
+}
+
+/// Turns engine state into rate-limited [`SyncProgress`] updates for one job.
+pub struct Reporter<'a> {
+    job_id: String,
+    sink: &'a dyn ProgressSink,
+    last_report: Mutex<Option<Instant>>,
+    /// The phase of the last update.
+    phase: Mutex<Option<Phase>>,
+}
+
+impl<'a> Reporter<'a> {
+    pub fn new(job_id: String, sink: &'a dyn ProgressSink) -> Self {
+        Self {
+            job_id,
+            sink,
+            last_report: Mutex::new(None),
+            phase: Mutex::new(None),
+        }
+    }
+
+    pub fn job_id(&self) -> &str {
+        &self.job_id
+    }
+
+    /// Send a progress update for `phase`, which began at `phase_start`.
+    ///
+    /// Updates are rate-limited to one per [`PROGRESS_INTERVAL`] unless `force` is set,
+    /// which phase boundaries use so the frontend never misses a transition.
+    pub fn report(&self, phase: Phase, stats: &TransferStats, phase_start: Instant, force: bool) {
+        {
+            let mut last = self.last_report.lock().unwrap();
+            if !force && last.is_some_and(|t| t.elapsed() < PROGRESS_INTERVAL) {
+                return;
+            }
+            *last = Some(Instant::now());
+        }
+        if self.phase.lock().unwrap().replace(phase) != Some(phase) {
+            events::emit(self.sink, JobEvent::phase_started(&self.job_id, phase));
+        }
+        let eta_seconds = match stats.files_total {
+            Some(total) if stats.files_done > 0 => {
+                let elapsed = phase_start.elapsed().as_secs_f64();
+                let remaining = total.saturating_sub(stats.files_done) as f64;
+                Some((elapsed * remaining / stats.files_done as f64).round() as u64)
+            }
+            _ => None,
+        };
+        let update = SyncProgress {
+            job_id: self.job_id.clone(),
+            phase,
+            files_done: stats.files_done,
+            files_total: stats.files_total,
+            bytes_transferred: stats.bytes_transferred,
+            current_file: stats.current_file.clone(),
+            eta_seconds,
+        };
+        self.sink.progress(&update);
+        events::emit(self.sink, JobEvent::progress(&update));
+    }
+
+    /// Tell the sink of a problem that doesn't fail the job.
+    pub fn warning(&self, message: &Message) {
+        events::emit(self.sink, JobEvent::warning(&self.job_id, message));
+    }
+}
@@ -0,0 +1,233 @@
+//! Cloning a profile's server into a new local environment: an empty
+//! directory made into a working copy of the site in one job.
+//!
+//! WordPress core is downloaded at the server's version, a `wp-config.php`
+//! is written from a template with fresh salts, and its database created;
+//! then a new profile for the copy is saved and pulled, which brings the
+//! content and the database, with the server's URL replaced by the new one.
+//!
+//! The template is `~/.wordpress-sync/wp-config-template.php` if there is
+//! one, or else a plain `wp-config.php`. Its `{{db_name}}`, `{{db_user}}`,
+//! `{{db_password}}`, `{{db_host}}`, `{{table_prefix}}`, `{{site_url}}`, and
+//! `{{salts}}` are filled in.
+//!
+//! As hardening, the copy's tables can be given a random prefix in place of
+//! the one asked for: it's written into `wp-config.php`, and the pull's dump
+//! rewrite renames the tables, and the option and user meta keys named after
+//! the prefix, to it.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::endpoint::Endpoint;
+use super::salts::{salt, SALTS};
+use super::{connect_ssh, run, Components, Direction, JobHandle, ProgressSink, SyncSummary};
+use crate::error::SyncError;
+use crate::messages::{self, Message};
+use crate::paths;
+use crate::profile::wp_config::WP_CONFIG;
+use crate::profile::{self, DatabaseSettings, LocalEnvironment, Profile};
+use crate::telemetry::{self, Feature};
+
+/// The template used without one of the user's.
+const DEFAULT_TEMPLATE: &str = "<?php
+define( 'DB_NAME', '{{db_name}}' );
+define( 'DB_USER', '{{db_user}}' );
+define( 'DB_PASSWORD', '{{db_password}}' );
+define( 'DB_HOST', '{{db_host}}' );
+define( 'DB_CHARSET', 'utf8mb4' );
+define( 'DB_COLLATE', '' );
+
+{{salts}}
+
+$table_prefix = '{{table_prefix}}';
+
+define( 'WP_HOME', '{{site_url}}' );
+define( 'WP_SITEURL', '{{site_url}}' );
+define( 'WP_DEBUG', false );
+
+if ( ! defined( 'ABSPATH' ) ) {
+	define( 'ABSPATH', __DIR__ . '/' );
+}
+
+require_once ABSPATH . 'wp-settings.php';
+";
+
+/// Where and how the copy is made.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CloneRequest {
+    /// The profile whose server is cloned.
+    pub profile_id: String,
+    /// The new profile's name.
+    pub name: String,
+    /// An empty or missing directory for the copy's WordPress root.
+    pub path: PathBuf,
+    /// The copy's URL, which replaces the server's in its database.
+    pub url: String,
+    /// The database to create for the copy.
+    pub database: DatabaseSettings,
+    #[serde(default)]
+    pub db_password: String,
+    /// Give the copy's tables a random prefix, such as `wp_3f9a1c_`, instead
+    /// of the database's `table_prefix`.
+    #[serde(default)]
+    pub random_table_prefix: bool,
+}
+
+/// The copy's profile, saved, and the pull that filled it.
+#[derive(Debug, Clone, Serialize)]
+pub struct CloneResult {
+    pub profile: Profile,
+    pub summary: SyncSummary,
+}
+
+/// Make the empty directory in `request` a working copy of its profile's
+/// server, and save a profile for it. The job reports to `sink` and is
+/// cancelled through `handle` as a pull, which, if it fails, is resumed like
+/// any other once the profile is saved.
+pub async fn clone_site(
+    request: CloneRequest,
+    handle: &JobHandle,
+    sink: &dyn ProgressSink,
+) -> Result<CloneResult, SyncError> {
+    telemetry::record(Feature::CloneSite);
+    let source = profile::load(&request.profile_id).map_err(SyncError::config)?;
+    if !source.remote.transfer.uses_ssh() {
+        return Err(SyncError::Config(Message::new(
+            "error.no_ssh.commands",
+            &[],
+        )));
+    }
+    let path = request.path.clone();
+    check_empty(&path).map_err(SyncError::config)?;
+
+    let remote = connect_ssh(&source, sink).await?;
+    let version = Endpoint::Remote {
+        remote: Some(&remote),
+        env: &source.remote,
+    }
+    .wp(&["core", "version"])
+    .await;
+    remote.close().await;
+    let version = version.map_err(SyncError::remote_command)?;
+    let version = version.trim();
+
+    let local = LocalEnvironment {
+        path: path.clone(),
+        url: request.url.trim().trim_end_matches('/').to_string(),
+        database: None,
+        container: None,
+    };
+    fs::create_dir_all(&path).map_err(|e| {
+        SyncError::Config(Message::new(
+            "error.failed.create_path",
+            &[("path", &path.display()), ("error", &e)],
+        ))
+    })?;
+    let site = Endpoint::Local(&local);
+    tracing::info!(version, path = %path.display(), "downloading WordPress for a clone");
+    site.wp(&[
+        "core",
+        "download",
+        &format!("--version={version}"),
+        "--skip-content",
+    ])
+    .await
+    .map_err(SyncError::remote_command)?;
+    let mut request = request;
+    if request.random_table_prefix {
+        request.database.table_prefix = random_prefix();
+    }
+    let config = render(&template()?, &request, &local.url);
+    let config_path = path.join(WP_CONFIG);
+    fs::write(&config_path, config).map_err(|e| {
+        SyncError::Config(Message::new(
+            "error.failed.write_path",
+            &[("path", &config_path.display()), ("error", &e)],
+        ))
+    })?;
+    site.wp(&["db", "create"])
+        .await
+        .map_err(SyncError::database)?;
+
+    let mut copy = source.clone();
+    copy.id = String::new();
+    copy.name = request.name.trim().to_string();
+    copy.local = local;
+    copy.remap_table_prefix |= request.random_table_prefix;
+    let copy = profile::save(copy).map_err(SyncError::config)?;
+    tracing::info!(profile = %copy.id, from = %source.id, "saved a cloned site's profile");
+
+    // There's nothing of the copy's own to back up yet.
+    let mut first = copy.clone();
+    first.backup_before_sync = false;
+    let summary = run(&first, Direction::Pull, Components::All, handle, sink).await?;
+    Ok(CloneResult {
+        profile: copy,
+        summary,
+    })
+}
+
+fn check_empty(path: &Path) -> Result<(), String> {
+    match fs::read_dir(path) {
+        Ok(mut entries) => match entries.next() {
+            Some(_) => Err(messages::text(
+                "error.path_isnt_empty",
+                &[("path", &path.display())],
+            )),
+            None => Ok(()),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(messages::text(
+            "error.failed.read_path",
+            &[("path", &path.display()), ("error", &e)],
+        )),
+    }
+}
+
+/// The user's template, or the built-in one.
+fn template() -> Result<String, SyncError> {
+    let path = paths::wp_config_template().map_err(SyncError::config)?;
+    match fs::read_to_string(&path) {
+        Ok(template) => Ok(template),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(DEFAULT_TEMPLATE.to_string()),
+        Err(e) => Err(SyncError::Config(Message::new(
+            "error.failed.read_path",
+            &[("path", &path.display()), ("error", &e)],
+        ))),
+    }
+}
+
+/// `template` with the copy's settings, quoted for PHP's single-quoted strings.
+fn render(template: &str, request: &CloneRequest, url: &str) -> String {
+    let salts: String = SALTS
+        .iter()
+        .map(|name| format!("define( '{name}', '{}' );\n", php_quote(&salt())))
+        .collect();
+    let database = &request.database;
+    [
+        ("db_name", php_quote(&database.name)),
+        ("db_user", php_quote(&database.user)),
+        ("db_password", php_quote(&request.db_password)),
+        ("db_host", php_quote(&database.host)),
+        ("table_prefix", php_quote(&database.table_prefix)),
+        ("site_url", php_quote(url)),
+        ("salts", salts.trim_end().to_string()),
+    ]
+    .iter()
+    .fold(template.to_string(), |text, (name, value)| {
+        text.replace(&format!("{{{{{name}}}}}"), value)
+    })
+}
+
+/// `wp_` and six random hex digits.
+fn random_prefix() -> String {
+    let id = uuid::Uuid::new_v4().simple().to_string();
+    format!("wp_{}_", &id[..6])
+}
+
+fn php_quote(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\'', "\\'")
+}
@@ -0,0 +1,215 @@
+//! Syncs waiting their turn: profiles are enqueued and then run in order, one
+//! at a time or as many at once as the `queue_concurrency` setting allows.
+//! Two syncs of the same profile never run together, as its lock would refuse
+//! the second.
+
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use serde::Serialize;
+use tokio::sync::Notify;
+
+use super::events::{self, JobEvent};
+use super::{run, Components, Direction, JobHandle, JobRegistry, ProgressSink};
+use crate::error::SyncError;
+use crate::messages;
+use crate::profile;
+use crate::settings;
+
+/// Finished entries kept for their status; older ones are dropped.
+const MAX_FINISHED: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+/// One sync in the queue.
+#[derive(Debug, Clone, Serialize)]
+pub struct QueuedSync {
+    /// The queue entry's own id, which `remove` takes.
+    pub id: String,
+    pub profile_id: String,
+    pub profile_name: String,
+    pub direction: Direction,
+    pub components: Components,
+    pub status: QueueStatus,
+    /// The sync's job id once it has started, as in progress events and the history.
+    pub job_id: Option<String>,
+    pub error: Option<SyncError>,
+    pub enqueued_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// The queue, in the order entries were added.
+#[derive(Default)]
+pub struct JobQueue {
+    entries: Mutex<Vec<QueuedSync>>,
+    /// Woken whenever an entry is added, or the concurrency may have changed.
+    added: Notify,
+}
+
+impl JobQueue {
+    /// Add a sync of a saved profile to the end of the queue.
+    pub fn enqueue(
+        &self,
+        profile_id: &str,
+        direction: Direction,
+        components: Components,
+    ) -> Result<QueuedSync, String> {
+        let profile = profile::load(profile_id)?;
+        let entry = QueuedSync {
+            id: uuid::Uuid::new_v4().to_string(),
+            profile_id: profile.id,
+            profile_name: profile.name,
+            direction,
+            components,
+            status: QueueStatus::Queued,
+            job_id: None,
+            error: None,
+            enqueued_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+        };
+        self.entries.lock().unwrap().push(entry.clone());
+        self.added.notify_one();
+        Ok(entry)
+    }
+
+    /// Every entry: finished, running, and waiting, in queue order.
+    pub fn entries(&self) -> Vec<QueuedSync> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    /// Take an entry out of the queue. A running sync is cancelled instead and
+    /// stays listed, as cancelled, once it has stopped.
+    pub fn remove(&self, id: &str, jobs: &JobRegistry) -> Result<(), String> {
+        let mut entries = self.entries.lock().unwrap();
+        let index = entries
+            .iter()
+            .position(|entry| entry.id == id)
+            .ok_or_else(|| messages::text("error.no_queued_sync_id", &[("id", &id)]))?;
+        match (&entries[index].status, &entries[index].job_id) {
+            (QueueStatus::Running, Some(job_id)) => {
+                jobs.cancel(job_id);
+            }
+            _ => {
+                entries.remove(index);
+            }
+        }
+        Ok(())
+    }
+
+    /// Start another sync if the concurrency setting has been raised.
+    pub fn settings_changed(&self) {
+        self.added.notify_one();
+    }
+
+    /// Run queued syncs in turn, for as long as the app runs. Their jobs are
+    /// registered in `jobs`, so they can be cancelled like any other, and
+    /// report to `sink`, which also hears of every change to the queue.
+    pub async fn run(&self, jobs: &JobRegistry, sink: &dyn ProgressSink) {
+        let mut running = FuturesUnordered::new();
+        loop {
+            while running.len() < settings::queue_concurrency() {
+                let Some((entry, handle)) = self.start_next(jobs) else {
+                    break;
+                };
+                sink.queue(&self.entries());
+                events::emit(
+                    sink,
+                    JobEvent::queued(
+                        &handle.id,
+                        &entry.id,
+                        &entry.profile_id,
+                        entry.direction,
+                        entry.components,
+                        entry.enqueued_at,
+                    ),
+                );
+                running.push(self.run_entry(entry, handle, jobs, sink));
+            }
+            if running.is_empty() {
+                self.added.notified().await;
+                continue;
+            }
+            tokio::select! {
+                Some(()) = running.next() => {}
+                () = self.added.notified() => {}
+            }
+        }
+    }
+
+    async fn run_entry(
+        &self,
+        entry: QueuedSync,
+        handle: JobHandle,
+        jobs: &JobRegistry,
+        sink: &dyn ProgressSink,
+    ) {
+        let result = match profile::load(&entry.profile_id) {
+            Ok(profile) => run(&profile, entry.direction, entry.components, &handle, sink)
+                .await
+                .map(drop),
+            Err(e) => Err(SyncError::config(e)),
+        };
+        jobs.finish(&handle.id);
+        self.finish(&entry.id, result);
+        sink.queue(&self.entries());
+    }
+
+    /// Mark the first waiting entry whose profile isn't already syncing as
+    /// running, and register its job.
+    fn start_next(&self, jobs: &JobRegistry) -> Option<(QueuedSync, JobHandle)> {
+        let mut entries = self.entries.lock().unwrap();
+        let busy: Vec<String> = entries
+            .iter()
+            .filter(|entry| entry.status == QueueStatus::Running)
+            .map(|entry| entry.profile_id.clone())
+            .collect();
+        let entry = entries.iter_mut().find(|entry| {
+            entry.status == QueueStatus::Queued && !busy.contains(&entry.profile_id)
+        })?;
+        let handle = jobs.start();
+        entry.status = QueueStatus::Running;
+        entry.started_at = Some(Utc::now());
+        entry.job_id = Some(handle.id.clone());
+        Some((entry.clone(), handle))
+    }
+
+    fn finish(&self, id: &str, result: Result<(), SyncError>) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(entry) = entries.iter_mut().find(|entry| entry.id == id) {
+            entry.finished_at = Some(Utc::now());
+            entry.status = match &result {
+                Ok(()) => QueueStatus::Succeeded,
+                Err(SyncError::Cancelled) => QueueStatus::Cancelled,
+                Err(_) => QueueStatus::Failed,
+            };
+            entry.error = result.err();
+        }
+        let finished = |entry: &QueuedSync| {
+            !matches!(entry.status, QueueStatus::Queued | QueueStatus::Running)
+        };
+        let excess = entries
+            .iter()
+            .filter(|entry| finished(entry))
+            .count()
+            .saturating_sub(MAX_FINISHED);
+        let mut dropped = 0;
+        entries.retain(|entry| {
+            if dropped < excess && finished(entry) {
+                dropped += 1;
+                return false;
+            }
+            true
+        });
+    }
+}
@@ -0,0 +1,179 @@
+//! Flushing a Redis object cache on the destination once a database has been
+//! imported into it, so the site stops serving what it cached of the old one.
+//!
+//! The cache is taken to be Redis if the `object-cache.php` drop-in says so,
+//! as those of Redis Object Cache and Object Cache Pro do. Object Cache Pro's
+//! own `wp redis flush` is tried first; otherwise `redis-cli` flushes the
+//! database the drop-in uses, found from the `WP_REDIS_*` constants in
+//! `wp-config.php` where wp-cli can read them. The whole of that Redis
+//! database is flushed, so sites sharing one lose their cache too.
+
+use super::endpoint::Endpoint;
+use super::{local, Job};
+use crate::messages::{self, Message};
+use crate::shell;
+
+/// The object cache drop-in, relative to the content directory.
+const DROP_IN: &str = "object-cache.php";
+
+/// How much of the drop-in is read to recognise it.
+const DROP_IN_HEAD: usize = 8192;
+
+/// Where the drop-in's Redis is, as `wp-config.php` says or by default.
+struct RedisServer {
+    host: String,
+    port: String,
+    /// A Unix socket, used instead of the host and port.
+    socket: Option<String>,
+    database: String,
+    password: Option<String>,
+}
+
+impl Job<'_> {
+    /// Flush the destination's Redis object cache, if the profile asks for it,
+    /// it has one, and this job imported a database. A failure leaves a
+    /// working (if stale) site, so it's only a warning.
+    pub(super) async fn flush_redis(&self, dest: &Endpoint<'_>) {
+        if !self.profile.post_sync.flush_redis || !self.syncs_database() {
+            return;
+        }
+        let drop_in = format!("{}/{DROP_IN}", self.profile.project.content_dir());
+        match uses_redis(dest, &drop_in).await {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => {
+                tracing::debug!(error = %e, "couldn't read the object cache drop-in");
+                return;
+            }
+        }
+        let wp_cli = self.has_wp_cli(dest).await.unwrap_or(false);
+        if wp_cli && dest.wp(&["redis", "flush"]).await.is_ok() {
+            tracing::info!("flushed the Redis object cache with wp-cli");
+            return;
+        }
+        let server = if wp_cli {
+            server(dest).await
+        } else {
+            RedisServer::default()
+        };
+        match flush(dest, &server).await {
+            Ok(()) => tracing::info!(database = %server.database, "flushed the Redis object cache"),
+            Err(e) => self.warn(Message::new(
+                "warning.redis_object_cache_was_not_flushed",
+                &[("error", &e)],
+            )),
+        }
+    }
+}
+
+impl Default for RedisServer {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            port: "6379".to_string(),
+            socket: None,
+            database: "0".to_string(),
+            password: None,
+        }
+    }
+}
+
+/// Whether `side`'s object cache drop-in, at `drop_in` under the root, is a Redis one.
+async fn uses_redis(side: &Endpoint<'_>, drop_in: &str) -> Result<bool, String> {
+    let path = side.path(drop_in);
+    let head = match side {
+        Endpoint::Local(_) => match tokio::fs::read(&path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => {
+                return Err(messages::text(
+                    "error.failed.read_path",
+                    &[("path", &path), ("error", &e)],
+                ))
+            }
+        },
+        Endpoint::Remote { .. } => {
+            let path = shell::quote(&path);
+            side.session()?
+                .exec(&format!(
+                    "if [ -f {path} ]; then head -c {DROP_IN_HEAD} {path}; fi"
+                ))
+                .await?
+                .into_bytes()
+        }
+    };
+    let head = &head[..head.len().min(DROP_IN_HEAD)];
+    Ok(String::from_utf8_lossy(head)
+        .to_ascii_lowercase()
+        .contains("redis"))
+}
+
+/// The Redis `side`'s `wp-config.php` points the drop-in at.
+async fn server(side: &Endpoint<'_>) -> RedisServer {
+    let mut server = RedisServer::default();
+    let constant = |name: &'static str| async move {
+        side.wp(&["config", "get", name])
+            .await
+            .ok()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+    if let Some(host) = constant("WP_REDIS_HOST").await {
+        server.host = host;
+    }
+    if let Some(port) = constant("WP_REDIS_PORT").await {
+        server.port = port;
+    }
+    if let Some(database) = constant("WP_REDIS_DATABASE").await {
+        server.database = database;
+    }
+    if constant("WP_REDIS_SCHEME").await.as_deref() == Some("unix") {
+        server.socket = constant("WP_REDIS_PATH").await;
+    }
+    server.password = constant("WP_REDIS_PASSWORD").await;
+    server
+}
+
+/// Flush `server`'s database with `redis-cli` on `side`. The password goes
+/// on its stdin with the commands, so it's never on a command line.
+async fn flush(side: &Endpoint<'_>, server: &RedisServer) -> Result<(), String> {
+    let mut args = vec!["redis-cli"];
+    match &server.socket {
+        Some(socket) => args.extend(["-s", socket.as_str()]),
+        None => args.extend(["-h", server.host.as_str(), "-p", server.port.as_str()]),
+    }
+    let mut input = String::new();
+    if let Some(password) = &server.password {
+        input.push_str(&format!("AUTH {}\n", quote(password)));
+    }
+    input.push_str(&format!("SELECT {}\nFLUSHDB\n", quote(&server.database)));
+    let output = match side {
+        Endpoint::Local(env) => {
+            let mut cmd = local::command(args[0]);
+            cmd.args(&args[1..]).current_dir(&env.path);
+            local::output_with_input(cmd, "redis-cli", input.as_bytes()).await?
+        }
+        Endpoint::Remote { .. } => {
+            side.session()?
+                .exec_with_input(&shell::join(args), input.as_bytes())
+                .await?
+        }
+    };
+    // redis-cli exits 0 whatever the server answers.
+    match output
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.eq_ignore_ascii_case("OK"))
+    {
+        Some(reply) => Err(messages::text(
+            "error.redis_answered_reply",
+            &[("reply", &reply)],
+        )),
+        None => Ok(()),
+    }
+}
+
+/// `value` as one argument on a `redis-cli` command line.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
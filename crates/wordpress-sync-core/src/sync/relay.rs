@@ -0,0 +1,797 @@
+//! Syncing one server from another, as when staging is deployed to
+//! production, without pulling the site into the local install first.
+//!
+//! Each server is reached through its own profile. The database always comes
+//! through this machine, as in any sync: it's dumped from the source, its
+//! URLs rewritten to the destination's, and imported once it's all here.
+//! Files go one of two routes. Directly, the source server runs rsync over
+//! SSH to the destination, which needs rsync on both and a login the source
+//! can make by itself, with its own key or the agent its profile forwards.
+//! Relayed, they're pulled into a staging directory here and pushed on from
+//! it, over each profile's own transfer method, which works whenever the
+//! two profiles work on their own.
+//!
+//! The destination is dealt with as a push of its profile would deal with
+//! it. The dump's URLs are rewritten by that profile's replacements, with the
+//! source profile's carried through them. Before the destination changes,
+//! its warning policy is heeded, its hooks and custom steps run, and a
+//! restore point is made; it's in maintenance mode while it's overwritten.
+//! What the files would change is worked out first, for those to go by: the
+//! direct rsync run dry, or the staging directory against the destination.
+
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::audit;
+use super::db;
+use super::endpoint::Endpoint;
+use super::events::{self, JobEvent};
+use super::lock::ProfileLock;
+use super::multisite;
+use super::progress::Reporter;
+use super::remote::Remote;
+use super::resources::{ResourceMonitor, ResourceUsage};
+use super::salts;
+use super::temp::{self, TempDir};
+use super::transfer::{self, Excludes, MirrorOptions, TransferStats};
+use super::{arbiter, pacing};
+use super::{
+    connect_ssh, excludes, Components, Direction, Job, JobHandle, Phase, PhaseReport, ProgressSink,
+};
+use crate::error::SyncError;
+use crate::history::{self, HistoryEntry, JobStatus};
+use crate::messages::{self, Message};
+use crate::profile::{HookPoint, Profile};
+use crate::search_replace::PrefixRemap;
+use crate::shell;
+use crate::telemetry::{self, Feature};
+
+/// How files get from one server to the other.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerRoute {
+    /// Directly if the source server can reach the destination, else relayed.
+    #[default]
+    Auto,
+    /// The source server runs rsync to the destination.
+    Direct,
+    /// Through a staging directory on this machine.
+    Relay,
+}
+
+/// What a sync between servers did.
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerSyncSummary {
+    pub job_id: String,
+    pub from_profile_id: String,
+    pub to_profile_id: String,
+    pub components: Components,
+    /// The route the files took; `None` if no files were synced.
+    pub route: Option<ServerRoute>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    /// Files copied to the destination. A direct route counts regular files only.
+    pub files_transferred: u64,
+    pub bytes_transferred: u64,
+    /// The restore point the destination can be rolled back to, as for a push.
+    pub restore_point_id: Option<String>,
+    pub warnings: Vec<String>,
+    /// The catalog messages the warnings are, in the same order.
+    pub warning_messages: Vec<Message>,
+    /// How much of this machine the job used.
+    pub resources: ResourceUsage,
+}
+
+/// Sync the server of profile `to` from that of profile `from`: the database,
+/// files, or both, as `components` says, the files by `route`. Both profiles
+/// are locked while it runs, it's cancelled through `handle` like any other
+/// job, and it's recorded in the history as a push of `to`. Confirming a push
+/// to a protected `to` is up to the caller.
+pub async fn sync_servers(
+    from: &Profile,
+    to: &Profile,
+    components: Components,
+    route: ServerRoute,
+    handle: &JobHandle,
+    sink: &dyn ProgressSink,
+) -> Result<ServerSyncSummary, SyncError> {
+    telemetry::record(Feature::ServerSync);
+    for profile in [from, to] {
+        if !profile.remote.transfer.uses_ssh() {
+            return Err(SyncError::Config(Message::new(
+                "error.no_ssh.server_sync",
+                &[("name", &profile.name)],
+            )));
+        }
+    }
+    let (source, dest) = (&from.remote, &to.remote);
+    if source.host == dest.host && source.port == dest.port && source.path == dest.path {
+        return Err(SyncError::Config(Message::new(
+            "error.name_name2_are_same_install",
+            &[("name", &from.name), ("name2", &to.name)],
+        )));
+    }
+    components.check(to)?;
+    let _locks = (
+        ProfileLock::acquire(&from.id, &handle.id)?,
+        ProfileLock::acquire(&to.id, &handle.id)?,
+    );
+    let _pacing = pacing::follow(&to.bandwidth, Direction::Push, &handle.throttle);
+    let _share = arbiter::share_bandwidth(&handle.throttle);
+    let temp = TempDir::hold(&handle.id);
+    let started_at = Utc::now();
+    tracing::info!(
+        job_id = %handle.id,
+        from = %from.name,
+        to = %to.name,
+        ?components,
+        ?route,
+        "syncing between servers"
+    );
+
+    let reporter = Reporter::new(handle.id.clone(), sink);
+    let monitor = ResourceMonitor::start();
+    let connect_start = Instant::now();
+    reporter.report(
+        Phase::Connect,
+        &TransferStats::default(),
+        connect_start,
+        true,
+    );
+    let connect = async {
+        let (source, dest) = tokio::try_join!(connect_ssh(from, sink), connect_ssh(to, sink))?;
+        let transfer = transfer::open(to, Some(&dest))
+            .await
+            .map_err(SyncError::connection)?;
+        Ok::<_, SyncError>((source, dest, transfer))
+    };
+    let connected = tokio::select! {
+        connected = connect => connected,
+        _ = handle.cancel.cancelled() => Err(SyncError::Cancelled),
+    };
+    let (source, dest, transfer) = connected?;
+    let mut job = Job::new(
+        to,
+        Direction::Push,
+        components,
+        Some(dest),
+        transfer,
+        reporter,
+    );
+    job.from_server = Some(from);
+    job.throttle = handle.throttle.clone();
+    job.allow_large_deletion = handle.allow_large_deletion;
+    let servers = Servers {
+        from,
+        source: &source,
+        job,
+        components,
+        handle,
+        phases: Mutex::default(),
+    };
+    servers.finished(
+        Phase::Connect,
+        connect_start.elapsed(),
+        &TransferStats::default(),
+    );
+    let result = tokio::select! {
+        result = servers.run(route) => result,
+        _ = handle.cancel.cancelled() => Err(SyncError::Cancelled),
+    };
+    // Outside the cancellation race, so it runs whatever stopped the sync.
+    if result.is_err() {
+        servers.clean_up().await;
+    }
+    let Servers { job, phases, .. } = servers;
+    let phases = phases.into_inner().unwrap();
+    let warnings = job.take_warnings();
+    let resources = monitor.finish(to, &phases);
+    source.close().await;
+    if let Some(dest) = &job.remote {
+        dest.close().await;
+    }
+    drop(job);
+    let staged = [
+        temp::path(&handle.id, STAGED_FILES),
+        temp::path(&handle.id, DUMP),
+        temp::path(&handle.id, USERS),
+    ];
+    let _ = tokio::task::spawn_blocking(move || {
+        for path in staged {
+            let _ = if path.is_dir() {
+                std::fs::remove_dir_all(&path)
+            } else {
+                std::fs::remove_file(&path)
+            };
+        }
+        drop(temp);
+    })
+    .await;
+
+    let finished_at = Utc::now();
+    match &result {
+        Ok(done) => tracing::info!(
+            job_id = %handle.id,
+            files = done.files,
+            bytes = done.bytes,
+            "synced between servers"
+        ),
+        Err(e) => tracing::error!(job_id = %handle.id, error = %e, "sync between servers failed"),
+    }
+    // It's in the history as a push to the destination.
+    let entry = HistoryEntry {
+        job_id: handle.id.clone(),
+        profile_id: to.id.clone(),
+        profile_name: to.name.clone(),
+        direction: Direction::Push,
+        components,
+        started_at,
+        finished_at,
+        status: match &result {
+            Ok(_) => JobStatus::Succeeded,
+            Err(SyncError::Cancelled) => JobStatus::Cancelled,
+            Err(_) => JobStatus::Failed,
+        },
+        error: result.as_ref().err().cloned(),
+        phases,
+        bytes_transferred: result.as_ref().map_or(0, |done| done.bytes),
+        files_transferred: result.as_ref().map_or(0, |done| done.files),
+        warnings: warnings.iter().map(ToString::to_string).collect(),
+        restore_point_id: result
+            .as_ref()
+            .ok()
+            .and_then(|done| done.restore_point_id.clone()),
+        git_head: None,
+        screenshots: Vec::new(),
+        resources: Some(resources.clone()),
+    };
+    sink.finished(&entry);
+    events::emit(sink, JobEvent::completed(&entry));
+    audit::record(to, &entry, handle.confirmation);
+    let _ = tokio::task::spawn_blocking(move || history::record(&entry)).await;
+
+    let done = result?;
+    Ok(ServerSyncSummary {
+        job_id: handle.id.clone(),
+        from_profile_id: from.id.clone(),
+        to_profile_id: to.id.clone(),
+        components,
+        route: done.route,
+        started_at,
+        finished_at,
+        files_transferred: done.files,
+        bytes_transferred: done.bytes,
+        restore_point_id: done.restore_point_id,
+        warnings: warnings.iter().map(ToString::to_string).collect(),
+        warning_messages: warnings,
+        resources,
+    })
+}
+
+/// The job's temporary files.
+const STAGED_FILES: &str = "files";
+const DUMP: &str = "dump.sql";
+const USERS: &str = "users.sql";
+
+/// Both servers of a sync between them, connected.
+struct Servers<'a> {
+    from: &'a Profile,
+    source: &'a Remote,
+    /// The destination's side of the sync, a push of its profile from the
+    /// source server.
+    job: Job<'a>,
+    components: Components,
+    handle: &'a JobHandle,
+    /// The phases finished so far, for the history.
+    phases: Mutex<Vec<PhaseReport>>,
+}
+
+/// What [`Servers::run`] got done.
+#[derive(Default)]
+struct Done {
+    route: Option<ServerRoute>,
+    files: u64,
+    bytes: u64,
+    restore_point_id: Option<String>,
+}
+
+/// Files ready to go to the destination, once what they change there is known.
+struct StagedFiles {
+    excludes: Excludes,
+    /// The staging directory they were pulled into, if they're relayed.
+    staging: Option<String>,
+    /// How long working it out took, of the transfer phase.
+    took: Duration,
+}
+
+impl Servers<'_> {
+    fn to(&self) -> &Profile {
+        self.job.profile
+    }
+
+    fn source(&self) -> Endpoint<'_> {
+        Endpoint::Remote {
+            remote: Some(self.source),
+            env: &self.from.remote,
+        }
+    }
+
+    fn dest(&self) -> Endpoint<'_> {
+        self.job.remote()
+    }
+
+    async fn run(&self, route: ServerRoute) -> Result<Done, SyncError> {
+        let job = &self.job;
+        let mut done = Done::default();
+        if self.components.database() {
+            self.detect_network()
+                .await
+                .map_err(|e| Phase::Connect.error(e))?;
+            self.dump().await?;
+        }
+        let staged = match self.components.files() {
+            true => Some(self.stage_files(route).await?),
+            false => None,
+        };
+        // Staging worked out the destination's changes, so this weighs their
+        // deletions too.
+        job.check_warnings().await?;
+        job.run_hooks(HookPoint::BeforeSync)
+            .await
+            .map_err(|e| Phase::Connect.error(e))?;
+        if job.backs_up() {
+            let start = Instant::now();
+            self.report(Phase::Backup, &TransferStats::default(), start, true);
+            job.back_up().await.map_err(|e| Phase::Backup.error(e))?;
+            done.restore_point_id = Some(job.id.clone());
+            self.finished(Phase::Backup, start.elapsed(), &TransferStats::default());
+        }
+        if let Some(staged) = staged {
+            job.begin_maintenance()
+                .await
+                .map_err(|e| Phase::TransferFiles.error(e))?;
+            self.send_files(staged, &mut done).await?;
+            job.run_hooks(HookPoint::AfterFiles)
+                .await
+                .map_err(|e| Phase::TransferFiles.error(e))?;
+        }
+        if self.components.database() {
+            job.begin_maintenance()
+                .await
+                .map_err(|e| Phase::ImportDatabase.error(e))?;
+            self.import(&mut done).await?;
+            job.run_hooks(HookPoint::AfterDbImport)
+                .await
+                .map_err(|e| Phase::ImportDatabase.error(e))?;
+        }
+        if self.to().post_sync.rotate_salts {
+            if let Err(e) = salts::rotate(&self.dest()).await {
+                job.warn(Message::new(
+                    "warning.name_auth_keys_salts_werent_rotated",
+                    &[("name", &self.to().name), ("error", &e)],
+                ));
+            }
+        }
+        if let Err(e) = self.dest().wp(&["cache", "flush"]).await {
+            job.warn(Message::new(
+                "warning.failed.flush_name_object_cache",
+                &[("name", &self.to().name), ("error", &e)],
+            ));
+        }
+        let start = Instant::now();
+        self.report(Phase::Finalize, &TransferStats::default(), start, true);
+        if let Err(e) = job.prune_backups().await {
+            job.warn(Message::new(
+                "warning.old_restore_points_were_not_pruned",
+                &[("error", &e)],
+            ));
+        }
+        job.record_fingerprint().await;
+        job.end_maintenance()
+            .await
+            .map_err(|e| Phase::Finalize.error(e))?;
+        self.finished(Phase::Finalize, start.elapsed(), &TransferStats::default());
+        job.run_hooks(HookPoint::AfterSync)
+            .await
+            .map_err(|e| Phase::Finalize.error(e))?;
+        Ok(done)
+    }
+
+    /// After a failed or cancelled sync, remove the backup it started but
+    /// didn't record, and take the destination out of maintenance mode.
+    async fn clean_up(&self) {
+        let _ = self.job.remove_incomplete_backup().await;
+        if let Err(e) = self.job.end_maintenance().await {
+            self.job.warn(e);
+        }
+    }
+
+    /// Note whether the installs are a multisite network, which they must
+    /// both be or neither, for the search-replace to rewrite its host name.
+    async fn detect_network(&self) -> Result<(), String> {
+        let sides = (self.source(), self.dest());
+        let (source, dest) =
+            tokio::try_join!(db::is_multisite(&sides.0), db::is_multisite(&sides.1))?;
+        if source != dest {
+            let describe = |network| match network {
+                true => "a multisite network",
+                false => "a single site",
+            };
+            return Err(messages::text(
+                "error.name_source_but_name2_dest",
+                &[
+                    ("name", &self.from.name),
+                    ("source", &describe(source)),
+                    ("name2", &self.to().name),
+                    ("dest", &describe(dest)),
+                ],
+            ));
+        }
+        let _ = self.job.network.set(source);
+        Ok(())
+    }
+
+    /// Dump the source database here and point it at the destination's URLs
+    /// and table prefix.
+    async fn dump(&self) -> Result<(), SyncError> {
+        let (source, dest) = (self.source(), self.dest());
+        let dump = temp::path(&self.handle.id, DUMP);
+
+        let filters = if self.from.skip_cache_rows {
+            let prefix = db::table_prefix(&source)
+                .await
+                .map_err(SyncError::database)?;
+            let tables = db::tables(&source).await.map_err(SyncError::database)?;
+            db::cache_row_filters(&tables, &prefix)
+        } else {
+            Vec::new()
+        };
+        let start = Instant::now();
+        let mut dumped = TransferStats::default();
+        self.report(Phase::DumpDatabase, &dumped, start, true);
+        db::export(
+            &source,
+            &self.from.table_selection(),
+            &filters,
+            &dump,
+            self.from.compression,
+            &mut |stats| {
+                dumped = stats.clone();
+                self.report(Phase::DumpDatabase, stats, start, false);
+            },
+        )
+        .await
+        .map_err(SyncError::database)?;
+        self.finished(Phase::DumpDatabase, start.elapsed(), &dumped);
+
+        let prefix = if self.from.remap_table_prefix {
+            let (from, to) = tokio::try_join!(db::table_prefix(&source), db::table_prefix(&dest))
+                .map_err(SyncError::database)?;
+            (from != to).then_some(PrefixRemap { from, to })
+        } else {
+            None
+        };
+        let pairs = self.job.replacements();
+        let start = Instant::now();
+        let mut replaced = TransferStats::default();
+        self.report(Phase::SearchReplace, &replaced, start, true);
+        db::search_replace(&dump, &pairs, prefix.as_ref(), &[], &mut |stats| {
+            replaced = stats.clone();
+            self.report(Phase::SearchReplace, stats, start, false);
+        })
+        .await
+        .map_err(SyncError::database)?;
+        self.job
+            .warn_mixed_content(&dump)
+            .await
+            .map_err(SyncError::database)?;
+        self.finished(Phase::SearchReplace, start.elapsed(), &replaced);
+        Ok(())
+    }
+
+    /// Import the dump into the destination.
+    async fn import(&self, done: &mut Done) -> Result<(), SyncError> {
+        let dest = self.dest();
+        let dump = temp::path(&self.handle.id, DUMP);
+        let start = Instant::now();
+        let mut sent = TransferStats::default();
+        self.report(Phase::ImportDatabase, &sent, start, true);
+        let mut on_progress = |stats: &TransferStats| {
+            sent = stats.clone();
+            self.report(Phase::ImportDatabase, stats, start, false);
+        };
+        let to = self.to();
+        let imported = if to.preserve_users {
+            let users = temp::path(&self.handle.id, USERS);
+            db::import_preserving_users(&dest, &dump, &users, to.compression, &mut on_progress)
+                .await
+        } else {
+            db::import(&dest, &dump, to.compression, &mut on_progress).await
+        };
+        imported.map_err(SyncError::database)?;
+        self.finished(Phase::ImportDatabase, start.elapsed(), &sent);
+        done.bytes += sent.bytes_transferred;
+        Ok(())
+    }
+
+    /// Choose the files' route by `route` and work out what they change on
+    /// the destination, pulling them into the staging directory to relay them.
+    async fn stage_files(&self, route: ServerRoute) -> Result<StagedFiles, SyncError> {
+        let excludes = excludes(self.from, self.components, false).map_err(SyncError::config)?;
+        let direct = match route {
+            ServerRoute::Direct => {
+                self.reaches_dest().await.map_err(SyncError::transfer)?;
+                true
+            }
+            ServerRoute::Relay => false,
+            ServerRoute::Auto => match self.reaches_dest().await {
+                Ok(()) => true,
+                Err(e) => {
+                    tracing::info!(error = %e, "relaying files through this machine");
+                    false
+                }
+            },
+        };
+        let start = Instant::now();
+        self.report(Phase::TransferFiles, &TransferStats::default(), start, true);
+        let (changes, staging) = if direct {
+            let output = self
+                .source
+                .exec(&self.direct_command(&excludes, true))
+                .await
+                .map_err(SyncError::transfer)?;
+            (transfer::itemized_changes(&output), None)
+        } else {
+            let staging = self.pull(&excludes, start).await?;
+            let dest = self.files_root(self.to(), &self.dest());
+            let changes = self
+                .job
+                .transfer
+                .plan(Direction::Push, &staging, &dest, &excludes)
+                .await
+                .map_err(SyncError::transfer)?;
+            (changes, Some(staging))
+        };
+        let _ = self.job.server_changes.set(changes);
+        Ok(StagedFiles {
+            excludes,
+            staging,
+            took: start.elapsed(),
+        })
+    }
+
+    /// Mirror the `staged` files onto the destination by the route chosen
+    /// for them.
+    async fn send_files(&self, staged: StagedFiles, done: &mut Done) -> Result<(), SyncError> {
+        let start = Instant::now();
+        let before = (done.bytes, done.files);
+        self.report(Phase::TransferFiles, &TransferStats::default(), start, true);
+        match &staged.staging {
+            None => {
+                self.direct(&staged.excludes, done).await?;
+                done.route = Some(ServerRoute::Direct);
+            }
+            Some(staging) => {
+                self.push(staging, &staged.excludes, start, done).await?;
+                done.route = Some(ServerRoute::Relay);
+            }
+        }
+        let moved = TransferStats {
+            bytes_transferred: done.bytes - before.0,
+            files_done: done.files - before.1,
+            ..TransferStats::default()
+        };
+        self.finished(Phase::TransferFiles, staged.took + start.elapsed(), &moved);
+        Ok(())
+    }
+
+    /// The directory mirrored on `side` of `profile`.
+    fn files_root(&self, profile: &Profile, side: &Endpoint<'_>) -> String {
+        match multisite::files_dir(profile, self.components) {
+            Some(dir) => side.path(&dir),
+            None => side.root(),
+        }
+    }
+
+    /// Whether the source server has rsync and can log in to the destination
+    /// without being asked anything, and the destination has rsync too.
+    async fn reaches_dest(&self) -> Result<(), String> {
+        if self.to().remote.jump_host.is_some() {
+            return Err(messages::text(
+                "error.name_reached_through_jump_host_which",
+                &[("name", &self.to().name)],
+            ));
+        }
+        let check = format!(
+            "command -v rsync >/dev/null && ssh {} {} command -v rsync",
+            self.ssh_options(),
+            shell::quote(&self.dest_login()),
+        );
+        self.source.exec(&check).await.map(drop).map_err(|e| {
+            messages::text(
+                "error.name_server_cant_reach_name2",
+                &[
+                    ("name", &self.from.name),
+                    ("name2", &self.to().name),
+                    ("error", &e),
+                ],
+            )
+        })
+    }
+
+    /// The command line the source server rsyncs its files to the destination
+    /// with, or with `dry_run` lists what that would change.
+    fn direct_command(&self, excludes: &Excludes, dry_run: bool) -> String {
+        let source = self.files_root(self.from, &self.source());
+        let dest = self.files_root(self.to(), &self.dest());
+        let program = |profile: &Profile| {
+            profile
+                .rsync
+                .remote_path
+                .clone()
+                .unwrap_or_else(|| "rsync".to_string())
+        };
+        let mut args = vec![
+            program(self.from),
+            "-as".to_string(),
+            "--delete".to_string(),
+        ];
+        if dry_run {
+            args.extend(["--dry-run".to_string(), "--itemize-changes".to_string()]);
+        } else {
+            args.extend(["--partial".to_string(), "--stats".to_string()]);
+            if let Some(level) = self.from.compression.level() {
+                args.extend(["-z".to_string(), format!("--compress-level={level}")]);
+            }
+            if let Some(limit) = self.handle.throttle.limit() {
+                args.push(format!("--bwlimit={limit}"));
+            }
+        }
+        if self.to().rsync.remote_path.is_some() {
+            args.push(format!(
+                "--rsync-path={}",
+                shell::quote(&program(self.to()))
+            ));
+        }
+        args.extend(excludes.rsync_filters());
+        args.push(format!("--rsh=ssh {}", self.ssh_options()));
+        args.push(format!("{}/", source.trim_end_matches('/')));
+        args.push(format!(
+            "{}:{}/",
+            self.dest_login(),
+            dest.trim_end_matches('/')
+        ));
+        let command: Vec<String> = args.iter().map(|arg| shell::quote(arg)).collect();
+        command.join(" ")
+    }
+
+    /// Have the source server rsync its files to the destination.
+    async fn direct(&self, excludes: &Excludes, done: &mut Done) -> Result<(), SyncError> {
+        let stats = self
+            .source
+            .exec(&self.direct_command(excludes, false))
+            .await
+            .map_err(SyncError::transfer)?;
+        done.files += stat(&stats, "Number of regular files transferred");
+        done.bytes += stat(&stats, "Total bytes sent");
+        Ok(())
+    }
+
+    /// Pull the source's files into a staging directory here, returning it.
+    async fn pull(&self, excludes: &Excludes, start: Instant) -> Result<String, SyncError> {
+        let staging = temp::path(&self.handle.id, STAGED_FILES);
+        tokio::fs::create_dir_all(&staging).await.map_err(|e| {
+            SyncError::Transfer(Message::new(
+                "error.failed.create_path",
+                &[("path", &staging.display()), ("error", &e)],
+            ))
+        })?;
+        let staging = path_str(&staging)?;
+        let options = MirrorOptions {
+            throttle: &self.handle.throttle,
+            checksums: None,
+        };
+        let source = self.files_root(self.from, &self.source());
+        let pulled = transfer::open(self.from, Some(self.source))
+            .await
+            .map_err(SyncError::transfer)?;
+        pulled
+            .mirror(
+                Direction::Pull,
+                &source,
+                &staging,
+                excludes,
+                &options,
+                &mut |stats| self.report(Phase::TransferFiles, stats, start, false),
+            )
+            .await
+            .map_err(SyncError::transfer)?;
+        Ok(staging)
+    }
+
+    /// Push the files pulled into `staging` on to the destination.
+    async fn push(
+        &self,
+        staging: &str,
+        excludes: &Excludes,
+        start: Instant,
+        done: &mut Done,
+    ) -> Result<(), SyncError> {
+        let options = MirrorOptions {
+            throttle: &self.handle.throttle,
+            checksums: None,
+        };
+        let dest = self.files_root(self.to(), &self.dest());
+        let mirrored = self
+            .job
+            .transfer
+            .mirror(
+                Direction::Push,
+                staging,
+                &dest,
+                excludes,
+                &options,
+                &mut |stats| self.report(Phase::TransferFiles, stats, start, false),
+            )
+            .await
+            .map_err(SyncError::transfer)?;
+        done.files += mirrored.copied.len() as u64;
+        done.bytes += mirrored.changes.bytes;
+        Ok(())
+    }
+
+    /// `user@host` of the destination.
+    fn dest_login(&self) -> String {
+        format!("{}@{}", self.to().remote.user, self.to().remote.host)
+    }
+
+    /// The options the source server's `ssh` reaches the destination with:
+    /// never asking, so a login it can't make fails instead of hanging.
+    fn ssh_options(&self) -> String {
+        format!(
+            "-p {} -o BatchMode=yes -o ConnectTimeout=15",
+            self.to().remote.port
+        )
+    }
+
+    fn report(&self, phase: Phase, stats: &TransferStats, start: Instant, force: bool) {
+        self.job.reporter.report(phase, stats, start, force);
+    }
+
+    /// Note that `phase` has finished, having taken `took` and moved `stats`.
+    fn finished(&self, phase: Phase, took: Duration, stats: &TransferStats) {
+        let report = PhaseReport::new(phase, took.as_millis() as u64, stats);
+        self.phases.lock().unwrap().push(report);
+    }
+}
+
+/// The number rsync's `--stats` gives after `label`, such as `Total bytes
+/// sent: 1,234`; 0 if it isn't there.
+fn stat(stats: &str, label: &str) -> u64 {
+    stats
+        .lines()
+        .find_map(|line| line.trim().strip_prefix(label)?.strip_prefix(':'))
+        .and_then(|value| {
+            let digits: String = value
+                .split_whitespace()
+                .next()?
+                .chars()
+                .filter(char::is_ascii_digit)
+                .collect();
+            digits.parse().ok()
+        })
+        .unwrap_or(0)
+}
+
+fn path_str(path: &Path) -> Result<String, SyncError> {
+    path.to_str().map(str::to_string).ok_or_else(|| {
+        SyncError::Transfer(Message::new(
+            "error.path_isnt_utf_8",
+            &[("path", &path.display())],
+        ))
+    })
+}
@@ -0,0 +1,282 @@
+//! The remote side of a sync: a managed SSH session plus an rsync bridge over it.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use russh::client::Msg;
+use russh::{Channel, ChannelMsg};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::OnceCell;
+
+use super::arbiter::{self, SessionSlot};
+use super::php::{self, Launcher};
+
+use crate::byte_path;
+use crate::credentials::{self, SSH_PASSWORD_SERVICE};
+use crate::messages;
+use crate::profile::{AuthMethod, JumpHost, Profile, RemoteEnvironment, TransferMethod};
+use crate::ssh::{self, Ask, Auth, Bridge, ConnectError, Session, SshTarget, Tunnel};
+use crate::{proxy, stderr};
+
+/// Private keys tried when a profile doesn't name one, in OpenSSH's order of preference.
+const DEFAULT_KEYS: &[&str] = &["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// An open connection to the profile's server.
+pub struct Remote {
+    session: Arc<Session>,
+    bridge: Bridge,
+    destination: String,
+    /// How wp-cli is started, found the first time it's run.
+    wp_cli: OnceCell<Launcher>,
+    /// How long the session stays open once closed.
+    linger: Duration,
+    /// Its place under the cap on sessions in use.
+    _slot: SessionSlot,
+}
+
+impl Remote {
+    /// Connect and authenticate using the profile's configured method, any
+    /// questions the server asks put to the user with `ask`.
+    pub async fn connect(profile: &Profile, ask: Ask<'_>) -> Result<Self, ConnectError> {
+        let target = target(profile)?;
+        let slot = arbiter::session_slot().await;
+        let session = ssh::connect_shared(&target, ask).await?;
+        let bridge = Bridge::start(session.clone()).await?;
+        tracing::info!(user = %target.user, host = %target.host, "connected");
+        Ok(Self {
+            session,
+            bridge,
+            destination: format!("{}@{}", target.user, target.host),
+            wp_cli: OnceCell::new(),
+            linger: profile.timeouts.linger(),
+            _slot: slot,
+        })
+    }
+
+    /// `user@host`, as rsync expects it before a remote path.
+    pub fn destination(&self) -> &str {
+        &self.destination
+    }
+
+    /// The `-e` value telling rsync to reach the server through this connection.
+    pub fn rsync_shell(&self) -> Result<String, String> {
+        self.bridge.rsync_shell()
+    }
+
+    /// Forward a loopback port on this machine to `host:port` from the server,
+    /// for as long as the returned tunnel is kept.
+    pub async fn forward(&self, host: &str, port: u16) -> Result<Tunnel, String> {
+        Tunnel::open(self.session.clone(), host, port).await
+    }
+
+    /// Run a shell command line on the server, returning its stdout. Bytes
+    /// that aren't UTF-8, as in file names, are kept [as `byte_path` does](byte_path).
+    pub async fn exec(&self, command: &str) -> Result<String, String> {
+        let output = self.session.exec(command).await?;
+        if output.success() {
+            return Ok(byte_path::from_bytes(&output.stdout));
+        }
+        Err(command_failed(command, output.exit_status, &output.stderr))
+    }
+
+    /// Run a shell command line on the server with `input` as its stdin, returning its stdout.
+    pub async fn exec_with_input(&self, command: &str, input: &[u8]) -> Result<String, String> {
+        let mut channel = self.open_exec(command).await?;
+        let mut writer = channel.make_writer();
+        let send = async {
+            writer.write_all(input).await?;
+            // Shutting the writer down sends EOF.
+            writer.shutdown().await
+        };
+        let receive = async {
+            let (mut stdout, mut stderr, mut exit_status) = (Vec::new(), Vec::new(), None);
+            while let Some(msg) = channel.wait().await {
+                match msg {
+                    ChannelMsg::Data { data } => stdout.extend_from_slice(&data),
+                    // Extended data type 1 is stderr
+                    ChannelMsg::ExtendedData { data, ext: 1 } => stderr::push(&mut stderr, &data),
+                    ChannelMsg::ExitStatus { exit_status: code } => exit_status = Some(code),
+                    _ => {}
+                }
+            }
+            (stdout, stderr, exit_status)
+        };
+        let (sent, (stdout, stderr, exit_status)) = tokio::join!(send, receive);
+        ssh::audit_exit(command, exit_status);
+        // A command that exits without reading everything breaks the write; its own error says why.
+        if exit_status != Some(0) {
+            return Err(command_failed(command, exit_status, &stderr));
+        }
+        sent.map_err(|e| {
+            messages::text(
+                "error.failed.send_input_command",
+                &[("command", &command), ("error", &e)],
+            )
+        })?;
+        Ok(byte_path::from_bytes(&stdout))
+    }
+
+    /// Start a shell command line on a new channel, for callers that stream its
+    /// input or output themselves.
+    pub async fn open_exec(&self, command: &str) -> Result<Channel<Msg>, String> {
+        self.session.open_exec(command).await
+    }
+
+    /// The SSH session itself, for transfers that outlive this borrow.
+    /// How wp-cli is started for `env`, the install this connection is to.
+    pub(super) async fn wp_cli(&self, env: &RemoteEnvironment) -> Result<&Launcher, String> {
+        self.wp_cli
+            .get_or_try_init(|| php::launcher(self, env))
+            .await
+    }
+
+    /// The PHP wp-cli was found to run with, once it has been run.
+    pub(super) fn found_php(&self) -> Option<&str> {
+        self.wp_cli.get()?.php.as_deref()
+    }
+
+    pub fn ssh(&self) -> Arc<Session> {
+        self.session.clone()
+    }
+
+    /// Start an SSH subsystem such as `sftp` on a new channel.
+    pub async fn open_subsystem(&self, name: &str) -> Result<Channel<Msg>, String> {
+        self.session.open_subsystem(name).await
+    }
+
+    /// Be done with the connection. It's kept open for the profile's linger
+    /// time, for the next job to the server to go on with.
+    pub async fn close(&self) {
+        ssh::release(self.session.clone(), self.linger).await;
+    }
+}
+
+/// The error for a remote command that didn't exit successfully.
+pub fn command_failed(command: &str, exit_status: Option<u32>, stderr: &[u8]) -> String {
+    let stderr = String::from_utf8_lossy(stderr);
+    match exit_status {
+        Some(code) => messages::text(
+            "error.remote_command_exited",
+            &[
+                ("command", &command),
+                ("code", &code),
+                ("stderr", &stderr.trim()),
+            ],
+        ),
+        None => messages::text(
+            "error.remote_command_no_exit_status",
+            &[("command", &command), ("stderr", &stderr.trim())],
+        ),
+    }
+}
+
+/// Translate the profile's connection settings into an SSH target.
+fn target(profile: &Profile) -> Result<SshTarget, String> {
+    let env = &profile.remote;
+    let timeouts = &profile.timeouts;
+    // The first hop is the one that goes through the proxy.
+    let mut proxy = proxy::route(&env.proxy)?;
+    let jump = match &env.jump_host {
+        Some(jump) => Some(Box::new(SshTarget {
+            host: jump.host.clone(),
+            port: jump.port,
+            user: jump.user.clone(),
+            auth: auth_methods(
+                jump.auth,
+                jump.key_path.as_deref(),
+                profile.secrets.jump_password.as_deref(),
+                &JumpHost::password_account(&profile.id),
+            )
+            .map_err(|e| {
+                messages::text("error.jump_host", &[("error", &e), ("host", &jump.host)])
+            })?,
+            forward_agent: false,
+            // The connection tunnelled through it is compressed, if anything.
+            compress: false,
+            jump: None,
+            proxy: proxy.take(),
+            family: env.address_family,
+            connect_timeout: timeouts.connect(),
+            keepalive_interval: timeouts.keepalive(),
+            keepalive_max: timeouts.keepalive_max as usize,
+        })),
+        None => None,
+    };
+    Ok(SshTarget {
+        host: env.host.clone(),
+        port: env.port,
+        user: env.user.clone(),
+        auth: auth_methods(
+            env.auth,
+            env.key_path.as_deref(),
+            profile.secrets.ssh_password.as_deref(),
+            &profile.id,
+        )?,
+        forward_agent: env.forward_agent,
+        // rsync compresses for itself; SFTP relies on the connection.
+        compress: env.transfer == TransferMethod::Sftp && profile.compression.level().is_some(),
+        jump,
+        proxy,
+        family: env.address_family,
+        connect_timeout: timeouts.connect(),
+        keepalive_interval: timeouts.keepalive(),
+        keepalive_max: timeouts.keepalive_max as usize,
+    })
+}
+
+/// The methods to try for one hop, with any password the named secret
+/// `password` refers to, or else read from the credential store under
+/// `password_account`.
+fn auth_methods(
+    method: AuthMethod,
+    key_path: Option<&Path>,
+    password: Option<&str>,
+    password_account: &str,
+) -> Result<Vec<Auth>, String> {
+    let key_files = || match key_path {
+        Some(path) => vec![path.to_path_buf()],
+        None => default_key_files(),
+    };
+    let password = || credentials::resolve(password, SSH_PASSWORD_SERVICE, password_account);
+
+    Ok(match method {
+        AuthMethod::Auto => {
+            let mut methods = vec![Auth::Agent];
+            methods.extend(key_files().into_iter().map(Auth::Key));
+            let password = password()?;
+            if let Some(password) = &password {
+                methods.push(Auth::Password(password.clone()));
+            }
+            methods.push(Auth::KeyboardInteractive { password });
+            methods
+        }
+        AuthMethod::Agent => vec![Auth::Agent],
+        AuthMethod::Key => key_files().into_iter().map(Auth::Key).collect(),
+        // Servers that log in through PAM often only ask for it that way.
+        AuthMethod::Password => match password()? {
+            Some(password) => vec![
+                Auth::Password(password.clone()),
+                Auth::KeyboardInteractive {
+                    password: Some(password),
+                },
+            ],
+            None => return Err(messages::text("error.no_ssh_password_stored", &[])),
+        },
+        AuthMethod::KeyboardInteractive => vec![Auth::KeyboardInteractive {
+            password: password()?,
+        }],
+    })
+}
+
+/// The user's standard private keys that actually exist.
+fn default_key_files() -> Vec<PathBuf> {
+    let Some(ssh_dir) = dirs::home_dir().map(|home| home.join(".ssh")) else {
+        return Vec::new();
+    };
+    DEFAULT_KEYS
+        .iter()
+        .map(|name| ssh_dir.join(name))
+        .filter(|path| path.is_file())
+        .collect()
+}
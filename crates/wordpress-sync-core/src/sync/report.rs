@@ -0,0 +1,380 @@
+//! Reports of finished syncs to share, as on a client's ticket: what the job
+//! did, how long each phase took, what it warned about, and how that
+//! compares with the dry run made before it.
+
+use chrono::{DateTime, Local, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::notify::{duration, JobNotification};
+use super::plan::PlannedTotals;
+use super::space::size;
+use super::{Phase, ResourceUsage};
+use crate::history::{self, text, HistoryEntry};
+use crate::messages;
+
+/// A dry run made longer than this before the sync doesn't speak for it.
+const PLAN_MAX_AGE: TimeDelta = TimeDelta::hours(24);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    Markdown,
+    /// A page that stands on its own, styles included.
+    Html,
+}
+
+/// A report of the finished job `job_id`, written in `format`.
+pub fn report(job_id: &str, format: ReportFormat) -> Result<String, String> {
+    let entry = history::get(job_id)?;
+    let plan = PlannedTotals::load(&entry.profile_id, entry.direction)?.filter(|plan| {
+        plan.components == entry.components
+            && plan.planned_at <= entry.started_at
+            && entry.started_at - plan.planned_at <= PLAN_MAX_AGE
+    });
+    let document = document(&entry, plan.as_ref());
+    Ok(match format {
+        ReportFormat::Markdown => document.markdown(),
+        ReportFormat::Html => document.html(),
+    })
+}
+
+struct Document {
+    title: String,
+    sections: Vec<(String, Content)>,
+}
+
+enum Content {
+    Fields(Vec<(String, String)>),
+    Table {
+        header: Vec<String>,
+        rows: Vec<Vec<String>>,
+    },
+    List(Vec<String>),
+    Text(String),
+}
+
+fn document(entry: &HistoryEntry, plan: Option<&PlannedTotals>) -> Document {
+    let seconds = (entry.finished_at - entry.started_at).num_seconds().max(0);
+    let mut summary = vec![
+        ("report.profile", entry.profile_name.clone()),
+        ("report.direction", name("direction", &entry.direction)),
+        ("report.components", name("components", &entry.components)),
+        ("report.status", name("status", &entry.status)),
+        ("report.started", local_time(entry.started_at)),
+        ("report.finished", local_time(entry.finished_at)),
+        ("report.duration", duration(seconds)),
+        ("report.data_moved", size(entry.bytes_transferred)),
+        ("report.files_changed", entry.files_transferred.to_string()),
+    ];
+    if let Some(error) = &entry.error {
+        summary.push(("report.error", error.message()));
+    }
+    if let Some(restore_point) = &entry.restore_point_id {
+        summary.push(("report.restore_point", restore_point.clone()));
+    }
+    if let Some(head) = &entry.git_head {
+        summary.push(("report.git_head", head.clone()));
+    }
+    summary.push(("report.job", entry.job_id.clone()));
+    let summary = summary
+        .into_iter()
+        .map(|(id, value)| (say(id), value))
+        .collect();
+
+    let phases = entry
+        .phases
+        .iter()
+        .map(|report| {
+            vec![
+                report.phase.label(),
+                duration((report.duration_ms / 1000) as i64),
+                size(report.bytes_transferred),
+                report.files_transferred.to_string(),
+            ]
+        })
+        .collect();
+    let warnings = match entry.warnings.is_empty() {
+        true => Content::Text(say("report.no_warnings")),
+        false => Content::List(entry.warnings.clone()),
+    };
+    let compared = match plan {
+        Some(plan) => plan_against(entry, plan),
+        None => Content::Text(say("report.no_plan")),
+    };
+    let mut sections = vec![
+        (say("report.summary"), Content::Fields(summary)),
+        (
+            say("report.phases"),
+            Content::Table {
+                header: header(&[
+                    "report.phase",
+                    "report.duration",
+                    "report.data",
+                    "report.files",
+                ]),
+                rows: phases,
+            },
+        ),
+        (say("report.warnings"), warnings),
+        (say("report.planned_and_done"), compared),
+    ];
+    if !entry.screenshots.is_empty() {
+        sections.push((say("report.screenshots"), screenshots(entry)));
+    }
+    if let Some(usage) = &entry.resources {
+        sections.push((say("report.resources"), resources(usage)));
+    }
+    Document {
+        title: JobNotification::of(entry).title,
+        sections,
+    }
+}
+
+/// How much each page the job took screenshots of changed, and where they are.
+fn screenshots(entry: &HistoryEntry) -> Content {
+    let file = |path: &Option<std::path::PathBuf>| {
+        path.as_ref()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default()
+    };
+    let rows = entry
+        .screenshots
+        .iter()
+        .map(|shot| {
+            vec![
+                shot.path.clone(),
+                match (shot.changed, &shot.error) {
+                    (Some(changed), _) => format!("{changed:.1}%"),
+                    (None, Some(e)) => e.clone(),
+                    (None, None) => String::new(),
+                },
+                file(&shot.before),
+                file(&shot.after),
+            ]
+        })
+        .collect();
+    Content::Table {
+        header: header(&[
+            "report.page",
+            "report.changed",
+            "report.before",
+            "report.after",
+        ]),
+        rows,
+    }
+}
+
+/// How much of this machine the job used, and the settings it ran with.
+fn resources(usage: &ResourceUsage) -> Content {
+    let unknown = || say("report.unknown");
+    let fields = vec![
+        (
+            "report.cpu_time",
+            usage
+                .cpu_ms
+                .map_or_else(unknown, |ms| duration((ms / 1000) as i64)),
+        ),
+        (
+            "report.peak_cpu",
+            usage
+                .peak_cpu_percent
+                .map_or_else(unknown, |percent| format!("{percent:.0}%")),
+        ),
+        (
+            "report.peak_memory",
+            usage.peak_memory_bytes.map_or_else(unknown, size),
+        ),
+        ("report.network", size(usage.network_bytes)),
+        (
+            "report.peak_rate",
+            usage
+                .peak_bytes_per_second
+                .map_or_else(unknown, |rate| format!("{}/s", size(rate))),
+        ),
+        (
+            "report.parallel_transfers",
+            usage.parallel_transfers.to_string(),
+        ),
+        ("report.import_workers", usage.import_workers.to_string()),
+        ("report.compression", text(&usage.compression)),
+    ];
+    Content::Fields(
+        fields
+            .into_iter()
+            .map(|(id, value)| (say(id), value))
+            .collect(),
+    )
+}
+
+/// What the dry run `plan` expected of the job, beside what it did. The
+/// history counts files copied but not deleted, nor values replaced, so those
+/// are left blank.
+fn plan_against(entry: &HistoryEntry, plan: &PlannedTotals) -> Content {
+    let transfer = entry
+        .phases
+        .iter()
+        .find(|report| report.phase == Phase::TransferFiles);
+    let done = |value: Option<String>| value.unwrap_or_else(|| say("report.not_run"));
+    let mut rows = vec![
+        vec![
+            say("report.files_copied"),
+            plan.files_to_copy.to_string(),
+            done(transfer.map(|report| report.files_transferred.to_string())),
+        ],
+        vec![
+            say("report.file_data"),
+            size(plan.bytes),
+            done(transfer.map(|report| size(report.bytes_transferred))),
+        ],
+    ];
+    if plan.files_to_delete > 0 {
+        rows.push(vec![
+            say("report.files_deleted"),
+            plan.files_to_delete.to_string(),
+            String::new(),
+        ]);
+    }
+    if let Some(tables) = plan.tables {
+        let imported = entry
+            .phases
+            .iter()
+            .any(|report| report.phase == Phase::ImportDatabase);
+        rows.push(vec![
+            say("report.tables_written"),
+            tables.to_string(),
+            match imported {
+                true => say("report.imported"),
+                false => say("report.not_imported"),
+            },
+        ]);
+    }
+    if let Some(replacements) = plan.replacements {
+        rows.push(vec![
+            say("report.values_replaced"),
+            replacements.to_string(),
+            String::new(),
+        ]);
+    }
+    Content::Table {
+        header: header(&["report.what", "report.planned", "report.done"]),
+        rows,
+    }
+}
+
+impl Document {
+    fn markdown(&self) -> String {
+        let mut out = format!("# {}\n", self.title);
+        for (heading, content) in &self.sections {
+            out.push_str(&format!("\n## {heading}\n\n"));
+            match content {
+                Content::Fields(fields) => {
+                    for (name, value) in fields {
+                        out.push_str(&format!("- **{name}:** {value}\n"));
+                    }
+                }
+                Content::Table { header, rows } => {
+                    let row = |cells: &[String]| {
+                        let cells: Vec<String> =
+                            cells.iter().map(|cell| cell.replace('|', "\\|")).collect();
+                        format!("| {} |\n", cells.join(" | "))
+                    };
+                    out.push_str(&row(header));
+                    out.push_str(&row(&vec!["---".to_string(); header.len()]));
+                    for cells in rows {
+                        out.push_str(&row(cells));
+                    }
+                }
+                Content::List(items) => {
+                    for item in items {
+                        out.push_str(&format!("- {item}\n"));
+                    }
+                }
+                Content::Text(paragraph) => out.push_str(&format!("{paragraph}\n")),
+            }
+        }
+        out
+    }
+
+    fn html(&self) -> String {
+        let title = escape(&self.title);
+        let lang = escape(&messages::locale());
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html lang=\"{lang}\">\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n"
+        );
+        for (heading, content) in &self.sections {
+            out.push_str(&format!("<h2>{}</h2>\n", escape(heading)));
+            match content {
+                Content::Fields(fields) => {
+                    out.push_str("<dl>\n");
+                    for (name, value) in fields {
+                        out.push_str(&format!(
+                            "<dt>{}</dt><dd>{}</dd>\n",
+                            escape(name),
+                            escape(value)
+                        ));
+                    }
+                    out.push_str("</dl>\n");
+                }
+                Content::Table { header, rows } => {
+                    out.push_str("<table>\n<tr>");
+                    for cell in header {
+                        out.push_str(&format!("<th>{}</th>", escape(cell)));
+                    }
+                    out.push_str("</tr>\n");
+                    for cells in rows {
+                        out.push_str("<tr>");
+                        for cell in cells {
+                            out.push_str(&format!("<td>{}</td>", escape(cell)));
+                        }
+                        out.push_str("</tr>\n");
+                    }
+                    out.push_str("</table>\n");
+                }
+                Content::List(items) => {
+                    out.push_str("<ul>\n");
+                    for item in items {
+                        out.push_str(&format!("<li>{}</li>\n", escape(item)));
+                    }
+                    out.push_str("</ul>\n");
+                }
+                Content::Text(paragraph) => {
+                    out.push_str(&format!("<p>{}</p>\n", escape(paragraph)));
+                }
+            }
+        }
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+}
+
+const STYLE: &str = "body{font-family:system-ui,sans-serif;max-width:48rem;margin:2rem auto;padding:0 1rem;color:#222}\
+dl{display:grid;grid-template-columns:max-content auto;gap:.25rem 1rem}dt{font-weight:600}dd{margin:0}\
+table{border-collapse:collapse}th,td{border:1px solid #ccc;padding:.25rem .75rem;text-align:left}";
+
+/// Message `id`, which has no parameters, in the current locale.
+fn say(id: &str) -> String {
+    messages::text(id, &[])
+}
+
+/// A unit enum's `value` as a person would write it, by its `kind`'s message
+/// for it, such as `direction.push`.
+fn name(kind: &str, value: &impl Serialize) -> String {
+    say(&format!("{kind}.{}", text(value)))
+}
+
+fn header(ids: &[&str]) -> Vec<String> {
+    ids.iter().map(|id| say(id)).collect()
+}
+
+fn local_time(at: DateTime<Utc>) -> String {
+    at.with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,240 @@
+//! How much of this machine a job used, so a report that a sync froze
+//! someone's laptop can be set beside the settings it ran with, and the
+//! defaults tuned from what syncs really take.
+//!
+//! The process is sampled once a second while the job runs. Its CPU time
+//! takes in the rsync, ssh, and wp-cli children it has waited for, except on
+//! Windows; its memory is its own resident size, without theirs. Jobs running
+//! side by side in the app share the one process, so their figures overlap.
+//! The network figures are what the job's phases moved, which is most of what
+//! it sends and receives. A figure the platform can't give is left out.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::PhaseReport;
+use crate::profile::{Compression, Profile};
+
+/// How often the process is sampled.
+const INTERVAL: Duration = Duration::from_secs(1);
+
+/// How much of this machine a job used, and the settings that weigh most on it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    /// CPU time used while the job ran.
+    pub cpu_ms: Option<u64>,
+    /// The busiest second's CPU use, in percent of one core, so more than 100
+    /// on several.
+    pub peak_cpu_percent: Option<f64>,
+    pub peak_memory_bytes: Option<u64>,
+    pub network_bytes: u64,
+    /// The fastest phase's rate.
+    pub peak_bytes_per_second: Option<u64>,
+    pub parallel_transfers: u32,
+    pub import_workers: u32,
+    pub compression: Compression,
+}
+
+/// Samples the process from when a job starts until it's finished or dropped.
+pub(super) struct ResourceMonitor {
+    cpu_at_start: Option<Duration>,
+    peaks: Arc<Mutex<Peaks>>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct Peaks {
+    cpu_percent: Option<f64>,
+    memory: Option<u64>,
+}
+
+impl Peaks {
+    /// Take in the memory now, and the CPU used since `then`.
+    fn sample(&mut self, then: (Instant, Option<Duration>), now: (Instant, Option<Duration>)) {
+        if let Some(memory) = resident_bytes() {
+            self.memory = Some(self.memory.map_or(memory, |peak| peak.max(memory)));
+        }
+        let wall = now.0.duration_since(then.0).as_secs_f64();
+        // The first tick comes at once, too soon after the start to go by.
+        if let (Some(before), Some(after)) = (then.1, now.1) {
+            if wall >= INTERVAL.as_secs_f64() / 2.0 {
+                let percent = after.saturating_sub(before).as_secs_f64() / wall * 100.0;
+                self.cpu_percent = Some(self.cpu_percent.map_or(percent, |peak| peak.max(percent)));
+            }
+        }
+    }
+}
+
+impl ResourceMonitor {
+    pub(super) fn start() -> Self {
+        let cpu_at_start = cpu_time();
+        let peaks = Arc::new(Mutex::new(Peaks::default()));
+        let task = tokio::spawn({
+            let peaks = peaks.clone();
+            async move {
+                let mut ticks = tokio::time::interval(INTERVAL);
+                let mut then = (Instant::now(), cpu_at_start);
+                loop {
+                    ticks.tick().await;
+                    let now = (Instant::now(), cpu_time());
+                    peaks.lock().unwrap().sample(then, now);
+                    then = now;
+                }
+            }
+        });
+        Self {
+            cpu_at_start,
+            peaks,
+            task,
+        }
+    }
+
+    /// Stop sampling and total up, with what the job's `phases` moved and
+    /// the settings of `profile` it ran with.
+    pub(super) fn finish(&self, profile: &Profile, phases: &[PhaseReport]) -> ResourceUsage {
+        self.task.abort();
+        let cpu_now = cpu_time();
+        let mut peaks = self.peaks.lock().unwrap();
+        if let Some(memory) = resident_bytes() {
+            peaks.memory = Some(peaks.memory.map_or(memory, |peak| peak.max(memory)));
+        }
+        let cpu_ms = match (self.cpu_at_start, cpu_now) {
+            (Some(start), Some(now)) => Some(now.saturating_sub(start).as_millis() as u64),
+            _ => None,
+        };
+        ResourceUsage {
+            cpu_ms,
+            peak_cpu_percent: peaks
+                .cpu_percent
+                .map(|percent| (percent * 10.0).round() / 10.0),
+            peak_memory_bytes: peaks.memory,
+            network_bytes: phases.iter().map(|phase| phase.bytes_transferred).sum(),
+            peak_bytes_per_second: phases
+                .iter()
+                .filter_map(|phase| phase.bytes_per_second)
+                .max(),
+            parallel_transfers: profile.remote.parallel_transfers.max(1),
+            import_workers: profile.import_workers.max(1),
+            compression: profile.compression,
+        }
+    }
+}
+
+impl Drop for ResourceMonitor {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// The CPU time this process, and the children it has waited for, have used.
+#[cfg(unix)]
+fn cpu_time() -> Option<Duration> {
+    let used = |who| {
+        let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+        // SAFETY: `usage` is written by a successful call.
+        if unsafe { libc::getrusage(who, usage.as_mut_ptr()) } != 0 {
+            return None;
+        }
+        // SAFETY: getrusage succeeded, so it filled `usage` in.
+        let usage = unsafe { usage.assume_init() };
+        let time = |tv: libc::timeval| {
+            Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+        };
+        Some(time(usage.ru_utime) + time(usage.ru_stime))
+    };
+    Some(used(libc::RUSAGE_SELF)? + used(libc::RUSAGE_CHILDREN)?)
+}
+
+/// The CPU time this process has used.
+#[cfg(windows)]
+fn cpu_time() -> Option<Duration> {
+    use windows_sys::Win32::Foundation::FILETIME;
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, GetProcessTimes};
+
+    let zero = || FILETIME {
+        dwLowDateTime: 0,
+        dwHighDateTime: 0,
+    };
+    let (mut created, mut exited, mut kernel, mut user) = (zero(), zero(), zero(), zero());
+    // SAFETY: the current process's pseudo-handle needs no closing, and each
+    // time is written by a successful call.
+    let ok = unsafe {
+        GetProcessTimes(
+            GetCurrentProcess(),
+            &mut created,
+            &mut exited,
+            &mut kernel,
+            &mut user,
+        )
+    };
+    if ok == 0 {
+        return None;
+    }
+    // In 100-nanosecond ticks.
+    let ticks = |time: FILETIME| {
+        (u64::from(time.dwHighDateTime) << 32 | u64::from(time.dwLowDateTime)) * 100
+    };
+    Some(Duration::from_nanos(ticks(kernel) + ticks(user)))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn cpu_time() -> Option<Duration> {
+    None
+}
+
+/// This process's resident memory, from `/proc`.
+#[cfg(target_os = "linux")]
+fn resident_bytes() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    // SAFETY: sysconf has no preconditions.
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(pages * u64::try_from(page_size).ok()?)
+}
+
+#[cfg(target_os = "macos")]
+fn resident_bytes() -> Option<u64> {
+    let mut info = std::mem::MaybeUninit::<libc::proc_taskinfo>::uninit();
+    let size = std::mem::size_of::<libc::proc_taskinfo>() as libc::c_int;
+    // SAFETY: `info` has room for the `size` bytes asked for, and is written
+    // by a call that returns them all.
+    let written = unsafe {
+        libc::proc_pidinfo(
+            libc::getpid(),
+            libc::PROC_PIDTASKINFO,
+            0,
+            info.as_mut_ptr().cast(),
+            size,
+        )
+    };
+    if written != size {
+        return None;
+    }
+    // SAFETY: proc_pidinfo filled `info` in.
+    Some(unsafe { info.assume_init() }.pti_resident_size)
+}
+
+#[cfg(windows)]
+fn resident_bytes() -> Option<u64> {
+    use windows_sys::Win32::System::ProcessStatus::{
+        K32GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS,
+    };
+    use windows_sys::Win32::System::Threading::GetCurrentProcess;
+
+    let mut counters = std::mem::MaybeUninit::<PROCESS_MEMORY_COUNTERS>::uninit();
+    let size = std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32;
+    // SAFETY: `counters` has room for `size` bytes, and is written by a
+    // successful call.
+    if unsafe { K32GetProcessMemoryInfo(GetCurrentProcess(), counters.as_mut_ptr(), size) } == 0 {
+        return None;
+    }
+    // SAFETY: the call succeeded, so it filled `counters` in.
+    Some(unsafe { counters.assume_init() }.WorkingSetSize as u64)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn resident_bytes() -> Option<u64> {
+    None
+}
@@ -0,0 +1,366 @@
+//! Comparing the source's and destination's sites once a sync is done: the
+//! same pages are fetched from both, and should answer alike. A permalink only
+//! one of them has, or an image the destination is missing, shows up here
+//! before a visitor finds it.
+//!
+//! The pages are the profile's paths and a sample of the source's sitemap,
+//! spread across it so every kind of page it lists has a chance of being in it.
+
+use std::collections::HashSet;
+use std::time::Instant;
+
+use serde::Serialize;
+use url::Url;
+
+use super::health::{self, Page};
+use super::Direction;
+use crate::messages;
+use crate::net::AddressFamily;
+use crate::profile::Profile;
+use crate::proxy::ProxyChoice;
+
+/// Where a site's sitemap is looked for: WordPress's own, then where SEO
+/// plugins put theirs.
+const SITEMAPS: &[&str] = &["/wp-sitemap.xml", "/sitemap_index.xml", "/sitemap.xml"];
+
+/// How many of a sitemap index's sitemaps are read for pages.
+const MAX_SITEMAPS: usize = 10;
+
+/// How many of a page's uploaded images are requested from the destination.
+const MAX_IMAGES: usize = 5;
+
+/// How one page compared.
+#[derive(Debug, Clone, Serialize)]
+pub struct PageComparison {
+    /// The path requested of both sites.
+    pub path: String,
+    /// The status of each site's response once redirects were followed, or
+    /// `None` if none came.
+    pub source_status: Option<u16>,
+    pub destination_status: Option<u16>,
+    /// Whether the destination answered as the source did.
+    pub matched: bool,
+    /// How they differed, if they did.
+    pub differences: Vec<String>,
+    pub duration_ms: u64,
+}
+
+/// One of the sites compared.
+struct Site {
+    /// Its URL, without a trailing slash.
+    url: String,
+    client: reqwest::Client,
+}
+
+/// Compare the profile's pages on the source's and destination's sites of a
+/// sync in `direction`, one after another.
+pub(super) async fn compare(profile: &Profile, direction: Direction) -> Vec<PageComparison> {
+    let settings = &profile.response_diff;
+    let remote = &profile.remote;
+    // The local site is never behind the proxy.
+    let local = (&profile.local.url, &ProxyChoice::Direct, AddressFamily::Any);
+    let server = (&remote.url, &remote.proxy, remote.address_family);
+    let (source, destination) = match direction {
+        Direction::Push => (local, server),
+        Direction::Pull => (server, local),
+    };
+    let site = |(url, proxy, family): (&String, &ProxyChoice, AddressFamily)| {
+        health::client(proxy, family, settings.verify_tls).map(|client| Site {
+            url: url.trim_end_matches('/').to_string(),
+            client,
+        })
+    };
+    let (source, destination) = match (site(source), site(destination)) {
+        (Ok(source), Ok(destination)) => (source, destination),
+        (Err(e), _) | (_, Err(e)) => {
+            return settings
+                .paths
+                .iter()
+                .map(|path| PageComparison {
+                    path: path.clone(),
+                    source_status: None,
+                    destination_status: None,
+                    matched: false,
+                    differences: vec![messages::text("error.failed.set_up_http", &[("error", &e)])],
+                    duration_ms: 0,
+                })
+                .collect()
+        }
+    };
+
+    let mut seen = HashSet::new();
+    let sampled = sitemap_paths(&source, settings.sitemap_sample as usize).await;
+    let paths: Vec<String> = settings
+        .paths
+        .iter()
+        .cloned()
+        .chain(sampled)
+        .filter(|path| seen.insert(path.clone()))
+        .collect();
+
+    let mut results = Vec::with_capacity(paths.len());
+    for path in paths {
+        let result = compare_page(&source, &destination, path).await;
+        if result.matched {
+            tracing::info!(path = %result.path, "the page matches on both sites");
+        } else {
+            tracing::warn!(
+                path = %result.path,
+                "the page differs between the sites: {}",
+                result.differences.join("; ")
+            );
+        }
+        results.push(result);
+    }
+    results
+}
+
+async fn compare_page(source: &Site, destination: &Site, path: String) -> PageComparison {
+    let started = Instant::now();
+    let (from_url, to_url) = (
+        health::full_url(&source.url, &path),
+        health::full_url(&destination.url, &path),
+    );
+    let (from, to) = tokio::join!(
+        health::fetch(&source.client, &from_url),
+        health::fetch(&destination.client, &to_url),
+    );
+    let differences = match (&from, &to) {
+        (Err(e), _) => vec![messages::text(
+            "compare.source_didnt_answer",
+            &[("error", e)],
+        )],
+        (_, Err(e)) => vec![messages::text(
+            "compare.destination_didnt_answer",
+            &[("error", e)],
+        )],
+        (Ok(from), Ok(to)) => differences(source, destination, from, to).await,
+    };
+    PageComparison {
+        path,
+        source_status: from.as_ref().ok().map(|page| page.status),
+        destination_status: to.as_ref().ok().map(|page| page.status),
+        matched: differences.is_empty(),
+        differences,
+        duration_ms: started.elapsed().as_millis() as u64,
+    }
+}
+
+/// How the destination's answer `to` differs from the source's `from`.
+async fn differences(source: &Site, destination: &Site, from: &Page, to: &Page) -> Vec<String> {
+    let mut differences = Vec::new();
+    if from.status != to.status {
+        differences.push(messages::text(
+            "compare.statuses_differ",
+            &[("source", &from.status), ("destination", &to.status)],
+        ));
+    }
+    let (from_path, to_path) = (
+        site_path(&source.url, &from.url),
+        site_path(&destination.url, &to.url),
+    );
+    if from_path != to_path {
+        let path = |path: Option<String>, page: &Page| path.unwrap_or_else(|| page.url.to_string());
+        differences.push(messages::text(
+            "compare.redirects_differ",
+            &[
+                ("source", &path(from_path, from)),
+                ("destination", &path(to_path, to)),
+            ],
+        ));
+    }
+    // Error pages are only compared by their status.
+    if !(200..300).contains(&from.status) || !(200..300).contains(&to.status) {
+        return differences;
+    }
+
+    if let Some(marker) = health::error_marker(&to.body) {
+        if health::error_marker(&from.body).is_none() {
+            differences.push(messages::text(
+                "compare.destination_shows_error",
+                &[("marker", &format_args!("{marker:?}"))],
+            ));
+        }
+    }
+    let (from_title, to_title) = (title(&from.body), title(&to.body));
+    if from_title != to_title {
+        differences.push(messages::text(
+            "compare.titles_differ",
+            &[
+                (
+                    "source",
+                    &format_args!("{:?}", from_title.unwrap_or_default()),
+                ),
+                (
+                    "destination",
+                    &format_args!("{:?}", to_title.unwrap_or_default()),
+                ),
+            ],
+        ));
+    }
+    let (from_images, to_images) = (images(&from.body), images(&to.body));
+    if from_images.len() != to_images.len() {
+        differences.push(messages::text(
+            "compare.image_counts_differ",
+            &[
+                ("source", &from_images.len()),
+                ("destination", &to_images.len()),
+            ],
+        ));
+    }
+
+    let destination_host = to.url.host_str();
+    let uploads = to_images
+        .iter()
+        .filter_map(|src| to.url.join(src).ok())
+        .filter(|url| url.host_str() == destination_host && url.path().contains("/uploads/"))
+        .take(MAX_IMAGES);
+    for url in uploads {
+        let missing = match destination.client.get(url.clone()).send().await {
+            Ok(response) if response.status().is_success() => continue,
+            Ok(response) => messages::text(
+                "compare.image_answers",
+                &[
+                    ("path", &url.path()),
+                    ("status", &response.status().as_u16()),
+                ],
+            ),
+            Err(e) => messages::text(
+                "compare.image_didnt_load",
+                &[("path", &url.path()), ("error", &e.without_url())],
+            ),
+        };
+        differences.push(missing);
+    }
+    differences
+}
+
+/// A sample of `sample` pages from `site`'s sitemap, as paths on the site.
+/// A site without a sitemap has none.
+async fn sitemap_paths(site: &Site, sample: usize) -> Vec<String> {
+    if sample == 0 {
+        return Vec::new();
+    }
+    let mut pages = Vec::new();
+    for sitemap in SITEMAPS {
+        let body = match health::fetch(&site.client, &format!("{}{sitemap}", site.url)).await {
+            Ok(page) if page.status == 200 => page.body,
+            _ => continue,
+        };
+        let locations = locations(&body);
+        if locations.is_empty() {
+            continue;
+        }
+        if !body.contains("<sitemapindex") {
+            pages = locations;
+            break;
+        }
+        for sitemap in locations.iter().take(MAX_SITEMAPS) {
+            match health::fetch(&site.client, sitemap).await {
+                Ok(page) if page.status == 200 => pages.extend(self::locations(&page.body)),
+                _ => tracing::debug!(%sitemap, "couldn't read a sitemap"),
+            }
+        }
+        break;
+    }
+
+    let base = Url::parse(&site.url).ok();
+    let mut seen = HashSet::new();
+    let paths: Vec<String> = pages
+        .iter()
+        .filter_map(|page| site_path(&site.url, &base.as_ref()?.join(page).ok()?))
+        .filter(|path| seen.insert(path.clone()))
+        .collect();
+    if paths.len() <= sample {
+        return paths;
+    }
+    (0..sample)
+        .map(|i| paths[i * paths.len() / sample].clone())
+        .collect()
+}
+
+/// The `<loc>`s of a sitemap.
+fn locations(xml: &str) -> Vec<String> {
+    xml.split("<loc>")
+        .skip(1)
+        .filter_map(|rest| rest.split_once("</loc>"))
+        .map(|(loc, _)| {
+            let loc = loc.trim();
+            let loc = loc
+                .strip_prefix("<![CDATA[")
+                .and_then(|loc| loc.strip_suffix("]]>"))
+                .unwrap_or(loc);
+            unescape(loc.trim())
+        })
+        .filter(|loc| !loc.is_empty())
+        .collect()
+}
+
+/// `url` as a path on the site at `site_url`, with its query, or `None` if
+/// it's on another site.
+fn site_path(site_url: &str, url: &Url) -> Option<String> {
+    let site = Url::parse(site_url).ok()?;
+    if site.host_str() != url.host_str() {
+        return None;
+    }
+    let base = site.path().trim_end_matches('/');
+    let path = url.path().strip_prefix(base)?;
+    if !(path.is_empty() || path.starts_with('/')) {
+        return None;
+    }
+    let mut path = if path.is_empty() { "/" } else { path }.to_string();
+    if let Some(query) = url.query() {
+        path.push('?');
+        path.push_str(query);
+    }
+    Some(path)
+}
+
+/// The page's `<title>`, its whitespace collapsed.
+fn title(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let start = lower.find("<title")?;
+    let open = start + lower[start..].find('>')? + 1;
+    let close = open + lower[open..].find("</title>")?;
+    Some(
+        html[open..close]
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+}
+
+/// The `src` of each `<img>` on the page, or an empty one for those without.
+fn images(html: &str) -> Vec<String> {
+    let lower = html.to_ascii_lowercase();
+    lower
+        .match_indices("<img")
+        .filter_map(|(start, _)| {
+            let end = start + lower[start..].find('>')?;
+            let tag = &lower[start..end];
+            // Only `src` itself, not a lazy loader's `data-src`.
+            let Some((at, _)) = tag
+                .match_indices("src=")
+                .find(|(at, _)| tag[..*at].ends_with(char::is_whitespace))
+            else {
+                return Some(String::new());
+            };
+            let value = &html[start + at + "src=".len()..end];
+            let src = match value.chars().next() {
+                Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or_default(),
+                _ => value.split(char::is_whitespace).next().unwrap_or_default(),
+            };
+            Some(unescape(src))
+        })
+        .collect()
+}
+
+/// `text` with XML's and HTML's escapes of the characters it reserves undone.
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#039;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
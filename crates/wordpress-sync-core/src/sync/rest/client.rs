@@ -0,0 +1,216 @@
+//! Requests to a site's REST API, logged in with an Application Password.
+
+use std::path::Path;
+
+use reqwest::{RequestBuilder, Response};
+use serde_json::Value;
+use tokio::io::AsyncWriteExt;
+
+use super::super::http;
+use crate::credentials::{self, REST_PASSWORD_SERVICE};
+use crate::messages;
+use crate::profile::Profile;
+use crate::proxy;
+
+/// Items asked for per page of a listing, the most the API allows.
+const PER_PAGE: &str = "100";
+
+/// The REST API of the profile's server.
+pub(super) struct Client {
+    http: reqwest::Client,
+    /// The API root, such as `https://example.com/wp-json`.
+    root: String,
+    user: String,
+    password: String,
+}
+
+impl Client {
+    /// A client for `profile`'s server, with its stored Application Password.
+    pub(super) fn new(profile: &Profile) -> Result<Self, String> {
+        let reference = profile.secrets.rest_password.as_deref();
+        let password = credentials::resolve(reference, REST_PASSWORD_SERVICE, &profile.id)?
+            .ok_or_else(|| {
+                messages::text(
+                    "error.no_application_password_stored_name",
+                    &[("name", &profile.name)],
+                )
+            })?;
+        let route = proxy::route(&profile.remote.proxy)?;
+        let mut http = http::builder(route.as_ref(), profile.remote.address_family)?
+            .connect_timeout(profile.timeouts.connect());
+        if let Some(timeout) = profile.timeouts.read() {
+            http = http.read_timeout(timeout);
+        }
+        let http = http
+            .build()
+            .map_err(|e| messages::text("error.failed.set_up_http", &[("error", &e)]))?;
+        Ok(Self {
+            http,
+            root: format!("{}/wp-json", profile.remote.url.trim_end_matches('/')),
+            user: profile.remote.user.clone(),
+            password,
+        })
+    }
+
+    /// Log in, returning the user's display name. Fails unless they can edit
+    /// pages, which every content change needs.
+    pub(super) async fn log_in(&self) -> Result<String, String> {
+        let me = self.get("/wp/v2/users/me", &[("context", "edit")]).await?;
+        if me["capabilities"]["edit_pages"] != Value::Bool(true) {
+            return Err(messages::text(
+                "error.user_cant_edit_pages_server_so",
+                &[("user", &self.user)],
+            ));
+        }
+        Ok(me["name"].as_str().unwrap_or(&self.user).to_string())
+    }
+
+    /// Every item of a listing such as `/wp/v2/posts`, a page at a time.
+    pub(super) async fn list(
+        &self,
+        route: &str,
+        query: &[(&str, &str)],
+    ) -> Result<Vec<Value>, String> {
+        let mut items = Vec::new();
+        let mut page = 1u32;
+        loop {
+            let page_number = page.to_string();
+            let response = send(
+                self.request(reqwest::Method::GET, route)
+                    .query(query)
+                    .query(&[("per_page", PER_PAGE), ("page", &page_number)]),
+                route,
+            )
+            .await?;
+            let pages: u32 = response
+                .headers()
+                .get("X-WP-TotalPages")
+                .and_then(|value| value.to_str().ok()?.parse().ok())
+                .unwrap_or(1);
+            match json(response, route).await? {
+                Value::Array(batch) => items.extend(batch),
+                other => {
+                    return Err(messages::text(
+                        "error.route_returned_other_instead_list",
+                        &[("route", &route), ("other", &other)],
+                    ))
+                }
+            }
+            if page >= pages {
+                return Ok(items);
+            }
+            page += 1;
+        }
+    }
+
+    pub(super) async fn get(&self, route: &str, query: &[(&str, &str)]) -> Result<Value, String> {
+        let request = self.request(reqwest::Method::GET, route).query(query);
+        json(send(request, route).await?, route).await
+    }
+
+    /// Create or update through `route`, returning the item as saved.
+    pub(super) async fn post(&self, route: &str, body: &Value) -> Result<Value, String> {
+        let request = self.request(reqwest::Method::POST, route).json(body);
+        json(send(request, route).await?, route).await
+    }
+
+    /// Upload the file at `path` to the media library as `file_name`,
+    /// returning the new attachment. The file is streamed from disk, so a
+    /// large video isn't read into memory first.
+    pub(super) async fn upload(
+        &self,
+        path: &Path,
+        file_name: &str,
+        mime: &str,
+    ) -> Result<Value, String> {
+        let read_failed = |e: std::io::Error| {
+            messages::text(
+                "error.failed.read_path",
+                &[("path", &path.display()), ("error", &e)],
+            )
+        };
+        let file = tokio::fs::File::open(path).await.map_err(read_failed)?;
+        let size = file.metadata().await.map_err(read_failed)?.len();
+        let disposition = format!("attachment; filename=\"{}\"", file_name.replace('"', ""));
+        let request = self
+            .request(reqwest::Method::POST, "/wp/v2/media")
+            .header(reqwest::header::CONTENT_TYPE, mime)
+            .header(reqwest::header::CONTENT_DISPOSITION, disposition)
+            .header(reqwest::header::CONTENT_LENGTH, size)
+            .body(reqwest::Body::from(file));
+        json(send(request, "/wp/v2/media").await?, "/wp/v2/media").await
+    }
+
+    /// Download `url` to `path` a chunk at a time, returning its size.
+    pub(super) async fn download(&self, url: &str, path: &Path) -> Result<u64, String> {
+        let mut response = self.http.get(url).send().await.map_err(|e| {
+            messages::text("error.failed.download_url", &[("url", &url), ("error", &e)])
+        })?;
+        if !response.status().is_success() {
+            return Err(messages::text(
+                "error.failed.download_url",
+                &[("url", &url), ("error", &response.status())],
+            ));
+        }
+        let write_failed = |e: std::io::Error| {
+            messages::text(
+                "error.failed.write_path",
+                &[("path", &path.display()), ("error", &e)],
+            )
+        };
+        let mut file = tokio::fs::File::create(path).await.map_err(write_failed)?;
+        let mut size = 0;
+        while let Some(chunk) = response.chunk().await.map_err(|e| {
+            messages::text("error.failed.download_url", &[("url", &url), ("error", &e)])
+        })? {
+            file.write_all(&chunk).await.map_err(write_failed)?;
+            size += chunk.len() as u64;
+        }
+        file.flush().await.map_err(write_failed)?;
+        Ok(size)
+    }
+
+    fn request(&self, method: reqwest::Method, route: &str) -> RequestBuilder {
+        self.http
+            .request(method, format!("{}{route}", self.root))
+            .basic_auth(&self.user, Some(&self.password))
+    }
+}
+
+/// Send `request`, turning an error response into the API's own message.
+async fn send(request: RequestBuilder, route: &str) -> Result<Response, String> {
+    let response = request.send().await.map_err(|e| {
+        messages::text(
+            "error.request_route_failed",
+            &[("route", &route), ("error", &e)],
+        )
+    })?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    // WordPress errors are `{ "code": ..., "message": ... }`.
+    let message = response
+        .json::<Value>()
+        .await
+        .ok()
+        .and_then(|body| body["message"].as_str().map(str::to_string))
+        .unwrap_or_else(|| status.to_string());
+    Err(messages::text(
+        "error.route_failed_status_message",
+        &[
+            ("route", &route),
+            ("status", &status.as_u16()),
+            ("message", &message),
+        ],
+    ))
+}
+
+async fn json(response: Response, route: &str) -> Result<Value, String> {
+    response.json().await.map_err(|e| {
+        messages::text(
+            "error.route_returned_something_other_than_json",
+            &[("route", &route), ("error", &e)],
+        )
+    })
+}
@@ -0,0 +1,300 @@
+//! Content syncs over the WordPress REST API, for profiles whose host offers
+//! neither SSH nor FTP.
+//!
+//! Without a shell or file access on the server there are no files or tables
+//! to copy, so such a sync moves content instead, through the API on the
+//! server and wp-cli on this machine:
+//!
+//! - posts and pages, matched by type and slug, copied when the destination
+//!   lacks one or has an older version, with the site URL rewritten in their
+//!   text as a database sync would;
+//! - media, matched by file name, added when the destination lacks them;
+//! - the site settings in [`SETTINGS`], copied where they differ.
+//!
+//! The database components pick posts, pages, and settings; the file ones,
+//! media. Nothing is ever deleted on the destination, and items without a slug
+//! (drafts never saved with one) are left out.
+
+mod client;
+mod site;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::temp;
+use super::transfer::{OnProgress, TransferStats};
+use super::Job;
+use crate::messages::{self, Message};
+use crate::profile::{Profile, TransferMethod};
+use client::Client;
+use site::Site;
+
+/// A kind of post the sync copies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Post,
+    Page,
+}
+
+impl Kind {
+    const ALL: [Kind; 2] = [Kind::Post, Kind::Page];
+
+    /// The `post_type` WordPress stores.
+    fn post_type(self) -> &'static str {
+        match self {
+            Kind::Post => "post",
+            Kind::Page => "page",
+        }
+    }
+
+    /// Its REST API collection.
+    fn route(self) -> &'static str {
+        match self {
+            Kind::Post => "/wp/v2/posts",
+            Kind::Page => "/wp/v2/pages",
+        }
+    }
+}
+
+/// The statuses of the posts copied: everything but trash and auto-drafts.
+const STATUSES: &str = "publish,future,draft,pending,private";
+
+/// A post or page on one side.
+#[derive(Debug, Clone)]
+struct Post {
+    id: u64,
+    slug: String,
+    title: String,
+    content: String,
+    excerpt: String,
+    status: String,
+    /// `YYYY-MM-DD HH:MM:SS`, in UTC.
+    date_gmt: String,
+    /// `YYYY-MM-DD HH:MM:SS`, in UTC, so later versions compare greater.
+    modified_gmt: String,
+    menu_order: i64,
+}
+
+/// An item of a media library on one side.
+#[derive(Debug, Clone)]
+struct Media {
+    /// Its file's name, which matches it up with the other side's.
+    file_name: String,
+    title: String,
+    caption: String,
+    alt: String,
+    mime: String,
+    /// Where its file is: a URL on the server, a path on this machine.
+    file: String,
+}
+
+/// How a setting's value is typed in the API.
+#[derive(Debug, Clone, Copy)]
+enum SettingType {
+    String,
+    Integer,
+    Boolean,
+}
+
+/// The site settings copied, by API name, with the option each is stored in.
+/// The site URL, admin email, and settings naming posts by id are left alone.
+const SETTINGS: &[(&str, &str, SettingType)] = &[
+    ("title", "blogname", SettingType::String),
+    ("description", "blogdescription", SettingType::String),
+    ("timezone", "timezone_string", SettingType::String),
+    ("date_format", "date_format", SettingType::String),
+    ("time_format", "time_format", SettingType::String),
+    ("start_of_week", "start_of_week", SettingType::Integer),
+    ("posts_per_page", "posts_per_page", SettingType::Integer),
+    (
+        "default_comment_status",
+        "default_comment_status",
+        SettingType::String,
+    ),
+    (
+        "default_ping_status",
+        "default_ping_status",
+        SettingType::String,
+    ),
+    ("use_smilies", "use_smilies", SettingType::Boolean),
+];
+
+/// Settings by API name, each value in the one form both sides can compare:
+/// integers in decimal and booleans as `1` or `0`.
+type Settings = HashMap<&'static str, String>;
+
+/// One change to make on the destination.
+enum Task {
+    Post {
+        kind: Kind,
+        post: Post,
+        /// The destination's post to update, or `None` to create one.
+        existing: Option<u64>,
+    },
+    Media(Media),
+    Settings(Settings),
+}
+
+impl Task {
+    fn name(&self) -> String {
+        match self {
+            Task::Post { kind, post, .. } => format!("{} {}", kind.post_type(), post.slug),
+            Task::Media(media) => media.file_name.clone(),
+            Task::Settings(_) => "settings".to_string(),
+        }
+    }
+}
+
+/// Log in to `profile`'s REST API, returning the user's display name.
+pub(super) async fn log_in(profile: &Profile) -> Result<String, String> {
+    Client::new(profile)?.log_in().await
+}
+
+impl Job<'_> {
+    /// Whether this job moves content over the REST API instead of files and
+    /// tables.
+    pub(super) fn syncs_content(&self) -> bool {
+        self.profile.remote.transfer == TransferMethod::RestApi
+    }
+
+    /// Log in to the server's API, as the connect phase of a content sync.
+    pub(super) async fn check_content_api(&self) -> Result<(), String> {
+        let name = log_in(self.profile).await?;
+        tracing::info!(user = %name, "logged in to the REST API");
+        Ok(())
+    }
+
+    /// Copy posts, pages, media, and settings across, as the components ask.
+    pub(super) async fn sync_content(&self, on_progress: OnProgress<'_>) -> Result<(), String> {
+        let client = Client::new(self.profile)?;
+        let local = self.local();
+        let staging = temp::path(&self.id, "media");
+        let (api, wp_cli) = (Site::Api(&client), Site::WpCli(&local));
+        let (source, dest) = match self.direction {
+            super::Direction::Push => (&wp_cli, &api),
+            super::Direction::Pull => (&api, &wp_cli),
+        };
+
+        let tasks = self.content_tasks(source, dest).await?;
+        tracing::info!(changes = tasks.len(), "content to copy");
+        let mut stats = TransferStats {
+            files_total: Some(tasks.len() as u64),
+            ..TransferStats::default()
+        };
+        on_progress(&stats);
+        let result = async {
+            for task in tasks {
+                stats.current_file = Some(task.name());
+                on_progress(&stats);
+                match task {
+                    Task::Post {
+                        kind,
+                        post,
+                        existing,
+                    } => dest.save_post(kind, existing, &post, &staging).await?,
+                    Task::Media(media) => {
+                        let file = source.media_file(&media, &staging).await?;
+                        stats.bytes_transferred += dest.add_media(&media, &file).await?;
+                    }
+                    Task::Settings(settings) => dest.save_settings(&settings).await?,
+                }
+                stats.files_done += 1;
+            }
+            Ok::<(), String>(())
+        }
+        .await;
+        stats.current_file = None;
+        on_progress(&stats);
+        if let Err(e) = tokio::fs::remove_dir_all(&staging).await {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                self.warn(Message::new(
+                    "error.failed.remove_path",
+                    &[("path", &staging.display()), ("error", &e)],
+                ));
+            }
+        }
+        result
+    }
+
+    /// Work out every change the destination needs, before making any.
+    async fn content_tasks(&self, source: &Site<'_>, dest: &Site<'_>) -> Result<Vec<Task>, String> {
+        let mut tasks = Vec::new();
+        if self.components.database() {
+            let pairs = self.replacements();
+            let rewrite = |text: &str| {
+                pairs
+                    .iter()
+                    .fold(text.to_string(), |text, (from, to)| text.replace(from, to))
+            };
+            for kind in Kind::ALL {
+                let theirs: HashMap<String, Post> = dest
+                    .posts(kind)
+                    .await?
+                    .into_iter()
+                    .map(|post| (post.slug.clone(), post))
+                    .collect();
+                for mut post in source.posts(kind).await? {
+                    let existing = theirs.get(&post.slug);
+                    if post.slug.is_empty()
+                        || existing.is_some_and(|theirs| theirs.modified_gmt >= post.modified_gmt)
+                    {
+                        continue;
+                    }
+                    post.title = rewrite(&post.title);
+                    post.content = rewrite(&post.content);
+                    post.excerpt = rewrite(&post.excerpt);
+                    tasks.push(Task::Post {
+                        kind,
+                        existing: existing.map(|theirs| theirs.id),
+                        post,
+                    });
+                }
+            }
+        }
+        if self.components.files() {
+            let theirs: Vec<String> = dest
+                .media()
+                .await?
+                .into_iter()
+                .map(|media| media.file_name)
+                .collect();
+            tasks.extend(
+                source
+                    .media()
+                    .await?
+                    .into_iter()
+                    .filter(|media| !theirs.contains(&media.file_name))
+                    .map(Task::Media),
+            );
+        }
+        if self.components.database() {
+            let theirs = dest.settings().await?;
+            let changed: Settings = source
+                .settings()
+                .await?
+                .into_iter()
+                .filter(|(name, value)| theirs.get(name) != Some(value))
+                .collect();
+            if !changed.is_empty() {
+                tasks.push(Task::Settings(changed));
+            }
+        }
+        Ok(tasks)
+    }
+}
+
+/// Where a media file is staged on its way from the server.
+async fn staged(staging: &Path, media: &Media) -> Result<PathBuf, String> {
+    staged_file(staging, &media.file_name).await
+}
+
+/// A path for `name` in the staging directory, which is created if need be.
+async fn staged_file(staging: &Path, name: &str) -> Result<PathBuf, String> {
+    tokio::fs::create_dir_all(staging).await.map_err(|e| {
+        messages::text(
+            "error.failed.create_path",
+            &[("path", &staging.display()), ("error", &e)],
+        )
+    })?;
+    Ok(staging.join(name))
+}
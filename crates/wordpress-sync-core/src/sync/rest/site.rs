@@ -0,0 +1,396 @@
+//! The two sides of a content sync: the server, through its REST API, and the
+//! local install, through wp-cli.
+
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Map, Value};
+
+use super::client::Client;
+use super::{staged, staged_file, Kind, Media, Post, SettingType, Settings, SETTINGS, STATUSES};
+use crate::messages;
+use crate::sync::endpoint::Endpoint;
+
+/// The fields of the local posts read.
+const POST_FIELDS: &str = "ID,post_name,post_title,post_content,post_excerpt,post_status,\
+                           post_date_gmt,post_modified_gmt,menu_order";
+
+/// PHP printing the local media library as JSON, for `wp eval`.
+const LIST_MEDIA: &str = r#"
+$items = array();
+foreach (get_posts(array('post_type' => 'attachment', 'post_status' => 'inherit', 'numberposts' => -1)) as $item) {
+    $file = get_attached_file($item->ID);
+    if ($file) {
+        $items[] = array(
+            'file' => $file,
+            'title' => $item->post_title,
+            'caption' => $item->post_excerpt,
+            'alt' => (string) get_post_meta($item->ID, '_wp_attachment_image_alt', true),
+            'mime' => $item->post_mime_type,
+        );
+    }
+}
+echo wp_json_encode($items);
+"#;
+
+/// One side of a content sync.
+pub(super) enum Site<'a> {
+    /// The server.
+    Api(&'a Client),
+    /// The local install.
+    WpCli(&'a Endpoint<'a>),
+}
+
+impl Site<'_> {
+    /// Every post of `kind` worth copying.
+    pub(super) async fn posts(&self, kind: Kind) -> Result<Vec<Post>, String> {
+        match self {
+            Site::Api(client) => {
+                let query = [("context", "edit"), ("status", STATUSES)];
+                let items = client.list(kind.route(), &query).await?;
+                Ok(items
+                    .iter()
+                    .map(|item| Post {
+                        id: item["id"].as_u64().unwrap_or(0),
+                        slug: text(&item["slug"]),
+                        title: text(&item["title"]["raw"]),
+                        content: text(&item["content"]["raw"]),
+                        excerpt: text(&item["excerpt"]["raw"]),
+                        status: text(&item["status"]),
+                        date_gmt: date(&text(&item["date_gmt"])),
+                        modified_gmt: date(&text(&item["modified_gmt"])),
+                        menu_order: item["menu_order"].as_i64().unwrap_or(0),
+                    })
+                    .collect())
+            }
+            Site::WpCli(endpoint) => {
+                let post_type = format!("--post_type={}", kind.post_type());
+                let statuses = format!("--post_status={STATUSES}");
+                let fields = format!("--fields={POST_FIELDS}");
+                let output = endpoint
+                    .wp(&[
+                        "post",
+                        "list",
+                        &post_type,
+                        &statuses,
+                        &fields,
+                        "--format=json",
+                    ])
+                    .await?;
+                let items: Vec<Value> = serde_json::from_str(&output).map_err(|e| {
+                    messages::text(
+                        "error.wp_post_list_returned_unexpected_output",
+                        &[("error", &e)],
+                    )
+                })?;
+                Ok(items
+                    .iter()
+                    .map(|item| Post {
+                        id: number(&item["ID"]) as u64,
+                        slug: text(&item["post_name"]),
+                        title: text(&item["post_title"]),
+                        content: text(&item["post_content"]),
+                        excerpt: text(&item["post_excerpt"]),
+                        status: text(&item["post_status"]),
+                        date_gmt: date(&text(&item["post_date_gmt"])),
+                        modified_gmt: date(&text(&item["post_modified_gmt"])),
+                        menu_order: number(&item["menu_order"]),
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    /// Update the post `existing` to match `post`, or create it if `None`.
+    /// A new post keeps its source's date; an updated one keeps its own. Files
+    /// wp-cli needs are written to `staging`.
+    pub(super) async fn save_post(
+        &self,
+        kind: Kind,
+        existing: Option<u64>,
+        post: &Post,
+        staging: &Path,
+    ) -> Result<(), String> {
+        match self {
+            Site::Api(client) => {
+                let mut body = Map::new();
+                body.insert("title".into(), json!(post.title));
+                body.insert("content".into(), json!(post.content));
+                body.insert("status".into(), json!(post.status));
+                match kind {
+                    Kind::Post => body.insert("excerpt".into(), json!(post.excerpt)),
+                    Kind::Page => body.insert("menu_order".into(), json!(post.menu_order)),
+                };
+                let route = match existing {
+                    Some(id) => format!("{}/{id}", kind.route()),
+                    None => {
+                        body.insert("slug".into(), json!(post.slug));
+                        if !post.date_gmt.is_empty() {
+                            body.insert("date_gmt".into(), json!(post.date_gmt.replace(' ', "T")));
+                        }
+                        kind.route().to_string()
+                    }
+                };
+                client.post(&route, &Value::Object(body)).await?;
+                Ok(())
+            }
+            Site::WpCli(endpoint) => {
+                // The content goes in a file: a long post would overflow the
+                // command line, on Windows especially.
+                let content =
+                    staged_file(staging, &format!("{}-{}.html", kind.post_type(), post.slug))
+                        .await?;
+                tokio::fs::write(&content, &post.content)
+                    .await
+                    .map_err(|e| {
+                        messages::text(
+                            "error.failed.write_path",
+                            &[("path", &content.display()), ("error", &e)],
+                        )
+                    })?;
+                let content = content.display().to_string();
+                let mut args = vec![
+                    format!("--post_title={}", post.title),
+                    format!("--post_excerpt={}", post.excerpt),
+                    format!("--post_status={}", post.status),
+                    format!("--menu_order={}", post.menu_order),
+                ];
+                match existing {
+                    Some(id) => {
+                        args.splice(
+                            0..0,
+                            ["post".into(), "update".into(), id.to_string(), content],
+                        );
+                    }
+                    None => {
+                        args.splice(0..0, ["post".into(), "create".into(), content]);
+                        args.push(format!("--post_type={}", kind.post_type()));
+                        args.push(format!("--post_name={}", post.slug));
+                        args.push("--porcelain".to_string());
+                        if !post.date_gmt.is_empty() {
+                            args.push(format!("--post_date_gmt={}", post.date_gmt));
+                        }
+                    }
+                }
+                let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                endpoint.wp(&args).await?;
+                Ok(())
+            }
+        }
+    }
+
+    /// The media library.
+    pub(super) async fn media(&self) -> Result<Vec<Media>, String> {
+        match self {
+            Site::Api(client) => {
+                let items = client.list("/wp/v2/media", &[("context", "edit")]).await?;
+                Ok(items
+                    .iter()
+                    .map(|item| {
+                        let url = text(&item["source_url"]);
+                        let file = item["media_details"]["file"]
+                            .as_str()
+                            .unwrap_or(&url)
+                            .to_string();
+                        Media {
+                            file_name: base_name(&file).to_string(),
+                            title: text(&item["title"]["raw"]),
+                            caption: text(&item["caption"]["raw"]),
+                            alt: text(&item["alt_text"]),
+                            mime: text(&item["mime_type"]),
+                            file: url,
+                        }
+                    })
+                    .collect())
+            }
+            Site::WpCli(endpoint) => {
+                let output = endpoint.wp(&["eval", LIST_MEDIA]).await?;
+                let items: Vec<Value> = serde_json::from_str(output.trim()).map_err(|e| {
+                    messages::text(
+                        "error.listing_local_media_returned_unexpected_output",
+                        &[("error", &e)],
+                    )
+                })?;
+                Ok(items
+                    .iter()
+                    .map(|item| {
+                        let file = text(&item["file"]);
+                        Media {
+                            file_name: base_name(&file).to_string(),
+                            title: text(&item["title"]),
+                            caption: text(&item["caption"]),
+                            alt: text(&item["alt"]),
+                            mime: text(&item["mime"]),
+                            file,
+                        }
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    /// A local path to `media`'s file, downloading it first to `staging` from
+    /// the server.
+    pub(super) async fn media_file(
+        &self,
+        media: &Media,
+        staging: &Path,
+    ) -> Result<PathBuf, String> {
+        match self {
+            Site::Api(client) => {
+                let path = staged(staging, media).await?;
+                client.download(&media.file, &path).await?;
+                Ok(path)
+            }
+            Site::WpCli(_) => Ok(PathBuf::from(&media.file)),
+        }
+    }
+
+    /// Add `media`, its file at `file`, to the library, returning the file's size.
+    pub(super) async fn add_media(&self, media: &Media, file: &Path) -> Result<u64, String> {
+        let size = tokio::fs::metadata(file)
+            .await
+            .map_err(|e| {
+                messages::text(
+                    "error.failed.read_path",
+                    &[("path", &file.display()), ("error", &e)],
+                )
+            })?
+            .len();
+        match self {
+            Site::Api(client) => {
+                let added = client.upload(file, &media.file_name, &media.mime).await?;
+                let id = added["id"]
+                    .as_u64()
+                    .ok_or_else(|| messages::text("error.upload_made_no_attachment", &[]))?;
+                let details = json!({
+                    "title": media.title,
+                    "caption": media.caption,
+                    "alt_text": media.alt,
+                });
+                client.post(&format!("/wp/v2/media/{id}"), &details).await?;
+            }
+            Site::WpCli(endpoint) => {
+                let mut args = vec![
+                    "media".to_string(),
+                    "import".to_string(),
+                    file.display().to_string(),
+                    format!("--title={}", media.title),
+                    "--porcelain".to_string(),
+                ];
+                if !media.caption.is_empty() {
+                    args.push(format!("--caption={}", media.caption));
+                }
+                if !media.alt.is_empty() {
+                    args.push(format!("--alt={}", media.alt));
+                }
+                let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                endpoint.wp(&args).await?;
+            }
+        }
+        Ok(size)
+    }
+
+    /// The values of [`SETTINGS`].
+    pub(super) async fn settings(&self) -> Result<Settings, String> {
+        let mut settings = Settings::new();
+        match self {
+            Site::Api(client) => {
+                let values = client.get("/wp/v2/settings", &[]).await?;
+                for &(name, _, kind) in SETTINGS {
+                    let value = &values[name];
+                    let value = match kind {
+                        SettingType::String => value.as_str().map(str::to_string),
+                        SettingType::Integer => value.as_i64().map(|value| value.to_string()),
+                        SettingType::Boolean => value.as_bool().map(|on| flag(on).to_string()),
+                    };
+                    // Settings the server doesn't expose are left alone.
+                    if let Some(value) = value {
+                        settings.insert(name, value);
+                    }
+                }
+            }
+            Site::WpCli(endpoint) => {
+                for &(name, option, kind) in SETTINGS {
+                    let value = endpoint.wp(&["option", "get", option]).await?;
+                    let value = value.trim();
+                    let value = match kind {
+                        SettingType::String | SettingType::Integer => value.to_string(),
+                        SettingType::Boolean => flag(!value.is_empty() && value != "0").to_string(),
+                    };
+                    settings.insert(name, value);
+                }
+            }
+        }
+        Ok(settings)
+    }
+
+    /// Change the settings in `settings`, leaving the others as they are.
+    pub(super) async fn save_settings(&self, settings: &Settings) -> Result<(), String> {
+        match self {
+            Site::Api(client) => {
+                let mut body = Map::new();
+                for &(name, _, kind) in SETTINGS {
+                    let Some(value) = settings.get(name) else {
+                        continue;
+                    };
+                    let value = match kind {
+                        SettingType::String => json!(value),
+                        SettingType::Integer => json!(value.parse::<i64>().unwrap_or(0)),
+                        SettingType::Boolean => json!(value == "1"),
+                    };
+                    body.insert(name.to_string(), value);
+                }
+                client.post("/wp/v2/settings", &Value::Object(body)).await?;
+            }
+            Site::WpCli(endpoint) => {
+                for &(name, option, _) in SETTINGS {
+                    if let Some(value) = settings.get(name) {
+                        endpoint.wp(&["option", "update", option, value]).await?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A string field, or empty if it's missing or null.
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Number(number) => number.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// A numeric field, which wp-cli may give as a string.
+fn number(value: &Value) -> i64 {
+    value
+        .as_i64()
+        .or_else(|| value.as_str()?.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// A UTC date as `YYYY-MM-DD HH:MM:SS`, whichever form it came in, or empty
+/// for the zero date of a post never published.
+fn date(value: &str) -> String {
+    let value = value.replace('T', " ");
+    if value.starts_with("0000") {
+        String::new()
+    } else {
+        value
+    }
+}
+
+fn flag(on: bool) -> &'static str {
+    if on {
+        "1"
+    } else {
+        "0"
+    }
+}
+
+/// The last segment of a path or URL.
+fn base_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
@@ -0,0 +1,268 @@
+//! Checking a rollback before and after it's run: what a restore point would
+//! put back, and whether it did.
+//!
+//! A restore point records the exact row count of each table it saves, and
+//! the SHA-256 of a few of the files. Planning a rollback sets those beside
+//! the destination as it is now, without changing anything; after a rollback
+//! the same counts and hashes are taken again and kept with the restore
+//! point, so a table or a file that didn't come back shows. A live site
+//! writes rows of its own meanwhile, so a count a few rows off in a log or
+//! session table isn't necessarily the rollback's doing. Restore points made
+//! before any of this was recorded plan without their tables' rows, and
+//! aren't checked afterwards.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::backup::{self, RestorePoint};
+use super::endpoint::Endpoint;
+use super::mysql::Mysql;
+use super::{db, transfer, Direction, ProgressSink};
+use crate::error::SyncError;
+use crate::profile;
+
+/// How many of a restore point's files are hashed.
+const SPOT_CHECKS: usize = 8;
+
+/// Tables counted in one query.
+const COUNT_BATCH: usize = 50;
+
+/// What rolling back to a restore point would change on its destination.
+#[derive(Debug, Clone, Serialize)]
+pub struct RollbackPlan {
+    pub restore_point_id: String,
+    pub profile_id: String,
+    /// The sync's direction, which says which side is put back.
+    pub direction: Direction,
+    pub created_at: DateTime<Utc>,
+    /// When it was rolled back to before, if it was.
+    pub rolled_back_at: Option<DateTime<Utc>>,
+    /// Whether the backup is still where the restore point says it is.
+    pub backup_found: bool,
+    /// Whether the saved database is imported.
+    pub database: bool,
+    /// Each table the saved database puts back, with its rows then and now.
+    /// Empty for a restore point from before its tables were recorded.
+    pub tables: Vec<TableRevert>,
+    /// Tables there now that the saved database hasn't, which the import
+    /// leaves as they are.
+    pub tables_kept: Vec<String>,
+    /// Files put back as they were, relative to the WordPress root.
+    pub files_restored: Vec<String>,
+    /// Files the sync added, which are deleted.
+    pub files_removed: Vec<String>,
+    /// Directories the sync added, deleted if they're empty again.
+    pub dirs_removed: Vec<String>,
+}
+
+/// One table a rollback puts back.
+#[derive(Debug, Clone, Serialize)]
+pub struct TableRevert {
+    pub table: String,
+    /// Its rows when the restore point was made.
+    pub rows_then: u64,
+    /// Its rows now, or `None` if it's gone.
+    pub rows_now: Option<u64>,
+}
+
+/// How the destination compared with its restore point after a rollback.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoreVerification {
+    pub verified_at: DateTime<Utc>,
+    pub tables: Vec<TableCheck>,
+    pub files: Vec<FileCheck>,
+    /// Whether every table has its rows back and every file its contents.
+    pub passed: bool,
+}
+
+/// One table's rows after a rollback.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableCheck {
+    pub table: String,
+    pub expected_rows: u64,
+    /// `None` if the table is missing.
+    pub rows: Option<u64>,
+}
+
+/// One file's contents after a rollback.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileCheck {
+    /// Relative to the WordPress root.
+    pub path: String,
+    /// Whether its SHA-256 is the one recorded; `false` too when it's missing.
+    pub matches: bool,
+}
+
+/// What rolling back to restore point `id` would change, worked out without
+/// changing anything. An untrusted host key is passed to `sink`, as for a sync.
+pub async fn plan_rollback(id: &str, sink: &dyn ProgressSink) -> Result<RollbackPlan, SyncError> {
+    let point = backup::load(id).map_err(SyncError::config)?;
+    let profile = profile::load(&point.profile_id).map_err(SyncError::config)?;
+    let (remote, mysql) = backup::connect_to_backup(&profile, &point, sink).await?;
+    let side = backup::side(&profile, &point, remote.as_ref());
+    let result = plan(&side, &point, mysql.as_ref()).await;
+    drop(mysql);
+    if let Some(remote) = &remote {
+        remote.close().await;
+    }
+    result
+}
+
+async fn plan(
+    side: &Endpoint<'_>,
+    point: &RestorePoint,
+    mysql: Option<&Mysql>,
+) -> Result<RollbackPlan, SyncError> {
+    let backup_found = backup::exists(side, &point.location)
+        .await
+        .map_err(SyncError::transfer)?;
+    let (tables, tables_kept) = if point.database {
+        let now = row_counts(side, mysql).await.map_err(SyncError::database)?;
+        let tables = point
+            .table_rows
+            .iter()
+            .map(|(table, &rows_then)| TableRevert {
+                table: table.clone(),
+                rows_then,
+                rows_now: now.get(table).copied(),
+            })
+            .collect();
+        let kept = match point.table_rows.is_empty() {
+            true => Vec::new(),
+            false => now
+                .into_keys()
+                .filter(|table| !point.table_rows.contains_key(table))
+                .collect(),
+        };
+        (tables, kept)
+    } else {
+        (Vec::new(), Vec::new())
+    };
+    let (dirs_removed, files_removed) = point
+        .added
+        .iter()
+        .cloned()
+        .partition(|path| path.ends_with('/'));
+    Ok(RollbackPlan {
+        restore_point_id: point.id.clone(),
+        profile_id: point.profile_id.clone(),
+        direction: point.direction,
+        created_at: point.created_at,
+        rolled_back_at: point.rolled_back_at,
+        backup_found,
+        database: point.database,
+        tables,
+        tables_kept,
+        files_restored: point.files.clone(),
+        files_removed,
+        dirs_removed,
+    })
+}
+
+/// Compare `side`, just rolled back, with what `point` recorded of it.
+/// `None` for a restore point that recorded nothing to compare.
+pub(super) async fn verify(
+    side: &Endpoint<'_>,
+    point: &RestorePoint,
+    mysql: Option<&Mysql>,
+) -> Result<Option<RestoreVerification>, String> {
+    if point.table_rows.is_empty() && point.checksums.is_empty() {
+        return Ok(None);
+    }
+    let now = match point.table_rows.is_empty() {
+        true => BTreeMap::new(),
+        false => row_counts(side, mysql).await?,
+    };
+    let tables: Vec<TableCheck> = point
+        .table_rows
+        .iter()
+        .map(|(table, &expected_rows)| TableCheck {
+            table: table.clone(),
+            expected_rows,
+            rows: now.get(table).copied(),
+        })
+        .collect();
+    let paths: Vec<String> = point.checksums.keys().cloned().collect();
+    let hashes = spot_hashes_of(side, &paths).await?;
+    let files: Vec<FileCheck> = point
+        .checksums
+        .iter()
+        .map(|(path, expected)| FileCheck {
+            path: path.clone(),
+            matches: hashes.get(path) == Some(expected),
+        })
+        .collect();
+    let passed = tables
+        .iter()
+        .all(|check| check.rows == Some(check.expected_rows))
+        && files.iter().all(|check| check.matches);
+    Ok(Some(RestoreVerification {
+        verified_at: Utc::now(),
+        tables,
+        files,
+        passed,
+    }))
+}
+
+/// The exact number of rows in each table of `side`'s database, read
+/// through `mysql` when there's no wp-cli to do it.
+pub(super) async fn row_counts(
+    side: &Endpoint<'_>,
+    mysql: Option<&Mysql>,
+) -> Result<BTreeMap<String, u64>, String> {
+    let tables = match mysql {
+        Some(mysql) => mysql.tables().await?,
+        None => db::tables(side).await?,
+    };
+    let mut counts = BTreeMap::new();
+    for batch in tables.chunks(COUNT_BATCH) {
+        // Each row is named by its place in the batch, which needs no quoting.
+        let sql = batch
+            .iter()
+            .enumerate()
+            .map(|(i, table)| format!("SELECT {i}, COUNT(*) FROM `{}`", table.replace('`', "``")))
+            .collect::<Vec<_>>()
+            .join(" UNION ALL ");
+        let rows = match mysql {
+            Some(mysql) => mysql.query(&sql).await?,
+            None => db::query(side, &sql).await?,
+        };
+        for row in rows {
+            let (Some(i), Some(count)) = (
+                row.first().and_then(|i| i.trim().parse::<usize>().ok()),
+                row.get(1)
+                    .and_then(|count| count.trim().parse::<u64>().ok()),
+            ) else {
+                continue;
+            };
+            if let Some(table) = batch.get(i) {
+                counts.insert(table.clone(), count);
+            }
+        }
+    }
+    Ok(counts)
+}
+
+/// The SHA-256 of a few of `files`, spread through them, as they are on
+/// `side` now. Files that can't be read are left out.
+pub(super) async fn spot_hashes(
+    side: &Endpoint<'_>,
+    files: &[String],
+) -> Result<BTreeMap<String, String>, String> {
+    let step = files.len().div_ceil(SPOT_CHECKS).max(1);
+    let sample: Vec<String> = files.iter().step_by(step).cloned().collect();
+    spot_hashes_of(side, &sample).await
+}
+
+async fn spot_hashes_of(
+    side: &Endpoint<'_>,
+    paths: &[String],
+) -> Result<BTreeMap<String, String>, String> {
+    if paths.is_empty() {
+        return Ok(BTreeMap::new());
+    }
+    let hashes = transfer::sha256_all(side, &side.root(), paths).await?;
+    Ok(hashes.into_iter().collect())
+}
@@ -0,0 +1,187 @@
+//! Picking up an interrupted sync where it stopped.
+//!
+//! While a job runs, its manifest in `~/.wordpress-sync/jobs/<job-id>.json`
+//! records the phases it has finished. A job that fails, or whose process dies,
+//! keeps its manifest, its staged database dump, and its restore point, and
+//! [`resume`] runs it again under the same id, skipping every finished phase
+//! but connect, which each run needs. An interrupted file transfer starts over,
+//! but rsync and the SFTP/FTP tree diff skip files the destination already has,
+//! and rsync keeps partly sent files, so little is sent twice. The manifest is
+//! removed once the job succeeds or is cancelled.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+
+use super::{
+    at_rest, execute, Components, Confirmation, Direction, Job, JobHandle, Phase, PhaseReport,
+    ProgressSink, SyncSummary,
+};
+use crate::error::SyncError;
+use crate::messages;
+use crate::paths;
+use crate::profile::{self, Profile};
+
+/// What a job has got through, as saved after each phase.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct Manifest {
+    pub job_id: String,
+    pub profile_id: String,
+    pub direction: Direction,
+    pub components: Components,
+    pub started_at: DateTime<Utc>,
+    /// The phases finished so far, in order.
+    pub phases: Vec<PhaseReport>,
+    pub restore_point_id: Option<String>,
+    /// The commit the local site's git repository was at before a pull.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_head: Option<String>,
+    /// The profile preset the job runs, whose options it's resumed with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+}
+
+impl Manifest {
+    pub(super) fn new(
+        job_id: &str,
+        profile: &Profile,
+        direction: Direction,
+        components: Components,
+    ) -> Self {
+        Self {
+            job_id: job_id.to_string(),
+            profile_id: profile.id.clone(),
+            direction,
+            components,
+            started_at: Utc::now(),
+            phases: Vec::new(),
+            restore_point_id: None,
+            git_head: None,
+            preset: None,
+        }
+    }
+
+    /// Whether job `job_id` has a manifest, and so can be resumed.
+    pub(super) fn exists(job_id: &str) -> bool {
+        path_for(job_id).is_ok_and(|path| path.exists())
+    }
+
+    pub(super) fn finished(&self, phase: Phase) -> bool {
+        self.phases.iter().any(|report| report.phase == phase)
+    }
+
+    /// Record a finished phase, replacing the report of an earlier run of it.
+    pub(super) fn finish(&mut self, report: PhaseReport) {
+        match self
+            .phases
+            .iter_mut()
+            .find(|other| other.phase == report.phase)
+        {
+            Some(other) => *other = report,
+            None => self.phases.push(report),
+        }
+    }
+
+    fn load(job_id: &str) -> Result<Self, String> {
+        let path = path_for(job_id)?;
+        let raw = match fs::read_to_string(&path) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(messages::text(
+                    "error.sync_job_id_cant_resumed_finished",
+                    &[("job_id", &job_id)],
+                ));
+            }
+            Err(e) => {
+                return Err(messages::text(
+                    "error.failed.read_path",
+                    &[("path", &path.display()), ("error", &e)],
+                ))
+            }
+        };
+        serde_json::from_str(&raw).map_err(|e| {
+            messages::text(
+                "error.invalid_job_manifest_path",
+                &[("path", &path.display()), ("error", &e)],
+            )
+        })
+    }
+
+    pub(super) fn save(&self) -> Result<(), String> {
+        let path = path_for(&self.job_id)?;
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| messages::text("error.failed.serialize_job_manifest", &[("error", &e)]))?;
+        let dir = paths::job_manifests_dir()?;
+        fs::create_dir_all(&dir).map_err(|e| {
+            messages::text(
+                "error.failed.create_path",
+                &[("path", &dir.display()), ("error", &e)],
+            )
+        })?;
+        paths::atomic_write(&path, json)
+    }
+
+    pub(super) fn remove(&self) {
+        if let Ok(path) = path_for(&self.job_id) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+fn path_for(job_id: &str) -> Result<PathBuf, String> {
+    if job_id.is_empty()
+        || !job_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return Err(messages::text(
+            "error.invalid_job_id",
+            &[("job_id", &format_args!("{job_id:?}"))],
+        ));
+    }
+    Ok(paths::job_manifests_dir()?.join(format!("{job_id}.json")))
+}
+
+/// Continue the interrupted sync `handle.id` from its last finished phase,
+/// with its original direction and components and the profile as it is now.
+/// Like [`run`](super::run), it is recorded in the history, replacing the
+/// failed attempt.
+pub async fn resume(handle: &JobHandle, sink: &dyn ProgressSink) -> Result<SyncSummary, SyncError> {
+    let mut manifest = Manifest::load(&handle.id).map_err(SyncError::config)?;
+    let mut profile = profile::load(&manifest.profile_id).map_err(SyncError::config)?;
+    if let Some(name) = &manifest.preset {
+        let preset = profile.preset(name).map_err(SyncError::config)?.clone();
+        preset.apply(&mut profile);
+    }
+    // A dump in the temp directory may not have survived a restart, or may
+    // not decrypt; make it again.
+    let dump = Job::dump_path_for(&manifest.job_id);
+    let readable = dump.exists() && {
+        // Reading its start decrypts the first chunk, which another key fails.
+        let read = match at_rest::open(&dump).await {
+            Ok(mut reader) => reader.read(&mut [0; 1]).await.map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
+        match read {
+            Ok(_) => true,
+            Err(e) => {
+                tracing::warn!(error = %e, "dumping the database again");
+                false
+            }
+        }
+    };
+    if !readable {
+        manifest.phases.retain(|report| {
+            !matches!(
+                report.phase,
+                Phase::DumpDatabase | Phase::SearchReplace | Phase::Anonymize
+            )
+        });
+    }
+    tracing::info!(job_id = %handle.id, finished = manifest.phases.len(), "resuming sync");
+    execute(&profile, manifest, Confirmation::Resumed, handle, sink).await
+}
@@ -0,0 +1,132 @@
+//! Trying a job again after a failure that may not happen twice, such as a
+//! connection dropped by flaky Wi-Fi.
+//!
+//! A retry reconnects and runs the job again from the phase that failed, the
+//! way [`resume`](super::resume) does, after a wait that grows with each
+//! attempt. Only errors whose message looks like a network or server hiccup
+//! are retried; a missing file or a rejected password fails at once.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use super::{Job, Phase};
+use crate::error::SyncError;
+use crate::profile::RetryPolicy;
+
+/// Lowercased fragments of the messages transient failures produce: from the
+/// OS, russh, rsync, and MySQL.
+const TRANSIENT: &[&str] = &[
+    "connection reset",
+    "connection aborted",
+    "connection closed",
+    "connection lost",
+    "broken pipe",
+    "timed out",
+    "timeout",
+    "network is unreachable",
+    "network is down",
+    "no route to host",
+    "temporary failure in name resolution",
+    "unexpected eof",
+    "disconnected",
+    "channel closed",
+    "error in socket io",
+    "error in rsync protocol data stream",
+    "lost connection to mysql server",
+    "mysql server has gone away",
+    "deadlock found",
+];
+
+/// A failed attempt that is about to be retried, sent before the wait.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncRetry {
+    pub job_id: String,
+    /// The phase that failed.
+    pub phase: Phase,
+    /// The attempt about to start, from 2.
+    pub attempt: u32,
+    pub max_attempts: u32,
+    /// How long the job waits before that attempt.
+    pub delay_ms: u64,
+    pub error: SyncError,
+}
+
+/// Whether `error` looks like it may not happen if the job simply tries again.
+pub(super) fn is_transient(error: &SyncError) -> bool {
+    if !error.retryable() || matches!(error, SyncError::InProgress { .. }) {
+        return false;
+    }
+    let message = error.message().to_lowercase();
+    TRANSIENT.iter().any(|fragment| message.contains(fragment))
+}
+
+/// The attempts made since the job last got further, and the waits between them.
+pub(super) struct Backoff<'a> {
+    policy: &'a RetryPolicy,
+    /// Phases finished when the count of attempts was last reset.
+    finished: usize,
+    /// Attempts made so far at the phase the job is stuck on.
+    attempts: u32,
+}
+
+impl<'a> Backoff<'a> {
+    pub(super) fn new(policy: &'a RetryPolicy, finished: usize) -> Self {
+        Self {
+            policy,
+            finished,
+            attempts: 0,
+        }
+    }
+
+    pub(super) fn max_attempts(&self) -> u32 {
+        self.policy.max_attempts.max(1)
+    }
+
+    /// Count a failed attempt, with `finished` phases done, and return how long
+    /// to wait before the next, or `None` once the attempts have run out. An
+    /// attempt that finished more phases than the last starts the count again.
+    pub(super) fn next(&mut self, finished: usize) -> Option<(u32, Duration)> {
+        if finished > self.finished {
+            self.finished = finished;
+            self.attempts = 0;
+        }
+        self.attempts += 1;
+        if self.attempts >= self.max_attempts() {
+            return None;
+        }
+        let doublings = (self.attempts - 1).min(31);
+        let backoff = self
+            .policy
+            .initial_backoff_ms
+            .saturating_mul(1 << doublings)
+            .min(self.policy.max_backoff_ms);
+        let delay_ms = if self.policy.jitter {
+            backoff / 2 + random() % (backoff / 2 + 1)
+        } else {
+            backoff
+        };
+        Some((self.attempts + 1, Duration::from_millis(delay_ms)))
+    }
+}
+
+/// A random number, from the seed std gives each hash map.
+fn random() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+impl Job<'_> {
+    /// Whether `phase` can safely run again after failing part way.
+    pub(super) fn retries(&self, phase: Phase) -> bool {
+        match phase {
+            // Rewriting the dump is local, and doing it twice would rewrite
+            // URLs that contain their own replacement again.
+            Phase::SearchReplace => false,
+            // A second attempt would snapshot users the first may have replaced.
+            Phase::ImportDatabase => !self.profile.preserve_users,
+            _ => true,
+        }
+    }
+}
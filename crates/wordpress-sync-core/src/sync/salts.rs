@@ -0,0 +1,68 @@
+//! WordPress's auth keys and salts: fresh ones for a cloned site's
+//! `wp-config.php`, and new ones for a server a push has set up, so the
+//! logins and cookies of the site it was copied from don't work there.
+
+use super::endpoint::Endpoint;
+use super::{Direction, Job};
+
+use crate::messages::Message;
+
+/// The keys and salts WordPress reads from `wp-config.php`.
+pub(super) const SALTS: [&str; 8] = [
+    "AUTH_KEY",
+    "SECURE_AUTH_KEY",
+    "LOGGED_IN_KEY",
+    "NONCE_KEY",
+    "AUTH_SALT",
+    "SECURE_AUTH_SALT",
+    "LOGGED_IN_SALT",
+    "NONCE_SALT",
+];
+
+/// The characters salts are made of, as WordPress's own generator uses.
+const SALT_CHARS: &[u8] =
+    b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789!@#$%^&*()-_[]{}<>~+=,.;:/?|";
+
+/// 64 random characters, from the random bytes of v4 uuids: all but the two
+/// that hold the version and variant. It never starts with `-`, which wp-cli
+/// would take for an option.
+pub(super) fn salt() -> String {
+    std::iter::repeat_with(uuid::Uuid::new_v4)
+        .flat_map(|uuid| {
+            let bytes = *uuid.as_bytes();
+            (0..bytes.len())
+                .filter(|&i| i != 6 && i != 8)
+                .map(move |i| bytes[i])
+        })
+        .map(|byte| SALT_CHARS[usize::from(byte) % SALT_CHARS.len()] as char)
+        .skip_while(|&c| c == '-')
+        .take(64)
+        .collect()
+}
+
+/// Set each of the install's keys and salts in its `wp-config.php` to a new
+/// one, logging everyone out of it.
+pub(super) async fn rotate(side: &Endpoint<'_>) -> Result<(), String> {
+    for name in SALTS {
+        side.wp(&["config", "set", name, &salt(), "--type=constant"])
+            .await?;
+    }
+    Ok(())
+}
+
+impl Job<'_> {
+    /// Give the server new keys and salts after a push, if the profile asks
+    /// for it. A failure is a warning: the site works, on the old ones.
+    pub(super) async fn rotate_salts(&self, dest: &Endpoint<'_>) {
+        if !self.profile.post_sync.rotate_salts || self.direction != Direction::Push {
+            return;
+        }
+        tracing::info!("rotating the server's auth keys and salts");
+        if let Err(e) = rotate(dest).await {
+            self.warn(Message::new(
+                "warning.servers_auth_keys_salts_werent_rotated",
+                &[("error", &e)],
+            ));
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! The profile's sync scope, as exclude rules and the `wp-config.php` check.
+//!
+//! A `wp_content` scope mirrors that directory instead of the root, so it needs
+//! no rules. `custom_roots` mirrors the root with every path excluded but the
+//! roots and the directories leading down to them. `full_install` mirrors the
+//! root with core, and lets `wp-config.php` through only once both installs'
+//! copies are known to name the same database.
+//!
+//! Included plugins and themes narrow any scope to their directories, the same
+//! way custom roots do; excluded ones are plain exclude rules.
+//!
+//! A Composer project's `wp_content` scope mirrors the root too, keeping its
+//! content directory and the `composer.json` and `composer.lock` that say what
+//! else to install.
+
+use std::collections::BTreeSet;
+
+use super::endpoint::Endpoint;
+use super::environment::read_wp_config;
+use super::Job;
+use crate::messages::{self, Message};
+use crate::profile::{wp_config, Profile, SyncScope};
+
+/// The WordPress configuration file, relative to the root.
+const WP_CONFIG: &str = "wp-config.php";
+
+/// What says which packages a Composer project installs, relative to the root.
+const COMPOSER_FILES: &[&str] = &["composer.json", "composer.lock"];
+
+/// Exclude rules, relative to the WordPress root, for the profile's scope and
+/// its plugin and theme selections.
+pub(super) fn scope_rules(profile: &Profile) -> Vec<String> {
+    let content = profile.project.content_dir();
+    let (plugins, themes) = (format!("{content}/plugins"), format!("{content}/themes"));
+    let extensions = [
+        (plugins.as_str(), &profile.plugins),
+        (themes.as_str(), &profile.themes),
+    ];
+    // A single-file plugin is `<name>.php` where others are a directory.
+    let paths = |dir: &str, name: &str| [format!("{dir}/{name}"), format!("{dir}/{name}.php")];
+    let mut roots: Vec<String> = extensions
+        .iter()
+        .flat_map(|(dir, selection)| selection.include.iter().flat_map(|name| paths(dir, name)))
+        .collect();
+    match profile.scope {
+        SyncScope::CustomRoots => roots.extend(profile.custom_roots.iter().cloned()),
+        SyncScope::WpContent if profile.project.uses_composer() => {
+            if roots.is_empty() {
+                roots.push(content.to_string());
+            }
+            roots.extend(COMPOSER_FILES.iter().map(|file| file.to_string()));
+        }
+        SyncScope::WpContent | SyncScope::FullInstall => {}
+    }
+    let mut rules = root_rules(&roots);
+    rules.extend(extensions.iter().flat_map(|(dir, selection)| {
+        selection
+            .exclude
+            .iter()
+            .flat_map(|name| paths(dir, name).map(|path| format!("/{path}")))
+    }));
+    rules
+}
+
+/// Exclude rules, relative to the WordPress root, that leave only `roots`:
+/// each directory on the way down to a root has everything else in it
+/// excluded, then the next step re-included.
+pub(super) fn root_rules(roots: &[String]) -> Vec<String> {
+    let roots: Vec<&str> = roots
+        .iter()
+        .map(|root| root.trim_matches('/'))
+        .filter(|root| !root.is_empty())
+        .collect();
+    if roots.is_empty() {
+        return Vec::new();
+    }
+    // Every directory above a root, the WordPress root (`""`) included.
+    let mut parents = BTreeSet::new();
+    for root in &roots {
+        parents.insert("");
+        for (i, _) in root.match_indices('/') {
+            parents.insert(&root[..i]);
+        }
+    }
+    let mut rules = Vec::new();
+    // In order, each directory comes before those inside it.
+    for parent in parents.into_iter().filter(|parent| !roots.contains(parent)) {
+        let dir = if parent.is_empty() {
+            String::new()
+        } else {
+            format!("/{parent}")
+        };
+        rules.push(format!("{dir}/*"));
+        let children: BTreeSet<&str> = roots
+            .iter()
+            .filter_map(|root| {
+                let rest = match parent {
+                    "" => root,
+                    _ => root.strip_prefix(parent)?.strip_prefix('/')?,
+                };
+                Some(rest.split('/').next().unwrap_or(rest))
+            })
+            .collect();
+        rules.extend(children.into_iter().map(|child| format!("!{dir}/{child}")));
+    }
+    rules
+}
+
+impl Job<'_> {
+    /// Whether `wp-config.php` is mirrored: the scope is the full install and
+    /// both installs' copies name the same database. Worked out once per job;
+    /// a copy that can't be read or doesn't match keeps the destination's.
+    pub(super) async fn syncs_wp_config(&self) -> bool {
+        // Between servers, this machine's copy has nothing to say about it.
+        if self.profile.scope != SyncScope::FullInstall || self.from_server.is_some() {
+            return false;
+        }
+        *self
+            .wp_config
+            .get_or_init(|| async {
+                match self.same_database().await {
+                    Ok(true) => true,
+                    Ok(false) => {
+                        self.warn(Message::new(
+                            "warning.kept_destinations_wp_config_sources_names",
+                            &[("wp_config", &WP_CONFIG)],
+                        ));
+                        false
+                    }
+                    Err(e) => {
+                        self.warn(Message::new(
+                            "warning.kept_destinations_wp_config",
+                            &[("wp_config", &WP_CONFIG), ("error", &e)],
+                        ));
+                        false
+                    }
+                }
+            })
+            .await
+    }
+
+    /// Whether both installs' `wp-config.php` name the same database, with
+    /// the same credentials and table prefix.
+    async fn same_database(&self) -> Result<bool, String> {
+        let source = self.read_wp_config(&self.source()).await?;
+        let dest = self.read_wp_config(&self.destination()).await?;
+        if source == dest {
+            return Ok(true);
+        }
+        let (a, b) = (wp_config::parse(&source)?, wp_config::parse(&dest)?);
+        Ok(a.name == b.name
+            && a.user == b.user
+            && a.host == b.host
+            && a.table_prefix == b.table_prefix
+            && wp_config::password(&source) == wp_config::password(&dest))
+    }
+
+    /// The PHP source of `side`'s `wp-config.php`, in its WordPress root.
+    async fn read_wp_config(&self, side: &Endpoint<'_>) -> Result<String, String> {
+        let path = side.path(WP_CONFIG);
+        match side {
+            Endpoint::Local(_) => tokio::fs::read_to_string(&path).await.map_err(|e| {
+                messages::text("error.failed.read_path", &[("path", &path), ("error", &e)])
+            }),
+            Endpoint::Remote { remote, .. } => {
+                let remote = remote.ok_or_else(|| {
+                    messages::text(
+                        "error.servers_wp_config_cant_read_without",
+                        &[("wp_config", &WP_CONFIG)],
+                    )
+                })?;
+                read_wp_config(remote, &path).await
+            }
+        }
+    }
+}
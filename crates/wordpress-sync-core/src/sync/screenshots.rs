@@ -0,0 +1,334 @@
+//! Screenshots of the server's pages before and after a push, taken with a
+//! headless Chrome and compared pixel by pixel, so a push that changed how the
+//! site looks shows in its result and report.
+//!
+//! They are kept in a directory per job under `~/.wordpress-sync/screenshots`,
+//! for the most recent jobs only. The browser goes through the profile's proxy
+//! if it has one, but can't log in to it.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::{local, temp};
+use crate::messages;
+use crate::paths;
+use crate::profile::Profile;
+use crate::proxy::{self, ProxyKind};
+
+/// How long one screenshot, the page's loading included, may take.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a page is given to finish loading, in the browser's own time.
+const SETTLE_MS: u32 = 5000;
+
+/// How far apart two pixels' channels may be for them to count as the same,
+/// so antialiasing doesn't count as a change.
+const TOLERANCE: u8 = 16;
+
+/// How many jobs' screenshots are kept.
+const KEEP_JOBS: usize = 20;
+
+/// The browsers looked for when the profile doesn't name one, in order.
+#[cfg(target_os = "macos")]
+const BROWSERS: &[&str] = &[
+    "/Applications/Google Chrome.app/Contents/MacOS/Google Chrome",
+    "/Applications/Chromium.app/Contents/MacOS/Chromium",
+    "/Applications/Microsoft Edge.app/Contents/MacOS/Microsoft Edge",
+];
+#[cfg(target_os = "windows")]
+const BROWSERS: &[&str] = &[
+    r"C:\Program Files\Google\Chrome\Application\chrome.exe",
+    r"C:\Program Files (x86)\Google\Chrome\Application\chrome.exe",
+    r"C:\Program Files (x86)\Microsoft\Edge\Application\msedge.exe",
+];
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const BROWSERS: &[&str] = &[
+    "google-chrome",
+    "google-chrome-stable",
+    "chromium",
+    "chromium-browser",
+    "microsoft-edge",
+];
+
+/// One page's screenshots, and how much it changed between them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScreenshotDiff {
+    /// The page's path on the site.
+    pub path: String,
+    pub before: Option<PathBuf>,
+    pub after: Option<PathBuf>,
+    /// The percentage of its pixels that changed, once both were taken.
+    pub changed: Option<f64>,
+    /// Why a screenshot wasn't taken or compared, if one wasn't.
+    pub error: Option<String>,
+}
+
+/// A moment the pages are taken at.
+#[derive(Clone, Copy)]
+enum Moment {
+    Before,
+    After,
+}
+
+/// Take the profile's pages on the server before job `job_id` pushes.
+pub(super) async fn before(profile: &Profile, job_id: &str) -> Vec<ScreenshotDiff> {
+    let mut shots: Vec<ScreenshotDiff> = profile
+        .screenshots
+        .paths
+        .iter()
+        .map(|path| ScreenshotDiff {
+            path: path.clone(),
+            before: None,
+            after: None,
+            changed: None,
+            error: None,
+        })
+        .collect();
+    take_all(profile, job_id, Moment::Before, &mut shots).await;
+    prune(job_id);
+    shots
+}
+
+/// Take the pages of `shots` again now job `job_id` has pushed, and compare
+/// each with how it was before.
+pub(super) async fn after(profile: &Profile, job_id: &str, shots: &mut [ScreenshotDiff]) {
+    take_all(profile, job_id, Moment::After, shots).await;
+    for shot in shots.iter_mut() {
+        let (Some(before), Some(after)) = (shot.before.clone(), shot.after.clone()) else {
+            continue;
+        };
+        let compared = tokio::task::spawn_blocking(move || changed(&before, &after))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|result| result);
+        match compared {
+            Ok(changed) => {
+                tracing::info!(path = %shot.path, changed, "compared screenshots");
+                shot.changed = Some(changed);
+            }
+            Err(e) => shot.error = Some(e),
+        }
+    }
+}
+
+async fn take_all(profile: &Profile, job_id: &str, moment: Moment, shots: &mut [ScreenshotDiff]) {
+    let taken = async {
+        let dir = paths::screenshots_dir()?.join(job_id);
+        fs::create_dir_all(&dir).map_err(|e| {
+            messages::text(
+                "error.failed.create_path",
+                &[("path", &dir.display()), ("error", &e)],
+            )
+        })?;
+        let browser = browser(profile).await?;
+        Ok::<_, String>((dir, browser))
+    };
+    let (dir, browser) = match taken.await {
+        Ok(found) => found,
+        Err(e) => {
+            for shot in shots.iter_mut().filter(|shot| shot.error.is_none()) {
+                shot.error = Some(e.clone());
+            }
+            return;
+        }
+    };
+    let profile_dir = temp::path(job_id, "browser");
+    for (i, shot) in shots.iter_mut().enumerate() {
+        // A page that couldn't be taken before isn't taken after.
+        if shot.error.is_some() {
+            continue;
+        }
+        let name = match moment {
+            Moment::Before => format!("{i}-before.png"),
+            Moment::After => format!("{i}-after.png"),
+        };
+        let file = dir.join(name);
+        match take(profile, &browser, &profile_dir, &shot.path, &file).await {
+            Ok(()) => match moment {
+                Moment::Before => shot.before = Some(file),
+                Moment::After => shot.after = Some(file),
+            },
+            Err(e) => {
+                tracing::warn!(path = %shot.path, "screenshot failed: {e}");
+                shot.error = Some(e);
+            }
+        }
+    }
+    let _ = fs::remove_dir_all(&profile_dir);
+}
+
+/// Take a screenshot of the server's page at `path` into `file`, with
+/// `profile_dir` as the browser's profile so it leaves the user's alone.
+async fn take(
+    profile: &Profile,
+    browser: &str,
+    profile_dir: &Path,
+    path: &str,
+    file: &Path,
+) -> Result<(), String> {
+    let settings = &profile.screenshots;
+    let url = format!("{}{path}", profile.remote.url.trim_end_matches('/'));
+    let _ = fs::remove_file(file);
+    let mut cmd = local::command(browser);
+    cmd.args([
+        "--headless=new",
+        "--disable-gpu",
+        "--hide-scrollbars",
+        "--mute-audio",
+        "--no-first-run",
+        "--no-default-browser-check",
+    ])
+    .arg(format!("--user-data-dir={}", profile_dir.display()))
+    .arg(format!(
+        "--window-size={},{}",
+        settings.width, settings.height
+    ))
+    .arg(format!("--virtual-time-budget={SETTLE_MS}"))
+    .arg(format!("--screenshot={}", file.display()));
+    if !settings.verify_tls {
+        cmd.arg("--ignore-certificate-errors");
+    }
+    if let Some(proxy) = proxy::effective(&profile.remote.proxy)? {
+        let scheme = match proxy.kind {
+            ProxyKind::Http => "http",
+            ProxyKind::Socks5 => "socks5",
+        };
+        cmd.arg(format!(
+            "--proxy-server={scheme}://{}:{}",
+            proxy.host, proxy.port
+        ));
+        if !proxy.bypass.is_empty() {
+            cmd.arg(format!("--proxy-bypass-list={}", proxy.bypass.join(";")));
+        }
+    }
+    cmd.arg(&url);
+    match tokio::time::timeout(TIMEOUT, local::output(cmd, "the browser")).await {
+        Ok(result) => result?,
+        Err(_) => {
+            return Err(messages::text(
+                "error.url_took_over_timeout_load",
+                &[("url", &url), ("timeout", &TIMEOUT.as_secs())],
+            ))
+        }
+    };
+    if !file.exists() {
+        return Err(messages::text(
+            "error.browser_made_no_screenshot_url",
+            &[("url", &url)],
+        ));
+    }
+    Ok(())
+}
+
+/// The browser to take screenshots with: the profile's, or the first of
+/// [`BROWSERS`] that runs.
+async fn browser(profile: &Profile) -> Result<String, String> {
+    if let Some(browser) = &profile.screenshots.browser {
+        return Ok(browser.display().to_string());
+    }
+    for browser in BROWSERS {
+        let mut cmd = local::command(browser);
+        cmd.arg("--version");
+        match cmd.output().await {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            _ => return Ok(browser.to_string()),
+        }
+    }
+    Err(messages::text(
+        "error.no_chrome_chromium_edge_was_found",
+        &[],
+    ))
+}
+
+/// The percentage of pixels that differ between the PNGs `before` and
+/// `after`. All of them do if the two aren't the same size.
+fn changed(before: &Path, after: &Path) -> Result<f64, String> {
+    let (before, after) = (decode(before)?, decode(after)?);
+    if (before.width, before.height) != (after.width, after.height) {
+        return Ok(100.0);
+    }
+    let total = before.width as usize * before.height as usize;
+    if total == 0 {
+        return Ok(0.0);
+    }
+    let differing = before
+        .pixels()
+        .zip(after.pixels())
+        .filter(|(a, b)| a.iter().zip(b).any(|(a, b)| a.abs_diff(*b) > TOLERANCE))
+        .count();
+    Ok(differing as f64 * 100.0 / total as f64)
+}
+
+/// A decoded screenshot, 8 bits a channel.
+struct Image {
+    width: u32,
+    height: u32,
+    /// Channels a pixel: 1 or 2 for grey, 3 or 4 for colour, alpha last.
+    channels: usize,
+    data: Vec<u8>,
+}
+
+impl Image {
+    /// Each pixel's red, green, blue and alpha.
+    fn pixels(&self) -> impl Iterator<Item = [u8; 4]> + '_ {
+        self.data
+            .chunks_exact(self.channels)
+            .map(|pixel| match *pixel {
+                [grey] => [grey, grey, grey, u8::MAX],
+                [grey, alpha] => [grey, grey, grey, alpha],
+                [r, g, b] => [r, g, b, u8::MAX],
+                [r, g, b, a, ..] => [r, g, b, a],
+                [] => [0; 4],
+            })
+    }
+}
+
+fn decode(path: &Path) -> Result<Image, String> {
+    let failed = |e: &dyn std::fmt::Display| {
+        messages::text(
+            "error.failed.read_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    };
+    let file = File::open(path).map_err(|e| failed(&e))?;
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| failed(&e))?;
+    let size = reader
+        .output_buffer_size()
+        .ok_or_else(|| failed(&"it's too large"))?;
+    let mut data = vec![0; size];
+    let info = reader.next_frame(&mut data).map_err(|e| failed(&e))?;
+    let channels = info.color_type.samples();
+    // Rows are packed, 8 bits a channel, so the frame is the buffer's start.
+    data.truncate(info.width as usize * info.height as usize * channels);
+    Ok(Image {
+        width: info.width,
+        height: info.height,
+        channels,
+        data,
+    })
+}
+
+/// Remove the screenshots of all but the most recent jobs, `job_id`'s among them.
+fn prune(job_id: &str) {
+    let Ok(dir) = paths::screenshots_dir() else {
+        return;
+    };
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return;
+    };
+    let mut jobs: Vec<(std::time::SystemTime, PathBuf)> = entries
+        .flatten()
+        .filter(|entry| entry.file_name() != job_id)
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .collect();
+    jobs.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    for (_, path) in jobs.into_iter().skip(KEEP_JOBS - 1) {
+        let _ = fs::remove_dir_all(path);
+    }
+}
@@ -0,0 +1,106 @@
+//! Rebuilding the destination's site search index once a database has been
+//! imported into it. Search plugins keep their own index of the posts, which
+//! an import leaves describing the old database, so search quietly returns
+//! the wrong results, or none, until it's rebuilt.
+
+use std::time::Instant;
+
+use super::endpoint::Endpoint;
+use super::transfer::TransferStats;
+use super::{Job, Phase};
+use crate::messages::Message;
+use crate::profile::SearchPlugin;
+
+impl SearchPlugin {
+    fn name(self) -> &'static str {
+        match self {
+            Self::ElasticPress => "ElasticPress",
+            Self::Relevanssi => "Relevanssi",
+        }
+    }
+
+    /// The plugin's directory names, as `wp plugin is-active` takes them.
+    fn slugs(self) -> &'static [&'static str] {
+        match self {
+            Self::ElasticPress => &["elasticpress"],
+            Self::Relevanssi => &["relevanssi", "relevanssi-premium"],
+        }
+    }
+
+    /// The wp-cli commands that rebuild its index, the first the plugin's
+    /// current one and any after it what older versions call it.
+    fn commands(self) -> &'static [&'static [&'static str]] {
+        match self {
+            // ElasticPress 4 renamed `index` to `sync`; `--setup` drops the
+            // old index, whose post ids the import has made meaningless.
+            Self::ElasticPress => &[
+                &["elasticpress", "sync", "--setup", "--yes"],
+                &["elasticpress", "index", "--setup", "--yes"],
+            ],
+            Self::Relevanssi => &[&["relevanssi", "index"]],
+        }
+    }
+}
+
+impl Job<'_> {
+    /// Rebuild the index of each search plugin the profile names that is
+    /// active on the destination, once this job has imported a database there.
+    /// Their output is shown as the post-sync phase's progress. A failure
+    /// leaves search stale, not the site broken, so it's only a warning.
+    pub(super) async fn reindex_search(&self, dest: &Endpoint<'_>) {
+        let plugins = &self.profile.post_sync.reindex_search;
+        if plugins.is_empty() || !self.syncs_database() {
+            return;
+        }
+        if !self.has_wp_cli(dest).await.unwrap_or(false) {
+            self.warn(Message::new(
+                "warning.site_search_index_was_not_rebuilt",
+                &[],
+            ));
+            return;
+        }
+        for &plugin in plugins {
+            if !is_active(dest, plugin).await {
+                continue;
+            }
+            tracing::info!(plugin = plugin.name(), "rebuilding the search index");
+            if let Err(e) = self.reindex(dest, plugin).await {
+                self.warn(Message::new(
+                    "warning.name_search_index_was_not_rebuilt",
+                    &[("name", &plugin.name()), ("error", &e)],
+                ));
+            }
+        }
+    }
+
+    async fn reindex(&self, dest: &Endpoint<'_>, plugin: SearchPlugin) -> Result<(), String> {
+        let started = Instant::now();
+        let mut stats = TransferStats::default();
+        let mut result = Ok(());
+        for args in plugin.commands() {
+            result = dest
+                .wp_streaming(args, |line| {
+                    stats.current_file = Some(format!("{}: {}", plugin.name(), line.trim()));
+                    self.reporter
+                        .report(Phase::PostSync, &stats, started, false);
+                })
+                .await;
+            // Only a command this version doesn't have moves on to the next.
+            match &result {
+                Err(e) if e.contains("is not a registered") => continue,
+                _ => break,
+            }
+        }
+        result
+    }
+}
+
+/// Whether `plugin` is active on `side`.
+async fn is_active(side: &Endpoint<'_>, plugin: SearchPlugin) -> bool {
+    for slug in plugin.slugs() {
+        if side.wp(&["plugin", "is-active", slug]).await.is_ok() {
+            return true;
+        }
+    }
+    false
+}
@@ -0,0 +1,212 @@
+//! Checking, once connected, that the sync has room for what it is about to
+//! write, so a full disk fails it at the start with a clear message instead of
+//! part way through with "No space left on device".
+//!
+//! The estimates are the dumped tables' size, for the dump staged on this machine
+//! (twice, since rewriting its URLs makes a second copy), and the size of the
+//! files the transfer would copy, for the destination's WordPress root. Each
+//! is checked on its own. The database server's own storage isn't checked: it
+//! is often another machine, out of reach. Anything that can't be measured is
+//! logged and let through rather than failing the sync.
+
+use std::path::Path;
+
+use super::endpoint::Endpoint;
+use super::remote::Remote;
+use super::temp;
+use super::Job;
+use crate::error::SyncError;
+use crate::messages::{self, Message};
+use crate::shell;
+
+/// Room to spare beyond an estimate, as a fraction of it.
+const MARGIN: u64 = 10;
+
+/// Something the sync will write, and where.
+struct Need<'a> {
+    what: &'static str,
+    /// The server holding `path`, or `None` for this machine.
+    remote: Option<&'a Remote>,
+    path: String,
+    bytes: u64,
+}
+
+impl Job<'_> {
+    /// Fail with [`SyncError::DiskSpace`] if this machine or the destination
+    /// is too full for the sync.
+    pub(super) async fn check_space(&self) -> Result<(), SyncError> {
+        let mut needs = Vec::new();
+        if self.syncs_database() {
+            match self.database_bytes().await {
+                Ok(bytes) => needs.push(Need {
+                    what: "the database dump",
+                    remote: None,
+                    path: temp::root().display().to_string(),
+                    bytes: bytes * 2,
+                }),
+                Err(e) => tracing::warn!("not checking room for the database dump: {e}"),
+            }
+        }
+        if self.transfers_files() {
+            match self.files_need().await {
+                Ok(need) => needs.extend(need),
+                Err(e) => tracing::warn!("not checking room for the files: {e}"),
+            }
+        }
+
+        for need in needs.into_iter().filter(|need| need.bytes > 0) {
+            let free = match need.remote {
+                Some(remote) => remote_free(remote, &need.path).await,
+                None => local_free(Path::new(&need.path)),
+            };
+            let free = match free {
+                Ok(free) => free,
+                Err(e) => {
+                    tracing::warn!(path = %need.path, "not checking free space: {e}");
+                    continue;
+                }
+            };
+            let wanted = need.bytes + need.bytes / MARGIN;
+            tracing::debug!(path = %need.path, wanted, free, "free space");
+            if free < wanted {
+                let machine = match need.remote {
+                    Some(_) => "the server",
+                    None => "this machine",
+                };
+                return Err(SyncError::DiskSpace(Message::new(
+                    "error.not_enough_disk_space_machine_what",
+                    &[
+                        ("machine", &machine),
+                        ("what", &need.what),
+                        ("wanted", &size(wanted)),
+                        ("path", &need.path),
+                        ("free", &size(free)),
+                    ],
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// The files the transfer would copy to the destination, or `None` if its
+    /// free space can't be asked for (FTP).
+    async fn files_need(&self) -> Result<Option<Need<'_>>, String> {
+        let dest = self.destination();
+        let remote = match &dest {
+            Endpoint::Local(_) => None,
+            Endpoint::Remote { remote: None, .. } => return Ok(None),
+            Endpoint::Remote {
+                remote: Some(remote),
+                ..
+            } => Some(*remote),
+        };
+        let path = self.files_root(&dest);
+        let changes = self
+            .transfer
+            .plan(
+                self.direction,
+                &self.files_root(&self.source()),
+                &path,
+                &self.excludes().await?,
+            )
+            .await?;
+        Ok(Some(Need {
+            what: "the files",
+            remote,
+            path,
+            bytes: changes.bytes,
+        }))
+    }
+}
+
+/// The bytes free to this user on the file system holding `path`, or its
+/// nearest existing parent.
+pub(super) fn local_free(path: &Path) -> Result<u64, String> {
+    let existing = path
+        .ancestors()
+        .find(|dir| dir.exists())
+        .ok_or_else(|| messages::text("error.path_doesnt_exist", &[("path", &path.display())]))?;
+    available(existing).map_err(|e| {
+        messages::text(
+            "error.failed.read_free_space_path",
+            &[("path", &existing.display()), ("error", &e)],
+        )
+    })
+}
+
+#[cfg(unix)]
+fn available(path: &Path) -> std::io::Result<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    let mut stats = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stats` is written by a successful call.
+    if unsafe { libc::statvfs(path.as_ptr(), stats.as_mut_ptr()) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // SAFETY: statvfs succeeded, so it filled `stats` in.
+    let stats = unsafe { stats.assume_init() };
+    // The fields are 32 or 64 bits depending on the platform.
+    let bytes = u128::from(stats.f_bavail) * u128::from(stats.f_frsize);
+    Ok(u64::try_from(bytes).unwrap_or(u64::MAX))
+}
+
+#[cfg(windows)]
+fn available(path: &Path) -> std::io::Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let wide: Vec<u16> = path
+        .as_os_str()
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect();
+    let mut free = 0u64;
+    // SAFETY: `wide` is NUL-terminated, and the totals not wanted may be null.
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &mut free,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    if ok == 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(free)
+}
+
+/// The bytes free on the server's file system holding `path`, or its nearest
+/// existing parent, from POSIX `df`.
+async fn remote_free(remote: &Remote, path: &str) -> Result<u64, String> {
+    let line = format!(
+        "p={}; while [ ! -e \"$p\" ]; do p=$(dirname \"$p\"); done; df -Pk \"$p\"",
+        shell::quote(path)
+    );
+    let output = remote.exec(&line).await?;
+    // Filesystem, 1024-blocks, Used, Available, Capacity, Mounted on
+    output
+        .lines()
+        .last()
+        .and_then(|line| line.split_whitespace().nth(3))
+        .and_then(|kib| kib.parse::<u64>().ok())
+        .map(|kib| kib * 1024)
+        .ok_or_else(|| messages::text("error.unexpected_df_output", &[("output", &output.trim())]))
+}
+
+/// `bytes` for a person to read, e.g. `1.4 GiB`.
+pub(super) fn size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["bytes", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} bytes")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
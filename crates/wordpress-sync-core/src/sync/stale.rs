@@ -0,0 +1,158 @@
+//! Cleaning up after jobs that didn't get to: the maintenance mode, lock
+//! owners, and temporary files a crash, a force-quit, or a lost connection
+//! left behind, on this machine and the server.
+//!
+//! [`sweep_stale_state`] runs when the app or the CLI starts. It removes the
+//! temporary files and lock owners of jobs that aren't running, and takes a
+//! local site out of the maintenance mode a stopped job left it in. A
+//! server's maintenance file needs a connection, so it waits for the
+//! profile's next sync, which takes it over and removes it as its own, or for
+//! [`cleanup_stale_state`]. Only maintenance files this app wrote are
+//! touched, never one of a WordPress update.
+
+use serde::Serialize;
+
+use super::endpoint::Endpoint;
+use super::lock::{self, ProfileLock};
+use super::maintenance::{self, MAINTENANCE_FILE};
+use super::remote::Remote;
+use super::{connect_ssh, temp, Direction, ProgressSink};
+use crate::error::SyncError;
+use crate::messages;
+use crate::profile::{self, LocalEnvironment, Profile};
+use crate::shell;
+
+/// What [`cleanup_stale_state`] found and removed.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StaleCleanup {
+    /// The maintenance files removed, as `local` or `server` and their path.
+    pub maintenance_removed: Vec<String>,
+    pub temp_dirs_removed: usize,
+    pub lock_owners_removed: usize,
+}
+
+/// Remove what crashed jobs left on this machine, as the app or the CLI
+/// starts: every job's that isn't running now.
+pub fn sweep_stale_state() {
+    temp::sweep_temp_files();
+    lock::sweep();
+    let Ok(profiles) = profile::list() else {
+        return;
+    };
+    for profile in profiles {
+        // A profile being synced right now is its job's to look after.
+        let Ok(_lock) = ProfileLock::acquire(&profile.id, &sweep_id()) else {
+            continue;
+        };
+        if let Err(e) = end_local(&profile.local) {
+            tracing::warn!(
+                profile = %profile.id,
+                error = %e,
+                "the local site is still in maintenance mode"
+            );
+        }
+    }
+}
+
+/// Clean up what stopped jobs left of `profile_id`'s: its maintenance files,
+/// here and on the server if it's reached over SSH, along with every job's
+/// temporary files and lock owners. Fails if a sync of the profile is running.
+pub async fn cleanup_stale_state(
+    profile_id: &str,
+    sink: &dyn ProgressSink,
+) -> Result<StaleCleanup, SyncError> {
+    let profile = profile::load(profile_id).map_err(SyncError::config)?;
+    let _lock = ProfileLock::acquire(&profile.id, &sweep_id())?;
+    let mut cleanup = StaleCleanup {
+        temp_dirs_removed: temp::sweep_temp_files(),
+        lock_owners_removed: lock::sweep(),
+        ..StaleCleanup::default()
+    };
+    if let Some(path) = end_local(&profile.local).map_err(SyncError::config)? {
+        cleanup.maintenance_removed.push(format!("local: {path}"));
+    }
+    if profile.remote.transfer.uses_ssh() {
+        let remote = connect_ssh(&profile, sink).await?;
+        let ended = end_remote(&remote, &profile).await;
+        remote.close().await;
+        if let Some(path) = ended.map_err(SyncError::remote_command)? {
+            cleanup.maintenance_removed.push(format!("server: {path}"));
+        }
+    }
+    tracing::info!(profile = %profile.id, ?cleanup, "cleaned up after stopped jobs");
+    Ok(cleanup)
+}
+
+/// Take `direction`'s destination out of the maintenance mode a job gave up
+/// on with it still on, as when its retries ran out while the server was
+/// unreachable: on a new connection, if it has to be the server's.
+pub(super) async fn end_maintenance(
+    profile: &Profile,
+    direction: Direction,
+    sink: &dyn ProgressSink,
+) {
+    let ended = match direction {
+        Direction::Pull => end_local(&profile.local),
+        // Without SSH the job couldn't have put the server in it.
+        Direction::Push if !profile.remote.transfer.uses_ssh() => return,
+        Direction::Push => match connect_ssh(profile, sink).await {
+            Ok(remote) => {
+                let ended = end_remote(&remote, profile).await;
+                remote.close().await;
+                ended
+            }
+            Err(e) => Err(e.to_string()),
+        },
+    };
+    if let Err(e) = ended {
+        tracing::warn!(
+            error = %e,
+            "the destination is still in maintenance mode, until the next sync"
+        );
+    }
+}
+
+/// Remove the local site's maintenance file if this app wrote it, returning
+/// its path if it did.
+fn end_local(local: &LocalEnvironment) -> Result<Option<String>, String> {
+    let path = Endpoint::Local(local).path(MAINTENANCE_FILE);
+    let ours = std::fs::read_to_string(&path).is_ok_and(|contents| maintenance::is_ours(&contents));
+    if !ours {
+        return Ok(None);
+    }
+    std::fs::remove_file(&path).map_err(|e| {
+        messages::text(
+            "error.failed.remove_path",
+            &[("path", &path), ("error", &e)],
+        )
+    })?;
+    tracing::info!(%path, "took the local site out of a stopped job's maintenance mode");
+    Ok(Some(path))
+}
+
+/// Remove the server's maintenance file if this app wrote it, returning its
+/// path if it did.
+async fn end_remote(remote: &Remote, profile: &Profile) -> Result<Option<String>, String> {
+    let path = Endpoint::Remote {
+        remote: Some(remote),
+        env: &profile.remote,
+    }
+    .path(MAINTENANCE_FILE);
+    let quoted = shell::quote(&path);
+    let removed = remote
+        .exec(&format!(
+            "if [ -f {quoted} ] && {}; then rm -f {quoted} && echo removed; fi",
+            maintenance::is_ours_test(&quoted)
+        ))
+        .await?;
+    if removed.trim() != "removed" {
+        return Ok(None);
+    }
+    tracing::info!(%path, "took the server out of a stopped job's maintenance mode");
+    Ok(Some(path))
+}
+
+/// The job id a cleanup holds a profile's lock under.
+fn sweep_id() -> String {
+    format!("cleanup-{}", std::process::id())
+}
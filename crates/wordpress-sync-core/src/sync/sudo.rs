@@ -0,0 +1,75 @@
+//! Running the server's steps through `sudo`, where the profile allows it.
+//!
+//! A command always runs with `sudo -n`, which fails rather than wait for a
+//! password and never reads its input for one. With a password, it's sent as
+//! the first line of the input and handed on a pipe of its own to `sudo -v`
+//! first, so it never appears on a command line or reaches the command, even
+//! when sudo turns out not to need it; the command then runs on the
+//! credentials that left cached, which are cleared after. A server whose
+//! sudoers keeps no cached credentials (`timestamp_timeout=0`) needs
+//! passwordless sudo.
+
+use super::Job;
+use crate::credentials::{self, SUDO_PASSWORD_SERVICE};
+use crate::messages;
+use crate::profile::{Sudo, SudoStep};
+use crate::shell;
+
+/// The `sudo` invocation a command line is handed to, before the command.
+fn sudo_prefix(sudo: &Sudo) -> String {
+    let mut prefix = "sudo -n".to_string();
+    if let Some(user) = &sudo.user {
+        prefix.push_str(&format!(" -u {}", shell::quote(user)));
+    }
+    prefix
+}
+
+/// Wrap the sudo command line `line` so the first line of its input goes to
+/// `sudo -v`, and only the rest to `line`.
+fn with_password(line: &str) -> String {
+    let script = format!(
+        "IFS= read -r p; printf '%s\\n' \"$p\" | sudo -k -S -p '' -v || exit $?; unset p; \
+         {line}; s=$?; sudo -k; exit $s"
+    );
+    format!("sh -c {}", shell::quote(&script))
+}
+
+/// rsync's `--rsync-path` for running the server's end through passwordless sudo.
+pub(super) fn rsync_path(sudo: &Sudo, rsync: &str) -> String {
+    format!("{} {rsync}", sudo_prefix(sudo))
+}
+
+impl Job<'_> {
+    /// Run the shell command `line` on the server with `input` as its stdin,
+    /// through sudo if the profile lets `step` use it, returning its stdout.
+    pub(super) async fn run_remote(
+        &self,
+        step: SudoStep,
+        line: &str,
+        input: &[u8],
+    ) -> Result<String, String> {
+        let session = self.remote().session()?;
+        let sudo = &self.profile.sudo;
+        if !sudo.allows(step) {
+            return session.exec_with_input(line, input).await;
+        }
+        let mut line = format!("{} -- sh -c {}", sudo_prefix(sudo), shell::quote(line));
+        let mut stdin = Vec::new();
+        if sudo.password {
+            line = with_password(&line);
+            let reference = self.profile.secrets.sudo_password.as_deref();
+            let password =
+                credentials::resolve(reference, SUDO_PASSWORD_SERVICE, &self.profile.id)?
+                    .ok_or_else(|| {
+                        messages::text(
+                            "error.no_sudo_password_stored_name",
+                            &[("name", &self.profile.name)],
+                        )
+                    })?;
+            stdin.extend_from_slice(password.as_bytes());
+            stdin.push(b'\n');
+        }
+        stdin.extend_from_slice(input);
+        session.exec_with_input(&line, &stdin).await
+    }
+}
@@ -0,0 +1,216 @@
+//! Where jobs keep their temporary files: database dumps, snapshots of the
+//! destination's user tables, and staged backups and media.
+//!
+//! Each job has a directory of its own under `wordpress-sync-<uid>` in the
+//! system's temp directory, which only the user can open; if someone else
+//! already has that name, one under `~/.wordpress-sync/tmp` is used instead. A
+//! job holds a lock on its directory while it runs, and removes what it
+//! staged as it finishes, successfully or not. What a crash leaves behind is
+//! removed by [`sweep_temp_files`] when the app or the CLI next starts: every
+//! directory nobody holds, except those of jobs that can still be resumed,
+//! whose dump the resumed job picks up.
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use super::resume::Manifest;
+use crate::paths;
+
+/// Held by whoever uses a job's directory.
+const LOCK_FILE: &str = ".lock";
+
+/// Below the config directory, the fallback for the temp directory.
+const FALLBACK_DIR: &str = "tmp";
+
+/// The directory every job's directory is in.
+pub(super) fn root() -> &'static Path {
+    static ROOT: OnceLock<PathBuf> = OnceLock::new();
+    ROOT.get_or_init(|| {
+        let shared = std::env::temp_dir().join(root_name());
+        let Err(e) = make_private(&shared) else {
+            return shared;
+        };
+        tracing::warn!(error = %e, dir = %shared.display(), "can't keep temporary files in the temp directory");
+        let Ok(fallback) = paths::config_dir().map(|dir| dir.join(FALLBACK_DIR)) else {
+            return shared;
+        };
+        if let Err(e) = fs::create_dir_all(&fallback).and_then(|()| make_private(&fallback)) {
+            tracing::warn!(error = %e, dir = %fallback.display(), "can't keep temporary files in the config directory either");
+        }
+        fallback
+    })
+}
+
+/// Where the job, or restore, `id` keeps its temporary file `name`.
+pub(super) fn path(id: &str, name: &str) -> PathBuf {
+    root().join(id).join(name)
+}
+
+/// A hold on the temporary directory of a job or restore, made if need be,
+/// which the sweep leaves alone while it's held. Dropping it removes the
+/// directory if nothing was left in it.
+pub(super) struct TempDir {
+    dir: PathBuf,
+    lock: Option<File>,
+}
+
+impl TempDir {
+    /// Hold `id`'s directory, making it if need be. A directory that can't be
+    /// made is only logged: creating a file in it then fails as it explains.
+    pub(super) fn hold(id: &str) -> Self {
+        let dir = root().join(id);
+        let lock = match lock(&dir) {
+            Ok(lock) => Some(lock),
+            Err(e) => {
+                tracing::warn!(error = %e, dir = %dir.display(), "can't set up a job's temporary directory");
+                None
+            }
+        };
+        Self { dir, lock }
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        // Closed first: Windows won't delete an open file.
+        if self.lock.take().is_some() {
+            let _ = fs::remove_file(self.dir.join(LOCK_FILE));
+            // Fails, as it should, if a dump is being kept for a resume.
+            let _ = fs::remove_dir(&self.dir);
+        }
+    }
+}
+
+/// Make `dir` and lock it, again if a sweep removed it while this waited
+/// for the lock.
+fn lock(dir: &Path) -> io::Result<File> {
+    loop {
+        make_private(dir)?;
+        let path = dir.join(LOCK_FILE);
+        let file = File::options()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        file.lock()?;
+        if path.exists() {
+            return Ok(file);
+        }
+    }
+}
+
+/// Remove the temporary files crashed jobs left behind: every job directory
+/// nobody holds, except those of jobs that can be resumed. Returns how many
+/// directories were removed.
+pub fn sweep_temp_files() -> usize {
+    let Ok(entries) = fs::read_dir(root()) else {
+        return 0;
+    };
+    let mut swept = 0;
+    for entry in entries.flatten() {
+        let dir = entry.path();
+        let id = entry.file_name().to_string_lossy().into_owned();
+        if !dir.is_dir() || Manifest::exists(&id) {
+            continue;
+        }
+        let path = dir.join(LOCK_FILE);
+        let Ok(lock) = File::options()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+        else {
+            continue;
+        };
+        if lock.try_lock().is_err() {
+            continue;
+        }
+        let removed = remove_contents(&dir);
+        // Deleted while it's held where the OS allows, so anyone waiting for
+        // it sees it gone and makes the directory again; Windows only deletes
+        // closed files.
+        let unlinked = fs::remove_file(&path).is_ok();
+        drop(lock);
+        let removed = removed
+            .and_then(|()| {
+                if unlinked {
+                    Ok(())
+                } else {
+                    fs::remove_file(&path)
+                }
+            })
+            .and_then(|()| fs::remove_dir(&dir));
+        match removed {
+            Ok(()) => {
+                tracing::info!(dir = %dir.display(), "removed a crashed job's temporary files");
+                swept += 1;
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, dir = %dir.display(), "failed to remove a crashed job's temporary files")
+            }
+        }
+    }
+    swept
+}
+
+/// Remove everything in `dir` but its lock.
+fn remove_contents(dir: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_name() == LOCK_FILE {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            fs::remove_dir_all(entry.path())?;
+        } else {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// The name of the directory in the temp directory, which is shared by
+/// every user on most Unix systems.
+fn root_name() -> String {
+    #[cfg(unix)]
+    {
+        format!("wordpress-sync-{}", unsafe { libc::getuid() })
+    }
+    #[cfg(not(unix))]
+    {
+        "wordpress-sync".to_string()
+    }
+}
+
+/// Make `dir`, or check the one there, so only this user can open it.
+fn make_private(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+        match fs::DirBuilder::new().mode(0o700).create(dir) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+        // Not followed if it's a link: someone else's link could lead anywhere.
+        let metadata = fs::symlink_metadata(dir)?;
+        if !metadata.is_dir() || metadata.uid() != unsafe { libc::getuid() } {
+            return Err(io::Error::other(format!(
+                "{} belongs to someone else",
+                dir.display()
+            )));
+        }
+        if metadata.mode() & 0o077 != 0 {
+            fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+        }
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    {
+        // The temp directory is the user's own.
+        fs::create_dir_all(dir)
+    }
+}
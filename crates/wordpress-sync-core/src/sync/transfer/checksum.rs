@@ -0,0 +1,222 @@
+//! Comparing files by content, for profiles in integrity mode.
+//!
+//! Local files are hashed natively; remote ones with `sha256sum` (or the
+//! `shasum -a 256` of macOS servers, or the `sha256` of FreeBSD ones without
+//! Perl). However many there are, that's one command: their paths go to
+//! `xargs` on its stdin, and the hashes come back as it works through them,
+//! so a link with a long round trip pays for it once rather than per batch.
+//! They need a shell on the server, so FTP profiles can't use it.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+
+use russh::ChannelMsg;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+
+use crate::byte_path;
+use crate::messages;
+use crate::shell;
+use crate::ssh;
+use crate::sync::endpoint::Endpoint;
+use crate::sync::local_path;
+use crate::sync::remote::{command_failed, Remote};
+
+/// Hashes the NUL-separated paths on its stdin, a line for each, as
+/// `sha256sum` writes them. A missing file only leaves its line out.
+/// `sha256 -r` puts one space after the hash, not sha256sum's two.
+const HASH_PATHS: &str = "if command -v sha256sum >/dev/null 2>&1; then xargs -0 sha256sum --; \
+     elif command -v shasum >/dev/null 2>&1; then xargs -0 shasum -a 256 --; \
+     else xargs -0 sha256 -r -- | sed 's/ /  /'; fi";
+
+/// Hashes files on the two sides of a transfer.
+pub struct Checksums<'a> {
+    source: Endpoint<'a>,
+    dest: Endpoint<'a>,
+}
+
+impl<'a> Checksums<'a> {
+    pub fn new(source: Endpoint<'a>, dest: Endpoint<'a>) -> Self {
+        Self { source, dest }
+    }
+
+    /// Those of `paths`, relative to `source_root` on the source and
+    /// `dest_root` on the destination, whose contents differ, or that one
+    /// side couldn't hash.
+    pub async fn differing(
+        &self,
+        source_root: &str,
+        dest_root: &str,
+        paths: &[String],
+    ) -> Result<Vec<String>, String> {
+        if paths.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (source, dest) = tokio::try_join!(
+            sha256_all(&self.source, source_root, paths),
+            sha256_all(&self.dest, dest_root, paths),
+        )?;
+        Ok(paths
+            .iter()
+            .filter(|path| match (source.get(*path), dest.get(*path)) {
+                (Some(source), Some(dest)) => source != dest,
+                _ => true,
+            })
+            .cloned()
+            .collect())
+    }
+}
+
+/// The hex SHA-256 of each of `paths` below `root` on `side`. Files that can't
+/// be read are left out.
+pub(crate) async fn sha256_all(
+    side: &Endpoint<'_>,
+    root: &str,
+    paths: &[String],
+) -> Result<HashMap<String, String>, String> {
+    match side {
+        Endpoint::Local(_) => {
+            let (root, paths) = (root.to_string(), paths.to_vec());
+            tokio::task::spawn_blocking(move || sha256_local(Path::new(&root), &paths))
+                .await
+                .map_err(|e| messages::text("error.hashing_local_files_failed", &[("error", &e)]))
+        }
+        Endpoint::Remote { .. } => sha256_remote(side.session()?, root, paths).await,
+    }
+}
+
+fn sha256_local(root: &Path, paths: &[String]) -> HashMap<String, String> {
+    let hash = |path: &Path| -> std::io::Result<String> {
+        let mut file = std::fs::File::open(path)?;
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0u8; 256 * 1024];
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                return Ok(hex(&hasher.finalize()));
+            }
+            hasher.update(&buf[..n]);
+        }
+    };
+    paths
+        .iter()
+        .filter_map(|relative| {
+            Some((
+                relative.clone(),
+                hash(&local_path::join(root, relative)).ok()?,
+            ))
+        })
+        .collect()
+}
+
+async fn sha256_remote(
+    remote: &Remote,
+    root: &str,
+    paths: &[String],
+) -> Result<HashMap<String, String>, String> {
+    // The exit status of a file that couldn't be hashed is ignored.
+    let line = format!(
+        "cd {} && {{ {HASH_PATHS}; }} 2>/dev/null; true",
+        shell::quote(root)
+    );
+    let mut channel = remote.open_exec(&line).await?;
+    let mut writer = channel.make_writer();
+    let send = async {
+        for path in paths {
+            writer.write_all(&byte_path::to_bytes(path)).await?;
+            writer.write_all(&[0]).await?;
+        }
+        // Shutting the writer down sends EOF.
+        writer.shutdown().await
+    };
+    // Each hash is taken as its line arrives, so only a partial line is
+    // ever held back.
+    let receive = async {
+        let (mut hashes, mut pending, mut exit_status) = (HashMap::new(), Vec::new(), None);
+        while let Some(msg) = channel.wait().await {
+            match msg {
+                ChannelMsg::Data { data } => {
+                    pending.extend_from_slice(&data);
+                    take_lines(&mut pending, &mut hashes);
+                }
+                ChannelMsg::ExitStatus { exit_status: code } => exit_status = Some(code),
+                _ => {}
+            }
+        }
+        pending.push(b'\n');
+        take_lines(&mut pending, &mut hashes);
+        (hashes, exit_status)
+    };
+    let (sent, (hashes, exit_status)) = tokio::join!(send, receive);
+    ssh::audit_exit(&line, exit_status);
+    // A command that exits without reading everything breaks the write; its own error says why.
+    if exit_status != Some(0) {
+        return Err(command_failed(&line, exit_status, &[]));
+    }
+    sent.map_err(|e| {
+        messages::text(
+            "error.failed.send_input_command",
+            &[("command", &line), ("error", &e)],
+        )
+    })?;
+    Ok(hashes)
+}
+
+/// Parse the whole lines at the front of `pending` into `hashes`, leaving
+/// the one still arriving.
+fn take_lines(pending: &mut Vec<u8>, hashes: &mut HashMap<String, String>) {
+    let Some(end) = pending.iter().rposition(|b| *b == b'\n') else {
+        return;
+    };
+    hashes.extend(
+        byte_path::from_bytes(&pending[..end])
+            .lines()
+            .filter_map(parse_line),
+    );
+    pending.drain(..=end);
+}
+
+/// Split a `sha256sum` line, `<hash>  <path>` (or `<hash> *<path>` for binary
+/// mode). Lines for names with a newline or backslash start with `\` and
+/// escape them; those are skipped, so the file counts as unhashed.
+fn parse_line(line: &str) -> Option<(String, String)> {
+    if line.starts_with('\\') {
+        return None;
+    }
+    let (hash, rest) = line.split_once(' ')?;
+    let path = rest.strip_prefix(' ').or_else(|| rest.strip_prefix('*'))?;
+    Some((path.to_string(), hash.to_ascii_lowercase()))
+}
+
+pub(super) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_taken_as_they_arrive() {
+        let mut hashes = HashMap::new();
+        let mut pending = b"AB  wp-content/a.jpg\ncd  wp-con".to_vec();
+        take_lines(&mut pending, &mut hashes);
+        assert_eq!(hashes.len(), 1);
+        assert_eq!(pending, b"cd  wp-con");
+        pending.extend_from_slice(b"tent/b c.jpg\n\\ef  odd\\nname\n01 *bin");
+        take_lines(&mut pending, &mut hashes);
+        assert_eq!(pending, b"01 *bin");
+        pending.push(b'\n');
+        take_lines(&mut pending, &mut hashes);
+        assert!(pending.is_empty());
+        assert_eq!(
+            hashes,
+            HashMap::from([
+                ("wp-content/a.jpg".to_string(), "ab".to_string()),
+                ("wp-content/b c.jpg".to_string(), "cd".to_string()),
+                ("bin".to_string(), "01".to_string()),
+            ])
+        );
+    }
+}
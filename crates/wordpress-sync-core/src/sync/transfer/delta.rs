@@ -0,0 +1,484 @@
+//! Sending only the changed parts of large files the destination already has
+//! a version of, for SFTP transfers. rsync does this itself, and FTP has no
+//! shell on the server to help.
+//!
+//! Pushes use rsync's algorithm. The server reports an Adler-32 and a SHA-256
+//! of each fixed-size block of its copy; this machine slides a window over the
+//! new file, rolling the Adler-32 along a byte at a time, and wherever both
+//! sums match a block it sends a reference to that block instead of the data.
+//! The server rebuilds the file from its old copy and those instructions, so
+//! data inserted or removed part way only costs the blocks around it.
+//!
+//! Pulls compare the two versions block by block at the same offsets, since the
+//! sliding half would have to run on the server: data appended or changed in
+//! place is all that is fetched, but an insertion costs everything after it.
+//!
+//! The server's half is a few lines of Perl using only core modules, which
+//! practically every host has. If the server has no Perl, the half fails, or
+//! most of the file has changed anyway, the caller copies the file whole.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use russh_sftp::client::SftpSession;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use super::checksum;
+use super::pool::Meter;
+use crate::messages;
+use crate::shell;
+use crate::ssh::Session;
+
+/// Files smaller than this, on either side, are copied whole.
+const MIN_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The longest run of literal data in one delta instruction.
+const MAX_LITERAL: usize = 1024 * 1024;
+
+const ADLER_MOD: u32 = 65521;
+
+/// Prints `<adler32> <sha256>` for each block of the file `$ARGV[0]`, in
+/// blocks of `$ARGV[1]` bytes.
+const SIGNATURE_SCRIPT: &str = r#"
+use strict;
+use Compress::Zlib qw(adler32);
+use Digest::SHA qw(sha256_hex);
+open(my $in, "<", $ARGV[0]) or die "$ARGV[0]: $!\n";
+binmode $in;
+while (read($in, my $block, $ARGV[1])) {
+    printf "%08x %s\n", adler32($block), sha256_hex($block);
+}
+"#;
+
+/// Rebuilds the file `$ARGV[0]` from its old blocks of `$ARGV[2]` bytes and the
+/// delta on stdin, by way of `$ARGV[1]`, keeping its permissions.
+const APPLY_SCRIPT: &str = r#"
+use strict;
+my ($old, $tmp, $bs) = @ARGV;
+open(my $in, "<", $old) or die "$old: $!\n";
+open(my $out, ">", $tmp) or die "$tmp: $!\n";
+binmode $in;
+binmode $out;
+binmode STDIN;
+sub take {
+    my ($n) = @_;
+    my $buf = "";
+    while (length($buf) < $n) {
+        read(STDIN, $buf, $n - length($buf), length($buf)) or die "truncated delta\n";
+    }
+    return $buf;
+}
+while (read(STDIN, my $op, 1)) {
+    if ($op eq "C") {
+        my ($hi, $lo) = unpack("NN", take(8));
+        seek($in, ($hi * 4294967296 + $lo) * $bs, 0) or die "$old: $!\n";
+        read($in, my $block, $bs);
+        print $out $block or die "$tmp: $!\n";
+    } elsif ($op eq "L") {
+        print $out take(unpack("N", take(4))) or die "$tmp: $!\n";
+    } else {
+        die "bad delta\n";
+    }
+}
+close($out) or die "$tmp: $!\n";
+chmod((stat($old))[2] & 07777, $tmp);
+rename($tmp, $old) or die "$old: $!\n";
+"#;
+
+/// Whether a file of `size` bytes is worth patching over the destination's
+/// `old_size`-byte copy rather than copying whole.
+pub(super) fn worthwhile(size: u64, old_size: u64) -> bool {
+    size >= MIN_SIZE && old_size >= MIN_SIZE
+}
+
+/// The block size for an old version of `len` bytes: about 4,000 blocks, and
+/// between 64 KiB and 4 MiB each.
+fn block_size(len: u64) -> usize {
+    (len / 4096)
+        .next_power_of_two()
+        .clamp(64 * 1024, 4 * 1024 * 1024) as usize
+}
+
+/// The two sessions a delta copy works over, and where it reports progress.
+pub(super) struct Delta<'a, 'm> {
+    pub ssh: &'a Session,
+    pub sftp: &'a SftpSession,
+    pub meter: &'a Meter<'m>,
+}
+
+impl Delta<'_, '_> {
+    /// Bring the server's `remote`, `old_size` bytes long, up to date with the
+    /// local `local`. Returns false, having changed nothing, if so much
+    /// differs that the file should be copied whole.
+    pub(super) async fn push(
+        &self,
+        local: &str,
+        remote: &str,
+        old_size: u64,
+    ) -> Result<bool, String> {
+        if !self.ssh.platform().await.perl {
+            return Ok(false);
+        }
+        let block = block_size(old_size);
+        let signature = self.signature(remote, block).await?;
+        // Only whole blocks can match a window; a short last block never does.
+        let whole = (old_size / block as u64) as usize;
+        let delta_path = temp_path(local);
+        let (source, out) = (PathBuf::from(local), delta_path.clone());
+        let scanned = tokio::task::spawn_blocking(move || {
+            diff(
+                &source,
+                block,
+                &signature[..whole.min(signature.len())],
+                &out,
+            )
+        })
+        .await
+        .map_err(|e| {
+            messages::text(
+                "error.comparing_local_failed",
+                &[("local", &local), ("error", &e)],
+            )
+        })?;
+        let result = async {
+            let (literal, size) = scanned.map_err(|e| {
+                messages::text(
+                    "error.failed.compare_local",
+                    &[("local", &local), ("error", &e)],
+                )
+            })?;
+            if literal > size / 2 {
+                return Ok(false);
+            }
+            let mut delta = tokio::fs::File::open(&delta_path).await.map_err(|e| {
+                messages::text(
+                    "error.failed.open_path",
+                    &[("path", &delta_path.display()), ("error", &e)],
+                )
+            })?;
+            let tmp = format!("{remote}.wpsync-delta");
+            let line = format!(
+                "perl -e {} {} {} {block}",
+                shell::quote(APPLY_SCRIPT),
+                shell::quote(remote),
+                shell::quote(&tmp)
+            );
+            let output = self.ssh.exec_with_reader(&line, &mut delta).await?;
+            if !output.success() {
+                return Err(failed("rebuild", remote, &output.stderr));
+            }
+            self.meter.sent(literal as usize).await;
+            tracing::debug!(remote, literal, size, "pushed a delta");
+            Ok(true)
+        }
+        .await;
+        let _ = tokio::fs::remove_file(&delta_path).await;
+        result
+    }
+
+    /// Bring the local `local`, `old_size` bytes long, up to date with the
+    /// server's `remote`, which is `new_size` bytes. Returns false, having
+    /// changed nothing, if so much differs that the file should be copied whole.
+    pub(super) async fn pull(
+        &self,
+        remote: &str,
+        local: &str,
+        new_size: u64,
+        old_size: u64,
+    ) -> Result<bool, String> {
+        if !self.ssh.platform().await.perl {
+            return Ok(false);
+        }
+        let block = block_size(old_size);
+        let signature = self.signature(remote, block).await?;
+        let path = PathBuf::from(local);
+        let old = tokio::task::spawn_blocking(move || block_hashes(&path, block))
+            .await
+            .map_err(|e| {
+                messages::text(
+                    "error.comparing_local_failed",
+                    &[("local", &local), ("error", &e)],
+                )
+            })?
+            .map_err(|e| {
+                messages::text(
+                    "error.failed.compare_local",
+                    &[("local", &local), ("error", &e)],
+                )
+            })?;
+        let changed: Vec<bool> = signature
+            .iter()
+            .enumerate()
+            .map(|(i, (_, strong))| old.get(i) != Some(strong))
+            .collect();
+        let fetched = changed.iter().filter(|&&changed| changed).count() as u64 * block as u64;
+        if fetched > new_size / 2 {
+            return Ok(false);
+        }
+
+        let tmp = temp_path(local);
+        let result = self
+            .rebuild(remote, local, &tmp, block, new_size, &changed)
+            .await;
+        match result {
+            Ok(()) => tokio::fs::rename(&tmp, local).await.map_err(|e| {
+                messages::text(
+                    "error.failed.replace_path",
+                    &[("path", &local), ("error", &e)],
+                )
+            })?,
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&tmp).await;
+                return Err(e);
+            }
+        }
+        tracing::debug!(remote, fetched, new_size, "pulled a delta");
+        Ok(true)
+    }
+
+    /// Write the new version of a pulled file to `tmp`: the `changed` blocks
+    /// from the server, the rest from the old copy at `local`.
+    async fn rebuild(
+        &self,
+        remote: &str,
+        local: &str,
+        tmp: &Path,
+        block: usize,
+        new_size: u64,
+        changed: &[bool],
+    ) -> Result<(), String> {
+        let mut old = tokio::fs::File::open(local).await.map_err(|e| {
+            messages::text("error.failed.open_path", &[("path", &local), ("error", &e)])
+        })?;
+        let mut server = self.sftp.open(remote).await.map_err(|e| {
+            messages::text(
+                "error.failed.open_path_over_sftp",
+                &[("path", &remote), ("error", &e)],
+            )
+        })?;
+        let mut out = tokio::fs::File::create(tmp).await.map_err(|e| {
+            messages::text(
+                "error.failed.create_path",
+                &[("path", &tmp.display()), ("error", &e)],
+            )
+        })?;
+        let mut buf = vec![0u8; block];
+        for (i, &changed) in changed.iter().enumerate() {
+            let offset = i as u64 * block as u64;
+            let len = (new_size - offset).min(block as u64) as usize;
+            let buf = &mut buf[..len];
+            if changed {
+                server
+                    .seek(io::SeekFrom::Start(offset))
+                    .await
+                    .map_err(|e| {
+                        messages::text(
+                            "error.failed.seek_path",
+                            &[("path", &remote), ("error", &e)],
+                        )
+                    })?;
+                server.read_exact(buf).await.map_err(|e| {
+                    messages::text(
+                        "error.failed.read_path_over_sftp",
+                        &[("path", &remote), ("error", &e)],
+                    )
+                })?;
+                self.meter.sent(len).await;
+            } else {
+                old.seek(io::SeekFrom::Start(offset)).await.map_err(|e| {
+                    messages::text("error.failed.seek_path", &[("path", &local), ("error", &e)])
+                })?;
+                old.read_exact(buf).await.map_err(|e| {
+                    messages::text("error.failed.read_path", &[("path", &local), ("error", &e)])
+                })?;
+            }
+            out.write_all(buf).await.map_err(|e| {
+                messages::text(
+                    "error.failed.write_path",
+                    &[("path", &tmp.display()), ("error", &e)],
+                )
+            })?;
+        }
+        out.flush().await.map_err(|e| {
+            messages::text(
+                "error.failed.write_path",
+                &[("path", &tmp.display()), ("error", &e)],
+            )
+        })
+    }
+
+    /// The Adler-32 and hex SHA-256 of each `block`-byte block of the server's `path`.
+    async fn signature(&self, path: &str, block: usize) -> Result<Vec<(u32, String)>, String> {
+        let line = format!(
+            "perl -e {} {} {block}",
+            shell::quote(SIGNATURE_SCRIPT),
+            shell::quote(path)
+        );
+        let output = self.ssh.exec(&line).await?;
+        if !output.success() {
+            return Err(failed("sign", path, &output.stderr));
+        }
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(|line| {
+                let (weak, strong) = line.split_once(' ')?;
+                Some((u32::from_str_radix(weak, 16).ok()?, strong.to_string()))
+            })
+            .collect::<Option<_>>()
+            .ok_or_else(|| {
+                messages::text("error.unexpected_block_signature_path", &[("path", &path)])
+            })
+    }
+}
+
+fn failed(action: &str, path: &str, stderr: &[u8]) -> String {
+    messages::text(
+        "error.failed.action_path_server_stderr",
+        &[
+            ("action", &action),
+            ("path", &path),
+            ("stderr", &String::from_utf8_lossy(stderr).trim()),
+        ],
+    )
+}
+
+/// A scratch file next to `path`, for the delta of a push or the new version of a pull.
+fn temp_path(path: &str) -> PathBuf {
+    PathBuf::from(format!("{path}.wpsync-delta"))
+}
+
+/// Adler-32 over a window of bytes, which can slide along a byte at a time.
+#[derive(Clone, Copy)]
+struct Adler {
+    a: u32,
+    b: u32,
+}
+
+impl Adler {
+    fn of(data: &[u8]) -> Self {
+        let (mut a, mut b) = (1u32, 0u32);
+        for &byte in data {
+            a = (a + u32::from(byte)) % ADLER_MOD;
+            b = (b + a) % ADLER_MOD;
+        }
+        Self { a, b }
+    }
+
+    /// The sum of the window `len` bytes long one byte on, without `out` and with `next`.
+    fn roll(self, out: u8, next: u8, len: usize) -> Self {
+        let m = u64::from(ADLER_MOD);
+        let a = (u64::from(self.a) + m - u64::from(out) + u64::from(next)) % m;
+        let dropped = (len as u64 % m) * u64::from(out) % m;
+        let b = (u64::from(self.b) + m - dropped + a + m - 1) % m;
+        Self {
+            a: a as u32,
+            b: b as u32,
+        }
+    }
+
+    fn value(self) -> u32 {
+        (self.b << 16) | self.a
+    }
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    checksum::hex(&Sha256::digest(data))
+}
+
+/// Scan the file at `path` for the blocks in `signature`, writing the delta that
+/// rebuilds it to `out`. Returns the literal bytes the delta carries and the
+/// file's size.
+fn diff(
+    path: &Path,
+    block: usize,
+    signature: &[(u32, String)],
+    out: &Path,
+) -> io::Result<(u64, u64)> {
+    let mut blocks: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (index, (weak, _)) in signature.iter().enumerate() {
+        blocks.entry(*weak).or_default().push(index);
+    }
+    let mut input = BufReader::new(File::open(path)?);
+    let mut writer = BufWriter::new(File::create(out)?);
+    let mut chunk = vec![0u8; MAX_LITERAL];
+    // `buf[literal..start]` is data not yet matched; the window starts at `start`.
+    let (mut buf, mut literal, mut start) = (Vec::new(), 0, 0);
+    let (mut eof, mut weak, mut literal_bytes, mut size) = (false, None, 0, 0);
+    loop {
+        // Rolling on needs the byte after the window too.
+        if !eof && buf.len() - start <= block {
+            buf.drain(..literal);
+            start -= literal;
+            literal = 0;
+            let n = input.read(&mut chunk)?;
+            eof = n == 0;
+            buf.extend_from_slice(&chunk[..n]);
+            size += n as u64;
+            continue;
+        }
+        if buf.len() - start < block {
+            break;
+        }
+        let window = &buf[start..start + block];
+        let sum = *weak.get_or_insert_with(|| Adler::of(window));
+        let found = blocks.get(&sum.value()).and_then(|candidates| {
+            let strong = sha256_hex(window);
+            candidates
+                .iter()
+                .find(|&&index| signature[index].1 == strong)
+        });
+        if let Some(&index) = found {
+            literal_bytes += write_literal(&mut writer, &buf[literal..start])?;
+            writer.write_all(b"C")?;
+            writer.write_all(&(index as u64).to_be_bytes())?;
+            start += block;
+            literal = start;
+            weak = None;
+            continue;
+        }
+        if start + block == buf.len() {
+            break;
+        }
+        weak = Some(sum.roll(buf[start], buf[start + block], block));
+        start += 1;
+        if start - literal >= MAX_LITERAL {
+            literal_bytes += write_literal(&mut writer, &buf[literal..start])?;
+            literal = start;
+        }
+    }
+    literal_bytes += write_literal(&mut writer, &buf[literal..])?;
+    writer.flush()?;
+    Ok((literal_bytes, size))
+}
+
+fn write_literal(writer: &mut impl Write, data: &[u8]) -> io::Result<u64> {
+    for piece in data.chunks(MAX_LITERAL) {
+        writer.write_all(b"L")?;
+        writer.write_all(&(piece.len() as u32).to_be_bytes())?;
+        writer.write_all(piece)?;
+    }
+    Ok(data.len() as u64)
+}
+
+/// The hex SHA-256 of each `block`-byte block of the local file at `path`.
+fn block_hashes(path: &Path, block: usize) -> io::Result<Vec<String>> {
+    let mut input = File::open(path)?;
+    let mut buf = vec![0u8; block];
+    let mut hashes = Vec::new();
+    loop {
+        let mut len = 0;
+        while len < block {
+            match input.read(&mut buf[len..])? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        if len == 0 {
+            return Ok(hashes);
+        }
+        hashes.push(sha256_hex(&buf[..len]));
+    }
+}
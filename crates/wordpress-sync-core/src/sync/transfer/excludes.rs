@@ -0,0 +1,177 @@
+//! `.gitignore`-style exclude rules for file transfers.
+//!
+//! The rules follow gitignore: blank lines and lines starting with `#` are
+//! ignored, a leading `!` re-includes what an earlier rule excluded, a trailing
+//! `/` matches only directories, and a pattern containing a `/` anywhere but at
+//! the end is relative to the transfer root, while one without matches a name
+//! at any depth. `*` stays within one path component and `**` spans any number.
+//! The last rule that matches a path decides, and nothing inside an excluded
+//! directory can be re-included.
+//!
+//! Transfer methods that walk trees themselves ask [`Excludes::is_excluded`];
+//! rsync gets the same rules translated into its filter syntax. On Windows,
+//! whose names match whatever their case, so do the rules.
+
+use globset::{GlobBuilder, GlobMatcher};
+
+use crate::messages;
+use crate::sync::local_path;
+
+/// A compiled list of exclude rules.
+#[derive(Debug, Default)]
+pub struct Excludes {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    /// The line as written, for reporting which rule decided.
+    raw: String,
+    matcher: GlobMatcher,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool,
+    /// The pattern without `!`, the trailing `/`, or the anchoring `/`.
+    pattern: String,
+}
+
+impl Excludes {
+    /// The rules in `patterns`, matching names whatever their case where
+    /// this machine's do.
+    pub fn new(patterns: &[String]) -> Result<Self, String> {
+        Self::matching(patterns, local_path::CASE_INSENSITIVE)
+    }
+
+    /// The rules in `patterns`, matching names whatever their case if
+    /// `ignore_case`.
+    pub(crate) fn matching(patterns: &[String], ignore_case: bool) -> Result<Self, String> {
+        let rules = patterns
+            .iter()
+            .filter_map(|raw| Rule::parse(raw, ignore_case).transpose())
+            .collect::<Result<_, String>>()?;
+        Ok(Self { rules })
+    }
+
+    /// Whether `path`, relative to the transfer root with `/` separators, is excluded.
+    pub fn is_excluded(&self, path: &str, is_dir: bool) -> bool {
+        self.decision(path, is_dir)
+            .is_some_and(|(excluded, _)| excluded)
+    }
+
+    /// Whether `path` is excluded and the rule that decided it, or `None` if no
+    /// rule matches it or any of its parent directories.
+    pub fn decision(&self, path: &str, is_dir: bool) -> Option<(bool, &str)> {
+        let path = path.trim_matches('/');
+        // An excluded parent hides everything below it, whatever later rules say.
+        for (i, _) in path.match_indices('/') {
+            if let Some((true, rule)) = self.own_decision(&path[..i], true) {
+                return Some((true, rule));
+            }
+        }
+        self.own_decision(path, is_dir)
+    }
+
+    /// The last rule matching `path` itself.
+    fn own_decision(&self, path: &str, is_dir: bool) -> Option<(bool, &str)> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| (is_dir || !rule.dir_only) && rule.matcher.is_match(path))
+            .map(|rule| (!rule.negated, rule.raw.as_str()))
+    }
+
+    /// The rules as rsync `--filter` arguments.
+    ///
+    /// rsync stops at the first matching rule where gitignore takes the last,
+    /// so the order is reversed.
+    pub fn rsync_filters(&self) -> Vec<String> {
+        self.rules
+            .iter()
+            .rev()
+            .map(|rule| {
+                let sign = if rule.negated { '+' } else { '-' };
+                let anchor = if rule.anchored { "/" } else { "" };
+                let slash = if rule.dir_only { "/" } else { "" };
+                format!("--filter={sign} {anchor}{}{slash}", rule.pattern)
+            })
+            .collect()
+    }
+}
+
+impl Rule {
+    /// Compile one line, matching whatever the case if `ignore_case`; `None`
+    /// for blank lines and comments.
+    fn parse(raw: &str, ignore_case: bool) -> Result<Option<Self>, String> {
+        let line = raw.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        // `\#` and `\!` stand for a leading `#` or `!` that means itself.
+        let line = line
+            .strip_prefix('\\')
+            .filter(|rest| rest.starts_with(['#', '!']))
+            .unwrap_or(line);
+        let dir_only = line.ends_with('/');
+        let line = line.trim_end_matches('/');
+        // A leading `**/` matches at any depth, which is what an unanchored pattern does.
+        let (anchored, pattern) = match line.strip_prefix("**/") {
+            Some(rest) => (false, rest),
+            None => (line.contains('/'), line.trim_start_matches('/')),
+        };
+        if pattern.is_empty() {
+            return Err(messages::text(
+                "error.invalid_exclude_pattern_raw_nothing_match",
+                &[("raw", &format_args!("{raw:?}"))],
+            ));
+        }
+        let glob = if anchored || pattern.contains('/') {
+            pattern.to_string()
+        } else {
+            format!("**/{pattern}")
+        };
+        let matcher = GlobBuilder::new(&glob)
+            .literal_separator(true)
+            .backslash_escape(true)
+            .case_insensitive(ignore_case)
+            .build()
+            .map_err(|e| {
+                messages::text(
+                    "error.invalid_exclude_pattern_raw",
+                    &[("raw", &format_args!("{raw:?}")), ("error", &e)],
+                )
+            })?
+            .compile_matcher();
+        Ok(Some(Self {
+            raw: raw.to_string(),
+            matcher,
+            negated,
+            dir_only,
+            anchored,
+            pattern: pattern.to_string(),
+        }))
+    }
+}
+
+/// Re-express a rule written relative to the WordPress root for a transfer
+/// rooted at `base` below it. Rules anchored outside `base` no longer apply and
+/// give `None`; unanchored rules apply unchanged.
+pub fn rebase_exclude(raw: &str, base: &str) -> Option<String> {
+    let line = raw.trim_end();
+    let (bang, rest) = match line.strip_prefix('!') {
+        Some(rest) => ("!", rest),
+        None => ("", line),
+    };
+    let unanchored = rest.starts_with("**/") || !rest.trim_end_matches('/').contains('/');
+    if rest.is_empty() || rest.starts_with('#') || unanchored {
+        return Some(raw.to_string());
+    }
+    let inner = rest
+        .trim_start_matches('/')
+        .strip_prefix(base.trim_matches('/'))?
+        .strip_prefix('/')?;
+    Some(format!("{bang}/{inner}"))
+}
@@ -0,0 +1,668 @@
+//! File transfer over FTP, normally secured with TLS, for hosts with no SSH access.
+//!
+//! Only one data connection can be open per FTP session, so parallel copies
+//! each log in separately, and every operation holds its connection's lock for
+//! its whole transfer. Change detection mirrors the
+//! SFTP backend, except that modification times are only compared when the
+//! server can report them precisely (`MLSD`) and, for pushes, set them (`MFMT`);
+//! otherwise files are compared by size alone. A large file whose copy was cut
+//! off carries on where it stopped, with `APPE` or `REST`; see
+//! [`partial`](super::partial). FTP can't create symlinks, so links are only
+//! ever followed, on this machine's side, or skipped.
+
+use std::io::{self, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use chrono::{DateTime, Utc};
+use suppaftp::list::{File as ListEntry, ListParser};
+use suppaftp::tokio::{AsyncRustlsConnector, AsyncRustlsFtpStream};
+use suppaftp::{FtpError, Mode, Status};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+use super::partial;
+use super::pool::{self, Meter};
+use super::tree::{self, Entry, Tree};
+use super::walk::{self, Lister, Walk};
+use super::{BoxFuture, Excludes, FileChanges, MirrorOptions, Mirrored, OnProgress, Transfer};
+use crate::credentials::{self, FTP_PASSWORD_SERVICE};
+use crate::messages;
+use crate::net;
+use crate::profile::{FtpTls, Profile, SymlinkPolicy};
+use crate::sync::Direction;
+
+/// Read/write chunk size; also how often byte progress is reported.
+const CHUNK_SIZE: usize = 256 * 1024;
+
+pub struct Ftp {
+    /// One logged-in connection per parallel copy.
+    streams: Vec<Mutex<AsyncRustlsFtpStream>>,
+    /// The server lists directories with `MLSD`, which gives exact UTC times.
+    mlsd: bool,
+    /// The server can set modification times with `MFMT`.
+    mfmt: bool,
+    /// Follow this machine's symlinks rather than skip them.
+    follow_links: bool,
+}
+
+impl Ftp {
+    /// Connect, secure, and log in with the profile's FTP settings and stored
+    /// password, `parallel` times over or as often as the server allows.
+    pub async fn open(profile: &Profile, parallel: u32) -> Result<Self, String> {
+        let reference = profile.secrets.ftp_password.as_deref();
+        let password = credentials::resolve(reference, FTP_PASSWORD_SERVICE, &profile.id)?
+            .ok_or_else(|| {
+                messages::text(
+                    "error.no_ftp_password_stored_name",
+                    &[("name", &profile.name)],
+                )
+            })?;
+        let mut first = log_in(profile, &password).await?;
+        let features = first.feat().await.unwrap_or_default();
+        let supports = |name: &str| features.keys().any(|key| key.eq_ignore_ascii_case(name));
+        let (mlsd, mfmt) = (supports("MLST"), supports("MFMT"));
+
+        let mut streams = vec![Mutex::new(first)];
+        while streams.len() < parallel as usize {
+            // Shared hosts often cap the connections one user may have open.
+            match log_in(profile, &password).await {
+                Ok(stream) => streams.push(Mutex::new(stream)),
+                Err(e) => {
+                    tracing::info!(connections = streams.len(), "no more FTP connections: {e}");
+                    break;
+                }
+            }
+        }
+        Ok(Self {
+            streams,
+            mlsd,
+            mfmt,
+            follow_links: profile.symlinks == SymlinkPolicy::Follow,
+        })
+    }
+
+    /// The connection that lists, creates, and deletes.
+    fn control(&self) -> &Mutex<AsyncRustlsFtpStream> {
+        &self.streams[0]
+    }
+
+    /// Copy one file across, counting bytes into `meter`, and carry over its
+    /// modification time where the server allows. With `staged`, the file is
+    /// written to its partial copy from that offset on, then renamed into place.
+    async fn copy(
+        &self,
+        stream: &Mutex<AsyncRustlsFtpStream>,
+        direction: Direction,
+        (source, dest): (&str, &str),
+        mtime: Option<u64>,
+        staged: Option<u64>,
+        meter: &Meter<'_>,
+    ) -> Result<(), String> {
+        let mut stream = stream.lock().await;
+        let mut buf = vec![0u8; CHUNK_SIZE];
+        let offset = staged.unwrap_or(0);
+        let written = match staged {
+            Some(_) => partial::path(dest),
+            None => dest.to_string(),
+        };
+        match direction {
+            Direction::Push => {
+                let mut reader = tokio::fs::File::open(source).await.map_err(|e| {
+                    messages::text(
+                        "error.failed.open_path",
+                        &[("path", &source), ("error", &e)],
+                    )
+                })?;
+                let upload = if offset > 0 {
+                    reader.seek(SeekFrom::Start(offset)).await.map_err(|e| {
+                        messages::text(
+                            "error.failed.read_path",
+                            &[("path", &source), ("error", &e)],
+                        )
+                    })?;
+                    stream.append_with_stream(&written).await
+                } else {
+                    stream.put_with_stream(&written).await
+                };
+                let mut upload = upload.map_err(|e| {
+                    messages::text(
+                        "error.failed.upload_dest",
+                        &[("dest", &dest), ("error", &e)],
+                    )
+                })?;
+                loop {
+                    let n = reader.read(&mut buf).await.map_err(|e| {
+                        messages::text(
+                            "error.failed.read_path",
+                            &[("path", &source), ("error", &e)],
+                        )
+                    })?;
+                    if n == 0 {
+                        break;
+                    }
+                    upload.write_all(&buf[..n]).await.map_err(|e| {
+                        messages::text(
+                            "error.failed.upload_dest",
+                            &[("dest", &dest), ("error", &e)],
+                        )
+                    })?;
+                    meter.sent(n).await;
+                }
+                upload.finish().await.map_err(|e| {
+                    messages::text(
+                        "error.failed.upload_dest",
+                        &[("dest", &dest), ("error", &e)],
+                    )
+                })?;
+                if staged.is_some() {
+                    // Not every server's rename replaces an existing file.
+                    let _ = stream.rm(dest).await;
+                    stream.rename(written.as_str(), dest).await.map_err(|e| {
+                        messages::text(
+                            "error.failed.move_written_dest_over_ftp",
+                            &[("written", &written), ("dest", &dest), ("error", &e)],
+                        )
+                    })?;
+                }
+                if let (true, Some(mtime)) = (self.mfmt, mtime) {
+                    let command = format!("MFMT {} {dest}", format_time(mtime));
+                    stream
+                        .custom_command(command, &[Status::File])
+                        .await
+                        .map_err(|e| {
+                            messages::text(
+                                "error.failed.set_modification_time_dest",
+                                &[("dest", &dest), ("error", &e)],
+                            )
+                        })?;
+                }
+                Ok(())
+            }
+            Direction::Pull => {
+                if offset > 0 {
+                    stream.resume_transfer(offset as usize).await.map_err(|e| {
+                        messages::text(
+                            "error.failed.resume_downloading_source",
+                            &[("source", &source), ("error", &e)],
+                        )
+                    })?;
+                }
+                let mut download = stream.retr_as_stream(source).await.map_err(|e| {
+                    messages::text(
+                        "error.failed.download_url",
+                        &[("url", &source), ("error", &e)],
+                    )
+                })?;
+                let writer = if offset > 0 {
+                    tokio::fs::File::options().append(true).open(&written).await
+                } else {
+                    tokio::fs::File::create(&written).await
+                };
+                let mut writer = writer.map_err(|e| {
+                    messages::text(
+                        "error.failed.open_path",
+                        &[("path", &written), ("error", &e)],
+                    )
+                })?;
+                loop {
+                    let n = download.read(&mut buf).await.map_err(|e| {
+                        messages::text(
+                            "error.failed.download_url",
+                            &[("url", &source), ("error", &e)],
+                        )
+                    })?;
+                    if n == 0 {
+                        break;
+                    }
+                    writer.write_all(&buf[..n]).await.map_err(|e| {
+                        messages::text("error.failed.write_path", &[("path", &dest), ("error", &e)])
+                    })?;
+                    meter.sent(n).await;
+                }
+                download.finish().await.map_err(|e| {
+                    messages::text(
+                        "error.failed.download_url",
+                        &[("url", &source), ("error", &e)],
+                    )
+                })?;
+                writer.flush().await.map_err(|e| {
+                    messages::text("error.failed.write_path", &[("path", &dest), ("error", &e)])
+                })?;
+                if let Some(mtime) = mtime {
+                    writer
+                        .into_std()
+                        .await
+                        .set_modified(UNIX_EPOCH + Duration::from_secs(mtime))
+                        .map_err(|e| {
+                            messages::text(
+                                "error.failed.set_modification_time_dest",
+                                &[("dest", &dest), ("error", &e)],
+                            )
+                        })?;
+                }
+                if staged.is_some() {
+                    tokio::fs::rename(&written, dest).await.map_err(|e| {
+                        messages::text(
+                            "error.failed.move_written_dest",
+                            &[("written", &written), ("dest", &dest), ("error", &e)],
+                        )
+                    })?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Walk both sides of a mirror, leaving out the names this protocol
+    /// can't send, and the source's links unless `links` is set.
+    fn walk<'a>(
+        &'a self,
+        direction: Direction,
+        (source, dest): (&'a str, &'a str),
+        excludes: &'a Excludes,
+        links: bool,
+    ) -> Walk<'a> {
+        let local = |root: &'a str, follow| -> Box<dyn Lister + 'a> {
+            Box::new(walk::Local {
+                root: Path::new(root),
+                excludes,
+                follow,
+            })
+        };
+        let remote = |root: &'a str| -> Box<dyn Lister + 'a> {
+            Box::new(Listing {
+                ftp: self,
+                root,
+                excludes,
+            })
+        };
+        match direction {
+            Direction::Push => Walk::new(
+                direction,
+                local(source, self.follow_links),
+                remote(dest),
+                links,
+            ),
+            Direction::Pull => Walk::new(direction, remote(source), local(dest, false), links),
+        }
+    }
+
+    /// List a tree on the server, skipping excluded paths; symlinks are
+    /// listed as links, whatever they point to.
+    async fn remote_tree(&self, root: &str, excludes: &Excludes) -> Result<Tree, String> {
+        let mut listing = Tree::new();
+        let mut pending = vec![String::new()];
+        while let Some(dir) = pending.pop() {
+            let entries = self.remote_dir(root, &dir, excludes).await?;
+            pending.extend(
+                entries
+                    .iter()
+                    .filter(|(_, entry)| **entry == Entry::Dir)
+                    .map(|(relative, _)| relative.clone()),
+            );
+            listing.extend(entries);
+        }
+        Ok(listing)
+    }
+
+    /// The entries of the directory `dir` in a tree on the server, listed as
+    /// [`remote_tree`](Self::remote_tree) lists them.
+    async fn remote_dir(&self, root: &str, dir: &str, excludes: &Excludes) -> Result<Tree, String> {
+        let mut listing = Tree::new();
+        let path = tree::join_remote(root, dir);
+        let lines = {
+            let mut stream = self.control().lock().await;
+            if self.mlsd {
+                stream.mlsd(Some(&path)).await
+            } else {
+                stream.list(Some(&path)).await
+            }
+        };
+        let lines = match lines {
+            Ok(lines) => lines,
+            // A destination that doesn't exist yet is simply empty.
+            Err(_) if dir.is_empty() => return Ok(listing),
+            Err(e) => {
+                return Err(messages::text(
+                    "error.failed.list_path_over_ftp",
+                    &[("path", &path), ("error", &e)],
+                ))
+            }
+        };
+        for line in lines {
+            let parsed = if self.mlsd {
+                ListParser::parse_mlsd(&line)
+            } else {
+                ListEntry::try_from(line.as_str())
+            };
+            let Ok(entry) = parsed else {
+                continue; // Unparseable listing lines (e.g. `total 42`)
+            };
+            let name = entry.name();
+            if name == "." || name == ".." || name.is_empty() {
+                continue;
+            }
+            let relative = tree::join_relative(dir, name);
+            if entry.is_directory() {
+                if !excludes.is_excluded(&relative, true) {
+                    listing.insert(relative, Entry::Dir);
+                }
+            } else if entry.is_file() && !excludes.is_excluded(&relative, false) {
+                let mtime = self
+                    .mlsd
+                    .then(|| entry.modified().duration_since(UNIX_EPOCH).ok())
+                    .flatten()
+                    .map(|since| since.as_secs());
+                let entry = Entry::File {
+                    size: entry.size() as u64,
+                    mtime,
+                };
+                listing.insert(relative, entry);
+            } else if entry.is_symlink() && !excludes.is_excluded(&relative, false) {
+                let target = entry.symlink().unwrap_or(Path::new(""));
+                listing.insert(relative, Entry::link(&target.to_string_lossy()));
+            }
+        }
+        Ok(listing)
+    }
+
+    async fn create_dir(&self, direction: Direction, path: &str) -> Result<(), String> {
+        match direction {
+            Direction::Push => self.control().lock().await.mkdir(path).await.map_err(|e| {
+                messages::text(
+                    "error.failed.create_path_over_ftp",
+                    &[("path", &path), ("error", &e)],
+                )
+            }),
+            Direction::Pull => tokio::fs::create_dir_all(path).await.map_err(|e| {
+                messages::text(
+                    "error.failed.create_path",
+                    &[("path", &path), ("error", &e)],
+                )
+            }),
+        }
+    }
+
+    async fn remove(&self, direction: Direction, path: &str, entry: Entry) -> Result<(), String> {
+        let result = match direction {
+            Direction::Push => {
+                let mut stream = self.control().lock().await;
+                match entry {
+                    Entry::Dir => stream.rmdir(path).await,
+                    Entry::File { .. } | Entry::Link { .. } => stream.rm(path).await,
+                }
+                .map_err(|e| e.to_string())
+            }
+            Direction::Pull => match entry {
+                Entry::Dir => tokio::fs::remove_dir(path).await,
+                Entry::File { .. } | Entry::Link { .. } => tokio::fs::remove_file(path).await,
+            }
+            .map_err(|e| e.to_string()),
+        };
+        result.map_err(|e| {
+            messages::text(
+                "error.failed.delete_path",
+                &[("path", &path), ("error", &e)],
+            )
+        })
+    }
+
+    /// Whether two versions of a file can be considered the same.
+    fn unchanged(&self, direction: Direction, source: Entry, dest: Entry) -> bool {
+        let (
+            Entry::File {
+                size: source_size,
+                mtime: source_mtime,
+            },
+            Entry::File {
+                size: dest_size,
+                mtime: dest_mtime,
+            },
+        ) = (source, dest)
+        else {
+            return false;
+        };
+        // Uploaded files keep the upload time unless MFMT can fix it, so their
+        // times would never line up with the local copy.
+        let times_comparable = self.mlsd && (direction == Direction::Pull || self.mfmt);
+        source_size == dest_size && (!times_comparable || source_mtime == dest_mtime)
+    }
+}
+
+impl Transfer for Ftp {
+    fn mirror<'a>(
+        &'a self,
+        direction: Direction,
+        source: &'a str,
+        dest: &'a str,
+        excludes: &'a Excludes,
+        options: &'a MirrorOptions<'a>,
+        on_progress: OnProgress<'a>,
+    ) -> BoxFuture<'a, Result<Mirrored, String>> {
+        Box::pin(async move {
+            let mut walk = self.walk(direction, (source, dest), excludes, false);
+            let join_source = |relative: &str| tree::join_side(direction, true, source, relative);
+            let join_dest = |relative: &str| tree::join_side(direction, false, dest, relative);
+
+            // What differs is found first, and only then changed, so nothing
+            // is deleted from under the walk.
+            let mut stale = Vec::new();
+            let mut dirs = Vec::new();
+            let mut files = Vec::new();
+            while let Some(pair) = walk.next().await? {
+                if let (true, Some(existing)) = (pair.is_stale(), pair.dest) {
+                    stale.push((pair.relative.clone(), existing));
+                }
+                let (relative, existing) = (pair.relative, pair.dest);
+                match pair.source {
+                    Some(Entry::Dir) if existing != Some(Entry::Dir) => dirs.push(relative),
+                    Some(entry @ Entry::File { .. })
+                        if !existing.is_some_and(|dest| self.unchanged(direction, entry, dest)) =>
+                    {
+                        files.push((relative, entry, existing))
+                    }
+                    // Links are left out of the walk.
+                    _ => {}
+                }
+            }
+            let Walk {
+                partials,
+                skipped_links,
+                skipped_names,
+                ..
+            } = walk;
+
+            let mut changes = FileChanges::default();
+            // Deepest paths first, so directories are empty by the time they're removed.
+            for (relative, entry) in stale.into_iter().rev() {
+                self.remove(direction, &join_dest(&relative), entry).await?;
+                changes.deleted.push(tree::display(&relative, entry));
+            }
+
+            // The root itself may be missing on first sync; a failed MKD on an
+            // existing directory is harmless.
+            let _ = self.create_dir(direction, dest).await;
+
+            for relative in dirs {
+                self.create_dir(direction, &join_dest(&relative)).await?;
+                changes.added.push(tree::display(&relative, Entry::Dir));
+            }
+            let mut to_copy = Vec::new();
+            for (relative, entry, existing) in files {
+                let Entry::File { size, mtime } = entry else {
+                    continue;
+                };
+                let staged = (size >= partial::MIN_SIZE)
+                    .then(|| partial::resume_from(partials.get(&relative), size, mtime));
+                match existing {
+                    Some(Entry::File { .. }) => changes.updated.push(relative.clone()),
+                    _ => changes.added.push(relative.clone()),
+                }
+                changes.bytes += size;
+                to_copy.push((relative, (mtime, staged)));
+            }
+            // Partial copies that won't be carried on with are only in the way.
+            for (relative, &entry) in &partials {
+                let resumed = to_copy
+                    .iter()
+                    .any(|(path, (_, staged))| path == relative && staged.is_some_and(|at| at > 0));
+                if !resumed {
+                    self.remove(direction, &join_dest(&partial::path(relative)), entry)
+                        .await?;
+                }
+            }
+
+            // With no shell on the server there are no checksums to compare.
+            let copied = to_copy.iter().map(|(path, _)| path.clone()).collect();
+            let meter = Meter::new(to_copy.len(), options.throttle, on_progress);
+            pool::copy_all(
+                &self.streams,
+                to_copy,
+                &meter,
+                |stream, relative, (mtime, staged)| {
+                    let (source, dest) = (join_source(&relative), join_dest(&relative));
+                    let meter = &meter;
+                    async move {
+                        let paths = (source.as_str(), dest.as_str());
+                        self.copy(stream, direction, paths, mtime, staged, meter)
+                            .await
+                    }
+                },
+            )
+            .await?;
+            Ok(Mirrored {
+                copied,
+                skipped_links,
+                skipped_names,
+                changes,
+            })
+        })
+    }
+
+    fn plan<'a>(
+        &'a self,
+        direction: Direction,
+        source: &'a str,
+        dest: &'a str,
+        excludes: &'a Excludes,
+    ) -> BoxFuture<'a, Result<FileChanges, String>> {
+        Box::pin(async move {
+            let walk = self.walk(direction, (source, dest), excludes, true);
+            walk::changes(walk, |source, dest| self.unchanged(direction, source, dest)).await
+        })
+    }
+
+    fn list<'a>(
+        &'a self,
+        root: &'a str,
+        excludes: &'a Excludes,
+    ) -> BoxFuture<'a, Result<Tree, String>> {
+        Box::pin(async move {
+            let mut listing = self.remote_tree(root, excludes).await?;
+            partial::split(&mut listing);
+            tree::take_links(&mut listing);
+            Ok(listing)
+        })
+    }
+}
+
+/// A tree on the server, as one side of a walk.
+struct Listing<'a> {
+    ftp: &'a Ftp,
+    root: &'a str,
+    excludes: &'a Excludes,
+}
+
+impl Lister for Listing<'_> {
+    fn read_dir<'a>(&'a self, dir: &'a str) -> BoxFuture<'a, Result<Tree, String>> {
+        Box::pin(self.ftp.remote_dir(self.root, dir, self.excludes))
+    }
+}
+
+/// Open one connection to the server and log in.
+async fn log_in(profile: &Profile, password: &str) -> Result<AsyncRustlsFtpStream, String> {
+    let env = &profile.remote;
+    let settings = &env.ftp;
+    let connecting = async {
+        let tcp = net::connect(&env.host, settings.port, env.address_family)
+            .await
+            .map_err(|e| FtpError::ConnectionError(io::Error::other(e)))?;
+        match settings.tls {
+            FtpTls::Explicit => {
+                AsyncRustlsFtpStream::connect_with_stream(tcp)
+                    .await?
+                    .into_secure(tls_connector(), &env.host)
+                    .await
+            }
+            FtpTls::Implicit => {
+                // Which can only open its own connection, so is sent to the
+                // address that answered first.
+                let addr = tcp.peer_addr().map_err(FtpError::ConnectionError)?;
+                drop(tcp);
+                AsyncRustlsFtpStream::connect_secure_implicit(addr, tls_connector(), &env.host)
+                    .await
+            }
+            FtpTls::None => AsyncRustlsFtpStream::connect_with_stream(tcp).await,
+        }
+    };
+    let mut stream = tokio::time::timeout(profile.timeouts.connect(), connecting)
+        .await
+        .map_err(|_| {
+            messages::text(
+                "error.timed_out_connecting_host_port",
+                &[("host", &env.host), ("port", &settings.port)],
+            )
+        })?
+        .map_err(|e| {
+            messages::text(
+                "error.failed.connect_host_port",
+                &[("host", &env.host), ("port", &settings.port), ("error", &e)],
+            )
+        })?;
+    stream
+        .login(env.user.as_str(), password)
+        .await
+        .map_err(|e| {
+            messages::text(
+                "error.ftp_login_failed_user_host",
+                &[("user", &env.user), ("host", &env.host), ("error", &e)],
+            )
+        })?;
+    stream
+        .transfer_type(suppaftp::types::FileType::Binary)
+        .await
+        .map_err(|e| messages::text("error.failed.switch_ftp_binary_mode", &[("error", &e)]))?;
+    if settings.passive {
+        stream.set_mode(Mode::Passive);
+        // Shared hosts behind NAT often advertise a private address for passive data.
+        stream.set_passive_nat_workaround(true);
+    } else {
+        stream.set_mode(Mode::Active);
+    }
+    Ok(stream)
+}
+
+/// TLS setup trusting the bundled Mozilla root certificates.
+fn tls_connector() -> AsyncRustlsConnector {
+    let roots = RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let config = ClientConfig::builder_with_provider(Arc::new(
+        tokio_rustls::rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .expect("ring supports the default protocol versions")
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+    AsyncRustlsConnector::from(TlsConnector::from(Arc::new(config)))
+}
+
+/// `MFMT`'s `YYYYMMDDHHMMSS` timestamp, in UTC.
+fn format_time(secs: u64) -> String {
+    DateTime::<Utc>::from_timestamp(secs as i64, 0)
+        .unwrap_or_default()
+        .format("%Y%m%d%H%M%S")
+        .to_string()
+}
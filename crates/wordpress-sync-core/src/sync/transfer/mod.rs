@@ -0,0 +1,218 @@
+//! Moving files between the two installs.
+//!
+//! rsync is the default and by far the fastest, but plenty of shared hosts don't
+//! have it installed, so the pipeline only talks to the [`Transfer`] trait and the
+//! profile picks the implementation. FTP is the odd one out: it doesn't go over
+//! SSH at all, which also means no wp-cli on the server. REST API profiles
+//! don't transfer files at all, so theirs is a placeholder that refuses to.
+
+mod checksum;
+mod delta;
+mod excludes;
+mod ftp;
+mod partial;
+mod pool;
+mod rsync;
+mod rsync_binary;
+mod sftp;
+mod throttle;
+mod tree;
+mod walk;
+
+use std::future::Future;
+use std::pin::Pin;
+
+use serde::Serialize;
+
+pub(crate) use checksum::sha256_all;
+pub use checksum::Checksums;
+pub use excludes::{rebase_exclude, Excludes};
+pub(crate) use rsync::itemized_changes;
+pub(crate) use rsync_binary::{local_rsync, remote_rsync, Capabilities};
+pub(crate) use sftp::start_session;
+pub use throttle::Throttle;
+pub use tree::{local_tree, match_case, Entry, Tree};
+
+use super::remote::Remote;
+use super::Direction;
+use crate::messages;
+use crate::profile::{Profile, SudoStep, TransferMethod};
+use crate::shell;
+
+/// Running totals for one transfer, reported as it progresses.
+#[derive(Debug, Clone, Default)]
+pub struct TransferStats {
+    pub files_done: u64,
+    pub files_total: Option<u64>,
+    pub bytes_transferred: u64,
+    pub current_file: Option<String>,
+}
+
+/// What a mirror would change on the destination, as paths relative to the
+/// transfer root. Directories end in `/`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FileChanges {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub deleted: Vec<String>,
+    /// The total size of the files to be copied, as they are on the source.
+    pub bytes: u64,
+}
+
+/// What a [`mirror`](Transfer::mirror) did.
+#[derive(Debug, Clone, Default)]
+pub struct Mirrored {
+    /// The files copied, relative to the two directories.
+    pub copied: Vec<String>,
+    /// The source's symlinks left out, by the profile's policy or because the
+    /// destination can't have them.
+    pub skipped_links: Vec<String>,
+    /// The source's files left out because their names aren't UTF-8, which
+    /// the transfer method can't send.
+    pub skipped_names: Vec<String>,
+    /// What it changed on the destination, for the job's file manifest.
+    pub changes: FileChanges,
+}
+
+/// How a [`mirror`](Transfer::mirror) goes about its copying.
+pub struct MirrorOptions<'a> {
+    /// Paces the file data.
+    pub throttle: &'a Throttle,
+    /// Compare files by content rather than size and modification time, if set.
+    pub checksums: Option<&'a Checksums<'a>>,
+}
+
+/// Called with updated totals whenever a transfer makes progress.
+pub type OnProgress<'a> = &'a mut (dyn FnMut(&TransferStats) + Send);
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A way of copying files between this machine and the server.
+///
+/// Paths are given as they appear on their own side. `direction` says which side
+/// each is on: for a push, `source` is local and `dest` remote; for a pull, the reverse.
+pub trait Transfer: Send + Sync {
+    /// Make the directory `dest` match `source`: copy new and changed files, keeping
+    /// modification times, and delete anything the source no longer has. Excluded
+    /// paths are neither copied nor deleted. Symlinks are skipped, copied, or
+    /// followed as the profile's [`SymlinkPolicy`](crate::profile::SymlinkPolicy) says.
+    fn mirror<'a>(
+        &'a self,
+        direction: Direction,
+        source: &'a str,
+        dest: &'a str,
+        excludes: &'a Excludes,
+        options: &'a MirrorOptions<'a>,
+        on_progress: OnProgress<'a>,
+    ) -> BoxFuture<'a, Result<Mirrored, String>>;
+
+    /// Work out what [`mirror`](Transfer::mirror) would change, without changing
+    /// anything. Files are compared by size and modification time.
+    fn plan<'a>(
+        &'a self,
+        direction: Direction,
+        source: &'a str,
+        dest: &'a str,
+        excludes: &'a Excludes,
+    ) -> BoxFuture<'a, Result<FileChanges, String>>;
+
+    /// List the server's tree under `root`, skipping excluded paths, symlinks,
+    /// and special files. A root that doesn't exist yet is empty.
+    fn list<'a>(
+        &'a self,
+        root: &'a str,
+        excludes: &'a Excludes,
+    ) -> BoxFuture<'a, Result<Tree, String>>;
+}
+
+/// Set up the profile's chosen transfer method. `remote` is the SSH connection,
+/// which every method except FTP and the REST API runs over.
+pub async fn open(profile: &Profile, remote: Option<&Remote>) -> Result<Box<dyn Transfer>, String> {
+    let method = profile.remote.transfer;
+    let parallel = profile.remote.parallel_transfers.max(1);
+    let ssh = || {
+        remote.ok_or_else(|| {
+            messages::text(
+                "error.method_transfers_need_ssh_connection",
+                &[("method", &format_args!("{method:?}"))],
+            )
+        })
+    };
+    Ok(match method {
+        TransferMethod::Rsync => {
+            let sudo = &profile.sudo;
+            let binaries = &profile.rsync;
+            let server = binaries
+                .remote_path
+                .as_deref()
+                .map_or_else(|| "rsync".to_string(), shell::quote);
+            let rsync_path = match sudo.allows(SudoStep::Files) {
+                true => Some(super::sudo::rsync_path(sudo, &server)),
+                false => binaries.remote_path.as_ref().map(|_| server),
+            };
+            Box::new(
+                rsync::Rsync::new(
+                    ssh()?,
+                    profile.compression,
+                    profile.symlinks,
+                    binaries,
+                    rsync_path,
+                    profile.timeouts.read(),
+                )
+                .await?,
+            )
+        }
+        TransferMethod::Sftp => Box::new(
+            sftp::Sftp::open(ssh()?, parallel, profile.symlinks, profile.timeouts.read()).await?,
+        ),
+        TransferMethod::Ftps => Box::new(ftp::Ftp::open(profile, parallel).await?),
+        TransferMethod::RestApi => Box::new(NoFiles),
+    })
+}
+
+/// The transfer of a profile that syncs over the REST API, which moves content
+/// rather than files.
+struct NoFiles;
+
+impl NoFiles {
+    fn refuse<'a, T: Send + 'a>() -> BoxFuture<'a, Result<T, String>> {
+        Box::pin(async {
+            Err(messages::text(
+                "error.profiles_that_sync_over_rest_api",
+                &[],
+            ))
+        })
+    }
+}
+
+impl Transfer for NoFiles {
+    fn mirror<'a>(
+        &'a self,
+        _direction: Direction,
+        _source: &'a str,
+        _dest: &'a str,
+        _excludes: &'a Excludes,
+        _options: &'a MirrorOptions<'a>,
+        _on_progress: OnProgress<'a>,
+    ) -> BoxFuture<'a, Result<Mirrored, String>> {
+        Self::refuse()
+    }
+
+    fn plan<'a>(
+        &'a self,
+        _direction: Direction,
+        _source: &'a str,
+        _dest: &'a str,
+        _excludes: &'a Excludes,
+    ) -> BoxFuture<'a, Result<FileChanges, String>> {
+        Self::refuse()
+    }
+
+    fn list<'a>(
+        &'a self,
+        _root: &'a str,
+        _excludes: &'a Excludes,
+    ) -> BoxFuture<'a, Result<Tree, String>> {
+        Self::refuse()
+    }
+}
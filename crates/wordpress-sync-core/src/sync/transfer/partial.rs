@@ -0,0 +1,73 @@
+//! Resuming large files whose copy was cut off, for the transfer methods that
+//! copy file by file. rsync does the same with `--partial`.
+//!
+//! A file of at least [`MIN_SIZE`] is written to a hidden sibling,
+//! `.<name>.wpsync-partial`, and renamed over the destination once it is
+//! complete, so a dropped connection leaves the bytes copied so far behind.
+//! The next mirror that copies the file carries on from the end of that
+//! partial copy, provided it is shorter than the source and was last written
+//! after the source was last modified; any other partial copy is deleted.
+//! Partial copies are left out of the listings a mirror compares, so they are
+//! never copied or planned as files of their own.
+
+use std::collections::BTreeSet;
+
+use super::tree::{Entry, Tree};
+
+/// Smaller files are written in place: a cut-off copy of one costs little.
+pub(super) const MIN_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The end of a partial copy's name.
+const SUFFIX: &str = ".wpsync-partial";
+
+/// Where the partial copy of the file at `dest` is written, beside it.
+/// Works on local paths and on paths relative to a tree's root alike.
+pub(super) fn path(dest: &str) -> String {
+    let split = dest.rfind(['/', '\\']).map_or(0, |i| i + 1);
+    let (dir, name) = dest.split_at(split);
+    format!("{dir}.{name}{SUFFIX}")
+}
+
+/// The path of the file whose partial copy is at `relative`, if it is one.
+fn target(relative: &str) -> Option<String> {
+    let split = relative.rfind('/').map_or(0, |i| i + 1);
+    let (dir, name) = relative.split_at(split);
+    let name = name.strip_prefix('.')?.strip_suffix(SUFFIX)?;
+    (!name.is_empty()).then(|| format!("{dir}{name}"))
+}
+
+/// Take the partial copies out of `tree`, returning them keyed by the path
+/// of the file each is a copy of.
+pub(super) fn split(tree: &mut Tree) -> Tree {
+    let paths: BTreeSet<String> = tree
+        .iter()
+        .filter(|(relative, entry)| {
+            matches!(entry, Entry::File { .. }) && target(relative).is_some()
+        })
+        .map(|(relative, _)| relative.clone())
+        .collect();
+    paths
+        .into_iter()
+        .filter_map(|relative| {
+            let entry = tree.remove(&relative)?;
+            Some((target(&relative)?, entry))
+        })
+        .collect()
+}
+
+/// How much of a `size`-byte source file last modified at `mtime` can be
+/// kept from its partial copy: all of it if the copy looks like the start of
+/// this version of the file, otherwise none. Copies and sources whose times
+/// are unknown are never resumed.
+pub(super) fn resume_from(partial: Option<&Entry>, size: u64, mtime: Option<u64>) -> u64 {
+    match (partial, mtime) {
+        (
+            Some(&Entry::File {
+                size: done,
+                mtime: Some(written),
+            }),
+            Some(mtime),
+        ) if done < size && written >= mtime => done,
+        _ => 0,
+    }
+}
@@ -0,0 +1,87 @@
+//! Copying files over several connections at once, for the transfer methods
+//! that copy file by file.
+//!
+//! Each connection gets a worker that takes the next file off a shared list as
+//! soon as its last copy is done, so one large file doesn't hold up the small
+//! ones behind it. Directories are created and deletions made before the
+//! copies start, over the first connection alone.
+
+use std::future::Future;
+use std::sync::Mutex;
+
+use futures_util::future::try_join_all;
+
+use super::{OnProgress, Throttle, TransferStats};
+
+/// The running totals of a mirror's copies, reported as they grow, with the
+/// throttle those copies keep to between them.
+pub(super) struct Meter<'a> {
+    stats: Mutex<TransferStats>,
+    throttle: &'a Throttle,
+    on_progress: Mutex<OnProgress<'a>>,
+}
+
+impl<'a> Meter<'a> {
+    pub(super) fn new(
+        files_total: usize,
+        throttle: &'a Throttle,
+        on_progress: OnProgress<'a>,
+    ) -> Self {
+        let meter = Self {
+            stats: Mutex::new(TransferStats {
+                files_total: Some(files_total as u64),
+                ..Default::default()
+            }),
+            throttle,
+            on_progress: Mutex::new(on_progress),
+        };
+        meter.update(|_| {});
+        meter
+    }
+
+    /// Change the totals and report them.
+    fn update(&self, change: impl FnOnce(&mut TransferStats)) {
+        let mut stats = self.stats.lock().unwrap();
+        change(&mut stats);
+        (self.on_progress.lock().unwrap())(&stats);
+    }
+
+    /// Count `bytes` of file data just copied, waiting if the throttle says so.
+    pub(super) async fn sent(&self, bytes: usize) {
+        self.update(|stats| stats.bytes_transferred += bytes as u64);
+        self.throttle.consume(bytes as u64).await;
+    }
+}
+
+/// Run `copy` on every file in `files`, at most one at a time per connection
+/// in `connections`, reporting each to `meter` as it starts and finishes.
+/// The first failure stops the rest.
+pub(super) async fn copy_all<'c, C, T, F, Fut>(
+    connections: &'c [C],
+    files: Vec<(String, T)>,
+    meter: &Meter<'_>,
+    copy: F,
+) -> Result<(), String>
+where
+    F: Fn(&'c C, String, T) -> Fut,
+    Fut: Future<Output = Result<(), String>>,
+{
+    let queue = &Mutex::new(files.into_iter());
+    let copy = &copy;
+    let workers = connections
+        .iter()
+        .enumerate()
+        .map(|(i, connection)| async move {
+            loop {
+                meter.throttle.wait_for_slot(i).await;
+                meter.throttle.wait_while_paused().await;
+                let Some((relative, item)) = queue.lock().unwrap().next() else {
+                    return Ok::<(), String>(());
+                };
+                meter.update(|stats| stats.current_file = Some(relative.clone()));
+                copy(connection, relative, item).await?;
+                meter.update(|stats| stats.files_done += 1);
+            }
+        });
+    try_join_all(workers).await.map(drop)
+}
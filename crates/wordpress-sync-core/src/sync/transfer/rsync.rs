@@ -0,0 +1,544 @@
+//! File transfer via rsync.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::rsync_binary::{self, Capabilities};
+use super::tree::{self, Entry, Tree};
+use super::{
+    BoxFuture, Excludes, FileChanges, MirrorOptions, Mirrored, OnProgress, Throttle, Transfer,
+    TransferStats,
+};
+use crate::byte_path;
+use crate::messages;
+use crate::profile::{Compression, RsyncBinaries, SymlinkPolicy};
+use crate::shell;
+use crate::ssh::Session;
+use crate::sync::remote::Remote;
+use crate::sync::Direction;
+use crate::sync::{local, local_path};
+
+/// Prints `<type> <size> <mtime> <path>`, NUL-terminated, for each directory
+/// and regular file under `$ARGV[0]`, with paths relative to it. rsync has no
+/// portable way of listing a tree with exact times, and `find -printf` is GNU's.
+const LIST_SCRIPT: &str = r#"
+use strict;
+use File::Find;
+my $root = $ARGV[0];
+exit 0 unless -d $root;
+find({ no_chdir => 1, wanted => sub {
+    return if $File::Find::name eq $root;
+    my $relative = substr($File::Find::name, length($root) + 1);
+    my @stat = lstat($File::Find::name);
+    if (-d _) {
+        print "d 0 0 $relative\0";
+    } elsif (-f _) {
+        print "f $stat[7] $stat[9] $relative\0";
+    }
+} }, $root);
+"#;
+
+/// Find each directory and regular file under `root` and `stat` it, for
+/// servers without Perl. `stat` is the server's way of printing `<mode>
+/// <size> <mtime> <path>` a line.
+fn find_line(root: &str, stat: &str) -> String {
+    let root = shell::quote(root);
+    format!("if [ -d {root} ]; then find {root} \\( -type d -o -type f \\) -exec {stat} {{}} +; fi")
+}
+
+/// Read what [`find_line`] printed into a tree relative to `root`. A name with
+/// a newline in it is split across lines, so it's left out.
+fn parse_found(root: &str, output: &str) -> Tree {
+    let prefix = format!("{root}/");
+    let mut listing = Tree::new();
+    for line in output.lines() {
+        let mut fields = line.splitn(4, ' ');
+        let (Some(mode), Some(size), Some(mtime), Some(relative)) = (
+            fields.next(),
+            fields.next(),
+            fields.next(),
+            fields.next().and_then(|path| path.strip_prefix(&prefix)),
+        ) else {
+            continue;
+        };
+        let entry = match mode.chars().next() {
+            Some('d') => Entry::Dir,
+            Some('-') => Entry::File {
+                size: size.parse().unwrap_or(0),
+                mtime: mtime.parse().ok(),
+            },
+            _ => continue,
+        };
+        listing.insert(relative.to_string(), entry);
+    }
+    listing
+}
+
+/// What rsync's progress meter measures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Meter {
+    /// `--progress`: each file in turn.
+    PerFile,
+    /// `--info=progress2`: the whole run so far, on top of `base` bytes from
+    /// the runs a restart came after.
+    Whole { base: u64 },
+}
+
+/// Fold one line of `rsync -v --progress` output into the totals.
+///
+/// rsync prints each file's name on its own line, followed by a meter such as
+/// `  32768 100%  31.25MB/s  0:00:00 (xfer#3, to-check=10/42)`. Newer versions
+/// spell the counters `xfr#` and `to-chk=`. The counters only appear on a
+/// file's final meter update, which is when its size is added to the total.
+/// With `--info=progress2` the meter's size is the run's total so far instead,
+/// taken from every update.
+fn observe(stats: &mut TransferStats, line: &str, meter: Meter) {
+    let trimmed = line.trim();
+    if trimmed.is_empty() {
+        return;
+    }
+    if line.starts_with(' ') && trimmed.contains('%') {
+        let size = trimmed
+            .split_whitespace()
+            .next()
+            .and_then(|n| n.replace(',', "").parse::<u64>().ok());
+        if let (Meter::Whole { base }, Some(size)) = (meter, size) {
+            stats.bytes_transferred = base + size;
+        }
+        let Some(check) = trimmed
+            .split_once("to-check=")
+            .or_else(|| trimmed.split_once("to-chk="))
+            .map(|(_, rest)| rest.trim_end_matches(')'))
+        else {
+            return; // An intermediate meter update for the current file
+        };
+        if let Some((remaining, total)) = check.split_once('/') {
+            if let (Ok(remaining), Ok(total)) = (remaining.parse::<u64>(), total.parse::<u64>()) {
+                stats.files_done = total.saturating_sub(remaining);
+                stats.files_total = Some(total);
+            }
+        }
+        if let (Meter::PerFile, Some(size)) = (meter, size) {
+            stats.bytes_transferred += size;
+        }
+    } else if !is_rsync_chatter(trimmed) {
+        stats.current_file = Some(itemized_name(trimmed).to_string());
+    }
+}
+
+/// The size of the files a dry run would copy, from the `Total transferred
+/// file size: 1,234 bytes` line of `--stats`. Version 3 groups the digits.
+fn transferred_size(line: &str) -> Option<u64> {
+    let size = line.strip_prefix("Total transferred file size:")?;
+    let digits: String = size
+        .trim_end()
+        .strip_suffix("bytes")?
+        .chars()
+        .filter(char::is_ascii_digit)
+        .collect();
+    digits.parse().ok()
+}
+
+/// The symlink a `skipping non-regular file "<path>"` line names, as rsync
+/// prints for each link `--no-links` leaves out.
+fn skipped_link(line: &str) -> Option<&str> {
+    line.trim()
+        .strip_prefix("skipping non-regular file \"")?
+        .strip_suffix('"')
+}
+
+/// rsync's verbose header and summary lines, which aren't file names.
+fn is_rsync_chatter(line: &str) -> bool {
+    skipped_link(line).is_some()
+        || line.ends_with("file list")
+        || line.starts_with("sent ")
+        || line.starts_with("total size is")
+        || line.starts_with("building file list")
+        || line.starts_with("deleting ")
+        || line.starts_with("*deleting ")
+}
+
+/// The name in a line of `--itemize-changes` output, after its change summary.
+fn itemized_name(line: &str) -> &str {
+    match line.split_once(' ') {
+        Some((item, name))
+            if item.len() >= 9
+                && item.starts_with(['<', '>', 'c', 'h', '.'])
+                && item[1..].starts_with(['f', 'd', 'L', 'D', 'S']) =>
+        {
+            name
+        }
+        _ => line,
+    }
+}
+
+/// A name as rsync printed it, with the `\\#ooo` octal escapes it writes
+/// bytes that aren't printable or UTF-8 as turned back into them.
+fn unescape(name: &str) -> String {
+    let bytes = name.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = bytes.get(i..i + 5).filter(|escape| {
+            escape.starts_with(b"\\#")
+                && escape[2..]
+                    .iter()
+                    .all(|digit| (b'0'..=b'7').contains(digit))
+        });
+        match escape {
+            Some(escape) => {
+                let value = escape[2..]
+                    .iter()
+                    .fold(0u32, |value, digit| value * 8 + u32::from(digit - b'0'));
+                out.push(value as u8);
+                i += 5;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    byte_path::from_bytes(&out)
+}
+
+/// What the output of an `rsync --dry-run --itemize-changes` would change,
+/// with the bytes it would send if `--stats` was given too.
+pub(crate) fn itemized_changes(output: &str) -> FileChanges {
+    let mut changes = FileChanges::default();
+    for line in output.lines() {
+        match transferred_size(line) {
+            Some(bytes) => changes.bytes = bytes,
+            None => itemize(&mut changes, line),
+        }
+    }
+    changes
+}
+
+/// Sort one line of `rsync --itemize-changes` output into `changes`.
+///
+/// Each line is a change summary, a space, and the path: `>f+++++++++ a.jpg` for
+/// a new file, `>f.st...... b.css` for a changed one, `cd+++++++++ dir/` for a
+/// new directory, and `*deleting   old.txt` for a deletion. Pushes start with `<`
+/// instead of `>`, and the 2.6.9 that ships with macOS prints two fewer columns.
+/// Attribute-only changes (`.`), symlinks, and special files are skipped.
+fn itemize(changes: &mut FileChanges, line: &str) {
+    let Some((item, path)) = line.split_once(' ') else {
+        return;
+    };
+    if item == "*deleting" {
+        changes.deleted.push(unescape(path.trim_start()));
+        return;
+    }
+    let path = unescape(path);
+    let mut flags = item.chars();
+    let (Some(update), Some(kind)) = (flags.next(), flags.next()) else {
+        return;
+    };
+    let is_new = flags.as_str().chars().all(|c| c == '+');
+    match (update, kind) {
+        (_, 'd') if path == "./" => {}
+        ('c', 'd') if is_new => changes.added.push(path),
+        ('<' | '>', 'f') if is_new => changes.added.push(path),
+        ('<' | '>', 'f') => changes.updated.push(path),
+        _ => {}
+    }
+}
+
+/// Transfers with the local rsync binary, tunnelled over the managed SSH session.
+pub struct Rsync {
+    /// The rsync run here.
+    program: PathBuf,
+    /// What both ends' versions let the flags use.
+    capabilities: Capabilities,
+    /// The `-e` argument routing rsync through the session.
+    shell: String,
+    /// `user@host`, prefixed to remote paths.
+    destination: String,
+    /// The `-z` level, if rsync should compress.
+    compress_level: Option<u32>,
+    symlinks: SymlinkPolicy,
+    /// The command that runs rsync on the server, when it isn't just `rsync`.
+    rsync_path: Option<String>,
+    /// How long rsync may go without sending or receiving anything.
+    io_timeout: Option<Duration>,
+    /// For listing the server's trees.
+    ssh: Arc<Session>,
+}
+
+impl Rsync {
+    /// Ask both ends' rsync its version, to know which flags to give them.
+    pub async fn new(
+        remote: &Remote,
+        compression: Compression,
+        symlinks: SymlinkPolicy,
+        binaries: &RsyncBinaries,
+        rsync_path: Option<String>,
+        io_timeout: Option<Duration>,
+    ) -> Result<Self, String> {
+        let ((program, here), there) = tokio::join!(
+            rsync_binary::local_rsync(binaries.local_path.as_deref()),
+            rsync_binary::remote_rsync(remote, binaries.remote_path.as_deref()),
+        );
+        let capabilities = Capabilities::of(here, there);
+        tracing::info!(
+            program = %program.display(),
+            here = ?here.map(|version| version.to_string()),
+            there = ?there.map(|version| version.to_string()),
+            ?capabilities,
+            "found rsync"
+        );
+        Ok(Self {
+            program,
+            capabilities,
+            shell: remote.rsync_shell()?,
+            destination: remote.destination().to_string(),
+            compress_level: compression.level(),
+            symlinks,
+            rsync_path,
+            io_timeout,
+            ssh: remote.ssh(),
+        })
+    }
+
+    /// Express both sides of a transfer as rsync arguments.
+    fn locations(&self, direction: Direction, source: &str, dest: &str) -> (String, String) {
+        let remote = |path: &str| format!("{}:{path}", self.destination);
+        match direction {
+            Direction::Push => (local_path::for_rsync(source), remote(dest)),
+            Direction::Pull => (remote(source), local_path::for_rsync(dest)),
+        }
+    }
+
+    /// The arguments that make rsync mirror one directory onto another.
+    fn mirror_args(
+        &self,
+        direction: Direction,
+        source: &str,
+        dest: &str,
+        excludes: &Excludes,
+    ) -> (Vec<String>, String, String) {
+        // Trailing slashes make rsync copy the directories' contents, not the directories.
+        let (source, dest) = self.locations(
+            direction,
+            &format!("{}/", source.trim_end_matches('/')),
+            &format!("{}/", dest.trim_end_matches('/')),
+        );
+        // Keep partly sent files, so a resumed sync carries on with them.
+        let mut flags = vec!["--delete".to_string(), "--partial".to_string()];
+        if self.capabilities.protect_args {
+            flags.push("--protect-args".to_string());
+        }
+        if let Some(level) = self.compress_level {
+            flags.extend(["-z".to_string(), format!("--compress-level={level}")]);
+        }
+        if let Some(path) = &self.rsync_path {
+            flags.push(format!("--rsync-path={path}"));
+        }
+        if let Some(timeout) = self.io_timeout {
+            flags.push(format!("--timeout={}", timeout.as_secs()));
+        }
+        // `-a` copies links as links.
+        match self.symlinks {
+            SymlinkPolicy::Skip => flags.push("--no-links".to_string()),
+            SymlinkPolicy::Preserve => {}
+            SymlinkPolicy::Follow => flags.push("--copy-links".to_string()),
+        }
+        flags.extend(excludes.rsync_filters());
+        (flags, source, dest)
+    }
+
+    /// Run rsync with `extra` flags at `throttle`'s limit, feeding its progress
+    /// meter to `on_progress`, and return the files it names as it copies,
+    /// skips, or deletes them.
+    /// rsync can't change its limit as it runs, so a new limit restarts it, and
+    /// a pause stops it until the throttle is let go.
+    async fn run(
+        &self,
+        extra: &[String],
+        source: &str,
+        dest: &str,
+        throttle: &Throttle,
+        on_progress: OnProgress<'_>,
+    ) -> Result<Mirrored, String> {
+        let mut stats = TransferStats::default();
+        let mut changes = FileChanges::default();
+        let mut skipped_links = Vec::new();
+        let mut limits = throttle.watch();
+        loop {
+            throttle.wait_while_paused().await;
+            let limit = *limits.borrow_and_update();
+            let (meter, progress) = match self.capabilities.progress2 {
+                true => (
+                    Meter::Whole {
+                        base: stats.bytes_transferred,
+                    },
+                    "--info=progress2",
+                ),
+                false => (Meter::PerFile, "--progress"),
+            };
+            let mut cmd = local::command(&self.program);
+            // Itemized, each name comes with what changed, so the job knows which were new.
+            cmd.args(["-a", "-v", progress, "--itemize-changes", "-e"])
+                .arg(&self.shell)
+                .args(extra);
+            if let Some(limit) = limit {
+                cmd.arg(format!("--bwlimit={limit}"));
+            }
+            cmd.arg(source).arg(dest);
+
+            let running = local::stream_lines(cmd, "rsync", |line| {
+                if let Some(link) = skipped_link(line) {
+                    skipped_links.push(unescape(link));
+                }
+                observe(&mut stats, line, meter);
+                itemize(&mut changes, line.trim_end());
+                on_progress(&stats);
+            });
+            tokio::select! {
+                result = running => {
+                    result?;
+                    // A restart names the file it was on again, as changed if
+                    // the first run left part of it.
+                    for list in [&mut changes.added, &mut changes.updated, &mut changes.deleted] {
+                        list.sort();
+                        list.dedup();
+                    }
+                    let added = std::mem::take(&mut changes.added);
+                    changes.updated.retain(|path| added.binary_search(path).is_err());
+                    changes.added = added;
+                    changes.bytes = stats.bytes_transferred;
+                    skipped_links.sort();
+                    skipped_links.dedup();
+                    let mut copied: Vec<String> = changes
+                        .added
+                        .iter()
+                        .chain(&changes.updated)
+                        .filter(|path| !path.ends_with('/'))
+                        .cloned()
+                        .collect();
+                    copied.sort();
+                    return Ok(Mirrored {
+                        copied,
+                        skipped_links,
+                        skipped_names: Vec::new(),
+                        changes,
+                    });
+                }
+                Ok(()) = limits.changed() => {
+                    tracing::info!(?limit, "restarting rsync with a new bandwidth limit");
+                }
+                () = throttle.wait_for_pause() => {
+                    tracing::info!("stopping rsync while the sync is paused");
+                }
+            }
+        }
+    }
+}
+
+impl Transfer for Rsync {
+    fn mirror<'a>(
+        &'a self,
+        direction: Direction,
+        source: &'a str,
+        dest: &'a str,
+        excludes: &'a Excludes,
+        options: &'a MirrorOptions<'a>,
+        on_progress: OnProgress<'a>,
+    ) -> BoxFuture<'a, Result<Mirrored, String>> {
+        Box::pin(async move {
+            let (mut flags, source, dest) = self.mirror_args(direction, source, dest, excludes);
+            if options.checksums.is_some() {
+                // rsync compares whole-file checksums on both sides itself.
+                flags.push("--checksum".to_string());
+            }
+            self.run(&flags, &source, &dest, options.throttle, on_progress)
+                .await
+        })
+    }
+
+    fn plan<'a>(
+        &'a self,
+        direction: Direction,
+        source: &'a str,
+        dest: &'a str,
+        excludes: &'a Excludes,
+    ) -> BoxFuture<'a, Result<FileChanges, String>> {
+        Box::pin(async move {
+            let (flags, source, dest) = self.mirror_args(direction, source, dest, excludes);
+            let mut cmd = local::command(&self.program);
+            cmd.args(["-a", "--dry-run", "--itemize-changes", "--stats", "-e"])
+                .arg(&self.shell)
+                .args(&flags)
+                .arg(&source)
+                .arg(&dest);
+            let output = local::output(cmd, "rsync --dry-run").await?;
+            Ok(itemized_changes(&output))
+        })
+    }
+
+    fn list<'a>(
+        &'a self,
+        root: &'a str,
+        excludes: &'a Excludes,
+    ) -> BoxFuture<'a, Result<Tree, String>> {
+        Box::pin(async move {
+            let root = root.trim_end_matches('/');
+            let platform = self.ssh.platform().await;
+            if !platform.perl {
+                let output = self
+                    .ssh
+                    .exec(&find_line(root, platform.stat_listing()))
+                    .await?;
+                if !output.success() {
+                    return Err(messages::text(
+                        "error.failed.list_root_server_stderr",
+                        &[
+                            ("root", &root),
+                            ("stderr", &String::from_utf8_lossy(&output.stderr).trim()),
+                        ],
+                    ));
+                }
+                let listing = parse_found(root, &byte_path::from_bytes(&output.stdout));
+                return Ok(tree::pruned(listing, excludes));
+            }
+            let line = format!(
+                "perl -e {} {}",
+                shell::quote(LIST_SCRIPT),
+                shell::quote(root)
+            );
+            let output = self.ssh.exec(&line).await?;
+            if !output.success() {
+                return Err(messages::text(
+                    "error.failed.list_root_server_stderr",
+                    &[
+                        ("root", &root),
+                        ("stderr", &String::from_utf8_lossy(&output.stderr).trim()),
+                    ],
+                ));
+            }
+            let mut listing = Tree::new();
+            for record in byte_path::from_bytes(&output.stdout).split_terminator('\0') {
+                let mut fields = record.splitn(4, ' ');
+                let (Some(kind), Some(size), Some(mtime), Some(relative)) =
+                    (fields.next(), fields.next(), fields.next(), fields.next())
+                else {
+                    return Err(messages::text(
+                        "error.unexpected_listing_root_record",
+                        &[("root", &root), ("record", &format_args!("{record:?}"))],
+                    ));
+                };
+                let entry = match kind {
+                    "d" => Entry::Dir,
+                    _ => Entry::File {
+                        size: size.parse().unwrap_or(0),
+                        mtime: mtime.parse().ok(),
+                    },
+                };
+                listing.insert(relative.to_string(), entry);
+            }
+            Ok(tree::pruned(listing, excludes))
+        })
+    }
+}
@@ -0,0 +1,107 @@
+//! Which rsync each end of a transfer runs, and the flags it understands.
+//!
+//! macOS still ships rsync 2.6.9, while Homebrew's and most servers' are 3.x,
+//! so both ends are asked their version before a transfer starts and given
+//! only what they can take: `--info=progress2` needs 3.1 here, and
+//! `--protect-args` needs 3.0 on both ends. An rsync that can't say its
+//! version is taken to be 2.6.9.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::shell;
+use crate::sync::local;
+use crate::sync::remote::Remote;
+
+/// Where Homebrew installs rsync, on Apple silicon and on Intel Macs.
+const HOMEBREW: &[&str] = &["/opt/homebrew/bin/rsync", "/usr/local/bin/rsync"];
+
+/// An rsync release, such as 3.2.7.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RsyncVersion(pub u32, pub u32, pub u32);
+
+impl RsyncVersion {
+    /// The version `rsync --version` gives, as in `rsync  version 3.2.7
+    /// protocol version 31`, or the `rsync version 2.6.9 compatible` on the
+    /// second line of the openrsync newer macOS ships.
+    pub fn parse(output: &str) -> Option<Self> {
+        let words: Vec<&str> = output.split_whitespace().collect();
+        let number = words
+            .windows(3)
+            .find(|words| words[0] == "rsync" && words[1] == "version")?[2];
+        let mut parts = number.trim_start_matches('v').split('.').map(|part| {
+            let digits: String = part.chars().take_while(char::is_ascii_digit).collect();
+            digits.parse::<u32>().ok()
+        });
+        let major = parts.next()??;
+        let minor = parts.next().flatten().unwrap_or(0);
+        let patch = parts.next().flatten().unwrap_or(0);
+        Some(Self(major, minor, patch))
+    }
+}
+
+impl fmt::Display for RsyncVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+/// What the flags of a transfer can rely on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// One meter for the whole transfer, rather than one a file.
+    pub progress2: bool,
+    /// Paths sent to the server as they are, without its shell splitting them.
+    pub protect_args: bool,
+}
+
+impl Capabilities {
+    /// What rsync `local` here and `remote` on the server can both do.
+    pub fn of(local: Option<RsyncVersion>, remote: Option<RsyncVersion>) -> Self {
+        let at_least = |version: Option<RsyncVersion>, least| version.is_some_and(|v| v >= least);
+        Self {
+            progress2: at_least(local, RsyncVersion(3, 1, 0)),
+            protect_args: at_least(local, RsyncVersion(3, 0, 0))
+                && at_least(remote, RsyncVersion(3, 0, 0)),
+        }
+    }
+}
+
+/// The rsync this machine runs: `configured`, or else the newest of the one
+/// on the `PATH` and Homebrew's, with its version if it gave one.
+pub async fn local_rsync(configured: Option<&Path>) -> (PathBuf, Option<RsyncVersion>) {
+    if let Some(path) = configured {
+        return (path.to_path_buf(), version(path).await);
+    }
+    let candidates: Vec<PathBuf> = std::iter::once(PathBuf::from("rsync"))
+        .chain(
+            HOMEBREW
+                .iter()
+                .map(PathBuf::from)
+                .filter(|path| path.exists()),
+        )
+        .collect();
+    let versions = futures_util::future::join_all(candidates.iter().map(|path| version(path)));
+    let versions = versions.await;
+    candidates
+        .into_iter()
+        .zip(versions)
+        .filter(|(_, version)| version.is_some())
+        .max_by_key(|(_, version)| *version)
+        .unwrap_or_else(|| (PathBuf::from("rsync"), None))
+}
+
+/// The version of `program` here, if it runs and says.
+async fn version(program: &Path) -> Option<RsyncVersion> {
+    let mut cmd = local::command(program);
+    cmd.arg("--version");
+    let output = local::output(cmd, "rsync --version").await.ok()?;
+    RsyncVersion::parse(&output)
+}
+
+/// The version of `program`, or of the `rsync` on the `PATH`, on the server.
+pub async fn remote_rsync(remote: &Remote, program: Option<&str>) -> Option<RsyncVersion> {
+    let program = program.map_or_else(|| "rsync".to_string(), shell::quote);
+    let output = remote.exec(&format!("{program} --version")).await.ok()?;
+    RsyncVersion::parse(&output)
+}
@@ -0,0 +1,814 @@
+//! File transfer over the SSH session's SFTP subsystem, for servers without rsync.
+//!
+//! Change detection is rsync's quick check: a file is copied when it is missing
+//! on the destination or its size or modification time differs. Copied files get
+//! the source's modification time so the next sync can skip them. Symlinks are
+//! skipped, recreated, or followed by the profile's policy; links pulled onto
+//! Windows are always skipped. Special files are skipped on both sides.
+//!
+//! Large files that changed are patched rather than copied again where the
+//! server can help; see [`delta`](super::delta). One whose copy was cut off
+//! carries on where it stopped; see [`partial`](super::partial).
+
+use std::io::SeekFrom;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use russh_sftp::client::{Config, SftpSession};
+use russh_sftp::protocol::{FileAttributes, OpenFlags};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+
+use super::delta::{self, Delta};
+use super::partial;
+use super::pool::{self, Meter};
+use super::tree::{self, Entry, Tree};
+use super::walk::{self, Lister, Walk};
+use super::{BoxFuture, Excludes, FileChanges, MirrorOptions, Mirrored, OnProgress, Transfer};
+use crate::messages;
+use crate::profile::SymlinkPolicy;
+use crate::shell;
+use crate::ssh::Session;
+use crate::sync::remote::Remote;
+use crate::sync::{local_path, Direction};
+
+/// Read/write chunk size; also how often byte progress is reported.
+const CHUNK_SIZE: usize = 256 * 1024;
+
+pub struct Sftp {
+    /// One SFTP session per parallel copy, each on its own channel of the SSH
+    /// connection. The first also lists, creates, and deletes.
+    sessions: Vec<SftpSession>,
+    /// For the server's half of delta copies, and creating links.
+    ssh: Arc<Session>,
+    symlinks: SymlinkPolicy,
+}
+
+impl Sftp {
+    /// Start `parallel` SFTP sessions, or as many as the server allows, whose
+    /// requests fail if unanswered within `request_timeout`.
+    pub async fn open(
+        remote: &Remote,
+        parallel: u32,
+        symlinks: SymlinkPolicy,
+        request_timeout: Option<Duration>,
+    ) -> Result<Self, String> {
+        let mut sessions = vec![start_session(remote, request_timeout).await?];
+        while sessions.len() < parallel as usize {
+            // Servers cap the channels a connection may open (sshd's MaxSessions).
+            match start_session(remote, request_timeout).await {
+                Ok(session) => sessions.push(session),
+                Err(e) => {
+                    tracing::info!(sessions = sessions.len(), "no more SFTP sessions: {e}");
+                    break;
+                }
+            }
+        }
+        Ok(Self {
+            sessions,
+            ssh: remote.ssh(),
+            symlinks,
+        })
+    }
+
+    fn session(&self) -> &SftpSession {
+        &self.sessions[0]
+    }
+
+    /// Copy one file across, reporting bytes to `meter` as they go, and give the
+    /// copy the source's modification time. With `staged`, the file is written
+    /// to its partial copy from that offset on, then renamed into place.
+    async fn copy(
+        session: &SftpSession,
+        direction: Direction,
+        (source, dest): (&str, &str),
+        mtime: Option<u64>,
+        staged: Option<u64>,
+        meter: &Meter<'_>,
+    ) -> Result<(), String> {
+        let offset = staged.unwrap_or(0);
+        let written = match staged {
+            Some(_) => partial::path(dest),
+            None => dest.to_string(),
+        };
+        match direction {
+            Direction::Push => {
+                let mut reader = tokio::fs::File::open(source).await.map_err(|e| {
+                    messages::text(
+                        "error.failed.open_path",
+                        &[("path", &source), ("error", &e)],
+                    )
+                })?;
+                let mut writer = if offset > 0 {
+                    let mut writer = session
+                        .open_with_flags(&written, OpenFlags::WRITE)
+                        .await
+                        .map_err(|e| {
+                            messages::text(
+                                "error.failed.open_path_over_sftp",
+                                &[("path", &written), ("error", &e)],
+                            )
+                        })?;
+                    reader.seek(SeekFrom::Start(offset)).await.map_err(|e| {
+                        messages::text(
+                            "error.failed.read_path",
+                            &[("path", &source), ("error", &e)],
+                        )
+                    })?;
+                    writer.seek(SeekFrom::Start(offset)).await.map_err(|e| {
+                        messages::text(
+                            "error.failed.write_path_over_sftp",
+                            &[("path", &written), ("error", &e)],
+                        )
+                    })?;
+                    writer
+                } else {
+                    session.create(&written).await.map_err(|e| {
+                        messages::text(
+                            "error.failed.create_path_over_sftp",
+                            &[("path", &written), ("error", &e)],
+                        )
+                    })?
+                };
+                pump(&mut reader, &mut writer, dest, meter).await?;
+                writer.shutdown().await.map_err(|e| {
+                    messages::text(
+                        "error.failed.write_path_over_sftp",
+                        &[("path", &dest), ("error", &e)],
+                    )
+                })?;
+                if staged.is_some() {
+                    // Plain SFTP renames refuse to replace an existing file.
+                    let _ = session.remove_file(dest).await;
+                    session.rename(&written, dest).await.map_err(|e| {
+                        messages::text(
+                            "error.failed.move_written_dest_over_sftp",
+                            &[("written", &written), ("dest", &dest), ("error", &e)],
+                        )
+                    })?;
+                }
+                let mtime = match mtime {
+                    Some(mtime) => Some(mtime),
+                    None => tree::local_mtime(&reader.metadata().await.map_err(|e| e.to_string())?),
+                };
+                let Some(mtime) = mtime else {
+                    return Ok(());
+                };
+                let attrs = FileAttributes {
+                    atime: Some(mtime as u32),
+                    mtime: Some(mtime as u32),
+                    ..FileAttributes::empty()
+                };
+                session.set_metadata(dest, attrs).await.map_err(|e| {
+                    messages::text(
+                        "error.failed.set_modification_time_dest",
+                        &[("dest", &dest), ("error", &e)],
+                    )
+                })
+            }
+            Direction::Pull => {
+                let mut reader = session.open(source).await.map_err(|e| {
+                    messages::text(
+                        "error.failed.open_path_over_sftp",
+                        &[("path", &source), ("error", &e)],
+                    )
+                })?;
+                let mut writer = if offset > 0 {
+                    reader.seek(SeekFrom::Start(offset)).await.map_err(|e| {
+                        messages::text(
+                            "error.failed.read_path_over_sftp",
+                            &[("path", &source), ("error", &e)],
+                        )
+                    })?;
+                    tokio::fs::File::options()
+                        .append(true)
+                        .open(&written)
+                        .await
+                        .map_err(|e| {
+                            messages::text(
+                                "error.failed.open_path",
+                                &[("path", &written), ("error", &e)],
+                            )
+                        })?
+                } else {
+                    tokio::fs::File::create(&written).await.map_err(|e| {
+                        messages::text(
+                            "error.failed.create_path",
+                            &[("path", &written), ("error", &e)],
+                        )
+                    })?
+                };
+                pump(&mut reader, &mut writer, dest, meter).await?;
+                writer.flush().await.map_err(|e| {
+                    messages::text("error.failed.write_path", &[("path", &dest), ("error", &e)])
+                })?;
+                let mtime = match mtime {
+                    Some(mtime) => Some(mtime),
+                    None => remote_mtime(&session.metadata(source).await.map_err(|e| {
+                        messages::text(
+                            "error.failed.stat_source_over_sftp",
+                            &[("source", &source), ("error", &e)],
+                        )
+                    })?),
+                };
+                if let Some(mtime) = mtime {
+                    writer
+                        .into_std()
+                        .await
+                        .set_modified(UNIX_EPOCH + Duration::from_secs(mtime))
+                        .map_err(|e| {
+                            messages::text(
+                                "error.failed.set_modification_time_dest",
+                                &[("dest", &dest), ("error", &e)],
+                            )
+                        })?;
+                }
+                if staged.is_some() {
+                    tokio::fs::rename(&written, dest).await.map_err(|e| {
+                        messages::text(
+                            "error.failed.move_written_dest",
+                            &[("written", &written), ("dest", &dest), ("error", &e)],
+                        )
+                    })?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Patch the destination's older copy, `old_size` bytes, of a `size`-byte
+    /// file into the new one and give it the source's modification time.
+    /// Returns false if the file should be copied whole instead.
+    async fn copy_delta(
+        &self,
+        session: &SftpSession,
+        direction: Direction,
+        (source, dest): (&str, &str),
+        (size, old_size, mtime): (u64, u64, Option<u64>),
+        meter: &Meter<'_>,
+    ) -> Result<bool, String> {
+        let delta = Delta {
+            ssh: &self.ssh,
+            sftp: session,
+            meter,
+        };
+        let patched = match direction {
+            Direction::Push => delta.push(source, dest, old_size).await?,
+            Direction::Pull => delta.pull(source, dest, size, old_size).await?,
+        };
+        let Some(mtime) = mtime.filter(|_| patched) else {
+            return Ok(patched);
+        };
+        match direction {
+            Direction::Push => {
+                let attrs = FileAttributes {
+                    atime: Some(mtime as u32),
+                    mtime: Some(mtime as u32),
+                    ..FileAttributes::empty()
+                };
+                session
+                    .set_metadata(dest, attrs)
+                    .await
+                    .map_err(|e| e.to_string())
+            }
+            Direction::Pull => std::fs::File::options()
+                .write(true)
+                .open(dest)
+                .and_then(|file| file.set_modified(UNIX_EPOCH + Duration::from_secs(mtime)))
+                .map_err(|e| e.to_string()),
+        }
+        .map_err(|e| {
+            messages::text(
+                "error.failed.set_modification_time_dest",
+                &[("dest", &dest), ("error", &e)],
+            )
+        })?;
+        Ok(true)
+    }
+
+    /// Recreate the link at `source` at `dest`, pointing to the same place,
+    /// in place of whatever link is there.
+    async fn copy_link(
+        &self,
+        direction: Direction,
+        source: &str,
+        dest: &str,
+    ) -> Result<(), String> {
+        match direction {
+            Direction::Push => {
+                let target = tokio::fs::read_link(source).await.map_err(|e| {
+                    messages::text(
+                        "error.failed.read_link_source",
+                        &[("source", &source), ("error", &e)],
+                    )
+                })?;
+                // SFTP's own symlink request has its arguments swapped by OpenSSH.
+                let line = format!(
+                    "ln -sfn {} {}",
+                    shell::quote(&local_path::for_server(&target)),
+                    shell::quote(dest)
+                );
+                let output = self.ssh.exec(&line).await?;
+                if !output.success() {
+                    return Err(messages::text(
+                        "error.failed.create_link_dest_server_stderr",
+                        &[
+                            ("dest", &dest),
+                            ("stderr", &String::from_utf8_lossy(&output.stderr).trim()),
+                        ],
+                    ));
+                }
+                Ok(())
+            }
+            Direction::Pull => {
+                let target = self.session().read_link(source).await.map_err(|e| {
+                    messages::text(
+                        "error.failed.read_link_path_over_sftp",
+                        &[("path", &source), ("error", &e)],
+                    )
+                })?;
+                let _ = tokio::fs::remove_file(dest).await;
+                make_link(&target, dest).await.map_err(|e| {
+                    messages::text(
+                        "error.failed.create_link_dest",
+                        &[("dest", &dest), ("error", &e)],
+                    )
+                })
+            }
+        }
+    }
+
+    /// Whether this mirror's links are recreated on the destination.
+    fn preserves_links(&self, direction: Direction) -> bool {
+        self.symlinks == SymlinkPolicy::Preserve && (direction == Direction::Push || cfg!(unix))
+    }
+
+    /// Walk both sides of a mirror, leaving out the names this protocol
+    /// can't send, and the source's links unless `links` is set.
+    fn walk<'a>(
+        &'a self,
+        direction: Direction,
+        (source, dest): (&'a str, &'a str),
+        excludes: &'a Excludes,
+        links: bool,
+    ) -> Walk<'a> {
+        let follow = self.symlinks == SymlinkPolicy::Follow;
+        let local = |root: &'a str, follow| -> Box<dyn Lister + 'a> {
+            Box::new(walk::Local {
+                root: Path::new(root),
+                excludes,
+                follow,
+            })
+        };
+        let remote = |root: &'a str, follow| -> Box<dyn Lister + 'a> {
+            Box::new(Listing {
+                sftp: self,
+                root,
+                excludes,
+                follow,
+            })
+        };
+        match direction {
+            Direction::Push => {
+                Walk::new(direction, local(source, follow), remote(dest, false), links)
+            }
+            Direction::Pull => {
+                Walk::new(direction, remote(source, follow), local(dest, false), links)
+            }
+        }
+    }
+
+    /// List a tree on the server, skipping excluded paths and special files;
+    /// with `follow`, links are listed as what they point to, except those
+    /// that lead back up to a directory they're in.
+    async fn remote_tree(
+        &self,
+        root: &str,
+        excludes: &Excludes,
+        follow: bool,
+    ) -> Result<Tree, String> {
+        let mut listing = Tree::new();
+        let mut pending = vec![String::new()];
+        while let Some(dir) = pending.pop() {
+            let entries = self.remote_dir(root, &dir, excludes, follow).await?;
+            pending.extend(
+                entries
+                    .iter()
+                    .filter(|(_, entry)| **entry == Entry::Dir)
+                    .map(|(relative, _)| relative.clone()),
+            );
+            listing.extend(entries);
+        }
+        Ok(listing)
+    }
+
+    /// The entries of the directory `dir` in a tree on the server, listed as
+    /// [`remote_tree`](Self::remote_tree) lists them.
+    async fn remote_dir(
+        &self,
+        root: &str,
+        dir: &str,
+        excludes: &Excludes,
+        follow: bool,
+    ) -> Result<Tree, String> {
+        let mut listing = Tree::new();
+        // A destination that doesn't exist yet is simply empty.
+        if dir.is_empty() && !self.remote_exists(root).await {
+            return Ok(listing);
+        }
+        let path = tree::join_remote(root, dir);
+        let entries = self.session().read_dir(&path).await.map_err(|e| {
+            messages::text(
+                "error.failed.list_path_over_sftp",
+                &[("path", &path), ("error", &e)],
+            )
+        })?;
+        for entry in entries {
+            let name = entry.file_name();
+            if name == "." || name == ".." {
+                continue;
+            }
+            let relative = tree::join_relative(dir, &name);
+            let mut metadata = entry.metadata();
+            if entry.file_type().is_symlink() {
+                let link = tree::join_remote(&path, &name);
+                let followed = match follow {
+                    true => self.followed_metadata(&path, &link).await,
+                    false => None,
+                };
+                match followed {
+                    Some(target) => metadata = target,
+                    None => {
+                        if !excludes.is_excluded(&relative, false) {
+                            let target = self.session().read_link(&link).await.map_err(|e| {
+                                messages::text(
+                                    "error.failed.read_link_path_over_sftp",
+                                    &[("path", &link), ("error", &e)],
+                                )
+                            })?;
+                            listing.insert(relative, Entry::link(&target));
+                        }
+                        continue;
+                    }
+                }
+            }
+            let file_type = metadata.file_type();
+            if file_type.is_dir() {
+                if !excludes.is_excluded(&relative, true) {
+                    listing.insert(relative, Entry::Dir);
+                }
+            } else if file_type.is_file() && !excludes.is_excluded(&relative, false) {
+                let entry = Entry::File {
+                    size: metadata.len(),
+                    mtime: remote_mtime(&metadata),
+                };
+                listing.insert(relative, entry);
+            }
+        }
+        Ok(listing)
+    }
+
+    /// What the link at `link`, in the server's directory `dir`, points to,
+    /// unless it is broken or leads back up to `dir` or above.
+    async fn followed_metadata(&self, dir: &str, link: &str) -> Option<FileAttributes> {
+        let metadata = self.session().metadata(link).await.ok()?;
+        if metadata.file_type().is_dir() {
+            let target = self.session().canonicalize(link).await.ok()?;
+            let dir = self.session().canonicalize(dir).await.ok()?;
+            if Path::new(&dir).starts_with(&target) {
+                return None;
+            }
+        }
+        Some(metadata)
+    }
+
+    async fn remote_exists(&self, path: &str) -> bool {
+        self.session().try_exists(path).await.unwrap_or(false)
+    }
+
+    async fn create_dir(&self, direction: Direction, path: &str) -> Result<(), String> {
+        match direction {
+            Direction::Push => self.session().create_dir(path).await.map_err(|e| {
+                messages::text(
+                    "error.failed.create_path_over_sftp",
+                    &[("path", &path), ("error", &e)],
+                )
+            }),
+            Direction::Pull => tokio::fs::create_dir_all(path).await.map_err(|e| {
+                messages::text(
+                    "error.failed.create_path",
+                    &[("path", &path), ("error", &e)],
+                )
+            }),
+        }
+    }
+
+    async fn remove(&self, direction: Direction, path: &str, entry: Entry) -> Result<(), String> {
+        let result = match (direction, entry) {
+            (Direction::Push, Entry::Dir) => self
+                .session()
+                .remove_dir(path)
+                .await
+                .map_err(|e| e.to_string()),
+            (Direction::Push, Entry::File { .. } | Entry::Link { .. }) => self
+                .session()
+                .remove_file(path)
+                .await
+                .map_err(|e| e.to_string()),
+            (Direction::Pull, Entry::Dir) => {
+                tokio::fs::remove_dir(path).await.map_err(|e| e.to_string())
+            }
+            (Direction::Pull, Entry::File { .. } | Entry::Link { .. }) => {
+                tokio::fs::remove_file(path)
+                    .await
+                    .map_err(|e| e.to_string())
+            }
+        };
+        result.map_err(|e| {
+            messages::text(
+                "error.failed.delete_path",
+                &[("path", &path), ("error", &e)],
+            )
+        })
+    }
+}
+
+impl Transfer for Sftp {
+    fn mirror<'a>(
+        &'a self,
+        direction: Direction,
+        source: &'a str,
+        dest: &'a str,
+        excludes: &'a Excludes,
+        options: &'a MirrorOptions<'a>,
+        on_progress: OnProgress<'a>,
+    ) -> BoxFuture<'a, Result<Mirrored, String>> {
+        Box::pin(async move {
+            // Links that are followed and still listed as links are broken.
+            let links = self.preserves_links(direction);
+            let mut walk = self.walk(direction, (source, dest), excludes, links);
+            let join_source = |relative: &str| tree::join_side(direction, true, source, relative);
+            let join_dest = |relative: &str| tree::join_side(direction, false, dest, relative);
+
+            // What differs is found first, and only then changed, so nothing
+            // is deleted from under the walk.
+            let mut stale = Vec::new();
+            let mut dirs = Vec::new();
+            // The links to copy, and whether each replaces one.
+            let mut new_links = Vec::new();
+            let mut to_copy = Vec::new();
+            // Files the same size on both sides, for the checksums to decide.
+            let mut same_size = Vec::new();
+            while let Some(pair) = walk.next().await? {
+                if let (true, Some(existing)) = (pair.is_stale(), pair.dest) {
+                    stale.push((pair.relative.clone(), existing));
+                }
+                let (relative, existing) = (pair.relative, pair.dest);
+                let Some(entry) = pair.source else {
+                    continue;
+                };
+                match (entry, existing) {
+                    (Entry::Dir, Some(Entry::Dir)) => {}
+                    (Entry::Dir, _) => dirs.push(relative),
+                    (
+                        Entry::File { size, mtime },
+                        Some(Entry::File {
+                            size: dest_size, ..
+                        }),
+                    ) if options.checksums.is_some() && size == dest_size => {
+                        same_size.push((relative, (size, Some(dest_size), mtime)))
+                    }
+                    (Entry::File { size, mtime }, _) if existing != Some(entry) => {
+                        let old_size = match existing {
+                            Some(Entry::File { size, .. }) => Some(size),
+                            _ => None,
+                        };
+                        to_copy.push((relative, (size, old_size, mtime)))
+                    }
+                    (Entry::File { .. }, _) => {}
+                    (Entry::Link { .. }, _) if existing != Some(entry) => {
+                        new_links.push((relative, matches!(existing, Some(Entry::Link { .. }))))
+                    }
+                    (Entry::Link { .. }, _) => {}
+                }
+            }
+            let Walk {
+                partials,
+                skipped_links,
+                skipped_names,
+                ..
+            } = walk;
+
+            let mut changes = FileChanges::default();
+            // Delete first, deepest paths before their parents, so an entry that
+            // changed between file and directory is out of the way before its
+            // replacement arrives.
+            for (relative, entry) in stale.into_iter().rev() {
+                self.remove(direction, &join_dest(&relative), entry).await?;
+                changes.deleted.push(tree::display(&relative, entry));
+            }
+
+            match direction {
+                Direction::Push if !self.remote_exists(dest).await => {
+                    self.create_dir(direction, dest).await?
+                }
+                Direction::Pull => self.create_dir(direction, dest).await?,
+                Direction::Push => {}
+            }
+
+            for relative in dirs {
+                self.create_dir(direction, &join_dest(&relative)).await?;
+                changes.added.push(tree::display(&relative, Entry::Dir));
+            }
+            for (relative, replaces) in new_links {
+                self.copy_link(direction, &join_source(&relative), &join_dest(&relative))
+                    .await?;
+                match replaces {
+                    true => changes.updated.push(relative),
+                    false => changes.added.push(relative),
+                }
+            }
+            if let Some(checksums) = options.checksums {
+                let paths: Vec<String> = same_size.iter().map(|(path, _)| path.clone()).collect();
+                let differing = checksums.differing(source, dest, &paths).await?;
+                to_copy.extend(
+                    same_size
+                        .into_iter()
+                        .filter(|(path, _)| differing.contains(path)),
+                );
+            }
+            let to_copy: Vec<_> = to_copy
+                .into_iter()
+                .map(|(relative, (size, old_size, mtime))| {
+                    let staged = (size >= partial::MIN_SIZE)
+                        .then(|| partial::resume_from(partials.get(&relative), size, mtime));
+                    (relative, (size, old_size, mtime, staged))
+                })
+                .collect();
+            // Partial copies that won't be carried on with are only in the way.
+            for (relative, &entry) in &partials {
+                let resumed = to_copy.iter().any(|(path, (.., staged))| {
+                    path == relative && staged.is_some_and(|at| at > 0)
+                });
+                if !resumed {
+                    self.remove(direction, &join_dest(&partial::path(relative)), entry)
+                        .await?;
+                }
+            }
+
+            let copied = to_copy.iter().map(|(path, _)| path.clone()).collect();
+            for (relative, (size, old_size, ..)) in &to_copy {
+                match old_size {
+                    Some(_) => changes.updated.push(relative.clone()),
+                    None => changes.added.push(relative.clone()),
+                }
+                changes.bytes += size;
+            }
+            let meter = Meter::new(to_copy.len(), options.throttle, on_progress);
+            pool::copy_all(
+                &self.sessions,
+                to_copy,
+                &meter,
+                |session, relative, file| {
+                    let (source, dest) = (join_source(&relative), join_dest(&relative));
+                    let meter = &meter;
+                    async move {
+                        let (size, old_size, mtime, staged) = file;
+                        let resuming = staged.is_some_and(|at| at > 0);
+                        if let Some(old_size) =
+                            old_size.filter(|&old| !resuming && delta::worthwhile(size, old))
+                        {
+                            let paths = (source.as_str(), dest.as_str());
+                            match self
+                                .copy_delta(
+                                    session,
+                                    direction,
+                                    paths,
+                                    (size, old_size, mtime),
+                                    meter,
+                                )
+                                .await
+                            {
+                                Ok(true) => return Ok(()),
+                                Ok(false) => {}
+                                Err(e) => tracing::info!(path = %dest, "copying whole: {e}"),
+                            }
+                        }
+                        let paths = (source.as_str(), dest.as_str());
+                        Self::copy(session, direction, paths, mtime, staged, meter).await
+                    }
+                },
+            )
+            .await?;
+            Ok(Mirrored {
+                copied,
+                skipped_links,
+                skipped_names,
+                changes,
+            })
+        })
+    }
+
+    fn plan<'a>(
+        &'a self,
+        direction: Direction,
+        source: &'a str,
+        dest: &'a str,
+        excludes: &'a Excludes,
+    ) -> BoxFuture<'a, Result<FileChanges, String>> {
+        Box::pin(async move {
+            let walk = self.walk(direction, (source, dest), excludes, true);
+            walk::changes(walk, |source, dest| source == dest).await
+        })
+    }
+
+    fn list<'a>(
+        &'a self,
+        root: &'a str,
+        excludes: &'a Excludes,
+    ) -> BoxFuture<'a, Result<Tree, String>> {
+        Box::pin(async move {
+            let mut listing = self.remote_tree(root, excludes, false).await?;
+            partial::split(&mut listing);
+            tree::take_links(&mut listing);
+            Ok(listing)
+        })
+    }
+}
+
+/// A tree on the server, as one side of a walk.
+struct Listing<'a> {
+    sftp: &'a Sftp,
+    root: &'a str,
+    excludes: &'a Excludes,
+    follow: bool,
+}
+
+impl Lister for Listing<'_> {
+    fn read_dir<'a>(&'a self, dir: &'a str) -> BoxFuture<'a, Result<Tree, String>> {
+        Box::pin(
+            self.sftp
+                .remote_dir(self.root, dir, self.excludes, self.follow),
+        )
+    }
+}
+
+/// Copy everything from `reader` to `writer`, counting bytes into `meter`.
+async fn pump(
+    reader: &mut (impl AsyncRead + Unpin),
+    writer: &mut (impl AsyncWrite + Unpin),
+    dest: &str,
+    meter: &Meter<'_>,
+) -> Result<(), String> {
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        let n = reader.read(&mut buf).await.map_err(|e| {
+            messages::text(
+                "error.failed.read_while_copying_dest",
+                &[("dest", &dest), ("error", &e)],
+            )
+        })?;
+        if n == 0 {
+            return Ok(());
+        }
+        writer.write_all(&buf[..n]).await.map_err(|e| {
+            messages::text("error.failed.write_path", &[("path", &dest), ("error", &e)])
+        })?;
+        meter.sent(n).await;
+    }
+}
+
+/// Create a symlink at `link` on this machine pointing to `target`.
+#[cfg(unix)]
+async fn make_link(target: &str, link: &str) -> std::io::Result<()> {
+    tokio::fs::symlink(target, link).await
+}
+
+/// Windows only lets administrators and developer mode create symlinks.
+#[cfg(not(unix))]
+async fn make_link(_target: &str, _link: &str) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "symlinks aren't created on Windows",
+    ))
+}
+
+/// Open an SFTP session on a new channel.
+pub(crate) async fn start_session(
+    remote: &Remote,
+    request_timeout: Option<Duration>,
+) -> Result<SftpSession, String> {
+    let channel = remote.open_subsystem("sftp").await?;
+    let config = Config {
+        // It can't wait forever, but this is as long as forever.
+        request_timeout_secs: request_timeout.map_or(u64::MAX, |timeout| timeout.as_secs()),
+        ..Config::default()
+    };
+    SftpSession::new_with_config(channel.into_stream(), config)
+        .await
+        .map_err(|e| messages::text("error.failed.start_sftp_session", &[("error", &e)]))
+}
+
+fn remote_mtime(attrs: &FileAttributes) -> Option<u64> {
+    attrs.mtime.map(u64::from)
+}
@@ -0,0 +1,186 @@
+//! Holding a transfer to a bandwidth limit that can change while it runs.
+//!
+//! SFTP and FTP copies call [`Throttle::consume`] for every chunk, which sleeps
+//! just long enough to keep the average rate under the limit. rsync is given
+//! the limit as `--bwlimit` instead, and restarted with the new one when it
+//! changes; `--partial` keeps what it had sent of the file it was on.
+//!
+//! The limit in force is the lower of the job's own and its share of the
+//! app-wide limit, which changes as other jobs start and finish transferring.
+//!
+//! A throttle can also be paused, which holds copies back between files (and
+//! an import between tables) until it is let go again. rsync is stopped for a
+//! pause and started again after it.
+//!
+//! It can also hold SFTP and FTP copies to fewer files at once than they have
+//! connections, as a bandwidth window may ask; the connections past the
+//! number wait between files until it rises again.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tokio::sync::watch;
+
+/// How far a transfer may fall behind its limit before the lost time is
+/// forgotten, so a pause between files doesn't turn into a burst after it.
+const MAX_LAG: Duration = Duration::from_secs(1);
+
+/// A job's bandwidth limit, in KiB per second, shared by its transfers.
+pub struct Throttle {
+    /// The limit in force.
+    limit: watch::Sender<Option<u64>>,
+    /// The job's own limit, and its share of the app-wide one.
+    own: Mutex<Option<u64>>,
+    share: Mutex<Option<u64>>,
+    paused: watch::Sender<bool>,
+    /// How many files may be copied at once, if fewer than the connections.
+    concurrency: watch::Sender<Option<usize>>,
+    pace: Mutex<Pace>,
+}
+
+/// Bytes sent since `since` under the limit then in force.
+struct Pace {
+    since: Instant,
+    bytes: u64,
+    limit: Option<u64>,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl Throttle {
+    /// A throttle at `limit` KiB per second, or unlimited for `None`.
+    pub fn new(limit: Option<u64>) -> Self {
+        Self {
+            limit: watch::Sender::new(limit),
+            own: Mutex::new(limit),
+            share: Mutex::new(None),
+            paused: watch::Sender::new(false),
+            concurrency: watch::Sender::new(None),
+            pace: Mutex::new(Pace {
+                since: Instant::now(),
+                bytes: 0,
+                limit,
+            }),
+        }
+    }
+
+    /// The limit in force in KiB per second, if there is one.
+    pub fn limit(&self) -> Option<u64> {
+        *self.limit.borrow()
+    }
+
+    /// The job's own limit, which its share of the app-wide one may lower.
+    pub fn own_limit(&self) -> Option<u64> {
+        *self.own.lock().unwrap()
+    }
+
+    /// Change the job's limit, or lift it with `None`, for the transfer
+    /// running now and any after it. A limit of 0 counts as none.
+    pub fn set(&self, limit: Option<u64>) {
+        *self.own.lock().unwrap() = limit.filter(|&limit| limit > 0);
+        self.publish();
+    }
+
+    /// Hold the job to `share` of the app-wide limit as well, or with `None`
+    /// just to its own.
+    pub(crate) fn set_share(&self, share: Option<u64>) {
+        *self.share.lock().unwrap() = share.filter(|&share| share > 0);
+        self.publish();
+    }
+
+    /// Put the lower of the two limits in force, if that changes it: each
+    /// change restarts rsync.
+    fn publish(&self) {
+        let own = self.own_limit();
+        let share = *self.share.lock().unwrap();
+        let limit = match (own, share) {
+            (Some(own), Some(share)) => Some(own.min(share)),
+            (limit, None) | (None, limit) => limit,
+        };
+        self.limit.send_if_modified(|current| {
+            let changed = *current != limit;
+            *current = limit;
+            changed
+        });
+    }
+
+    /// A receiver that sees the limit each time it is [`set`](Self::set).
+    pub(crate) fn watch(&self) -> watch::Receiver<Option<u64>> {
+        self.limit.subscribe()
+    }
+
+    /// Hold the job's transfers back at the next file, or let them go on.
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.send_replace(paused);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Wait until the throttle isn't paused.
+    pub(crate) async fn wait_while_paused(&self) {
+        // The sender lives as long as `self`, so waiting can't fail.
+        let _ = self.paused.subscribe().wait_for(|paused| !paused).await;
+    }
+
+    /// Wait until the throttle is paused.
+    pub(crate) async fn wait_for_pause(&self) {
+        let _ = self.paused.subscribe().wait_for(|paused| *paused).await;
+    }
+
+    /// Copy at most `files` at once from the next file on, or with `None` as
+    /// many as there are connections. Below 1 counts as 1.
+    pub fn set_concurrency(&self, files: Option<usize>) {
+        self.concurrency
+            .send_replace(files.map(|files| files.max(1)));
+    }
+
+    pub fn concurrency(&self) -> Option<usize> {
+        *self.concurrency.borrow()
+    }
+
+    /// Wait until the connection numbered `worker`, from 0, is among those
+    /// allowed to copy. The first always is.
+    pub(crate) async fn wait_for_slot(&self, worker: usize) {
+        let _ = self
+            .concurrency
+            .subscribe()
+            .wait_for(|files| files.is_none_or(|files| worker < files))
+            .await;
+    }
+
+    /// Account for `bytes` just sent, waiting if they put the transfer ahead
+    /// of its limit.
+    pub(crate) async fn consume(&self, bytes: u64) {
+        let limit = self.limit();
+        let wait = {
+            let mut pace = self.pace.lock().unwrap();
+            if pace.limit != limit {
+                *pace = Pace {
+                    since: Instant::now(),
+                    bytes: 0,
+                    limit,
+                };
+            }
+            let Some(limit) = limit else {
+                return;
+            };
+            pace.bytes += bytes;
+            let due = Duration::from_secs_f64(pace.bytes as f64 / (limit * 1024) as f64);
+            let elapsed = pace.since.elapsed();
+            if elapsed > due + MAX_LAG {
+                pace.since = Instant::now();
+                pace.bytes = 0;
+            }
+            due.saturating_sub(elapsed)
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Directory listings shared by the transfer methods that mirror trees themselves.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use super::{partial, Excludes};
+use crate::byte_path;
+use crate::messages;
+use crate::sync::{local_path, Direction};
+
+/// What a mirror needs to know about one entry of a tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Entry {
+    Dir,
+    /// `mtime` is whole seconds since the epoch, or `None` if the side can't report it.
+    File {
+        size: u64,
+        mtime: Option<u64>,
+    },
+    /// A symbolic link, with a hash of where it points for telling whether
+    /// two links agree. Links that are followed are listed as what they
+    /// point to; this is what is left of them, the broken ones included.
+    Link {
+        target: u64,
+    },
+}
+
+impl Entry {
+    pub(super) fn link(target: &str) -> Self {
+        let mut hash = DefaultHasher::new();
+        target.hash(&mut hash);
+        Self::Link {
+            target: hash.finish(),
+        }
+    }
+}
+
+/// Every directory and regular file under a root, keyed by relative `/`-separated path.
+/// Ordered, so parents always come before their children.
+pub type Tree = BTreeMap<String, Entry>;
+
+/// List a tree on this machine, skipping excluded paths, symlinks, special
+/// files, and [partial copies](super::partial).
+pub async fn local_tree(root: &Path, excludes: &Excludes) -> Result<Tree, String> {
+    let mut tree = local_listing(root, excludes, false).await?;
+    partial::split(&mut tree);
+    take_links(&mut tree);
+    Ok(tree)
+}
+
+/// [`local_tree`], with partial copies and symlinks; with `follow`, links
+/// are listed as what they point to, except those that lead back up to a
+/// directory they're in.
+pub(super) async fn local_listing(
+    root: &Path,
+    excludes: &Excludes,
+    follow: bool,
+) -> Result<Tree, String> {
+    let mut tree = Tree::new();
+    let mut pending = vec![String::new()];
+    while let Some(dir) = pending.pop() {
+        let entries = local_dir(root, &dir, excludes, follow).await?;
+        pending.extend(
+            entries
+                .iter()
+                .filter(|(_, entry)| **entry == Entry::Dir)
+                .map(|(relative, _)| relative.clone()),
+        );
+        tree.extend(entries);
+    }
+    Ok(tree)
+}
+
+/// The entries of the directory `dir` in a tree on this machine, listed as
+/// [`local_listing`] lists them; none if the tree's root doesn't exist.
+pub(super) async fn local_dir(
+    root: &Path,
+    dir: &str,
+    excludes: &Excludes,
+    follow: bool,
+) -> Result<Tree, String> {
+    let mut tree = Tree::new();
+    if dir.is_empty() && !root.exists() {
+        return Ok(tree);
+    }
+    let path = local_path::join(root, dir);
+    let mut entries = tokio::fs::read_dir(&path).await.map_err(|e| {
+        messages::text(
+            "error.failed.list_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })?;
+    while let Some(entry) = entries.next_entry().await.map_err(|e| {
+        messages::text(
+            "error.failed.list_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    })? {
+        let name = byte_path::from_os(&entry.file_name());
+        let relative = join_relative(dir, &name);
+        let mut metadata = tokio::fs::symlink_metadata(entry.path())
+            .await
+            .map_err(|e| {
+                messages::text(
+                    "error.failed.stat_path",
+                    &[("path", &entry.path().display()), ("error", &e)],
+                )
+            })?;
+        if metadata.is_symlink() {
+            let followed = match follow {
+                true => followed_metadata(&path, &entry.path()).await,
+                false => None,
+            };
+            match followed {
+                Some(target) => metadata = target,
+                None => {
+                    if !excludes.is_excluded(&relative, false) {
+                        let target = tokio::fs::read_link(entry.path()).await.map_err(|e| {
+                            messages::text(
+                                "error.failed.read_path",
+                                &[("path", &entry.path().display()), ("error", &e)],
+                            )
+                        })?;
+                        tree.insert(
+                            relative,
+                            Entry::link(&byte_path::from_os(target.as_os_str())),
+                        );
+                    }
+                    continue;
+                }
+            }
+        }
+        if metadata.is_dir() {
+            if !excludes.is_excluded(&relative, true) {
+                tree.insert(relative, Entry::Dir);
+            }
+        } else if metadata.is_file() && !excludes.is_excluded(&relative, false) {
+            let entry = Entry::File {
+                size: metadata.len(),
+                mtime: local_mtime(&metadata),
+            };
+            tree.insert(relative, entry);
+        }
+    }
+    Ok(tree)
+}
+
+/// What the link at `link`, in the directory `dir`, points to, unless it is
+/// broken or leads back up to `dir` or above, which would never end.
+async fn followed_metadata(dir: &Path, link: &Path) -> Option<std::fs::Metadata> {
+    let metadata = tokio::fs::metadata(link).await.ok()?;
+    if metadata.is_dir() {
+        let target = tokio::fs::canonicalize(link).await.ok()?;
+        let dir = tokio::fs::canonicalize(dir).await.ok()?;
+        if dir.starts_with(&target) {
+            return None;
+        }
+    }
+    Some(metadata)
+}
+
+/// Take the symlinks out of `tree`, returning their paths.
+pub(super) fn take_links(tree: &mut Tree) -> Vec<String> {
+    let links: Vec<String> = tree
+        .iter()
+        .filter(|(_, entry)| matches!(entry, Entry::Link { .. }))
+        .map(|(relative, _)| relative.clone())
+        .collect();
+    for link in &links {
+        tree.remove(link);
+    }
+    links
+}
+
+/// Take the symlinks out of a mirror's `source`, for one that doesn't copy
+/// them, returning their paths. The destination's links are left alone,
+/// unless something from the source is to take their place.
+pub(super) fn leave_links(source: &mut Tree, dest: &mut Tree) -> Vec<String> {
+    let skipped = take_links(source);
+    dest.retain(|relative, entry| {
+        !matches!(entry, Entry::Link { .. }) || source.contains_key(relative)
+    });
+    skipped
+}
+
+/// Take the entries of a mirror's `source` whose names SFTP and FTP can't
+/// send out of it, returning their paths, and leave the destination's alone.
+/// Both name files in UTF-8, so a name with other bytes, or with the
+/// replacement character a server's listing put in their place, can't be
+/// copied; rsync, and commands over SSH, send such names as they are.
+pub(super) fn leave_unsendable(source: &mut Tree, dest: &mut Tree) -> Vec<String> {
+    let unsendable = |relative: &str| {
+        byte_path::is_raw(relative) || relative.contains(char::REPLACEMENT_CHARACTER)
+    };
+    let skipped: Vec<String> = source
+        .keys()
+        .filter(|relative| unsendable(relative))
+        .cloned()
+        .collect();
+    for relative in &skipped {
+        source.remove(relative);
+    }
+    dest.retain(|relative, _| !unsendable(relative));
+    skipped
+}
+
+/// Spell the paths of this machine's `local` tree as `other` does those that
+/// differ only in case, where the case of a name doesn't matter, so they're
+/// compared as the same file and not as one deleted and another added.
+pub fn match_case(local: &mut Tree, other: &Tree) {
+    if !local_path::CASE_INSENSITIVE {
+        return;
+    }
+    let spellings: HashMap<String, &String> = other
+        .keys()
+        .map(|relative| (relative.to_lowercase(), relative))
+        .collect();
+    let respelled: Vec<(String, String)> = local
+        .keys()
+        .filter(|relative| !other.contains_key(*relative))
+        .filter_map(|relative| {
+            let spelling = spellings.get(&relative.to_lowercase())?;
+            Some((relative.clone(), (*spelling).clone()))
+        })
+        .collect();
+    for (relative, spelling) in respelled {
+        if let Some(entry) = local.remove(&relative) {
+            local.insert(spelling, entry);
+        }
+    }
+}
+
+/// `listing` without its excluded paths and everything inside an excluded directory.
+pub fn pruned(listing: Tree, excludes: &Excludes) -> Tree {
+    let mut tree = Tree::new();
+    // Parents sort before their children, so each is decided first.
+    for (relative, entry) in listing {
+        let parent_kept = match relative.rsplit_once('/') {
+            Some((parent, _)) => tree.contains_key(parent),
+            None => true,
+        };
+        if parent_kept && !excludes.is_excluded(&relative, matches!(entry, Entry::Dir)) {
+            tree.insert(relative, entry);
+        }
+    }
+    tree
+}
+
+pub fn local_mtime(metadata: &std::fs::Metadata) -> Option<u64> {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|since| since.as_secs())
+}
+
+/// A path in a tree as [`FileChanges`](super::FileChanges) lists it: a directory's ends in `/`.
+pub fn display(relative: &str, entry: Entry) -> String {
+    match entry {
+        Entry::Dir => format!("{relative}/"),
+        Entry::File { .. } | Entry::Link { .. } => relative.to_string(),
+    }
+}
+
+/// Join a tree-relative path onto the root of one side of the transfer.
+pub fn join_side(direction: Direction, is_source: bool, root: &str, relative: &str) -> String {
+    let is_local = matches!(
+        (direction, is_source),
+        (Direction::Push, true) | (Direction::Pull, false)
+    );
+    if is_local {
+        local_path::join(Path::new(root), relative)
+            .display()
+            .to_string()
+    } else {
+        join_remote(root, relative)
+    }
+}
+
+pub fn join_remote(root: &str, relative: &str) -> String {
+    if relative.is_empty() {
+        root.to_string()
+    } else {
+        format!("{}/{relative}", root.trim_end_matches('/'))
+    }
+}
+
+pub fn join_relative(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{dir}/{name}")
+    }
+}
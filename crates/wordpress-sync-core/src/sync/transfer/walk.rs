@@ -0,0 +1,371 @@
+//! Walking both sides of a mirror together, for the transfer methods that
+//! compare trees themselves.
+//!
+//! Each directory is listed on both sides and the two listings merged by
+//! name, and only then is the walk carried down into its subdirectories, so
+//! what is held at once is the directories on the way down to the one being
+//! compared, never a listing of either whole tree. Paths come out parents
+//! first, and each directory's entries in the order of their names.
+
+use std::path::Path;
+
+use super::tree::{self, Entry, Tree};
+use super::{partial, BoxFuture, Excludes, FileChanges};
+use crate::sync::Direction;
+
+/// One side of a walk, listed a directory at a time.
+pub(super) trait Lister: Send + Sync {
+    /// The entries of the directory `dir`, relative to the side's root (the
+    /// root itself is `""`), keyed by their paths relative to the root and
+    /// with excluded paths left out. A root that doesn't exist is empty.
+    fn read_dir<'a>(&'a self, dir: &'a str) -> BoxFuture<'a, Result<Tree, String>>;
+}
+
+/// A tree on this machine, as one side of a walk.
+pub(super) struct Local<'a> {
+    pub root: &'a Path,
+    pub excludes: &'a Excludes,
+    /// List links as what they point to, as [`tree::local_listing`] does.
+    pub follow: bool,
+}
+
+impl Lister for Local<'_> {
+    fn read_dir<'a>(&'a self, dir: &'a str) -> BoxFuture<'a, Result<Tree, String>> {
+        Box::pin(tree::local_dir(self.root, dir, self.excludes, self.follow))
+    }
+}
+
+/// A path on either side of a mirror, with what each side has there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Pair {
+    pub relative: String,
+    pub source: Option<Entry>,
+    pub dest: Option<Entry>,
+}
+
+impl Pair {
+    /// Whether the destination's entry has to go before the source can be
+    /// copied over: the source no longer has it, or it changed between file,
+    /// directory, and link.
+    pub fn is_stale(&self) -> bool {
+        match (self.source, self.dest) {
+            (_, None) => false,
+            (None, Some(_)) => true,
+            (Some(source), Some(dest)) => {
+                std::mem::discriminant(&source) != std::mem::discriminant(&dest)
+            }
+        }
+    }
+}
+
+/// Both sides of a mirror, walked together.
+pub(super) struct Walk<'a> {
+    direction: Direction,
+    source: Box<dyn Lister + 'a>,
+    dest: Box<dyn Lister + 'a>,
+    /// Whether the source's links are copied; if not, they are left out with
+    /// the destination's that nothing from the source takes the place of.
+    links: bool,
+    /// The directories being walked, the deepest last, each with the paths
+    /// in it still to come.
+    stack: Vec<std::vec::IntoIter<Pair>>,
+    /// The directory to go down into before the next path, and whether the
+    /// source and the destination have it.
+    descend: Option<(String, bool, bool)>,
+    started: bool,
+    /// The destination's partial copies, keyed by the path of the file each
+    /// is a copy of.
+    pub partials: Tree,
+    /// The source's links left out.
+    pub skipped_links: Vec<String>,
+    /// The source's paths left out because their names can't be sent.
+    pub skipped_names: Vec<String>,
+}
+
+impl<'a> Walk<'a> {
+    /// A walk of mirroring `source` onto `dest` in `direction`, with the
+    /// source's links copied if `links` is set, or else left out.
+    pub fn new(
+        direction: Direction,
+        source: Box<dyn Lister + 'a>,
+        dest: Box<dyn Lister + 'a>,
+        links: bool,
+    ) -> Self {
+        Self {
+            direction,
+            source,
+            dest,
+            links,
+            stack: Vec::new(),
+            descend: None,
+            started: false,
+            partials: Tree::new(),
+            skipped_links: Vec::new(),
+            skipped_names: Vec::new(),
+        }
+    }
+
+    /// The next path on either side, or `None` once both are walked.
+    pub async fn next(&mut self) -> Result<Option<Pair>, String> {
+        if !self.started {
+            self.started = true;
+            self.descend = Some((String::new(), true, true));
+        }
+        if let Some((dir, source, dest)) = self.descend.take() {
+            let pairs = self.read(&dir, source, dest).await?;
+            self.stack.push(pairs.into_iter());
+        }
+        while let Some(level) = self.stack.last_mut() {
+            let Some(pair) = level.next() else {
+                self.stack.pop();
+                continue;
+            };
+            let source = pair.source == Some(Entry::Dir);
+            let dest = pair.dest == Some(Entry::Dir);
+            if source || dest {
+                self.descend = Some((pair.relative.clone(), source, dest));
+            }
+            return Ok(Some(pair));
+        }
+        Ok(None)
+    }
+
+    /// The paths in the directory `dir` on both sides, from the sides that
+    /// have it, as a mirror compares them.
+    async fn read(
+        &mut self,
+        dir: &str,
+        in_source: bool,
+        in_dest: bool,
+    ) -> Result<Vec<Pair>, String> {
+        let mut source = match in_source {
+            true => self.source.read_dir(dir).await?,
+            false => Tree::new(),
+        };
+        let mut dest = match in_dest {
+            true => self.dest.read_dir(dir).await?,
+            false => Tree::new(),
+        };
+        match self.direction {
+            Direction::Push => tree::match_case(&mut source, &dest),
+            Direction::Pull => tree::match_case(&mut dest, &source),
+        }
+        self.skipped_names
+            .extend(tree::leave_unsendable(&mut source, &mut dest));
+        partial::split(&mut source);
+        self.partials.extend(partial::split(&mut dest));
+        if !self.links {
+            self.skipped_links
+                .extend(tree::leave_links(&mut source, &mut dest));
+        }
+        let mut pairs = Vec::with_capacity(source.len().max(dest.len()));
+        let mut dest = dest.into_iter().peekable();
+        for (relative, entry) in source {
+            while let Some((before, existing)) = dest.next_if(|(path, _)| *path < relative) {
+                pairs.push(Pair {
+                    relative: before,
+                    source: None,
+                    dest: Some(existing),
+                });
+            }
+            let existing = dest.next_if(|(path, _)| *path == relative);
+            pairs.push(Pair {
+                relative,
+                source: Some(entry),
+                dest: existing.map(|(_, existing)| existing),
+            });
+        }
+        pairs.extend(dest.map(|(relative, existing)| Pair {
+            relative,
+            source: None,
+            dest: Some(existing),
+        }));
+        Ok(pairs)
+    }
+
+    /// How many paths the walk holds, listed but not yet walked.
+    #[cfg(test)]
+    fn held(&self) -> usize {
+        self.stack.iter().map(ExactSizeIterator::len).sum()
+    }
+}
+
+/// What mirroring a walk's source onto its destination changes. `unchanged`
+/// decides whether a file present on both sides can be left alone.
+pub(super) async fn changes(
+    mut walk: Walk<'_>,
+    unchanged: impl Fn(Entry, Entry) -> bool,
+) -> Result<FileChanges, String> {
+    let mut changes = FileChanges::default();
+    while let Some(pair) = walk.next().await? {
+        let stale = pair.is_stale();
+        if let (true, Some(existing)) = (stale, pair.dest) {
+            changes
+                .deleted
+                .push(tree::display(&pair.relative, existing));
+        }
+        let Some(entry) = pair.source else {
+            continue;
+        };
+        match pair.dest {
+            Some(existing) if !stale => {
+                let changed = match entry {
+                    Entry::Dir => false,
+                    Entry::File { .. } => !unchanged(entry, existing),
+                    Entry::Link { .. } => entry != existing,
+                };
+                if !changed {
+                    continue;
+                }
+                changes.updated.push(tree::display(&pair.relative, entry));
+            }
+            _ => changes.added.push(tree::display(&pair.relative, entry)),
+        }
+        if let Entry::File { size, .. } = entry {
+            changes.bytes += size;
+        }
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tree of `dirs` directories of `files` files each, made up as it's listed.
+    struct Generated {
+        dirs: usize,
+        files: usize,
+        size: u64,
+    }
+
+    impl Lister for Generated {
+        fn read_dir<'a>(&'a self, dir: &'a str) -> BoxFuture<'a, Result<Tree, String>> {
+            let listing = match dir {
+                "" => (0..self.dirs)
+                    .map(|i| (format!("d{i:05}"), Entry::Dir))
+                    .collect(),
+                dir => (0..self.files)
+                    .map(|i| {
+                        let entry = Entry::File {
+                            size: self.size,
+                            mtime: Some(0),
+                        };
+                        (format!("{dir}/f{i:05}"), entry)
+                    })
+                    .collect(),
+            };
+            Box::pin(async move { Ok(listing) })
+        }
+    }
+
+    /// A tree given whole, listed a directory at a time.
+    struct Fixed(Tree);
+
+    impl Lister for Fixed {
+        fn read_dir<'a>(&'a self, dir: &'a str) -> BoxFuture<'a, Result<Tree, String>> {
+            let listing = self
+                .0
+                .iter()
+                .filter(|(relative, _)| {
+                    relative.rsplit_once('/').map_or("", |(parent, _)| parent) == dir
+                })
+                .map(|(relative, &entry)| (relative.clone(), entry))
+                .collect();
+            Box::pin(async move { Ok(listing) })
+        }
+    }
+
+    fn file(size: u64) -> Entry {
+        Entry::File {
+            size,
+            mtime: Some(0),
+        }
+    }
+
+    #[tokio::test]
+    async fn holds_a_directory_at_a_time() {
+        let (dirs, files) = (400, 400);
+        let source = Generated {
+            dirs,
+            files,
+            size: 2,
+        };
+        let dest = Generated {
+            dirs,
+            files,
+            size: 1,
+        };
+        let mut walk = Walk::new(Direction::Push, Box::new(source), Box::new(dest), true);
+        let (mut walked, mut held) = (0, 0);
+        while let Some(pair) = walk.next().await.unwrap() {
+            assert!(pair.source.is_some() && pair.dest.is_some());
+            walked += 1;
+            held = held.max(walk.held());
+        }
+        assert_eq!(walked, dirs * (files + 1));
+        assert!(held <= dirs + files, "held {held} paths at once");
+    }
+
+    #[tokio::test]
+    async fn compares_as_a_mirror_does() {
+        let source = Tree::from([
+            ("a".to_string(), Entry::Dir),
+            ("a/same".to_string(), file(1)),
+            ("a/grown".to_string(), file(2)),
+            ("a-b".to_string(), file(1)),
+            ("new".to_string(), Entry::Dir),
+            ("new/file".to_string(), file(3)),
+            ("was-dir".to_string(), file(4)),
+        ]);
+        let dest = Tree::from([
+            ("a".to_string(), Entry::Dir),
+            ("a/same".to_string(), file(1)),
+            ("a/grown".to_string(), file(1)),
+            ("a/gone".to_string(), file(1)),
+            ("a/.big.wpsync-partial".to_string(), file(9)),
+            ("was-dir".to_string(), Entry::Dir),
+            ("was-dir/inside".to_string(), file(1)),
+        ]);
+        let walk = || {
+            Walk::new(
+                Direction::Push,
+                Box::new(Fixed(source.clone())),
+                Box::new(Fixed(dest.clone())),
+                true,
+            )
+        };
+
+        let mut order = Vec::new();
+        let mut walking = walk();
+        while let Some(pair) = walking.next().await.unwrap() {
+            order.push(pair.relative);
+        }
+        assert_eq!(
+            order,
+            [
+                "a",
+                "a/gone",
+                "a/grown",
+                "a/same",
+                "a-b",
+                "new",
+                "new/file",
+                "was-dir",
+                "was-dir/inside",
+            ]
+        );
+        assert_eq!(
+            walking.partials,
+            Tree::from([("a/big".to_string(), file(9))])
+        );
+
+        let changes = changes(walk(), |source, dest| source == dest)
+            .await
+            .unwrap();
+        assert_eq!(changes.deleted, ["a/gone", "was-dir/", "was-dir/inside"]);
+        assert_eq!(changes.added, ["a-b", "new/", "new/file", "was-dir"]);
+        assert_eq!(changes.updated, ["a/grown"]);
+        assert_eq!(changes.bytes, 2 + 1 + 3 + 4);
+    }
+}
@@ -0,0 +1,296 @@
+//! Two-way syncs of the uploads directory.
+//!
+//! A one-way sync makes the destination a copy of the source. A two-way one
+//! compares each side with how the last two-way sync left both, recorded in
+//! `~/.wordpress-sync/two-way/<profile-id>.json`: a file only one side has is
+//! copied to the other, and one that differs is copied from the side that
+//! changed it. A file changed on both sides since, or differing with no record
+//! to go by (as on the first run), is a conflict, left alone on each side for
+//! the user to settle.
+//!
+//! Nothing is deleted: a file removed from one side is copied back from the
+//! other, so removing uploads is left to one-way syncs. A protected server is
+//! only written to by a confirmed push.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::transfer::{local_tree, match_case, Entry, Excludes, MirrorOptions, OnProgress, Tree};
+use super::{exclude_rules, scope, Components, Direction, Job};
+use crate::messages::{self, Message};
+use crate::paths;
+
+/// What a two-way sync copies each way, as paths relative to the uploads directory.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TwoWayPlan {
+    /// New or changed on this machine, so copied to the server.
+    pub push: Vec<String>,
+    /// New or changed on the server, so copied here.
+    pub pull: Vec<String>,
+    /// Changed on both sides since the last two-way sync, or differing with
+    /// nothing to tell which side changed; left as they are.
+    pub conflicts: Vec<String>,
+    /// The total size of the files to be copied.
+    pub bytes: u64,
+}
+
+/// One copy of a file, as its side's listing reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct Seen {
+    size: u64,
+    mtime: Option<u64>,
+}
+
+impl Seen {
+    fn of(entry: Option<&Entry>) -> Option<Self> {
+        match *entry? {
+            Entry::File { size, mtime } => Some(Self { size, mtime }),
+            Entry::Dir | Entry::Link { .. } => None,
+        }
+    }
+
+    /// Whether two copies look the same. One whose side can't report times
+    /// is compared by size alone.
+    fn matches(self, other: Self) -> bool {
+        self.size == other.size
+            && (self.mtime.is_none() || other.mtime.is_none() || self.mtime == other.mtime)
+    }
+}
+
+/// Both copies of a file that matched when the last two-way sync finished.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Synced {
+    local: Seen,
+    remote: Seen,
+}
+
+/// What the last two-way sync of a profile left on both sides.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    files: BTreeMap<String, Synced>,
+}
+
+impl State {
+    fn load(profile_id: &str) -> Result<Self, String> {
+        let path = state_path(profile_id)?;
+        match std::fs::read_to_string(&path) {
+            Ok(raw) => serde_json::from_str(&raw).map_err(|e| {
+                messages::text(
+                    "error.invalid_two_way_sync_state_path",
+                    &[("path", &path.display()), ("error", &e)],
+                )
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(messages::text(
+                "error.failed.read_path",
+                &[("path", &path.display()), ("error", &e)],
+            )),
+        }
+    }
+
+    fn save(&self, profile_id: &str) -> Result<(), String> {
+        let path = state_path(profile_id)?;
+        let json = serde_json::to_string(self).map_err(|e| {
+            messages::text(
+                "error.failed.serialize_two_way_sync_state",
+                &[("error", &e)],
+            )
+        })?;
+        let dir = paths::two_way_dir()?;
+        std::fs::create_dir_all(&dir).map_err(|e| {
+            messages::text(
+                "error.failed.create_path",
+                &[("path", &dir.display()), ("error", &e)],
+            )
+        })?;
+        paths::atomic_write(&path, json)
+    }
+
+    /// The files whose two copies match, from both sides' listings.
+    fn matching(local: &Tree, remote: &Tree) -> Self {
+        let files = local
+            .iter()
+            .filter_map(|(path, entry)| {
+                let local = Seen::of(Some(entry))?;
+                let remote = Seen::of(remote.get(path))?;
+                local
+                    .matches(remote)
+                    .then(|| (path.clone(), Synced { local, remote }))
+            })
+            .collect();
+        Self { files }
+    }
+}
+
+fn state_path(profile_id: &str) -> Result<PathBuf, String> {
+    Ok(paths::two_way_dir()?.join(format!("{profile_id}.json")))
+}
+
+/// Decide which way each file that differs between `local` and `remote` goes.
+fn reconcile(local: &Tree, remote: &Tree, state: &State) -> TwoWayPlan {
+    let mut plan = TwoWayPlan::default();
+    let paths: BTreeSet<&String> = local.keys().chain(remote.keys()).collect();
+    for path in paths {
+        let (here, there) = (local.get(path), remote.get(path));
+        match (Seen::of(here), Seen::of(there)) {
+            (None, None) => {}
+            (Some(_), None) if there.is_none() => plan.push.push(path.clone()),
+            (None, Some(_)) if here.is_none() => plan.pull.push(path.clone()),
+            (Some(l), Some(r)) if l.matches(r) => {}
+            (Some(l), Some(r)) => {
+                let last = state.files.get(path);
+                let local_changed = last.is_none_or(|last| last.local != l);
+                let remote_changed = last.is_none_or(|last| last.remote != r);
+                match (local_changed, remote_changed) {
+                    (true, false) => plan.push.push(path.clone()),
+                    (false, true) => plan.pull.push(path.clone()),
+                    _ => plan.conflicts.push(path.clone()),
+                }
+            }
+            // A file on one side where the other has a directory.
+            _ => plan.conflicts.push(path.clone()),
+        }
+    }
+    plan
+}
+
+/// `path` as an exclude pattern matching only itself.
+fn literal(path: &str) -> String {
+    path.chars()
+        .map(|c| match c {
+            '*' | '?' | '[' | '{' | '}' => format!("[{c}]"),
+            _ => c.to_string(),
+        })
+        .collect()
+}
+
+/// The total size of the files at `paths` in `tree`.
+fn size(tree: &Tree, paths: &[String]) -> u64 {
+    paths
+        .iter()
+        .filter_map(|path| Seen::of(tree.get(path)))
+        .map(|seen| seen.size)
+        .sum()
+}
+
+impl Job<'_> {
+    /// Whether the file phase syncs the uploads both ways instead of mirroring them.
+    pub(super) fn two_way(&self) -> bool {
+        self.profile.two_way_uploads
+            && self.components == Components::UploadsOnly
+            && self.from_server.is_none()
+    }
+
+    /// Both sides' uploads, this machine's first.
+    async fn two_way_trees(&self) -> Result<(Tree, Tree), String> {
+        let excludes = self.excludes().await?;
+        let mut local = local_tree(Path::new(&self.files_root(&self.local())), &excludes).await?;
+        let remote = self
+            .transfer
+            .list(&self.files_root(&self.remote()), &excludes)
+            .await?;
+        match_case(&mut local, &remote);
+        Ok((local, remote))
+    }
+
+    /// What a two-way sync would copy each way, from the listings of both sides.
+    fn reconcile(&self, local: &Tree, remote: &Tree) -> Result<TwoWayPlan, String> {
+        let mut plan = reconcile(local, remote, &State::load(&self.profile.id)?);
+        if self.profile.protected && self.direction != Direction::Push && !plan.push.is_empty() {
+            self.warn(Message::new(
+                "warning.count_new_changed_files_werent_copied",
+                &[("count", &plan.push.len()), ("name", &self.profile.name)],
+            ));
+            plan.push.clear();
+        }
+        plan.bytes = size(local, &plan.push) + size(remote, &plan.pull);
+        Ok(plan)
+    }
+
+    /// What a two-way sync would copy each way, without copying anything.
+    pub(super) async fn two_way_plan(&self) -> Result<TwoWayPlan, String> {
+        let (local, remote) = self.two_way_trees().await?;
+        self.reconcile(&local, &remote)
+    }
+
+    /// Copy each side's new and changed uploads to the other, report the
+    /// conflicts, and record what now matches for the next two-way sync.
+    pub(super) async fn sync_both_ways(&self, on_progress: OnProgress<'_>) -> Result<(), String> {
+        let (local, remote) = self.two_way_trees().await?;
+        let plan = self.reconcile(&local, &remote)?;
+        for (direction, paths) in [(Direction::Push, &plan.push), (Direction::Pull, &plan.pull)] {
+            if !paths.is_empty() {
+                self.copy_only(direction, paths, &mut *on_progress).await?;
+            }
+        }
+        if !plan.conflicts.is_empty() {
+            let shown: Vec<&str> = plan.conflicts.iter().take(10).map(String::as_str).collect();
+            let more = plan.conflicts.len() - shown.len();
+            self.warn(Message::new(
+                "warning.count_uploads_changed_both_sides_were",
+                &[
+                    ("count", &plan.conflicts.len()),
+                    ("shown", &shown.join(", ")),
+                    (
+                        "value2",
+                        &if more > 0 {
+                            format!(" and {more} more")
+                        } else {
+                            String::new()
+                        },
+                    ),
+                ],
+            ));
+            self.conflicts.lock().unwrap().extend(plan.conflicts);
+        }
+        let (local, remote) = self.two_way_trees().await?;
+        State::matching(&local, &remote).save(&self.profile.id)
+    }
+
+    /// Copy just the files at `paths` in `direction`, by mirroring with every
+    /// other path excluded.
+    pub(super) async fn copy_only(
+        &self,
+        direction: Direction,
+        paths: &[String],
+        on_progress: OnProgress<'_>,
+    ) -> Result<(), String> {
+        let (source, dest) = match direction {
+            Direction::Push => (self.local(), self.remote()),
+            Direction::Pull => (self.remote(), self.local()),
+        };
+        let excludes = self.only(paths)?;
+        let options = MirrorOptions {
+            throttle: &self.throttle,
+            checksums: None,
+        };
+        let mirrored = self
+            .transfer
+            .mirror(
+                direction,
+                &self.files_root(&source),
+                &self.files_root(&dest),
+                &excludes,
+                &options,
+                on_progress,
+            )
+            .await?;
+        self.record_file_changes(direction, &mirrored.changes);
+        if direction == Direction::Push {
+            self.apply_permissions(&mirrored.copied).await;
+        }
+        Ok(())
+    }
+
+    /// Excludes that leave just the files at `paths`, and never transferred
+    /// ones still excluded.
+    pub(super) fn only(&self, paths: &[String]) -> Result<Excludes, String> {
+        let mut rules = exclude_rules(self.profile, self.components, false);
+        let paths: Vec<String> = paths.iter().map(|path| literal(path)).collect();
+        rules.extend(scope::root_rules(&paths));
+        Excludes::new(&rules)
+    }
+}
@@ -0,0 +1,80 @@
+//! Keeping copies of a site that aren't production out of search engines.
+//!
+//! This machine's site is never production, and the server's isn't when the
+//! profile marks it `non_production`. After a sync into one, `blog_public` is
+//! set to 0, WordPress's "discourage search engines" setting, since the
+//! database just imported has production's 1; and as the profile asks, a
+//! `robots.txt` turning every crawler away is written, for crawlers and
+//! servers that don't go by WordPress's own. An option override for
+//! `blog_public` is applied afterwards, so it has the last word.
+
+use super::endpoint::{self, Endpoint};
+use super::{Direction, Job};
+
+use crate::messages::{self, Message};
+
+/// The `robots.txt` of a site that isn't production.
+const ROBOTS_TXT: &str = "User-agent: *\nDisallow: /\n";
+
+impl Job<'_> {
+    /// Whether the destination isn't production.
+    fn non_production_destination(&self) -> bool {
+        match self.direction {
+            Direction::Pull => true,
+            Direction::Push => self.profile.non_production,
+        }
+    }
+
+    /// Discourage search engines from the destination, if it isn't
+    /// production. A site left indexable still works, so failures are
+    /// warnings.
+    pub(super) async fn hide_from_search_engines(&self, dest: &Endpoint<'_>) {
+        if !self.non_production_destination() {
+            return;
+        }
+        let settings = &self.profile.post_sync;
+        // The setting is in the database the sync just replaced.
+        if settings.discourage_search_engines && self.syncs_database() {
+            let discouraged = match self.has_wp_cli(dest).await.unwrap_or(true) {
+                true => dest
+                    .wp(&["option", "update", "blog_public", "0"])
+                    .await
+                    .map(drop),
+                false => Err(messages::text("error.has_no_wp_cli", &[])),
+            };
+            if let Err(e) = discouraged {
+                self.warn(Message::new(
+                    "warning.search_engines_werent_discouraged_destination",
+                    &[("error", &e)],
+                ));
+            }
+        }
+        if settings.robots_txt {
+            if let Err(e) = write_robots_txt(dest).await {
+                self.warn(Message::new(
+                    "warning.destinations_robots_txt_wasnt_written",
+                    &[("error", &e)],
+                ));
+            }
+        }
+    }
+}
+
+/// Write [`ROBOTS_TXT`] to `dest`'s WordPress root.
+async fn write_robots_txt(dest: &Endpoint<'_>) -> Result<(), String> {
+    match dest {
+        Endpoint::Local(_) => {
+            let path = dest.path("robots.txt");
+            tokio::fs::write(&path, ROBOTS_TXT).await.map_err(|e| {
+                messages::text("error.failed.write_path", &[("path", &path), ("error", &e)])
+            })
+        }
+        Endpoint::Remote { env, .. } => {
+            let line = endpoint::remote_sh_line(env, "cat > robots.txt", &[]);
+            dest.session()?
+                .exec_with_input(&line, ROBOTS_TXT.as_bytes())
+                .await
+                .map(drop)
+        }
+    }
+}
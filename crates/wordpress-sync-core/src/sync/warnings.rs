@@ -0,0 +1,143 @@
+//! The profile's warning policy, applied as a sync runs.
+//!
+//! Each kind of warning in [`WarningPolicy`](crate::profile::WarningPolicy)
+//! is passed over, added to the job's warnings, or turned into a
+//! [`SyncError::WarningBlocked`] that stops the sync. The ones that can be
+//! known in advance are looked for once the job has connected, before either
+//! side changes; a missing rsync on the server is dealt with while
+//! connecting.
+//!
+//! A large deletion the policy blocks on, such as a mistyped path emptying
+//! the destination's uploads, can still go ahead when the user has seen it in
+//! the plan and says so for that one sync.
+
+use serde::Serialize;
+
+use super::transfer::{Entry, FileChanges};
+use super::{Direction, Job};
+use crate::error::SyncError;
+use crate::messages::Message;
+use crate::profile::WarningAction;
+
+/// More files deleted from the destination than the profile's warning
+/// policy lets a sync delete without a word.
+#[derive(Debug, Clone, Serialize)]
+pub struct LargeDeletion {
+    pub deleted: u64,
+    /// The files on the destination now, of those the sync mirrors; `None`
+    /// when the policy goes by the count alone.
+    pub destination_files: Option<u64>,
+    /// Whether the sync stops for it unless told to go ahead, rather than
+    /// only warning.
+    pub needs_confirmation: bool,
+}
+
+impl LargeDeletion {
+    pub fn warning(&self, direction: Direction) -> Message {
+        let id = match (direction, self.destination_files) {
+            (Direction::Push, Some(_)) => "warning.large_deletion_of_server_files",
+            (Direction::Pull, Some(_)) => "warning.large_deletion_of_local_files",
+            (Direction::Push, None) => "warning.large_deletion_from_server",
+            (Direction::Pull, None) => "warning.large_deletion_from_local",
+        };
+        match self.destination_files {
+            Some(files) => Message::new(id, &[("deleted", &self.deleted), ("files", &files)]),
+            None => Message::new(id, &[("deleted", &self.deleted)]),
+        }
+    }
+}
+
+impl Job<'_> {
+    /// Pass over `warning`, report it, or fail with it, as `action` says.
+    pub(super) fn heed(&self, action: WarningAction, warning: Message) -> Result<(), SyncError> {
+        match action {
+            WarningAction::Ignore => {
+                tracing::info!(%warning, "warning ignored by the profile's policy");
+                Ok(())
+            }
+            WarningAction::Warn => {
+                self.warn(warning);
+                Ok(())
+            }
+            WarningAction::Block => Err(SyncError::warning_blocked(warning)),
+        }
+    }
+
+    /// Look for the warnings the profile's policy doesn't ignore before the
+    /// sync writes anything. Charset mismatches are only looked for here when
+    /// they block; otherwise the search-replace phase reports them.
+    pub(super) async fn check_warnings(&self) -> Result<(), SyncError> {
+        let policy = &self.profile.warnings;
+        // A sync between servers brings the dump's collations over as they are.
+        if policy.charset_mismatch == WarningAction::Block
+            && self.syncs_database()
+            && self.from_server.is_none()
+        {
+            let collations = self.collations().await.map_err(SyncError::database)?;
+            if let Some(warning) = collations.warnings.into_iter().next() {
+                return Err(SyncError::WarningBlocked(warning));
+            }
+        }
+        if policy.drift != WarningAction::Ignore && self.direction == Direction::Push {
+            if let Some(drift) = self.drift().await.map_err(SyncError::remote_command)? {
+                self.heed(policy.drift, drift.warning())?;
+            }
+        }
+        // A two-way sync deletes nothing. A sync between servers has worked
+        // out what its files change already, whichever route they take.
+        if policy.large_deletion != WarningAction::Ignore
+            && (self.transfers_files() || self.server_changes.get().is_some())
+            && !self.two_way()
+        {
+            let changes = self.planned_changes().await.map_err(SyncError::transfer)?;
+            let large = self
+                .large_deletion(&changes)
+                .await
+                .map_err(SyncError::transfer)?;
+            if let Some(large) = large {
+                let action = match large.needs_confirmation && self.allow_large_deletion {
+                    true => WarningAction::Warn,
+                    false => policy.large_deletion,
+                };
+                self.heed(action, large.warning(self.direction))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// What `changes` delete, if it is more than the profile's warning policy
+    /// lets a sync delete without a word.
+    pub(super) async fn large_deletion(
+        &self,
+        changes: &FileChanges,
+    ) -> Result<Option<LargeDeletion>, String> {
+        let policy = &self.profile.warnings;
+        let deleted = changes.deleted.len() as u64;
+        if policy.large_deletion == WarningAction::Ignore || deleted == 0 {
+            return Ok(None);
+        }
+        let destination_files = match policy.large_deletion_percent {
+            Some(_) => {
+                let excludes = self.excludes().await?;
+                let root = self.files_root(&self.destination());
+                let listing = self.transfer.list(&root, &excludes).await?;
+                let files = listing
+                    .values()
+                    .filter(|entry| matches!(entry, Entry::File { .. }))
+                    .count();
+                Some(files as u64)
+            }
+            None => None,
+        };
+        let too_many = deleted > policy.large_deletion_threshold;
+        let too_much = match (policy.large_deletion_percent, destination_files) {
+            (Some(percent), Some(files)) => deleted * 100 > u64::from(percent) * files,
+            _ => false,
+        };
+        Ok((too_many || too_much).then_some(LargeDeletion {
+            deleted,
+            destination_files,
+            needs_confirmation: policy.large_deletion == WarningAction::Block,
+        }))
+    }
+}
@@ -0,0 +1,250 @@
+//! Watch mode: pushing theme and plugin files to the server as they are saved.
+//!
+//! For live development against a staging server. The local themes and
+//! plugins directories are watched, and once a burst of changes has been
+//! quiet for [`DEBOUNCE`], the files it touched are pushed, or deleted on the
+//! server if they were deleted here, under the profile's exclude rules. The
+//! watch holds the profile's lock, so no other sync of it runs meanwhile, and
+//! protected servers are never watched.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use notify::{EventKind, RecursiveMode, Watcher};
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
+
+use super::progress::Reporter;
+use super::transfer::{Excludes, TransferStats};
+use super::{connect, local_path, lock, Components, Direction, Job, ProgressSink};
+use crate::error::SyncError;
+use crate::messages::Message;
+use crate::profile::{Profile, TransferMethod};
+use crate::telemetry::{self, Feature};
+
+/// How long the files must go unchanged before what changed is pushed, so
+/// an editor's save, or a build writing many files, goes up as one batch.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// The directories watched, relative to the content directory.
+const WATCHED_DIRS: &[&str] = &["themes", "plugins"];
+
+/// One batch of changes pushed by a watch, or its end.
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchEvent {
+    pub profile_id: String,
+    /// Relative to the directory the profile's file syncs mirror.
+    pub pushed: Vec<String>,
+    /// Why the push, or the watch itself, failed.
+    pub error: Option<String>,
+    /// Whether the watch has stopped.
+    pub stopped: bool,
+    pub at: DateTime<Utc>,
+}
+
+impl WatchEvent {
+    /// The event announcing that the watch of `profile_id` has ended, with `error`.
+    pub fn stopped(profile_id: &str, error: Option<&SyncError>) -> Self {
+        Self {
+            profile_id: profile_id.to_string(),
+            pushed: Vec::new(),
+            error: error.map(ToString::to_string),
+            stopped: true,
+            at: Utc::now(),
+        }
+    }
+}
+
+/// The watches running in this process, by profile id.
+#[derive(Default)]
+pub struct Watches {
+    running: Mutex<HashMap<String, CancellationToken>>,
+}
+
+impl Watches {
+    /// Register a watch of `profile_id`, returning the token that stops it,
+    /// or `None` if the profile is already watched.
+    pub fn start(&self, profile_id: &str) -> Option<CancellationToken> {
+        let mut running = self.running.lock().unwrap();
+        if running.contains_key(profile_id) {
+            return None;
+        }
+        let cancel = CancellationToken::new();
+        running.insert(profile_id.to_string(), cancel.clone());
+        Some(cancel)
+    }
+
+    /// Ask the watch of `profile_id` to stop. Returns false if there is none.
+    pub fn stop(&self, profile_id: &str) -> bool {
+        match self.running.lock().unwrap().get(profile_id) {
+            Some(cancel) => {
+                cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Forget a watch once it has stopped.
+    pub fn finish(&self, profile_id: &str) {
+        self.running.lock().unwrap().remove(profile_id);
+    }
+
+    /// The ids of the profiles being watched.
+    pub fn profile_ids(&self) -> Vec<String> {
+        self.running.lock().unwrap().keys().cloned().collect()
+    }
+}
+
+/// Push `profile`'s theme and plugin files to its server as they change,
+/// until `cancel` is cancelled. Each batch is announced to `sink`.
+pub async fn watch(
+    profile: &Profile,
+    cancel: &CancellationToken,
+    sink: &dyn ProgressSink,
+) -> Result<(), SyncError> {
+    telemetry::record(Feature::Watch);
+    if profile.protected {
+        return Err(SyncError::Config(Message::new(
+            "error.name_protected_so_files_cant_pushed",
+            &[("name", &profile.name)],
+        )));
+    }
+    if profile.remote.transfer == TransferMethod::RestApi {
+        return Err(SyncError::Config(Message::new(
+            "error.profiles_that_sync_over_rest_api",
+            &[],
+        )));
+    }
+    let id = uuid::Uuid::new_v4().to_string();
+    let _lock = lock::ProfileLock::acquire(&profile.id, &id)?;
+    let (remote, transfer, _) = connect(profile, sink).await?;
+    let reporter = Reporter::new(id, sink);
+    let job = Job::new(
+        profile,
+        Direction::Push,
+        Components::Files,
+        remote,
+        transfer,
+        reporter,
+    );
+    let result = job.watch(cancel, sink).await;
+    if let Some(remote) = &job.remote {
+        remote.close().await;
+    }
+    result
+}
+
+impl Job<'_> {
+    async fn watch(
+        &self,
+        cancel: &CancellationToken,
+        sink: &dyn ProgressSink,
+    ) -> Result<(), SyncError> {
+        let local = self.local();
+        let root_path = self.files_root(&local);
+        let root = Path::new(&root_path);
+        let excludes = self.excludes().await.map_err(SyncError::config)?;
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            // The receiver only goes away once the watch is over.
+            let _ = tx.send(event);
+        })
+        .map_err(|e| {
+            SyncError::Config(Message::new(
+                "error.failed.watch_local_files",
+                &[("error", &e)],
+            ))
+        })?;
+        let content = self
+            .profile
+            .local
+            .path
+            .join(self.profile.project.content_dir());
+        for dir in WATCHED_DIRS {
+            let path = content.join(dir);
+            if path.is_dir() {
+                watcher
+                    .watch(&path, RecursiveMode::Recursive)
+                    .map_err(|e| {
+                        SyncError::Config(Message::new(
+                            "error.failed.watch_path",
+                            &[("path", &path.display()), ("error", &e)],
+                        ))
+                    })?;
+            }
+        }
+        tracing::info!(profile = %self.profile.name, "watching for changes");
+        loop {
+            let mut changed = BTreeSet::new();
+            tokio::select! {
+                _ = cancel.cancelled() => return Ok(()),
+                event = rx.recv() => match event {
+                    Some(event) => note_changes(root, &excludes, event, &mut changed),
+                    None => return Ok(()),
+                },
+            }
+            // Keep gathering until the files have been quiet for a moment.
+            loop {
+                tokio::select! {
+                    _ = cancel.cancelled() => return Ok(()),
+                    event = tokio::time::timeout(DEBOUNCE, rx.recv()) => match event {
+                        Ok(Some(event)) => note_changes(root, &excludes, event, &mut changed),
+                        Ok(None) => return Ok(()),
+                        Err(_) => break,
+                    },
+                }
+            }
+            if changed.is_empty() {
+                continue;
+            }
+            let paths: Vec<String> = changed.into_iter().collect();
+            let mut on_progress = |_: &TransferStats| {};
+            let result = tokio::select! {
+                _ = cancel.cancelled() => return Ok(()),
+                result = self.copy_only(Direction::Push, &paths, &mut on_progress) => result,
+            };
+            if let Err(e) = &result {
+                tracing::warn!("watch push failed: {e}");
+            }
+            sink.watch(&WatchEvent {
+                profile_id: self.profile.id.clone(),
+                pushed: paths,
+                error: result.err(),
+                stopped: false,
+                at: Utc::now(),
+            });
+        }
+    }
+}
+
+/// Add the paths `event` touched below `root`, relative to it, to `changed`,
+/// unless they are excluded.
+fn note_changes(
+    root: &Path,
+    excludes: &Excludes,
+    event: notify::Result<notify::Event>,
+    changed: &mut BTreeSet<String>,
+) {
+    let event = match event {
+        Ok(event) => event,
+        Err(e) => {
+            tracing::warn!("file watch error: {e}");
+            return;
+        }
+    };
+    if matches!(event.kind, EventKind::Access(_)) {
+        return;
+    }
+    for path in event.paths {
+        let Some(relative) = local_path::relative(root, &path) else {
+            continue;
+        };
+        if !relative.is_empty() && !excludes.is_excluded(&relative, path.is_dir()) {
+            changed.insert(relative);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Posting to the profile's webhooks once a sync has finished, so a team
+//! hears that staging was refreshed without opening the app.
+
+use std::time::Duration;
+
+use serde_json::json;
+
+use super::http;
+use super::notify::{duration, JobNotification};
+use super::space::size;
+use crate::credentials::{self, SECRET_REFERENCE_PREFIX};
+use crate::history::{text, HistoryEntry, JobStatus};
+use crate::messages;
+use crate::net::AddressFamily;
+use crate::profile::{Profile, Webhook, WebhookKind};
+use crate::proxy;
+
+/// How long one post may take.
+const TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Post the finished job `entry` to each of `profile`'s webhooks that wants
+/// to hear of it. Failures are logged, and the rest still posted to.
+pub(super) async fn send(profile: &Profile, entry: &HistoryEntry) {
+    let webhooks: Vec<&Webhook> = profile
+        .webhooks
+        .iter()
+        .filter(|webhook| match entry.status {
+            JobStatus::Succeeded => webhook.on_success,
+            JobStatus::Failed => webhook.on_failure,
+            JobStatus::Cancelled => false,
+        })
+        .collect();
+    if webhooks.is_empty() {
+        return;
+    }
+    let client = proxy::route(&profile.remote.proxy).and_then(|route| {
+        http::builder(route.as_ref(), AddressFamily::Any)?
+            .timeout(TIMEOUT)
+            .build()
+            .map_err(|e| e.to_string())
+    });
+    let client = match client {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::warn!("webhooks weren't posted: failed to set up HTTP: {e}");
+            return;
+        }
+    };
+    for webhook in webhooks {
+        if let Err(e) = post(&client, webhook, entry).await {
+            tracing::warn!("webhook wasn't posted: {e}");
+        }
+    }
+}
+
+async fn post(
+    client: &reqwest::Client,
+    webhook: &Webhook,
+    entry: &HistoryEntry,
+) -> Result<(), String> {
+    let url = match webhook.url.starts_with(SECRET_REFERENCE_PREFIX) {
+        true => credentials::named_secret(&webhook.url)?,
+        false => webhook.url.clone(),
+    };
+    let message = match &webhook.template {
+        Some(template) => render(template, entry),
+        None => {
+            let notification = JobNotification::of(entry);
+            format!("{}\n{}", notification.title, notification.body)
+        }
+    };
+    let body = match webhook.kind {
+        WebhookKind::Json => json!({ "message": message, "job": entry }),
+        WebhookKind::Slack => json!({ "text": message }),
+        WebhookKind::Discord => json!({ "content": message }),
+    };
+    let response = client.post(&url).json(&body).send().await.map_err(|e| {
+        messages::text(
+            "error.failed.reach_webhook_without_url",
+            &[("without_url", &e.without_url())],
+        )
+    })?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(messages::text(
+            "error.webhook_answered_status",
+            &[("status", &status)],
+        ));
+    }
+    tracing::info!(kind = ?webhook.kind, "webhook posted");
+    Ok(())
+}
+
+/// `template` with the job's details filled in.
+fn render(template: &str, entry: &HistoryEntry) -> String {
+    let seconds = (entry.finished_at - entry.started_at).num_seconds().max(0);
+    let error = entry
+        .error
+        .as_ref()
+        .map(|error| error.message())
+        .unwrap_or_default();
+    [
+        ("{profile}", entry.profile_name.clone()),
+        ("{direction}", text(&entry.direction)),
+        ("{status}", text(&entry.status)),
+        ("{duration}", duration(seconds)),
+        ("{bytes}", size(entry.bytes_transferred)),
+        ("{files}", entry.files_transferred.to_string()),
+        ("{warnings}", entry.warnings.len().to_string()),
+        ("{error}", error),
+        ("{job_id}", entry.job_id.clone()),
+    ]
+    .into_iter()
+    .fold(template.to_string(), |message, (placeholder, value)| {
+        message.replace(placeholder, &value)
+    })
+}
@@ -0,0 +1,236 @@
+//! Checking a WooCommerce store's orders before a sync replaces them, and
+//! putting a copied store's payment gateways into test mode afterwards.
+//!
+//! The gateways' settings are serialized arrays in the options table, patched
+//! key by key with `wp option patch` so the rest of each gateway's settings,
+//! its test keys included, stay as they were. Gateways the store hasn't set
+//! up have no options and are left alone.
+
+use serde_json::{Map, Value};
+
+use super::endpoint::Endpoint;
+use super::mysql::identifier;
+use super::{Direction, Job};
+use crate::messages::{self, Message};
+use crate::profile::PaymentTestMode;
+
+/// A payment gateway's settings option, and what putting it in test mode
+/// changes there.
+struct Gateway {
+    name: &'static str,
+    option: &'static str,
+    /// Settings set to these values.
+    test: &'static [(&'static str, &'static str)],
+    /// Secrets cleared, such as the one live webhooks are verified with.
+    clear: &'static [&'static str],
+}
+
+/// The gateways known to have a test mode, in WooCommerce's own plugins and
+/// the most used others.
+const GATEWAYS: &[Gateway] = &[
+    Gateway {
+        name: "Stripe",
+        option: "woocommerce_stripe_settings",
+        test: &[("testmode", "yes")],
+        clear: &["webhook_secret", "test_webhook_secret"],
+    },
+    Gateway {
+        name: "WooPayments",
+        option: "woocommerce_woocommerce_payments_settings",
+        test: &[("test_mode", "yes")],
+        clear: &[],
+    },
+    Gateway {
+        name: "PayPal Standard",
+        option: "woocommerce_paypal_settings",
+        test: &[("testmode", "yes")],
+        clear: &[],
+    },
+    Gateway {
+        name: "PayPal Checkout",
+        option: "woocommerce_ppec_paypal_settings",
+        test: &[("environment", "sandbox")],
+        clear: &[],
+    },
+    Gateway {
+        name: "Braintree",
+        option: "woocommerce_braintree_credit_card_settings",
+        test: &[("environment", "sandbox")],
+        clear: &[],
+    },
+    Gateway {
+        name: "Square",
+        option: "wc_square_settings",
+        test: &[("enable_sandbox", "yes")],
+        clear: &[],
+    },
+];
+
+impl Job<'_> {
+    /// A warning if the sync would replace orders on the destination newer
+    /// than the source's newest, when the profile's WooCommerce settings ask
+    /// for the check. Orders in WooCommerce's own tables are only compared
+    /// when they are synced; those stored as posts always are.
+    pub(super) async fn newer_orders_warning(&self) -> Result<Option<Message>, String> {
+        let Some(woocommerce) = self.profile.woocommerce.as_ref().filter(|w| w.check_orders) else {
+            return Ok(None);
+        };
+        let (source, dest) = (self.source(), self.destination());
+        if !source.has_shell() || !dest.has_shell() {
+            return Ok(None);
+        }
+        let own_tables = !woocommerce.skip_orders;
+        let Some(newest) = self.newest_order(&dest, own_tables).await? else {
+            return Ok(None);
+        };
+        let source_newest = self.newest_order(&source, own_tables).await?;
+        if source_newest
+            .as_ref()
+            .is_some_and(|source| *source >= newest)
+        {
+            return Ok(None);
+        }
+        Ok(Some(match source_newest {
+            Some(source) => Message::new(
+                "warning.destination_has_newer_orders",
+                &[("newest", &newest), ("source", &source)],
+            ),
+            None => Message::new(
+                "warning.destination_has_orders_source_has_none",
+                &[("newest", &newest)],
+            ),
+        }))
+    }
+
+    /// When `side`'s newest order was placed, from the posts and, with
+    /// `own_tables`, WooCommerce's order table, or `None` if it has none.
+    async fn newest_order(
+        &self,
+        side: &Endpoint<'_>,
+        own_tables: bool,
+    ) -> Result<Option<String>, String> {
+        let mut prefix = self.table_prefix(side).await?;
+        if let Some(id) = self.profile.multisite.subsite.filter(|&id| id > 1) {
+            prefix = format!("{prefix}{id}_");
+        }
+        let tables = self.tables(side).await?;
+        let mut newest = None;
+        let queries = [
+            (true, "posts", "post_date_gmt", "post_type"),
+            (own_tables, "wc_orders", "date_created_gmt", "type"),
+        ];
+        for (wanted, table, date, kind) in queries {
+            let table = format!("{prefix}{table}");
+            if !wanted || !tables.contains(&table) {
+                continue;
+            }
+            let sql = format!(
+                "SELECT MAX({date}) FROM {} WHERE {kind} = 'shop_order'",
+                identifier(&table)
+            );
+            let rows = self.query(side, &sql).await?;
+            let date = rows
+                .into_iter()
+                .flatten()
+                .next()
+                .filter(|date| date != "NULL" && !date.is_empty());
+            // Dates as MySQL writes them sort as text.
+            newest = newest.max(date);
+        }
+        Ok(newest)
+    }
+}
+
+impl Job<'_> {
+    /// Put the destination's payment gateways into test mode, when the
+    /// profile's WooCommerce settings ask for it on this side, and note each
+    /// setting changed. A gateway left live is warned about, loudly, rather
+    /// than failing a sync that is otherwise done.
+    pub(super) async fn enforce_test_mode(&self, dest: &Endpoint<'_>) {
+        let mode = self
+            .profile
+            .woocommerce
+            .as_ref()
+            .map_or(PaymentTestMode::Off, |woocommerce| {
+                woocommerce.payment_test_mode
+            });
+        let applies = match self.direction {
+            Direction::Pull => mode != PaymentTestMode::Off,
+            Direction::Push => mode == PaymentTestMode::Both,
+        };
+        // The gateways' settings are in the database the sync just replaced.
+        if !applies || !self.syncs_database() {
+            return;
+        }
+        if !self.has_wp_cli(dest).await.unwrap_or(true) {
+            self.warn(Message::new(
+                "warning.payment_gateways_not_switched_no_wp_cli",
+                &[],
+            ));
+            return;
+        }
+        for gateway in GATEWAYS {
+            match test_mode(dest, gateway).await {
+                Ok(changes) => self.test_mode_changes.lock().unwrap().extend(changes),
+                Err(e) => self.warn(Message::new(
+                    "warning.name_destination_was_not_switched_test",
+                    &[("name", &gateway.name), ("error", &e)],
+                )),
+            }
+        }
+    }
+}
+
+/// Put `gateway` on `dest` into test mode, returning what was changed; nothing
+/// when it isn't set up there or already is.
+async fn test_mode(dest: &Endpoint<'_>, gateway: &Gateway) -> Result<Vec<String>, String> {
+    let Some(settings) = settings(dest, gateway.option).await? else {
+        return Ok(Vec::new());
+    };
+    let mut changes = Vec::new();
+    for &(key, value) in gateway.test {
+        let action = match settings.get(key) {
+            Some(current) if current.as_str() == Some(value) => continue,
+            Some(_) => "update",
+            None => "insert",
+        };
+        dest.wp(&["option", "patch", action, gateway.option, key, value])
+            .await?;
+        changes.push(format!("{}: {key} set to {value}", gateway.name));
+    }
+    for &key in gateway.clear {
+        let set = settings
+            .get(key)
+            .and_then(Value::as_str)
+            .is_some_and(|secret| !secret.is_empty());
+        if !set {
+            continue;
+        }
+        dest.wp(&["option", "patch", "update", gateway.option, key, ""])
+            .await?;
+        changes.push(format!("{}: {key} cleared", gateway.name));
+    }
+    Ok(changes)
+}
+
+/// The settings array stored in `option` on `dest`, or `None` when there's
+/// no such option.
+async fn settings(dest: &Endpoint<'_>, option: &str) -> Result<Option<Map<String, Value>>, String> {
+    let output = match dest.wp(&["option", "get", option, "--format=json"]).await {
+        Ok(output) => output,
+        // wp-cli's message for an option that isn't there.
+        Err(e) if e.contains("Does it exist?") => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    match serde_json::from_str(output.trim()) {
+        Ok(Value::Object(settings)) => Ok(Some(settings)),
+        Ok(_) => Err(messages::text(
+            "error.option_isnt_settings_array",
+            &[("option", &option)],
+        )),
+        Err(e) => Err(messages::text(
+            "error.unexpected_output_wp_option_get",
+            &[("option", &option), ("error", &e)],
+        )),
+    }
+}
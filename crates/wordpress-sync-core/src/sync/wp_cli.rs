@@ -0,0 +1,136 @@
+//! Installing wp-cli on a server that doesn't have it, for minimal hosts
+//! whose SSH account can run PHP but was never given `wp`.
+//!
+//! The phar is downloaded here, checked against the SHA-512 checksum the
+//! wp-cli project publishes beside it, and uploaded over the SSH connection,
+//! so the server needs no way out to the internet. It goes under the SSH
+//! user's home, and the profile records where so every later command runs it.
+
+use sha2::{Digest, Sha512};
+
+use super::endpoint::Endpoint;
+use super::{connect_ssh, http, ProgressSink};
+use crate::error::SyncError;
+use crate::messages::{self, Message};
+use crate::net::AddressFamily;
+use crate::profile::{self, Profile};
+use crate::proxy::{self, ProxyChoice};
+use crate::shell;
+
+/// The latest stable wp-cli; its checksum is at the same URL plus `.sha512`.
+const PHAR_URL: &str = "https://raw.githubusercontent.com/wp-cli/builds/gh-pages/phar/wp-cli.phar";
+
+/// Where the phar goes, below the SSH user's home: wp-cli's own directory.
+const INSTALL_PATH: &str = ".wp-cli/bin/wp";
+
+/// Install wp-cli under the SSH user's home on `profile`'s server, and save
+/// the profile with its path, returning the saved profile. An untrusted host
+/// key is announced to `sink` just as it is for a real sync.
+pub async fn install_remote_wp_cli(
+    profile: &Profile,
+    sink: &dyn ProgressSink,
+) -> Result<Profile, SyncError> {
+    if !profile.remote.transfer.uses_ssh() {
+        return Err(SyncError::Config(Message::new(
+            "error.no_ssh.wp_cli_install",
+            &[],
+        )));
+    }
+    let phar = download().await?;
+
+    let remote = connect_ssh(profile, sink).await?;
+    let installed = async {
+        // The phar runs with the `php` on the PATH, through its shebang.
+        remote
+            .exec("command -v php")
+            .await
+            .map_err(|_| messages::text("error.server_has_no_php_wp_cli", &[]))?;
+        let home = remote.exec("printf %s \"$HOME\"").await?;
+        if home.is_empty() {
+            return Err(messages::text("error.ssh_user_has_no_home_directory", &[]));
+        }
+        let path = format!("{}/{INSTALL_PATH}", home.trim_end_matches('/'));
+        let dir = path.rsplit_once('/').map_or("", |(dir, _)| dir);
+        let tmp = format!("{path}.tmp");
+        remote
+            .exec_with_input(
+                &format!(
+                    "mkdir -p {dir} && cat > {tmp} && chmod 755 {tmp} && mv {tmp} {path}",
+                    dir = shell::quote(dir),
+                    tmp = shell::quote(&tmp),
+                    path = shell::quote(&path),
+                ),
+                &phar,
+            )
+            .await?;
+
+        let mut env = profile.remote.clone();
+        env.wp_cli = Some(path.clone());
+        let server = Endpoint::Remote {
+            remote: Some(&remote),
+            env: &env,
+        };
+        let version = server.wp(&["cli", "version"]).await?;
+        tracing::info!(%path, version = version.trim(), "installed wp-cli on the server");
+        Ok(path)
+    }
+    .await;
+    remote.close().await;
+    let path = installed.map_err(SyncError::remote_command)?;
+
+    let mut profile = profile.clone();
+    profile.remote.wp_cli = Some(path);
+    profile::save(profile).map_err(SyncError::config)
+}
+
+/// The wp-cli phar, once its checksum matches.
+async fn download() -> Result<Vec<u8>, SyncError> {
+    let route = proxy::route(&ProxyChoice::Global).map_err(SyncError::config)?;
+    let client = http::builder(route.as_ref(), AddressFamily::Any)
+        .map_err(SyncError::config)?
+        .timeout(std::time::Duration::from_secs(300))
+        .build()
+        .map_err(|e| {
+            SyncError::Connection(Message::new("error.failed.set_up_http", &[("error", &e)]))
+        })?;
+    let get = |url: String| {
+        let client = &client;
+        async move {
+            let response = client
+                .get(&url)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| {
+                    SyncError::Connection(Message::new(
+                        "error.failed.download_url",
+                        &[("url", &url), ("error", &e)],
+                    ))
+                })?;
+            response.bytes().await.map_err(|e| {
+                SyncError::Connection(Message::new(
+                    "error.failed.download_url",
+                    &[("url", &url), ("error", &e)],
+                ))
+            })
+        }
+    };
+    let checksum = get(format!("{PHAR_URL}.sha512")).await?;
+    let expected = String::from_utf8_lossy(&checksum)
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let phar = get(PHAR_URL.to_string()).await?;
+    let actual: String = Sha512::digest(&phar)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    if actual != expected {
+        return Err(SyncError::Connection(Message::new(
+            "error.downloaded_wp_cli_doesnt_match_published",
+            &[("expected", &expected), ("actual", &actual)],
+        )));
+    }
+    Ok(phar.to_vec())
+}
@@ -0,0 +1,377 @@
+//! Usage metrics: how often each feature is used and which kinds of failure
+//! come up, counted per day in `~/.wordpress-sync/metrics.sqlite`.
+//!
+//! The counts stay on this machine unless the `usage_metrics` setting is
+//! turned on, and then only whole days' totals are sent, with the app's
+//! version and platform and nothing else: no profile, host, path, install id,
+//! or message. [`local_metrics`] shows the counts and exactly what the next
+//! submission would send, so they can be looked over before it's turned on.
+//! The collector's URL is built in from `WPSYNC_METRICS_URL` in the release
+//! build; a build without it never sends anything.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use chrono::{NaiveDate, TimeDelta, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+use crate::error::SyncError;
+use crate::history::{HistoryEntry, JobStatus};
+use crate::messages::{self, Message};
+use crate::net::AddressFamily;
+use crate::proxy::{self, ProxyChoice};
+use crate::sync::{http, Components, Direction};
+use crate::{paths, settings};
+
+/// Where aggregates are sent.
+const METRICS_URL: Option<&str> = option_env!("WPSYNC_METRICS_URL");
+
+/// The version running.
+const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// How many days of counts are kept, sent or not.
+const KEEP_DAYS: i64 = 90;
+
+/// How often [`submit_periodically`] sends the days that have ended.
+const SUBMIT_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// How long to wait for another writer, such as a scheduled sync's process.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS counts (
+    day TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    name TEXT NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (day, kind, name)
+);
+CREATE TABLE IF NOT EXISTS state (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+";
+
+/// A feature whose use is counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    Push,
+    Pull,
+    /// A sync of the files alone, the uploads alone, or the database alone.
+    FilesOnly,
+    UploadsOnly,
+    DatabaseOnly,
+    DryRun,
+    ReplacementPreview,
+    Rollback,
+    Watch,
+    ScheduledSync,
+    ConnectionTest,
+    SetupWizard,
+    SqlConsole,
+    DatabaseDiff,
+    ServerSync,
+    CloneSite,
+}
+
+impl Feature {
+    fn name(self) -> &'static str {
+        match self {
+            Feature::Push => "push",
+            Feature::Pull => "pull",
+            Feature::FilesOnly => "files_only",
+            Feature::UploadsOnly => "uploads_only",
+            Feature::DatabaseOnly => "database_only",
+            Feature::DryRun => "dry_run",
+            Feature::ReplacementPreview => "replacement_preview",
+            Feature::Rollback => "rollback",
+            Feature::Watch => "watch",
+            Feature::ScheduledSync => "scheduled_sync",
+            Feature::ConnectionTest => "connection_test",
+            Feature::SetupWizard => "setup_wizard",
+            Feature::SqlConsole => "sql_console",
+            Feature::DatabaseDiff => "database_diff",
+            Feature::ServerSync => "server_sync",
+            Feature::CloneSite => "clone_site",
+        }
+    }
+}
+
+/// One day's counts.
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyMetrics {
+    pub day: NaiveDate,
+    /// Uses of each feature, by name.
+    pub features: BTreeMap<String, u64>,
+    /// Failures of each kind, by [`SyncError`] code.
+    pub failures: BTreeMap<String, u64>,
+    /// Whether the day's counts have been sent.
+    pub submitted: bool,
+}
+
+/// What a submission sends: the totals of the days since the last one.
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricsSubmission {
+    pub app_version: String,
+    /// The OS and architecture, such as `macos-aarch64`.
+    pub platform: String,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub features: BTreeMap<String, u64>,
+    pub failures: BTreeMap<String, u64>,
+}
+
+/// Everything kept, for the user to look over.
+#[derive(Debug, Clone, Serialize)]
+pub struct LocalMetrics {
+    /// Whether the `usage_metrics` setting lets the totals be sent.
+    pub enabled: bool,
+    /// Whether this build has anywhere to send them.
+    pub collector: bool,
+    /// The days counted, newest first.
+    pub days: Vec<DailyMetrics>,
+    /// What the next submission would send, if any day that's ended hasn't
+    /// been sent yet.
+    pub next_submission: Option<MetricsSubmission>,
+}
+
+/// Count a use of `feature` today. A count that can't be written is left out.
+pub fn record(feature: Feature) {
+    count("feature", feature.name());
+}
+
+/// Count the finished job `entry`: its direction, its components, and the
+/// kind of failure it ended in, if it failed.
+pub fn record_job(entry: &HistoryEntry) {
+    record(match entry.direction {
+        Direction::Push => Feature::Push,
+        Direction::Pull => Feature::Pull,
+    });
+    match entry.components {
+        Components::All => {}
+        Components::Files => record(Feature::FilesOnly),
+        Components::UploadsOnly => record(Feature::UploadsOnly),
+        Components::Database => record(Feature::DatabaseOnly),
+    }
+    if let (JobStatus::Failed, Some(error)) = (entry.status, &entry.error) {
+        record_failure(error);
+    }
+}
+
+/// Count a failure of `error`'s kind today.
+pub fn record_failure(error: &SyncError) {
+    count("failure", error.code());
+}
+
+/// The counts kept, and what the next submission would send.
+pub fn local_metrics() -> Result<LocalMetrics, String> {
+    let conn = open()?;
+    let failed =
+        |e: rusqlite::Error| messages::text("error.failed.read_usage_metrics", &[("error", &e)]);
+    let submitted = submitted_through(&conn)?;
+    let mut days: BTreeMap<NaiveDate, DailyMetrics> = BTreeMap::new();
+    let mut stmt = conn
+        .prepare("SELECT day, kind, name, count FROM counts")
+        .map_err(failed)?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, NaiveDate>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, i64>(3)?,
+            ))
+        })
+        .map_err(failed)?;
+    for row in rows {
+        let (day, kind, name, count) = row.map_err(failed)?;
+        let metrics = days.entry(day).or_insert_with(|| DailyMetrics {
+            day,
+            features: BTreeMap::new(),
+            failures: BTreeMap::new(),
+            submitted: submitted.is_some_and(|through| day <= through),
+        });
+        let counts = match kind.as_str() {
+            "failure" => &mut metrics.failures,
+            _ => &mut metrics.features,
+        };
+        counts.insert(name, count.max(0) as u64);
+    }
+    let days: Vec<DailyMetrics> = days.into_values().rev().collect();
+    Ok(LocalMetrics {
+        enabled: settings::usage_metrics(),
+        collector: METRICS_URL.is_some(),
+        next_submission: pending(&days),
+        days,
+    })
+}
+
+/// Forget every count, sent or not.
+pub fn clear_metrics() -> Result<(), String> {
+    open()?
+        .execute_batch("DELETE FROM counts;")
+        .map_err(|e| messages::text("error.failed.clear_usage_metrics", &[("error", &e)]))
+}
+
+/// Send the totals of the days that have ended since the last submission,
+/// if the `usage_metrics` setting allows it. Returns what was sent, or
+/// `None` if there was nothing to.
+pub async fn submit_metrics() -> Result<Option<MetricsSubmission>, SyncError> {
+    if !settings::usage_metrics() {
+        return Err(SyncError::Config(Message::new(
+            "error.usage_metrics_are_only_sent_once",
+            &[],
+        )));
+    }
+    let Some(url) = METRICS_URL else {
+        return Err(SyncError::Config(Message::new(
+            "error.this_build_app_doesnt_send_usage",
+            &[],
+        )));
+    };
+    let metrics = tokio::task::spawn_blocking(local_metrics)
+        .await
+        .map_err(|e| SyncError::config(e.to_string()))?
+        .map_err(SyncError::config)?;
+    let Some(submission) = metrics.next_submission else {
+        return Ok(None);
+    };
+    let route = proxy::route(&ProxyChoice::Global).map_err(SyncError::config)?;
+    let client = http::builder(route.as_ref(), AddressFamily::Any)
+        .map_err(SyncError::config)?
+        .timeout(Duration::from_secs(30))
+        .build()
+        .map_err(|e| {
+            SyncError::Connection(Message::new("error.failed.set_up_http", &[("error", &e)]))
+        })?;
+    client
+        .post(url)
+        .json(&submission)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| {
+            SyncError::Connection(Message::new(
+                "error.failed.send_usage_metrics_url",
+                &[("url", &url), ("error", &e)],
+            ))
+        })?;
+    let through = submission.to;
+    tokio::task::spawn_blocking(move || mark_submitted(through))
+        .await
+        .map_err(|e| SyncError::config(e.to_string()))?
+        .map_err(SyncError::config)?;
+    tracing::info!(from = %submission.from, to = %submission.to, "sent usage metrics");
+    Ok(Some(submission))
+}
+
+/// Send the ended days' totals now and then every [`SUBMIT_INTERVAL`], for as
+/// long as the app runs, while the setting is on. Failures wait for the next round.
+pub async fn submit_periodically() {
+    let mut interval = tokio::time::interval(SUBMIT_INTERVAL);
+    loop {
+        interval.tick().await;
+        if METRICS_URL.is_none() || !settings::usage_metrics() {
+            continue;
+        }
+        if let Err(e) = submit_metrics().await {
+            tracing::debug!(error = %e, "usage metrics weren't sent");
+        }
+    }
+}
+
+/// The totals of the unsent days in `days` that have ended.
+fn pending(days: &[DailyMetrics]) -> Option<MetricsSubmission> {
+    let today = Utc::now().date_naive();
+    let unsent: Vec<&DailyMetrics> = days
+        .iter()
+        .filter(|metrics| !metrics.submitted && metrics.day < today)
+        .collect();
+    let from = unsent.iter().map(|metrics| metrics.day).min()?;
+    let to = unsent.iter().map(|metrics| metrics.day).max()?;
+    let mut features = BTreeMap::new();
+    let mut failures = BTreeMap::new();
+    for metrics in unsent {
+        for (totals, counts) in [
+            (&mut features, &metrics.features),
+            (&mut failures, &metrics.failures),
+        ] {
+            for (name, count) in counts {
+                *totals.entry(name.clone()).or_insert(0) += count;
+            }
+        }
+    }
+    Some(MetricsSubmission {
+        app_version: CURRENT_VERSION.to_string(),
+        platform: format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH),
+        from,
+        to,
+        features,
+        failures,
+    })
+}
+
+fn count(kind: &str, name: &str) {
+    let today = Utc::now().date_naive();
+    let result = open().and_then(|conn| {
+        conn.execute(
+            "INSERT INTO counts (day, kind, name, count) VALUES (?1, ?2, ?3, 1)
+             ON CONFLICT (day, kind, name) DO UPDATE SET count = count + 1",
+            params![today, kind, name],
+        )
+        .and_then(|_| {
+            conn.execute(
+                "DELETE FROM counts WHERE day < ?1",
+                params![today - TimeDelta::days(KEEP_DAYS)],
+            )
+        })
+        .map_err(|e| messages::text("error.failed.count_name", &[("name", &name), ("error", &e)]))
+    });
+    if let Err(e) = result {
+        tracing::debug!(error = %e, "usage metric not recorded");
+    }
+}
+
+fn submitted_through(conn: &Connection) -> Result<Option<NaiveDate>, String> {
+    conn.query_row(
+        "SELECT value FROM state WHERE key = 'submitted_through'",
+        [],
+        |row| row.get::<_, NaiveDate>(0),
+    )
+    .optional()
+    .map_err(|e| messages::text("error.failed.read_usage_metrics", &[("error", &e)]))
+}
+
+fn mark_submitted(through: NaiveDate) -> Result<(), String> {
+    open()?
+        .execute(
+            "INSERT OR REPLACE INTO state (key, value) VALUES ('submitted_through', ?1)",
+            params![through],
+        )
+        .map(|_| ())
+        .map_err(|e| messages::text("error.failed.note_usage_metrics_sent", &[("error", &e)]))
+}
+
+fn open() -> Result<Connection, String> {
+    let path = paths::metrics_db()?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| {
+            messages::text(
+                "error.failed.create_path",
+                &[("path", &dir.display()), ("error", &e)],
+            )
+        })?;
+    }
+    let opened = |e: rusqlite::Error| {
+        messages::text(
+            "error.failed.open_path",
+            &[("path", &path.display()), ("error", &e)],
+        )
+    };
+    let conn = Connection::open(&path).map_err(opened)?;
+    conn.busy_timeout(BUSY_TIMEOUT).map_err(opened)?;
+    conn.execute_batch(SCHEMA).map_err(opened)?;
+    Ok(conn)
+}
@@ -0,0 +1,194 @@
+//! Updates to the app, from the release channel it follows.
+//!
+//! Tauri's updater fetches a channel's manifest, checks the new version's
+//! signature against the release key, and installs it. This module keeps
+//! what the updater doesn't know about: the channel the app follows, `stable`
+//! or `beta`, and this install's place in a staged rollout.
+//!
+//! The updater's endpoints, with its key in the app's `tauri.conf.json`, name
+//! the channel as `{{channel}}`, e.g. `https://example.com/{{channel}}.json`.
+//! While a release is still rolling out, its manifest's `rollout` is the
+//! percentage of installs offered it. The development configuration has
+//! neither endpoints nor key, which release builds fill in, so a build of it
+//! doesn't look for updates.
+
+use std::fs;
+use std::io;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::messages;
+use crate::paths;
+use crate::proxy::{self, ProxyChoice};
+
+/// How an endpoint names the channel.
+const CHANNEL_VARIABLE: &str = "{{channel}}";
+
+/// Which releases the app is offered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    #[default]
+    Stable,
+    /// Releases before they reach `stable`, for those willing to try them.
+    Beta,
+}
+
+impl Channel {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Stable => "stable",
+            Self::Beta => "beta",
+        }
+    }
+}
+
+/// A newer version than the one running, offered on the app's channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AvailableUpdate {
+    pub version: String,
+    pub channel: Channel,
+    /// What changed, as the release describes it.
+    pub notes: Option<String>,
+    pub published_at: Option<DateTime<Utc>>,
+}
+
+impl AvailableUpdate {
+    /// The update to `version` on `channel`, as the updater found it in the
+    /// channel's `manifest`.
+    pub fn new(version: &str, channel: Channel, manifest: &serde_json::Value) -> Self {
+        Self {
+            version: version.to_string(),
+            channel,
+            notes: manifest["notes"].as_str().map(str::to_string),
+            published_at: manifest["pub_date"]
+                .as_str()
+                .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+                .map(|date| date.with_timezone(&Utc)),
+        }
+    }
+}
+
+/// The update settings file.
+#[derive(Serialize, Deserialize)]
+struct Settings {
+    #[serde(default)]
+    channel: Channel,
+    /// Picks this install's place in each staged rollout.
+    install_id: String,
+}
+
+/// The channel the app follows.
+pub fn channel() -> Result<Channel, String> {
+    Ok(settings()?.channel)
+}
+
+/// Follow `channel` from now on.
+pub fn set_channel(channel: Channel) -> Result<(), String> {
+    let mut settings = settings()?;
+    settings.channel = channel;
+    write(&settings)
+}
+
+/// The updater's configured `endpoints` for `channel`, or the error to show
+/// if the build has none to look for updates at, or no key to check them with.
+pub fn endpoints(
+    endpoints: &[String],
+    pubkey: &str,
+    channel: Channel,
+) -> Result<Vec<url::Url>, String> {
+    if endpoints.is_empty() || pubkey.trim().is_empty() {
+        return Err(messages::text(
+            "error.this_build_app_doesnt_look_updates",
+            &[],
+        ));
+    }
+    endpoints
+        .iter()
+        .map(|endpoint| {
+            let endpoint = endpoint
+                .replace(CHANNEL_VARIABLE, channel.name())
+                .replace("%7B%7Bchannel%7D%7D", channel.name());
+            url::Url::parse(&endpoint).map_err(|e| {
+                messages::text(
+                    "error.invalid_update_endpoint",
+                    &[("url", &endpoint), ("error", &e)],
+                )
+            })
+        })
+        .collect()
+}
+
+/// The proxy to look for updates at `endpoint` through, as the app's proxy
+/// setting says, or `None` to reach it directly.
+pub fn proxy(endpoint: &url::Url) -> Result<Option<url::Url>, String> {
+    let Some(route) = proxy::route(&ProxyChoice::Global)? else {
+        return Ok(None);
+    };
+    match endpoint.host_str() {
+        Some(host) if route.proxy.bypasses(host) => Ok(None),
+        _ => route.url().map(Some),
+    }
+}
+
+/// Whether `version`, offered to the `rollout` percent of installs its
+/// manifest gives, if it gives one, has reached this install.
+pub fn rolled_out(version: &str, rollout: Option<u64>) -> Result<bool, String> {
+    let Some(rollout) = rollout else {
+        return Ok(true);
+    };
+    let offered = u64::from(rollout_bucket(&settings()?.install_id, version)) < rollout;
+    if !offered {
+        tracing::debug!(version, rollout, "update not rolled out here yet");
+    }
+    Ok(offered)
+}
+
+/// This install's place in a staged rollout of `version`, from 0 to 99: it's
+/// offered the version once the rollout's percentage passes it. Each version
+/// shuffles the installs anew, so the same ones aren't always first.
+fn rollout_bucket(install_id: &str, version: &str) -> u16 {
+    let hash = Sha256::digest(format!("{install_id}:{version}"));
+    u16::from_be_bytes([hash[0], hash[1]]) % 100
+}
+
+/// The settings file, made with a new install id the first time it's read.
+fn settings() -> Result<Settings, String> {
+    let path = paths::updates_file()?;
+    match fs::read_to_string(&path) {
+        Ok(raw) => serde_json::from_str(&raw).map_err(|e| {
+            messages::text(
+                "error.invalid_update_settings_path",
+                &[("path", &path.display()), ("error", &e)],
+            )
+        }),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let settings = Settings {
+                channel: Channel::default(),
+                install_id: uuid::Uuid::new_v4().to_string(),
+            };
+            write(&settings)?;
+            Ok(settings)
+        }
+        Err(e) => Err(messages::text(
+            "error.failed.read_path",
+            &[("path", &path.display()), ("error", &e)],
+        )),
+    }
+}
+
+fn write(settings: &Settings) -> Result<(), String> {
+    let path = paths::updates_file()?;
+    let json = serde_json::to_string_pretty(settings)
+        .map_err(|e| messages::text("error.failed.serialize_update_settings", &[("error", &e)]))?;
+    let dir = paths::config_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| {
+        messages::text(
+            "error.failed.create_path",
+            &[("path", &dir.display()), ("error", &e)],
+        )
+    })?;
+    paths::atomic_write(&path, json)
+}
@@ -0,0 +1,609 @@
+//! `wpsync`: the desktop app's syncs from a terminal or CI job.
+//!
+//! It reads the same profiles (`~/.wordpress-sync/profiles`), records the same
+//! history, and runs the same engine as the app. Progress goes to stderr; with
+//! `--json` the result, or the error, is printed to stdout as one JSON document.
+//!
+//! Exit codes: 0 on success, 1 if the sync failed, 2 for bad arguments, and 130
+//! if it was interrupted.
+
+use std::io::{IsTerminal, Write};
+use std::process::ExitCode;
+use std::sync::Mutex;
+
+use clap::{Args, Parser, Subcommand};
+use serde::Serialize;
+use wordpress_sync_core::error::SyncError;
+use wordpress_sync_core::messages::Message;
+use wordpress_sync_core::profile::{self, Profile};
+use wordpress_sync_core::ssh::{self, AuthPrompt, UnverifiedHostKey};
+use wordpress_sync_core::support;
+use wordpress_sync_core::sync::{
+    self, Components, Direction, JobHandle, JobRegistry, Phase, ProgressSink, PushConfirmations,
+    ReportFormat, SyncProgress, SyncRetry, SyncSummary,
+};
+use wordpress_sync_core::{byte_path, logging, BRIDGE_FLAG, SCHEDULED_SYNC_FLAG};
+
+#[derive(Parser)]
+#[command(
+    name = "wpsync",
+    version,
+    about = "Sync WordPress installs using your WordPress Sync profiles"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Push or pull a profile.
+    Run(SyncArgs),
+    /// Show what a push or pull would change, without changing anything.
+    Plan(SyncArgs),
+    /// Show how much a push or pull would move and roughly how long it would take.
+    Estimate(SyncArgs),
+    /// Continue a failed or interrupted sync from its last finished phase.
+    Resume {
+        /// The job id the sync printed or recorded in the history.
+        job_id: String,
+        /// Print the result as JSON on stdout.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print a report of a finished sync to share, as Markdown or HTML.
+    Report {
+        /// The job id the sync printed or recorded in the history.
+        job_id: String,
+        /// markdown or html.
+        #[arg(long, default_value = "markdown", value_parser = parse_format)]
+        format: ReportFormat,
+    },
+    /// Check this machine's setup: rsync, ssh, the credential store, disk
+    /// space, the app data directory, the servers, and the config files.
+    Doctor {
+        /// Print the checks as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Write a support bundle, a zip of the doctor checks, the profiles, and
+    /// the recent logs with their secrets redacted, to attach to a bug report.
+    Bundle {
+        /// Where to write the zip.
+        #[arg(long, short, default_value = "wpsync-support.zip")]
+        output: std::path::PathBuf,
+    },
+    /// List the saved profiles.
+    Profiles {
+        /// Print the profiles as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+#[derive(Args)]
+struct SyncArgs {
+    /// The profile's id or name.
+    #[arg(long)]
+    profile: String,
+    /// Copy the local install to the server.
+    #[arg(long, conflicts_with = "pull", required_unless_present = "pull")]
+    push: bool,
+    /// Copy the server's install to this machine.
+    #[arg(long)]
+    pull: bool,
+    /// What to sync: all, files, uploads_only, or database.
+    #[arg(long, default_value = "all", value_parser = parse_components)]
+    components: Components,
+    /// The profile's name, confirming a push to a protected profile.
+    #[arg(long, value_name = "NAME")]
+    confirm: Option<String>,
+    /// Sync even in one of the profile's blackout windows.
+    #[arg(long)]
+    ignore_blackout: bool,
+    /// Delete more files than the profile's warning policy allows.
+    #[arg(long)]
+    allow_large_deletion: bool,
+    /// Print the result as JSON on stdout.
+    #[arg(long)]
+    json: bool,
+}
+
+impl SyncArgs {
+    fn direction(&self) -> Direction {
+        if self.push {
+            Direction::Push
+        } else {
+            Direction::Pull
+        }
+    }
+}
+
+fn parse_components(value: &str) -> Result<Components, String> {
+    serde_json::from_value(serde_json::Value::String(value.to_string()))
+        .map_err(|_| "expected all, files, uploads_only, or database".to_string())
+}
+
+fn parse_format(value: &str) -> Result<ReportFormat, String> {
+    serde_json::from_value(serde_json::Value::String(value.to_string()))
+        .map_err(|_| "expected markdown or html".to_string())
+}
+
+fn main() -> ExitCode {
+    // The engine starts this binary as rsync's remote shell and from the OS
+    // scheduler, just as it does the app's.
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some(BRIDGE_FLAG) => return exit_code(wordpress_sync_core::run_bridge_client(&args[2..])),
+        Some(SCHEDULED_SYNC_FLAG) => {
+            return exit_code(wordpress_sync_core::run_scheduled_sync(&args[2..]))
+        }
+        _ => {}
+    }
+
+    let cli = Cli::parse();
+    logging::init();
+    // What a crashed run left behind.
+    sync::sweep_stale_state();
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("wpsync: failed to start the runtime: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    runtime.block_on(async {
+        match cli.command {
+            Command::Run(args) => run(&args).await,
+            Command::Plan(args) => plan(&args).await,
+            Command::Estimate(args) => estimate(&args).await,
+            Command::Resume { job_id, json } => resume(&job_id, json).await,
+            Command::Report { job_id, format } => report(&job_id, format),
+            Command::Doctor { json } => doctor(json).await,
+            Command::Bundle { output } => bundle(&output).await,
+            Command::Profiles { json } => profiles(json),
+        }
+    })
+}
+
+fn exit_code(code: i32) -> ExitCode {
+    ExitCode::from(u8::try_from(code).unwrap_or(1))
+}
+
+async fn run(args: &SyncArgs) -> ExitCode {
+    let profile = match find_profile(&args.profile) {
+        Ok(profile) => profile,
+        Err(e) => return fail(args.json, &e),
+    };
+    if let Err(e) = confirm(&profile, args) {
+        return fail(args.json, &e);
+    }
+    if !args.ignore_blackout {
+        if let Err(e) = sync::check_blackouts(&profile) {
+            return fail(args.json, &e);
+        }
+    }
+    let jobs = JobRegistry::default();
+    let mut handle = jobs.start();
+    handle.allow_large_deletion = args.allow_large_deletion;
+    cancel_on_ctrl_c(&handle);
+    let sink = Terminal::default();
+    let result = sync::run(&profile, args.direction(), args.components, &handle, &sink).await;
+    if matches!(&result, Err(e) if !matches!(e, SyncError::Cancelled | SyncError::InProgress { .. }))
+        && !args.json
+    {
+        eprintln!(
+            "To carry on from where it stopped: wpsync resume {}",
+            handle.id
+        );
+    }
+    finish(result, args.json)
+}
+
+async fn resume(job_id: &str, json: bool) -> ExitCode {
+    let jobs = JobRegistry::default();
+    let handle = jobs
+        .start_with_id(job_id)
+        .expect("no other job runs in this process");
+    cancel_on_ctrl_c(&handle);
+    let result = sync::resume(&handle, &Terminal::default()).await;
+    finish(result, json)
+}
+
+/// Check `--confirm` if the sync is a push to a protected profile.
+fn confirm(profile: &Profile, args: &SyncArgs) -> Result<(), SyncError> {
+    let confirmations = PushConfirmations::default();
+    let token = match &args.confirm {
+        Some(typed) => Some(confirmations.issue(profile, typed)?.token),
+        None => None,
+    };
+    confirmations.redeem(profile, args.direction(), token.as_deref())
+}
+
+/// Cancel the job when the user presses Ctrl-C.
+fn cancel_on_ctrl_c(handle: &JobHandle) {
+    let cancel = handle.cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            eprintln!("Cancelling…");
+            cancel.cancel();
+        }
+    });
+}
+
+/// Print the outcome of a sync and pick the exit code.
+fn finish(result: Result<SyncSummary, SyncError>, json: bool) -> ExitCode {
+    let summary = match result {
+        Ok(summary) => summary,
+        Err(e) => return fail(json, &e),
+    };
+    if json {
+        print_json(&summary);
+        return ExitCode::SUCCESS;
+    }
+    for warning in &summary.warnings {
+        eprintln!("warning: {warning}");
+    }
+    let name = profile::load(&summary.profile_id)
+        .map(|profile| profile.name)
+        .unwrap_or_else(|_| summary.profile_id.clone());
+    let seconds = (summary.finished_at - summary.started_at).num_seconds();
+    let files: u64 = summary.phases.iter().map(|p| p.files_transferred).sum();
+    let bytes: u64 = summary.phases.iter().map(|p| p.bytes_transferred).sum();
+    println!(
+        "Synced {name} in {seconds}s: {files} files, {bytes} bytes (job {})",
+        summary.job_id
+    );
+    let usage = &summary.resources;
+    if let (Some(cpu), Some(memory)) = (usage.peak_cpu_percent, usage.peak_memory_bytes) {
+        println!(
+            "Peak use: {cpu:.0}% CPU, {} MiB memory",
+            memory / (1024 * 1024)
+        );
+    }
+    if !summary.test_mode_changes.is_empty() {
+        println!("Payment gateways switched to test mode:");
+        for change in &summary.test_mode_changes {
+            println!("  {change}");
+        }
+    }
+    if !summary.health_checks.is_empty() {
+        let passed = summary
+            .health_checks
+            .iter()
+            .filter(|check| check.passed)
+            .count();
+        println!(
+            "Health checks: {passed} of {} passed",
+            summary.health_checks.len()
+        );
+    }
+    if !summary.page_comparisons.is_empty() {
+        let matched = summary
+            .page_comparisons
+            .iter()
+            .filter(|page| page.matched)
+            .count();
+        println!(
+            "Pages compared: {matched} of {} match",
+            summary.page_comparisons.len()
+        );
+    }
+    if !summary.screenshots.is_empty() {
+        println!("Screenshots:");
+        for shot in &summary.screenshots {
+            match (shot.changed, &shot.error) {
+                (Some(changed), _) => println!("  {}: {changed:.1}% changed", shot.path),
+                (None, Some(e)) => println!("  {}: {e}", shot.path),
+                (None, None) => {}
+            }
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+async fn plan(args: &SyncArgs) -> ExitCode {
+    let profile = match find_profile(&args.profile) {
+        Ok(profile) => profile,
+        Err(e) => return fail(args.json, &e),
+    };
+    let result = sync::plan(
+        &profile,
+        args.direction(),
+        args.components,
+        &Terminal::default(),
+    )
+    .await;
+    let plan = match result {
+        Ok(plan) => plan,
+        Err(e) => return fail(args.json, &e),
+    };
+    if args.json {
+        print_json(&plan);
+        return ExitCode::SUCCESS;
+    }
+    if let Some(drift) = &plan.drift {
+        eprintln!("warning: {}", drift.warning());
+    }
+    if let Some(large) = &plan.large_deletion {
+        eprintln!("warning: {}", large.warning(plan.direction));
+        if large.needs_confirmation {
+            eprintln!("The sync stops for it unless run with --allow-large-deletion");
+        }
+    }
+    if let Some(two_way) = &plan.two_way {
+        println!(
+            "Uploads: {} to push, {} to pull, {} in conflict ({} bytes to copy)",
+            two_way.push.len(),
+            two_way.pull.len(),
+            two_way.conflicts.len(),
+            two_way.bytes
+        );
+        for (mark, paths) in [
+            ("↑", &two_way.push),
+            ("↓", &two_way.pull),
+            ("!", &two_way.conflicts),
+        ] {
+            for path in paths {
+                println!("  {mark} {}", byte_path::display(path));
+            }
+        }
+    } else {
+        let files = &plan.files;
+        println!(
+            "Files: {} added, {} updated, {} deleted ({} bytes to copy)",
+            files.added.len(),
+            files.updated.len(),
+            files.deleted.len(),
+            files.bytes
+        );
+        for (mark, paths) in [
+            ("+", &files.added),
+            ("~", &files.updated),
+            ("-", &files.deleted),
+        ] {
+            for path in paths {
+                println!("  {mark} {}", byte_path::display(path));
+            }
+        }
+    }
+    if let Some(database) = &plan.database {
+        println!(
+            "Tables: {} replaced, {} created",
+            database.replaced_tables.len(),
+            database.created_tables.len()
+        );
+        for replacement in &database.replacements {
+            println!(
+                "  {} → {} ({} values)",
+                replacement.from, replacement.to, replacement.occurrences
+            );
+        }
+        for remap in &database.collation_remaps {
+            println!("Collation: {} → {}", remap.from, remap.to);
+        }
+        for warning in &database.warnings {
+            eprintln!("warning: {warning}");
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+async fn estimate(args: &SyncArgs) -> ExitCode {
+    let profile = match find_profile(&args.profile) {
+        Ok(profile) => profile,
+        Err(e) => return fail(args.json, &e),
+    };
+    let result = sync::estimate(
+        &profile,
+        args.direction(),
+        args.components,
+        &Terminal::default(),
+    )
+    .await;
+    let estimate = match result {
+        Ok(estimate) => estimate,
+        Err(e) => return fail(args.json, &e),
+    };
+    if args.json {
+        print_json(&estimate);
+        return ExitCode::SUCCESS;
+    }
+    println!(
+        "Files: {} to copy ({} bytes), {} to delete",
+        estimate.files_to_copy, estimate.file_bytes, estimate.files_to_delete
+    );
+    if let Some(bytes) = estimate.database_bytes {
+        println!("Database: {bytes} bytes");
+    }
+    match estimate.duration_ms {
+        Some(ms) => println!(
+            "About {}s, going by the last {} syncs",
+            ms.div_ceil(1000),
+            estimate.based_on_syncs
+        ),
+        None => println!("No earlier syncs like this one to estimate the time from"),
+    }
+    ExitCode::SUCCESS
+}
+
+fn report(job_id: &str, format: ReportFormat) -> ExitCode {
+    match sync::report(job_id, format) {
+        Ok(report) => {
+            print!("{report}");
+            ExitCode::SUCCESS
+        }
+        Err(e) => fail(false, &SyncError::config(e)),
+    }
+}
+
+async fn doctor(json: bool) -> ExitCode {
+    let report = sync::doctor().await;
+    if json {
+        print_json(&report);
+    } else {
+        for check in &report.checks {
+            let name = serde_json::to_value(check.check)
+                .ok()
+                .and_then(|name| name.as_str().map(str::to_string))
+                .unwrap_or_default();
+            let status = serde_json::to_value(check.status)
+                .ok()
+                .and_then(|status| status.as_str().map(str::to_string))
+                .unwrap_or_default();
+            println!("{status:>7}  {}: {}", name.replace('_', " "), check.detail);
+        }
+    }
+    if report.healthy {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+async fn bundle(output: &std::path::Path) -> ExitCode {
+    match support::export_bundle(output, env!("CARGO_PKG_VERSION")).await {
+        Ok(()) => {
+            println!("Wrote {}", output.display());
+            ExitCode::SUCCESS
+        }
+        Err(e) => fail(false, &SyncError::config(e)),
+    }
+}
+
+fn profiles(json: bool) -> ExitCode {
+    let profiles = match profile::list() {
+        Ok(profiles) => profiles,
+        Err(e) => return fail(json, &SyncError::config(e)),
+    };
+    if json {
+        print_json(&profiles);
+    } else {
+        for profile in profiles {
+            println!("{}\t{}", profile.id, profile.name);
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// The profile with id `key`, or else the one named `key`, ignoring case.
+fn find_profile(key: &str) -> Result<Profile, SyncError> {
+    if let Ok(profile) = profile::load(key) {
+        return Ok(profile);
+    }
+    let mut named: Vec<Profile> = profile::list()
+        .map_err(SyncError::config)?
+        .into_iter()
+        .filter(|profile| profile.name.eq_ignore_ascii_case(key))
+        .collect();
+    match named.len() {
+        1 => Ok(named.remove(0)),
+        0 => Err(SyncError::Config(Message::new(
+            "error.no_profile_named",
+            &[("key", &format_args!("{key:?}"))],
+        ))),
+        n => Err(SyncError::Config(Message::new(
+            "error.profiles_share_name",
+            &[("count", &n), ("key", &format_args!("{key:?}"))],
+        ))),
+    }
+}
+
+fn fail(json: bool, error: &SyncError) -> ExitCode {
+    if json {
+        print_json(error);
+    } else {
+        eprintln!("wpsync: {error}");
+        if let Some(hint) = error.hint() {
+            eprintln!("hint: {hint}");
+        }
+    }
+    match error {
+        SyncError::Cancelled => ExitCode::from(130),
+        _ => ExitCode::FAILURE,
+    }
+}
+
+fn print_json(value: &impl Serialize) {
+    let mut stdout = std::io::stdout().lock();
+    let _ = serde_json::to_writer_pretty(&mut stdout, value);
+    let _ = writeln!(stdout);
+}
+
+/// Progress on stderr: a line as each phase starts.
+#[derive(Default)]
+struct Terminal {
+    phase: Mutex<Option<Phase>>,
+}
+
+impl ProgressSink for Terminal {
+    fn progress(&self, update: &SyncProgress) {
+        let mut phase = self.phase.lock().unwrap();
+        if *phase != Some(update.phase) {
+            *phase = Some(update.phase);
+            let name = serde_json::to_value(update.phase)
+                .ok()
+                .and_then(|name| name.as_str().map(str::to_string))
+                .unwrap_or_default();
+            eprintln!("==> {}", name.replace('_', " "));
+        }
+    }
+
+    fn host_key(&self, key: &UnverifiedHostKey) {
+        let problem = if key.changed {
+            "has CHANGED since it was last trusted"
+        } else {
+            "isn't trusted yet"
+        };
+        eprintln!(
+            "The {} key of {}:{} ({}) {problem}. Check it, then add it to ~/.ssh/known_hosts \
+             or accept it in the app.",
+            key.algorithm, key.host, key.port, key.fingerprint
+        );
+    }
+
+    /// Ask on the terminal, from a thread of its own so the connection keeps
+    /// going while the user types.
+    fn auth_prompt(&self, prompt: &AuthPrompt) -> bool {
+        if !std::io::stdin().is_terminal() {
+            return false;
+        }
+        let prompt = prompt.clone();
+        std::thread::spawn(move || {
+            eprintln!("{}@{} asks:", prompt.user, prompt.host);
+            for line in [&prompt.name, &prompt.instructions] {
+                if !line.trim().is_empty() {
+                    eprintln!("{}", line.trim());
+                }
+            }
+            let mut answers = Vec::new();
+            for field in &prompt.fields {
+                // The terminal isn't told to hide what's typed.
+                let shown = if field.echo { "" } else { " (shown as typed)" };
+                eprint!("{}{shown} ", field.prompt.trim_end());
+                let _ = std::io::stderr().flush();
+                let mut answer = String::new();
+                if std::io::stdin().read_line(&mut answer).unwrap_or(0) == 0 {
+                    let _ = ssh::answer_auth_prompt(&prompt.id, None);
+                    return;
+                }
+                answers.push(answer.trim_end_matches(['\r', '\n']).to_string());
+            }
+            let _ = ssh::answer_auth_prompt(&prompt.id, Some(answers));
+        });
+        true
+    }
+
+    fn retry(&self, retry: &SyncRetry) {
+        eprintln!(
+            "{}; retrying in {}s (attempt {} of {})",
+            retry.error,
+            retry.delay_ms.div_ceil(1000),
+            retry.attempt,
+            retry.max_attempts
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use crate::credentials::{
+    self, platform_store, CredentialMigration, CredentialStore, StoredCredential,
+};
+use crate::error::SyncError;
+
+/// Store a credential in the platform credential store. With `require_presence`,
+/// reading it back needs Touch ID or the login password first, on macOS; other
+/// platforms store it as usual.
+#[tauri::command]
+pub fn store_credential(
+    service: &str,
+    account: &str,
+    password: &str,
+    require_presence: Option<bool>,
+) -> Result<(), SyncError> {
+    let store = platform_store();
+    if require_presence.unwrap_or(false) {
+        store.set_protected(service, account, password)
+    } else {
+        store.set(service, account, password)
+    }
+    .map_err(SyncError::config)
+}
+
+/// Retrieve a credential from the platform credential store.
+/// Returns None if the credential does not exist.
+/// Runs off the main thread, since a protected credential waits for Touch ID.
+#[tauri::command(async)]
+pub fn get_credential(service: &str, account: &str) -> Result<Option<String>, SyncError> {
+    platform_store()
+        .get(service, account)
+        .map_err(SyncError::config)
+}
+
+/// Delete a credential from the platform credential store.
+/// Silently succeeds if the credential does not exist.
+#[tauri::command]
+pub fn delete_credential(service: &str, account: &str) -> Result<(), SyncError> {
+    platform_store()
+        .delete(service, account)
+        .map_err(SyncError::config)
+}
+
+/// The credentials stored under services starting with `service_prefix`
+/// (by default, all of the app's own), names only and never secrets. Those
+/// belonging to deleted profiles are marked `orphaned`, ready for cleanup with
+/// `delete_credential`.
+#[tauri::command]
+pub fn list_credentials(
+    service_prefix: Option<String>,
+) -> Result<Vec<StoredCredential>, SyncError> {
+    credentials::audit(
+        service_prefix
+            .as_deref()
+            .unwrap_or(credentials::SERVICE_PREFIX),
+    )
+    .map_err(SyncError::config)
+}
+
+/// Move every credential stored under `old_service` to `new_service` and
+/// remove the old entries, as when the app's service names change. Accounts
+/// the new service already has keep their credential there.
+#[tauri::command(async)]
+pub fn migrate_credentials(
+    old_service: &str,
+    new_service: &str,
+) -> Result<CredentialMigration, SyncError> {
+    credentials::migrate(old_service, new_service).map_err(SyncError::config)
+}
@@ -0,0 +1,61 @@
+use crate::error::SyncError;
+use crate::history::{self, HistoryEntry};
+use crate::sync::{
+    self, AuditEntry, JobEvent, ManifestEntry, ManifestNode, PerformanceTrends, ReportFormat,
+};
+
+/// Past sync jobs, newest first, for one profile or (with no `profile_id`) all
+/// of them. At most `limit` entries are returned, 100 by default.
+#[tauri::command]
+pub fn list_history(
+    profile_id: Option<String>,
+    limit: Option<u32>,
+) -> Result<Vec<HistoryEntry>, SyncError> {
+    history::list(profile_id.as_deref(), limit).map_err(SyncError::config)
+}
+
+#[tauri::command]
+pub fn get_history_entry(job_id: String) -> Result<HistoryEntry, SyncError> {
+    history::get(&job_id).map_err(SyncError::config)
+}
+
+/// A report of a finished sync to share, in `markdown` or `html`: its
+/// summary, phases, warnings, and the dry run before it against what it did.
+#[tauri::command]
+pub fn generate_report(job_id: String, format: ReportFormat) -> Result<String, SyncError> {
+    sync::report(&job_id, format).map_err(SyncError::config)
+}
+
+/// Every push to a protected profile, oldest first: who ran it on which
+/// machine, how it was confirmed, what its dry run expected and how it ended.
+#[tauri::command]
+pub fn get_audit_log(profile_id: String) -> Result<Vec<AuditEntry>, SyncError> {
+    sync::audit_log(&profile_id).map_err(SyncError::config)
+}
+
+/// What a past job changed in one directory of its synced files, the root
+/// when `path` is empty, with counts of the changes below each subdirectory.
+#[tauri::command]
+pub fn browse_manifest(job_id: String, path: String) -> Result<Vec<ManifestNode>, SyncError> {
+    sync::browse_manifest(&job_id, &path).map_err(SyncError::config)
+}
+
+/// The files a past job changed whose paths match `pattern`, a glob or any
+/// part of a path.
+#[tauri::command]
+pub fn search_manifest(job_id: String, pattern: String) -> Result<Vec<ManifestEntry>, SyncError> {
+    sync::search_manifest(&job_id, &pattern).map_err(SyncError::config)
+}
+
+/// A recent job's events, oldest first, as they were sent on `sync://event`.
+#[tauri::command]
+pub fn job_events(job_id: String) -> Result<Vec<JobEvent>, SyncError> {
+    sync::job_events(&job_id).map_err(SyncError::config)
+}
+
+/// How long the profile's syncs have taken, week by week, and how fast each
+/// of their phases went.
+#[tauri::command]
+pub fn get_performance_trends(profile_id: String) -> Result<PerformanceTrends, SyncError> {
+    sync::performance_trends(&profile_id).map_err(SyncError::config)
+}
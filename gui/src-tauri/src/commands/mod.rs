@@ -0,0 +1,17 @@
+//! Tauri command handlers, grouped by feature.
+//!
+//! Handlers stay thin: they translate between the frontend's arguments and the
+//! backend modules, which know nothing about Tauri.
+
+pub mod credentials;
+pub mod history;
+pub mod profiles;
+pub mod providers;
+pub mod proxy;
+pub mod queue;
+pub mod schedules;
+pub mod settings;
+pub mod ssh;
+pub mod sync;
+pub mod updates;
+pub mod watch;
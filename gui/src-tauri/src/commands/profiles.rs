@@ -0,0 +1,175 @@
+use std::path::Path;
+
+use tauri::AppHandle;
+
+use super::sync::EventSink;
+use crate::error::SyncError;
+use crate::profile::{
+    self, wp_config, DatabaseSettings, Issue, Profile, ProfileTemplate, ResolvedProfile, Site,
+    SiteEnvironment,
+};
+use crate::schedule;
+use crate::settings;
+use crate::sync::{
+    self, ExcludeTest, LocalSite, Prerequisites, RemoteEntry, RemoteFileStat, WizardAnswers,
+    WizardResult,
+};
+
+/// All saved sync profiles, sorted by name.
+#[tauri::command]
+pub fn list_profiles() -> Result<Vec<Profile>, SyncError> {
+    profile::list().map_err(SyncError::config)
+}
+
+#[tauri::command]
+pub fn get_profile(id: String) -> Result<Profile, SyncError> {
+    profile::load(&id).map_err(SyncError::config)
+}
+
+/// The sites the saved profiles make up, each with its environments and the
+/// syncs between them, for the pull from / push to matrix.
+#[tauri::command]
+pub fn list_sites() -> Result<Vec<Site>, SyncError> {
+    profile::list_sites().map_err(SyncError::config)
+}
+
+/// The environments of one site, the local install first.
+#[tauri::command]
+pub fn list_environments(site_id: String) -> Result<Vec<SiteEnvironment>, SyncError> {
+    profile::list_environments(&site_id).map_err(SyncError::config)
+}
+
+/// A saved profile as syncs see it, merged over the bases it extends, with
+/// the settings it takes from them.
+#[tauri::command]
+pub fn resolve_profile(profile_id: String) -> Result<ResolvedProfile, SyncError> {
+    profile::resolve(&profile_id).map_err(SyncError::config)
+}
+
+/// Create or update a profile. A profile with an empty id is new and gets one
+/// assigned; the saved profile is returned either way.
+#[tauri::command]
+pub fn save_profile(profile: Profile) -> Result<Profile, SyncError> {
+    profile::save(profile).map_err(SyncError::config)
+}
+
+/// Delete a profile, its schedule, and its settings. Its stored passwords are
+/// left for the frontend to remove.
+#[tauri::command]
+pub fn delete_profile(id: String) -> Result<(), SyncError> {
+    profile::delete(&id).map_err(SyncError::config)?;
+    schedule::remove(&id).map_err(SyncError::config)?;
+    settings::remove_profile_settings(&id).map_err(SyncError::config)
+}
+
+/// Write the saved profile `profile_id` to `path` as one portable file. With
+/// a `passphrase`, its stored passwords go in too, encrypted with it;
+/// without, they are left out.
+/// Runs off the main thread, since reading a protected password waits for Touch ID.
+#[tauri::command(async)]
+pub fn export_profile(
+    profile_id: String,
+    path: String,
+    passphrase: Option<String>,
+) -> Result<(), SyncError> {
+    profile::export(&profile_id, Path::new(&path), passphrase.as_deref()).map_err(SyncError::config)
+}
+
+/// Save the profile exported to `path` as a new one, storing any passwords
+/// exported with it, which need the `passphrase` it was exported with.
+#[tauri::command(async)]
+pub fn import_profile(path: String, passphrase: Option<String>) -> Result<Profile, SyncError> {
+    profile::import(Path::new(&path), passphrase.as_deref()).map_err(SyncError::config)
+}
+
+/// Check a profile, saved or not, and list everything wrong with it so the form
+/// can flag fields before a sync is attempted.
+#[tauri::command]
+pub async fn validate_profile(profile: Profile) -> Vec<Issue> {
+    profile::validate(&profile).await
+}
+
+/// Report which of `sample_paths`, relative to the WordPress root, a sync of the
+/// saved profile would skip, and the rule responsible.
+#[tauri::command]
+pub fn test_exclude_rules(
+    profile_id: String,
+    sample_paths: Vec<String>,
+) -> Result<Vec<ExcludeTest>, SyncError> {
+    let profile = profile::load(&profile_id).map_err(SyncError::config)?;
+    sync::test_excludes(&profile, &sample_paths).map_err(SyncError::config)
+}
+
+/// Read `DB_NAME`, `DB_USER`, `DB_HOST`, and `$table_prefix` from a
+/// `wp-config.php`, or from the one in a WordPress root at `path`, to fill in a
+/// profile's database settings. With a `profile_id`, `path` is on that
+/// profile's server; otherwise it is on this machine.
+#[tauri::command]
+pub async fn parse_wp_config(
+    app: AppHandle,
+    path: String,
+    profile_id: Option<String>,
+) -> Result<DatabaseSettings, SyncError> {
+    match profile_id {
+        Some(profile_id) => {
+            let profile = profile::load(&profile_id).map_err(SyncError::config)?;
+            sync::remote_wp_config(&profile, &path, &EventSink(app)).await
+        }
+        None => wp_config::read(Path::new(&path)).map_err(SyncError::config),
+    }
+}
+
+/// The entries of a directory on a profile's server, to browse it for the
+/// WordPress root. A relative `path`, or none, starts at the SSH user's home.
+#[tauri::command]
+pub async fn list_remote_dir(
+    app: AppHandle,
+    profile_id: String,
+    path: String,
+) -> Result<Vec<RemoteEntry>, SyncError> {
+    let profile = profile::load(&profile_id).map_err(SyncError::config)?;
+    sync::list_remote_dir(&profile, &path, &EventSink(app)).await
+}
+
+/// What one path on a profile's server is, and whether it's a WordPress root.
+#[tauri::command]
+pub async fn stat_remote_file(
+    app: AppHandle,
+    profile_id: String,
+    path: String,
+) -> Result<RemoteFileStat, SyncError> {
+    let profile = profile::load(&profile_id).map_err(SyncError::config)?;
+    sync::stat_remote_file(&profile, &path, &EventSink(app)).await
+}
+
+/// The WordPress sites Local, DDEV, Lando, and MAMP have set up on this
+/// machine, to offer as a new profile's local install.
+#[tauri::command]
+pub async fn detect_local_sites() -> Vec<LocalSite> {
+    sync::detect_local_sites().await
+}
+
+/// `profile`, unsaved, with `template`'s settings applied, for the new-profile form.
+#[tauri::command]
+pub fn apply_profile_template(mut profile: Profile, template: ProfileTemplate) -> Profile {
+    template.apply(&mut profile);
+    profile
+}
+
+/// What the first-run setup wizard needs from this machine — ssh, rsync, and
+/// wp-cli — and the local sites it could start from.
+#[tauri::command]
+pub async fn detect_prerequisites() -> Prerequisites {
+    sync::detect_prerequisites().await
+}
+
+/// Make a profile from the setup wizard's `answers`, validate it, and test
+/// its connection, saving it if both pass. Failed checks are part of the
+/// result, not an error.
+#[tauri::command]
+pub async fn create_profile_from_wizard(
+    app: AppHandle,
+    answers: WizardAnswers,
+) -> Result<WizardResult, SyncError> {
+    sync::create_profile_from_wizard(answers, &EventSink(app)).await
+}
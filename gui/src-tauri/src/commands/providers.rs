@@ -0,0 +1,20 @@
+use crate::error::SyncError;
+use crate::profile::{self, LocalEnvironment, Profile};
+use crate::providers::{self, Provider, ProviderSite};
+
+/// The sites of the managed host account whose API credentials are stored for
+/// `provider`, one per environment, for picking one to make a profile of.
+#[tauri::command]
+pub async fn list_provider_sites(provider: Provider) -> Result<Vec<ProviderSite>, SyncError> {
+    providers::list_sites(provider).await
+}
+
+/// Save a new profile syncing a managed host's site with `local`, its server's
+/// details filled in from the provider.
+#[tauri::command]
+pub fn create_profile_from_provider(
+    site: ProviderSite,
+    local: LocalEnvironment,
+) -> Result<Profile, SyncError> {
+    profile::save(site.profile(local)).map_err(SyncError::config)
+}
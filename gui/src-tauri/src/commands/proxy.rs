@@ -0,0 +1,17 @@
+use crate::error::SyncError;
+use crate::proxy::{self, Proxy};
+
+/// The proxy every profile connects through unless it chooses otherwise, or
+/// `None` if connections go out directly.
+#[tauri::command]
+pub fn get_proxy_settings() -> Result<Option<Proxy>, SyncError> {
+    proxy::settings().map_err(SyncError::config)
+}
+
+/// Connect through `proxy` from now on, or with `None`, directly. Its
+/// password is stored with `store_credential` under the
+/// `com.wordpress-sync.proxy` service, for `user@host:port`.
+#[tauri::command]
+pub fn set_proxy_settings(proxy: Option<Proxy>) -> Result<(), SyncError> {
+    proxy::set_settings(proxy.as_ref()).map_err(SyncError::config)
+}
@@ -0,0 +1,58 @@
+use tauri::{AppHandle, Manager, State};
+
+use super::sync::EventSink;
+use crate::error::SyncError;
+use crate::profile;
+use crate::sync::{
+    self, Components, Direction, JobQueue, JobRegistry, PushConfirmations, QueuedSync,
+};
+
+/// Start the queue's worker in the background. Each change to the queue is
+/// announced with a `sync://queue` event carrying every entry.
+pub fn start_queue(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let (queue, jobs) = (app.state::<JobQueue>(), app.state::<JobRegistry>());
+        queue.run(&jobs, &EventSink(app.clone())).await;
+    });
+}
+
+/// Add a push or pull of a profile to the end of the queue. Queued syncs run
+/// one at a time, in order; progress is streamed as for `run_sync`. A push to
+/// a protected profile needs its `confirmation` token when it is queued, and
+/// one queued in a blackout window needs `ignore_blackout`.
+#[tauri::command]
+pub fn enqueue_sync(
+    queue: State<'_, JobQueue>,
+    confirmations: State<'_, PushConfirmations>,
+    profile_id: String,
+    direction: Direction,
+    components: Option<Components>,
+    confirmation: Option<String>,
+    ignore_blackout: Option<bool>,
+) -> Result<QueuedSync, SyncError> {
+    let profile = profile::load(&profile_id).map_err(SyncError::config)?;
+    confirmations.redeem(&profile, direction, confirmation.as_deref())?;
+    if !ignore_blackout.unwrap_or(false) {
+        sync::check_blackouts(&profile)?;
+    }
+    queue
+        .enqueue(&profile_id, direction, components.unwrap_or_default())
+        .map_err(SyncError::config)
+}
+
+/// The queue: recently finished syncs, the running one, and those waiting.
+#[tauri::command]
+pub fn get_queue(queue: State<'_, JobQueue>) -> Vec<QueuedSync> {
+    queue.entries()
+}
+
+/// Take a sync out of the queue, or cancel it if it is running.
+#[tauri::command]
+pub fn remove_from_queue(
+    queue: State<'_, JobQueue>,
+    jobs: State<'_, JobRegistry>,
+    queue_id: String,
+) -> Result<(), SyncError> {
+    queue.remove(&queue_id, &jobs).map_err(SyncError::config)
+}
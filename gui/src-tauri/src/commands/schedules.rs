@@ -0,0 +1,85 @@
+use tauri::{AppHandle, Manager};
+
+use super::sync::EventSink;
+use crate::error::SyncError;
+use crate::schedule::email::{self, EmailSettings};
+use crate::schedule::{self, Schedule};
+use crate::sync::{Components, Direction, JobRegistry};
+
+/// Start the scheduler in the background. Scheduled syncs stream
+/// `sync://progress` events and can be cancelled like any other.
+pub fn start_scheduler(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let jobs = app.state::<JobRegistry>();
+        schedule::run_scheduled(&jobs, &EventSink(app.clone())).await;
+    });
+}
+
+/// Every profile's schedule, soonest first, then the disabled ones.
+#[tauri::command]
+pub fn list_schedules() -> Result<Vec<Schedule>, SyncError> {
+    schedule::list().map_err(SyncError::config)
+}
+
+/// Sync a profile whenever `cron` (five fields, local time) fires while the
+/// app is open, replacing any schedule it already has. `components` defaults
+/// to `all` and `enabled` to true.
+#[tauri::command]
+pub fn set_schedule(
+    profile_id: String,
+    cron: String,
+    direction: Direction,
+    components: Option<Components>,
+    enabled: Option<bool>,
+) -> Result<Schedule, SyncError> {
+    schedule::set(
+        &profile_id,
+        &cron,
+        direction,
+        components.unwrap_or_default(),
+        enabled.unwrap_or(true),
+    )
+    .map_err(SyncError::config)
+}
+
+/// Stop syncing a profile on a schedule.
+#[tauri::command]
+pub fn remove_schedule(profile_id: String) -> Result<(), SyncError> {
+    schedule::remove(&profile_id).map_err(SyncError::config)
+}
+
+/// Have the operating system run a profile's schedule — launchd on macOS, a
+/// systemd user timer on Linux, the Task Scheduler on Windows — so it fires
+/// while the app is closed too. The app's own scheduler then leaves it alone.
+#[tauri::command]
+pub fn install_schedule_task(profile_id: String) -> Result<Schedule, SyncError> {
+    schedule::set_os_task(&profile_id, true).map_err(SyncError::config)
+}
+
+/// Remove a profile's OS scheduled task, handing its schedule back to the app.
+#[tauri::command]
+pub fn remove_schedule_task(profile_id: String) -> Result<Schedule, SyncError> {
+    schedule::set_os_task(&profile_id, false).map_err(SyncError::config)
+}
+
+/// The mail server scheduled syncs' results are emailed through, or `None`
+/// if email reports aren't set up.
+#[tauri::command]
+pub fn get_email_settings() -> Result<Option<EmailSettings>, SyncError> {
+    email::settings().map_err(SyncError::config)
+}
+
+/// Email scheduled syncs' results through `settings`, or with `None`, stop.
+/// The SMTP password is stored with `store_credential` under the
+/// `com.wordpress-sync.smtp` service, for the SMTP username.
+#[tauri::command]
+pub fn set_email_settings(settings: Option<EmailSettings>) -> Result<(), SyncError> {
+    email::set_settings(settings.as_ref()).map_err(SyncError::config)
+}
+
+/// Send a test email with the saved settings.
+#[tauri::command]
+pub async fn send_test_email() -> Result<(), SyncError> {
+    email::send_test().await.map_err(SyncError::config)
+}
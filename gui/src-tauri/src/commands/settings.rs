@@ -0,0 +1,90 @@
+use std::collections::BTreeMap;
+
+use tauri::{AppHandle, Emitter, State};
+
+use crate::error::SyncError;
+use crate::messages;
+use crate::settings::{self, Setting, SettingKey};
+use crate::sync::JobQueue;
+use crate::telemetry::{self, LocalMetrics, MetricsSubmission};
+
+/// Event carrying the [`Setting`] as it now is, each time one is changed.
+pub const SETTINGS_EVENT: &str = "settings://changed";
+
+/// A setting's value for a profile, or with no `profile_id` for the whole
+/// app, with where it comes from and its default.
+#[tauri::command]
+pub fn get_setting(key: SettingKey, profile_id: Option<String>) -> Result<Setting, SyncError> {
+    settings::get_setting(key, profile_id.as_deref()).map_err(SyncError::config)
+}
+
+/// Every setting, for a profile or the whole app.
+#[tauri::command]
+pub fn list_settings(profile_id: Option<String>) -> Result<Vec<Setting>, SyncError> {
+    settings::all_settings(profile_id.as_deref()).map_err(SyncError::config)
+}
+
+/// Set a setting for a profile, or with no `profile_id` for the whole app; a
+/// null `value` puts it back to what it would be unset.
+#[tauri::command]
+pub fn set_setting(
+    app: AppHandle,
+    queue: State<'_, JobQueue>,
+    key: SettingKey,
+    value: serde_json::Value,
+    profile_id: Option<String>,
+) -> Result<Setting, SyncError> {
+    let setting =
+        settings::set_setting(key, profile_id.as_deref(), value).map_err(SyncError::config)?;
+    if key == SettingKey::QueueConcurrency {
+        queue.settings_changed();
+    }
+    let _ = app.emit(SETTINGS_EVENT, &setting);
+    Ok(setting)
+}
+
+/// Show the backend's messages — errors, phase names, reports, and
+/// notifications — in `locale` from now on, for which there must be a
+/// catalog. Returns the locale now in use.
+#[tauri::command]
+pub fn set_locale(app: AppHandle, locale: String) -> Result<String, SyncError> {
+    let locale = messages::set_locale(&locale).map_err(SyncError::config)?;
+    if let Ok(setting) = settings::get_setting(SettingKey::Locale, None) {
+        let _ = app.emit(SETTINGS_EVENT, &setting);
+    }
+    Ok(locale)
+}
+
+/// The locales there are message catalogs for, English first.
+#[tauri::command]
+pub fn list_locales() -> Vec<String> {
+    messages::locales()
+}
+
+/// Every backend message by id, in `locale` or by default the current one,
+/// for the frontend to show the ids it's given.
+#[tauri::command]
+pub fn message_catalog(locale: Option<String>) -> Result<BTreeMap<String, String>, SyncError> {
+    messages::catalog(locale.as_deref()).map_err(SyncError::config)
+}
+
+/// The usage metrics counted on this machine, day by day, and exactly what
+/// the next submission would send, to look over before turning on the
+/// `usage_metrics` setting.
+#[tauri::command(async)]
+pub fn get_local_metrics() -> Result<LocalMetrics, SyncError> {
+    telemetry::local_metrics().map_err(SyncError::config)
+}
+
+/// Forget the usage metrics counted so far.
+#[tauri::command(async)]
+pub fn clear_local_metrics() -> Result<(), SyncError> {
+    telemetry::clear_metrics().map_err(SyncError::config)
+}
+
+/// Send the usage metrics of the days not yet sent now, rather than with the
+/// next periodic submission. Needs the `usage_metrics` setting on.
+#[tauri::command]
+pub async fn submit_metrics() -> Result<Option<MetricsSubmission>, SyncError> {
+    telemetry::submit_metrics().await
+}
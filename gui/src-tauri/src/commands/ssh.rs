@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+
+use crate::error::SyncError;
+use crate::ssh::{self, host_keys, ResolvedHost, UnverifiedHostKey};
+
+/// Trust a host key announced by an `ssh://host-key` event and record it in
+/// `~/.ssh/known_hosts`, replacing the old key if the server's key changed.
+/// The sync that hit it has already failed and needs to be started again.
+#[tauri::command]
+pub fn confirm_host_key(fingerprint: String) -> Result<UnverifiedHostKey, SyncError> {
+    host_keys::confirm(&fingerprint).map_err(SyncError::config)
+}
+
+/// Answer the questions of an `ssh://auth-prompt` event, in their order, so
+/// the login waiting on them goes on; with no `answers` it is given up on and
+/// its sync fails.
+#[tauri::command]
+pub fn answer_auth_prompt(id: String, answers: Option<Vec<String>>) -> Result<(), SyncError> {
+    ssh::answer_auth_prompt(&id, answers).map_err(SyncError::config)
+}
+
+/// Check that the private key at `path` can be used for syncs: with
+/// `passphrase`, that it decrypts the key, which is then stored under the key's
+/// path; without, that the key is unencrypted or its stored passphrase still
+/// works. Keys unlocked this way don't need an SSH agent.
+#[tauri::command]
+pub fn unlock_key(path: PathBuf, passphrase: Option<String>) -> Result<(), SyncError> {
+    ssh::unlock_key(&path, passphrase.as_deref()).map_err(SyncError::auth)
+}
+
+/// Look up a host alias in `~/.ssh/config`, so a profile can be filled in from it.
+#[tauri::command]
+pub fn resolve_ssh_host(alias: String) -> Result<ResolvedHost, SyncError> {
+    ssh::resolve_host(&alias).map_err(SyncError::config)
+}
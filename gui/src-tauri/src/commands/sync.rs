@@ -0,0 +1,654 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::error::SyncError;
+use crate::history::HistoryEntry;
+use crate::logging;
+use crate::messages::Message;
+use crate::notifications;
+use crate::profile;
+use crate::ssh::{AuthPrompt, UnverifiedHostKey};
+use crate::support;
+use crate::sync::{
+    self, CloneRequest, CloneResult, Components, ConnectionTest, DatabaseDiff, Direction,
+    DoctorReport, Environment, Extension, JobEvent, JobRegistry, ProgressSink, PushConfirmation,
+    PushConfirmations, QueryEnvironment, QueryResult, QueuedSync, ReplacementPreview, RestorePoint,
+    RollbackPlan, ServerRoute, ServerSyncSummary, StaleCleanup, StepPlugin, SyncEstimate, SyncPlan,
+    SyncProgress, SyncRetry, SyncSummary, UploadPartition, WatchEvent,
+};
+use crate::tray;
+
+/// Event carrying [`SyncProgress`] updates for every running job. Each job's
+/// own are sent on `sync://progress/<job id>` too, for a view of just one.
+pub const PROGRESS_EVENT: &str = "sync://progress";
+
+/// Event carrying an [`UnverifiedHostKey`] the user needs to accept or reject.
+pub const HOST_KEY_EVENT: &str = "ssh://host-key";
+
+/// Event carrying an [`AuthPrompt`] of questions a server asks to log in, to
+/// be answered with `answer_auth_prompt`.
+pub const AUTH_PROMPT_EVENT: &str = "ssh://auth-prompt";
+
+/// Event carrying every [`QueuedSync`] whenever the job queue changes.
+pub const QUEUE_EVENT: &str = "sync://queue";
+
+/// Event carrying a [`SyncRetry`] when a job is about to try a failed phase again.
+pub const RETRY_EVENT: &str = "sync://retry";
+
+/// Event carrying a [`WatchEvent`] for each batch a watch pushes, and when it stops.
+pub const WATCH_EVENT: &str = "sync://watch";
+
+/// Event carrying the [`HistoryEntry`] of each job as it finishes.
+pub const FINISHED_EVENT: &str = "sync://finished";
+
+/// Event carrying every [`JobEvent`], in the versioned schema meant for tooling.
+pub const JOB_EVENT: &str = "sync://event";
+
+/// Event carrying a [`LogBatch`](logging::LogBatch) of a followed job's log.
+pub const LOG_EVENT: &str = "log://lines";
+
+/// Event carrying the job id whose followed log has ended with the job.
+pub const LOG_ENDED_EVENT: &str = "log://ended";
+
+/// Forwards engine progress to the frontend as Tauri events.
+pub(super) struct EventSink(pub(super) AppHandle);
+
+impl ProgressSink for EventSink {
+    fn progress(&self, update: &SyncProgress) {
+        // A closed window just means nobody is listening.
+        let _ = self.0.emit(PROGRESS_EVENT, update);
+        let _ = self
+            .0
+            .emit(&format!("{PROGRESS_EVENT}/{}", update.job_id), update);
+        tray::progress(&self.0, update);
+    }
+
+    fn host_key(&self, key: &UnverifiedHostKey) {
+        let _ = self.0.emit(HOST_KEY_EVENT, key);
+    }
+
+    fn auth_prompt(&self, prompt: &AuthPrompt) -> bool {
+        self.0.emit(AUTH_PROMPT_EVENT, prompt).is_ok()
+    }
+
+    fn queue(&self, entries: &[QueuedSync]) {
+        let _ = self.0.emit(QUEUE_EVENT, entries);
+        tray::queue_changed(&self.0);
+    }
+
+    fn retry(&self, retry: &SyncRetry) {
+        let _ = self.0.emit(RETRY_EVENT, retry);
+    }
+
+    fn watch(&self, event: &WatchEvent) {
+        let _ = self.0.emit(WATCH_EVENT, event);
+    }
+
+    fn finished(&self, entry: &HistoryEntry) {
+        let _ = self.0.emit(FINISHED_EVENT, entry);
+        notifications::notify_job_result(&self.0, entry);
+        tray::job_finished(&self.0, entry);
+    }
+
+    fn event(&self, event: &JobEvent) {
+        let _ = self.0.emit(JOB_EVENT, event);
+    }
+}
+
+/// Run a push or pull for a profile, resolving once every phase has completed.
+/// `components` picks what moves (`files`, `uploads_only`, `database`, or the
+/// default `all`).
+/// Progress is streamed as `sync://progress` events while it runs; their `job_id`
+/// is what `cancel_sync` expects. An untrusted server key is announced with an
+/// `ssh://host-key` event before the call fails.
+///
+/// A push to a protected profile also needs the `confirmation` token from
+/// `request_push_confirmation`, and fails with `confirmation_required` without
+/// one. In one of the profile's blackout windows it fails with `blackout`
+/// unless `ignore_blackout` is set, and deleting more files than the
+/// profile's warning policy allows fails with `warning_blocked` unless
+/// `allow_large_deletion` is.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn run_sync(
+    app: AppHandle,
+    jobs: State<'_, JobRegistry>,
+    confirmations: State<'_, PushConfirmations>,
+    profile_id: String,
+    direction: Direction,
+    components: Option<Components>,
+    confirmation: Option<String>,
+    ignore_blackout: Option<bool>,
+    allow_large_deletion: Option<bool>,
+) -> Result<SyncSummary, SyncError> {
+    let profile = profile::load(&profile_id).map_err(SyncError::config)?;
+    confirmations.redeem(&profile, direction, confirmation.as_deref())?;
+    if !ignore_blackout.unwrap_or(false) {
+        sync::check_blackouts(&profile)?;
+    }
+    let components = components.unwrap_or_default();
+    let mut handle = jobs.start();
+    handle.allow_large_deletion = allow_large_deletion.unwrap_or(false);
+    tray::job_started(&app, &handle.id, &profile.name, direction);
+    let result = sync::run(&profile, direction, components, &handle, &EventSink(app)).await;
+    jobs.finish(&handle.id);
+    result
+}
+
+/// Run the profile's preset named `preset`, its push or pull with the options
+/// it sets. It reports, resolves, and needs a `confirmation`,
+/// `ignore_blackout`, or `allow_large_deletion` like `run_sync`.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn run_preset(
+    app: AppHandle,
+    jobs: State<'_, JobRegistry>,
+    confirmations: State<'_, PushConfirmations>,
+    profile_id: String,
+    preset: String,
+    confirmation: Option<String>,
+    ignore_blackout: Option<bool>,
+    allow_large_deletion: Option<bool>,
+) -> Result<SyncSummary, SyncError> {
+    let profile = profile::load(&profile_id).map_err(SyncError::config)?;
+    let direction = profile
+        .preset(&preset)
+        .map_err(SyncError::config)?
+        .direction;
+    confirmations.redeem(&profile, direction, confirmation.as_deref())?;
+    if !ignore_blackout.unwrap_or(false) {
+        sync::check_blackouts(&profile)?;
+    }
+    let mut handle = jobs.start();
+    handle.allow_large_deletion = allow_large_deletion.unwrap_or(false);
+    tray::job_started(&app, &handle.id, &profile.name, direction);
+    let result = sync::run_preset(&profile, &preset, &handle, &EventSink(app)).await;
+    jobs.finish(&handle.id);
+    result
+}
+
+/// Sync the server of profile `to_profile_id` from that of `from_profile_id`,
+/// such as production from staging, without going through the local install.
+/// `route` is how the files go: `direct` from one server to the other,
+/// `relay` through this machine, or the default `auto`, directly if the
+/// source server can reach the destination. It reports like `run_sync`, and
+/// a protected destination needs a `confirmation`, and a large deletion
+/// there `allow_large_deletion`, as for a push.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn sync_servers(
+    app: AppHandle,
+    jobs: State<'_, JobRegistry>,
+    confirmations: State<'_, PushConfirmations>,
+    from_profile_id: String,
+    to_profile_id: String,
+    components: Option<Components>,
+    route: Option<ServerRoute>,
+    confirmation: Option<String>,
+    ignore_blackout: Option<bool>,
+    allow_large_deletion: Option<bool>,
+) -> Result<ServerSyncSummary, SyncError> {
+    let from = profile::load(&from_profile_id).map_err(SyncError::config)?;
+    let to = profile::load(&to_profile_id).map_err(SyncError::config)?;
+    confirmations.redeem(&to, Direction::Push, confirmation.as_deref())?;
+    if !ignore_blackout.unwrap_or(false) {
+        sync::check_blackouts(&to)?;
+    }
+    let mut handle = jobs.start();
+    handle.allow_large_deletion = allow_large_deletion.unwrap_or(false);
+    tray::job_started(&app, &handle.id, &to.name, Direction::Push);
+    let result = sync::sync_servers(
+        &from,
+        &to,
+        components.unwrap_or_default(),
+        route.unwrap_or_default(),
+        &handle,
+        &EventSink(app),
+    )
+    .await;
+    jobs.finish(&handle.id);
+    result
+}
+
+/// Make an empty directory a working copy of a profile's server: download
+/// WordPress, write its `wp-config.php` and create its database, and save and
+/// pull a new profile for it. It reports like `run_sync`.
+#[tauri::command]
+pub async fn clone_site(
+    app: AppHandle,
+    jobs: State<'_, JobRegistry>,
+    request: CloneRequest,
+) -> Result<CloneResult, SyncError> {
+    let handle = jobs.start();
+    tray::job_started(&app, &handle.id, &request.name, Direction::Pull);
+    let result = sync::clone_site(request, &handle, &EventSink(app)).await;
+    jobs.finish(&handle.id);
+    result
+}
+
+/// Get the token a push to a protected profile needs, valid for that one push
+/// for two minutes. `typed_name` is what the user typed to confirm it, and must
+/// be the profile's name. On a Mac with Touch ID, the user is asked for their
+/// fingerprint as well.
+#[tauri::command]
+pub async fn request_push_confirmation(
+    confirmations: State<'_, PushConfirmations>,
+    profile_id: String,
+    typed_name: String,
+) -> Result<PushConfirmation, SyncError> {
+    let profile = profile::load(&profile_id).map_err(SyncError::config)?;
+    // A mistyped name fails before Touch ID is asked.
+    confirmations.check(&profile, &typed_name)?;
+    #[cfg(target_os = "macos")]
+    {
+        let reason = crate::messages::text("touch_id.push_to_url", &[("url", &profile.remote.url)]);
+        touch_id::authenticate(reason).await?;
+    }
+    confirmations.issue(&profile, &typed_name)
+}
+
+/// Continue a sync that failed, or whose app quit, from its last finished
+/// phase, under the same `job_id`. It reports and resolves like `run_sync`.
+#[tauri::command]
+pub async fn resume_sync(
+    app: AppHandle,
+    jobs: State<'_, JobRegistry>,
+    job_id: String,
+) -> Result<SyncSummary, SyncError> {
+    let handle = jobs
+        .start_with_id(&job_id)
+        .ok_or_else(|| SyncError::InProgress {
+            message: Message::new("error.sync_already_running", &[("job_id", &job_id)]),
+            job_id: job_id.clone(),
+        })?;
+    let result = sync::resume(&handle, &EventSink(app)).await;
+    jobs.finish(&handle.id);
+    result
+}
+
+/// Dry-run a push or pull: list the files that would be added, updated, or
+/// deleted, the tables that would be replaced, and the URL replacements that
+/// would run, without changing either side.
+#[tauri::command]
+pub async fn plan_sync(
+    app: AppHandle,
+    profile_id: String,
+    direction: Direction,
+    components: Option<Components>,
+) -> Result<SyncPlan, SyncError> {
+    let profile = profile::load(&profile_id).map_err(SyncError::config)?;
+    let components = components.unwrap_or_default();
+    sync::plan(&profile, direction, components, &EventSink(app)).await
+}
+
+/// Dump the source database of a push or pull and count what each URL
+/// replacement would change in it, with a few sample values before and after,
+/// without changing either side.
+#[tauri::command]
+pub async fn preview_replacements(
+    app: AppHandle,
+    profile_id: String,
+    direction: Direction,
+) -> Result<ReplacementPreview, SyncError> {
+    let profile = profile::load(&profile_id).map_err(SyncError::config)?;
+    sync::preview_replacements(&profile, direction, &EventSink(app)).await
+}
+
+/// List the year and month folders of the profile's uploads on both sides,
+/// with the files and bytes each side has in them and whether the profile's
+/// partition selection includes them.
+#[tauri::command]
+pub async fn list_upload_partitions(
+    app: AppHandle,
+    profile_id: String,
+) -> Result<Vec<UploadPartition>, SyncError> {
+    let profile = profile::load(&profile_id).map_err(SyncError::config)?;
+    sync::upload_partitions(&profile, &EventSink(app)).await
+}
+
+/// Work out how many files and bytes a push or pull would move, and how long
+/// it would take going by the profile's earlier syncs, without changing either
+/// side. Quicker than `plan_sync`, which also counts URL replacements.
+#[tauri::command]
+pub async fn estimate_sync(
+    app: AppHandle,
+    profile_id: String,
+    direction: Direction,
+    components: Option<Components>,
+) -> Result<SyncEstimate, SyncError> {
+    let profile = profile::load(&profile_id).map_err(SyncError::config)?;
+    let components = components.unwrap_or_default();
+    sync::estimate(&profile, direction, components, &EventSink(app)).await
+}
+
+/// Check everything a sync of the profile needs from the server — the SSH
+/// login, wp-cli, rsync, the WordPress path, its database, and write access —
+/// and return the checklist. Failed checks are part of the result, not an error.
+#[tauri::command]
+pub async fn test_connection(
+    app: AppHandle,
+    profile_id: String,
+) -> Result<ConnectionTest, SyncError> {
+    let profile = profile::load(&profile_id).map_err(SyncError::config)?;
+    Ok(sync::test_connection(&profile, &EventSink(app)).await)
+}
+
+/// Clean up what crashed or force-quit jobs of the profile left behind: its
+/// maintenance files here and on the server, and temporary files and lock
+/// owners. Fails with `in_progress` while a sync of the profile runs.
+#[tauri::command]
+pub async fn cleanup_stale_state(
+    app: AppHandle,
+    profile_id: String,
+) -> Result<StaleCleanup, SyncError> {
+    sync::cleanup_stale_state(&profile_id, &EventSink(app)).await
+}
+
+/// Install wp-cli on the profile's server for a connection test that found
+/// none: download the phar, check its checksum, upload it under the SSH
+/// user's home, and save its path in the profile. Returns the saved profile.
+#[tauri::command]
+pub async fn install_remote_wpcli(
+    app: AppHandle,
+    profile_id: String,
+) -> Result<profile::Profile, SyncError> {
+    let profile = profile::load(&profile_id).map_err(SyncError::config)?;
+    sync::install_remote_wp_cli(&profile, &EventSink(app)).await
+}
+
+/// Check this machine's setup for the Troubleshooting panel — rsync and ssh,
+/// the credential store, disk space, the app data directory, reaching each
+/// profile's server, and the config files — and return the checklist.
+#[tauri::command]
+pub async fn run_doctor() -> DoctorReport {
+    sync::doctor().await
+}
+
+/// Write a support bundle to `path`, a zip of the doctor checklist, the
+/// profiles, and the recent logs, with secrets redacted, for attaching to a
+/// bug report.
+#[tauri::command]
+pub async fn export_support_bundle(app: AppHandle, path: String) -> Result<(), SyncError> {
+    let version = app.package_info().version.to_string();
+    support::export_bundle(std::path::Path::new(&path), &version)
+        .await
+        .map_err(SyncError::config)
+}
+
+/// Describe the profile's remote WordPress install: its WordPress and PHP
+/// versions, active theme, table prefix, site URL, and multisite status, plus
+/// any profile settings that disagree with it.
+#[tauri::command]
+pub async fn detect_environment(
+    app: AppHandle,
+    profile_id: String,
+) -> Result<Environment, SyncError> {
+    let profile = profile::load(&profile_id).map_err(SyncError::config)?;
+    sync::detect_environment(&profile, &EventSink(app)).await
+}
+
+/// List the tables in a profile's remote database, for choosing which to sync.
+#[tauri::command]
+pub async fn list_remote_tables(
+    app: AppHandle,
+    profile_id: String,
+) -> Result<Vec<String>, SyncError> {
+    let profile = profile::load(&profile_id).map_err(SyncError::config)?;
+    sync::remote_tables(&profile, &EventSink(app)).await
+}
+
+/// List the plugins and themes on a profile's remote site, for choosing which to sync.
+#[tauri::command]
+pub async fn list_remote_plugins(
+    app: AppHandle,
+    profile_id: String,
+) -> Result<Vec<Extension>, SyncError> {
+    let profile = profile::load(&profile_id).map_err(SyncError::config)?;
+    sync::remote_extensions(&profile, &EventSink(app)).await
+}
+
+/// List the plugins and themes on a profile's local site.
+#[tauri::command]
+pub async fn list_local_plugins(profile_id: String) -> Result<Vec<Extension>, SyncError> {
+    let profile = profile::load(&profile_id).map_err(SyncError::config)?;
+    sync::local_extensions(&profile).await
+}
+
+/// Compare the tables of a profile's local database with the server's.
+#[tauri::command]
+pub async fn diff_databases(app: AppHandle, profile_id: String) -> Result<DatabaseDiff, SyncError> {
+    let profile = profile::load(&profile_id).map_err(SyncError::config)?;
+    sync::diff_databases(&profile, &EventSink(app)).await
+}
+
+/// Run one SQL statement against the database of a profile's local site or
+/// server, read-only unless `read_only` is false.
+#[tauri::command]
+pub async fn run_query(
+    app: AppHandle,
+    profile_id: String,
+    environment: QueryEnvironment,
+    sql: String,
+    read_only: Option<bool>,
+) -> Result<QueryResult, SyncError> {
+    let profile = profile::load(&profile_id).map_err(SyncError::config)?;
+    let read_only = read_only.unwrap_or(true);
+    sync::run_query(&profile, environment, &sql, read_only, &EventSink(app)).await
+}
+
+/// The installed plugins that add steps to syncs, for choosing a profile's custom steps.
+#[tauri::command]
+pub fn list_step_plugins() -> Result<Vec<StepPlugin>, SyncError> {
+    sync::step_plugins().map_err(SyncError::config)
+}
+
+/// The restore points made by a profile's syncs, newest first.
+#[tauri::command]
+pub fn list_restore_points(profile_id: String) -> Result<Vec<RestorePoint>, SyncError> {
+    sync::restore_points(&profile_id).map_err(SyncError::config)
+}
+
+/// Remove a profile's restore points that its retention rules no longer keep,
+/// returning the ones removed. This also happens after every sync and
+/// periodically in the background.
+#[tauri::command]
+pub async fn prune_backups(
+    app: AppHandle,
+    profile_id: String,
+) -> Result<Vec<RestorePoint>, SyncError> {
+    let profile = profile::load(&profile_id).map_err(SyncError::config)?;
+    sync::prune(&profile, &EventSink(app)).await
+}
+
+/// What rolling back to a restore point would change: the tables it puts
+/// back, with their rows then and now, and the files it restores or removes.
+/// Nothing is changed.
+#[tauri::command]
+pub async fn plan_rollback(
+    app: AppHandle,
+    restore_point_id: String,
+) -> Result<RollbackPlan, SyncError> {
+    sync::plan_rollback(&restore_point_id, &EventSink(app)).await
+}
+
+/// Undo the sync that made a restore point, putting its destination's files
+/// and database back as they were. Returns the restore point, now marked as
+/// rolled back, with how its tables' rows and some of its files compared
+/// after.
+#[tauri::command]
+pub async fn rollback(app: AppHandle, restore_point_id: String) -> Result<RestorePoint, SyncError> {
+    sync::rollback(&restore_point_id, &EventSink(app)).await
+}
+
+/// Cancel a running sync. Its `run_sync` call then fails with a `cancelled` error.
+#[tauri::command]
+pub fn cancel_sync(jobs: State<'_, JobRegistry>, job_id: String) -> Result<(), SyncError> {
+    if jobs.cancel(&job_id) {
+        Ok(())
+    } else {
+        Err(SyncError::Config(Message::new(
+            "error.no_running_sync",
+            &[("job_id", &job_id)],
+        )))
+    }
+}
+
+/// Change the bandwidth limit of a running sync's file transfer, in KiB per
+/// second, or lift it with `null`. It lasts until the sync finishes; the
+/// profile's own limit is untouched.
+#[tauri::command]
+pub fn set_runtime_bandwidth(
+    jobs: State<'_, JobRegistry>,
+    job_id: String,
+    limit: Option<u64>,
+) -> Result<(), SyncError> {
+    if jobs.set_bandwidth(&job_id, limit) {
+        Ok(())
+    } else {
+        Err(SyncError::Config(Message::new(
+            "error.no_running_sync",
+            &[("job_id", &job_id)],
+        )))
+    }
+}
+
+/// Pause a running sync to free up bandwidth: its file copies stop at the next
+/// file, and a table-by-table database import at the next table, until
+/// `unpause_sync`. (`resume_sync` is for syncs that were interrupted.)
+#[tauri::command]
+pub fn pause_sync(jobs: State<'_, JobRegistry>, job_id: String) -> Result<(), SyncError> {
+    set_paused(&jobs, &job_id, true)
+}
+
+/// Let a paused sync carry on where it stopped.
+#[tauri::command]
+pub fn unpause_sync(jobs: State<'_, JobRegistry>, job_id: String) -> Result<(), SyncError> {
+    set_paused(&jobs, &job_id, false)
+}
+
+fn set_paused(jobs: &JobRegistry, job_id: &str, paused: bool) -> Result<(), SyncError> {
+    if jobs.set_paused(job_id, paused) {
+        Ok(())
+    } else {
+        Err(SyncError::Config(Message::new(
+            "error.no_running_sync",
+            &[("job_id", &job_id)],
+        )))
+    }
+}
+
+/// The log of a sync job, running or finished: its last `tail` lines, or all of it.
+#[tauri::command]
+pub fn get_job_log(job_id: String, tail: Option<usize>) -> Result<String, SyncError> {
+    logging::job_log(&job_id, tail).map_err(SyncError::config)
+}
+
+/// The job logs being streamed, each with the number of the stream that
+/// owns it, so starting one again or stopping it retires the old stream.
+#[derive(Default)]
+pub struct LogStreams(Mutex<(u64, HashMap<String, u64>)>);
+
+impl LogStreams {
+    fn start(&self, job_id: &str) -> u64 {
+        let mut streams = self.0.lock().unwrap();
+        streams.0 += 1;
+        let stream = streams.0;
+        streams.1.insert(job_id.to_string(), stream);
+        stream
+    }
+
+    fn owns(&self, job_id: &str, stream: u64) -> bool {
+        self.0.lock().unwrap().1.get(job_id) == Some(&stream)
+    }
+
+    fn stop(&self, job_id: &str) {
+        self.0.lock().unwrap().1.remove(job_id);
+    }
+}
+
+/// Stream a sync job's log, running or finished, as `log://lines` events:
+/// the lines so far, then each as it is written, tagged with their level and
+/// phase. Batches come at most ten times a second; a `skipped` count says
+/// how many lines were dropped when the window fell too far behind. A
+/// `log://ended` event follows once the job has ended. Streaming the same
+/// job again replaces its earlier stream.
+#[tauri::command]
+pub fn stream_job_log(
+    app: AppHandle,
+    streams: State<'_, LogStreams>,
+    job_id: String,
+) -> Result<(), SyncError> {
+    let mut follower = logging::follow(&job_id).map_err(SyncError::config)?;
+    let stream = streams.start(&job_id);
+    tauri::async_runtime::spawn(async move {
+        let streams = app.state::<LogStreams>();
+        while let Some(batch) = follower.next_batch().await {
+            if !streams.owns(&job_id, stream) {
+                return;
+            }
+            let _ = app.emit(LOG_EVENT, &batch);
+        }
+        if streams.owns(&job_id, stream) {
+            streams.stop(&job_id);
+            let _ = app.emit(LOG_ENDED_EVENT, &job_id);
+        }
+    });
+    Ok(())
+}
+
+/// Stop streaming a job's log. Succeeds if it isn't streamed.
+#[tauri::command]
+pub fn stop_job_log_stream(streams: State<'_, LogStreams>, job_id: String) {
+    streams.stop(&job_id);
+}
+
+#[cfg(target_os = "macos")]
+mod touch_id {
+    use block2::RcBlock;
+    use objc2::runtime::Bool;
+    use objc2_foundation::{NSError, NSString};
+    use objc2_local_authentication::{LAContext, LAPolicy};
+
+    use crate::error::SyncError;
+    use crate::messages::Message;
+
+    /// Ask for the user's fingerprint, giving `reason` as what it is for. A Mac
+    /// without Touch ID set up lets the confirmation through on the typed
+    /// name alone.
+    pub(super) async fn authenticate(reason: String) -> Result<(), SyncError> {
+        let passed = tauri::async_runtime::spawn_blocking(move || {
+            let policy = LAPolicy::DeviceOwnerAuthenticationWithBiometrics;
+            // SAFETY: a fresh context, used only on this thread and kept alive
+            // until its reply has come.
+            let context = unsafe { LAContext::new() };
+            if unsafe { context.canEvaluatePolicy_error(policy) }.is_err() {
+                return true;
+            }
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let reply = RcBlock::new(move |success: Bool, _: *mut NSError| {
+                let _ = sender.send(success.as_bool());
+            });
+            // SAFETY: the block only sends on a channel, which any thread may do.
+            unsafe {
+                context.evaluatePolicy_localizedReason_reply(
+                    policy,
+                    &NSString::from_str(&reason),
+                    &reply,
+                )
+            };
+            receiver.recv().unwrap_or(false)
+        })
+        .await
+        .unwrap_or(false);
+        if passed {
+            Ok(())
+        } else {
+            Err(SyncError::ConfirmationRequired(Message::new(
+                "error.touch_id_didnt_confirm_push",
+                &[],
+            )))
+        }
+    }
+}
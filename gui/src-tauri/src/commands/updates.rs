@@ -0,0 +1,88 @@
+use tauri::AppHandle;
+use tauri_plugin_updater::{Update, UpdaterExt};
+
+use crate::error::SyncError;
+use crate::messages::Message;
+use crate::update::{self, AvailableUpdate, Channel};
+
+/// Look for a newer version of the app on its release channel, or `None` if
+/// it's up to date. Fails with `config` in a build that has no update server.
+#[tauri::command]
+pub async fn check_for_updates(app: AppHandle) -> Result<Option<AvailableUpdate>, SyncError> {
+    Ok(find(&app)
+        .await?
+        .map(|(found, channel)| AvailableUpdate::new(&found.version, channel, &found.raw_json)))
+}
+
+/// Download and install the update `check_for_updates` offers, once the
+/// updater has checked its signature against the release key. The update is
+/// looked for again rather than taken from the frontend. The new version runs
+/// from the app's next start.
+#[tauri::command]
+pub async fn install_update(app: AppHandle) -> Result<AvailableUpdate, SyncError> {
+    let (found, channel) = find(&app)
+        .await?
+        .ok_or_else(|| SyncError::Config(Message::new("error.app_already_up_date", &[])))?;
+    found
+        .download_and_install(|_, _| {}, || {})
+        .await
+        .map_err(|e| {
+            SyncError::Connection(Message::new(
+                "error.failed.install_update",
+                &[("error", &e)],
+            ))
+        })?;
+    Ok(AvailableUpdate::new(
+        &found.version,
+        channel,
+        &found.raw_json,
+    ))
+}
+
+/// The update on the app's channel that has rolled out to this install, if
+/// there is one.
+async fn find(app: &AppHandle) -> Result<Option<(Update, Channel)>, SyncError> {
+    let channel = update::channel().map_err(SyncError::config)?;
+    let config = app.config().plugins.0.get("updater");
+    let endpoints: Vec<String> = config
+        .and_then(|config| config["endpoints"].as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|endpoint| endpoint.as_str().map(str::to_string))
+        .collect();
+    let pubkey = config
+        .and_then(|config| config["pubkey"].as_str())
+        .unwrap_or_default();
+    let endpoints = update::endpoints(&endpoints, pubkey, channel).map_err(SyncError::config)?;
+
+    let failed = |e: tauri_plugin_updater::Error| {
+        SyncError::Connection(Message::new("error.failed.check_updates", &[("error", &e)]))
+    };
+    let mut builder = app.updater_builder();
+    if let Some(proxy) = update::proxy(&endpoints[0]).map_err(SyncError::config)? {
+        builder = builder.proxy(proxy);
+    }
+    let updater = builder
+        .endpoints(endpoints)
+        .map_err(failed)?
+        .build()
+        .map_err(failed)?;
+    let Some(found) = updater.check().await.map_err(failed)? else {
+        return Ok(None);
+    };
+    let rollout = found.raw_json["rollout"].as_u64();
+    let offered = update::rolled_out(&found.version, rollout).map_err(SyncError::config)?;
+    Ok(offered.then_some((found, channel)))
+}
+
+/// The release channel the app follows: `stable` or `beta`.
+#[tauri::command]
+pub fn get_update_channel() -> Result<Channel, SyncError> {
+    update::channel().map_err(SyncError::config)
+}
+
+/// Follow `channel` from the next check on.
+#[tauri::command]
+pub fn set_update_channel(channel: Channel) -> Result<(), SyncError> {
+    update::set_channel(channel).map_err(SyncError::config)
+}
@@ -0,0 +1,53 @@
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use super::sync::{EventSink, WATCH_EVENT};
+use crate::error::SyncError;
+use crate::messages::Message;
+use crate::profile;
+use crate::sync::{self, WatchEvent, Watches};
+
+/// Start pushing a profile's theme and plugin files to its server as they
+/// are saved. Resolves once the watch is set up; each push, and the end of
+/// the watch, is announced with a `sync://watch` event.
+#[tauri::command]
+pub async fn start_watch(
+    app: AppHandle,
+    watches: State<'_, Watches>,
+    profile_id: String,
+) -> Result<(), SyncError> {
+    let profile = profile::load(&profile_id).map_err(SyncError::config)?;
+    let cancel = watches.start(&profile.id).ok_or_else(|| {
+        SyncError::Config(Message::new(
+            "error.profile_already_watched",
+            &[("name", &profile.name)],
+        ))
+    })?;
+    tauri::async_runtime::spawn(async move {
+        let result = sync::watch(&profile, &cancel, &EventSink(app.clone())).await;
+        app.state::<Watches>().finish(&profile.id);
+        let _ = app.emit(
+            WATCH_EVENT,
+            WatchEvent::stopped(&profile.id, result.as_ref().err()),
+        );
+    });
+    Ok(())
+}
+
+/// Stop watching a profile.
+#[tauri::command]
+pub fn stop_watch(watches: State<'_, Watches>, profile_id: String) -> Result<(), SyncError> {
+    if watches.stop(&profile_id) {
+        Ok(())
+    } else {
+        Err(SyncError::Config(Message::new(
+            "error.profile_isnt_watched",
+            &[("profile_id", &profile_id)],
+        )))
+    }
+}
+
+/// The ids of the profiles being watched.
+#[tauri::command]
+pub fn list_watches(watches: State<'_, Watches>) -> Vec<String> {
+    watches.profile_ids()
+}
@@ -1,65 +1,180 @@
-use security_framework::passwords::{
-    delete_generic_password, get_generic_password, set_generic_password,
-};
-
-/// Store a credential in the macOS Keychain.
-#[tauri::command]
-fn store_credential(service: &str, account: &str, password: &str) -> Result<(), String> {
-    // Delete any existing entry first (set_generic_password fails if it already exists)
-    let _ = delete_generic_password(service, account);
-    set_generic_password(service, account, password.as_bytes())
-        .map_err(|e| format!("Failed to store credential: {e}"))
-}
+mod commands;
+mod links;
+mod notifications;
+mod tray;
 
-/// Retrieve a credential from the macOS Keychain.
-/// Returns None if the credential does not exist.
-#[tauri::command]
-fn get_credential(service: &str, account: &str) -> Result<Option<String>, String> {
-    match get_generic_password(service, account) {
-        Ok(bytes) => {
-            let s = String::from_utf8(bytes).map_err(|e| format!("Invalid UTF-8: {e}"))?;
-            Ok(Some(s))
-        }
-        Err(e) => {
-            // errSecItemNotFound (-25300) means no credential stored — not an error
-            if e.code() == -25300 {
-                Ok(None)
-            } else {
-                Err(format!("Failed to retrieve credential: {e}"))
-            }
-        }
-    }
-}
+use tauri::Manager;
+use wordpress_sync_core::{
+    credentials, deep_link, error, history, instance, logging, messages, profile, providers, proxy,
+    schedule, settings, ssh, support, sync, telemetry, update,
+};
 
-/// Delete a credential from the macOS Keychain.
-/// Silently succeeds if the credential does not exist.
-#[tauri::command]
-fn delete_credential(service: &str, account: &str) -> Result<(), String> {
-    match delete_generic_password(service, account) {
-        Ok(()) => Ok(()),
-        Err(e) => {
-            if e.code() == -25300 {
-                Ok(()) // Not found — nothing to delete
-            } else {
-                Err(format!("Failed to delete credential: {e}"))
-            }
-        }
-    }
-}
+pub use wordpress_sync_core::{
+    run_bridge_client, run_scheduled_sync, BRIDGE_FLAG, SCHEDULED_SYNC_FLAG,
+};
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    logging::init();
+    let args: Vec<String> = std::env::args().collect();
+    // A later copy hands its arguments, and any link among them, to the first and exits.
+    let Some(instance) = instance::claim(&args) else {
+        return;
+    };
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_notification::init())
+        .plugin(tauri_plugin_updater::Builder::new().build())
+        .manage(sync::JobRegistry::default())
+        .manage(sync::JobQueue::default())
+        .manage(sync::PushConfirmations::default())
+        .manage(sync::Watches::default())
+        .manage(notifications::LastNotification::default())
+        .manage(tray::TrayState::default())
+        .manage(commands::sync::LogStreams::default())
+        .setup(move |app| {
+            tauri::async_runtime::spawn(sync::prune_periodically());
+            tauri::async_runtime::spawn(telemetry::submit_periodically());
+            tauri::async_runtime::spawn_blocking(sync::sweep_stale_state);
+            commands::schedules::start_scheduler(app.handle());
+            commands::queue::start_queue(app.handle());
+            tray::create(app.handle())?;
+            let handle = app.handle().clone();
+            instance.serve(move |args| {
+                tray::open_window(&handle);
+                links::handle_args(&handle, &args);
+            });
+            // Without it, links just don't reach the app.
+            let _ = deep_link::register();
+            links::handle_args(app.handle(), &args);
+            Ok(())
+        })
+        .on_window_event(|window, event| match event {
+            tauri::WindowEvent::Focused(true) => notifications::window_focused(window.app_handle()),
+            // Syncs go on with the window closed; the tray's menu quits.
+            tauri::WindowEvent::CloseRequested { api, .. } => {
+                api.prevent_close();
+                let _ = window.hide();
+            }
+            _ => {}
+        })
         .invoke_handler(tauri::generate_handler![
-            store_credential,
-            get_credential,
-            delete_credential,
+            commands::credentials::store_credential,
+            commands::credentials::get_credential,
+            commands::credentials::delete_credential,
+            commands::credentials::list_credentials,
+            commands::credentials::migrate_credentials,
+            commands::history::list_history,
+            commands::history::get_history_entry,
+            commands::history::generate_report,
+            commands::history::get_audit_log,
+            commands::history::browse_manifest,
+            commands::history::search_manifest,
+            commands::history::job_events,
+            commands::history::get_performance_trends,
+            commands::settings::get_setting,
+            commands::settings::list_settings,
+            commands::settings::set_setting,
+            commands::settings::set_locale,
+            commands::settings::list_locales,
+            commands::settings::message_catalog,
+            commands::settings::get_local_metrics,
+            commands::settings::clear_local_metrics,
+            commands::settings::submit_metrics,
+            commands::ssh::confirm_host_key,
+            commands::ssh::answer_auth_prompt,
+            commands::ssh::resolve_ssh_host,
+            commands::ssh::unlock_key,
+            commands::profiles::list_profiles,
+            commands::profiles::list_sites,
+            commands::profiles::list_environments,
+            commands::profiles::get_profile,
+            commands::profiles::resolve_profile,
+            commands::profiles::save_profile,
+            commands::profiles::delete_profile,
+            commands::profiles::export_profile,
+            commands::profiles::import_profile,
+            commands::profiles::validate_profile,
+            commands::profiles::test_exclude_rules,
+            commands::profiles::parse_wp_config,
+            commands::profiles::list_remote_dir,
+            commands::profiles::stat_remote_file,
+            commands::profiles::detect_local_sites,
+            commands::profiles::apply_profile_template,
+            commands::profiles::detect_prerequisites,
+            commands::profiles::create_profile_from_wizard,
+            commands::providers::list_provider_sites,
+            commands::providers::create_profile_from_provider,
+            commands::proxy::get_proxy_settings,
+            commands::proxy::set_proxy_settings,
+            commands::queue::enqueue_sync,
+            commands::queue::get_queue,
+            commands::queue::remove_from_queue,
+            commands::schedules::list_schedules,
+            commands::schedules::set_schedule,
+            commands::schedules::remove_schedule,
+            commands::schedules::install_schedule_task,
+            commands::schedules::remove_schedule_task,
+            commands::schedules::get_email_settings,
+            commands::schedules::set_email_settings,
+            commands::schedules::send_test_email,
+            commands::sync::list_remote_tables,
+            commands::sync::list_remote_plugins,
+            commands::sync::list_local_plugins,
+            commands::sync::run_query,
+            commands::sync::diff_databases,
+            commands::sync::list_step_plugins,
+            commands::sync::plan_sync,
+            commands::sync::preview_replacements,
+            commands::sync::list_upload_partitions,
+            commands::sync::estimate_sync,
+            commands::sync::test_connection,
+            commands::sync::cleanup_stale_state,
+            commands::sync::install_remote_wpcli,
+            commands::sync::run_doctor,
+            commands::sync::export_support_bundle,
+            commands::sync::detect_environment,
+            commands::sync::request_push_confirmation,
+            commands::sync::run_sync,
+            commands::sync::run_preset,
+            commands::sync::sync_servers,
+            commands::sync::clone_site,
+            commands::sync::resume_sync,
+            commands::sync::cancel_sync,
+            commands::sync::set_runtime_bandwidth,
+            commands::sync::pause_sync,
+            commands::sync::unpause_sync,
+            commands::sync::get_job_log,
+            commands::sync::stream_job_log,
+            commands::sync::stop_job_log_stream,
+            commands::sync::list_restore_points,
+            commands::sync::prune_backups,
+            commands::sync::plan_rollback,
+            commands::sync::rollback,
+            commands::updates::check_for_updates,
+            commands::updates::install_update,
+            commands::updates::get_update_channel,
+            commands::updates::set_update_channel,
+            commands::watch::start_watch,
+            commands::watch::stop_watch,
+            commands::watch::list_watches,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| match event {
+            // macOS delivers links as an event rather than on the command line.
+            #[cfg(target_os = "macos")]
+            tauri::RunEvent::Opened { urls } => {
+                for url in urls {
+                    links::open(app, url.as_str());
+                }
+            }
+            // The app's icon was clicked again, with the window hidden in the tray.
+            #[cfg(target_os = "macos")]
+            tauri::RunEvent::Reopen { .. } => tray::open_window(app),
+            _ => {}
+        });
 }
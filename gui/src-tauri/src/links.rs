@@ -0,0 +1,72 @@
+//! Handling `wpsync://` links, which arrive on the command line of this copy
+//! or of a later one, or as an event on macOS.
+
+use tauri::AppHandle;
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
+
+use crate::deep_link::{self, DeepLink};
+use crate::messages;
+use crate::notifications;
+use crate::sync::{Components, Direction};
+use crate::tray;
+
+/// Act on the `wpsync://` link among `args`, if there is one.
+pub fn handle_args(app: &AppHandle, args: &[String]) {
+    if let Some(link) = deep_link::find(args) {
+        open(app, link);
+    }
+}
+
+/// Bring the window forward and queue the sync `link` asks for, once it's
+/// confirmed: any page can open a link, so none runs a sync by itself.
+pub fn open(app: &AppHandle, link: &str) {
+    tray::open_window(app);
+    let parsed = DeepLink::parse(link).and_then(|link| Ok((link.profile()?, link)));
+    let (profile, link) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            notifications::notify_failure(
+                app,
+                &messages::text("notification.couldnt_open_link", &[]),
+                &e,
+            );
+            return;
+        }
+    };
+    let DeepLink::Run {
+        direction,
+        components,
+        ..
+    } = link;
+    let what = match components {
+        Components::All => "link.components.all",
+        Components::Files => "link.components.files",
+        Components::UploadsOnly => "link.components.uploads_only",
+        Components::Database => "link.components.database",
+    };
+    let (ask, verb) = match direction {
+        Direction::Push => ("link.asks_to_push", "direction.push"),
+        Direction::Pull => ("link.asks_to_pull", "direction.pull"),
+    };
+    let message = messages::text(
+        ask,
+        &[
+            ("what", &messages::text(what, &[])),
+            ("name", &profile.name),
+        ],
+    );
+    let confirming = app.clone();
+    app.dialog()
+        .message(message)
+        .title(messages::text("link.run_this_sync", &[]))
+        .kind(MessageDialogKind::Warning)
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            messages::text(verb, &[]),
+            messages::text("link.cancel", &[]),
+        ))
+        .show(move |confirmed| {
+            if confirmed {
+                tray::enqueue(&confirming, &profile, direction, components);
+            }
+        });
+}
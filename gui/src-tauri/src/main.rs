@@ -2,5 +2,14 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    // rsync launches this binary as its remote shell to reach the managed SSH session.
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some(gui_lib::BRIDGE_FLAG) {
+        std::process::exit(gui_lib::run_bridge_client(&args[2..]));
+    }
+    // The OS scheduler launches it to run a scheduled sync while the app is closed.
+    if args.get(1).map(String::as_str) == Some(gui_lib::SCHEDULED_SYNC_FLAG) {
+        std::process::exit(gui_lib::run_scheduled_sync(&args[2..]));
+    }
     gui_lib::run()
 }
@@ -0,0 +1,68 @@
+//! Desktop notifications of finished jobs, sent from the backend as each job
+//! is recorded, so they arrive whether or not the frontend is watching.
+//!
+//! Desktop notifications don't tell the app which one was clicked, but
+//! clicking one brings the app forward: when its window is focused soon
+//! after a notification, the frontend is told to show that job's history entry.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_notification::NotificationExt;
+
+use crate::history::{HistoryEntry, JobStatus};
+use crate::settings;
+use crate::sync::JobNotification;
+
+/// Event carrying the job id whose history entry the app was brought
+/// forward to show.
+pub const FOCUS_HISTORY_EVENT: &str = "history://focus";
+
+/// How long after a notification focusing the window counts as clicking it.
+const CLICK_WINDOW: Duration = Duration::from_secs(60);
+
+/// The job last notified of, and when, until the window is next focused.
+#[derive(Default)]
+pub struct LastNotification(Mutex<Option<(String, Instant)>>);
+
+/// Notify the user that the job `entry` records has finished, unless they
+/// have turned notifications off for its profile, or for those that didn't fail.
+pub fn notify_job_result(app: &AppHandle, entry: &HistoryEntry) {
+    let failed = entry.status == JobStatus::Failed;
+    if !settings::notifies(Some(&entry.profile_id), failed) {
+        return;
+    }
+    let notification = JobNotification::of(entry);
+    let shown = app
+        .notification()
+        .builder()
+        .title(&notification.title)
+        .body(&notification.body)
+        .show();
+    // Notifications the system won't show, as without permission, go unseen.
+    if shown.is_ok() {
+        *app.state::<LastNotification>().0.lock().unwrap() =
+            Some((notification.job_id, Instant::now()));
+    }
+}
+
+/// Tell the user something they asked for from outside the window failed,
+/// unless they have turned notifications off.
+pub fn notify_failure(app: &AppHandle, title: &str, body: &str) {
+    if !settings::notifies(None, true) {
+        return;
+    }
+    let _ = app.notification().builder().title(title).body(body).show();
+}
+
+/// The app's window was focused: if that follows a notification, ask the
+/// frontend to show the job it was about.
+pub fn window_focused(app: &AppHandle) {
+    let last = app.state::<LastNotification>().0.lock().unwrap().take();
+    if let Some((job_id, at)) = last {
+        if at.elapsed() < CLICK_WINDOW {
+            let _ = app.emit(FOCUS_HISTORY_EVENT, job_id);
+        }
+    }
+}
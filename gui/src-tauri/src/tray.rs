@@ -0,0 +1,364 @@
+//! The tray icon, kept by the backend so syncs, queued and scheduled ones
+//! included, can run with the main window closed: closing the window only
+//! hides it, and the app quits from the tray's menu.
+//!
+//! The menu shows what is running, the last few results, and quick actions:
+//! pulling or pushing a profile through the queue, cancelling what is
+//! running, and opening the app. It's rebuilt as jobs start and finish; only
+//! the status line changes as a job makes progress.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tauri::menu::{Menu, MenuBuilder, MenuEvent, MenuItem, SubmenuBuilder};
+use tauri::tray::TrayIconBuilder;
+use tauri::{AppHandle, Emitter, Manager, Wry};
+
+use crate::history::{self, HistoryEntry, JobStatus};
+use crate::messages;
+use crate::notifications::{self, FOCUS_HISTORY_EVENT};
+use crate::profile::{self, Profile};
+use crate::sync::{self, Components, Direction, JobQueue, JobRegistry, QueueStatus, SyncProgress};
+
+const TRAY_ID: &str = "main";
+
+/// The app's one window, as `tauri.conf.json` leaves it labelled.
+const WINDOW_LABEL: &str = "main";
+
+/// Finished jobs the menu lists.
+const RECENT: u32 = 5;
+
+/// How often the status line follows a running job's progress.
+const STATUS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Menu ids of the fixed actions; the others are `run:{direction}:{profile id}`
+/// and `recent:{job id}`.
+const OPEN: &str = "open";
+const CANCEL: &str = "cancel";
+const QUIT: &str = "quit";
+
+/// What the tray knows of running jobs, and the status line it shows them in.
+#[derive(Default)]
+pub struct TrayState(Mutex<Inner>);
+
+#[derive(Default)]
+struct Inner {
+    running: Vec<RunningJob>,
+    status: Option<MenuItem<Wry>>,
+    updated: Option<Instant>,
+}
+
+struct RunningJob {
+    job_id: String,
+    /// What the job does, e.g. `Pushing My Site`, once it is known.
+    label: Option<String>,
+    progress: Option<SyncProgress>,
+}
+
+/// Put the icon in the tray, with the menu as things stand.
+pub fn create(app: &AppHandle) -> tauri::Result<()> {
+    let menu = build_menu(app)?;
+    let mut tray = TrayIconBuilder::with_id(TRAY_ID)
+        .menu(&menu)
+        .tooltip("WordPress Sync")
+        .on_menu_event(menu_event);
+    if let Some(icon) = app.default_window_icon() {
+        tray = tray.icon(icon.clone());
+    }
+    tray.build(app)?;
+    Ok(())
+}
+
+/// A job started for `profile_name` in `direction`, as the app's own syncs say
+/// before their first progress.
+pub fn job_started(app: &AppHandle, job_id: &str, profile_name: &str, direction: Direction) {
+    {
+        let state = app.state::<TrayState>();
+        let mut inner = state.0.lock().unwrap();
+        let label = Some(label(profile_name, direction));
+        match inner.running.iter_mut().find(|job| job.job_id == job_id) {
+            Some(job) => job.label = label,
+            None => inner.running.push(RunningJob {
+                job_id: job_id.to_string(),
+                label,
+                progress: None,
+            }),
+        }
+    }
+    refresh(app);
+}
+
+/// A job made progress: follow it in the status line, at most once every
+/// [`STATUS_INTERVAL`] unless it's a job the tray hadn't heard of.
+pub fn progress(app: &AppHandle, update: &SyncProgress) {
+    let state = app.state::<TrayState>();
+    let mut inner = state.0.lock().unwrap();
+    match inner
+        .running
+        .iter_mut()
+        .find(|job| job.job_id == update.job_id)
+    {
+        Some(job) => job.progress = Some(update.clone()),
+        None => {
+            inner.running.push(RunningJob {
+                job_id: update.job_id.clone(),
+                label: queued_label(app, &update.job_id),
+                progress: Some(update.clone()),
+            });
+            drop(inner);
+            refresh(app);
+            return;
+        }
+    }
+    if inner
+        .updated
+        .is_some_and(|at| at.elapsed() < STATUS_INTERVAL)
+    {
+        return;
+    }
+    inner.updated = Some(Instant::now());
+    let line = status_line(&inner.running);
+    // Released first: the text is set on the main thread, which may be
+    // waiting for the lock to rebuild the menu.
+    let status = inner.status.clone();
+    drop(inner);
+    if let Some(item) = status {
+        let _ = item.set_text(line);
+    }
+}
+
+/// The queue changed: its running entries name their jobs, and those it has
+/// finished are no longer running, whether or not they got as far as the history.
+pub fn queue_changed(app: &AppHandle) {
+    {
+        let state = app.state::<TrayState>();
+        let mut inner = state.0.lock().unwrap();
+        for entry in app.state::<JobQueue>().entries() {
+            let Some(job_id) = entry.job_id else {
+                continue;
+            };
+            let known = inner.running.iter().position(|job| job.job_id == job_id);
+            match (entry.status, known) {
+                (QueueStatus::Running, Some(index)) => {
+                    inner.running[index].label = Some(label(&entry.profile_name, entry.direction));
+                }
+                (QueueStatus::Running, None) => inner.running.push(RunningJob {
+                    job_id,
+                    label: Some(label(&entry.profile_name, entry.direction)),
+                    progress: None,
+                }),
+                (QueueStatus::Queued, _) | (_, None) => {}
+                (_, Some(index)) => {
+                    inner.running.remove(index);
+                }
+            }
+        }
+    }
+    refresh(app);
+}
+
+/// The job `entry` records has finished: it leaves the status line and joins
+/// the recent results.
+pub fn job_finished(app: &AppHandle, entry: &HistoryEntry) {
+    app.state::<TrayState>()
+        .0
+        .lock()
+        .unwrap()
+        .running
+        .retain(|job| job.job_id != entry.job_id);
+    refresh(app);
+}
+
+/// Show the main window, bringing it forward if it's behind others.
+pub fn open_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window(WINDOW_LABEL) {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+/// Rebuild the menu. The menu changes on the main thread, so this returns
+/// before it has.
+fn refresh(app: &AppHandle) {
+    let handle = app.clone();
+    let _ = app.run_on_main_thread(move || {
+        let Some(tray) = handle.tray_by_id(TRAY_ID) else {
+            return;
+        };
+        // A menu that can't be rebuilt stays as it was.
+        if let Ok(menu) = build_menu(&handle) {
+            let _ = tray.set_menu(Some(menu));
+        }
+    });
+}
+
+fn build_menu(app: &AppHandle) -> tauri::Result<Menu<Wry>> {
+    let state = app.state::<TrayState>();
+    let mut inner = state.0.lock().unwrap();
+    let status = MenuItem::with_id(
+        app,
+        "status",
+        status_line(&inner.running),
+        false,
+        None::<&str>,
+    )?;
+    let running = !inner.running.is_empty();
+    inner.status = Some(status.clone());
+    inner.updated = Some(Instant::now());
+    drop(inner);
+
+    let mut recent = SubmenuBuilder::new(app, messages::text("tray.recent_syncs", &[]));
+    let entries = history::list(None, Some(RECENT)).unwrap_or_default();
+    for entry in &entries {
+        recent = recent.text(format!("recent:{}", entry.job_id), result_line(entry));
+    }
+    let recent = recent.enabled(!entries.is_empty()).build()?;
+
+    let mut run = SubmenuBuilder::new(app, messages::text("tray.run", &[]));
+    let profiles = profile::list().unwrap_or_default();
+    for profile in &profiles {
+        // A protected profile's push needs confirming, which only the app can ask for.
+        let mut actions = SubmenuBuilder::new(app, &profile.name).text(
+            format!("run:pull:{}", profile.id),
+            messages::text("direction.pull", &[]),
+        );
+        if !profile.protected {
+            actions = actions.text(
+                format!("run:push:{}", profile.id),
+                messages::text("direction.push", &[]),
+            );
+        }
+        run = run.item(&actions.build()?);
+    }
+    let run = run.enabled(!profiles.is_empty()).build()?;
+
+    let cancel = MenuItem::with_id(
+        app,
+        CANCEL,
+        messages::text("tray.cancel_running_syncs", &[]),
+        running,
+        None::<&str>,
+    )?;
+    MenuBuilder::new(app)
+        .item(&status)
+        .item(&recent)
+        .separator()
+        .item(&run)
+        .item(&cancel)
+        .separator()
+        .text(OPEN, messages::text("tray.open", &[]))
+        .text(QUIT, messages::text("tray.quit", &[]))
+        .build()
+}
+
+fn menu_event(app: &AppHandle, event: MenuEvent) {
+    let id = event.id().as_ref();
+    match id {
+        OPEN => open_window(app),
+        QUIT => app.exit(0),
+        CANCEL => {
+            let jobs = app.state::<JobRegistry>();
+            for job in &app.state::<TrayState>().0.lock().unwrap().running {
+                jobs.cancel(&job.job_id);
+            }
+        }
+        _ => {
+            if let Some(job_id) = id.strip_prefix("recent:") {
+                open_window(app);
+                let _ = app.emit(FOCUS_HISTORY_EVENT, job_id);
+            } else if let Some(run) = id.strip_prefix("run:") {
+                let (direction, profile_id) = run.split_once(':').unwrap_or_default();
+                let direction = match direction {
+                    "push" => Direction::Push,
+                    _ => Direction::Pull,
+                };
+                match profile::load(profile_id) {
+                    Ok(profile) => enqueue(app, &profile, direction, Components::All),
+                    Err(e) => notifications::notify_failure(
+                        app,
+                        &messages::text("notification.couldnt_start_sync", &[]),
+                        &e,
+                    ),
+                }
+            }
+        }
+    }
+}
+
+/// Queue a sync asked for outside the window, from the menu or a link,
+/// keeping to the profile's blackout windows. A protected profile's push
+/// needs confirming, which only the window can ask for, so it's shown instead.
+/// A sync that can't be queued is reported in a notification.
+pub fn enqueue(app: &AppHandle, profile: &Profile, direction: Direction, components: Components) {
+    let queued = if direction == Direction::Push && profile.protected {
+        open_window(app);
+        Err(messages::text(
+            "error.protected_push_needs_app",
+            &[("name", &profile.name)],
+        ))
+    } else {
+        sync::check_blackouts(profile)
+            .map_err(|e| e.message())
+            .and_then(|()| {
+                app.state::<JobQueue>()
+                    .enqueue(&profile.id, direction, components)
+            })
+    };
+    if let Err(e) = queued {
+        notifications::notify_failure(
+            app,
+            &messages::text("notification.couldnt_start_sync", &[]),
+            &e,
+        );
+    }
+}
+
+/// The status line: idle, or what the running jobs are doing.
+fn status_line(running: &[RunningJob]) -> String {
+    let Some(job) = running.first() else {
+        return messages::text("tray.no_syncs_running", &[]);
+    };
+    let mut line = job
+        .label
+        .clone()
+        .unwrap_or_else(|| messages::text("tray.syncing", &[]));
+    if let Some(progress) = &job.progress {
+        let phase = progress.phase.label();
+        line = messages::text("tray.job_phase", &[("job", &line), ("phase", &phase)]);
+        if let Some(total) = progress.files_total.filter(|&total| total > 0) {
+            let percent = (progress.files_done * 100 / total).min(100);
+            line = messages::text("tray.job_percent", &[("job", &line), ("percent", &percent)]);
+        }
+    }
+    match running.len() {
+        1 => line,
+        n => messages::text("tray.job_and_more", &[("job", &line), ("count", &(n - 1))]),
+    }
+}
+
+/// A finished job in the recent results, e.g. `✓ Pushed My Site`.
+fn result_line(entry: &HistoryEntry) -> String {
+    let mark = match entry.status {
+        JobStatus::Succeeded => "✓",
+        JobStatus::Failed => "✗",
+        JobStatus::Cancelled => "–",
+    };
+    format!("{mark} {}", sync::JobNotification::of(entry).title)
+}
+
+fn label(profile_name: &str, direction: Direction) -> String {
+    match direction {
+        Direction::Push => messages::text("tray.pushing", &[("name", &profile_name)]),
+        Direction::Pull => messages::text("tray.pulling", &[("name", &profile_name)]),
+    }
+}
+
+/// The label of a job the queue started, if it did.
+fn queued_label(app: &AppHandle, job_id: &str) -> Option<String> {
+    app.state::<JobQueue>()
+        .entries()
+        .into_iter()
+        .find(|entry| entry.job_id.as_deref() == Some(job_id))
+        .map(|entry| label(&entry.profile_name, entry.direction))
+}
@@ -1,8 +1,9 @@
 /**
- * macOS Keychain integration for storing sensitive credentials.
+ * System credential store integration for storing sensitive credentials.
  *
- * Uses Tauri invoke commands backed by the security-framework Rust crate
- * to store, retrieve, and delete passwords in the system Keychain.
+ * Uses Tauri invoke commands backed by the Rust `credentials` module, which
+ * stores passwords in the macOS Keychain, the Windows Credential Manager, or
+ * the Linux Secret Service depending on the platform.
  * Passwords never touch the filesystem — they live only in the OS credential store.
  */
 